@@ -0,0 +1,447 @@
+//! 不依赖 ffmpeg 的动图编码器
+//!
+//! 短小的循环动画可以直接写成 GIF 或 APNG，不需要安装 ffmpeg。
+//!
+//! GIF 每帧最多只有 256 种颜色：每一帧用 NeuQuant 单独量化出局部调色板，再用
+//! Floyd–Steinberg 误差扩散抖动，平滑着色的渐变不会出现明显的色带。APNG 是无损的，
+//! 每一帧都是完整的 RGB 图像，文件通常比 GIF 大。两种格式都无限循环播放。
+
+use crate::stream::{sub_filter, write_chunk, CHUNK, SIGNATURE};
+use color_quant::NeuQuant;
+use deflate::write::ZlibEncoder;
+use deflate::Compression;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// NeuQuant 训练时的采样间隔，1 质量最好但最慢，10 是常用的折中
+const SAMPLE_FACTOR: i32 = 10;
+
+/// 帧率为 `fps` 时第 `frame` 帧的 GIF 延迟（单位为 10 毫秒）
+///
+/// GIF 的延迟只能是整数个 10 毫秒，每帧按累计时间取整，长时间播放时总时长不会漂移。
+fn gif_delay(frame: usize, fps: usize) -> u16 {
+    let at = |frame: usize| (frame as f64 * 100.0 / fps as f64).round() as u64;
+    (at(frame + 1) - at(frame)).clamp(1, u16::MAX as u64) as u16
+}
+
+#[test]
+fn test_gif_delay() {
+    assert_eq!(gif_delay(0, 25), 4);
+    // 30 帧每秒时延迟在 3 和 4 之间交替，每 3 帧正好 10 个单位
+    let delays: Vec<u16> = (0..6).map(|frame| gif_delay(frame, 30)).collect();
+    assert_eq!(delays, [3, 4, 3, 3, 4, 3]);
+    assert_eq!(gif_delay(0, 1000), 1);
+}
+
+/// 用 Floyd–Steinberg 误差扩散把 `width` 像素宽的 RGB 图像 `pixels` 映射为调色板
+/// `palette` 中的下标，`nearest` 给出离某个颜色最近的调色板下标
+fn dither(
+    pixels: &[u8],
+    width: usize,
+    palette: &[u8],
+    nearest: impl Fn([u8; 3]) -> usize,
+) -> Vec<u8> {
+    let mut indices = Vec::with_capacity(pixels.len() / 3);
+    // 当前行和下一行累积的误差，两端各多留一个像素，省去边界判断
+    let mut current = vec![[0f32; 3]; width + 2];
+    let mut next = vec![[0f32; 3]; width + 2];
+    for row in pixels.chunks_exact(width * 3) {
+        for (x, pixel) in row.chunks_exact(3).enumerate() {
+            let mut wanted = [0u8; 3];
+            let mut target = [0f32; 3];
+            for channel in 0..3 {
+                target[channel] =
+                    (pixel[channel] as f32 + current[x + 1][channel]).clamp(0.0, 255.0);
+                wanted[channel] = target[channel].round() as u8;
+            }
+            let index = nearest(wanted);
+            indices.push(index as u8);
+            for channel in 0..3 {
+                let error = target[channel] - palette[index * 3 + channel] as f32;
+                current[x + 2][channel] += error * 7.0 / 16.0;
+                next[x][channel] += error * 3.0 / 16.0;
+                next[x + 1][channel] += error * 5.0 / 16.0;
+                next[x + 2][channel] += error / 16.0;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|error| *error = [0.0; 3]);
+    }
+    indices
+}
+
+#[test]
+fn test_dither() {
+    // 只有黑白两种颜色时，灰度 64 按比例抖动成约四分之一的白色像素
+    let palette = [0, 0, 0, 255, 255, 255];
+    let (width, height) = (16, 16);
+    let pixels = vec![64u8; width * height * 3];
+    let indices = dither(&pixels, width, &palette, |color| (color[0] >= 128) as usize);
+    let whites = indices.iter().filter(|&&index| index == 1).count();
+    assert!((56..=72).contains(&whites), "{} white pixels", whites);
+}
+
+/// 把一帧 RGB 像素量化为最多 256 种颜色，返回调色板和每个像素的下标
+///
+/// 颜色不超过 256 种时直接使用这些颜色，结果无损；否则用 NeuQuant 训练调色板并抖动。
+fn quantize(pixels: &[u8], width: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut colors: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut palette = Vec::new();
+    for pixel in pixels.chunks_exact(3) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        if !colors.contains_key(&color) {
+            if colors.len() == 256 {
+                let rgba: Vec<u8> = pixels
+                    .chunks_exact(3)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                    .collect();
+                let quantizer = NeuQuant::new(SAMPLE_FACTOR, 256, &rgba);
+                let palette = quantizer.color_map_rgb();
+                let indices = dither(pixels, width, &palette, |[r, g, b]| {
+                    quantizer.index_of(&[r, g, b, 255])
+                });
+                return (palette, indices);
+            }
+            colors.insert(color, colors.len() as u8);
+            palette.extend_from_slice(&color);
+        }
+    }
+    let indices = pixels
+        .chunks_exact(3)
+        .map(|pixel| colors[&[pixel[0], pixel[1], pixel[2]]])
+        .collect();
+    (palette, indices)
+}
+
+#[test]
+fn test_quantize() {
+    let pixels = [1, 2, 3, 4, 5, 6, 1, 2, 3];
+    assert_eq!(
+        quantize(&pixels, 3),
+        (vec![1, 2, 3, 4, 5, 6], vec![0, 1, 0])
+    );
+
+    // 颜色太多时量化为 256 种，平均颜色基本不变
+    let pixels: Vec<u8> = (0..64 * 64)
+        .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 128])
+        .collect();
+    let (palette, indices) = quantize(&pixels, 64);
+    assert_eq!(palette.len(), 256 * 3);
+    for channel in 0..3 {
+        let mean = |values: &mut dyn Iterator<Item = u8>| {
+            values.map(|value| value as f64).sum::<f64>() / (64 * 64) as f64
+        };
+        let original = mean(&mut pixels.iter().skip(channel).step_by(3).copied());
+        let quantized = mean(
+            &mut indices
+                .iter()
+                .map(|&index| palette[index as usize * 3 + channel]),
+        );
+        assert!(
+            (original - quantized).abs() < 2.0,
+            "{} vs {}",
+            original,
+            quantized
+        );
+    }
+}
+
+/// 在 `GifEncoder` 和 `gif::Encoder` 之间共享的输出
+///
+/// `gif::Encoder` 在析构时才写出文件尾，写出时的错误要么被忽略要么引起 panic，而且它
+/// 不交还输出。共享之后 `finish` 先取回输出，此后 `gif::Encoder` 写出的内容都被丢弃，
+/// 再自己写出文件尾并报告错误。
+struct Shared<W>(Rc<RefCell<Option<W>>>);
+
+impl<W: Write> Write for Shared<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self.0.borrow_mut().as_mut() {
+            Some(output) => output.write(bytes),
+            None => Ok(bytes.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.borrow_mut().as_mut() {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// GIF 的文件尾
+const TRAILER: u8 = 0x3b;
+
+/// 逐帧写出的 GIF 编码器
+pub struct GifEncoder<W: Write> {
+    encoder: gif::Encoder<Shared<W>>,
+    output: Rc<RefCell<Option<W>>>,
+    bounds: (usize, usize),
+    fps: usize,
+    /// 已经写入的帧数
+    frames: usize,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// 写出 `bounds` 大小、帧率为 `fps`、无限循环的 GIF 的文件头
+    pub fn new(output: W, bounds: (usize, usize), fps: usize) -> io::Result<GifEncoder<W>> {
+        let (width, height) = match (u16::try_from(bounds.0), u16::try_from(bounds.1)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::other(format!(
+                    "GIF frames cannot be larger than {}x{}",
+                    u16::MAX,
+                    u16::MAX
+                )))
+            }
+        };
+        let output = Rc::new(RefCell::new(Some(output)));
+        // 每一帧都带自己的局部调色板，全局调色板只是占位
+        let mut encoder = gif::Encoder::new(Shared(output.clone()), width, height, &[0; 6])?;
+        encoder.write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))?;
+        Ok(GifEncoder {
+            encoder,
+            output,
+            bounds,
+            fps,
+            frames: 0,
+        })
+    }
+
+    /// 量化、抖动并写入一帧 RGB 像素
+    pub fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        assert_eq!(pixels.len(), self.bounds.0 * self.bounds.1 * 3);
+        let (palette, indices) = quantize(pixels, self.bounds.0);
+        let frame = gif::Frame {
+            delay: gif_delay(self.frames, self.fps),
+            width: self.bounds.0 as u16,
+            height: self.bounds.1 as u16,
+            palette: Some(palette),
+            buffer: Cow::Owned(indices),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// 写出文件尾，返回底层的输出
+    ///
+    /// 不调用 `finish` 就丢弃编码器时不会写出文件尾，输出的 GIF 是不完整的。
+    pub fn finish(self) -> io::Result<W> {
+        let GifEncoder {
+            encoder, output, ..
+        } = self;
+        let mut output = output
+            .borrow_mut()
+            .take()
+            .unwrap_or_else(|| unreachable!("the GIF output has been taken"));
+        drop(encoder);
+        output.write_all(&[TRAILER])?;
+        output.flush()?;
+        Ok(output)
+    }
+}
+
+#[test]
+fn test_gif_encoder() {
+    let bounds = (12, 8);
+    let frames: Vec<Vec<u8>> = (0..3u8)
+        .map(|frame| {
+            (0..bounds.0 * bounds.1)
+                .flat_map(|i| [frame * 100, (i * 2) as u8, 50])
+                .collect()
+        })
+        .collect();
+    let mut encoder = GifEncoder::new(Vec::new(), bounds, 25).unwrap();
+    for pixels in &frames {
+        encoder.write_frame(pixels).unwrap();
+    }
+    let bytes = encoder.finish().unwrap();
+    assert_eq!(bytes.last(), Some(&TRAILER));
+    // 文件尾只写出一次，最后一帧以长度为 0 的数据块结束
+    assert_eq!(bytes[bytes.len() - 2], 0);
+
+    let mut reader = gif::Decoder::new(&bytes[..]).read_info().unwrap();
+    assert_eq!((reader.width(), reader.height()), (12, 8));
+    let mut decoded = 0;
+    while let Some(frame) = reader.read_next_frame().unwrap() {
+        assert_eq!(frame.delay, 4);
+        assert_eq!((frame.width, frame.height), (12, 8));
+        // 每帧的颜色不超过 256 种，量化是无损的
+        let palette = frame.palette.as_ref().unwrap();
+        let colors: Vec<u8> = frame
+            .buffer
+            .iter()
+            .flat_map(|&index| palette[index as usize * 3..index as usize * 3 + 3].to_vec())
+            .collect();
+        assert_eq!(colors, frames[decoded]);
+        decoded += 1;
+    }
+    assert_eq!(decoded, 3);
+
+    // 写不下文件尾时 finish 报告错误
+    let mut buffer = vec![0; bytes.len() - 1];
+    let mut encoder = GifEncoder::new(&mut buffer[..], bounds, 25).unwrap();
+    for pixels in &frames {
+        encoder.write_frame(pixels).unwrap();
+    }
+    assert!(encoder.finish().is_err());
+}
+
+/// 逐帧写出的 APNG 编码器，帧数必须事先给出
+pub struct ApngEncoder<W: Write> {
+    output: W,
+    bounds: (usize, usize),
+    fps: u16,
+    /// 动画的总帧数
+    frames: usize,
+    /// 已经写入的帧数
+    written: usize,
+    /// 下一个 `fcTL` 或 `fdAT` 块的序号
+    sequence: u32,
+}
+
+impl<W: Write> ApngEncoder<W> {
+    /// 写出 `bounds` 大小、帧率为 `fps`、共 `frames` 帧、无限循环的 8 位 RGB APNG 的文件头
+    pub fn new(
+        mut output: W,
+        bounds: (usize, usize),
+        fps: usize,
+        frames: usize,
+    ) -> io::Result<ApngEncoder<W>> {
+        let (width, height) = (
+            u32::try_from(bounds.0).map_err(io::Error::other)?,
+            u32::try_from(bounds.1).map_err(io::Error::other)?,
+        );
+        let fps = u16::try_from(fps).map_err(io::Error::other)?;
+        let count = u32::try_from(frames).map_err(io::Error::other)?;
+        output.write_all(&SIGNATURE)?;
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut output, b"IHDR", &header)?;
+        // 帧数和播放次数（0 表示无限循环）
+        let mut control = count.to_be_bytes().to_vec();
+        control.extend_from_slice(&0u32.to_be_bytes());
+        write_chunk(&mut output, b"acTL", &control)?;
+        Ok(ApngEncoder {
+            output,
+            bounds,
+            fps,
+            frames,
+            written: 0,
+            sequence: 0,
+        })
+    }
+
+    /// 写入一帧 RGB 像素
+    pub fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        assert_eq!(pixels.len(), self.bounds.0 * self.bounds.1 * 3);
+        if self.written == self.frames {
+            return Err(io::Error::other("more frames than announced"));
+        }
+        // 帧控制：序号、尺寸、偏移 (0, 0)、显示 1/fps 秒、不清除、直接覆盖
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        control.extend_from_slice(&(self.bounds.0 as u32).to_be_bytes());
+        control.extend_from_slice(&(self.bounds.1 as u32).to_be_bytes());
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&1u16.to_be_bytes());
+        control.extend_from_slice(&self.fps.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_chunk(&mut self.output, b"fcTL", &control)?;
+        self.sequence += 1;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+        let mut filtered = vec![0; 1 + self.bounds.0 * 3];
+        for row in pixels.chunks_exact(self.bounds.0 * 3) {
+            sub_filter(row, 3, &mut filtered);
+            encoder.write_all(&filtered)?;
+        }
+        let compressed = encoder.finish()?;
+        // 第一帧同时是不支持动画的查看器显示的默认图像，写成 IDAT；之后的帧写成带序号的 fdAT
+        for data in compressed.chunks(CHUNK) {
+            if self.written == 0 {
+                write_chunk(&mut self.output, b"IDAT", data)?;
+            } else {
+                let mut chunk = self.sequence.to_be_bytes().to_vec();
+                chunk.extend_from_slice(data);
+                write_chunk(&mut self.output, b"fdAT", &chunk)?;
+                self.sequence += 1;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// 写出文件尾，返回底层的输出
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.written != self.frames {
+            return Err(io::Error::other(format!(
+                "only {} of {} frames were written",
+                self.written, self.frames
+            )));
+        }
+        write_chunk(&mut self.output, b"IEND", &[])?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[test]
+fn test_apng_encoder() {
+    use crate::stream::crc32;
+    use image::DynamicImage;
+
+    let bounds = (5, 4);
+    let frames: Vec<Vec<u8>> = (0..3)
+        .map(|frame| {
+            (0..bounds.0 * bounds.1 * 3)
+                .map(|i| (i * 37 + frame * 11) as u8)
+                .collect()
+        })
+        .collect();
+    let mut encoder = ApngEncoder::new(Vec::new(), bounds, 30, 3).unwrap();
+    for pixels in &frames {
+        encoder.write_frame(pixels).unwrap();
+    }
+    assert!(encoder.write_frame(&frames[0]).is_err());
+    let bytes = encoder.finish().unwrap();
+
+    // 块的顺序和序号，并校验每个块的 CRC
+    let mut chunks = Vec::new();
+    let mut at = SIGNATURE.len();
+    while at < bytes.len() {
+        let length = u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let body = &bytes[at + 4..at + 8 + length];
+        let crc = u32::from_be_bytes(bytes[at + 8 + length..at + 12 + length].try_into().unwrap());
+        assert_eq!(crc32(body), crc);
+        let kind = String::from_utf8(body[..4].to_vec()).unwrap();
+        if kind == "fcTL" || kind == "fdAT" {
+            let sequence = u32::from_be_bytes(body[4..8].try_into().unwrap());
+            chunks.push(format!("{}{}", kind, sequence));
+        } else {
+            chunks.push(kind);
+        }
+        at += 12 + length;
+    }
+    assert_eq!(
+        chunks,
+        ["IHDR", "acTL", "fcTL0", "IDAT", "fcTL1", "fdAT2", "fcTL3", "fdAT4", "IEND"]
+    );
+
+    // 不支持动画的解码器看到的是第一帧
+    let DynamicImage::ImageRgb8(decoded) = image::load_from_memory(&bytes).unwrap() else {
+        panic!("expected an RGB image");
+    };
+    assert_eq!(decoded.into_raw(), frames[0]);
+
+    let mut encoder = ApngEncoder::new(Vec::new(), bounds, 30, 2).unwrap();
+    encoder.write_frame(&frames[0]).unwrap();
+    assert!(encoder.finish().is_err());
+}
@@ -0,0 +1,151 @@
+//! 自适应抗锯齿
+//!
+//! 均匀超采样在集合内部和远离边界的平滑区域上白白多算了许多子像素。自适应抗锯齿先以
+//! 每像素一个采样渲染并着色，找出与相邻像素颜色相差超过阈值的像素，只对这些像素
+//! 超采样，其余像素的子像素直接沿用原来的逃逸值。
+
+use crate::progress::Progress;
+use crate::{cancelled, encode_escape, Coloring, Fractal, PixelTransform};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 标记 RGB 缓冲区 `pixels` 中需要超采样的像素
+///
+/// 一个像素只要与周围 8 个相邻像素之一在某个颜色通道上相差超过 `threshold`，就视为
+/// 位于边界上。返回与像素一一对应的标记。
+pub fn edge_pixels(pixels: &[u8], bounds: (usize, usize), threshold: u8) -> Vec<bool> {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    let color = |column: usize, row: usize| {
+        let start = (row * bounds.0 + column) * 3;
+        &pixels[start..start + 3]
+    };
+
+    let mut edges = vec![false; bounds.0 * bounds.1];
+    edges
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, edge) in band.iter_mut().enumerate() {
+                let here = color(column, row);
+                *edge = (row.saturating_sub(1)..(row + 2).min(bounds.1)).any(|y| {
+                    (column.saturating_sub(1)..(column + 2).min(bounds.0)).any(|x| {
+                        color(x, y)
+                            .iter()
+                            .zip(here)
+                            .any(|(&a, &b)| a.abs_diff(b) > threshold)
+                    })
+                });
+            }
+        });
+    edges
+}
+
+#[test]
+fn test_edge_pixels() {
+    // 3x2 的图像，只有右上角的像素与众不同
+    let mut pixels = [0; 18];
+    pixels[6..9].copy_from_slice(&[0, 0, 200]);
+    assert_eq!(
+        edge_pixels(&pixels, (3, 2), 16),
+        [false, true, true, false, true, true]
+    );
+    assert_eq!(edge_pixels(&pixels, (3, 2), 200), [false; 6]);
+}
+
+/// 把迭代缓冲区 `iterations` 扩展为每个方向放大 `factor` 倍的超采样缓冲区
+///
+/// `edges` 标记的像素在其 `factor * factor` 个子像素上重新计算逃逸值，其他像素的
+/// 子像素都复制原来的值。其余参数的含义与 `render` 相同，各行子像素并行计算，
+/// 每完成一行就在 `progress` 上记录一次。
+#[allow(clippy::too_many_arguments)]
+pub fn refine(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    iterations: &[u32],
+    edges: &[bool],
+    factor: usize,
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+    progress: &Progress,
+) -> Vec<u32> {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    assert_eq!(edges.len(), iterations.len());
+    let sample_bounds = (bounds.0 * factor, bounds.1 * factor);
+    let transform = transform.subdivided(factor);
+    let spacing = transform.spacing();
+    progress.start(sample_bounds.1, "rows");
+
+    let mut samples = vec![0; sample_bounds.0 * sample_bounds.1];
+    samples
+        .par_chunks_mut(sample_bounds.0)
+        .enumerate()
+        .for_each(|(sub_row, band)| {
+            let row = sub_row / factor;
+            // 渲染被中断后不再重新计算，边界像素保留原来的逃逸值
+            let cancelled = cancelled();
+            for (sub_column, sample) in band.iter_mut().enumerate() {
+                let index = row * bounds.0 + sub_column / factor;
+                *sample = if edges[index] && !cancelled {
+                    let point = transform.point((sub_column, sub_row));
+                    encode_escape(fractal.escape_value(coloring, point, limit, spacing))
+                } else {
+                    iterations[index]
+                };
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    samples
+}
+
+#[test]
+fn test_refine() {
+    use num::Complex;
+
+    let bounds = (8, 6);
+    let upper_left = Complex { re: -2.0, im: 1.2 };
+    let lower_right = Complex { re: 1.0, im: -1.2 };
+    let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    crate::render(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        100,
+        &mut iterations,
+        bounds,
+        transform,
+    );
+
+    // 全部标记为边界时与直接以超采样尺寸渲染相同
+    let mut expected = vec![0; bounds.0 * bounds.1 * 4];
+    crate::render(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        100,
+        &mut expected,
+        (bounds.0 * 2, bounds.1 * 2),
+        PixelTransform::from_corners((bounds.0 * 2, bounds.1 * 2), upper_left, lower_right),
+    );
+    let all = vec![true; iterations.len()];
+    let refine = |edges: &[bool]| {
+        refine(
+            Fractal::Mandelbrot,
+            Coloring::EscapeTime,
+            100,
+            &iterations,
+            edges,
+            2,
+            bounds,
+            transform,
+            &Progress::hidden(),
+        )
+    };
+    assert_eq!(refine(&all), expected);
+
+    // 没有边界时只是把每个像素复制成 2x2 个子像素
+    let none = vec![false; iterations.len()];
+    let samples = refine(&none);
+    assert_eq!(samples[0..2], [iterations[0]; 2]);
+    assert_eq!(samples[bounds.0 * 2], iterations[0]);
+    assert_eq!(samples[2], iterations[1]);
+}
@@ -0,0 +1,377 @@
+//! HTTP 渲染服务
+//!
+//! 长期运行，供网页前端或聊天机器人调用：
+//!
+//! - `POST /jobs` 提交渲染任务，请求体是描述场景的 JSON 对象，键与 `render` 的命令行选项
+//!   相同（见 `config::json_config_args`），立即返回任务的编号；
+//! - `GET /jobs` 列出所有任务的状态，`GET /jobs/{id}` 查询一个任务的状态；
+//! - `GET /jobs/{id}/result` 在任务完成后下载渲染结果。
+//!
+//! 任务按提交的顺序在一个后台线程中逐个渲染，每个任务内部照常并行。任务和结果只保存在
+//! 这个进程中，进程结束后不再能查询。
+
+use crate::serve::{accept, respond, HTTP_TIMEOUT};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// 请求体的最大字节数
+const MAX_BODY: usize = 1 << 20;
+
+/// 一个 HTTP 请求
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// 去掉查询字符串的路径
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// 读取一个请求，请求体的长度由 `Content-Length` 给出
+pub fn read_request(input: &mut impl BufRead) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let method = words.next().ok_or_else(|| invalid("empty request"))?;
+    let target = words.next().unwrap_or("");
+    let (method, path) = (
+        method.to_string(),
+        target.split('?').next().unwrap_or("").to_string(),
+    );
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("bad Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Request { method, path, body })
+}
+
+#[test]
+fn test_read_request() {
+    let text = "POST /jobs?wait=1 HTTP/1.1\r\nHost: x\r\ncontent-length: 7\r\n\r\n{\"a\":1}extra";
+    assert_eq!(
+        read_request(&mut text.as_bytes()).unwrap(),
+        Request {
+            method: "POST".to_string(),
+            path: "/jobs".to_string(),
+            body: b"{\"a\":1}".to_vec(),
+        }
+    );
+    let text = "GET / HTTP/1.1\r\n\r\n";
+    assert_eq!(read_request(&mut text.as_bytes()).unwrap().body, b"");
+    assert!(read_request(&mut "".as_bytes()).is_err());
+    let text = "POST /jobs HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort";
+    assert!(read_request(&mut text.as_bytes()).is_err());
+}
+
+/// 任务的状态
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Queued,
+    Running,
+    /// 渲染完成，结果保存在文件中
+    Done {
+        path: PathBuf,
+        content_type: &'static str,
+    },
+    Failed(String),
+}
+
+/// 任务状态的 JSON
+pub fn status_value(id: usize, status: &Status) -> Value {
+    match status {
+        Status::Queued => json!({"id": id, "status": "queued"}),
+        Status::Running => json!({"id": id, "status": "running"}),
+        Status::Done { .. } => json!({
+            "id": id,
+            "status": "done",
+            "result": format!("/jobs/{}/result", id),
+        }),
+        Status::Failed(message) => json!({"id": id, "status": "failed", "error": message}),
+    }
+}
+
+/// 提交的任务，编号就是它在列表中的下标
+pub struct Jobs<T> {
+    jobs: Mutex<Vec<(Status, Option<T>)>>,
+    queued: Condvar,
+}
+
+impl<T> Default for Jobs<T> {
+    fn default() -> Self {
+        Jobs {
+            jobs: Mutex::new(Vec::new()),
+            queued: Condvar::new(),
+        }
+    }
+}
+
+impl<T> Jobs<T> {
+    /// 排入一个任务，返回它的编号
+    pub fn submit(&self, job: T) -> usize {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push((Status::Queued, Some(job)));
+        self.queued.notify_one();
+        jobs.len() - 1
+    }
+
+    /// 编号为 `id` 的任务的状态
+    pub fn status(&self, id: usize) -> Option<Status> {
+        Some(self.jobs.lock().unwrap().get(id)?.0.clone())
+    }
+
+    /// 所有任务的状态，按编号排列
+    pub fn statuses(&self) -> Vec<Status> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().map(|(status, _)| status.clone()).collect()
+    }
+
+    /// 等待并取出最早排队的任务，把它标记为正在渲染
+    fn next(&self) -> (usize, T) {
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            let queued = jobs.iter_mut().enumerate().find_map(|(id, (status, job))| {
+                let job = job.take()?;
+                *status = Status::Running;
+                Some((id, job))
+            });
+            if let Some(queued) = queued {
+                return queued;
+            }
+            jobs = self.queued.wait(jobs).unwrap();
+        }
+    }
+
+    /// 等待并执行下一个排队的任务；`execute` 返回结果文件及其媒体类型，或者失败的原因
+    fn run_next(&self, execute: &impl Fn(usize, T) -> Result<(PathBuf, &'static str), String>) {
+        let (id, job) = self.next();
+        let status = match execute(id, job) {
+            Ok((path, content_type)) => Status::Done { path, content_type },
+            Err(message) => Status::Failed(message),
+        };
+        self.jobs.lock().unwrap()[id].0 = status;
+    }
+}
+
+#[test]
+fn test_jobs() {
+    let jobs = Jobs::default();
+    assert_eq!(jobs.submit("a.png"), 0);
+    assert_eq!(jobs.submit("bad"), 1);
+    assert_eq!(jobs.status(1), Some(Status::Queued));
+    assert_eq!(jobs.status(2), None);
+    let execute = |_, job| match job {
+        "bad" => Err("no such palette".to_string()),
+        job => Ok((PathBuf::from(job), "image/png")),
+    };
+    jobs.run_next(&execute);
+    assert_eq!(
+        jobs.statuses(),
+        [
+            Status::Done {
+                path: PathBuf::from("a.png"),
+                content_type: "image/png"
+            },
+            Status::Queued
+        ]
+    );
+    jobs.run_next(&execute);
+    assert_eq!(
+        jobs.status(1),
+        Some(Status::Failed("no such palette".to_string()))
+    );
+}
+
+#[test]
+fn test_status_value() {
+    assert_eq!(
+        status_value(3, &Status::Queued),
+        json!({"id": 3, "status": "queued"})
+    );
+    let done = Status::Done {
+        path: PathBuf::from("/tmp/job_3.png"),
+        content_type: "image/png",
+    };
+    assert_eq!(
+        status_value(3, &done),
+        json!({"id": 3, "status": "done", "result": "/jobs/3/result"})
+    );
+    assert_eq!(
+        status_value(4, &Status::Failed("bad".to_string())),
+        json!({"id": 4, "status": "failed", "error": "bad"})
+    );
+}
+
+/// 写出 JSON 响应
+fn respond_json(stream: &mut impl Write, status: &str, value: &Value) -> io::Result<()> {
+    let body = format!("{}\n", value);
+    respond(stream, status, "application/json", body.as_bytes())
+}
+
+/// 处理一个请求，之后关闭连接
+fn handle<T>(
+    stream: TcpStream,
+    jobs: &Jobs<T>,
+    prepare: &impl Fn(&Value) -> Result<T, String>,
+) -> io::Result<()> {
+    let request = read_request(&mut BufReader::new(&stream));
+    let mut stream = &stream;
+    let error = |message: String| json!({ "error": message });
+    let request = match request {
+        Ok(request) => request,
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            return respond_json(&mut stream, "400 Bad Request", &error(err.to_string()));
+        }
+        Err(err) => return Err(err),
+    };
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let id = |segment: &str| segment.parse().ok().filter(|&id| jobs.status(id).is_some());
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => {
+            let job = serde_json::from_slice(&request.body)
+                .map_err(|err| format!("invalid JSON: {}", err))
+                .and_then(|scene| prepare(&scene));
+            match job {
+                Ok(job) => {
+                    let id = jobs.submit(job);
+                    respond_json(
+                        &mut stream,
+                        "202 Accepted",
+                        &status_value(id, &Status::Queued),
+                    )
+                }
+                Err(message) => respond_json(&mut stream, "400 Bad Request", &error(message)),
+            }
+        }
+        ("GET", ["jobs"]) => {
+            let statuses: Vec<Value> = jobs
+                .statuses()
+                .iter()
+                .enumerate()
+                .map(|(id, status)| status_value(id, status))
+                .collect();
+            respond_json(&mut stream, "200 OK", &Value::Array(statuses))
+        }
+        ("GET", ["jobs", segment]) => match id(segment) {
+            Some(id) => {
+                let status = jobs.status(id).expect("the job exists");
+                respond_json(&mut stream, "200 OK", &status_value(id, &status))
+            }
+            None => respond_json(&mut stream, "404 Not Found", &error("no such job".into())),
+        },
+        ("GET", ["jobs", segment, "result"]) => match id(segment).map(|id| (id, jobs.status(id))) {
+            Some((_, Some(Status::Done { path, content_type }))) => {
+                respond(&mut stream, "200 OK", content_type, &std::fs::read(path)?)
+            }
+            Some((id, Some(status))) => {
+                respond_json(&mut stream, "409 Conflict", &status_value(id, &status))
+            }
+            _ => respond_json(&mut stream, "404 Not Found", &error("no such job".into())),
+        },
+        (_, ["jobs", ..]) => respond_json(
+            &mut stream,
+            "405 Method Not Allowed",
+            &error("method not allowed".into()),
+        ),
+        _ => respond_json(&mut stream, "404 Not Found", &error("not found".into())),
+    }
+}
+
+/// 在 `listener` 上提供渲染服务，直到进程结束
+///
+/// `prepare` 在提交时检查场景，无效的场景直接以 400 拒绝，不会排队；`execute` 在后台
+/// 线程中依次渲染排队的任务，返回结果文件及其媒体类型，或者失败的原因。连接的读写超时与
+/// 瓦片服务器相同，接受连接出错时打印错误后继续。
+pub fn serve<T: Send + 'static>(
+    listener: TcpListener,
+    prepare: impl Fn(&Value) -> Result<T, String> + Sync,
+    execute: impl Fn(usize, T) -> Result<(PathBuf, &'static str), String> + Send + 'static,
+) -> io::Result<()> {
+    let jobs = Arc::new(Jobs::default());
+    let runner = jobs.clone();
+    thread::spawn(move || loop {
+        runner.run_next(&execute);
+    });
+    let (jobs, prepare) = (&*jobs, &prepare);
+    thread::scope(|scope| {
+        accept(&listener, HTTP_TIMEOUT, |stream| {
+            scope.spawn(move || {
+                let _ = handle(stream, jobs, prepare);
+            });
+        });
+        Ok(())
+    })
+}
+
+#[test]
+fn test_serve() {
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let result = std::env::temp_dir().join(format!("mandelbrot-api-test-{}", std::process::id()));
+    std::fs::write(&result, b"pixels").unwrap();
+    let path = result.clone();
+    thread::spawn(move || {
+        serve(
+            listener,
+            |scene| {
+                scene["size"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| "no size".to_string())
+            },
+            move |_, _| Ok((path.clone(), "image/png")),
+        )
+    });
+    let request = |text: String| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(text.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        String::from_utf8(response).unwrap()
+    };
+    let post = |body: &str| {
+        request(format!(
+            "POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+    };
+    let get = |path: &str| request(format!("GET {} HTTP/1.1\r\n\r\n", path));
+
+    assert!(post("{").starts_with("HTTP/1.1 400"));
+    assert!(post("{}").contains("no size"));
+    let response = post(r#"{"size": "10x10"}"#);
+    assert!(response.starts_with("HTTP/1.1 202"), "{}", response);
+    assert!(response.contains(r#""id":0"#), "{}", response);
+    while !get("/jobs/0").contains(r#""status":"done""#) {
+        thread::yield_now();
+    }
+    assert!(get("/jobs/0/result").ends_with("\r\n\r\npixels"));
+    assert!(get("/jobs").contains(r#""result":"/jobs/0/result""#));
+    assert!(get("/jobs/1").starts_with("HTTP/1.1 404"));
+    assert!(get("/jobs/x/result").starts_with("HTTP/1.1 404"));
+    assert!(request("DELETE /jobs/0 HTTP/1.1\r\n\r\n".to_string()).starts_with("HTTP/1.1 405"));
+    std::fs::remove_file(result).unwrap();
+}
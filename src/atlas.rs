@@ -0,0 +1,188 @@
+//! 朱利亚集图谱
+//!
+//! 把图像分成 N×M 个格子，每个格子里画一个小朱利亚集，它的常数 `c` 是格子中心在参数
+//! 平面（曼德博集所在的平面）中对应的点。`c` 属于曼德博集时朱利亚集是连通的，越过边界
+//! 就碎成尘埃，整张图谱因此近似地勾勒出曼德博集的形状。
+//!
+//! 每个格子里的朱利亚集都以原点为中心，覆盖范围由 `julia_zoom` 决定，与 `corners_from_center`
+//! 的缩放倍数含义相同。格子之间可以留出 `gap` 像素宽的分隔线，用调色板的内部颜色画出。
+
+use crate::progress::Progress;
+use crate::{corners_from_center, encode_escape, Fractal, PixelTransform};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 朱利亚集图谱的布局
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Atlas {
+    /// 横向和纵向的格子数
+    pub grid: (usize, usize),
+    /// 整张图像在参数平面中覆盖的范围
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+    /// 格子里朱利亚集的缩放倍数
+    pub julia_zoom: f64,
+    /// 格子之间分隔线的像素宽度
+    pub gap: usize,
+}
+
+/// 一个轴上的一段格子：格子的序号、起点和长度
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Span {
+    index: usize,
+    start: usize,
+    length: usize,
+}
+
+/// 把长度为 `length` 的轴尽量均匀地分成 `count` 段，返回每个像素所在的段
+fn divide(length: usize, count: usize) -> Vec<Span> {
+    let mut spans = Vec::with_capacity(length);
+    for index in 0..count {
+        let start = index * length / count;
+        let end = (index + 1) * length / count;
+        spans.extend(std::iter::repeat_n(
+            Span {
+                index,
+                start,
+                length: end - start,
+            },
+            end - start,
+        ));
+    }
+    spans
+}
+
+impl Atlas {
+    /// 第 `cell`（列、行）个格子的朱利亚集常数，即格子中心在参数平面中对应的点
+    pub fn parameter(&self, bounds: (usize, usize), cell: (usize, usize)) -> Complex<f64> {
+        let center = |length: usize, count: usize, index: usize| {
+            let start = index * length / count;
+            let end = (index + 1) * length / count;
+            (start + end) as f64 / 2.0 / length as f64
+        };
+        let x = center(bounds.0, self.grid.0, cell.0);
+        let y = center(bounds.1, self.grid.1, cell.1);
+        Complex {
+            re: self.upper_left.re + x * (self.lower_right.re - self.upper_left.re),
+            im: self.upper_left.im + y * (self.lower_right.im - self.upper_left.im),
+        }
+    }
+}
+
+#[test]
+fn test_atlas() {
+    assert_eq!(
+        divide(5, 2),
+        [
+            Span {
+                index: 0,
+                start: 0,
+                length: 2
+            },
+            Span {
+                index: 0,
+                start: 0,
+                length: 2
+            },
+            Span {
+                index: 1,
+                start: 2,
+                length: 3
+            },
+            Span {
+                index: 1,
+                start: 2,
+                length: 3
+            },
+            Span {
+                index: 1,
+                start: 2,
+                length: 3
+            },
+        ]
+    );
+    let atlas = Atlas {
+        grid: (4, 2),
+        upper_left: Complex::new(-2.0, 1.0),
+        lower_right: Complex::new(2.0, -1.0),
+        julia_zoom: 1.0,
+        gap: 1,
+    };
+    assert_eq!(atlas.parameter((40, 20), (0, 0)), Complex::new(-1.5, 0.5));
+    assert_eq!(atlas.parameter((40, 20), (3, 1)), Complex::new(1.5, -0.5));
+
+    // 每个格子都是以格子中心为常数的朱利亚集，左侧和上方留出分隔线
+    let bounds = (40, 20);
+    let limit = 100;
+    let iterations = render(&atlas, limit, false, bounds, &Progress::hidden());
+    let interior = encode_escape(None);
+    assert_eq!(iterations[10], interior);
+    assert_eq!(iterations[10 * 40 + 20], interior);
+    let c = atlas.parameter(bounds, (1, 1));
+    let (upper_left, lower_right) = corners_from_center((9, 9), Complex::new(0.0, 0.0), 1.0);
+    let transform = PixelTransform::from_corners((9, 9), upper_left, lower_right);
+    for (column, row) in [(0, 0), (4, 4), (8, 3)] {
+        let expected = Fractal::Julia(c)
+            .escape_time(transform.point((column, row)), limit)
+            .map(|i| i as f64);
+        let pixel = (11 + row) * 40 + 11 + column;
+        assert_eq!(iterations[pixel], encode_escape(expected));
+    }
+}
+
+/// 并行渲染 `bounds` 大小的朱利亚集图谱，返回逐行排列的迭代缓冲区，逃逸次数按
+/// `encode_escape` 编码，可以直接用 `colorize` 着色
+///
+/// `smooth` 为真时计算连续逃逸时间。每完成一行就在 `progress` 上记录一次。
+pub fn render(
+    atlas: &Atlas,
+    limit: usize,
+    smooth: bool,
+    bounds: (usize, usize),
+    progress: &Progress,
+) -> Vec<u32> {
+    let columns = divide(bounds.0, atlas.grid.0);
+    let rows = divide(bounds.1, atlas.grid.1);
+    // 第一列和第一行的格子不需要在外侧留出分隔线
+    let inset = |span: &Span| if span.index == 0 { 0 } else { atlas.gap };
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    progress.start(bounds.1, "rows");
+    iterations
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(y, band)| {
+            let row = rows[y];
+            let top = inset(&row);
+            // 同一个格子里的像素共用一个分形和一个像素变换，换到下一个格子时才重新计算
+            let mut cell: Option<(usize, Fractal, PixelTransform<f64>)> = None;
+            for (x, (value, column)) in band.iter_mut().zip(&columns).enumerate() {
+                let left = inset(column);
+                let local = (x - column.start, y - row.start);
+                if local.0 < left || local.1 < top {
+                    *value = encode_escape(None);
+                    continue;
+                }
+                if cell.is_none_or(|(index, _, _)| index != column.index) {
+                    let size = (column.length - left, row.length - top);
+                    let (upper_left, lower_right) =
+                        corners_from_center(size, Complex::new(0.0, 0.0), atlas.julia_zoom);
+                    let c = atlas.parameter(bounds, (column.index, row.index));
+                    cell = Some((
+                        column.index,
+                        Fractal::Julia(c),
+                        PixelTransform::from_corners(size, upper_left, lower_right),
+                    ));
+                }
+                let (_, fractal, transform) = cell.expect("the cell is set above");
+                let point = transform.point((local.0 - left, local.1 - top));
+                *value = encode_escape(if smooth {
+                    fractal.smooth_escape_time(point, limit)
+                } else {
+                    fractal.escape_time(point, limit).map(|i| i as f64)
+                });
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    iterations
+}
@@ -0,0 +1,168 @@
+//! 可配置的逃逸半径和逃逸判定的范数
+//!
+//! 内置着色方式各自选定逃逸半径：整数逃逸次数只需要 2，连续着色、距离估计和三角不等式
+//! 平均用大得多的半径，使逃逸后的修正项更准确。`WithBailout` 包装任意着色方式，改用给定
+//! 的半径和范数判定逃逸：更大的半径让连续着色和依赖轨道统计的着色更平滑；按实部或
+//! Manhattan 范数判定则会在外部留下 Fractint 风格的条纹。
+//!
+//! 半径不能小于 2，否则会把集合内部的点误判为逃逸。按 Manhattan 范数判定时
+//! `|Re z| + |Im z|` 可能在 `|z|` 不到 2 时就超过半径，集合边缘因此略有变形，这正是它的
+//! 风格效果。
+
+use crate::colorizer::{Colorizer, PointResult};
+use crate::evaluator::PointEvaluator;
+use crate::real::Real;
+use num::Complex;
+
+/// 逃逸判定使用的范数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Norm {
+    /// `|z|`
+    Modulus,
+    /// `|Re z|`
+    Real,
+    /// `|Re z| + |Im z|`
+    Manhattan,
+}
+
+/// 把字符串 `s` 解析为范数：`modulus`、`real` 或 `manhattan`
+pub fn parse_norm(s: &str) -> Option<Norm> {
+    match s {
+        "modulus" => Some(Norm::Modulus),
+        "real" => Some(Norm::Real),
+        "manhattan" => Some(Norm::Manhattan),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_norm() {
+    assert_eq!(parse_norm("modulus"), Some(Norm::Modulus));
+    assert_eq!(parse_norm("real"), Some(Norm::Real));
+    assert_eq!(parse_norm("manhattan"), Some(Norm::Manhattan));
+    assert_eq!(parse_norm("euclid"), None);
+}
+
+/// 逃逸判定：`z` 按 `norm` 的大小超过 `radius` 时逃逸
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bailout {
+    pub radius: f64,
+    pub norm: Norm,
+}
+
+impl Bailout {
+    /// `z` 是否已经逃逸
+    pub fn escaped<T: Real>(&self, z: Complex<T>) -> bool {
+        let radius = T::of(self.radius);
+        match self.norm {
+            Norm::Modulus => z.norm_sqr() > radius * radius,
+            Norm::Real => z.re.abs() > radius,
+            Norm::Manhattan => z.re.abs() + z.im.abs() > radius,
+        }
+    }
+}
+
+/// 改用 `bailout` 判定逃逸的着色方式 `colorizer`
+///
+/// 总是用 `PointEvaluator::evaluate` 迭代，不使用内置着色方式优化过的迭代。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WithBailout<C> {
+    pub colorizer: C,
+    pub bailout: Bailout,
+}
+
+impl<C: Colorizer> Colorizer for WithBailout<C> {
+    type Stats = C::Stats;
+
+    fn bailout(&self) -> f64 {
+        self.bailout.radius * self.bailout.radius
+    }
+
+    fn escaped<T: Real>(&self, z: Complex<T>) -> bool {
+        self.bailout.escaped(z)
+    }
+
+    fn needs_derivative(&self) -> bool {
+        self.colorizer.needs_derivative()
+    }
+
+    fn colors_interior(&self) -> bool {
+        self.colorizer.colors_interior()
+    }
+
+    /// 三种范数都不随共轭改变
+    fn is_symmetric(&self) -> bool {
+        self.colorizer.is_symmetric()
+    }
+
+    fn observe(&self, stats: &mut C::Stats, z: Complex<f64>, next: Complex<f64>, c: Complex<f64>) {
+        self.colorizer.observe(stats, z, next, c)
+    }
+
+    fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        result: &PointResult,
+        stats: &C::Stats,
+        limit: usize,
+        spacing: f64,
+    ) -> Option<f64> {
+        self.colorizer.value(fractal, result, stats, limit, spacing)
+    }
+}
+
+#[test]
+fn test_with_bailout() {
+    use crate::{Coloring, Fractal};
+
+    let fractal = Fractal::Mandelbrot;
+    let escaping = Complex::new(0.4, 0.6);
+    let with = |coloring, radius, norm| WithBailout {
+        colorizer: coloring,
+        bailout: Bailout { radius, norm },
+    };
+
+    // 半径为 2、按模判定时与内置的逃逸次数相同
+    let escape_time = with(Coloring::EscapeTime, 2.0, Norm::Modulus);
+    assert_eq!(
+        escape_time.escape_value(&fractal, escaping, 500, 0.01),
+        fractal.escape_time(escaping, 500).map(|count| count as f64)
+    );
+    // 更大的半径需要更多次迭代才逃逸，按实部判定比按模判定晚，按 Manhattan 范数判定比按模早
+    let count = |coloring: WithBailout<Coloring>| {
+        coloring
+            .escape_value(&fractal, escaping, 500, 0.01)
+            .unwrap()
+    };
+    let modulus = count(with(Coloring::EscapeTime, 1e3, Norm::Modulus));
+    assert!(modulus > count(escape_time));
+    assert!(count(with(Coloring::EscapeTime, 1e3, Norm::Real)) >= modulus);
+    assert!(count(with(Coloring::EscapeTime, 1e3, Norm::Manhattan)) <= modulus);
+    // 集合内部的点不论怎样判定都不逃逸
+    let inside = Complex::new(-0.12, 0.75);
+    for norm in [Norm::Modulus, Norm::Real, Norm::Manhattan] {
+        assert_eq!(
+            with(Coloring::Smooth, 2.0, norm).escape_value(&fractal, inside, 500, 0.01),
+            None
+        );
+    }
+
+    // 连续逃逸时间与逃逸半径无关，半径越大越接近理想值
+    let smooth = |radius| {
+        with(Coloring::Smooth, radius, Norm::Modulus)
+            .escape_value(&fractal, escaping, 500, 0.01)
+            .unwrap()
+    };
+    let ideal = smooth(1e8);
+    assert!((smooth(1e4) - ideal).abs() < 1e-9);
+    assert!((smooth(256.0) - ideal).abs() < 1e-6);
+    assert!((smooth(2.0) - ideal).abs() > 0.01);
+    assert!((smooth(256.0) - fractal.smooth_escape_time(escaping, 500).unwrap()).abs() < 1e-9);
+
+    // 三角不等式平均按实际的逃逸半径插值，半径相同时与内置的结果一致
+    let tia = with(Coloring::TriangleInequality, 1e6, Norm::Modulus);
+    assert_eq!(
+        tia.escape_value(&fractal, escaping, 500, 0.01),
+        Coloring::TriangleInequality.escape_value(&fractal, escaping, 500, 0.01)
+    );
+}
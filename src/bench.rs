@@ -0,0 +1,406 @@
+//! 渲染性能的基准测试
+//!
+//! `mandelbrot bench` 在固定的尺寸和迭代次数下渲染一组标准视图，依次比较几种并行方式：
+//!
+//! - `serial`：单线程逐行渲染
+//! - `crossbeam`：把图像按行均分为与线程数相同的几个横带，每个横带一个 crossbeam 线程
+//! - `rayon-rows`：每一行作为一个 rayon 任务，由窃取式调度分配
+//! - `rayon-tiles`：`render_parallel` 使用的二维分块
+//!
+//! 每种并行方式在 1 到 N 个线程下各测一次（`serial` 只测单线程），启用 `simd` 特性并且
+//! CPU 支持 AVX2 时再分别测标量和向量化的迭代。结果以每秒像素数以及相对于同一视图
+//! 标量 `serial` 的加速比给出。
+//!
+//! `mandelbrot bench --coordinates` 只测量把像素映射为复平面上的点的开销，比较逐个像素
+//! 调用 `pixed_to_point` 与 `render` 使用的按行起点、按列偏移相加的方式，参见 `Mapping`。
+
+use crate::location;
+use crate::npy::json_string;
+use crate::progress::Progress;
+use crate::tile::Tile;
+use crate::{
+    corners_from_center, parse_complex, pixed_to_point, render_parallel, render_tile, Coloring,
+    Fractal, PixelTransform,
+};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use std::hint::black_box;
+use std::time::Instant;
+
+/// 基准测试渲染的内置位置
+pub const SUITE: &[&str] = &["full", "seahorse", "elephant"];
+
+/// `rayon-tiles` 使用的分块尺寸，与 `render --tile` 的默认值相同
+const TILE: (usize, usize) = (64, 64);
+
+/// 一种并行渲染方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    Serial,
+    Crossbeam,
+    RayonRows,
+    RayonTiles,
+}
+
+impl Strategy {
+    /// 参与比较的全部方式
+    pub const ALL: [Strategy; 4] = [
+        Strategy::Serial,
+        Strategy::Crossbeam,
+        Strategy::RayonRows,
+        Strategy::RayonTiles,
+    ];
+
+    /// 报告中使用的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Serial => "serial",
+            Strategy::Crossbeam => "crossbeam",
+            Strategy::RayonRows => "rayon-rows",
+            Strategy::RayonTiles => "rayon-tiles",
+        }
+    }
+}
+
+/// 用 `threads` 个线程按 `strategy` 把曼德博集的逃逸次数渲染到 `iterations` 中
+///
+/// 其余参数的含义与 `render` 相同。rayon 的两种方式在一个新建的、大小为 `threads` 的
+/// 线程池中运行，不受全局线程池配置的影响；`serial` 忽略 `threads`。
+pub fn render_with(
+    strategy: Strategy,
+    threads: usize,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    let (fractal, coloring) = (Fractal::Mandelbrot, Coloring::EscapeTime);
+    let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+    // 像素坐标按整幅图像计算，各种方式的结果逐位相同
+    let band = |iterations: &mut [u32], top: usize| {
+        let tile = Tile {
+            x: 0,
+            y: top,
+            width: bounds.0,
+            height: iterations.len() / bounds.0,
+        };
+        iterations.copy_from_slice(&render_tile(&fractal, &coloring, limit, tile, transform));
+    };
+    let pool = || {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("cannot create the rayon thread pool")
+    };
+
+    match strategy {
+        Strategy::Serial => band(iterations, 0),
+        Strategy::Crossbeam => {
+            let rows_per_band = bounds.1.div_ceil(threads);
+            crossbeam::scope(|spawner| {
+                for (i, iterations) in iterations.chunks_mut(rows_per_band * bounds.0).enumerate() {
+                    spawner.spawn(move |_| band(iterations, rows_per_band * i));
+                }
+            })
+            .unwrap();
+        }
+        Strategy::RayonRows => pool().install(|| {
+            iterations
+                .par_chunks_mut(bounds.0)
+                .enumerate()
+                .for_each(|(row, iterations)| band(iterations, row));
+        }),
+        Strategy::RayonTiles => pool().install(|| {
+            render_parallel(
+                fractal,
+                coloring,
+                limit,
+                iterations,
+                bounds,
+                transform,
+                TILE,
+                false,
+                &Progress::hidden(),
+            );
+        }),
+    }
+}
+
+#[test]
+fn test_render_with() {
+    let bounds = (30, 20);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    render_with(
+        Strategy::Serial,
+        1,
+        200,
+        &mut expected,
+        bounds,
+        upper_left,
+        lower_right,
+    );
+    for strategy in Strategy::ALL {
+        for threads in [1, 3] {
+            let mut iterations = vec![0; bounds.0 * bounds.1];
+            render_with(
+                strategy,
+                threads,
+                200,
+                &mut iterations,
+                bounds,
+                upper_left,
+                lower_right,
+            );
+            assert_eq!(
+                iterations,
+                expected,
+                "{} with {} threads",
+                strategy.name(),
+                threads
+            );
+        }
+    }
+}
+
+/// 一次测量的结果
+pub struct Measurement {
+    /// 渲染的位置名称
+    pub view: String,
+    pub strategy: Strategy,
+    /// 是否使用了向量化的迭代
+    pub simd: bool,
+    pub threads: usize,
+    /// 多次渲染中最快的一次所用的秒数
+    pub seconds: f64,
+    /// 每次渲染的像素数
+    pub pixels: usize,
+    /// 相对于同一视图标量 `serial` 的加速比
+    pub speedup: f64,
+}
+
+impl Measurement {
+    /// 每秒渲染的像素数
+    pub fn pixels_per_second(&self) -> f64 {
+        self.pixels as f64 / self.seconds
+    }
+
+    /// 格式化为 JSON 对象
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"view\": {}, \"strategy\": {}, \"simd\": {}, \"threads\": {}, \"seconds\": {}, \
+             \"pixels_per_second\": {}, \"speedup\": {}}}",
+            json_string(&self.view),
+            json_string(self.strategy.name()),
+            self.simd,
+            self.threads,
+            self.seconds,
+            self.pixels_per_second(),
+            self.speedup
+        )
+    }
+}
+
+/// 基准测试的固定设置
+pub struct BenchConfig {
+    /// 图像的像素尺寸
+    pub bounds: (usize, usize),
+    /// 每个点的最大迭代次数
+    pub limit: usize,
+    /// 最多使用的线程数
+    pub max_threads: usize,
+    /// 每种组合重复渲染的次数，取最快的一次
+    pub repeat: usize,
+}
+
+/// 把 `measurements` 格式化为 JSON 文档，同时记录测量时的设置
+pub fn to_json(config: &BenchConfig, measurements: &[Measurement]) -> String {
+    let results = measurements
+        .iter()
+        .map(|measurement| format!("    {}", measurement.to_json()))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    format!(
+        "{{\n  \"width\": {},\n  \"height\": {},\n  \"max_iter\": {},\n  \"repeat\": {},\n  \
+         \"results\": [\n{}\n  ]\n}}\n",
+        config.bounds.0, config.bounds.1, config.limit, config.repeat, results
+    )
+}
+
+/// 依次测量 `SUITE` 中每个视图的每种组合，每得到一个结果就交给 `report`
+///
+/// 同一视图中标量的 `serial` 最先测量，作为计算加速比的基准。
+pub fn run(config: &BenchConfig, mut report: impl FnMut(&Measurement)) -> Vec<Measurement> {
+    #[cfg(feature = "simd")]
+    let modes = if crate::simd::available() {
+        vec![false, true]
+    } else {
+        vec![false]
+    };
+    #[cfg(not(feature = "simd"))]
+    let modes = vec![false];
+
+    let mut measurements = Vec::new();
+    for name in SUITE {
+        let location = location::find(name, &[]).expect("benchmark views are built-in locations");
+        let center = parse_complex(&location.center).expect("built-in centers are valid");
+        let (upper_left, lower_right) = corners_from_center(config.bounds, center, location.zoom);
+        let mut iterations = vec![0; config.bounds.0 * config.bounds.1];
+        let mut baseline = None;
+
+        for &simd in &modes {
+            #[cfg(feature = "simd")]
+            crate::simd::set_enabled(simd);
+            for strategy in Strategy::ALL {
+                let max_threads = if strategy == Strategy::Serial {
+                    1
+                } else {
+                    config.max_threads
+                };
+                for threads in 1..=max_threads {
+                    let seconds = (0..config.repeat.max(1))
+                        .map(|_| {
+                            let start = Instant::now();
+                            render_with(
+                                strategy,
+                                threads,
+                                config.limit,
+                                &mut iterations,
+                                config.bounds,
+                                upper_left,
+                                lower_right,
+                            );
+                            start.elapsed().as_secs_f64()
+                        })
+                        .fold(f64::INFINITY, f64::min);
+                    let baseline = *baseline.get_or_insert(seconds);
+                    let measurement = Measurement {
+                        view: name.to_string(),
+                        strategy,
+                        simd,
+                        threads,
+                        seconds,
+                        pixels: iterations.len(),
+                        speedup: baseline / seconds,
+                    };
+                    report(&measurement);
+                    measurements.push(measurement);
+                }
+            }
+        }
+    }
+    #[cfg(feature = "simd")]
+    crate::simd::set_enabled(true);
+    measurements
+}
+
+/// 把整幅图像的像素映射为复平面上的点的方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    /// 每个像素调用一次 `pixed_to_point`，每次都重新计算像素间距（含除法）
+    PerPixel,
+    /// 像素间距只算一次，每行的起点和每列的偏移各算一次，每个像素只做一次复数加法
+    Incremental,
+}
+
+impl Mapping {
+    /// 参与比较的全部方式，第一种是计算加速比的基准
+    pub const ALL: [Mapping; 2] = [Mapping::PerPixel, Mapping::Incremental];
+
+    /// 报告中使用的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mapping::PerPixel => "per-pixel",
+            Mapping::Incremental => "incremental",
+        }
+    }
+}
+
+/// 按 `mapping` 把 `bounds` 大小、覆盖 `upper_left` 到 `lower_right` 的图像的每个像素
+/// 映射为点，按从左到右、从上到下的顺序交给 `visit`
+pub fn map_points(
+    mapping: Mapping,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    mut visit: impl FnMut(Complex<f64>),
+) {
+    match mapping {
+        Mapping::PerPixel => {
+            for row in 0..bounds.1 {
+                for column in 0..bounds.0 {
+                    visit(pixed_to_point(
+                        bounds,
+                        (column, row),
+                        upper_left,
+                        lower_right,
+                    ));
+                }
+            }
+        }
+        Mapping::Incremental => {
+            let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+            let offsets = transform.column_offsets(0, bounds.0);
+            for row in 0..bounds.1 {
+                let start = transform.row_start(row);
+                for offset in &offsets {
+                    visit(start + offset);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_map_points() {
+    let bounds = (30, 20);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let collect = |mapping| {
+        let mut points = Vec::new();
+        map_points(mapping, bounds, upper_left, lower_right, |point| {
+            points.push(point)
+        });
+        points
+    };
+    let per_pixel = collect(Mapping::PerPixel);
+    assert_eq!(per_pixel.len(), bounds.0 * bounds.1);
+    assert_eq!(
+        per_pixel[bounds.0 + 2],
+        pixed_to_point(bounds, (2, 1), upper_left, lower_right)
+    );
+    assert_eq!(collect(Mapping::Incremental), per_pixel);
+}
+
+/// 单线程依次测量每种 `Mapping` 映射 `config.bounds` 大小的图像 `config.repeat` 次中
+/// 最快的一次所用的秒数，与 `Mapping::ALL` 的顺序相同
+///
+/// 映射出的点只累加到一个和中，不写入内存，测量的是计算本身而不是内存带宽。
+pub fn measure_mapping(config: &BenchConfig) -> Vec<(Mapping, f64)> {
+    let bounds = config.bounds;
+    let (upper_left, lower_right) = corners_from_center(bounds, Complex::new(-0.5, 0.0), 1.0);
+    Mapping::ALL
+        .into_iter()
+        .map(|mapping| {
+            let seconds = (0..config.repeat.max(1))
+                .map(|_| {
+                    let start = Instant::now();
+                    let mut sum = Complex::new(0.0, 0.0);
+                    map_points(
+                        mapping,
+                        black_box(bounds),
+                        black_box(upper_left),
+                        black_box(lower_right),
+                        |point| sum += point,
+                    );
+                    black_box(sum);
+                    start.elapsed().as_secs_f64()
+                })
+                .fold(f64::INFINITY, f64::min);
+            (mapping, seconds)
+        })
+        .collect()
+}
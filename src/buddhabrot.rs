@@ -0,0 +1,619 @@
+//! Buddhabrot 渲染
+//!
+//! 与逐像素判定逃逸不同，Buddhabrot 在复平面上随机选取大量的 `c`，对其中逃逸的点
+//! 把整条轨道经过的位置都记入一个密度缓冲区，最后把密度映射为亮度。每个 rayon 任务
+//! 使用独立的随机数发生器和密度缓冲区，结束后再逐个相加，避免原子操作的竞争。
+//!
+//! 反 Buddhabrot 用同样的采样和累积方式，但只累积迭代到上限仍未逃逸的轨道。这些轨道
+//! 大多收敛到吸引周期点，密度集中在少数像素上，按幂次映射亮度时其余部分几乎全黑，
+//! 通常改用对数映射（见 `Tone`）。
+//!
+//! 均匀采样的绝大多数 `c` 要么很快逃逸、轨道几乎不经过视图，要么在集合内部被丢弃，
+//! 放大到边界附近时尤其浪费。`accumulate_metropolis` 改用 Metropolis–Hastings 采样：
+//! 每个任务是一条马尔可夫链，目标分布是在“轨道被选中且至少有一个点落在视图内”的 `c`
+//! 上均匀分布。链对当前的 `c` 做小幅变异，变异后仍有贡献就接受，于是采样集中在对图像
+//! 有贡献的区域。没有贡献的 `c` 本来就不会改变密度，因此得到的密度与均匀采样成比例，
+//! 每条轨道的权重仍然都是 1。视图越小，有贡献的 `c` 越少，收敛就快得越多。
+
+use crate::in_main_cardioid_or_bulb;
+use crate::palette::Channel;
+use crate::progress::Progress;
+use num::Complex;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::ops::Range;
+
+/// 采样被分成的任务数，足够多才能让 rayon 在各线程之间均衡负载
+const CHUNKS: usize = 256;
+
+/// 采样 `c` 的范围：曼德博集完全落在以原点为中心、边长为 4 的正方形内
+const SAMPLE_SPAN: f64 = 4.0;
+
+/// Metropolis–Hastings 采样时，变异的步长相对于视图对角线长度的上限和下限，
+/// 步长在两者之间按对数均匀分布
+const MUTATION_MAX: f64 = 0.1;
+const MUTATION_MIN: f64 = 1e-4;
+
+/// 每一步不做小幅变异、而在整个采样范围内重新均匀选取 `c` 的概率，
+/// 保证马尔可夫链能够到达每一个有贡献的区域
+const JUMP_PROBABILITY: f64 = 0.2;
+
+/// 为马尔可夫链寻找有贡献的初始状态时，最多尝试的均匀采样数
+const START_TRIES: usize = 100_000;
+
+/// SplitMix64 伪随机数发生器，足以用于均匀采样，并且给定种子时结果可以复现
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// `[0, 1)` 区间内均匀分布的随机数
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_rng() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    for _ in 0..100 {
+        let x = a.next_f64();
+        assert_eq!(x, b.next_f64());
+        assert!((0.0..1.0).contains(&x));
+    }
+    assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+}
+
+/// 视图中的一个点对应的像素下标，点在视图之外时返回 `None`
+fn pixel_index(
+    bounds: (usize, usize),
+    point: Complex<f64>,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) -> Option<usize> {
+    let x = (point.re - upper_left.re) / (lower_right.re - upper_left.re) * bounds.0 as f64;
+    let y = (upper_left.im - point.im) / (upper_left.im - lower_right.im) * bounds.1 as f64;
+    if x >= 0.0 && y >= 0.0 && x < bounds.0 as f64 && y < bounds.1 as f64 {
+        Some(y as usize * bounds.0 + x as usize)
+    } else {
+        None
+    }
+}
+
+/// 哪些轨道记入密度缓冲区
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    /// 逃逸次数落在第 `k` 个区间（不含上界）内的轨道记入第 `k` 个通道
+    Escaping(Vec<Range<usize>>),
+    /// 迭代这么多次仍未逃逸的轨道记入唯一的通道，即反 Buddhabrot
+    Bounded(usize),
+}
+
+impl Selection {
+    fn channels(&self) -> usize {
+        match self {
+            Selection::Escaping(ranges) => ranges.len(),
+            Selection::Bounded(_) => 1,
+        }
+    }
+
+    fn limit(&self) -> usize {
+        match self {
+            Selection::Escaping(ranges) => ranges.iter().map(|range| range.end).max().unwrap_or(0),
+            Selection::Bounded(limit) => *limit,
+        }
+    }
+
+    /// 迭代 `c` 并把轨道记录在 `orbit` 中；轨道至少记入一个通道时返回逃逸次数
+    /// （未逃逸时为 `None`），否则返回 `None`
+    fn sample(&self, c: Complex<f64>, orbit: &mut Vec<Complex<f64>>) -> Option<Option<usize>> {
+        match self {
+            Selection::Escaping(ranges) => {
+                // 主心形和周期 2 圆盘内的点不会逃逸，直接跳过
+                if in_main_cardioid_or_bulb(c) {
+                    return None;
+                }
+                let escaped = trace(c, self.limit(), orbit)?;
+                ranges
+                    .iter()
+                    .any(|range| range.contains(&escaped))
+                    .then_some(Some(escaped))
+            }
+            Selection::Bounded(limit) => trace(c, *limit, orbit).is_none().then_some(None),
+        }
+    }
+
+    /// 逃逸次数为 `escaped` 的轨道是否记入第 `channel` 个通道
+    fn selects(&self, escaped: Option<usize>, channel: usize) -> bool {
+        match (self, escaped) {
+            (Selection::Escaping(ranges), Some(escaped)) => ranges[channel].contains(&escaped),
+            (Selection::Bounded(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// 采样 `c` 的方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// 在整个采样范围内均匀采样，见 `accumulate_selected`
+    Uniform,
+    /// Metropolis–Hastings 采样，见 `accumulate_metropolis`
+    Metropolis,
+}
+
+/// 解析 `uniform` 或 `metropolis`
+pub fn parse_sampling(s: &str) -> Option<Sampling> {
+    match s {
+        "uniform" => Some(Sampling::Uniform),
+        "metropolis" => Some(Sampling::Metropolis),
+        _ => None,
+    }
+}
+
+/// 采样范围内均匀分布的随机点
+fn uniform_point(rng: &mut Rng) -> Complex<f64> {
+    Complex {
+        re: (rng.next_f64() - 0.5) * SAMPLE_SPAN,
+        im: (rng.next_f64() - 0.5) * SAMPLE_SPAN,
+    }
+}
+
+/// 在 `[-2, 2] x [-2, 2]` 内随机选取 `samples` 个 `c`，把逃逸次数在 `iterations` 区间
+/// （不含上界）内的轨道累积到覆盖 `upper_left` 到 `lower_right` 的 `bounds` 大小的
+/// 密度缓冲区中
+///
+/// `seed` 决定采样序列，相同的参数总是得到相同的结果。每完成一个任务就在
+/// `progress` 上记录一次。
+pub fn accumulate(
+    samples: usize,
+    iterations: Range<usize>,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    seed: u64,
+    progress: &Progress,
+) -> Vec<u32> {
+    accumulate_channels(
+        samples,
+        &[iterations],
+        bounds,
+        upper_left,
+        lower_right,
+        seed,
+        progress,
+    )
+    .remove(0)
+}
+
+/// 与 `accumulate` 相同，但同时累积多个密度通道：逃逸次数落在 `channels[k]` 区间内的
+/// 轨道记入第 `k` 个缓冲区
+///
+/// 所有通道共用同一组采样，一条轨道可以同时记入多个通道。
+pub fn accumulate_channels(
+    samples: usize,
+    channels: &[Range<usize>],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    seed: u64,
+    progress: &Progress,
+) -> Vec<Vec<u32>> {
+    accumulate_selected(
+        samples,
+        &Selection::Escaping(channels.to_vec()),
+        bounds,
+        upper_left,
+        lower_right,
+        seed,
+        progress,
+    )
+}
+
+/// 与 `accumulate_channels` 相同，但由 `selection` 决定累积哪些轨道、有几个通道
+pub fn accumulate_selected(
+    samples: usize,
+    selection: &Selection,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    seed: u64,
+    progress: &Progress,
+) -> Vec<Vec<u32>> {
+    let limit = selection.limit();
+    let empty = || vec![vec![0u32; bounds.0 * bounds.1]; selection.channels()];
+    progress.start(CHUNKS, "chunks");
+    let density = (0..CHUNKS)
+        .into_par_iter()
+        .map(|chunk| {
+            let mut rng = Rng::new(seed ^ (chunk as u64).wrapping_mul(0xd134_2543_de82_ef95));
+            let mut density = empty();
+            let mut orbit = Vec::with_capacity(limit);
+            let count = samples / CHUNKS + usize::from(chunk < samples % CHUNKS);
+            for _ in 0..count {
+                let c = uniform_point(&mut rng);
+                let Some(escaped) = selection.sample(c, &mut orbit) else {
+                    continue;
+                };
+                for (k, channel) in density.iter_mut().enumerate() {
+                    if selection.selects(escaped, k) {
+                        add_orbit(channel, &orbit, bounds, upper_left, lower_right);
+                    }
+                }
+            }
+            progress.inc(1);
+            density
+        })
+        .reduce(empty, sum_density);
+    progress.finish();
+    density
+}
+
+/// 与 `accumulate_selected` 相同，但用 Metropolis–Hastings 采样：每个任务是一条马尔可夫链，
+/// 所有链共走 `samples` 步
+///
+/// 每一步把链的当前状态的轨道累积一次，得到的密度与均匀采样的期望成比例。
+pub fn accumulate_metropolis(
+    samples: usize,
+    selection: &Selection,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    seed: u64,
+    progress: &Progress,
+) -> Vec<Vec<u32>> {
+    let limit = selection.limit();
+    let empty = || vec![vec![0u32; bounds.0 * bounds.1]; selection.channels()];
+    let diagonal = (lower_right - upper_left).norm();
+    // 轨道被选中并且经过视图时返回逃逸次数
+    let evaluate = |c: Complex<f64>, orbit: &mut Vec<Complex<f64>>| {
+        let escaped = selection.sample(c, orbit)?;
+        orbit
+            .iter()
+            .any(|&z| pixel_index(bounds, z, upper_left, lower_right).is_some())
+            .then_some(escaped)
+    };
+    progress.start(CHUNKS, "chunks");
+    let density = (0..CHUNKS)
+        .into_par_iter()
+        .map(|chunk| {
+            let mut rng = Rng::new(seed ^ (chunk as u64).wrapping_mul(0xd134_2543_de82_ef95));
+            let mut density = empty();
+            let mut orbit = Vec::with_capacity(limit);
+            let start = (0..START_TRIES).find_map(|_| {
+                let c = uniform_point(&mut rng);
+                evaluate(c, &mut orbit).map(|escaped| (c, escaped))
+            });
+            // 找不到有贡献的点时这条链什么也不累积
+            let Some((mut c, mut escaped)) = start else {
+                progress.inc(1);
+                return density;
+            };
+            let mut current = orbit.clone();
+            let count = samples / CHUNKS + usize::from(chunk < samples % CHUNKS);
+            for _ in 0..count {
+                let proposal = if rng.next_f64() < JUMP_PROBABILITY {
+                    uniform_point(&mut rng)
+                } else {
+                    let radius = diagonal
+                        * MUTATION_MAX
+                        * (MUTATION_MIN / MUTATION_MAX).powf(rng.next_f64());
+                    let angle = rng.next_f64() * std::f64::consts::TAU;
+                    c + Complex::from_polar(radius, angle)
+                };
+                // 两种提议都是对称的，目标分布在有贡献的点上处处相等，有贡献就接受
+                if let Some(new_escaped) = evaluate(proposal, &mut orbit) {
+                    (c, escaped) = (proposal, new_escaped);
+                    std::mem::swap(&mut current, &mut orbit);
+                }
+                for (k, channel) in density.iter_mut().enumerate() {
+                    if selection.selects(escaped, k) {
+                        add_orbit(channel, &current, bounds, upper_left, lower_right);
+                    }
+                }
+            }
+            progress.inc(1);
+            density
+        })
+        .reduce(empty, sum_density);
+    progress.finish();
+    density
+}
+
+/// 把轨道经过视图的每个点记入密度缓冲区 `channel`
+fn add_orbit(
+    channel: &mut [u32],
+    orbit: &[Complex<f64>],
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+) {
+    for &z in orbit {
+        if let Some(index) = pixel_index(bounds, z, upper_left, lower_right) {
+            channel[index] = channel[index].saturating_add(1);
+        }
+    }
+}
+
+/// 把两个任务的密度缓冲区逐通道相加
+fn sum_density(mut total: Vec<Vec<u32>>, density: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+    for (total, channel) in total.iter_mut().zip(density) {
+        for (sum, value) in total.iter_mut().zip(channel) {
+            *sum = sum.saturating_add(value);
+        }
+    }
+    total
+}
+
+/// 从原点出发迭代 `z = z * z + c`，把逃逸之前的轨道记录在 `orbit` 中
+///
+/// 在 `limit` 次迭代内逃逸时返回逃逸次数，否则返回 `None`
+fn trace(c: Complex<f64>, limit: usize, orbit: &mut Vec<Complex<f64>>) -> Option<usize> {
+    orbit.clear();
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for i in 0..limit {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0 {
+            return Some(i + 1);
+        }
+        orbit.push(z);
+    }
+    None
+}
+
+/// 把密度缓冲区归一化为 `[0, 1]` 区间内的亮度，`gamma` 小于 1 时提亮暗部
+pub fn normalize(density: &[u32], gamma: f64) -> Vec<f64> {
+    let max = density.iter().copied().max().unwrap_or(0).max(1) as f64;
+    density
+        .iter()
+        .map(|&value| (value as f64 / max).powf(gamma))
+        .collect()
+}
+
+/// 密度到亮度的映射方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tone {
+    /// 按最大密度归一化后取 `gamma` 次幂，适合 Buddhabrot
+    Gamma,
+    /// 先取 `ln(1 + 密度)` 再归一化和取幂，压缩反 Buddhabrot 中极高的峰值
+    Log,
+}
+
+/// 解析 `gamma` 或 `log`
+pub fn parse_tone(s: &str) -> Option<Tone> {
+    match s {
+        "gamma" => Some(Tone::Gamma),
+        "log" => Some(Tone::Log),
+        _ => None,
+    }
+}
+
+/// 按 `tone` 把密度缓冲区映射为 `[0, 1]` 区间内的亮度
+pub fn tone_map(density: &[u32], tone: Tone, gamma: f64) -> Vec<f64> {
+    match tone {
+        Tone::Gamma => normalize(density, gamma),
+        Tone::Log => {
+            let max = density.iter().copied().max().unwrap_or(0) as f64;
+            let scale = max.ln_1p().max(f64::MIN_POSITIVE);
+            density
+                .iter()
+                .map(|&value| ((value as f64).ln_1p() / scale).powf(gamma))
+                .collect()
+        }
+    }
+}
+
+/// 把三个通道的亮度合成为 8 位或 16 位的 RGB 像素，每个通道单独归一化
+pub fn combine_rgb<C: Channel>(channels: &[Vec<u32>; 3], tone: Tone, gamma: f64) -> Vec<C> {
+    let [red, green, blue] = channels
+        .each_ref()
+        .map(|density| tone_map(density, tone, gamma));
+    red.iter()
+        .zip(&green)
+        .zip(&blue)
+        .flat_map(|((&r, &g), &b)| [r, g, b].map(C::from_fraction))
+        .collect()
+}
+
+/// 把形如 `MIN..MAX` 的字符串解析为逃逸次数区间，要求 `MIN < MAX`
+pub fn parse_range(s: &str) -> Option<Range<usize>> {
+    let (min, max) = s.split_once("..")?;
+    let range = min.parse().ok()?..max.parse().ok()?;
+    (!range.is_empty()).then_some(range)
+}
+
+/// 把形如 `0..5000,0..500,0..50` 的字符串解析为红、绿、蓝三个通道的逃逸次数区间
+pub fn parse_channels(s: &str) -> Option<[Range<usize>; 3]> {
+    let mut ranges = s.split(',').map(parse_range);
+    let channels = [ranges.next()??, ranges.next()??, ranges.next()??];
+    ranges.next().is_none().then_some(channels)
+}
+
+#[test]
+fn test_parse_channels() {
+    assert_eq!(parse_range("10..200"), Some(10..200));
+    assert_eq!(parse_range("5..5"), None);
+    assert_eq!(parse_range("5"), None);
+    assert_eq!(
+        parse_channels("0..5000,0..500,0..50"),
+        Some([0..5000, 0..500, 0..50])
+    );
+    assert_eq!(parse_channels("0..5000,0..500"), None);
+    assert_eq!(parse_channels("0..5000,0..500,0..50,0..5"), None);
+    assert_eq!(parse_channels("0..5000,0..500,x"), None);
+}
+
+#[test]
+fn test_normalize() {
+    assert_eq!(normalize(&[0, 4, 16], 0.5), [0.0, 0.5, 1.0]);
+    assert_eq!(normalize(&[0, 0], 1.0), [0.0, 0.0]);
+    assert_eq!(tone_map(&[0, 4, 16], Tone::Gamma, 0.5), [0.0, 0.5, 1.0]);
+    let log = tone_map(&[0, 1, 99], Tone::Log, 1.0);
+    assert_eq!(log[0], 0.0);
+    assert!((log[1] - 2f64.ln() / 100f64.ln()).abs() < 1e-12);
+    assert_eq!(log[2], 1.0);
+    assert_eq!(tone_map(&[0, 0], Tone::Log, 1.0), [0.0, 0.0]);
+    assert_eq!(parse_tone("log"), Some(Tone::Log));
+    assert_eq!(parse_tone("linear"), None);
+    assert_eq!(
+        combine_rgb::<u8>(&[vec![0, 2], vec![4, 4], vec![0, 0]], Tone::Gamma, 1.0),
+        [0, 255, 0, 255, 255, 0]
+    );
+    assert_eq!(
+        combine_rgb::<u16>(&[vec![0, 2], vec![4, 4], vec![0, 0]], Tone::Gamma, 1.0),
+        [0, 65535, 0, 65535, 65535, 0]
+    );
+}
+
+#[test]
+fn test_accumulate() {
+    let bounds = (40, 40);
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+    let render = |seed| {
+        accumulate(
+            200000,
+            0..100,
+            bounds,
+            upper_left,
+            lower_right,
+            seed,
+            &Progress::hidden(),
+        )
+    };
+    let density = render(7);
+    assert_eq!(density, render(7));
+
+    // 多通道累积时每个通道与单独累积的结果相同
+    let channels = accumulate_channels(
+        200000,
+        &[0..100, 0..10],
+        bounds,
+        upper_left,
+        lower_right,
+        7,
+        &Progress::hidden(),
+    );
+    assert_eq!(channels[0], density);
+    assert!(channels[1].iter().zip(&density).all(|(a, b)| a <= b));
+    assert!(density.iter().any(|&value| value > 0));
+
+    // 轨道关于实轴对称
+    let mirrored: u64 = (0..bounds.1)
+        .map(|y| {
+            (0..bounds.0)
+                .map(|x| {
+                    density[y * bounds.0 + x].abs_diff(density[(bounds.1 - 1 - y) * bounds.0 + x])
+                        as u64
+                })
+                .sum::<u64>()
+        })
+        .sum();
+    let total: u64 = density.iter().map(|&value| value as u64).sum();
+    assert!(mirrored * 5 < total, "{} vs {}", mirrored, total);
+}
+
+#[test]
+fn test_accumulate_bounded() {
+    let bounds = (40, 40);
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+    let density = accumulate_selected(
+        100000,
+        &Selection::Bounded(100),
+        bounds,
+        upper_left,
+        lower_right,
+        7,
+        &Progress::hidden(),
+    )
+    .remove(0);
+    assert!(density.iter().any(|&value| value > 0));
+
+    // 不逃逸的轨道始终在半径为 2 的圆内，四个角上的像素没有密度
+    for (x, y) in [(0, 0), (39, 0), (0, 39), (39, 39)] {
+        assert_eq!(density[y * bounds.0 + x], 0);
+    }
+    // 主心形内的点收敛到不动点，密度集中在心形内部
+    let inside = pixel_index(
+        bounds,
+        Complex { re: -0.1, im: 0.0 },
+        upper_left,
+        lower_right,
+    );
+    let outside = pixel_index(
+        bounds,
+        Complex { re: 0.45, im: 0.0 },
+        upper_left,
+        lower_right,
+    );
+    assert!(density[inside.unwrap()] > density[outside.unwrap()]);
+}
+
+#[test]
+fn test_accumulate_metropolis() {
+    let bounds = (16, 16);
+    let upper_left = Complex { re: -0.9, im: 0.35 };
+    let lower_right = Complex { re: -0.6, im: 0.05 };
+    let selection = Selection::Escaping(std::iter::once(0..100).collect());
+    let normalized = |density: Vec<Vec<u32>>| {
+        let total: f64 = density[0].iter().map(|&value| value as f64).sum();
+        density[0]
+            .iter()
+            .map(|&value| value as f64 / total)
+            .collect::<Vec<_>>()
+    };
+    let uniform = |samples, seed| {
+        normalized(accumulate_selected(
+            samples,
+            &selection,
+            bounds,
+            upper_left,
+            lower_right,
+            seed,
+            &Progress::hidden(),
+        ))
+    };
+    let metropolis = |seed| {
+        accumulate_metropolis(
+            100_000,
+            &selection,
+            bounds,
+            upper_left,
+            lower_right,
+            seed,
+            &Progress::hidden(),
+        )
+    };
+    let density = metropolis(5);
+    assert_eq!(density, metropolis(5));
+
+    // 放大的视图中，同样的采样数下 Metropolis–Hastings 的密度比均匀采样更接近参考图像
+    let reference = uniform(2_000_000, 1);
+    let error = |density: &[f64]| -> f64 {
+        density
+            .iter()
+            .zip(&reference)
+            .map(|(a, b)| (a - b).abs())
+            .sum()
+    };
+    let metropolis_error = error(&normalized(density));
+    let uniform_error = error(&uniform(100_000, 5));
+    assert!(
+        metropolis_error < uniform_error,
+        "{} vs {}",
+        metropolis_error,
+        uniform_error
+    );
+}
@@ -0,0 +1,141 @@
+//! 限时渲染
+//!
+//! `render --budget 30s` 在给定的时间内渲染尽可能好的图像：先以较少的迭代次数渐进地渲染
+//! （见 `progressive`），从 1/8 的分辨率细化到全分辨率，再逐次加倍迭代次数重新渲染，直到
+//! `--max-iter`。每个阶段开始之前按上一个阶段的耗时估计它需要的时间，来不及完成就停下，
+//! 输出最后完成的阶段的结果。
+//!
+//! 估计时假定耗时与计算的像素数和迭代次数都成正比。大部分像素很早就逃逸，实际的耗时
+//! 增长得更慢，因此估计偏保守，渲染不会明显超出预算。
+
+use crate::progressive::COARSEST;
+use std::time::Duration;
+
+/// 第一个阶段的迭代次数
+pub const START_LIMIT: usize = 64;
+
+/// 解析 `30s`、`2m`、`1.5h`、`500ms` 这样的时长，没有单位时按秒计
+pub fn parse_budget(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => s.split_at(index),
+        None => (s, "s"),
+    };
+    let seconds = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    let value: f64 = number.trim().parse().ok()?;
+    (value.is_finite() && value > 0.0).then(|| Duration::from_secs_f64(value * seconds))
+}
+
+/// 限时渲染的一个阶段
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// 渐进式渲染中步长为 `step` 的一遍
+    Pass { step: usize, limit: usize },
+    /// 全分辨率的完整渲染
+    Full { limit: usize },
+}
+
+impl Stage {
+    pub fn limit(&self) -> usize {
+        match *self {
+            Stage::Pass { limit, .. } | Stage::Full { limit } => limit,
+        }
+    }
+
+    /// 这个阶段结束后图像的分辨率是全分辨率的几分之一
+    pub fn step(&self) -> usize {
+        match *self {
+            Stage::Pass { step, .. } => step,
+            Stage::Full { .. } => 1,
+        }
+    }
+
+    /// 相对的计算量：计算的像素占全部像素的比例乘以迭代次数
+    pub fn cost(&self) -> f64 {
+        let pixels = match *self {
+            Stage::Pass { step: COARSEST, .. } => 1.0 / (COARSEST * COARSEST) as f64,
+            // 之后每一遍计算步长为 step 的网格中前几遍没有算过的 3/4
+            Stage::Pass { step, .. } => 0.75 / (step * step) as f64,
+            Stage::Full { .. } => 1.0,
+        };
+        pixels * self.limit() as f64
+    }
+}
+
+/// 迭代次数最多为 `max_limit` 时的各个阶段
+///
+/// `progressive` 为假（例如超采样时）没有渐进的几遍，直接从 `START_LIMIT` 开始完整渲染。
+pub fn stages(max_limit: usize, progressive: bool) -> Vec<Stage> {
+    let mut limit = START_LIMIT.min(max_limit);
+    let mut stages = Vec::new();
+    if progressive {
+        let mut step = COARSEST;
+        while step >= 1 {
+            stages.push(Stage::Pass { step, limit });
+            step /= 2;
+        }
+        if limit == max_limit {
+            return stages;
+        }
+        limit = (limit * 2).min(max_limit);
+    }
+    loop {
+        stages.push(Stage::Full { limit });
+        if limit == max_limit {
+            return stages;
+        }
+        limit = (limit * 2).min(max_limit);
+    }
+}
+
+/// 按上一个阶段的耗时 `previous` 估计 `next` 阶段需要的时间
+pub fn predict(previous: (Stage, Duration), next: Stage) -> Duration {
+    let (stage, elapsed) = previous;
+    elapsed.mul_f64(next.cost() / stage.cost())
+}
+
+#[test]
+fn test_budget() {
+    assert_eq!(parse_budget("30s"), Some(Duration::from_secs(30)));
+    assert_eq!(parse_budget("1.5m"), Some(Duration::from_secs(90)));
+    assert_eq!(parse_budget("250ms"), Some(Duration::from_millis(250)));
+    assert_eq!(parse_budget("2"), Some(Duration::from_secs(2)));
+    assert_eq!(parse_budget("0s"), None);
+    assert_eq!(parse_budget("5 days"), None);
+
+    let pass = |step| Stage::Pass { step, limit: 64 };
+    let full = |limit| Stage::Full { limit };
+    assert_eq!(
+        stages(300, true),
+        [
+            pass(8),
+            pass(4),
+            pass(2),
+            pass(1),
+            full(128),
+            full(256),
+            full(300)
+        ]
+    );
+    assert_eq!(stages(64, true), [pass(8), pass(4), pass(2), pass(1)]);
+    assert_eq!(stages(10, false), [full(10)]);
+    assert_eq!(stages(200, false), [full(64), full(128), full(200)]);
+
+    // 四遍渐进渲染的计算量合起来正好是一次完整渲染
+    let total: f64 = stages(64, true).iter().map(Stage::cost).sum();
+    assert!((total - full(64).cost()).abs() < 1e-9);
+    assert_eq!(
+        predict((full(100), Duration::from_secs(2)), full(200)),
+        Duration::from_secs(4)
+    );
+    assert_eq!(
+        predict((pass(8), Duration::from_millis(10)), pass(4)),
+        Duration::from_millis(30)
+    );
+}
@@ -0,0 +1,245 @@
+//! 断点续算
+//!
+//! 渲染很大的图像时进程可能被中断。`render --checkpoint FILE` 每完成一个分块就把它的
+//! 逃逸值追加到检查点文件中；加上 `--resume` 重新运行同样的命令时，已保存的分块直接
+//! 从文件读入，只计算剩下的分块。文件格式（整数均为小端序）：
+//!
+//! - 4 字节魔数 `MBC1`
+//! - `u64` 渲染参数的指纹（参见 `fingerprint`），参数不同的检查点不能用来续算
+//! - 任意多条分块记录：`u32` 左上角的列和行、`u32` 宽度和高度，以及宽度乘高度个
+//!   按 `encode_escape` 编码的 `u32` 逃逸值
+//!
+//! 末尾不完整的记录（写入时进程被中断）会被忽略。
+
+use crate::tile::Tile;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"MBC1";
+
+/// 用 FNV-1a 把描述渲染参数的字符串散列为指纹
+///
+/// 与标准库的散列不同，FNV-1a 的结果不随编译器版本变化，检查点可以跨版本续算。
+pub fn fingerprint(description: &str) -> u64 {
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// 分块记录的键：左上角的列、行以及宽度和高度
+type TileKey = (usize, usize, usize, usize);
+
+fn key(tile: Tile) -> TileKey {
+    (tile.x, tile.y, tile.width, tile.height)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// 读取 `u32`，文件恰好在此结束时返回 `None`
+fn read_u32(reader: &mut impl Read) -> io::Result<Option<u32>> {
+    let mut bytes = [0; 4];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(u32::from_le_bytes(bytes))),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_header(writer: &mut impl Write, fingerprint: u64) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&fingerprint.to_le_bytes())
+}
+
+fn write_record(writer: &mut impl Write, tile: Tile, values: &[u32]) -> io::Result<()> {
+    assert_eq!(values.len(), tile.len());
+    for field in [tile.x, tile.y, tile.width, tile.height] {
+        let field = u32::try_from(field).map_err(|_| invalid("tile does not fit in u32"))?;
+        writer.write_all(&field.to_le_bytes())?;
+    }
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// 读取检查点中的全部完整记录，指纹与 `fingerprint` 不一致或者记录的分块比 `tile` 大时报错
+fn read_records(
+    reader: &mut impl Read,
+    fingerprint: u64,
+    tile: (usize, usize),
+) -> io::Result<HashMap<TileKey, Vec<u32>>> {
+    let mut magic = [0; 4];
+    let mut saved = [0; 8];
+    reader.read_exact(&mut magic)?;
+    reader.read_exact(&mut saved)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a mandelbrot checkpoint file"));
+    }
+    if u64::from_le_bytes(saved) != fingerprint {
+        return Err(invalid(
+            "checkpoint was written by a render with different parameters",
+        ));
+    }
+
+    let mut records = HashMap::new();
+    'records: loop {
+        let mut fields = [0; 4];
+        for field in &mut fields {
+            match read_u32(reader)? {
+                Some(value) => *field = value as usize,
+                None => break 'records,
+            }
+        }
+        let [x, y, width, height] = fields;
+        // 先检查分块的尺寸，损坏的文件不能让这里分配任意大的缓冲区
+        if width > tile.0 || height > tile.1 {
+            return Err(invalid("checkpoint tile is larger than the tile size"));
+        }
+        let mut values = Vec::with_capacity(width * height);
+        for _ in 0..width * height {
+            match read_u32(reader)? {
+                Some(value) => values.push(value),
+                None => break 'records,
+            }
+        }
+        records.insert((x, y, width, height), values);
+    }
+    Ok(records)
+}
+
+#[test]
+fn test_read_records() {
+    let tile = Tile {
+        x: 64,
+        y: 0,
+        width: 2,
+        height: 1,
+    };
+    let mut bytes = Vec::new();
+    write_header(&mut bytes, 42).unwrap();
+    write_record(&mut bytes, tile, &[7, 8]).unwrap();
+    let complete = bytes.len();
+    write_record(&mut bytes, Tile { x: 0, ..tile }, &[1, 2]).unwrap();
+
+    // 被中断的最后一条记录被忽略
+    let records = read_records(&mut &bytes[..bytes.len() - 1], 42, (64, 64)).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[&key(tile)], [7, 8]);
+    assert_eq!(
+        read_records(&mut &bytes[..complete], 42, (64, 64))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        read_records(&mut &bytes[..], 42, (64, 64)).unwrap().len(),
+        2
+    );
+
+    // 比分块大的记录说明文件损坏，不按它分配缓冲区
+    assert!(read_records(&mut &bytes[..], 42, (1, 64)).is_err());
+    let mut huge = Vec::new();
+    write_header(&mut huge, 42).unwrap();
+    for field in [0, 0, u32::MAX, u32::MAX] {
+        huge.extend_from_slice(&field.to_le_bytes());
+    }
+    assert!(read_records(&mut &huge[..], 42, (64, 64)).is_err());
+
+    assert!(read_records(&mut &bytes[..], 43, (64, 64)).is_err());
+    bytes[0] = b'X';
+    assert!(read_records(&mut &bytes[..], 42, (64, 64)).is_err());
+}
+
+/// 一次渲染的检查点：已经完成的分块以及追加新分块的文件
+pub struct Checkpoint {
+    path: PathBuf,
+    completed: HashMap<TileKey, Vec<u32>>,
+    writer: Mutex<BufWriter<File>>,
+    /// 追加分块时遇到的第一个错误，之后不再写入
+    error: Mutex<Option<io::Error>>,
+}
+
+impl Checkpoint {
+    /// 打开 `path` 处的检查点，渲染按 `tile` 大小的分块进行
+    ///
+    /// `resume` 为真且文件存在时读入其中已完成的分块，文件由指纹不同的渲染写入或者记录了
+    /// 比 `tile` 大的分块时报错；否则从空的检查点开始。无论哪种情况都会重写文件，去掉末尾
+    /// 不完整的记录。
+    pub fn open(
+        path: &Path,
+        fingerprint: u64,
+        tile: (usize, usize),
+        resume: bool,
+    ) -> io::Result<Checkpoint> {
+        let completed = match File::open(path) {
+            Ok(file) if resume => read_records(&mut BufReader::new(file), fingerprint, tile)?,
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => HashMap::new(),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_header(&mut writer, fingerprint)?;
+        let mut tiles: Vec<&TileKey> = completed.keys().collect();
+        tiles.sort();
+        for &(x, y, width, height) in tiles {
+            let tile = Tile {
+                x,
+                y,
+                width,
+                height,
+            };
+            write_record(&mut writer, tile, &completed[&(x, y, width, height)])?;
+        }
+        writer.flush()?;
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            completed,
+            writer: Mutex::new(writer),
+            error: Mutex::new(None),
+        })
+    }
+
+    /// 检查点中已完成的分块数
+    pub fn len(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// 检查点中是否没有任何已完成的分块
+    pub fn is_empty(&self) -> bool {
+        self.completed.is_empty()
+    }
+
+    /// 分块 `tile` 已保存的逃逸值
+    pub fn completed(&self, tile: Tile) -> Option<&[u32]> {
+        self.completed.get(&key(tile)).map(Vec::as_slice)
+    }
+
+    /// 把新完成的分块追加到文件中并立即写入磁盘
+    ///
+    /// 写入失败不会中断渲染，错误由 `finish` 报告。
+    pub fn save(&self, tile: Tile, values: &[u32]) {
+        let mut error = self.error.lock().unwrap();
+        if error.is_some() {
+            return;
+        }
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = write_record(&mut *writer, tile, values).and_then(|_| writer.flush()) {
+            *error = Some(err);
+        }
+    }
+
+    /// 渲染完成后删除检查点文件，追加分块时出过错则返回该错误
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(err) = self.error.into_inner().unwrap() {
+            return Err(err);
+        }
+        drop(self.writer);
+        std::fs::remove_file(&self.path)
+    }
+}
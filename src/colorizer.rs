@@ -0,0 +1,446 @@
+//! 可替换的着色方式
+//!
+//! 渲染时每个像素先迭代出一个结果（逃逸次数、最后的 `z`、需要时还有导数），迭代过程中
+//! 可以顺便累积轨道统计，最后由着色方式把它们换算为交给调色板的逃逸值。`Colorizer`
+//! 描述了这几步：需要多大的逃逸半径、是否要迭代导数、每一步记录什么、结束后怎样换算。
+//! 内置的 `Coloring` 实现了它；库的使用者实现这个 trait 就能把自己的着色方式传给
+//! `render` 或 `render_parallel`，不必改动渲染的代码。
+//!
+//! ```
+//! use mandelbrot::colorizer::{Colorizer, PointResult};
+//! use mandelbrot::evaluator::PointEvaluator;
+//!
+//! /// 按逃逸时 `z` 的辐角着色
+//! struct Angle;
+//!
+//! impl Colorizer for Angle {
+//!     type Stats = ();
+//!
+//!     fn value<F: PointEvaluator>(
+//!         &self,
+//!         _: &F,
+//!         result: &PointResult,
+//!         _: &(),
+//!         limit: usize,
+//!         _: f64,
+//!     ) -> Option<f64> {
+//!         result.iterations?;
+//!         let turn = result.final_z.arg() / std::f64::consts::TAU + 0.5;
+//!         Some(turn * limit as f64)
+//!     }
+//! }
+//! ```
+
+use crate::evaluator::PointEvaluator;
+use crate::interior::{period_value, AtomDomain};
+use crate::real::Real;
+use crate::tia::{self, Average};
+use crate::{distance_value, smooth_value, trap, Coloring, DISTANCE_BAILOUT, SMOOTH_BAILOUT};
+use num::Complex;
+
+/// 一个点的迭代结果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointResult {
+    /// 逃逸时的迭代次数，达到上限仍未逃逸时为 `None`
+    pub iterations: Option<usize>,
+    /// 最后得到的 `z`，逃逸时是第一个超出逃逸半径的值
+    pub final_z: Complex<f64>,
+    /// `z` 对参数的导数（曼德博类分形对 `c` 求导，朱利亚集对起点求导），
+    /// 着色方式不需要时为零
+    pub dz: Complex<f64>,
+    /// 迭代公式中 `z` 的次数，用来计算连续逃逸时间
+    pub degree: f64,
+    /// 迭代时使用的逃逸半径的平方
+    pub bailout: f64,
+}
+
+impl PointResult {
+    /// 由逃逸次数、最后的 `z` 和导数构造迭代结果，迭代公式中 `z` 的次数为 `degree`，
+    /// 逃逸半径的平方为 `bailout`
+    pub fn new(
+        iterations: Option<usize>,
+        final_z: Complex<f64>,
+        dz: Complex<f64>,
+        degree: f64,
+        bailout: f64,
+    ) -> PointResult {
+        PointResult {
+            iterations,
+            final_z,
+            dz,
+            degree,
+            bailout,
+        }
+    }
+
+    /// 连续逃逸时间，参见 `smooth_escape_time`；逃逸半径越大越接近理想的连续值
+    ///
+    /// 只有连续着色用得到，需要时才计算，省去其他着色方式每个像素的两次对数。
+    pub fn smooth(&self) -> Option<f64> {
+        self.iterations
+            .map(|i| smooth_value(i, self.final_z, self.degree))
+    }
+
+    /// 按 `|z| ln|z| / |dz|` 估计的到分形的距离，未逃逸时为 `None`，参见
+    /// `Fractal::distance_estimate`；只有迭代了导数的结果才有意义
+    pub fn distance(&self) -> Option<f64> {
+        self.iterations?;
+        let modulus = self.final_z.norm_sqr().sqrt();
+        Some(modulus * modulus.ln() / self.dz.norm())
+    }
+}
+
+/// 把每个点的迭代结果换算为逃逸值的着色方式
+pub trait Colorizer: Sync {
+    /// 迭代过程中累积的轨道统计，不需要时取 `()`
+    type Stats: Default;
+
+    /// 逃逸半径的平方
+    fn bailout(&self) -> f64 {
+        4.0
+    }
+
+    /// `z` 是否已经逃逸，默认在 `|z|²` 超过 `bailout` 时逃逸
+    fn escaped<T: Real>(&self, z: Complex<T>) -> bool {
+        z.norm_sqr() > T::of(self.bailout())
+    }
+
+    /// 是否需要同时迭代导数
+    fn needs_derivative(&self) -> bool {
+        false
+    }
+
+    /// 集合内部的点是否也有颜色；为假时可以不迭代就判定的内部点直接跳过
+    fn colors_interior(&self) -> bool {
+        false
+    }
+
+    /// 每一步迭代之后调用：`z` 迭代为 `next`，`c` 是迭代公式中的常数
+    fn observe(
+        &self,
+        _stats: &mut Self::Stats,
+        _z: Complex<f64>,
+        _next: Complex<f64>,
+        _c: Complex<f64>,
+    ) {
+    }
+
+    /// 把分形 `fractal` 中一个点的迭代结果和轨道统计换算为 `[0, limit]` 区间内的逃逸值，
+    /// `None` 表示按集合内部着色；`spacing` 是相邻像素的间距
+    fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        result: &PointResult,
+        stats: &Self::Stats,
+        limit: usize,
+        spacing: f64,
+    ) -> Option<f64>;
+
+    /// 点 `point` 在分形 `fractal` 中的逃逸值：用 `PointEvaluator::evaluate` 迭代，再用
+    /// `value` 换算
+    ///
+    /// 内置的着色方式改用各自优化过的迭代，结果相同。
+    fn escape_value<T: Real, F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        point: Complex<T>,
+        limit: usize,
+        spacing: T,
+    ) -> Option<f64>
+    where
+        Self: Sized,
+    {
+        let (result, stats) = fractal.evaluate(self, point, limit);
+        self.value(fractal, &result, &stats, limit, spacing.f64())
+    }
+
+    /// 是否只需要整数逃逸次数，这时渲染可以使用向量化的迭代
+    fn is_escape_time(&self) -> bool {
+        false
+    }
+
+    /// 共轭的轨道是否得到相同的逃逸值，与 `Fractal::is_symmetric` 一起决定能否只渲染
+    /// 半幅图像再镜像（参见 `render_parallel`）；不确定时应为假
+    fn is_symmetric(&self) -> bool {
+        false
+    }
+}
+
+impl<C: Colorizer> Colorizer for &C {
+    type Stats = C::Stats;
+
+    fn bailout(&self) -> f64 {
+        (**self).bailout()
+    }
+
+    fn escaped<T: Real>(&self, z: Complex<T>) -> bool {
+        (**self).escaped(z)
+    }
+
+    fn needs_derivative(&self) -> bool {
+        (**self).needs_derivative()
+    }
+
+    fn colors_interior(&self) -> bool {
+        (**self).colors_interior()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        (**self).is_symmetric()
+    }
+
+    fn observe(&self, stats: &mut C::Stats, z: Complex<f64>, next: Complex<f64>, c: Complex<f64>) {
+        (**self).observe(stats, z, next, c)
+    }
+
+    fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        result: &PointResult,
+        stats: &C::Stats,
+        limit: usize,
+        spacing: f64,
+    ) -> Option<f64> {
+        (**self).value(fractal, result, stats, limit, spacing)
+    }
+
+    fn escape_value<T: Real, F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        point: Complex<T>,
+        limit: usize,
+        spacing: T,
+    ) -> Option<f64> {
+        (**self).escape_value(fractal, point, limit, spacing)
+    }
+
+    fn is_escape_time(&self) -> bool {
+        (**self).is_escape_time()
+    }
+}
+
+/// 内置着色方式迭代时累积的轨道统计
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitStats {
+    /// 轨道到陷阱的最小距离
+    pub trap_distance: f64,
+    /// 三角不等式平均
+    pub average: Average,
+    /// 原子域
+    pub atom: AtomDomain,
+}
+
+impl Default for OrbitStats {
+    fn default() -> OrbitStats {
+        OrbitStats {
+            trap_distance: f64::INFINITY,
+            average: Average::default(),
+            atom: AtomDomain::default(),
+        }
+    }
+}
+
+impl Colorizer for Coloring {
+    type Stats = OrbitStats;
+
+    fn bailout(&self) -> f64 {
+        match self {
+            Coloring::EscapeTime | Coloring::OrbitTrap(_) | Coloring::AtomDomain => 4.0,
+            Coloring::Smooth => SMOOTH_BAILOUT,
+            Coloring::Distance => DISTANCE_BAILOUT,
+            Coloring::TriangleInequality => tia::BAILOUT,
+        }
+    }
+
+    fn needs_derivative(&self) -> bool {
+        *self == Coloring::Distance
+    }
+
+    fn colors_interior(&self) -> bool {
+        matches!(self, Coloring::OrbitTrap(_) | Coloring::AtomDomain)
+    }
+
+    /// 轨道陷阱一般不关于实轴对称，其余方式只用到模、周期和导数的模
+    fn is_symmetric(&self) -> bool {
+        !matches!(self, Coloring::OrbitTrap(_))
+    }
+
+    fn observe(
+        &self,
+        stats: &mut OrbitStats,
+        _z: Complex<f64>,
+        next: Complex<f64>,
+        c: Complex<f64>,
+    ) {
+        match self {
+            Coloring::OrbitTrap(trap) => {
+                stats.trap_distance = stats.trap_distance.min(trap.distance(next));
+            }
+            Coloring::TriangleInequality => stats.average.observe(next, c),
+            Coloring::AtomDomain => stats.atom.observe(next, c),
+            _ => {}
+        }
+    }
+
+    fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        result: &PointResult,
+        stats: &OrbitStats,
+        limit: usize,
+        spacing: f64,
+    ) -> Option<f64> {
+        match self {
+            Coloring::OrbitTrap(_) => return Some(trap::trap_value(stats.trap_distance, limit)),
+            Coloring::AtomDomain => {
+                return Some(period_value(stats.atom.period(fractal, result), limit))
+            }
+            _ => {}
+        }
+        let count = result.iterations?;
+        Some(match self {
+            Coloring::EscapeTime => count as f64,
+            Coloring::Smooth => result.smooth()?,
+            Coloring::Distance => distance_value(result.distance()? / spacing, limit),
+            Coloring::TriangleInequality => {
+                let average = stats
+                    .average
+                    .value(result.final_z, fractal.degree(), result.bailout);
+                average * limit as f64
+            }
+            Coloring::OrbitTrap(_) | Coloring::AtomDomain => unreachable!("handled above"),
+        })
+    }
+
+    fn escape_value<T: Real, F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        point: Complex<T>,
+        limit: usize,
+        spacing: T,
+    ) -> Option<f64> {
+        match fractal.builtin() {
+            Some(builtin) => builtin.escape_value(*self, point, limit, spacing),
+            None => {
+                let (result, stats) = fractal.evaluate(self, point, limit);
+                self.value(fractal, &result, &stats, limit, spacing.f64())
+            }
+        }
+    }
+
+    fn is_escape_time(&self) -> bool {
+        *self == Coloring::EscapeTime
+    }
+}
+
+#[test]
+fn test_point_result() {
+    use crate::Fractal;
+
+    let fractal = Fractal::Mandelbrot;
+    let escaping = Complex::new(0.4, 0.6);
+    let result = fractal.point_result(escaping, 500, SMOOTH_BAILOUT);
+    assert!(result.iterations.is_some());
+    assert!(result.final_z.norm_sqr() > SMOOTH_BAILOUT);
+    assert_eq!(result.smooth(), fractal.smooth_escape_time(escaping, 500));
+
+    // 检测到吸引环提前结束时也保留最后的 z，它落在环附近而不是迭代的起点
+    let bounded = Complex::new(-0.12, 0.75);
+    let result = fractal.point_result(bounded, 100_000, 4.0);
+    assert_eq!((result.iterations, result.smooth()), (None, None));
+    assert!(result.final_z != Complex::new(0.0, 0.0) && result.final_z.norm() < 2.0);
+    assert_eq!(result.distance(), None);
+
+    let (result, _) = fractal.evaluate(&Coloring::Distance, escaping, 500);
+    assert_eq!(result.distance(), fractal.distance_estimate(escaping, 500));
+    assert!(result.distance().unwrap() > 0.0);
+}
+
+#[test]
+fn test_coloring_colorizer() {
+    use crate::Fractal;
+
+    // 内置着色方式走通用的迭代时，结果与各自优化过的迭代相同
+    let trap = crate::trap::Trap {
+        shape: crate::trap::TrapShape::Circle,
+        center: Complex::new(0.1, 0.2),
+        size: 0.5,
+    };
+    for fractal in [
+        Fractal::Mandelbrot,
+        Fractal::Julia(Complex::new(-0.8, 0.156)),
+        Fractal::BurningShip,
+        Fractal::Multibrot(3.0),
+    ] {
+        for coloring in [
+            Coloring::EscapeTime,
+            Coloring::Smooth,
+            Coloring::Distance,
+            Coloring::OrbitTrap(trap),
+            Coloring::TriangleInequality,
+            Coloring::AtomDomain,
+        ] {
+            for (re, im) in [(-0.75, 0.1), (0.3, 0.5), (-1.2, 0.3), (-0.2, 0.1)] {
+                let point = Complex::new(re, im);
+                let (result, stats) = fractal.evaluate(&coloring, point, 500);
+                assert_eq!(
+                    coloring.value(&fractal, &result, &stats, 500, 0.01),
+                    fractal.escape_value(coloring, point, 500, 0.01),
+                    "{:?} {:?} {:?}",
+                    fractal,
+                    coloring,
+                    point
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_custom_colorizer() {
+    use crate::{render, Fractal, PixelTransform};
+
+    /// 逃逸时 `z` 的实部是否为正，检验自定义着色方式能用于 `render`
+    struct Sign;
+
+    impl Colorizer for Sign {
+        type Stats = usize;
+
+        fn observe(&self, steps: &mut usize, _: Complex<f64>, _: Complex<f64>, _: Complex<f64>) {
+            *steps += 1;
+        }
+
+        fn value<F: PointEvaluator>(
+            &self,
+            _: &F,
+            result: &PointResult,
+            steps: &usize,
+            _: usize,
+            _: f64,
+        ) -> Option<f64> {
+            assert_eq!(result.iterations, Some(*steps));
+            Some(if result.final_z.re > 0.0 { 1.0 } else { 0.0 })
+        }
+    }
+
+    let bounds = (8, 6);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.0, im: 1.5 },
+        Complex { re: 2.0, im: -1.5 },
+    );
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    render(
+        Fractal::Julia(Complex::new(0.4, 0.4)),
+        &Sign,
+        100,
+        &mut iterations,
+        bounds,
+        transform,
+    );
+    for value in iterations {
+        assert!(
+            value == crate::encode_escape(Some(0.0)) || value == crate::encode_escape(Some(1.0))
+        );
+    }
+}
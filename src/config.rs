@@ -0,0 +1,177 @@
+//! 场景配置文件
+//!
+//! 参数较多的渲染可以写在 TOML 文件中，用 `render --config scene.toml` 读取。文件中的
+//! 每个键对应一个同名的命令行选项（下划线和连字符均可），表只用于分组，表名会被忽略：
+//!
+//! ```toml
+//! output = "seahorse.png"
+//!
+//! [view]
+//! size = "1920x1080"
+//! center = "-0.743643887037151,0.131825904205330"
+//! zoom = 5000
+//!
+//! [fractal]
+//! max_iter = 2000
+//!
+//! [color]
+//! coloring = "smooth"
+//! palette = "classic"
+//! histogram = true
+//! ```
+//!
+//! 配置被转换为命令行参数插在用户给出的参数之前，因此命令行上的选项总是优先。
+//! 渲染服务收到的 JSON 场景按同样的规则转换，见 `json_config_args`。
+
+use toml::{Table, Value};
+
+/// 配置中对应位置参数（而不是选项）的键
+pub const POSITIONAL: &str = "output";
+
+/// 一个键转换成的命令行参数
+enum Setting {
+    /// 带值的选项
+    Value(String),
+    /// 只写出选项名的开关
+    Flag,
+    /// 为假的开关，省略
+    Omit,
+}
+
+/// 把配置文件的内容转换为命令行参数
+///
+/// 返回位置参数 `output` 的值（如果有）以及其余的选项。字符串和数值原样作为选项的值，
+/// 布尔值为真时只写出选项名，为假时省略。
+pub fn config_args(text: &str) -> Result<(Option<String>, Vec<String>), String> {
+    let table: Table = text.parse().map_err(|err| format!("{}", err))?;
+    let mut entries: Vec<(&String, &Value)> = Vec::new();
+    for (key, value) in &table {
+        match value {
+            Value::Table(section) => entries.extend(section),
+            _ => entries.push((key, value)),
+        }
+    }
+    let mut settings = Vec::new();
+    for (key, value) in entries {
+        let setting = match value {
+            Value::String(s) => Setting::Value(s.clone()),
+            Value::Integer(n) => Setting::Value(n.to_string()),
+            Value::Float(x) => Setting::Value(x.to_string()),
+            Value::Boolean(true) => Setting::Flag,
+            Value::Boolean(false) => Setting::Omit,
+            _ => return Err(format!("unsupported value for `{}`", key)),
+        };
+        settings.push((key.as_str(), setting));
+    }
+    settings_args(settings)
+}
+
+/// 把 JSON 对象形式的场景转换为命令行参数，规则与 `config_args` 相同，嵌套的对象只用于分组
+pub fn json_config_args(
+    value: &serde_json::Value,
+) -> Result<(Option<String>, Vec<String>), String> {
+    use serde_json::Value;
+    let object = value.as_object().ok_or("the scene must be a JSON object")?;
+    let mut entries: Vec<(&String, &Value)> = Vec::new();
+    for (key, value) in object {
+        match value {
+            Value::Object(section) => entries.extend(section),
+            _ => entries.push((key, value)),
+        }
+    }
+    let mut settings = Vec::new();
+    for (key, value) in entries {
+        let setting = match value {
+            Value::String(s) => Setting::Value(s.clone()),
+            Value::Number(n) => Setting::Value(n.to_string()),
+            Value::Bool(true) => Setting::Flag,
+            Value::Bool(false) => Setting::Omit,
+            _ => return Err(format!("unsupported value for `{}`", key)),
+        };
+        settings.push((key.as_str(), setting));
+    }
+    settings_args(settings)
+}
+
+/// 把各个键的设置转换为命令行参数，分出位置参数 `output`
+fn settings_args(settings: Vec<(&str, Setting)>) -> Result<(Option<String>, Vec<String>), String> {
+    let mut output = None;
+    let mut args = Vec::new();
+    for (key, setting) in settings {
+        let value = match setting {
+            Setting::Value(value) => Some(value),
+            Setting::Flag => None,
+            Setting::Omit => continue,
+        };
+        if key == POSITIONAL {
+            output = Some(value.ok_or_else(|| format!("`{}` must be a file name", key))?);
+            continue;
+        }
+        args.push(format!("--{}", key.replace('_', "-")));
+        args.extend(value);
+    }
+    Ok((output, args))
+}
+
+#[test]
+fn test_config_args() {
+    let text = r#"
+        output = "out.png"
+        subdivide = true
+        adaptive = false
+
+        [view]
+        size = "800x600"
+        zoom = 2.5
+
+        [fractal]
+        max_iter = 1000
+    "#;
+    let (output, args) = config_args(text).unwrap();
+    assert_eq!(output.as_deref(), Some("out.png"));
+    assert_eq!(
+        args,
+        [
+            "--max-iter",
+            "1000",
+            "--subdivide",
+            "--size",
+            "800x600",
+            "--zoom",
+            "2.5"
+        ]
+    );
+
+    assert!(config_args("zoom = [1, 2]").is_err());
+    assert!(config_args("output = true").is_err());
+    assert!(config_args("zoom = ").is_err());
+}
+
+#[test]
+fn test_json_config_args() {
+    let scene = serde_json::json!({
+        "output": "out.png",
+        "view": {"size": "800x600", "zoom": 2.5},
+        "fractal": {"max_iter": 1000},
+        "subdivide": true,
+        "adaptive": false
+    });
+    let (output, args) = json_config_args(&scene).unwrap();
+    assert_eq!(output.as_deref(), Some("out.png"));
+    assert_eq!(
+        args,
+        [
+            "--max-iter",
+            "1000",
+            "--subdivide",
+            "--size",
+            "800x600",
+            "--zoom",
+            "2.5"
+        ]
+    );
+
+    assert!(json_config_args(&serde_json::json!({"zoom": [1, 2]})).is_err());
+    assert!(json_config_args(&serde_json::json!({"output": true})).is_err());
+    assert!(json_config_args(&serde_json::json!([1])).is_err());
+}
@@ -0,0 +1,20 @@
+// 逃逸时间内核的 CUDA 部分，由 cuda.rs 嵌入，与 gpu_escape.h 拼接后在运行时用 NVRTC 编译
+//
+// 用 double 迭代。
+
+typedef unsigned int uint;
+typedef double real;
+typedef double output;
+
+__device__ inline real from_output(output a) { return a; }
+__device__ inline output to_output(real a) { return a; }
+__device__ inline real add(real a, real b) { return a + b; }
+__device__ inline real sub(real a, real b) { return a - b; }
+__device__ inline real mul(real a, real b) { return a * b; }
+__device__ inline real absolute(real a) { return fabs(a); }
+
+// gpu_escape.h 中的内核主体需要的限定符和像素位置
+#define KERNEL extern "C" __global__
+#define GLOBAL
+#define COLUMN (blockIdx.x * blockDim.x + threadIdx.x)
+#define ROW (blockIdx.y * blockDim.y + threadIdx.y)
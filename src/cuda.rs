@@ -0,0 +1,231 @@
+//! 在 CUDA 设备上以 `f64` 渲染逃逸时间
+//!
+//! 内核源码 `cuda.cu` 与 `gpu_escape.h` 拼接后嵌入在可执行文件中，打开设备时用 NVRTC
+//! 编译。CUDA 驱动和 NVRTC 都在运行时加载，编译时不需要安装 CUDA。其余与 `opencl` 模块
+//! 相同：图像按分块提交给设备，读回逃逸次数和逃逸时的 `z` 后在主机上编码，只支持
+//! `gpu::supports` 中的分形和着色方式。
+
+use crate::gpu::{self, KernelFractal};
+use crate::progress::Progress;
+use crate::tile::{self, Tile};
+use crate::{cancelled, Coloring, Fractal, PixelTransform, UNFINISHED};
+use cudarc::driver::{
+    CudaContext, CudaFunction, CudaSlice, CudaStream, DriverError, LaunchConfig, PushKernelArg,
+};
+use cudarc::nvrtc;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::sync::{Arc, Mutex};
+
+/// 内核源码
+const SOURCE: &str = concat!(include_str!("cuda.cu"), include_str!("gpu_escape.h"));
+
+/// 每个线程块的边长，一个线程块计算 16×16 个像素
+const BLOCK: u32 = 16;
+
+/// 能否加载名为 `name` 的 CUDA 动态库
+///
+/// 找不到 CUDA 驱动或 NVRTC 的动态库时 cudarc 会 panic，所以先按 cudarc 查找的那些文件名
+/// （CUDA 12.0）试着加载一次，找不到就返回错误。
+fn library_present(name: &str) -> bool {
+    let pointer_width = usize::BITS;
+    [
+        format!("{DLL_PREFIX}{name}{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}_12{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}_120{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}_120_0{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}_10{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{pointer_width}_9{DLL_SUFFIX}"),
+        format!("{DLL_PREFIX}{name}{DLL_SUFFIX}.12"),
+        format!("{DLL_PREFIX}{name}{DLL_SUFFIX}.11"),
+        format!("{DLL_PREFIX}{name}{DLL_SUFFIX}.10"),
+        format!("{DLL_PREFIX}{name}{DLL_SUFFIX}.1"),
+    ]
+    .iter()
+    // 安全性：加载的是 CUDA 自己的动态库，与随后 cudarc 加载的是同一个
+    .any(|filename| unsafe { libloading::Library::new(filename) }.is_ok())
+}
+
+/// 打开的 CUDA 设备和编译好的内核
+pub struct Cuda {
+    context: Arc<CudaContext>,
+    stream: Arc<CudaStream>,
+    kernel: CudaFunction,
+    /// 在分块之间复用的设备缓冲区，按渲染过的最大分块分配
+    buffers: Mutex<Option<Buffers>>,
+}
+
+/// 能容纳 `len` 个像素的逃逸次数和逃逸时的 `z` 的设备缓冲区
+struct Buffers {
+    len: usize,
+    counts: CudaSlice<u32>,
+    final_z: CudaSlice<f64>,
+}
+
+impl Cuda {
+    /// 打开第一个 CUDA 设备并编译内核，失败时返回说明原因的错误信息
+    pub fn new() -> Result<Cuda, String> {
+        if !library_present("cuda") && !library_present("nvcuda") {
+            return Err("cannot load the CUDA driver".to_string());
+        }
+        let devices = CudaContext::device_count()
+            .map_err(|err| format!("cannot initialize the CUDA driver: {}", err))?;
+        if devices == 0 {
+            return Err("no CUDA device found".to_string());
+        }
+        let context =
+            CudaContext::new(0).map_err(|err| format!("cannot create a CUDA context: {}", err))?;
+        if !library_present("nvrtc") {
+            return Err("cannot load NVRTC".to_string());
+        }
+        let ptx = nvrtc::compile_ptx(SOURCE)
+            .map_err(|err| format!("cannot build the CUDA kernel: {}", err))?;
+        let kernel = context
+            .load_module(ptx)
+            .and_then(|module| module.load_function("escape"))
+            .map_err(|err| format!("cannot load the CUDA kernel: {}", err))?;
+        let stream = context.default_stream();
+        Ok(Cuda {
+            context,
+            stream,
+            kernel,
+            buffers: Mutex::new(None),
+        })
+    }
+
+    /// 设备的名字
+    pub fn name(&self) -> String {
+        self.context
+            .name()
+            .unwrap_or_else(|_| "unknown".to_string())
+    }
+
+    /// 在设备上把分形渲染到整个迭代缓冲区中
+    ///
+    /// 参数含义与 `OpenCl::render` 相同，只是固定用 `f64` 计算。
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        iterations: &mut [u32],
+        bounds: (usize, usize),
+        transform: PixelTransform<f64>,
+        tile: (usize, usize),
+        progress: &Progress,
+    ) -> Result<(), String> {
+        assert_eq!(iterations.len(), bounds.0 * bounds.1);
+        let tiles = tile::tiles(bounds, tile);
+        progress.start(tiles.len(), "tiles");
+        for tile in tiles {
+            let values = if cancelled() {
+                vec![UNFINISHED; tile.len()]
+            } else {
+                self.render_tile(fractal, coloring, limit, tile, transform)?
+            };
+            for (y, row) in values.chunks(tile.width).enumerate() {
+                let start = (tile.y + y) * bounds.0 + tile.x;
+                iterations[start..start + tile.width].copy_from_slice(row);
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    /// 在设备上渲染分块 `tile`，返回按行排列的编码后的逃逸值
+    ///
+    /// 分形和着色方式必须满足 `gpu::supports`。
+    pub fn render_tile(
+        &self,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        tile: Tile,
+        transform: PixelTransform<f64>,
+    ) -> Result<Vec<u32>, String> {
+        assert!(
+            gpu::supports(fractal, coloring),
+            "{:?} is not supported",
+            fractal
+        );
+        let mut buffers = self.buffers.lock().expect("CUDA buffers are not poisoned");
+        // 分块大小都不超过第一个分块，通常只在第一个分块分配一次
+        if buffers
+            .as_ref()
+            .is_none_or(|buffers| buffers.len < tile.len())
+        {
+            let buffer_error = |err| format!("cannot allocate CUDA buffers: {}", err);
+            *buffers = Some(Buffers {
+                len: tile.len(),
+                counts: self
+                    .stream
+                    .alloc_zeros::<u32>(tile.len())
+                    .map_err(buffer_error)?,
+                final_z: self
+                    .stream
+                    .alloc_zeros::<f64>(2 * tile.len())
+                    .map_err(buffer_error)?,
+            });
+        }
+        let buffers = buffers.as_mut().expect("the buffers were just allocated");
+        let (tile_counts, tile_z) = self
+            .run(fractal, coloring, limit, tile, transform, buffers)
+            .map_err(|err| format!("CUDA rendering failed: {}", err))?;
+        Ok(gpu::encode(fractal, coloring, limit, &tile_counts, &tile_z))
+    }
+
+    /// 在设备上计算分块 `tile`，读回逃逸次数和逃逸时的 `z`
+    ///
+    /// `buffers` 至少能容纳分块中的像素，只读回前面分块那么多的部分。
+    fn run(
+        &self,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        tile: Tile,
+        transform: PixelTransform<f64>,
+        buffers: &mut Buffers,
+    ) -> Result<(Vec<u32>, Vec<f64>), DriverError> {
+        let params = KernelFractal::new(fractal, coloring);
+        let (x, y) = (tile.x as u32, tile.y as u32);
+        let (width, height) = (tile.width as u32, tile.height as u32);
+        let limit = limit.min(u32::MAX as usize) as u32;
+        let config = LaunchConfig {
+            grid_dim: (width.div_ceil(BLOCK), height.div_ceil(BLOCK), 1),
+            block_dim: (BLOCK, BLOCK, 1),
+            shared_mem_bytes: 0,
+        };
+        // 参数的个数和类型必须与 gpu_escape.h 中的 escape 内核一致
+        let mut launch = self.stream.launch_builder(&self.kernel);
+        launch
+            .arg(&mut buffers.counts)
+            .arg(&mut buffers.final_z)
+            .arg(&x)
+            .arg(&y)
+            .arg(&width)
+            .arg(&height)
+            .arg(&transform.origin.re)
+            .arg(&transform.origin.im)
+            .arg(&transform.right.re)
+            .arg(&transform.right.im)
+            .arg(&transform.down.re)
+            .arg(&transform.down.im)
+            .arg(&params.kind)
+            .arg(&params.power)
+            .arg(&params.julia.re)
+            .arg(&params.julia.im)
+            .arg(&limit)
+            .arg(&params.bailout);
+        // 安全性：参数与内核签名一致，缓冲区能容纳分块中的像素，内核不会越界
+        unsafe { launch.launch(config) }?;
+        let counts = self
+            .stream
+            .memcpy_dtov(&buffers.counts.slice(..tile.len()))?;
+        let final_z = self
+            .stream
+            .memcpy_dtov(&buffers.final_z.slice(..2 * tile.len()))?;
+        Ok((counts, final_z))
+    }
+}
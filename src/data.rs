@@ -0,0 +1,314 @@
+//! 迭代缓冲区的保存与读取
+//!
+//! 只想换一种调色板时没有必要重新计算分形。`.mbd` 文件保存渲染得到的原始迭代缓冲区，
+//! 以后可以直接重新着色。文件格式（整数均为小端序）：
+//!
+//! - 4 字节魔数 `MBD2`
+//! - `u32` 宽度、`u32` 高度（即缓冲区的尺寸，超采样时是子像素的尺寸）
+//! - `u32` 每个方向的超采样数、`u32` 最大迭代次数、`u8` 着色方式（0 为整数逃逸次数，
+//!   1 为连续逃逸值，2 为距离估计，3 为轨道陷阱，4 为三角不等式平均；轨道陷阱之后还有
+//!   `u8` 陷阱形状和三个 `f64` 表示的中心实部、虚部和形状参数）
+//! - `u8` 是否保存了视图，为 1 时之后是 `u64` 参数指纹和六个 `f64` 表示的像素变换
+//!   （`origin`、`right`、`down` 的实部和虚部），`render --pan-from` 用它们复用重叠的像素
+//! - `u32` 长度加 UTF-8 文本，记录渲染时的参数，仅供查看
+//! - 宽度乘高度个 `u32`，按 `encode_escape` 编码的逃逸值
+//!
+//! 旧版本写入的 `MBD1` 文件没有视图一项，仍然可以读取。
+
+use crate::trap::{Trap, TrapShape};
+use crate::{Coloring, PixelTransform};
+use num::Complex;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"MBD2";
+
+/// 没有视图一项的旧版本格式
+const MAGIC_V1: &[u8; 4] = b"MBD1";
+
+/// 读取时接受的最多的像素数，对应 4 GiB 的逃逸值；更大的尺寸只可能来自损坏的文件
+const MAX_PIXELS: usize = 1 << 30;
+
+/// 读取时接受的最长的参数说明的字节数
+const MAX_DESCRIPTION: usize = 1 << 20;
+
+/// 渲染缓冲区时的视图，用来判断新的渲染能否复用这些像素
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SavedView {
+    /// 由 `checkpoint::fingerprint` 算出的分形、着色方式等参数的指纹
+    pub fingerprint: u64,
+    /// 缓冲区的像素到复平面的变换
+    pub transform: PixelTransform<f64>,
+}
+
+/// 保存在 `.mbd` 文件中的迭代缓冲区及其参数
+#[derive(Clone, Debug, PartialEq)]
+pub struct IterationData {
+    /// 缓冲区的宽度和高度
+    pub bounds: (usize, usize),
+    /// 每个输出像素在每个方向上的采样数，输出图像的尺寸是 `bounds` 除以它
+    pub samples: usize,
+    /// 渲染时每个点的最大迭代次数
+    pub limit: usize,
+    /// 缓冲区保存的是整数逃逸次数还是连续逃逸值
+    pub coloring: Coloring,
+    /// 渲染时的视图，深度缩放或旧版本的文件没有
+    pub view: Option<SavedView>,
+    /// 渲染时的参数说明
+    pub description: String,
+    /// 按 `encode_escape` 编码的逃逸值
+    pub iterations: Vec<u32>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(writer: &mut impl Write, value: usize) -> io::Result<()> {
+    let value = u32::try_from(value).map_err(|_| invalid("value does not fit in u32"))?;
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn read_trap(reader: &mut impl Read) -> io::Result<Trap> {
+    let mut shape = [0];
+    reader.read_exact(&mut shape)?;
+    let shape = match shape[0] {
+        0 => TrapShape::Point,
+        1 => TrapShape::Cross,
+        2 => TrapShape::Circle,
+        3 => TrapShape::Line,
+        _ => return Err(invalid("unknown trap shape")),
+    };
+    let center = Complex {
+        re: read_f64(reader)?,
+        im: read_f64(reader)?,
+    };
+    Ok(Trap {
+        shape,
+        center,
+        size: read_f64(reader)?,
+    })
+}
+
+fn read_view(reader: &mut impl Read) -> io::Result<Option<SavedView>> {
+    let mut present = [0];
+    reader.read_exact(&mut present)?;
+    match present[0] {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("invalid view flag")),
+    }
+    let mut fingerprint = [0; 8];
+    reader.read_exact(&mut fingerprint)?;
+    let mut complex = || -> io::Result<Complex<f64>> {
+        Ok(Complex {
+            re: read_f64(reader)?,
+            im: read_f64(reader)?,
+        })
+    };
+    let transform = PixelTransform {
+        origin: complex()?,
+        right: complex()?,
+        down: complex()?,
+    };
+    Ok(Some(SavedView {
+        fingerprint: u64::from_le_bytes(fingerprint),
+        transform,
+    }))
+}
+
+impl IterationData {
+    /// 按上面描述的格式写入 `writer`
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        assert_eq!(self.iterations.len(), self.bounds.0 * self.bounds.1);
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, self.bounds.0)?;
+        write_u32(&mut writer, self.bounds.1)?;
+        write_u32(&mut writer, self.samples)?;
+        write_u32(&mut writer, self.limit)?;
+        writer.write_all(&[match self.coloring {
+            Coloring::EscapeTime => 0,
+            Coloring::Smooth => 1,
+            Coloring::Distance => 2,
+            Coloring::OrbitTrap(_) => 3,
+            Coloring::TriangleInequality => 4,
+            Coloring::AtomDomain => 5,
+        }])?;
+        if let Coloring::OrbitTrap(trap) = self.coloring {
+            writer.write_all(&[match trap.shape {
+                TrapShape::Point => 0,
+                TrapShape::Cross => 1,
+                TrapShape::Circle => 2,
+                TrapShape::Line => 3,
+            }])?;
+            for value in [trap.center.re, trap.center.im, trap.size] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        match &self.view {
+            Some(view) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&view.fingerprint.to_le_bytes())?;
+                let PixelTransform {
+                    origin,
+                    right,
+                    down,
+                } = view.transform;
+                for value in [origin.re, origin.im, right.re, right.im, down.re, down.im] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            None => writer.write_all(&[0])?,
+        }
+        write_u32(&mut writer, self.description.len())?;
+        writer.write_all(self.description.as_bytes())?;
+        for value in &self.iterations {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// 从 `reader` 读取 `write` 写入的数据
+    pub fn read(mut reader: impl Read) -> io::Result<IterationData> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC && &magic != MAGIC_V1 {
+            return Err(invalid("not an .mbd file"));
+        }
+        let width = read_u32(&mut reader)? as usize;
+        let height = read_u32(&mut reader)? as usize;
+        let samples = read_u32(&mut reader)? as usize;
+        let limit = read_u32(&mut reader)? as usize;
+        if samples == 0 || !width.is_multiple_of(samples) || !height.is_multiple_of(samples) {
+            return Err(invalid("buffer size is not a multiple of the sample count"));
+        }
+        let mut coloring = [0];
+        reader.read_exact(&mut coloring)?;
+        let coloring = match coloring[0] {
+            0 => Coloring::EscapeTime,
+            1 => Coloring::Smooth,
+            2 => Coloring::Distance,
+            3 => Coloring::OrbitTrap(read_trap(&mut reader)?),
+            4 => Coloring::TriangleInequality,
+            5 => Coloring::AtomDomain,
+            _ => return Err(invalid("unknown coloring")),
+        };
+        let view = if &magic == MAGIC {
+            read_view(&mut reader)?
+        } else {
+            None
+        };
+        let length = read_u32(&mut reader)? as usize;
+        if length > MAX_DESCRIPTION {
+            return Err(invalid("description is too long"));
+        }
+        let mut description = vec![0; length];
+        reader.read_exact(&mut description)?;
+        let description =
+            String::from_utf8(description).map_err(|_| invalid("description is not UTF-8"))?;
+
+        // 尺寸来自文件，先检查再按它分配缓冲区
+        let pixels = width
+            .checked_mul(height)
+            .filter(|&pixels| pixels <= MAX_PIXELS)
+            .ok_or_else(|| invalid("buffer is too large"))?;
+        let mut bytes = vec![0; pixels * 4];
+        reader.read_exact(&mut bytes)?;
+        let iterations = bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        Ok(IterationData {
+            bounds: (width, height),
+            samples,
+            limit,
+            coloring,
+            view,
+            description,
+            iterations,
+        })
+    }
+
+    /// 写入名为 `filename` 的文件
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(filename)?))
+    }
+
+    /// 读取名为 `filename` 的文件
+    pub fn load(filename: &str) -> io::Result<IterationData> {
+        IterationData::read(BufReader::new(File::open(filename)?))
+    }
+
+    /// 输出图像的像素尺寸
+    pub fn image_bounds(&self) -> (usize, usize) {
+        (self.bounds.0 / self.samples, self.bounds.1 / self.samples)
+    }
+}
+
+#[test]
+fn test_write_read() {
+    let data = IterationData {
+        bounds: (4, 2),
+        samples: 2,
+        limit: 500,
+        coloring: Coloring::Smooth,
+        view: None,
+        description: "render --center -0.5,0".to_string(),
+        iterations: vec![0, 1, 2, 3, 4, 5, 6, u32::MAX],
+    };
+    let mut bytes = Vec::new();
+    data.write(&mut bytes).unwrap();
+    assert_eq!(IterationData::read(&bytes[..]).unwrap(), data);
+    assert_eq!(data.image_bounds(), (2, 1));
+
+    // 截断或魔数错误的文件都应当报错
+    assert!(IterationData::read(&bytes[..bytes.len() - 1]).is_err());
+    let mut huge = bytes.clone();
+    huge[4..12].copy_from_slice(&[0xff; 8]);
+    huge[12..16].copy_from_slice(&1u32.to_le_bytes());
+    assert!(IterationData::read(&huge[..]).is_err());
+    bytes[0] = b'X';
+    assert!(IterationData::read(&bytes[..]).is_err());
+
+    let data = IterationData {
+        coloring: Coloring::OrbitTrap(Trap {
+            shape: TrapShape::Circle,
+            center: Complex { re: 0.5, im: -0.5 },
+            size: 0.25,
+        }),
+        view: Some(SavedView {
+            fingerprint: 0x0123_4567_89ab_cdef,
+            transform: PixelTransform::from_corners(
+                (4, 2),
+                Complex { re: -2.0, im: 1.0 },
+                Complex { re: 2.0, im: -1.0 },
+            ),
+        }),
+        ..data
+    };
+    let mut bytes = Vec::new();
+    data.write(&mut bytes).unwrap();
+    assert_eq!(IterationData::read(&bytes[..]).unwrap(), data);
+
+    // 旧版本的文件没有视图一项
+    let data = IterationData { view: None, ..data };
+    let mut bytes = Vec::new();
+    data.write(&mut bytes).unwrap();
+    bytes[..4].copy_from_slice(MAGIC_V1);
+    // 视图标志位于描述的长度之前
+    let flag = bytes.len() - data.iterations.len() * 4 - data.description.len() - 5;
+    bytes.remove(flag);
+    assert_eq!(IterationData::read(&bytes[..]).unwrap(), data);
+}
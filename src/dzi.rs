@@ -0,0 +1,189 @@
+//! Deep Zoom Image（DZI）金字塔
+//!
+//! DZI 是 OpenSeadragon 等查看器使用的静态瓦片格式：`NAME.dzi` 描述整幅图像的尺寸、
+//! 瓦片边长和重叠宽度，`NAME_files/LEVEL/COLUMN_ROW.png` 是各级的瓦片。第 `max_level`
+//! 级是原始分辨率，每浅一级两个方向的尺寸都减半（向上取整），第 0 级只有 1×1 像素。
+//! 每块瓦片除了自己的 `tile_size` 见方的区域，还向相邻瓦片的方向多包含 `overlap`
+//! 个像素，查看器拼接时不会出现缝隙。
+//!
+//! 每一级都按自己的分辨率直接渲染，而不是从上一级缩小，因此任何时候只需要在内存中
+//! 保存一块瓦片，可以导出远超内存容量的图像。
+
+use crate::PixelTransform;
+use std::path::{Path, PathBuf};
+
+/// 尺寸为 `size` 的图像按 `tile_size` 和 `overlap` 切分的瓦片金字塔
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pyramid {
+    pub size: (usize, usize),
+    pub tile_size: usize,
+    pub overlap: usize,
+}
+
+impl Pyramid {
+    /// 原始分辨率所在的级别，即 `log2(max(width, height))` 向上取整
+    pub fn max_level(&self) -> usize {
+        let longest = self.size.0.max(self.size.1);
+        longest.next_power_of_two().trailing_zeros() as usize
+    }
+
+    /// 第 `level` 级相对原始分辨率缩小的倍数
+    pub fn scale(&self, level: usize) -> usize {
+        1 << (self.max_level() - level)
+    }
+
+    /// 第 `level` 级图像的像素尺寸
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        let scale = self.scale(level);
+        (self.size.0.div_ceil(scale), self.size.1.div_ceil(scale))
+    }
+
+    /// 第 `level` 级瓦片的列数和行数
+    pub fn tile_counts(&self, level: usize) -> (usize, usize) {
+        let (width, height) = self.level_size(level);
+        (
+            width.div_ceil(self.tile_size),
+            height.div_ceil(self.tile_size),
+        )
+    }
+
+    /// 第 `level` 级第 `column` 列、第 `row` 行的瓦片在该级图像中的左上角和像素尺寸，包括重叠部分
+    pub fn tile_rect(
+        &self,
+        level: usize,
+        (column, row): (usize, usize),
+    ) -> ((usize, usize), (usize, usize)) {
+        let (width, height) = self.level_size(level);
+        let span = |index: usize, length: usize| {
+            let start = (index * self.tile_size).saturating_sub(self.overlap);
+            let end = ((index + 1) * self.tile_size + self.overlap).min(length);
+            (start, end - start)
+        };
+        let (x, tile_width) = span(column, width);
+        let (y, tile_height) = span(row, height);
+        ((x, y), (tile_width, tile_height))
+    }
+
+    /// 原始分辨率的像素按 `transform` 映射时，第 `level` 级图像中以 `offset` 为左上角的
+    /// 瓦片的像素变换
+    pub fn tile_transform(
+        &self,
+        transform: PixelTransform<f64>,
+        level: usize,
+        offset: (usize, usize),
+    ) -> PixelTransform<f64> {
+        let scale = self.scale(level);
+        PixelTransform {
+            origin: transform.point((offset.0 * scale, offset.1 * scale)),
+            right: transform.right * scale as f64,
+            down: transform.down * scale as f64,
+        }
+    }
+
+    /// 扩展名为 `format` 的瓦片的 `.dzi` 描述文件
+    pub fn descriptor(&self, format: &str) -> String {
+        format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" Overlap=\"{}\" TileSize=\"{}\">\n",
+                "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+                "</Image>\n"
+            ),
+            format, self.overlap, self.tile_size, self.size.0, self.size.1
+        )
+    }
+}
+
+/// 描述文件 `descriptor`（如 `out/mandel.dzi`）旁边存放瓦片的目录（如 `out/mandel_files`）
+pub fn files_dir(descriptor: &Path) -> PathBuf {
+    let stem = descriptor.file_stem().unwrap_or_default().to_string_lossy();
+    descriptor.with_file_name(format!("{}_files", stem))
+}
+
+/// 瓦片目录 `files` 中第 `level` 级第 `column` 列、第 `row` 行、扩展名为 `extension` 的瓦片
+pub fn tile_path(
+    files: &Path,
+    level: usize,
+    (column, row): (usize, usize),
+    extension: &str,
+) -> PathBuf {
+    files
+        .join(level.to_string())
+        .join(format!("{}_{}.{}", column, row, extension))
+}
+
+#[test]
+fn test_pyramid() {
+    let pyramid = Pyramid {
+        size: (1000, 600),
+        tile_size: 254,
+        overlap: 1,
+    };
+    assert_eq!(pyramid.max_level(), 10);
+    assert_eq!(pyramid.level_size(10), (1000, 600));
+    assert_eq!(pyramid.level_size(9), (500, 300));
+    assert_eq!(pyramid.level_size(1), (2, 2));
+    assert_eq!(pyramid.level_size(0), (1, 1));
+    assert_eq!(pyramid.tile_counts(10), (4, 3));
+    assert_eq!(pyramid.tile_counts(0), (1, 1));
+
+    // 第一列只向右重叠，中间的列两侧都重叠，最后一列截断在图像边缘
+    assert_eq!(pyramid.tile_rect(10, (0, 0)), ((0, 0), (255, 255)));
+    assert_eq!(pyramid.tile_rect(10, (1, 1)), ((253, 253), (256, 256)));
+    assert_eq!(pyramid.tile_rect(10, (3, 2)), ((761, 507), (239, 93)));
+    assert_eq!(pyramid.tile_rect(0, (0, 0)), ((0, 0), (1, 1)));
+
+    assert_eq!(
+        Pyramid {
+            size: (512, 512),
+            tile_size: 256,
+            overlap: 0
+        }
+        .max_level(),
+        9
+    );
+}
+
+#[test]
+fn test_tile_transform() {
+    use num::Complex;
+
+    let pyramid = Pyramid {
+        size: (800, 400),
+        tile_size: 254,
+        overlap: 1,
+    };
+    let transform = PixelTransform::from_corners(
+        (800, 400),
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 2.0, im: -1.0 },
+    );
+    // 第 9 级缩小一半，该级的像素 (10, 20) 对应原始分辨率的像素 (20, 40)
+    let tile = pyramid.tile_transform(transform, 9, (6, 20));
+    assert_eq!(tile.point((4, 0)), transform.point((20, 40)));
+    assert_eq!(tile.spacing(), transform.spacing() * 2.0);
+}
+
+#[test]
+fn test_descriptor() {
+    let pyramid = Pyramid {
+        size: (1000, 600),
+        tile_size: 254,
+        overlap: 1,
+    };
+    assert_eq!(
+        pyramid.descriptor("png"),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"png\" Overlap=\"1\" TileSize=\"254\">\n  \
+         <Size Width=\"1000\" Height=\"600\"/>\n\
+         </Image>\n"
+    );
+    assert_eq!(
+        files_dir(Path::new("out/mandel.dzi")),
+        Path::new("out/mandel_files")
+    );
+    assert_eq!(
+        tile_path(Path::new("out/mandel_files"), 3, (2, 1), "png"),
+        Path::new("out/mandel_files/3/2_1.png")
+    );
+}
@@ -0,0 +1,103 @@
+//! 命令行程序的错误类型
+//!
+//! 每种错误的 `Display` 都是一句可以直接展示给用户的话，`mandelbrot` 可执行文件把它
+//! 打印到标准错误，并以 `exit_code` 给出的退出码结束。
+
+use std::fmt::Display;
+use std::io;
+use thiserror::Error;
+
+/// 渲染、读写文件和编码时可能遇到的错误
+#[derive(Debug, Error)]
+pub enum MandelbrotError {
+    /// 参数的取值不受支持，或几个参数之间互相矛盾
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    /// 配置文件、书签文件或关键帧文件的内容无法解析
+    #[error("error reading {path}: {message}")]
+    Parse { path: String, message: String },
+
+    /// 读取文件失败
+    #[error("error reading {path}: {source}")]
+    Read { path: String, source: io::Error },
+
+    /// 写入文件或创建目录失败，包括图像编码失败
+    #[error("error writing {path}: {source}")]
+    Write { path: String, source: io::Error },
+
+    /// 用 `ffmpeg` 编码视频失败
+    #[error("error encoding {path}: {source}")]
+    Encode { path: String, source: io::Error },
+
+    /// 交互窗口出错
+    #[error("viewer error: {0}")]
+    Viewer(io::Error),
+
+    /// 找不到 GPU 设备、内核编译失败或者设备上的渲染出错
+    #[error("GPU error: {0}")]
+    Gpu(String),
+
+    /// 瓦片服务器无法监听或接受连接
+    #[error("server error: {0}")]
+    Serve(io::Error),
+
+    /// 分布式渲染时无法连接工作进程、连接中断，或者工作进程无法完成任务
+    #[error("worker {address}: {source}")]
+    Worker { address: String, source: io::Error },
+
+    /// 执行队列时有任务失败，原因记录在队列中
+    #[error("{count} queued job(s) failed, see `queue list` for the reasons")]
+    JobsFailed { count: usize },
+
+    /// 渲染被 Ctrl-C 中断，已完成的部分写入了 `path`
+    #[error("interrupted, unfinished tiles are marked in {path}")]
+    Interrupted { path: String },
+}
+
+impl MandelbrotError {
+    /// 读取 `path` 失败时的错误，用于 `map_err`
+    pub fn reading(path: impl Display) -> impl FnOnce(io::Error) -> MandelbrotError {
+        move |source| MandelbrotError::Read {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// 写入 `path` 失败时的错误，用于 `map_err`
+    pub fn writing(path: impl Display) -> impl FnOnce(io::Error) -> MandelbrotError {
+        move |source| MandelbrotError::Write {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// 编码视频 `path` 失败时的错误，用于 `map_err`
+    pub fn encoding(path: impl Display) -> impl FnOnce(io::Error) -> MandelbrotError {
+        move |source| MandelbrotError::Encode {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// 进程的退出码：用法错误为 2（与 clap 报告命令行错误时一致），被 Ctrl-C 中断为
+    /// 130（与 shell 中被 SIGINT 终止的进程一致），其余为 1
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MandelbrotError::InvalidArgument(_) | MandelbrotError::Parse { .. } => 2,
+            MandelbrotError::Interrupted { .. } => 130,
+            _ => 1,
+        }
+    }
+}
+
+#[test]
+fn test_error_message() {
+    let err = MandelbrotError::writing("out.png")(io::Error::other("disk full"));
+    assert_eq!(err.to_string(), "error writing out.png: disk full");
+    assert_eq!(err.exit_code(), 1);
+
+    let err = MandelbrotError::InvalidArgument("--min-iter must be less than --max-iter".into());
+    assert_eq!(err.to_string(), "--min-iter must be less than --max-iter");
+    assert_eq!(err.exit_code(), 2);
+}
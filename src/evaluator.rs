@@ -0,0 +1,360 @@
+//! 可替换的迭代公式
+//!
+//! `render` 和并行渲染只需要知道怎样从一个像素对应的点开始迭代、每一步怎样迭代。
+//! `PointEvaluator` 描述了这些：迭代的起点、一步迭代（需要时连同导数），以及按着色
+//! 方式的要求迭代一个点的 `evaluate`。轨道总是带着上一步的 `z` 和当前的序号前进，Phoenix
+//! 这类下一步还取决于上一步的分形、混合分形这类按模式轮换公式的分形因此也能用同一个 trait
+//! 描述。内置的 `Fractal` 实现了它；库的使用者实现这个 trait 就能渲染新的迭代公式，不必
+//! 改动并行渲染的代码。
+//!
+//! ```
+//! use mandelbrot::evaluator::PointEvaluator;
+//! use mandelbrot::real::Real;
+//! use num::Complex;
+//!
+//! /// 凯尔特曼德博集：`z' = |Re(z²)| + i Im(z²) + c`
+//! struct Celtic;
+//!
+//! impl PointEvaluator for Celtic {
+//!     fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+//!         let square = z * z;
+//!         Complex::new(square.re.abs(), square.im) + c
+//!     }
+//! }
+//! ```
+
+use crate::colorizer::{Colorizer, PointResult};
+use crate::real::{widen, Real};
+use crate::Fractal;
+use num::Complex;
+
+/// 逐点迭代的分形
+pub trait PointEvaluator: Sync {
+    /// 对 `z` 做一次迭代，`c` 是迭代公式中的常数
+    fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T>;
+
+    /// 已知上一步的 `z` 为 `previous`（第一步之前为零）时对 `z` 做一次迭代
+    ///
+    /// 默认忽略 `previous`，只依赖当前 `z` 的分形不必实现它。用到上一步的分形实现它之后，
+    /// `evaluate` 就按二维的状态迭代；距离估计和吸引环分析仍然只用 `step`。
+    fn step_with_previous<T: Real>(
+        &self,
+        z: Complex<T>,
+        _previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        self.step(z, c)
+    }
+
+    /// 第 `i` 步（从 0 开始）的迭代，默认与序号无关；按模式轮换迭代公式的分形实现它
+    fn step_at<T: Real>(
+        &self,
+        _i: usize,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        self.step_with_previous(z, previous, c)
+    }
+
+    /// 复平面上的点 `point` 对应的迭代起点 `z` 和常数 `c`，默认从零开始、以 `point`
+    /// 为常数（曼德博类分形）
+    fn orbit_start<T: Real>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (Complex::new(T::zero(), T::zero()), point)
+    }
+
+    /// 迭代公式中 `z` 的次数，连续着色的修正项需要用到它
+    fn degree(&self) -> f64 {
+        2.0
+    }
+
+    /// 迭代前导数 `dz` 的初值：默认对 `c` 求导，为 0；对起点求导的分形（如朱利亚集）为 1
+    fn derivative_start<T: Real>(&self) -> Complex<T> {
+        Complex::new(T::zero(), T::zero())
+    }
+
+    /// 对 `z` 做一次迭代，同时迭代导数 `dz`
+    ///
+    /// 默认用有限差分近似导数，精度有限；需要距离估计时应当给出解析的导数。
+    fn step_with_derivative<T: Real>(
+        &self,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        let next = self.step(z, c);
+        let epsilon = T::of(1e-7) * (z.norm() + T::one());
+        let shifted = self.step(
+            z + dz.unscale(dz.norm().max(T::min_positive_value())) * epsilon,
+            c,
+        );
+        let derivative = (shifted - next) * dz.norm() / epsilon;
+        // 导数从零开始时对 c 求导，每一步还要加上对 c 的偏导
+        let zero = Complex::new(T::zero(), T::zero());
+        let shift = if self.derivative_start::<T>() == zero {
+            T::one()
+        } else {
+            T::zero()
+        };
+        (next, derivative + shift)
+    }
+
+    /// 第 `i` 步的迭代，同时迭代导数 `dz`，默认与序号无关
+    fn step_with_derivative_at<T: Real>(
+        &self,
+        _i: usize,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        self.step_with_derivative(z, dz, c)
+    }
+
+    /// 不必迭代就能确定 `point` 属于该分形时返回真，默认总是需要迭代
+    fn known_interior<T: Real>(&self, _point: Complex<T>) -> bool {
+        false
+    }
+
+    /// 内置的分形返回自身，以便渲染使用针对它优化过的迭代（如向量化的逃逸时间）
+    fn builtin(&self) -> Option<Fractal> {
+        None
+    }
+
+    /// 按 `colorizer` 的要求迭代点 `point`，最多 `limit` 次
+    ///
+    /// 返回迭代结果和累积的轨道统计。不需要为内部着色时，可以不迭代就判定的内部点直接
+    /// 返回 `iterations` 为 `None` 的结果，`final_z` 是迭代的起点。
+    fn evaluate<T: Real, C: Colorizer>(
+        &self,
+        colorizer: &C,
+        point: Complex<T>,
+        limit: usize,
+    ) -> (PointResult, C::Stats)
+    where
+        Self: Sized,
+    {
+        let mut stats = C::Stats::default();
+        let (mut z, c) = self.orbit_start(point);
+        let mut dz = self.derivative_start();
+        let bailout = colorizer.bailout();
+        let result = |iterations, z, dz| {
+            PointResult::new(iterations, widen(z), widen(dz), self.degree(), bailout)
+        };
+        if !colorizer.colors_interior() && self.known_interior(point) {
+            return (result(None, z, dz), stats);
+        }
+        let derivative = colorizer.needs_derivative();
+        let mut previous = Complex::new(T::zero(), T::zero());
+        for i in 0..limit {
+            if colorizer.escaped(z) {
+                return (result(Some(i), z, dz), stats);
+            }
+            let next = if derivative {
+                let next;
+                (next, dz) = self.step_with_derivative_at(i, z, dz, c);
+                next
+            } else {
+                self.step_at(i, z, previous, c)
+            };
+            colorizer.observe(&mut stats, widen(z), widen(next), widen(c));
+            (z, previous) = (next, z);
+        }
+        (result(None, z, dz), stats)
+    }
+}
+
+impl PointEvaluator for Fractal {
+    fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        Fractal::step(self, z, c)
+    }
+
+    fn step_with_previous<T: Real>(
+        &self,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        Fractal::step_with_previous(self, z, previous, c)
+    }
+
+    fn step_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        Fractal::step_at(self, i, z, previous, c)
+    }
+
+    fn orbit_start<T: Real>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        Fractal::orbit_start(self, point)
+    }
+
+    fn degree(&self) -> f64 {
+        Fractal::degree(self)
+    }
+
+    fn derivative_start<T: Real>(&self) -> Complex<T> {
+        Fractal::derivative_start(self)
+    }
+
+    fn step_with_derivative<T: Real>(
+        &self,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        Fractal::step_with_derivative(self, z, dz, c)
+    }
+
+    fn step_with_derivative_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        Fractal::step_with_derivative_at(self, i, z, dz, c)
+    }
+
+    fn known_interior<T: Real>(&self, point: Complex<T>) -> bool {
+        Fractal::known_interior(self, point)
+    }
+
+    fn builtin(&self) -> Option<Fractal> {
+        Some(*self)
+    }
+}
+
+impl<E: PointEvaluator> PointEvaluator for &E {
+    fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        (**self).step(z, c)
+    }
+
+    fn step_with_previous<T: Real>(
+        &self,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        (**self).step_with_previous(z, previous, c)
+    }
+
+    fn step_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        (**self).step_at(i, z, previous, c)
+    }
+
+    fn orbit_start<T: Real>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        (**self).orbit_start(point)
+    }
+
+    fn degree(&self) -> f64 {
+        (**self).degree()
+    }
+
+    fn derivative_start<T: Real>(&self) -> Complex<T> {
+        (**self).derivative_start()
+    }
+
+    fn step_with_derivative<T: Real>(
+        &self,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        (**self).step_with_derivative(z, dz, c)
+    }
+
+    fn step_with_derivative_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        (**self).step_with_derivative_at(i, z, dz, c)
+    }
+
+    fn known_interior<T: Real>(&self, point: Complex<T>) -> bool {
+        (**self).known_interior(point)
+    }
+
+    fn builtin(&self) -> Option<Fractal> {
+        (**self).builtin()
+    }
+}
+
+#[test]
+fn test_custom_evaluator() {
+    use crate::{render, Coloring, PixelTransform};
+
+    /// 与内置曼德博集相同的迭代，但不提供内部判定和优化过的迭代
+    struct Quadratic;
+
+    impl PointEvaluator for Quadratic {
+        fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+            z * z + c
+        }
+
+        fn step_with_derivative<T: Real>(
+            &self,
+            z: Complex<T>,
+            dz: Complex<T>,
+            c: Complex<T>,
+        ) -> (Complex<T>, Complex<T>) {
+            (z * z + c, z * dz * T::of(2.0) + T::one())
+        }
+    }
+
+    let bounds = (24, 16);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 1.0, im: -1.0 },
+    );
+    for coloring in [Coloring::EscapeTime, Coloring::Smooth, Coloring::Distance] {
+        let render_with = |custom: bool| {
+            let mut iterations = vec![0; bounds.0 * bounds.1];
+            if custom {
+                render(Quadratic, coloring, 300, &mut iterations, bounds, transform);
+            } else {
+                render(
+                    Fractal::Mandelbrot,
+                    coloring,
+                    300,
+                    &mut iterations,
+                    bounds,
+                    transform,
+                );
+            }
+            iterations
+        };
+        assert_eq!(render_with(true), render_with(false), "{:?}", coloring);
+    }
+
+    // 默认的有限差分导数与解析导数相近
+    struct Approximate;
+
+    impl PointEvaluator for Approximate {
+        fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+            z * z + c
+        }
+    }
+
+    let (z, dz, c) = (
+        Complex::new(0.3, -0.2),
+        Complex::new(1.5, 0.5),
+        Complex::new(-0.4, 0.6),
+    );
+    let (next, approximate) = Approximate.step_with_derivative(z, dz, c);
+    let (_, exact) = Quadratic.step_with_derivative(z, dz, c);
+    assert_eq!(next, Quadratic.step(z, c));
+    assert!((approximate - exact).norm() < 1e-5 * exact.norm());
+}
@@ -0,0 +1,328 @@
+//! 自动寻找值得深度缩放的位置
+//!
+//! 从起始视图出发，用很小的分辨率（`PROBE`）渲染当前视图，把它划分为 `GRID` 个格子，
+//! 以格子内迭代次数的熵为分数：远离边界的外部迭代次数几乎相同，内部全部达到迭代次数
+//! 限制，熵都接近零；边界附近的螺旋、触须和迷你曼德博集迭代次数变化丰富，熵很大。
+//! 每一步放大到分数最高的格子，以格子中迭代次数最多的像素为新的中心，逐步逼近边界，
+//! 直到达到目标缩放倍数；这样最后的视图往往正对着螺旋或者迷你曼德博集的中心。
+//!
+//! 为了得到互不相同的多个位置，第一步按分数从高到低各取一个格子，之后每个位置各自
+//! 贪心地下降。`animate --autozoom` 沿同样的下降路径生成缩放动画，见 `follow`。
+
+use crate::perturbation;
+use crate::precise::{self, bits_for_spacing, Fixed, FixedComplex};
+use crate::progress::Progress;
+use crate::{
+    corners_from_center, pixel_spacing, render_parallel, Coloring, Fractal, PixelTransform,
+};
+use std::collections::HashMap;
+
+/// 评估每个视图时渲染的像素尺寸
+pub const PROBE: (usize, usize) = (96, 72);
+
+/// 每个视图划分成的格子数，每一步放大到其中一个格子
+const GRID: (usize, usize) = (6, 6);
+
+/// 找到的位置
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    /// 视图中心，精度足以在目标缩放倍数下分辨单个像素
+    pub center: FixedComplex,
+    pub zoom: f64,
+    /// 整个视图的迭代次数的熵（比特）
+    pub score: f64,
+}
+
+/// 缩放倍数为 `zoom` 时使用的迭代次数限制：越深的位置需要越多的迭代次数才能分辨边界，
+/// 因此随缩放倍数的对数增长，但不低于 `base`
+pub fn iteration_limit(base: usize, zoom: f64) -> usize {
+    base.max(100 + (50.0 * zoom.log2().max(0.0)) as usize)
+}
+
+/// 迭代缓冲区中的值的香农熵（比特），内部像素作为单独一类
+pub fn entropy(values: impl IntoIterator<Item = u32>) -> f64 {
+    let mut counts = HashMap::new();
+    let mut total = 0;
+    for value in values {
+        *counts.entry(value).or_insert(0usize) += 1;
+        total += 1;
+    }
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum::<f64>()
+        .max(0.0)
+}
+
+/// 以 `center` 为中心、缩放倍数为 `zoom` 的 `PROBE` 大小的逃逸时间缓冲区
+///
+/// 超出 `f64` 的分辨率时用微扰渲染，`center` 的精度必须足以分辨像素间距。
+fn probe(fractal: Fractal, center: &FixedComplex, zoom: f64, limit: usize) -> Vec<u32> {
+    let spacing = pixel_spacing(PROBE, zoom);
+    let mut iterations = vec![0; PROBE.0 * PROBE.1];
+    let hidden = Progress::hidden();
+    if precise::required(center.to_complex(), spacing) {
+        perturbation::render_parallel(
+            fractal,
+            Coloring::EscapeTime,
+            limit,
+            &mut iterations,
+            PROBE,
+            center,
+            spacing,
+            &hidden,
+        );
+    } else {
+        let (upper_left, lower_right) = corners_from_center(PROBE, center.to_complex(), zoom);
+        render_parallel(
+            fractal,
+            Coloring::EscapeTime,
+            limit,
+            &mut iterations,
+            PROBE,
+            PixelTransform::from_corners(PROBE, upper_left, lower_right),
+            (PROBE.0, PROBE.1 / 4),
+            false,
+            &hidden,
+        );
+    }
+    iterations
+}
+
+/// 把 `center` 处缩放倍数为 `zoom` 的视图划分为 `GRID` 个格子，按分数从高到低返回
+/// 各个格子的分数和中心；零分的格子被丢掉
+fn children(
+    fractal: Fractal,
+    center: &FixedComplex,
+    zoom: f64,
+    base_limit: usize,
+) -> Vec<(f64, FixedComplex)> {
+    let iterations = probe(fractal, center, zoom, iteration_limit(base_limit, zoom));
+    let spacing = pixel_spacing(PROBE, zoom);
+    let bits = center.re.bits();
+    let cell = (PROBE.0 / GRID.0, PROBE.1 / GRID.1);
+    let mut children = Vec::new();
+    for row in 0..GRID.1 {
+        for column in 0..GRID.0 {
+            let (left, top) = (column * cell.0, row * cell.1);
+            let pixels: Vec<(usize, usize)> = (top..top + cell.1)
+                .flat_map(|y| (left..left + cell.0).map(move |x| (x, y)))
+                .collect();
+            let score = entropy(pixels.iter().map(|&(x, y)| iterations[y * PROBE.0 + x]));
+            if score <= 0.0 {
+                continue;
+            }
+            // 以格子中迭代次数最多（离边界最近）的像素为新的中心，逐步逼近边界
+            let &(x, y) = pixels
+                .iter()
+                .max_by_key(|&&(x, y)| iterations[y * PROBE.0 + x])
+                .expect("cells are not empty");
+            // 相对视图中心的偏移，行号越大虚部越小
+            let (x, y) = (
+                x as f64 - PROBE.0 as f64 / 2.0,
+                y as f64 - PROBE.1 as f64 / 2.0,
+            );
+            children.push((
+                score,
+                FixedComplex {
+                    re: &center.re + &Fixed::from_f64(x * spacing, bits),
+                    im: &center.im - &Fixed::from_f64(y * spacing, bits),
+                },
+            ));
+        }
+    }
+    children.sort_by(|a, b| b.0.total_cmp(&a.0));
+    children
+}
+
+/// 放大到 `target_zoom` 倍所需的二进制小数位数
+///
+/// 比 `PROBE` 所需的多留 16 位，缩略图和动画帧的分辨率可以比 `PROBE` 高得多。
+fn precision(target_zoom: f64) -> u32 {
+    bits_for_spacing(pixel_spacing(PROBE, target_zoom)) + 16
+}
+
+/// 从 `zoom` 倍放大一步后的缩放倍数，不超过 `target_zoom`
+fn next_zoom(zoom: f64, target_zoom: f64) -> f64 {
+    (zoom * GRID.0.min(GRID.1) as f64).min(target_zoom)
+}
+
+/// 从以 `start` 为中心、缩放倍数为 `start_zoom` 的视图出发，每一步放大到分数最高的格子，
+/// 直到 `target_zoom` 倍；返回途经的每个视图的中心和缩放倍数，第一项是起始视图
+///
+/// 每一步放大 `GRID` 倍，迭代次数限制见 `iteration_limit`。所有格子都是零分（完全落在
+/// 内部或者远离边界的外部）时停在当前的缩放倍数上。
+pub fn descend(
+    fractal: Fractal,
+    start: &FixedComplex,
+    start_zoom: f64,
+    target_zoom: f64,
+    base_limit: usize,
+) -> Vec<(FixedComplex, f64)> {
+    assert!(start_zoom > 0.0 && target_zoom >= start_zoom);
+    let mut path = vec![(start.with_bits(precision(target_zoom)), start_zoom)];
+    while let Some((center, zoom)) = path.last().filter(|(_, zoom)| *zoom < target_zoom) {
+        match children(fractal, center, *zoom, base_limit)
+            .into_iter()
+            .next()
+        {
+            Some((_, child)) => {
+                let zoom = next_zoom(*zoom, target_zoom);
+                path.push((child, zoom));
+            }
+            None => break,
+        }
+    }
+    path
+}
+
+/// 沿 `descend` 得到的路径放大到 `zoom` 倍时的视图中心
+///
+/// 在相邻的两个视图之间，下一个视图的中心在画面上匀速移到正中，同时缩放倍数按对数
+/// 均匀增长，因此整个过程中它都在画面内。超出路径两端时取端点。
+pub fn follow(path: &[(FixedComplex, f64)], zoom: f64) -> FixedComplex {
+    let next = path.partition_point(|&(_, waypoint)| waypoint <= zoom);
+    if next == 0 {
+        return path[0].0.clone();
+    }
+    let (from, from_zoom) = &path[next - 1];
+    let Some((to, to_zoom)) = path.get(next) else {
+        return from.clone();
+    };
+    let t = (zoom / from_zoom).ln() / (to_zoom / from_zoom).ln();
+    // 下一个中心相对视图中心的像素偏移随 t 线性减小到零
+    let scale = (1.0 - t) * from_zoom / zoom;
+    let bits = to.re.bits();
+    FixedComplex {
+        re: &to.re + &Fixed::from_f64((&from.re - &to.re).to_f64() * scale, bits),
+        im: &to.im + &Fixed::from_f64((&from.im - &to.im).to_f64() * scale, bits),
+    }
+}
+
+/// 从以 `start` 为中心、缩放倍数为 `start_zoom` 的视图出发，找出最多 `count` 个缩放
+/// 倍数为 `target_zoom` 的位置，按分数从高到低排列
+///
+/// 第一步按分数从高到低各取一个格子，之后各自用 `descend` 下降，因此下降途中失去边界的
+/// 位置会停在较小的缩放倍数上。每找到一个位置就在 `progress` 上记录一次。
+pub fn explore(
+    fractal: Fractal,
+    start: &FixedComplex,
+    start_zoom: f64,
+    target_zoom: f64,
+    count: usize,
+    base_limit: usize,
+    progress: &Progress,
+) -> Vec<Candidate> {
+    assert!(start_zoom > 0.0 && target_zoom >= start_zoom);
+    let start = start.with_bits(precision(target_zoom));
+    progress.start(count, "locations");
+    let first = if start_zoom < target_zoom {
+        let zoom = next_zoom(start_zoom, target_zoom);
+        children(fractal, &start, start_zoom, base_limit)
+            .into_iter()
+            .map(|(_, child)| (child, zoom))
+            .collect()
+    } else {
+        vec![(start, start_zoom)]
+    };
+    let mut candidates = Vec::new();
+    for (center, zoom) in first.into_iter().take(count) {
+        let (center, zoom) = descend(fractal, &center, zoom, target_zoom, base_limit)
+            .pop()
+            .expect("paths are not empty");
+        let limit = iteration_limit(base_limit, zoom);
+        let score = entropy(probe(fractal, &center, zoom, limit));
+        candidates.push(Candidate {
+            center,
+            zoom,
+            score,
+        });
+        progress.inc(1);
+    }
+    progress.finish();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+#[test]
+fn test_entropy() {
+    assert_eq!(entropy([7; 10]), 0.0);
+    assert_eq!(entropy([1, 2, 1, 2]), 1.0);
+    assert_eq!(entropy([1, 2, 3, crate::INTERIOR]), 2.0);
+    assert_eq!(iteration_limit(255, 1.0), 255);
+    assert_eq!(iteration_limit(255, 1024.0), 600);
+}
+
+#[test]
+fn test_explore() {
+    let start = FixedComplex::parse("-0.5,0", 64).unwrap();
+    let candidates = explore(
+        Fractal::Mandelbrot,
+        &start,
+        1.0,
+        1e4,
+        3,
+        100,
+        &Progress::hidden(),
+    );
+    assert_eq!(candidates.len(), 3);
+    for (i, candidate) in candidates.iter().enumerate() {
+        assert_eq!(candidate.zoom, 1e4);
+        assert!(candidate.score > 2.0);
+        // 位置都在边界附近，彼此不同
+        let center = candidate.center.to_complex();
+        assert!(center.norm() < 2.0);
+        for other in &candidates[..i] {
+            assert!((other.center.to_complex() - center).norm() > 1e-3);
+        }
+    }
+    assert!(candidates
+        .windows(2)
+        .all(|pair| pair[0].score >= pair[1].score));
+
+    // 已经在目标缩放倍数上时直接评估起始视图
+    let candidates = explore(
+        Fractal::Mandelbrot,
+        &start,
+        2.0,
+        2.0,
+        3,
+        100,
+        &Progress::hidden(),
+    );
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].center.to_complex(), start.to_complex());
+}
+
+#[test]
+fn test_descend() {
+    let start = FixedComplex::parse("-0.5,0", 64).unwrap();
+    let path = descend(Fractal::Mandelbrot, &start, 1.0, 1e3, 100);
+    let zooms: Vec<f64> = path.iter().map(|&(_, zoom)| zoom).collect();
+    assert_eq!(zooms, [1.0, 6.0, 36.0, 216.0, 1e3]);
+    // 每一步的中心都在上一个视图之内
+    for pair in path.windows(2) {
+        let ((from, zoom), (to, _)) = (&pair[0], &pair[1]);
+        let offset = to.to_complex() - from.to_complex();
+        let spacing = pixel_spacing(PROBE, *zoom);
+        assert!(offset.re.abs() <= spacing * PROBE.0 as f64 / 2.0);
+        assert!(offset.im.abs() <= spacing * PROBE.1 as f64 / 2.0);
+    }
+
+    // 下一个中心的像素偏移按缩放倍数的对数线性减小
+    let path = [
+        (FixedComplex::parse("0,0", 64).unwrap(), 1.0),
+        (FixedComplex::parse("1,-1", 64).unwrap(), 4.0),
+    ];
+    assert_eq!(follow(&path, 0.5), path[0].0);
+    assert_eq!(follow(&path, 1.0), path[0].0);
+    assert_eq!(
+        follow(&path, 2.0).to_complex(),
+        num::Complex::new(0.75, -0.75)
+    );
+    assert_eq!(follow(&path, 4.0), path[1].0);
+    assert_eq!(follow(&path, 1e6), path[1].0);
+}
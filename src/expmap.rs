@@ -0,0 +1,176 @@
+//! 指数映射（对数极坐标条带）
+//!
+//! 缩放动画的相邻帧之间绝大部分像素是重复计算的。指数映射把以缩放中心为原点的
+//! 对数极坐标平面渲染成一条竖直的条带：第 `column` 列对应辐角 `2π column / width`，
+//! 每向下一行半径缩小 `e^(2π / width)` 倍，这样条带中的像素在各处都近似为正方形。
+//! 整个缩放过程只需要渲染一次条带，之后每一帧都只是从条带中按半径和辐角取值，
+//! 比逐帧渲染快得多。
+
+use crate::progress::Progress;
+use crate::{encode_escape, pixel_spacing, Coloring, Fractal, PixelTransform};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use std::f64::consts::TAU;
+
+/// 以 `center` 为中心的对数极坐标条带
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExpMap {
+    pub center: Complex<f64>,
+    /// 条带的宽度，即一周的像素数
+    pub width: usize,
+    /// 条带的行数
+    pub height: usize,
+    /// 第一行的半径
+    pub outer: f64,
+}
+
+impl ExpMap {
+    /// 足以重投影出 `bounds` 大小、缩放倍数在 `start_zoom` 和 `end_zoom` 之间的所有帧的条带
+    ///
+    /// 第一行的圆经过最浅一帧的四角，最后一行的半径不超过最深一帧像素间距的一半；
+    /// 宽度使最浅一帧四角处条带的像素不大于帧的像素。
+    pub fn covering(
+        center: Complex<f64>,
+        bounds: (usize, usize),
+        start_zoom: f64,
+        end_zoom: f64,
+    ) -> ExpMap {
+        let diagonal = (bounds.0 as f64).hypot(bounds.1 as f64);
+        let outer = pixel_spacing(bounds, start_zoom.min(end_zoom)) * diagonal / 2.0;
+        let inner = pixel_spacing(bounds, start_zoom.max(end_zoom)) / 2.0;
+        let width = (TAU / 2.0 * diagonal).ceil() as usize;
+        let height = (width as f64 / TAU * (outer / inner).ln()).ceil() as usize + 1;
+        ExpMap {
+            center,
+            width,
+            height,
+            outer,
+        }
+    }
+
+    /// 条带的像素尺寸
+    pub fn bounds(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// 第 `row` 行的半径
+    pub fn radius(&self, row: usize) -> f64 {
+        self.outer * (-TAU * row as f64 / self.width as f64).exp()
+    }
+
+    /// 条带中像素 `pixed`（列、行）对应的点
+    pub fn point(&self, pixed: (usize, usize)) -> Complex<f64> {
+        let angle = TAU * pixed.0 as f64 / self.width as f64;
+        self.center + Complex::from_polar(self.radius(pixed.1), angle)
+    }
+
+    /// 离点 `point` 最近的条带像素的下标，比最后一行更靠近中心的点取最后一行
+    pub fn index(&self, point: Complex<f64>) -> usize {
+        let offset = point - self.center;
+        let scale = self.width as f64 / TAU;
+        let row = (scale * (self.outer / offset.norm()).ln()).round();
+        let row = if row.is_nan() {
+            self.height - 1
+        } else {
+            row.clamp(0.0, (self.height - 1) as f64) as usize
+        };
+        let column = (scale * offset.arg()).round().rem_euclid(self.width as f64) as usize;
+        row * self.width + column
+    }
+}
+
+#[test]
+fn test_exp_map() {
+    let center = Complex { re: -0.5, im: 0.25 };
+    let map = ExpMap::covering(center, (40, 30), 1.0, 100.0);
+    assert_eq!(map.width, 158);
+    // 最浅一帧的像素间距为 4 / 30，半对角线为 25 个像素；最深一帧的半个像素为 1 / 1500
+    assert!((map.outer - 10.0 / 3.0).abs() < 1e-12);
+    assert!(map.radius(map.height - 1) <= 1.0 / 1500.0);
+    assert!(map.radius(map.height - 2) > 1.0 / 1500.0);
+
+    let point = map.point((map.width / 4, 7));
+    assert!(((point - center).norm() - map.radius(7)).abs() < 1e-12);
+    assert!((point - center).re.abs() < map.radius(7) * 0.02);
+    assert_eq!(map.index(point), 7 * map.width + map.width / 4);
+    assert_eq!(map.index(map.point((map.width - 1, 3))), 4 * map.width - 1);
+    assert_eq!(map.index(center), (map.height - 1) * map.width);
+    assert_eq!(map.index(center + 100.0), 0);
+}
+
+/// 并行渲染条带 `map`，返回按 `encode_escape` 编码的逃逸值，每完成一行就在
+/// `progress` 上记录一次
+///
+/// 每个像素以它所在行的像素宽度 `2π r / width` 作为距离估计着色的像素间距。
+pub fn render(
+    map: &ExpMap,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    progress: &Progress,
+) -> Vec<u32> {
+    let mut strip = vec![0; map.width * map.height];
+    progress.start(map.height, "rows");
+    strip
+        .par_chunks_mut(map.width)
+        .enumerate()
+        .for_each(|(row, band)| {
+            let spacing = map.radius(row) * TAU / map.width as f64;
+            for (column, value) in band.iter_mut().enumerate() {
+                let point = map.point((column, row));
+                *value = encode_escape(fractal.escape_value(coloring, point, limit, spacing));
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    strip
+}
+
+/// 从 `render` 得到的条带 `strip` 中取出 `bounds` 大小、像素按 `transform` 映射的一帧
+pub fn reproject(
+    map: &ExpMap,
+    strip: &[u32],
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+) -> Vec<u32> {
+    assert_eq!(strip.len(), map.width * map.height);
+    let mut frame = vec![0; bounds.0 * bounds.1];
+    frame
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, value) in band.iter_mut().enumerate() {
+                *value = strip[map.index(transform.point((column, row)))];
+            }
+        });
+    frame
+}
+
+#[test]
+fn test_reproject() {
+    use crate::decode_escape;
+
+    // 重投影与直接渲染的逃逸次数几乎处处相同
+    let center = Complex { re: 0.3, im: 1.0 };
+    let bounds = (32, 24);
+    let map = ExpMap::covering(center, bounds, 1.0, 8.0);
+    let (fractal, coloring) = (Fractal::Mandelbrot, Coloring::EscapeTime);
+    let strip = render(&map, fractal, coloring, 100, &Progress::hidden());
+    for zoom in [1.0, 3.0, 8.0] {
+        let (upper_left, lower_right) = crate::corners_from_center(bounds, center, zoom);
+        let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+        let frame = reproject(&map, &strip, bounds, transform);
+        let mut expected = vec![0; bounds.0 * bounds.1];
+        crate::render(fractal, coloring, 100, &mut expected, bounds, transform);
+        // 取最近的条带像素带来不到一个像素的偏移，只会让逃逸次数差 1
+        let mismatches = frame
+            .iter()
+            .zip(&expected)
+            .filter(|&(&a, &b)| match (decode_escape(a), decode_escape(b)) {
+                (Some(a), Some(b)) => (a - b).abs() > 1.0,
+                (a, b) => a != b,
+            })
+            .count();
+        assert!(mismatches * 20 < frame.len(), "{} mismatches", mismatches);
+    }
+}
@@ -0,0 +1,375 @@
+//! 双倍精度（double-double）的深度缩放
+//!
+//! 用两个 `f64` 之和 `hi + lo` 表示一个数，有效数字约 106 位，可以把缩放倍数推到
+//! 大约 1e30。加减乘都只需要十几次 `f64` 运算，比 `precise` 的 `BigInt` 定点数快得多，
+//! 介于 `f64` 和任意精度之间。`DoubleComplex` 实现了 `precise::DeepComplex`，渲染时交给
+//! `precise::render_parallel`。
+
+use crate::precise::{DeepComplex, Fixed, FixedComplex};
+use crate::Fractal;
+use num::Complex;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// 双倍精度的浮点数，值为 `hi + lo`，其中 `|lo|` 不超过 `hi` 最低位的一半
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+/// 精确地计算 `a + b`，返回舍入后的和及其误差
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    let error = (a - (sum - b_virtual)) + (b - b_virtual);
+    (sum, error)
+}
+
+/// 与 `two_sum` 相同，但要求 `|a| >= |b|`
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    (sum, b - (sum - a))
+}
+
+/// 精确地计算 `a * b`，返回舍入后的积及其误差
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let product = a * b;
+    (product, a.mul_add(b, -product))
+}
+
+impl DoubleDouble {
+    /// 值为 `value` 的双倍精度数
+    pub fn from_f64(value: f64) -> DoubleDouble {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+
+    /// 最接近定点数 `value` 的双倍精度数
+    pub fn from_fixed(value: &Fixed) -> DoubleDouble {
+        let hi = value.to_f64();
+        let lo = (value - &Fixed::from_f64(hi, value.bits())).to_f64();
+        let (hi, lo) = quick_two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    /// 最接近该双倍精度数的 `f64` 值
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    /// 绝对值
+    pub fn abs(self) -> DoubleDouble {
+        if self.hi < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    /// 乘以 `f64` 值 `factor`，这比乘以一个双倍精度数便宜
+    pub fn mul_f64(self, factor: f64) -> DoubleDouble {
+        let (product, error) = two_product(self.hi, factor);
+        let (hi, lo) = quick_two_sum(product, error + self.lo * factor);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (sum, error) = two_sum(self.hi, other.hi);
+        let (low, low_error) = two_sum(self.lo, other.lo);
+        let (sum, error) = quick_two_sum(sum, error + low);
+        let (hi, lo) = quick_two_sum(sum, error + low_error);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (product, error) = two_product(self.hi, other.hi);
+        let error = error + (self.hi * other.lo + self.lo * other.hi);
+        let (hi, lo) = quick_two_sum(product, error);
+        DoubleDouble { hi, lo }
+    }
+}
+
+#[test]
+fn test_double_double_arithmetic() {
+    // 2^-80 在 f64 中加到 1 上会被舍去，在双倍精度中保留下来
+    let tiny = 2f64.powi(-80);
+    let one = DoubleDouble::from_f64(1.0);
+    let sum = one + DoubleDouble::from_f64(tiny);
+    assert_eq!(sum.to_f64(), 1.0);
+    assert_eq!((sum - one).to_f64(), tiny);
+    assert_eq!(((sum * sum) - one).to_f64(), 2.0 * tiny);
+    assert_eq!((-sum).abs(), sum);
+    assert_eq!(sum.mul_f64(-2.0), -(sum + sum));
+
+    let bits = 128;
+    let fixed = Fixed::parse("0.1000000000000000000000000001", bits).unwrap();
+    let value = DoubleDouble::from_fixed(&fixed);
+    assert_eq!(value.to_f64(), 0.1);
+    let error = (value - DoubleDouble::from_fixed(&Fixed::parse("0.1", bits).unwrap())).to_f64();
+    assert!((error - 1e-28).abs() < 1e-32);
+}
+
+/// 使用 `DoubleDouble` 表示实部和虚部的复数
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleComplex {
+    pub re: DoubleDouble,
+    pub im: DoubleDouble,
+}
+
+impl DoubleComplex {
+    /// 值为 `c` 的双倍精度复数
+    pub fn from_complex(c: Complex<f64>) -> DoubleComplex {
+        DoubleComplex {
+            re: DoubleDouble::from_f64(c.re),
+            im: DoubleDouble::from_f64(c.im),
+        }
+    }
+
+    /// 最接近高精度复数 `c` 的双倍精度复数
+    pub fn from_fixed(c: &FixedComplex) -> DoubleComplex {
+        DoubleComplex {
+            re: DoubleDouble::from_fixed(&c.re),
+            im: DoubleDouble::from_fixed(&c.im),
+        }
+    }
+
+    /// 最接近该复数的 `Complex<f64>`
+    pub fn to_complex(self) -> Complex<f64> {
+        Complex {
+            re: self.re.to_f64(),
+            im: self.im.to_f64(),
+        }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        (self.re * self.re + self.im * self.im).to_f64()
+    }
+
+    fn mul(self, other: DoubleComplex) -> DoubleComplex {
+        DoubleComplex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    /// 对 `self` 做一次分形 `fractal` 的迭代，与 `Fractal::step` 相同
+    ///
+    /// 调用者需要先用 `Fractal::supports_deep_zoom` 确认迭代公式的次数是整数
+    pub fn step(self, fractal: Fractal, c: DoubleComplex) -> DoubleComplex {
+        let z = match fractal {
+            Fractal::Mandelbrot | Fractal::Julia(_) => self,
+            Fractal::BurningShip => DoubleComplex {
+                re: self.re.abs(),
+                im: self.im.abs(),
+            },
+            Fractal::Tricorn => DoubleComplex {
+                re: self.re,
+                im: -self.im,
+            },
+            Fractal::Multibrot(power) => {
+                assert!(fractal.supports_deep_zoom(), "non-integer power {}", power);
+                let mut product = self;
+                for _ in 1..power as u32 {
+                    product = product.mul(self);
+                }
+                return DoubleComplex {
+                    re: product.re + c.re,
+                    im: product.im + c.im,
+                };
+            }
+            Fractal::Formula(_)
+            | Fractal::Phoenix { .. }
+            | Fractal::Lambda(_)
+            | Fractal::Hybrid { .. } => {
+                unreachable!("{:?} does not support deep zoom", fractal)
+            }
+        };
+        DoubleComplex {
+            re: z.re * z.re - z.im * z.im + c.re,
+            im: (z.re * z.im).mul_f64(2.0) + c.im,
+        }
+    }
+}
+
+/// 返回点 `point` 在分形 `fractal` 中的迭代起点和常数，与 `Fractal::orbit_start` 相同
+pub fn orbit_start(fractal: Fractal, point: DoubleComplex) -> (DoubleComplex, DoubleComplex) {
+    match fractal {
+        Fractal::Mandelbrot
+        | Fractal::BurningShip
+        | Fractal::Tricorn
+        | Fractal::Multibrot(_)
+        | Fractal::Formula(_)
+        | Fractal::Phoenix { c: None, .. }
+        | Fractal::Hybrid { c: None, .. } => (DoubleComplex::default(), point),
+        Fractal::Julia(c)
+        | Fractal::Phoenix { c: Some(c), .. }
+        | Fractal::Lambda(Some(c))
+        | Fractal::Hybrid { c: Some(c), .. } => (point, DoubleComplex::from_complex(c)),
+        Fractal::Lambda(None) => (DoubleComplex::from_complex(Complex::new(0.5, 0.0)), point),
+    }
+}
+
+/// 双倍精度的机器精度，即 `1 + ε` 是大于 1 的最小可表示值的近似
+const EPSILON: f64 = f64::EPSILON * f64::EPSILON;
+
+/// 判断以 `center` 为中心、像素间距为 `spacing` 的视图能否用 `DoubleDouble` 分辨，
+/// 判据与 `real::resolves` 相同
+pub fn resolves(center: Complex<f64>, spacing: f64) -> bool {
+    let magnitude = center.re.abs().max(center.im.abs()).max(1.0);
+    spacing >= magnitude * EPSILON * 256.0
+}
+
+#[test]
+fn test_resolves() {
+    let center = Complex { re: -0.75, im: 0.1 };
+    assert!(resolves(center, 1e-16));
+    assert!(resolves(center, 1e-28));
+    assert!(!resolves(center, 1e-32));
+}
+
+/// 以双倍精度从 `z` 出发迭代分形 `fractal`
+///
+/// 当 `|z|^2` 超过 `bailout` 时返回逃逸时的迭代次数和转换为 `f64` 的 `z`；
+/// 达到迭代次数限制仍未逃逸则返回 `None`
+pub fn escape(
+    fractal: Fractal,
+    mut z: DoubleComplex,
+    c: DoubleComplex,
+    limit: usize,
+    bailout: f64,
+) -> Option<(usize, Complex<f64>)> {
+    for i in 0..limit {
+        if z.norm_sqr() > bailout {
+            return Some((i, z.to_complex()));
+        }
+        z = z.step(fractal, c);
+    }
+    None
+}
+
+#[test]
+fn test_escape() {
+    let origin = DoubleComplex::default();
+    for c in [
+        Complex { re: -0.75, im: 0.1 },
+        Complex { re: 2.0, im: 2.0 },
+        Complex { re: 0.3, im: 0.5 },
+    ] {
+        let double = DoubleComplex::from_complex(c);
+        for fractal in [
+            Fractal::Mandelbrot,
+            Fractal::BurningShip,
+            Fractal::Tricorn,
+            Fractal::Multibrot(4.0),
+        ] {
+            assert_eq!(
+                escape(fractal, origin, double, 500, 4.0).map(|(count, _)| count),
+                fractal.escape_time(c, 500)
+            );
+        }
+    }
+    assert_eq!(escape(Fractal::Mandelbrot, origin, origin, 500, 4.0), None);
+}
+
+impl DeepComplex for DoubleComplex {
+    type Spacing = DoubleDouble;
+
+    fn view(center: &FixedComplex, spacing: &Fixed) -> (DoubleComplex, DoubleDouble) {
+        let half_spacing = DoubleDouble::from_fixed(spacing).mul_f64(0.5);
+        (DoubleComplex::from_fixed(center), half_spacing)
+    }
+
+    fn offset(&self, half_spacing: &DoubleDouble, re: i64, im: i64) -> DoubleComplex {
+        DoubleComplex {
+            re: self.re + half_spacing.mul_f64(re as f64),
+            im: self.im + half_spacing.mul_f64(im as f64),
+        }
+    }
+
+    fn orbit_start(fractal: Fractal, point: DoubleComplex) -> (DoubleComplex, DoubleComplex) {
+        orbit_start(fractal, point)
+    }
+
+    fn escape(
+        fractal: Fractal,
+        z: DoubleComplex,
+        c: &DoubleComplex,
+        limit: usize,
+        bailout: f64,
+    ) -> Option<(usize, Complex<f64>)> {
+        escape(fractal, z, *c, limit, bailout)
+    }
+}
+
+#[test]
+fn test_render_parallel() {
+    use crate::precise::render_parallel;
+    use crate::progress::Progress;
+    use crate::Coloring;
+
+    // 超出 f64 分辨率的视图中，双倍精度的结果应当与任意精度基本一致
+    let bounds = (12, 8);
+    let spacing = 1e-22;
+    let bits = crate::precise::bits_for_spacing(spacing);
+    // c = i 是 Misiurewicz 点，任意缩放倍数下附近都有逃逸次数不多的细节
+    let center = FixedComplex::parse("0,1", bits).unwrap();
+    let spacing = Fixed::from_f64(spacing, bits);
+
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    render_parallel::<FixedComplex>(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        1000,
+        &mut expected,
+        bounds,
+        &center,
+        &spacing,
+        &Progress::hidden(),
+    );
+    let mut actual = vec![0; bounds.0 * bounds.1];
+    render_parallel::<DoubleComplex>(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        1000,
+        &mut actual,
+        bounds,
+        &center,
+        &spacing,
+        &Progress::hidden(),
+    );
+    let matching = actual.iter().zip(&expected).filter(|(a, e)| a == e).count();
+    assert!(
+        matching * 10 >= actual.len() * 9,
+        "{:?} {:?}",
+        actual,
+        expected
+    );
+    // 像素之间应当能分辨出细节，而不是一整块颜色
+    assert!(
+        actual.iter().any(|&value| value != actual[0]),
+        "{:?}",
+        actual
+    );
+}
@@ -0,0 +1,635 @@
+//! 自定义迭代公式
+//!
+//! `--formula "z^2 + c*z + c"` 把迭代公式解析为表达式树，再编译为嵌套的闭包，每次迭代
+//! 直接调用闭包而不必重新遍历表达式树。公式可以使用：
+//!
+//! - 变量 `z`、`c` 和虚数单位 `i`，以及形如 `2`、`0.5`、`1e-3` 的实数
+//! - 运算符 `+`、`-`、`*`、`/`、`^`（乘方右结合，优先级高于一元负号），数字与变量或
+//!   括号相邻时可以省略乘号，如 `2z`
+//! - 函数 `sin`、`cos`、`tan`、`exp`、`log`、`sqrt`、`conj`、`abs`（模长）、`re`、`im`
+//!
+//! 距离估计需要公式对 `c` 的导数，为此同一棵表达式树还会编译一份以对偶数求值的闭包，
+//! 在迭代 `z` 的同时按链式法则求出导数。
+
+use num::Complex;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// 公式中可以调用的函数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Exp,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Re,
+    Im,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Function> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "exp" => Function::Exp,
+            "log" => Function::Log,
+            "sqrt" => Function::Sqrt,
+            "conj" => Function::Conj,
+            "abs" => Function::Abs,
+            "re" => Function::Re,
+            "im" => Function::Im,
+            _ => return None,
+        })
+    }
+}
+
+/// 公式的表达式树
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Z,
+    C,
+    Constant(Complex<f64>),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // 只有后面跟着数字（可以带符号）时 e 才是指数，否则是下一个标识符的开头
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && matches!(chars[j], '+' | '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| format!("invalid number `{}`", text))?;
+            tokens.push(Token::Number(value));
+        } else if ch.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/^()".contains(ch) {
+            tokens.push(Token::Op(ch));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character `{}`", ch));
+        }
+    }
+    Ok(tokens)
+}
+
+/// 递归下降的语法分析器
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        loop {
+            if self.eat('+') {
+                left = Expr::Add(Box::new(left), Box::new(self.term()?));
+            } else if self.eat('-') {
+                left = Expr::Sub(Box::new(left), Box::new(self.term()?));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    /// term := unary (('*' | '/')? unary)*，省略的乘号只能出现在数字、变量或括号之前
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            if self.eat('*') {
+                left = Expr::Mul(Box::new(left), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                left = Expr::Div(Box::new(left), Box::new(self.unary()?));
+            } else if matches!(
+                self.peek(),
+                Some(Token::Number(_) | Token::Ident(_) | Token::Op('('))
+            ) {
+                left = Expr::Mul(Box::new(left), Box::new(self.power()?));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    /// unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    /// power := primary ('^' unary)?
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// primary := number | 'z' | 'c' | 'i' | function '(' expr ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Constant(Complex::new(value, 0.0))),
+            Some(Token::Op('(')) => {
+                let inner = self.expr()?;
+                if !self.eat(')') {
+                    return Err("missing `)`".to_string());
+                }
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "z" => Ok(Expr::Z),
+                "c" => Ok(Expr::C),
+                "i" => Ok(Expr::Constant(Complex::new(0.0, 1.0))),
+                _ => {
+                    let function =
+                        Function::parse(&name).ok_or_else(|| format!("unknown name `{}`", name))?;
+                    if !self.eat('(') {
+                        return Err(format!("expected `(` after `{}`", name));
+                    }
+                    let argument = self.expr()?;
+                    if !self.eat(')') {
+                        return Err("missing `)`".to_string());
+                    }
+                    Ok(Expr::Call(function, Box::new(argument)))
+                }
+            },
+            Some(Token::Op(op)) => Err(format!("unexpected `{}`", op)),
+            None => Err("unexpected end of formula".to_string()),
+        }
+    }
+}
+
+/// 把字符串 `s` 解析为表达式树
+pub fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        position: 0,
+    };
+    let expr = parser.expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(Token::Op(op)) => Err(format!("unexpected `{}`", op)),
+        Some(Token::Number(value)) => Err(format!("unexpected `{}`", value)),
+        Some(Token::Ident(name)) => Err(format!("unexpected `{}`", name)),
+    }
+}
+
+#[test]
+fn test_parse_expr() {
+    use Expr::*;
+    let b = Box::new;
+    let number = |value| Constant(Complex::new(value, 0.0));
+    assert_eq!(
+        parse_expr("z^2 + c").unwrap(),
+        Add(b(Pow(b(Z), b(number(2.0)))), b(C))
+    );
+    // 乘方优先于一元负号，并且右结合
+    assert_eq!(
+        parse_expr("-z^2^3").unwrap(),
+        Neg(b(Pow(b(Z), b(Pow(b(number(2.0)), b(number(3.0)))))))
+    );
+    assert_eq!(
+        parse_expr("2z - 1e-3c").unwrap(),
+        Sub(b(Mul(b(number(2.0)), b(Z))), b(Mul(b(number(1e-3)), b(C))))
+    );
+    assert_eq!(
+        parse_expr("exp(z)/z^-1").unwrap(),
+        Div(
+            b(Call(Function::Exp, b(Z))),
+            b(Pow(b(Z), b(Neg(b(number(1.0))))))
+        )
+    );
+    assert!(parse_expr("z^2 +").is_err());
+    assert!(parse_expr("(z").is_err());
+    assert!(parse_expr("foo(z)").is_err());
+    assert!(parse_expr("sin z").is_err());
+    assert!(parse_expr("z $ c").is_err());
+    assert!(parse_expr("z)").is_err());
+}
+
+/// 可以代入公式求值的数：复数，或者同时携带导数的对偶数
+pub trait Value:
+    Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Send
+    + Sync
+    + 'static
+{
+    /// 导数为零的常数
+    fn constant(value: Complex<f64>) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn powf(self, x: f64) -> Self;
+    fn powc(self, exponent: Self) -> Self;
+    fn call(self, function: Function) -> Self;
+}
+
+impl Value for Complex<f64> {
+    fn constant(value: Complex<f64>) -> Self {
+        value
+    }
+
+    fn powi(self, n: i32) -> Self {
+        Complex::powi(&self, n)
+    }
+
+    fn powf(self, x: f64) -> Self {
+        Complex::powf(self, x)
+    }
+
+    fn powc(self, exponent: Self) -> Self {
+        Complex::powc(self, exponent)
+    }
+
+    fn call(self, function: Function) -> Self {
+        match function {
+            Function::Sin => self.sin(),
+            Function::Cos => self.cos(),
+            Function::Tan => self.tan(),
+            Function::Exp => self.exp(),
+            Function::Log => self.ln(),
+            Function::Sqrt => self.sqrt(),
+            Function::Conj => self.conj(),
+            Function::Abs => Complex::new(self.norm(), 0.0),
+            Function::Re => Complex::new(self.re, 0.0),
+            Function::Im => Complex::new(self.im, 0.0),
+        }
+    }
+}
+
+/// 对偶数 `value + derivative·ε`（`ε² = 0`），按链式法则随求值一起传播导数
+///
+/// `conj`、`abs`、`re` 和 `im` 不是全纯函数，它们的“导数”只是沿 `derivative` 方向的
+/// 方向导数，距离估计对这样的公式只是近似。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual {
+    pub value: Complex<f64>,
+    pub derivative: Complex<f64>,
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, other: Dual) -> Dual {
+        Dual {
+            value: self.value + other.value,
+            derivative: self.derivative + other.derivative,
+        }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, other: Dual) -> Dual {
+        Dual {
+            value: self.value - other.value,
+            derivative: self.derivative - other.derivative,
+        }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, other: Dual) -> Dual {
+        Dual {
+            value: self.value * other.value,
+            derivative: self.derivative * other.value + self.value * other.derivative,
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, other: Dual) -> Dual {
+        Dual {
+            value: self.value / other.value,
+            derivative: (self.derivative * other.value - self.value * other.derivative)
+                / (other.value * other.value),
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual {
+            value: -self.value,
+            derivative: -self.derivative,
+        }
+    }
+}
+
+impl Dual {
+    /// 值为 `value(x)`、导数为 `slope(x) * x'` 的对偶数
+    fn chain(self, value: Complex<f64>, slope: Complex<f64>) -> Dual {
+        Dual {
+            value,
+            derivative: slope * self.derivative,
+        }
+    }
+}
+
+impl Value for Dual {
+    fn constant(value: Complex<f64>) -> Self {
+        Dual {
+            value,
+            derivative: Complex::new(0.0, 0.0),
+        }
+    }
+
+    fn powi(self, n: i32) -> Self {
+        let slope = if n == 0 {
+            Complex::new(0.0, 0.0)
+        } else {
+            self.value.powi(n - 1) * n as f64
+        };
+        self.chain(self.value.powi(n), slope)
+    }
+
+    fn powf(self, x: f64) -> Self {
+        self.chain(self.value.powf(x), self.value.powf(x - 1.0) * x)
+    }
+
+    fn powc(self, exponent: Self) -> Self {
+        // a^b = exp(b ln a)，导数为 a^b (b' ln a + b a' / a)
+        let value = self.value.powc(exponent.value);
+        Dual {
+            value,
+            derivative: value
+                * (exponent.derivative * self.value.ln()
+                    + exponent.value * self.derivative / self.value),
+        }
+    }
+
+    fn call(self, function: Function) -> Self {
+        let x = self.value;
+        match function {
+            Function::Sin => self.chain(x.sin(), x.cos()),
+            Function::Cos => self.chain(x.cos(), -x.sin()),
+            Function::Tan => self.chain(x.tan(), (x.cos() * x.cos()).inv()),
+            Function::Exp => self.chain(x.exp(), x.exp()),
+            Function::Log => self.chain(x.ln(), x.inv()),
+            Function::Sqrt => self.chain(x.sqrt(), (x.sqrt() * 2.0).inv()),
+            Function::Conj => Dual {
+                value: x.conj(),
+                derivative: self.derivative.conj(),
+            },
+            Function::Abs => Dual {
+                value: Complex::new(x.norm(), 0.0),
+                derivative: Complex::new((x.conj() * self.derivative).re / x.norm(), 0.0),
+            },
+            Function::Re => Dual {
+                value: Complex::new(x.re, 0.0),
+                derivative: Complex::new(self.derivative.re, 0.0),
+            },
+            Function::Im => Dual {
+                value: Complex::new(x.im, 0.0),
+                derivative: Complex::new(self.derivative.im, 0.0),
+            },
+        }
+    }
+}
+
+/// 编译后的公式：以 `z` 和 `c` 为参数的闭包
+type Compiled<T> = Box<dyn Fn(T, T) -> T + Send + Sync>;
+
+/// 把表达式树编译为闭包，常数指数的乘方在编译时选定最快的求值方式
+pub fn compile<T: Value>(expr: &Expr) -> Compiled<T> {
+    let binary = |a: &Expr, b: &Expr, op: fn(T, T) -> T| -> Compiled<T> {
+        let (a, b) = (compile::<T>(a), compile::<T>(b));
+        Box::new(move |z, c| op(a(z, c), b(z, c)))
+    };
+    match expr {
+        Expr::Z => Box::new(|z, _| z),
+        Expr::C => Box::new(|_, c| c),
+        &Expr::Constant(value) => {
+            let value = T::constant(value);
+            Box::new(move |_, _| value)
+        }
+        Expr::Neg(a) => {
+            let a = compile::<T>(a);
+            Box::new(move |z, c| -a(z, c))
+        }
+        Expr::Add(a, b) => binary(a, b, |a, b| a + b),
+        Expr::Sub(a, b) => binary(a, b, |a, b| a - b),
+        Expr::Mul(a, b) => binary(a, b, |a, b| a * b),
+        Expr::Div(a, b) => binary(a, b, |a, b| a / b),
+        Expr::Pow(base, exponent) => {
+            let base = compile::<T>(base);
+            match constant(exponent) {
+                Some(k) if k.im == 0.0 && k.re.fract() == 0.0 && k.re.abs() <= i32::MAX as f64 => {
+                    let n = k.re as i32;
+                    Box::new(move |z, c| base(z, c).powi(n))
+                }
+                Some(k) if k.im == 0.0 => Box::new(move |z, c| base(z, c).powf(k.re)),
+                _ => {
+                    let exponent = compile::<T>(exponent);
+                    Box::new(move |z, c| base(z, c).powc(exponent(z, c)))
+                }
+            }
+        }
+        &Expr::Call(function, ref a) => {
+            let a = compile::<T>(a);
+            Box::new(move |z, c| a(z, c).call(function))
+        }
+    }
+}
+
+/// 不含 `z` 和 `c` 的表达式的值
+fn constant(expr: &Expr) -> Option<Complex<f64>> {
+    if contains_variable(expr) {
+        return None;
+    }
+    let zero = Complex::new(0.0, 0.0);
+    Some(compile::<Complex<f64>>(expr)(zero, zero))
+}
+
+fn contains_variable(expr: &Expr) -> bool {
+    match expr {
+        Expr::Z | Expr::C => true,
+        Expr::Constant(_) => false,
+        Expr::Neg(a) | Expr::Call(_, a) => contains_variable(a),
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Pow(a, b) => {
+            contains_variable(a) || contains_variable(b)
+        }
+    }
+}
+
+/// 表达式作为 `z` 的多项式的次数，无法确定时返回 `None`
+fn degree(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Z => Some(1.0),
+        Expr::C | Expr::Constant(_) => Some(0.0),
+        Expr::Neg(a)
+        | Expr::Call(Function::Conj | Function::Abs | Function::Re | Function::Im, a) => degree(a),
+        Expr::Add(a, b) | Expr::Sub(a, b) => Some(degree(a)?.max(degree(b)?)),
+        Expr::Mul(a, b) => Some(degree(a)? + degree(b)?),
+        Expr::Div(a, b) => Some(degree(a)? - degree(b)?),
+        Expr::Pow(a, k) => {
+            let k = constant(k).filter(|k| k.im == 0.0)?;
+            Some(degree(a)? * k.re)
+        }
+        Expr::Call(..) => None,
+    }
+}
+
+/// 解析并编译好的迭代公式 `z = f(z, c)`
+pub struct Formula {
+    source: String,
+    degree: f64,
+    step: Compiled<Complex<f64>>,
+    step_dual: Compiled<Dual>,
+}
+
+impl Formula {
+    /// 公式的原文
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 公式作为 `z` 的多项式的次数，用于连续着色的修正项
+    ///
+    /// 无法确定或不大于 1 时按 2 处理。
+    pub fn degree(&self) -> f64 {
+        self.degree
+    }
+
+    /// 对 `z` 做一次迭代
+    pub fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        (self.step)(z, c)
+    }
+
+    /// 对 `z` 做一次迭代，同时把 `z` 对 `c` 的导数 `dz` 推进一步
+    pub fn step_with_derivative(
+        &self,
+        z: Complex<f64>,
+        dz: Complex<f64>,
+        c: Complex<f64>,
+    ) -> (Complex<f64>, Complex<f64>) {
+        let z = Dual {
+            value: z,
+            derivative: dz,
+        };
+        let c = Dual {
+            value: c,
+            derivative: Complex::new(1.0, 0.0),
+        };
+        let next = (self.step_dual)(z, c);
+        (next.value, next.derivative)
+    }
+}
+
+impl PartialEq for Formula {
+    fn eq(&self, other: &Formula) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Debug for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Formula").field(&self.source).finish()
+    }
+}
+
+/// 把字符串 `s` 解析并编译为迭代公式，出错时返回说明错误的消息
+pub fn parse_formula(s: &str) -> Result<Formula, String> {
+    let expr = parse_expr(s)?;
+    if !contains_variable(&expr) {
+        return Err("formula must use z or c".to_string());
+    }
+    Ok(Formula {
+        source: s.trim().to_string(),
+        degree: degree(&expr).filter(|&d| d > 1.0).unwrap_or(2.0),
+        step: compile(&expr),
+        step_dual: compile(&expr),
+    })
+}
+
+#[test]
+fn test_formula() {
+    let formula = parse_formula("z^2 + c*z + c").unwrap();
+    let (z, c) = (Complex::new(0.5, -1.0), Complex::new(-0.25, 0.75));
+    assert_eq!(formula.step(z, c), z * z + c * z + c);
+    assert_eq!(formula.degree(), 2.0);
+
+    // 导数：d/dc (z^2 + cz + c) = (2z + c) dz + z + 1
+    let dz = Complex::new(2.0, 1.0);
+    let (next, derivative) = formula.step_with_derivative(z, dz, c);
+    assert_eq!(next, formula.step(z, c));
+    assert!((derivative - ((z * 2.0 + c) * dz + z + 1.0)).norm() < 1e-12);
+
+    let formula = parse_formula("sin(z)/c + i").unwrap();
+    assert!((formula.step(z, c) - (z.sin() / c + Complex::i())).norm() < 1e-12);
+    assert_eq!(formula.degree(), 2.0);
+    assert_eq!(parse_formula("z^3.5 - c").unwrap().degree(), 3.5);
+    assert!(parse_formula("2 + i").is_err());
+    assert!(parse_formula("z^").is_err());
+}
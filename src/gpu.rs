@@ -0,0 +1,119 @@
+//! OpenCL 和 CUDA 后端共用的部分
+//!
+//! 两个后端的内核主体相同，都在 `gpu_escape.h` 中，各自的源码只定义数值类型、算术和
+//! 内核的限定符，再与它拼接起来编译。主机端传给内核的分形参数和读回之后的编码也都在
+//! 这里：内核只写出逃逸时的迭代次数和 `z`，连续着色的修正项在主机上计算。
+
+use crate::real::{widen, Real};
+use crate::{encode_escape, smooth_value, Coloring, Fractal, SMOOTH_BAILOUT};
+use num::Complex;
+
+/// 内核中 `fractal` 参数的取值，与 `gpu_escape.h` 中的宏一致
+const FRACTAL_MANDELBROT: i32 = 0;
+const FRACTAL_JULIA: i32 = 1;
+const FRACTAL_BURNING_SHIP: i32 = 2;
+const FRACTAL_TRICORN: i32 = 3;
+const FRACTAL_MULTIBROT: i32 = 4;
+
+/// GPU 设备能否渲染分形 `fractal` 的 `coloring` 着色
+///
+/// 内核只实现了形如 `z^d + c`、次数为整数的内置迭代公式，以及只需要逃逸次数和逃逸时的
+/// `z` 的着色方式，与深度缩放的渲染方式相同。
+pub fn supports(fractal: Fractal, coloring: Coloring) -> bool {
+    fractal.supports_deep_zoom() && coloring.supports_deep_zoom()
+}
+
+#[test]
+fn test_supports() {
+    assert!(supports(Fractal::Mandelbrot, Coloring::Smooth));
+    assert!(supports(
+        Fractal::Julia(Complex::new(-0.8, 0.156)),
+        Coloring::Smooth
+    ));
+    assert!(supports(Fractal::Multibrot(3.0), Coloring::EscapeTime));
+    assert!(!supports(Fractal::Multibrot(2.5), Coloring::EscapeTime));
+    assert!(!supports(Fractal::Lambda(None), Coloring::EscapeTime));
+    assert!(!supports(Fractal::Mandelbrot, Coloring::Distance));
+}
+
+/// 内核的 `fractal`、`power`、`julia_re`、`julia_im` 和 `bailout` 参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KernelFractal {
+    pub kind: i32,
+    pub power: i32,
+    pub julia: Complex<f64>,
+    /// 逃逸半径的平方
+    pub bailout: f64,
+}
+
+impl KernelFractal {
+    /// 以 `coloring` 着色渲染 `fractal` 时的参数，两者必须满足 `supports`
+    pub fn new(fractal: Fractal, coloring: Coloring) -> KernelFractal {
+        let (kind, power) = match fractal {
+            Fractal::Mandelbrot => (FRACTAL_MANDELBROT, 2),
+            Fractal::Julia(_) => (FRACTAL_JULIA, 2),
+            Fractal::BurningShip => (FRACTAL_BURNING_SHIP, 2),
+            Fractal::Tricorn => (FRACTAL_TRICORN, 2),
+            Fractal::Multibrot(power) => (FRACTAL_MULTIBROT, power as i32),
+            _ => unreachable!("{:?} is not supported on GPU devices", fractal),
+        };
+        let julia = match fractal {
+            Fractal::Julia(c) => c,
+            _ => Complex::new(0.0, 0.0),
+        };
+        let bailout = match coloring {
+            Coloring::Smooth => SMOOTH_BAILOUT,
+            _ => 4.0,
+        };
+        KernelFractal {
+            kind,
+            power,
+            julia,
+            bailout,
+        }
+    }
+}
+
+/// 把内核读回的逃逸次数 `counts` 和逃逸时的 `z`（`final_z` 中实部、虚部交替排列）编码为
+/// 逃逸值，次数达到 `limit` 的是内部像素
+pub fn encode<T: Real>(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    counts: &[u32],
+    final_z: &[T],
+) -> Vec<u32> {
+    counts
+        .iter()
+        .zip(final_z.chunks(2))
+        .map(|(&count, z)| {
+            let count = count as usize;
+            encode_escape((count < limit).then(|| match coloring {
+                Coloring::Smooth => {
+                    smooth_value(count, widen(Complex::new(z[0], z[1])), fractal.degree())
+                }
+                _ => count as f64,
+            }))
+        })
+        .collect()
+}
+
+#[test]
+fn test_encode() {
+    use crate::INTERIOR;
+
+    let counts = [3, 10];
+    let final_z = [10.0f32, 0.0, 0.5, 0.5];
+    let values = encode(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        10,
+        &counts,
+        &final_z,
+    );
+    assert_eq!(values, [encode_escape(Some(3.0)), INTERIOR]);
+    let smooth = encode(Fractal::Mandelbrot, Coloring::Smooth, 10, &counts, &final_z);
+    let expected = smooth_value(3, Complex::new(10.0, 0.0), 2.0);
+    assert_eq!(smooth[0], encode_escape(Some(expected)));
+    assert_eq!(smooth[1], INTERIOR);
+}
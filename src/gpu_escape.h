@@ -0,0 +1,100 @@
+// 逃逸时间内核的主体，由 gpu.rs 嵌入，拼接在 opencl.cl 或 cuda.cu 之后编译
+//
+// 后端的源码要先定义：
+//   - 迭代的数值类型 real、写出和逃逸判定用的类型 output，以及 uint
+//   - 算术 add、sub、mul、absolute 和两种类型之间的转换 from_output、to_output
+//   - 限定符 KERNEL（内核入口）、GLOBAL（设备内存中的指针）
+//   - 当前像素在分块中的列号和行号 COLUMN、ROW
+//
+// 每个工作项（线程）计算分块中的一个像素，写出逃逸时的迭代次数（未逃逸为 limit）和
+// 逃逸时的 z，连续着色由主机端计算。
+
+// 与 gpu.rs 中的 FRACTAL_* 常数一致
+#define MANDELBROT 0
+#define JULIA 1
+#define BURNING_SHIP 2
+#define TRICORN 3
+#define MULTIBROT 4
+
+KERNEL void escape(
+    GLOBAL uint *counts,
+    GLOBAL output *final_z,
+    const uint x,
+    const uint y,
+    const uint width,
+    const uint height,
+    const real origin_re,
+    const real origin_im,
+    const real right_re,
+    const real right_im,
+    const real down_re,
+    const real down_im,
+    const int fractal,
+    const int power,
+    const real julia_re,
+    const real julia_im,
+    const uint limit,
+    const output bailout)
+{
+    const uint column = COLUMN;
+    const uint row = ROW;
+    if (column >= width || row >= height) {
+        return;
+    }
+    const uint index = row * width + column;
+
+    // 与 PixelTransform::point 相同：origin + right * 列 + down * 行
+    const real px = from_output((output)(x + column));
+    const real py = from_output((output)(y + row));
+    const real re = add(origin_re, add(mul(right_re, px), mul(down_re, py)));
+    const real im = add(origin_im, add(mul(right_im, px), mul(down_im, py)));
+
+    real zr, zi, cr, ci;
+    if (fractal == JULIA) {
+        zr = re;
+        zi = im;
+        cr = julia_re;
+        ci = julia_im;
+    } else {
+        zr = from_output(0);
+        zi = from_output(0);
+        cr = re;
+        ci = im;
+    }
+
+    uint i = 0;
+    for (; i < limit; i++) {
+        // 逃逸判定不需要完整的精度
+        const output r = to_output(zr);
+        const output s = to_output(zi);
+        if (r * r + s * s > bailout) {
+            break;
+        }
+        if (fractal == BURNING_SHIP) {
+            zr = absolute(zr);
+            zi = absolute(zi);
+        } else if (fractal == TRICORN) {
+            zi = -zi;
+        }
+        if (fractal == MULTIBROT) {
+            real pr = zr;
+            real pi = zi;
+            for (int k = 1; k < power; k++) {
+                const real t = sub(mul(pr, zr), mul(pi, zi));
+                pi = add(mul(pr, zi), mul(pi, zr));
+                pr = t;
+            }
+            zr = add(pr, cr);
+            zi = add(pi, ci);
+        } else {
+            const real t = add(sub(mul(zr, zr), mul(zi, zi)), cr);
+            const real u = mul(zr, zi);
+            zi = add(add(u, u), ci);
+            zr = t;
+        }
+    }
+
+    counts[index] = i;
+    final_z[2 * index] = to_output(zr);
+    final_z[2 * index + 1] = to_output(zi);
+}
@@ -0,0 +1,190 @@
+//! 混合分形：按固定的模式逐步轮换几种迭代公式
+//!
+//! `--hybrid "mandelbrot*2,burning-ship"` 表示第 0、1 步按曼德博集迭代，第 2 步按燃烧船
+//! 迭代，之后从头重复。模式中的每一项是 `mandelbrot`、`burning-ship` 或 `tricorn`，可以用
+//! `^d` 给出曼德博集的次数、用 `*n` 把同一项重复 `n` 次。已有的几种公式就这样组合出
+//! 形态各异的新分形。
+//!
+//! 每一步只换一个公式，轨道和导数都仍然逐步迭代，因此所有着色方式都适用；但迭代不再是
+//! 同一个映射的重复，依赖吸引环的内部着色不适用。
+
+use crate::{parse_power, Fractal};
+use std::fmt;
+
+/// 解析好的轮换模式
+pub struct Hybrid {
+    source: String,
+    steps: Vec<Fractal>,
+}
+
+impl Hybrid {
+    /// 模式的原文
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 展开重复之后的模式，每一项是一步迭代使用的分形
+    pub fn steps(&self) -> &[Fractal] {
+        &self.steps
+    }
+
+    /// 第 `i` 步（从 0 开始）使用的分形
+    pub fn at(&self, i: usize) -> Fractal {
+        self.steps[i % self.steps.len()]
+    }
+
+    /// 平均每一步的次数，即各步次数的几何平均，用于连续着色的修正项
+    pub fn degree(&self) -> f64 {
+        let log: f64 = self.steps.iter().map(|step| step.degree().ln()).sum();
+        (log / self.steps.len() as f64).exp()
+    }
+}
+
+impl PartialEq for Hybrid {
+    fn eq(&self, other: &Hybrid) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Debug for Hybrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Hybrid").field(&self.source).finish()
+    }
+}
+
+/// 把模式中的一项 `item`（形如 `mandelbrot^3*2`）解析为分形和重复次数
+fn parse_item(item: &str) -> Result<(Fractal, usize), String> {
+    let (item, count) = match item.split_once('*') {
+        Some((item, count)) => match count.trim().parse::<usize>() {
+            Ok(count) if count > 0 => (item, count),
+            _ => return Err(format!("invalid repeat count `{}`", count.trim())),
+        },
+        None => (item, 1),
+    };
+    let (name, power) = match item.split_once('^') {
+        Some((name, power)) => (
+            name,
+            parse_power(power.trim()).ok_or_else(|| format!("invalid power `{}`", power.trim()))?,
+        ),
+        None => (item, 2.0),
+    };
+    let fractal = match name.trim() {
+        "mandelbrot" => Fractal::Mandelbrot,
+        "burning-ship" => Fractal::BurningShip,
+        "tricorn" => Fractal::Tricorn,
+        name => {
+            return Err(format!(
+                "unknown hybrid step `{}` (expected `mandelbrot`, `burning-ship`, or `tricorn`)",
+                name
+            ))
+        }
+    };
+    let fractal = fractal.with_power(power).ok_or_else(|| {
+        format!(
+            "powers are only supported for `mandelbrot`, not `{}`",
+            name.trim()
+        )
+    })?;
+    Ok((fractal, count))
+}
+
+/// 把逗号分隔的模式 `s` 解析为混合分形，出错时返回说明错误的消息
+pub fn parse_hybrid(s: &str) -> Result<Hybrid, String> {
+    let mut steps = Vec::new();
+    for item in s.split(',') {
+        let (fractal, count) = parse_item(item)?;
+        steps.extend(std::iter::repeat_n(fractal, count));
+    }
+    Ok(Hybrid {
+        source: s.trim().to_string(),
+        steps,
+    })
+}
+
+#[test]
+fn test_parse_hybrid() {
+    let hybrid = parse_hybrid("mandelbrot*2, burning-ship").unwrap();
+    assert_eq!(
+        hybrid.steps(),
+        [
+            Fractal::Mandelbrot,
+            Fractal::Mandelbrot,
+            Fractal::BurningShip
+        ]
+    );
+    assert_eq!(hybrid.at(4), Fractal::Mandelbrot);
+    assert_eq!(hybrid.at(5), Fractal::BurningShip);
+    assert_eq!(hybrid.degree(), 2.0);
+
+    let hybrid = parse_hybrid("mandelbrot^3,tricorn^2").unwrap();
+    assert_eq!(hybrid.steps(), [Fractal::Multibrot(3.0), Fractal::Tricorn]);
+    assert!((hybrid.degree() - 6f64.sqrt()).abs() < 1e-12);
+
+    assert!(parse_hybrid("mandelbrot,newton").is_err());
+    assert!(parse_hybrid("burning-ship^3").is_err());
+    assert!(parse_hybrid("mandelbrot*0").is_err());
+    assert!(parse_hybrid("").is_err());
+}
+
+#[test]
+fn test_hybrid() {
+    use crate::evaluator::PointEvaluator;
+    use crate::Coloring;
+    use num::Complex;
+
+    let hybrid = |pattern, c| Fractal::Hybrid {
+        hybrid: Box::leak(Box::new(parse_hybrid(pattern).unwrap())),
+        c,
+    };
+    let points = [
+        Complex::new(0.3, 0.5),
+        Complex::new(-0.12, 0.75),
+        Complex::new(-1.75, 0.02),
+        Complex::new(0.4, 0.6),
+    ];
+    // 只有一种公式的模式与该公式本身相同，包括距离估计
+    let julia = Complex::new(-0.8, 0.156);
+    for point in points {
+        let mandelbrot = hybrid("mandelbrot*3", None);
+        assert_eq!(
+            mandelbrot.escape_time(point, 500),
+            Fractal::Mandelbrot.escape_time(point, 500)
+        );
+        assert_eq!(
+            mandelbrot.distance_estimate(point, 500),
+            Fractal::Mandelbrot.distance_estimate(point, 500)
+        );
+        assert_eq!(
+            hybrid("mandelbrot", Some(julia)).distance_estimate(point, 500),
+            Fractal::Julia(julia).distance_estimate(point, 500)
+        );
+    }
+
+    // 逐步轮换公式：手工按模式迭代的结果与逐点求值、带统计的求值一致
+    let fractal = hybrid("mandelbrot*2,burning-ship", None);
+    for point in points {
+        let mut z = Complex::new(0.0, 0.0);
+        let mut expected = None;
+        for i in 0..500 {
+            if z.norm_sqr() > 4.0 {
+                expected = Some(i);
+                break;
+            }
+            z = if i % 3 == 2 {
+                Fractal::BurningShip.step(z, point)
+            } else {
+                z * z + point
+            };
+        }
+        assert_eq!(fractal.escape_time(point, 500), expected);
+        let (result, _) = fractal.evaluate(&Coloring::EscapeTime, point, 500);
+        assert_eq!(result.iterations, expected);
+    }
+    // 周期检测只比较相隔整周的两步，有界的点仍然能提前结束
+    assert_eq!(
+        fractal.escape_time(Complex::new(-0.1, 0.1), usize::MAX),
+        None
+    );
+    assert!(!fractal.supports_interior());
+    assert!(!fractal.supports_deep_zoom());
+}
@@ -0,0 +1,282 @@
+//! 四元数和 triplex（Mandelbulb）分形的二维切片
+//!
+//! 四元数曼德博集在四维的 `c` 空间中迭代 `q = q^2 + c`，Mandelbulb 在三维的 `c` 空间中
+//! 迭代 White–Nylander 的 triplex 乘方 `v = v^n + c`（通常 `n = 8`）。图像只能显示其中的
+//! 一个平面：像素对应的复数 `x + iy` 映射为 `offset + x u + y v`，`u` 和 `v` 是从 `c` 空间
+//! 的前两个坐标轴出发、按 `orientation` 中的三个角度依次在 x–z、y–z 和 z–w 平面内旋转
+//! 得到的正交单位向量。三维的 triplex 不使用 w 分量，切片的偏移和第三个角度必须为零。
+//!
+//! 给出 `julia` 时改为朱利亚集：以它为常数 `c`，从像素对应的点出发。偏移和角度是普通的
+//! 实数，在两个切片之间线性插值就能做出切片平移、转动的动画。
+
+use crate::progress::Progress;
+use crate::{encode_escape, smooth_value, PixelTransform, SMOOTH_BAILOUT};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 四维向量 `(x, y, z, w)`，四元数 `x + y i + z j + w k`
+pub type Vector = [f64; 4];
+
+/// 迭代使用的乘法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algebra {
+    /// 四元数的平方
+    Quaternion,
+    /// 给定次数的 triplex 乘方，次数为 8 时就是 Mandelbulb
+    Triplex(f64),
+}
+
+/// Mandelbulb 默认的次数
+pub const MANDELBULB_POWER: f64 = 8.0;
+
+/// 把字符串 `s` 解析为乘法：`quaternion` 或 `mandelbulb`（次数为 `MANDELBULB_POWER`）
+pub fn parse_algebra(s: &str) -> Option<Algebra> {
+    match s {
+        "quaternion" => Some(Algebra::Quaternion),
+        "mandelbulb" => Some(Algebra::Triplex(MANDELBULB_POWER)),
+        _ => None,
+    }
+}
+
+impl Algebra {
+    /// 对 `z` 做一次迭代
+    pub fn step(&self, z: Vector, c: Vector) -> Vector {
+        let [x, y, z, w] = z;
+        let power = match *self {
+            Algebra::Quaternion => [
+                x * x - y * y - z * z - w * w,
+                2.0 * x * y,
+                2.0 * x * z,
+                2.0 * x * w,
+            ],
+            Algebra::Triplex(n) => {
+                let r = (x * x + y * y + z * z).sqrt();
+                let theta = (x * x + y * y).sqrt().atan2(z) * n;
+                let phi = y.atan2(x) * n;
+                let r = r.powf(n);
+                [
+                    r * theta.sin() * phi.cos(),
+                    r * theta.sin() * phi.sin(),
+                    r * theta.cos(),
+                    0.0,
+                ]
+            }
+        };
+        [
+            power[0] + c[0],
+            power[1] + c[1],
+            power[2] + c[2],
+            power[3] + c[3],
+        ]
+    }
+
+    /// 迭代公式的次数，连续着色的修正项需要用到它
+    pub fn degree(&self) -> f64 {
+        match *self {
+            Algebra::Quaternion => 2.0,
+            Algebra::Triplex(n) => n,
+        }
+    }
+}
+
+/// `c` 空间中的一个平面
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slice {
+    /// 像素平面的原点对应的点
+    pub offset: Vector,
+    /// 依次在 x–z、y–z 和 z–w 平面内旋转的角度（度）
+    pub orientation: [f64; 3],
+}
+
+impl Slice {
+    /// 像素平面的实轴和虚轴在 `c` 空间中的方向
+    pub fn axes(&self) -> (Vector, Vector) {
+        let mut u = [1.0, 0.0, 0.0, 0.0];
+        let mut v = [0.0, 1.0, 0.0, 0.0];
+        for (&degrees, (i, j)) in self.orientation.iter().zip([(0, 2), (1, 2), (2, 3)]) {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            for axis in [&mut u, &mut v] {
+                let (a, b) = (axis[i], axis[j]);
+                axis[i] = a * cos - b * sin;
+                axis[j] = a * sin + b * cos;
+            }
+        }
+        (u, v)
+    }
+
+    /// 像素平面中的点 `point` 在 `c` 空间中的位置
+    pub fn point(&self, point: Complex<f64>) -> Vector {
+        let (u, v) = self.axes();
+        std::array::from_fn(|k| self.offset[k] + point.re * u[k] + point.im * v[k])
+    }
+
+    /// 在 `self` 和 `end` 之间按比例 `t` 线性插值
+    pub fn interpolate(&self, end: &Slice, t: f64) -> Slice {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        Slice {
+            offset: std::array::from_fn(|k| lerp(self.offset[k], end.offset[k])),
+            orientation: std::array::from_fn(|k| lerp(self.orientation[k], end.orientation[k])),
+        }
+    }
+}
+
+/// 把形如 `X,Y,Z` 或 `X,Y,Z,W` 的字符串解析为向量，省略的 w 为零
+pub fn parse_vector(s: &str) -> Option<Vector> {
+    let components = s
+        .split(',')
+        .map(|component| {
+            component
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|x| x.is_finite())
+        })
+        .collect::<Option<Vec<f64>>>()?;
+    match components[..] {
+        [x, y, z] => Some([x, y, z, 0.0]),
+        [x, y, z, w] => Some([x, y, z, w]),
+        _ => None,
+    }
+}
+
+/// 把形如 `A,B,C` 的字符串解析为切片的三个旋转角度（度）
+pub fn parse_orientation(s: &str) -> Option<[f64; 3]> {
+    let angles = s
+        .split(',')
+        .map(|angle| angle.trim().parse::<f64>().ok().filter(|a| a.is_finite()))
+        .collect::<Option<Vec<f64>>>()?;
+    angles.try_into().ok()
+}
+
+#[test]
+fn test_parse_vector_and_orientation() {
+    assert_eq!(parse_vector("1,-2,0.5"), Some([1.0, -2.0, 0.5, 0.0]));
+    assert_eq!(parse_vector("0, 0, 0, 1"), Some([0.0, 0.0, 0.0, 1.0]));
+    assert_eq!(parse_vector("1,2"), None);
+    assert_eq!(parse_vector("1,2,3,4,5"), None);
+    assert_eq!(parse_vector("1,2,NaN"), None);
+    assert_eq!(parse_orientation("90,0,-45"), Some([90.0, 0.0, -45.0]));
+    assert_eq!(parse_orientation("90,0"), None);
+}
+
+/// 四元数或 triplex 分形的一个二维切片
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hypercomplex {
+    pub algebra: Algebra,
+    pub slice: Slice,
+    /// 给出时是以它为常数的朱利亚集
+    pub julia: Option<Vector>,
+}
+
+impl Hypercomplex {
+    /// 像素平面中的点 `point` 的逃逸值，最多迭代 `limit` 次，不逃逸时返回 `None`
+    ///
+    /// `smooth` 为真时用更大的逃逸半径求出连续逃逸时间，否则是整数逃逸次数。
+    pub fn escape_value(&self, point: Complex<f64>, limit: usize, smooth: bool) -> Option<f64> {
+        let point = self.slice.point(point);
+        let (mut z, c) = match self.julia {
+            Some(c) => (point, c),
+            None => ([0.0; 4], point),
+        };
+        let bailout = if smooth { SMOOTH_BAILOUT } else { 4.0 };
+        for i in 0..limit {
+            let norm_sqr: f64 = z.iter().map(|x| x * x).sum();
+            if norm_sqr > bailout {
+                if !smooth {
+                    return Some(i as f64);
+                }
+                let modulus = Complex::new(norm_sqr.sqrt(), 0.0);
+                return Some(smooth_value(i, modulus, self.algebra.degree()));
+            }
+            z = self.algebra.step(z, c);
+        }
+        None
+    }
+}
+
+#[test]
+fn test_escape_value() {
+    use crate::Fractal;
+
+    let flat = Slice {
+        offset: [0.0; 4],
+        orientation: [0.0; 3],
+    };
+    let quaternion = Hypercomplex {
+        algebra: Algebra::Quaternion,
+        slice: flat,
+        julia: None,
+    };
+    // 四元数曼德博集在 1、i 平面上就是曼德博集；它绕实轴旋转对称，1、j 平面上也一样
+    let turned = Hypercomplex {
+        slice: Slice {
+            orientation: [0.0, 90.0, 0.0],
+            ..flat
+        },
+        ..quaternion
+    };
+    let (u, v) = turned.slice.axes();
+    let close = |a: Vector, b: Vector| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12);
+    assert!(close(u, [1.0, 0.0, 0.0, 0.0]) && close(v, [0.0, 0.0, 1.0, 0.0]));
+    assert!(close(
+        turned.slice.point(Complex::new(2.0, 3.0)),
+        [2.0, 0.0, 3.0, 0.0]
+    ));
+    for point in [
+        Complex::new(0.3, 0.5),
+        Complex::new(-0.12, 0.75),
+        Complex::new(-1.75, 0.02),
+    ] {
+        let expected = Fractal::Mandelbrot
+            .escape_time(point, 200)
+            .map(|i| i as f64);
+        assert_eq!(quaternion.escape_value(point, 200, false), expected);
+        assert_eq!(turned.escape_value(point, 200, false), expected);
+        assert_eq!(
+            quaternion.escape_value(point, 200, true),
+            Fractal::Mandelbrot.smooth_escape_time(point, 200)
+        );
+    }
+
+    // triplex 乘方把模长变为 n 次方；原点属于 Mandelbulb，远处的点逃逸
+    let bulb = Hypercomplex {
+        algebra: Algebra::Triplex(MANDELBULB_POWER),
+        slice: flat,
+        julia: None,
+    };
+    assert_eq!(bulb.escape_value(Complex::new(0.0, 0.0), 100, false), None);
+    assert!(bulb
+        .escape_value(Complex::new(1.5, 0.0), 100, false)
+        .is_some());
+    let z = [0.3, -0.4, 0.5, 0.0];
+    let power = Algebra::Triplex(MANDELBULB_POWER).step(z, [0.0; 4]);
+    let modulus = |v: Vector| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    assert!((modulus(power) - modulus(z).powi(8)).abs() < 1e-12);
+}
+
+/// 并行渲染按 `transform` 映射到像素平面的 `bounds` 大小的切片，返回逐行排列的迭代缓冲区
+///
+/// 每完成一行就在 `progress` 上记录一次。
+pub fn render(
+    hypercomplex: &Hypercomplex,
+    limit: usize,
+    smooth: bool,
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+    progress: &Progress,
+) -> Vec<u32> {
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    progress.start(bounds.1, "rows");
+    iterations
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, value) in band.iter_mut().enumerate() {
+                let point = transform.point((column, row));
+                *value = encode_escape(hypercomplex.escape_value(point, limit, smooth));
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    iterations
+}
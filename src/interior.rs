@@ -0,0 +1,337 @@
+//! 集合内部的着色
+//!
+//! 达到迭代次数限制仍未逃逸的点通常已经落入一个吸引环。除了涂成调色板的内部颜色，
+//! 还可以按这个环的周期、环的乘子（`p` 次迭代的复合映射在环上的导数，吸引环的模小于
+//! 1，越靠近环所在区域的中心越小）或最后的 `z` 的辐角着色。内部的值与逃逸值一样换算到
+//! `[0, limit]` 区间内，共用同一个调色板。
+//!
+//! 原子域着色（`Coloring::AtomDomain`）同样按周期着色，但覆盖整个复平面：每个点按轨道
+//! 最接近原点的步数着色，双曲分量的周期与所在原子域的步数一致，得到经典的周期图。
+//!
+//! 内部着色总是用 `f64` 计算：外部的点按原来的着色方式求值，不逃逸的点再从头迭代一次，
+//! 迭代时用 Brent 的方法检测到环就提前结束（参见 `iterate`）。
+
+use crate::colorizer::{Colorizer, OrbitStats, PointResult};
+use crate::evaluator::PointEvaluator;
+use crate::real::{widen, Real};
+use crate::{iterate, Coloring};
+use num::Complex;
+
+/// 寻找吸引环时最多尝试的周期
+pub const MAX_PERIOD: usize = 1024;
+
+/// 回到起点的距离平方小于这个值时认为找到了环
+const CYCLE_TOLERANCE: f64 = 1e-16;
+
+/// 集合内部的着色方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interior {
+    /// 涂成调色板的内部颜色
+    Flat,
+    /// 按吸引环的周期着色
+    Period,
+    /// 按吸引环乘子的模着色
+    Multiplier,
+    /// 按最后的 `z` 的辐角着色
+    Angle,
+}
+
+/// 把字符串 `s` 解析为内部着色方式：`flat`、`period`、`multiplier` 或 `angle`
+pub fn parse_interior(s: &str) -> Option<Interior> {
+    match s {
+        "flat" => Some(Interior::Flat),
+        "period" => Some(Interior::Period),
+        "multiplier" => Some(Interior::Multiplier),
+        "angle" => Some(Interior::Angle),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_interior() {
+    assert_eq!(parse_interior("flat"), Some(Interior::Flat));
+    assert_eq!(parse_interior("period"), Some(Interior::Period));
+    assert_eq!(parse_interior("multiplier"), Some(Interior::Multiplier));
+    assert_eq!(parse_interior("angle"), Some(Interior::Angle));
+    assert_eq!(parse_interior("inside"), None);
+}
+
+/// 一个吸引环：周期和乘子
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cycle {
+    pub period: usize,
+    pub multiplier: Complex<f64>,
+}
+
+/// 从已经落在环上（或足够接近环）的 `z` 出发寻找分形 `fractal` 以 `c` 为常数的迭代的
+/// 吸引环，周期超过 `MAX_PERIOD` 或者 `z` 还没有收敛时返回 `None`
+///
+/// 乘子是沿环对 `z` 的导数之积。`step_with_derivative` 给出的导数关于 `dz` 是线性的，
+/// 再加上对 `c` 的偏导，因此分别以 `dz` 和零迭代一步，两者之差就是对 `z` 的导数作用在
+/// `dz` 上的结果。
+pub fn find_cycle<F: PointEvaluator>(
+    fractal: &F,
+    z: Complex<f64>,
+    c: Complex<f64>,
+) -> Option<Cycle> {
+    let zero = Complex::new(0.0, 0.0);
+    let (mut w, mut multiplier) = (z, Complex::new(1.0, 0.0));
+    for period in 1..=MAX_PERIOD {
+        let (next, derivative) = fractal.step_with_derivative(w, multiplier, c);
+        let (_, shift) = fractal.step_with_derivative(w, zero, c);
+        (w, multiplier) = (next, derivative - shift);
+        if (w - z).norm_sqr() < CYCLE_TOLERANCE {
+            return Some(Cycle { period, multiplier });
+        }
+    }
+    None
+}
+
+/// 把周期 `period` 换算为 `[0, limit]` 区间内的值
+///
+/// 周期 1、2、3…… 依次映射到 0、1/2、2/3……，小周期之间区别最明显；内部的周期着色和
+/// 原子域着色使用同样的换算，同一周期的区域颜色相同。
+pub fn period_value(period: usize, limit: usize) -> f64 {
+    (1.0 - 1.0 / period.max(1) as f64) * limit as f64
+}
+
+/// 原子域：轨道中模最小的点出现在第几步
+///
+/// 原子域把复平面按这个步数划分为区域，周期为 `p` 的双曲分量都落在步数为 `p` 的
+/// 区域中，于是集合外部也显示出各个分量的周期结构。收敛到吸引环的轨道每隔一个周期
+/// 回到原点附近且越来越近，最小值总出现在周期的某个倍数处，因此不逃逸的点改用
+/// `find_cycle` 找到的周期。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtomDomain {
+    /// 已经记录的步数
+    steps: usize,
+    /// 到目前为止模最小的点所在的步数
+    period: usize,
+    /// 到目前为止最小的模的平方
+    modulus: f64,
+    /// 迭代公式中的常数
+    c: Complex<f64>,
+}
+
+impl Default for AtomDomain {
+    fn default() -> AtomDomain {
+        AtomDomain {
+            steps: 0,
+            period: 0,
+            modulus: f64::INFINITY,
+            c: Complex::new(0.0, 0.0),
+        }
+    }
+}
+
+impl AtomDomain {
+    /// 记录一步迭代得到的 `next`，`c` 是迭代公式中的常数
+    pub fn observe(&mut self, next: Complex<f64>, c: Complex<f64>) {
+        self.steps += 1;
+        self.c = c;
+        let modulus = next.norm_sqr();
+        if modulus < self.modulus {
+            (self.modulus, self.period) = (modulus, self.steps);
+        }
+    }
+
+    /// 分形 `fractal` 中迭代结果为 `result` 的点的周期：逃逸的点是原子域的步数，不逃逸的
+    /// 点是吸引环的周期（找不到环时同样退回到原子域的步数）；一步都没有迭代时为 0
+    pub fn period<F: PointEvaluator>(&self, fractal: &F, result: &PointResult) -> usize {
+        match result.iterations {
+            None if self.steps > 0 => find_cycle(fractal, result.final_z, self.c)
+                .map_or(self.period, |cycle| cycle.period),
+            _ => self.period,
+        }
+    }
+}
+
+impl Interior {
+    /// 不逃逸的点 `point` 在分形 `fractal` 中按该方式得到的内部值，在 `[0, limit]` 区间内；
+    /// 涂成内部颜色或找不到吸引环（包括迭代 `limit` 次还没有收敛）时返回 `None`
+    pub fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        point: Complex<f64>,
+        limit: usize,
+    ) -> Option<f64> {
+        if *self == Interior::Flat {
+            return None;
+        }
+        let (z, c) = fractal.orbit_start(point);
+        let (iterations, z) = iterate(z, c, limit, 4.0, 1, |_, z, _, c| fractal.step(z, c));
+        if iterations.is_some() {
+            return None;
+        }
+        let fraction = match self {
+            Interior::Flat => unreachable!("handled above"),
+            Interior::Period => {
+                return Some(period_value(find_cycle(fractal, z, c)?.period, limit))
+            }
+            Interior::Multiplier => find_cycle(fractal, z, c)?.multiplier.norm().min(1.0),
+            Interior::Angle => z.arg() / std::f64::consts::TAU + 0.5,
+        };
+        Some(fraction * limit as f64)
+    }
+}
+
+/// 外部按 `exterior` 着色、内部按 `interior` 着色的着色方式
+///
+/// 轨道陷阱本身就为内部着色，不与内部着色方式组合。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InteriorColoring {
+    pub exterior: Coloring,
+    pub interior: Interior,
+}
+
+impl Colorizer for InteriorColoring {
+    type Stats = OrbitStats;
+
+    fn bailout(&self) -> f64 {
+        self.exterior.bailout()
+    }
+
+    fn needs_derivative(&self) -> bool {
+        self.exterior.needs_derivative()
+    }
+
+    /// 共轭的 `z` 辐角相反，按辐角着色时不对称
+    fn is_symmetric(&self) -> bool {
+        self.exterior.is_symmetric() && self.interior != Interior::Angle
+    }
+
+    fn observe(
+        &self,
+        stats: &mut OrbitStats,
+        z: Complex<f64>,
+        next: Complex<f64>,
+        c: Complex<f64>,
+    ) {
+        self.exterior.observe(stats, z, next, c)
+    }
+
+    fn value<F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        result: &PointResult,
+        stats: &OrbitStats,
+        limit: usize,
+        spacing: f64,
+    ) -> Option<f64> {
+        self.exterior.value(fractal, result, stats, limit, spacing)
+    }
+
+    fn escape_value<T: Real, F: PointEvaluator>(
+        &self,
+        fractal: &F,
+        point: Complex<T>,
+        limit: usize,
+        spacing: T,
+    ) -> Option<f64> {
+        self.exterior
+            .escape_value(fractal, point, limit, spacing)
+            .or_else(|| self.interior.value(fractal, widen(point), limit))
+    }
+}
+
+#[test]
+fn test_find_cycle() {
+    use crate::Fractal;
+
+    // 主心形中的点收敛到不动点 z* = (1 - sqrt(1 - 4c)) / 2，乘子为 2 z*
+    let c = Complex::new(-0.1, 0.1);
+    let fixed = (Complex::new(1.0, 0.0) - (Complex::new(1.0, 0.0) - c * 4.0).sqrt()) / 2.0;
+    let cycle = find_cycle(&Fractal::Mandelbrot, fixed, c).unwrap();
+    assert_eq!(cycle.period, 1);
+    assert!((cycle.multiplier - fixed * 2.0).norm() < 1e-12);
+
+    // 周期 2 圆盘中的乘子为 4 (c + 1)，周期 3 的鸟嘴中找到周期 3
+    let value = |interior: Interior, re, im| {
+        interior.value(&Fractal::Mandelbrot, Complex::new(re, im), 100_000)
+    };
+    assert_eq!(value(Interior::Period, -0.1, 0.1), Some(0.0));
+    assert_eq!(value(Interior::Period, -1.0, 0.1), Some(50_000.0));
+    let expected = 4.0 * Complex::new(0.0, 0.1).norm() * 100_000.0;
+    assert!((value(Interior::Multiplier, -1.0, 0.1).unwrap() - expected).abs() < 1e-3);
+    let third = value(Interior::Period, -0.12, 0.75).unwrap();
+    assert!((third - 2.0 / 3.0 * 100_000.0).abs() < 1e-6);
+    let angle = value(Interior::Angle, -0.12, 0.75).unwrap();
+    assert!((0.0..=100_000.0).contains(&angle));
+
+    // 逃逸的点和 flat 都没有内部值
+    assert_eq!(value(Interior::Period, 0.5, 0.5), None);
+    assert_eq!(value(Interior::Flat, -0.1, 0.1), None);
+}
+
+#[test]
+fn test_atom_domain() {
+    use crate::colorizer::Colorizer;
+    use crate::Fractal;
+
+    let period = |re, im| {
+        let coloring = Coloring::AtomDomain;
+        let (result, stats) = Fractal::Mandelbrot.evaluate(&coloring, Complex::new(re, im), 1000);
+        stats.atom.period(&Fractal::Mandelbrot, &result)
+    };
+    // 主心形、周期 2 圆盘和周期 3 分量的中心
+    assert_eq!(period(0.0, 0.0), 1);
+    assert_eq!(period(-1.0, 0.0), 2);
+    assert_eq!(period(-0.1226, 0.7449), 3);
+    // 集合外部的点也有原子域：周期 3 分量附近的外部点仍在周期 3 的原子域中
+    assert_eq!(period(-0.1226, 0.7449 + 0.05), 3);
+    assert!(period(0.5, 0.5) >= 1);
+    let value = |re, im| {
+        Fractal::Mandelbrot.escape_value(Coloring::AtomDomain, Complex::new(re, im), 1000, 0.01)
+    };
+    assert_eq!(value(-1.0, 0.0), Some(500.0));
+    assert!((0.0..=1000.0).contains(&value(0.5, 0.5).unwrap()));
+    assert!(Coloring::AtomDomain.colors_interior());
+}
+
+#[test]
+fn test_interior_coloring() {
+    use crate::{encode_escape, render, Fractal, PixelTransform, INTERIOR};
+
+    let bounds = (30, 20);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 1.0, im: -1.0 },
+    );
+    let render_with = |interior| {
+        let mut iterations = vec![0; bounds.0 * bounds.1];
+        let colorizer = InteriorColoring {
+            exterior: Coloring::Smooth,
+            interior,
+        };
+        render(
+            Fractal::Mandelbrot,
+            colorizer,
+            500,
+            &mut iterations,
+            bounds,
+            transform,
+        );
+        iterations
+    };
+    let flat = render_with(Interior::Flat);
+    let period = render_with(Interior::Period);
+    // 外部的像素不变，内部的像素大多有了颜色；靠近边界、迭代次数限制内还没有收敛到环的
+    // 像素仍是内部颜色
+    let mut colored = 0;
+    for (&flat, &period) in flat.iter().zip(&period) {
+        if flat == INTERIOR {
+            colored += usize::from(period != INTERIOR);
+        } else {
+            assert_eq!(flat, period);
+        }
+    }
+    let interior = flat.iter().filter(|&&value| value == INTERIOR).count();
+    assert!(
+        interior > 0 && colored * 4 > interior * 3,
+        "{} of {}",
+        colored,
+        interior
+    );
+    assert!(period.contains(&encode_escape(Some(0.0))));
+}
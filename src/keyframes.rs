@@ -0,0 +1,444 @@
+//! 关键帧动画文件
+//!
+//! `animate --keyframes FILE` 从 TOML 或 JSON 文件中读取一组关键帧，每个关键帧给出某一帧
+//! 的视图中心、缩放倍数、旋转角度、最大迭代次数、调色板偏移和朱利亚集常数，中间的帧
+//! 在相邻的两个关键帧之间插值：
+//!
+//! ```toml
+//! [[keyframes]]
+//! frame = 0
+//! center = "-0.75,0.1"
+//! easing = "ease-in-out"
+//!
+//! [[keyframes]]
+//! frame = 120
+//! center = "-0.7436,0.1318"
+//! zoom = 5000
+//! max_iter = 2000
+//! palette_offset = 0.5
+//! ```
+//!
+//! JSON 文件的结构相同：`{"keyframes": [{"frame": 0, ...}, ...]}`。关键帧省略的值沿用上一个
+//! 关键帧，第一个关键帧省略的值取命令行上的选项。`easing` 是从这个关键帧到下一个关键帧
+//! 这一段的缓动曲线。缩放倍数按对数插值，缩放的速度看起来是均匀的；其他值线性插值。
+
+use crate::parse_complex;
+use num::Complex;
+use toml::{Table, Value};
+
+/// 关键帧之间的缓动曲线
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    /// 匀速
+    #[default]
+    Linear,
+    /// 由慢到快（三次曲线）
+    EaseIn,
+    /// 由快到慢（三次曲线）
+    EaseOut,
+    /// 两端慢、中间快（三次曲线）
+    EaseInOut,
+    /// 保持不变，到下一个关键帧时突变
+    Hold,
+}
+
+impl Easing {
+    /// 把一段中的进度 `t`（0 到 1）映射为插值的比例
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+            Easing::Hold => 0.0,
+        }
+    }
+}
+
+/// 解析缓动曲线的名称：linear、ease-in、ease-out、ease-in-out 或 hold
+pub fn parse_easing(s: &str) -> Option<Easing> {
+    match s {
+        "linear" => Some(Easing::Linear),
+        "ease-in" => Some(Easing::EaseIn),
+        "ease-out" => Some(Easing::EaseOut),
+        "ease-in-out" => Some(Easing::EaseInOut),
+        "hold" => Some(Easing::Hold),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_easing() {
+    for easing in [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ] {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+    }
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    assert!(Easing::EaseIn.apply(0.25) < 0.25);
+    assert!(Easing::EaseOut.apply(0.25) > 0.25);
+    assert_eq!(Easing::Hold.apply(0.9), 0.0);
+    assert_eq!(parse_easing("ease-in-out"), Some(Easing::EaseInOut));
+    assert_eq!(parse_easing("bounce"), None);
+}
+
+/// 某一帧的全部动画参数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// 视图中心，保留原始的字符串，深度缩放时可以按任意精度解析
+    pub center: String,
+    pub zoom: f64,
+    /// 旋转角度（度）
+    pub rotate: f64,
+    pub max_iter: usize,
+    pub palette_offset: f64,
+    /// 朱利亚集的常数 c，为 `None` 时渲染命令行上给出的分形
+    pub c: Option<Complex<f64>>,
+}
+
+impl Scene {
+    /// 在 `self` 和 `next` 之间按比例 `s` 插值
+    fn interpolate(&self, next: &Scene, s: f64) -> Scene {
+        if s == 0.0 {
+            return self.clone();
+        }
+        let lerp = |a: f64, b: f64| a + (b - a) * s;
+        // 两端的中心相同时保留原始字符串，不损失深度缩放所需的精度
+        let center = if self.center == next.center {
+            self.center.clone()
+        } else {
+            let (a, b) = (
+                parse_complex(&self.center).expect("centers are validated"),
+                parse_complex(&next.center).expect("centers are validated"),
+            );
+            format!("{},{}", lerp(a.re, b.re), lerp(a.im, b.im))
+        };
+        Scene {
+            center,
+            zoom: lerp(self.zoom.ln(), next.zoom.ln()).exp(),
+            rotate: lerp(self.rotate, next.rotate),
+            max_iter: lerp(self.max_iter as f64, next.max_iter as f64).round() as usize,
+            palette_offset: lerp(self.palette_offset, next.palette_offset),
+            c: match (self.c, next.c) {
+                (Some(a), Some(b)) => Some(a + (b - a) * s),
+                (c, _) => c,
+            },
+        }
+    }
+}
+
+/// 文件中的一个关键帧，省略的值为 `None`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub center: Option<String>,
+    pub zoom: Option<f64>,
+    pub rotate: Option<f64>,
+    pub max_iter: Option<usize>,
+    pub palette_offset: Option<f64>,
+    pub c: Option<Complex<f64>>,
+    /// 从这个关键帧到下一个关键帧的缓动曲线
+    pub easing: Easing,
+}
+
+/// 把关键帧表 `table` 中的各项读入 `Keyframe`
+fn parse_keyframe(table: &Table) -> Result<Keyframe, String> {
+    let number = |key: &str, value: &Value| match value {
+        Value::Integer(n) => Ok(*n as f64),
+        Value::Float(x) if x.is_finite() => Ok(*x),
+        _ => Err(format!("`{}` must be a number", key)),
+    };
+    let complex = |key: &str, value: &Value| {
+        value
+            .as_str()
+            .and_then(parse_complex)
+            .ok_or_else(|| format!("`{}` must be a string such as \"-0.5,0.25\"", key))
+    };
+    let mut frame = None;
+    let mut keyframe = Keyframe::default();
+    for (key, value) in table {
+        match key.replace('-', "_").as_str() {
+            "frame" => {
+                frame = Some(
+                    value
+                        .as_integer()
+                        .and_then(|n| usize::try_from(n).ok())
+                        .ok_or("`frame` must be a non-negative integer")?,
+                )
+            }
+            "center" => {
+                complex(key, value)?;
+                keyframe.center = value.as_str().map(str::to_string);
+            }
+            "zoom" => {
+                let zoom = number(key, value)?;
+                if zoom <= 0.0 {
+                    return Err("`zoom` must be positive".to_string());
+                }
+                keyframe.zoom = Some(zoom);
+            }
+            "rotate" => keyframe.rotate = Some(number(key, value)?),
+            "max_iter" => {
+                keyframe.max_iter = Some(
+                    value
+                        .as_integer()
+                        .and_then(|n| usize::try_from(n).ok())
+                        .filter(|&n| n > 0)
+                        .ok_or("`max_iter` must be a positive integer")?,
+                )
+            }
+            "palette_offset" => keyframe.palette_offset = Some(number(key, value)?),
+            "c" => keyframe.c = Some(complex(key, value)?),
+            "easing" => {
+                keyframe.easing = value.as_str().and_then(parse_easing).ok_or(
+                    "`easing` must be `linear`, `ease-in`, `ease-out`, `ease-in-out`, or `hold`",
+                )?
+            }
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    keyframe.frame = frame.ok_or("missing `frame`")?;
+    Ok(keyframe)
+}
+
+/// 解析关键帧文件的内容，`json` 为真时按 JSON 解析，否则按 TOML 解析
+pub fn parse_keyframes(text: &str, json: bool) -> Result<Vec<Keyframe>, String> {
+    let root: Value = if json {
+        serde_json::from_str(text).map_err(|err| err.to_string())?
+    } else {
+        Value::Table(text.parse().map_err(|err| format!("{}", err))?)
+    };
+    let keyframes = root
+        .get("keyframes")
+        .and_then(Value::as_array)
+        .ok_or("expected an array `keyframes`")?;
+    keyframes
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            value
+                .as_table()
+                .ok_or_else(|| "expected a table".to_string())
+                .and_then(parse_keyframe)
+                .map_err(|err| format!("keyframe {}: {}", index + 1, err))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_keyframes() {
+    let toml = r#"
+        [[keyframes]]
+        frame = 0
+        easing = "ease-in"
+
+        [[keyframes]]
+        frame = 60
+        center = "-0.75,0.1"
+        zoom = 100
+        max-iter = 1000
+        c = "-0.8,0.156"
+    "#;
+    let keyframes = parse_keyframes(toml, false).unwrap();
+    let json = r#"{"keyframes": [
+        {"frame": 0, "easing": "ease-in"},
+        {"frame": 60, "center": "-0.75,0.1", "zoom": 100, "max_iter": 1000, "c": "-0.8,0.156"}
+    ]}"#;
+    assert_eq!(parse_keyframes(json, true).unwrap(), keyframes);
+    assert_eq!(
+        keyframes,
+        [
+            Keyframe {
+                easing: Easing::EaseIn,
+                ..Keyframe::default()
+            },
+            Keyframe {
+                frame: 60,
+                center: Some("-0.75,0.1".to_string()),
+                zoom: Some(100.0),
+                max_iter: Some(1000),
+                c: Some(Complex::new(-0.8, 0.156)),
+                ..Keyframe::default()
+            }
+        ]
+    );
+
+    let error = |text: &str| parse_keyframes(text, true).unwrap_err();
+    assert_eq!(error(r#"{"frames": []}"#), "expected an array `keyframes`");
+    assert_eq!(
+        error(r#"{"keyframes": [{"zoom": 2}]}"#),
+        "keyframe 1: missing `frame`"
+    );
+    assert_eq!(
+        error(r#"{"keyframes": [{"frame": 0}, {"frame": 1, "zoom": -2}]}"#),
+        "keyframe 2: `zoom` must be positive"
+    );
+    assert_eq!(
+        error(r#"{"keyframes": [{"frame": 0, "speed": 2}]}"#),
+        "keyframe 1: unknown key `speed`"
+    );
+    assert!(parse_keyframes("[[keyframes]]\nframe = ", false).is_err());
+}
+
+/// 补全了省略值的关键帧序列，可以求出任意一帧的参数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline {
+    keys: Vec<(usize, Scene, Easing)>,
+}
+
+impl Timeline {
+    /// 用 `start` 补全第一个关键帧省略的值，之后每个关键帧省略的值沿用上一个关键帧
+    ///
+    /// 关键帧必须从第 0 帧开始、按帧号严格递增。
+    pub fn new(keyframes: &[Keyframe], start: Scene) -> Result<Timeline, String> {
+        match keyframes.first() {
+            None => return Err("there are no keyframes".to_string()),
+            Some(first) if first.frame != 0 => {
+                return Err("the first keyframe must be frame 0".to_string())
+            }
+            _ => {}
+        }
+        let mut keys: Vec<(usize, Scene, Easing)> = Vec::with_capacity(keyframes.len());
+        let mut previous = start;
+        for keyframe in keyframes {
+            if let Some((frame, _, _)) = keys.last() {
+                if keyframe.frame <= *frame {
+                    return Err(format!(
+                        "keyframe at frame {} must come after frame {}",
+                        keyframe.frame, frame
+                    ));
+                }
+            }
+            if keyframe.c.is_some() && previous.c.is_none() && !keys.is_empty() {
+                return Err(
+                    "`c` must be set on the first keyframe (or with --fractal julia --c)"
+                        .to_string(),
+                );
+            }
+            let scene = Scene {
+                center: keyframe.center.clone().unwrap_or(previous.center),
+                zoom: keyframe.zoom.unwrap_or(previous.zoom),
+                rotate: keyframe.rotate.unwrap_or(previous.rotate),
+                max_iter: keyframe.max_iter.unwrap_or(previous.max_iter),
+                palette_offset: keyframe.palette_offset.unwrap_or(previous.palette_offset),
+                c: keyframe.c.or(previous.c),
+            };
+            keys.push((keyframe.frame, scene.clone(), keyframe.easing));
+            previous = scene;
+        }
+        Ok(Timeline { keys })
+    }
+
+    /// 动画的总帧数，最后一个关键帧是最后一帧
+    pub fn frames(&self) -> usize {
+        self.keys.last().map_or(0, |(frame, _, _)| frame + 1)
+    }
+
+    /// 各个关键帧的参数
+    pub fn scenes(&self) -> impl Iterator<Item = &Scene> {
+        self.keys.iter().map(|(_, scene, _)| scene)
+    }
+
+    /// 第 `frame` 帧的参数
+    pub fn at(&self, frame: usize) -> Scene {
+        let next = self.keys.partition_point(|&(key, _, _)| key <= frame);
+        let (start, scene, easing) = &self.keys[next - 1];
+        match self.keys.get(next) {
+            None => scene.clone(),
+            Some((end, next, _)) => {
+                let t = (frame - start) as f64 / (end - start) as f64;
+                scene.interpolate(next, easing.apply(t))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_timeline() {
+    let start = Scene {
+        center: "-0.5,0".to_string(),
+        zoom: 1.0,
+        rotate: 0.0,
+        max_iter: 100,
+        palette_offset: 0.0,
+        c: None,
+    };
+    let keyframes = parse_keyframes(
+        r#"
+        [[keyframes]]
+        frame = 0
+
+        [[keyframes]]
+        frame = 10
+        zoom = 100
+        rotate = 90
+        max_iter = 300
+        easing = "hold"
+
+        [[keyframes]]
+        frame = 20
+        center = "0.5,1"
+        palette_offset = 1
+    "#,
+        false,
+    )
+    .unwrap();
+    let timeline = Timeline::new(&keyframes, start.clone()).unwrap();
+    assert_eq!(timeline.frames(), 21);
+    assert_eq!(timeline.at(0), start);
+
+    // 缩放按对数插值：一半的帧数缩放 10 倍
+    let middle = timeline.at(5);
+    assert_eq!(middle.center, "-0.5,0");
+    assert!((middle.zoom - 10.0).abs() < 1e-9);
+    assert_eq!(middle.rotate, 45.0);
+    assert_eq!(middle.max_iter, 200);
+
+    // 第二段保持第二个关键帧的值，省略的值沿用上一个关键帧
+    let held = timeline.at(15);
+    assert_eq!(held.center, "-0.5,0");
+    assert_eq!(held.zoom, 100.0);
+    assert_eq!(held.palette_offset, 0.0);
+    let last = timeline.at(20);
+    assert_eq!(last.center, "0.5,1");
+    assert_eq!(last.zoom, 100.0);
+    assert_eq!(last.palette_offset, 1.0);
+    assert_eq!(timeline.at(30), last);
+
+    let frames = |frames: &[usize]| -> Vec<Keyframe> {
+        frames
+            .iter()
+            .map(|&frame| Keyframe {
+                frame,
+                ..Keyframe::default()
+            })
+            .collect()
+    };
+    assert!(Timeline::new(&frames(&[]), start.clone()).is_err());
+    assert!(Timeline::new(&frames(&[1, 2]), start.clone()).is_err());
+    assert!(Timeline::new(&frames(&[0, 5, 5]), start.clone()).is_err());
+    assert_eq!(
+        Timeline::new(&frames(&[0]), start.clone())
+            .unwrap()
+            .frames(),
+        1
+    );
+
+    // 朱利亚集的常数不能只在中途出现
+    let mut keyframes = frames(&[0, 10]);
+    keyframes[1].c = Some(Complex::new(0.0, 1.0));
+    assert!(Timeline::new(&keyframes, start.clone()).is_err());
+    keyframes[0].c = Some(Complex::new(0.0, -1.0));
+    let timeline = Timeline::new(&keyframes, start).unwrap();
+    assert_eq!(timeline.at(5).c, Some(Complex::new(0.0, 0.0)));
+}
@@ -0,0 +1,2638 @@
+//! 曼德博集与朱利亚集的渲染库
+//!
+//! 提供逃逸时间的判定、像素与复平面坐标之间的映射、命令行参数的解析以及
+//! 把渲染结果写入 PNG、JPEG、WebP 或 TIFF 文件的函数。`mandelbrot` 可执行文件
+//! 只是这些函数的一层命令行包装。
+
+pub mod animated;
+pub mod antialias;
+pub mod api;
+pub mod atlas;
+pub mod bailout;
+pub mod bench;
+pub mod buddhabrot;
+pub mod budget;
+pub mod checkpoint;
+pub mod colorizer;
+pub mod config;
+#[cfg(feature = "cuda")]
+pub mod cuda;
+pub mod data;
+pub mod dzi;
+pub mod error;
+pub mod evaluator;
+pub mod explore;
+pub mod expmap;
+pub mod extended;
+pub mod formula;
+#[cfg(any(feature = "opencl", feature = "cuda"))]
+pub mod gpu;
+pub mod hybrid;
+pub mod hypercomplex;
+pub mod interior;
+pub mod keyframes;
+pub mod location;
+pub mod lyapunov;
+pub mod maxiter;
+pub mod measure;
+pub mod misiurewicz;
+pub mod morph;
+pub mod newton;
+pub mod nova;
+pub mod npy;
+pub mod nucleus;
+#[cfg(feature = "opencl")]
+pub mod opencl;
+pub mod openexr;
+pub mod orbit;
+pub mod palette;
+pub mod pan;
+pub mod perturbation;
+pub mod precise;
+pub mod progress;
+pub mod progressive;
+pub mod queue;
+pub mod rays;
+pub mod real;
+pub mod remote;
+pub mod serve;
+pub mod shading;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod stats;
+pub mod stream;
+pub mod threads;
+pub mod tia;
+pub mod tile;
+pub mod transparency;
+pub mod trap;
+pub mod uncompressed;
+pub mod video;
+#[cfg(feature = "viewer")]
+pub mod viewer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use checkpoint::Checkpoint;
+use colorizer::{Colorizer, OrbitStats, PointResult};
+use evaluator::PointEvaluator;
+use formula::Formula;
+use hybrid::Hybrid;
+use image::jpeg::JPEGEncoder;
+use image::png::PNGEncoder;
+use image::ColorType;
+use num::Complex;
+use palette::{Channel, Palette};
+use progress::Progress;
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelSliceMut};
+use real::{narrow, widen, Real};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Seek, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tiff::encoder::{colortype, Compression, TiffEncoder};
+use tile::Tile;
+use trap::Trap;
+
+/// 要渲染的分形类型
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    /// 曼德博集：`z` 从原点出发，`c` 取像素对应的点
+    Mandelbrot,
+    /// 以给定常数 `c` 为参数的朱利亚集：`z` 从像素对应的点出发
+    Julia(Complex<f64>),
+    /// 燃烧船分形：与曼德博集相同地出发，但迭代 `z = (|Re z| + i|Im z|)^2 + c`
+    BurningShip,
+    /// 三角集（Mandelbar）：与曼德博集相同地出发，但迭代 `z = conj(z)^2 + c`
+    Tricorn,
+    /// 多重曼德博集：与曼德博集相同地出发，但迭代 `z = z^d + c`，`d` 是大于 1 的实数
+    Multibrot(f64),
+    /// 与曼德博集相同地出发，但迭代用户给出的公式 `z = f(z, c)`，参见 `formula` 模块
+    ///
+    /// 公式在整个进程中只解析一次，以 `'static` 引用保存，`Fractal` 因此仍然可以复制。
+    Formula(&'static Formula),
+    /// Phoenix 分形：迭代 `z = z^2 + c + p z₋₁`，`z₋₁` 是上一步的 `z`（第一步之前为零）
+    ///
+    /// 给出 `c` 时与朱利亚集一样从像素对应的点出发，否则与曼德博集一样以像素对应的点为 `c`。
+    /// 下一步同时取决于 `z` 和上一步，轨道是二维的，依赖一维轨道的距离估计和吸引环分析
+    /// 都不适用。
+    Phoenix {
+        c: Option<Complex<f64>>,
+        p: Complex<f64>,
+    },
+    /// Lambda 映射：迭代 `z = λ z (1 - z)`；给出 `λ` 时从像素对应的点出发，否则以像素对应的
+    /// 点为 `λ`、从临界点 1/2 出发
+    Lambda(Option<Complex<f64>>),
+    /// 混合分形：按 `hybrid` 给出的模式逐步轮换迭代公式，参见 `hybrid` 模块
+    ///
+    /// 给出 `c` 时与朱利亚集一样从像素对应的点出发，否则与曼德博集一样以像素对应的点为 `c`。
+    /// 模式与自定义公式一样以 `'static` 引用保存。
+    Hybrid {
+        hybrid: &'static Hybrid,
+        c: Option<Complex<f64>>,
+    },
+}
+
+/// Phoenix 分形默认的参数 `p`，与 `c = 0.5667` 一起给出最常见的 Phoenix 朱利亚集
+pub const PHOENIX_P: Complex<f64> = Complex { re: -0.5, im: 0.0 };
+
+impl Fractal {
+    /// 返回复平面上的点 `point` 对应的迭代起点 `z` 和常数 `c`
+    pub fn orbit_start<T: Real>(&self, point: Complex<T>) -> (Complex<T>, Complex<T>) {
+        match *self {
+            Fractal::Mandelbrot
+            | Fractal::BurningShip
+            | Fractal::Tricorn
+            | Fractal::Multibrot(_)
+            | Fractal::Formula(_)
+            | Fractal::Phoenix { c: None, .. }
+            | Fractal::Hybrid { c: None, .. } => (Complex::new(T::zero(), T::zero()), point),
+            Fractal::Julia(c)
+            | Fractal::Phoenix { c: Some(c), .. }
+            | Fractal::Lambda(Some(c))
+            | Fractal::Hybrid { c: Some(c), .. } => (point, narrow(c)),
+            Fractal::Lambda(None) => (Complex::new(T::of(0.5), T::zero()), point),
+        }
+    }
+
+    /// 迭代公式中 `z` 的次数，连续着色的修正项需要用到它
+    pub fn degree(&self) -> f64 {
+        match *self {
+            Fractal::Mandelbrot
+            | Fractal::Julia(_)
+            | Fractal::BurningShip
+            | Fractal::Tricorn
+            | Fractal::Phoenix { .. }
+            | Fractal::Lambda(_) => 2.0,
+            Fractal::Multibrot(power) => power,
+            Fractal::Formula(formula) => formula.degree(),
+            Fractal::Hybrid { hybrid, .. } => hybrid.degree(),
+        }
+    }
+
+    /// 该分形能否使用任意精度和微扰渲染，只有形如 `z^d + c`、次数为整数的内置迭代公式才可以
+    pub fn supports_deep_zoom(&self) -> bool {
+        !matches!(
+            self,
+            Fractal::Formula(_)
+                | Fractal::Phoenix { .. }
+                | Fractal::Lambda(_)
+                | Fractal::Hybrid { .. }
+        ) && self.degree().fract() == 0.0
+    }
+
+    /// 该分形是否关于实轴对称：以 `conj(point)` 出发的轨道处处是以 `point` 出发的轨道的
+    /// 共轭，逃逸次数因此相同
+    ///
+    /// 迭代公式的系数和参数都是实数时成立。燃烧船取绝对值后不再对称，自定义公式和混合
+    /// 分形无法判断，都按不对称处理。
+    pub fn is_symmetric(&self) -> bool {
+        match *self {
+            Fractal::Mandelbrot | Fractal::Tricorn | Fractal::Lambda(None) => true,
+            Fractal::Julia(c) | Fractal::Lambda(Some(c)) => c.im == 0.0,
+            Fractal::Multibrot(power) => power.fract() == 0.0,
+            Fractal::Phoenix { c, p } => p.im == 0.0 && c.is_none_or(|c| c.im == 0.0),
+            Fractal::BurningShip | Fractal::Formula(_) | Fractal::Hybrid { .. } => false,
+        }
+    }
+
+    /// 迭代公式是否还用到上一步的 `z`，参见 `Fractal::Phoenix`
+    pub fn uses_previous(&self) -> bool {
+        matches!(self, Fractal::Phoenix { .. })
+    }
+
+    /// 该分形能否按 `coloring` 着色：用到上一步的分形既没有距离估计需要的导数，也不能按
+    /// 一维轨道的吸引环划分原子域；三角不等式平均要求每一步都是 `z^d + c`，自定义公式、
+    /// Phoenix 分形和 Lambda 映射都不满足
+    pub fn supports_coloring(&self, coloring: Coloring) -> bool {
+        match coloring {
+            Coloring::Distance | Coloring::AtomDomain => !self.uses_previous(),
+            Coloring::TriangleInequality => !matches!(
+                self,
+                Fractal::Formula(_) | Fractal::Phoenix { .. } | Fractal::Lambda(_)
+            ),
+            _ => true,
+        }
+    }
+
+    /// 能否分析吸引环为内部着色：下一步只取决于当前 `z`、每一步都是同一个映射时才可以
+    pub fn supports_interior(&self) -> bool {
+        !self.uses_previous() && self.schedule_len() == 1
+    }
+
+    /// 迭代公式轮换一周的步数，只有混合分形可能大于 1
+    pub fn schedule_len(&self) -> usize {
+        match *self {
+            Fractal::Hybrid { hybrid, .. } => hybrid.steps().len(),
+            _ => 1,
+        }
+    }
+
+    /// 该分形是否迭代 `z = z * z + c`，只有这样的分形才能使用针对二次映射优化的代码路径
+    pub fn is_quadratic(&self) -> bool {
+        matches!(self, Fractal::Mandelbrot | Fractal::Julia(_))
+    }
+
+    /// 对 `z` 做一次该分形的迭代，Phoenix 分形把上一步的 `z` 当作零，混合分形做模式中的
+    /// 第一步
+    ///
+    /// 自定义公式编译为 `f64` 的闭包，其他精度下先转换为 `f64` 再迭代。
+    pub fn step<T: Real>(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        match *self {
+            Fractal::Mandelbrot | Fractal::Julia(_) => z * z + c,
+            Fractal::BurningShip => {
+                let folded = Complex {
+                    re: z.re.abs(),
+                    im: z.im.abs(),
+                };
+                folded * folded + c
+            }
+            Fractal::Tricorn => {
+                let conjugate = z.conj();
+                conjugate * conjugate + c
+            }
+            Fractal::Multibrot(power) if power.fract() == 0.0 => z.powi(power as i32) + c,
+            Fractal::Multibrot(power) => z.powf(T::of(power)) + c,
+            Fractal::Formula(formula) => narrow(formula.step(widen(z), widen(c))),
+            Fractal::Phoenix { .. } => z * z + c,
+            Fractal::Lambda(_) => c * z * (Complex::new(T::one(), T::zero()) - z),
+            Fractal::Hybrid { hybrid, .. } => hybrid.at(0).step(z, c),
+        }
+    }
+
+    /// 已知上一步的 `z` 为 `previous` 时对 `z` 做一次该分形的迭代
+    pub fn step_with_previous<T: Real>(
+        &self,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        match *self {
+            Fractal::Phoenix { p, .. } => z * z + c + previous * narrow::<T>(p),
+            _ => self.step(z, c),
+        }
+    }
+
+    /// 第 `i` 步（从 0 开始）的迭代，已知上一步的 `z` 为 `previous`；混合分形按模式选择公式
+    pub fn step_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        previous: Complex<T>,
+        c: Complex<T>,
+    ) -> Complex<T> {
+        match *self {
+            Fractal::Hybrid { hybrid, .. } => hybrid.at(i).step(z, c),
+            _ => self.step_with_previous(z, previous, c),
+        }
+    }
+
+    /// 不必迭代就能确定 `point` 属于该分形，目前只识别曼德博集的主心形和周期 2 圆盘
+    pub(crate) fn known_interior<T: Real>(&self, point: Complex<T>) -> bool {
+        *self == Fractal::Mandelbrot
+            && INTERIOR_CHECK.load(Ordering::Relaxed)
+            && in_main_cardioid_or_bulb(widen(point))
+    }
+
+    /// 是否在迭代时用导数判据代替周期检测认定内部点（参见 `iterate_with_chain`），只用于
+    /// 导数有闭式的 `z^d + c` 类内置分形，可以与主心形的闭式判据一起关闭
+    fn derivative_bailout(&self) -> bool {
+        matches!(
+            self,
+            Fractal::Mandelbrot
+                | Fractal::Julia(_)
+                | Fractal::BurningShip
+                | Fractal::Tricorn
+                | Fractal::Multibrot(_)
+        ) && INTERIOR_CHECK.load(Ordering::Relaxed)
+    }
+
+    /// 以 `bailout` 为逃逸半径的平方迭代点 `point`，最多 `limit` 次，返回完整的迭代结果
+    ///
+    /// 与 `PointEvaluator::evaluate` 不同，这里不迭代到距离估计用的导数、不累积轨道统计，
+    /// 但会检测吸引环而提前结束（参见 `iterate_with_chain`），此时 `final_z` 是检测到环时
+    /// 的值。
+    pub fn point_result<T: Real>(
+        &self,
+        point: Complex<T>,
+        limit: usize,
+        bailout: f64,
+    ) -> PointResult {
+        let (z, c) = self.orbit_start(point);
+        let zero = Complex::new(0.0, 0.0);
+        if self.known_interior(point) {
+            return PointResult::new(None, widen(z), zero, self.degree(), bailout);
+        }
+        let chain = self
+            .derivative_bailout()
+            .then_some(|z, dz| self.chain(z, dz));
+        let (iterations, z) = iterate_with_chain(
+            z,
+            c,
+            limit,
+            T::of(bailout),
+            self.schedule_len(),
+            |i, z, previous, c| self.step_at(i, z, previous, c),
+            chain,
+        );
+        PointResult::new(iterations, widen(z), zero, self.degree(), bailout)
+    }
+
+    /// 判定复平面上的点 `point` 在该分形中的逃逸时间，参见 `escape_time`
+    pub fn escape_time<T: Real>(&self, point: Complex<T>, limit: usize) -> Option<usize> {
+        self.point_result(point, limit, 4.0).iterations
+    }
+
+    /// 计算点 `point` 在该分形中的连续逃逸时间，参见 `smooth_escape_time`
+    pub fn smooth_escape_time<T: Real>(&self, point: Complex<T>, limit: usize) -> Option<f64> {
+        self.point_result(point, limit, SMOOTH_BAILOUT).smooth()
+    }
+
+    /// 估计逃逸点 `point` 到分形的距离，点不逃逸时返回 `None`
+    ///
+    /// 在迭代 `z` 的同时迭代它对参数的导数 `dz`（曼德博类分形对 `c` 求导，朱利亚集
+    /// 对起点求导），逃逸后按 `|z| ln|z| / |dz|` 估计距离。燃烧船的折叠在每个象限内
+    /// 都是反射，对 `dz` 施加同样的符号翻转即得到它的雅可比矩阵作用；三角集的共轭
+    /// 同样是反射，`conj(z)^2` 把 `dz` 映射为 `2 conj(z) conj(dz)`。自定义公式用对偶数
+    /// 同时求出 `z` 和 `dz`。
+    pub fn distance_estimate<T: Real>(&self, point: Complex<T>, limit: usize) -> Option<f64> {
+        let (result, _) = self.evaluate(&Coloring::Distance, point, limit);
+        result.distance()
+    }
+
+    /// 迭代前导数 `dz` 的初值：朱利亚类分形对起点求导为 1，曼德博类分形对 `c` 求导为 0
+    pub(crate) fn derivative_start<T: Real>(&self) -> Complex<T> {
+        match *self {
+            Fractal::Julia(_) | Fractal::Lambda(Some(_)) | Fractal::Hybrid { c: Some(_), .. } => {
+                Complex::new(T::one(), T::zero())
+            }
+            _ => Complex::new(T::zero(), T::zero()),
+        }
+    }
+
+    /// 对 `z` 做一次该分形的迭代，同时迭代导数 `dz`，参见 `distance_estimate`
+    ///
+    /// Phoenix 分形的导数还取决于上一步的导数，不支持；混合分形做模式中的第一步。
+    pub(crate) fn step_with_derivative<T: Real>(
+        &self,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        self.step_with_derivative_at(0, z, dz, c)
+    }
+
+    /// 第 `i` 步（从 0 开始）的迭代，同时迭代导数 `dz`；混合分形按模式选择公式
+    pub(crate) fn step_with_derivative_at<T: Real>(
+        &self,
+        i: usize,
+        z: Complex<T>,
+        dz: Complex<T>,
+        c: Complex<T>,
+    ) -> (Complex<T>, Complex<T>) {
+        let zero = T::zero();
+        let one = Complex::new(T::one(), zero);
+        let derivative = match *self {
+            Fractal::Phoenix { .. } => panic!("the phoenix fractal has no derivative"),
+            // 对 λ 求导时多出 z (1 - z) 一项，而不是对 c 求导时的 1
+            Fractal::Lambda(lambda) => {
+                let derivative = c * (one - z * T::of(2.0)) * dz;
+                let shift = match lambda {
+                    Some(_) => Complex::new(zero, zero),
+                    None => z * (one - z),
+                };
+                return (self.step(z, c), derivative + shift);
+            }
+            Fractal::Formula(formula) => {
+                let (next, derivative) =
+                    formula.step_with_derivative(widen(z), widen(dz), widen(c));
+                return (narrow(next), narrow(derivative));
+            }
+            Fractal::Hybrid { hybrid, .. } => hybrid.at(i).chain(z, dz),
+            _ => self.chain(z, dz),
+        };
+        let shift = match *self {
+            Fractal::Julia(_) | Fractal::Hybrid { c: Some(_), .. } => zero,
+            _ => T::one(),
+        };
+        let previous = Complex::new(zero, zero);
+        (self.step_at(i, z, previous, c), derivative + shift)
+    }
+
+    /// 一步迭代后导数 `dz` 经链式法则变成的值，不含对 `c` 的偏导，参见 `distance_estimate`
+    fn chain<T: Real>(&self, z: Complex<T>, dz: Complex<T>) -> Complex<T> {
+        let (zero, two) = (T::zero(), T::of(2.0));
+        match *self {
+            Fractal::Mandelbrot | Fractal::Julia(_) => z * dz * two,
+            Fractal::BurningShip => {
+                let reflect = |value: T, sign: T| if sign < zero { -value } else { value };
+                let folded = Complex {
+                    re: z.re.abs(),
+                    im: z.im.abs(),
+                };
+                let flipped = Complex {
+                    re: reflect(dz.re, z.re),
+                    im: reflect(dz.im, z.im),
+                };
+                folded * flipped * two
+            }
+            Fractal::Tricorn => z.conj() * dz.conj() * two,
+            Fractal::Multibrot(power) => z.powf(T::of(power - 1.0)) * dz * T::of(power),
+            Fractal::Formula(_)
+            | Fractal::Phoenix { .. }
+            | Fractal::Lambda(_)
+            | Fractal::Hybrid { .. } => unreachable!("{:?} has no closed-form derivative", self),
+        }
+    }
+
+    /// 按着色方式 `coloring` 求出点 `point` 的逃逸值：整数逃逸次数、连续逃逸时间或
+    /// 由距离估计、轨道陷阱或三角不等式平均换算的值（参见 `distance_value`、
+    /// `trap::trap_value` 和 `tia::average`），`spacing` 是相邻像素的间距。轨道陷阱和
+    /// 三角不等式平均总是用 `f64` 计算。
+    pub fn escape_value<T: Real>(
+        &self,
+        coloring: Coloring,
+        point: Complex<T>,
+        limit: usize,
+        spacing: T,
+    ) -> Option<f64> {
+        let (result, stats) = match coloring {
+            Coloring::EscapeTime | Coloring::Smooth => {
+                let result = self.point_result(point, limit, coloring.bailout());
+                (result, OrbitStats::default())
+            }
+            Coloring::Distance => self.evaluate(&coloring, point, limit),
+            Coloring::OrbitTrap(_) | Coloring::TriangleInequality | Coloring::AtomDomain => {
+                self.evaluate(&coloring, widen(point), limit)
+            }
+        };
+        coloring.value(self, &result, &stats, limit, spacing.f64())
+    }
+
+    /// 以 `power` 为迭代公式的次数
+    ///
+    /// 曼德博集可以推广为任意次数的多重曼德博集（次数为 2 时仍是曼德博集本身），
+    /// 其他分形只支持次数 2，否则返回 `None`
+    pub fn with_power(self, power: f64) -> Option<Fractal> {
+        match self {
+            _ if power == self.degree() => Some(self),
+            Fractal::Mandelbrot | Fractal::Multibrot(_) if power == 2.0 => {
+                Some(Fractal::Mandelbrot)
+            }
+            Fractal::Mandelbrot | Fractal::Multibrot(_) => Some(Fractal::Multibrot(power)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_with_power() {
+    let c = Complex { re: 0.0, im: 1.0 };
+    assert_eq!(
+        Fractal::Mandelbrot.with_power(3.0),
+        Some(Fractal::Multibrot(3.0))
+    );
+    assert_eq!(
+        Fractal::Multibrot(3.0).with_power(2.0),
+        Some(Fractal::Mandelbrot)
+    );
+    assert_eq!(Fractal::Julia(c).with_power(2.0), Some(Fractal::Julia(c)));
+    assert_eq!(Fractal::Julia(c).with_power(3.0), None);
+    assert_eq!(Fractal::BurningShip.with_power(2.5), None);
+}
+
+/// 把字符串 `s` 解析为迭代公式的次数，次数必须是大于 1 的有限值
+pub fn parse_power(s: &str) -> Option<f64> {
+    match s.parse::<f64>() {
+        Ok(power) if power.is_finite() && power > 1.0 => Some(power),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_power() {
+    assert_eq!(parse_power("3"), Some(3.0));
+    assert_eq!(parse_power("2.5"), Some(2.5));
+    assert_eq!(parse_power("1"), None);
+    assert_eq!(parse_power("NaN"), None);
+}
+
+/// 是否在迭代前用闭式判据排除曼德博集主心形和周期 2 圆盘内的点，并在迭代中用导数判据
+/// 认定内部点，默认开启
+static INTERIOR_CHECK: AtomicBool = AtomicBool::new(true);
+
+/// 开启或关闭主心形和周期 2 圆盘的预先判定以及导数判据，关闭后内部点只靠周期检测，
+/// 可以测量它们带来的加速
+pub fn set_interior_check(enabled: bool) {
+    INTERIOR_CHECK.store(enabled, Ordering::Relaxed);
+}
+
+/// 是否在分形和视图都关于实轴对称时只渲染一半再镜像（参见 `render_parallel`），默认开启
+static SYMMETRY: AtomicBool = AtomicBool::new(true);
+
+/// 开启或关闭关于实轴的镜像，关闭后总是渲染整幅图像，可以测量镜像带来的加速
+pub fn set_symmetry(enabled: bool) {
+    SYMMETRY.store(enabled, Ordering::Relaxed);
+}
+
+/// `c` 是否位于曼德博集的主心形或以 -1 为中心、半径 1/4 的周期 2 圆盘之内
+///
+/// 两者内部的点都不会逃逸，而且通常占据视图中相当大的面积，用闭式判据排除它们
+/// 可以省去满 `limit` 次的迭代。
+pub fn in_main_cardioid_or_bulb(c: Complex<f64>) -> bool {
+    let x = c.re - 0.25;
+    let y2 = c.im * c.im;
+    let q = x * x + y2;
+    let cardioid = q * (q + x) <= 0.25 * y2;
+    let bulb = (c.re + 1.0) * (c.re + 1.0) + y2 <= 1.0 / 16.0;
+    cardioid || bulb
+}
+
+#[test]
+fn test_in_main_cardioid_or_bulb() {
+    assert!(in_main_cardioid_or_bulb(Complex { re: 0.0, im: 0.0 }));
+    assert!(in_main_cardioid_or_bulb(Complex { re: 0.2, im: 0.3 }));
+    assert!(in_main_cardioid_or_bulb(Complex { re: -1.1, im: 0.1 }));
+    // 心形尖端之外、圆盘外侧和周期 3 圆盘中的点都不在判据范围内
+    assert!(!in_main_cardioid_or_bulb(Complex { re: 0.3, im: 0.0 }));
+    assert!(!in_main_cardioid_or_bulb(Complex { re: -1.3, im: 0.0 }));
+    assert!(!in_main_cardioid_or_bulb(Complex {
+        re: -0.12,
+        im: 0.75
+    }));
+    for (re, im) in [(0.2, 0.3), (-1.1, 0.1), (-0.75, 0.0)] {
+        let c = Complex { re, im };
+        assert_eq!(escape_time(Complex { re: 0.0, im: 0.0 }, c, 10000), None);
+    }
+}
+
+/// 像素灰度的着色方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coloring {
+    /// 直接使用整数逃逸次数，相邻次数之间会出现明显的色带
+    EscapeTime,
+    /// 使用经过 log(log(|z|)) 修正的归一化迭代次数，得到连续的渐变
+    Smooth,
+    /// 按到集合的估计距离着色，能清晰地显示逃逸时间着色中看不到的细丝
+    Distance,
+    /// 按轨道到陷阱图形的最小距离着色，集合内部的点也有颜色
+    OrbitTrap(Trap),
+    /// 三角不等式平均：按轨道每一步在三角不等式给出的范围中的相对位置的平均值着色
+    TriangleInequality,
+    /// 原子域：按轨道最接近原点的迭代步数着色，得到按周期划分的区域，集合内部的点也有颜色
+    AtomDomain,
+}
+
+impl Coloring {
+    /// 深度缩放的渲染方式是否支持该着色方式，它们只记录逃逸次数和逃逸时的值
+    pub fn supports_deep_zoom(&self) -> bool {
+        matches!(self, Coloring::EscapeTime | Coloring::Smooth)
+    }
+
+    /// 逃逸值是否就是（可能经过平滑修正的）迭代次数，只有这时按迭代次数统计的直方图才有意义
+    pub fn counts_iterations(&self) -> bool {
+        matches!(self, Coloring::EscapeTime | Coloring::Smooth)
+    }
+
+    /// 轨道陷阱着色时以 `trap` 为陷阱，其他着色方式不受影响
+    pub fn with_trap(self, trap: Trap) -> Coloring {
+        match self {
+            Coloring::OrbitTrap(_) => Coloring::OrbitTrap(trap),
+            coloring => coloring,
+        }
+    }
+}
+
+/// 把字符串 `s`（形如 `"escape-time"`、`"smooth"`、`"distance"`、`"orbit-trap"`、`"tia"` 或
+/// `"atom-domain"`）
+/// 解析成着色方式
+///
+/// 轨道陷阱使用默认的陷阱，可以再用 `Coloring::with_trap` 替换
+pub fn parse_coloring(s: &str) -> Option<Coloring> {
+    match s {
+        "escape-time" => Some(Coloring::EscapeTime),
+        "smooth" => Some(Coloring::Smooth),
+        "distance" => Some(Coloring::Distance),
+        "orbit-trap" => Some(Coloring::OrbitTrap(Trap::default())),
+        "tia" => Some(Coloring::TriangleInequality),
+        "atom-domain" => Some(Coloring::AtomDomain),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_coloring() {
+    assert_eq!(parse_coloring("escape-time"), Some(Coloring::EscapeTime));
+    assert_eq!(parse_coloring("smooth"), Some(Coloring::Smooth));
+    assert_eq!(parse_coloring("distance"), Some(Coloring::Distance));
+    assert_eq!(
+        parse_coloring("orbit-trap"),
+        Some(Coloring::OrbitTrap(Trap::default()))
+    );
+    assert_eq!(parse_coloring("tia"), Some(Coloring::TriangleInequality));
+    assert_eq!(parse_coloring("atom-domain"), Some(Coloring::AtomDomain));
+    assert_eq!(parse_coloring("banded"), None);
+}
+
+/// 距离估计使用的逃逸半径的平方，半径越大估计越准确
+pub(crate) const DISTANCE_BAILOUT: f64 = 1e10;
+
+/// 距离达到这么多个像素时对应调色板的起点
+const DISTANCE_RANGE: f64 = 256.0;
+
+/// 把以像素为单位的距离换算为 `[0, limit]` 区间内的逃逸值，以便与其他着色方式
+/// 共用调色板映射
+///
+/// 紧贴集合的点映射到 `limit`（调色板的终点），距离 `DISTANCE_RANGE` 个像素以外的
+/// 点映射到 0。取四次方根是为了让一两个像素宽的细丝也有明显的层次。
+pub fn distance_value(pixels: f64, limit: usize) -> f64 {
+    limit as f64 * (1.0 - (pixels / DISTANCE_RANGE).min(1.0).powf(0.25))
+}
+
+#[test]
+fn test_distance_estimate() {
+    // 实轴上 c > 1/4 的点到集合的距离就是 c - 1/4
+    for re in [0.5, 1.0, 2.0] {
+        let distance = Fractal::Mandelbrot
+            .distance_estimate(Complex { re, im: 0.0 }, 1000)
+            .unwrap();
+        let exact = re - 0.25;
+        assert!(
+            distance > exact / 4.0 && distance < exact * 4.0,
+            "{} vs {}",
+            distance,
+            exact
+        );
+    }
+    assert_eq!(
+        Fractal::Mandelbrot.distance_estimate(Complex { re: -0.5, im: 0.0 }, 1000),
+        None
+    );
+    assert_eq!(distance_value(0.0, 100), 100.0);
+    assert_eq!(distance_value(256.0, 100), 0.0);
+}
+
+/// 把字符串 `s`（形如 `"mandelbrot"`、`"julia"`、`"burning-ship"`、`"tricorn"`、`"phoenix"` 或
+/// `"lambda"`）连同常数 `c` 解析成分形类型
+///
+/// 朱利亚集必须提供常数 `c`，否则返回 `None`；Phoenix 分形和 Lambda 映射给出 `c` 时是朱利亚
+/// 类分形（Lambda 映射以 `c` 为 `λ`），Phoenix 分形的参数 `p` 取 `PHOENIX_P`
+pub fn parse_fractal(s: &str, c: Option<Complex<f64>>) -> Option<Fractal> {
+    match (s, c) {
+        ("mandelbrot", _) => Some(Fractal::Mandelbrot),
+        ("julia", Some(c)) => Some(Fractal::Julia(c)),
+        ("burning-ship", _) => Some(Fractal::BurningShip),
+        ("tricorn", _) => Some(Fractal::Tricorn),
+        ("phoenix", c) => Some(Fractal::Phoenix { c, p: PHOENIX_P }),
+        ("lambda", c) => Some(Fractal::Lambda(c)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_fractal() {
+    let c = Complex {
+        re: -0.8,
+        im: 0.156,
+    };
+    assert_eq!(parse_fractal("mandelbrot", None), Some(Fractal::Mandelbrot));
+    assert_eq!(parse_fractal("julia", Some(c)), Some(Fractal::Julia(c)));
+    assert_eq!(parse_fractal("julia", None), None);
+    assert_eq!(
+        parse_fractal("burning-ship", None),
+        Some(Fractal::BurningShip)
+    );
+    assert_eq!(parse_fractal("tricorn", None), Some(Fractal::Tricorn));
+    assert_eq!(
+        parse_fractal("phoenix", Some(c)),
+        Some(Fractal::Phoenix {
+            c: Some(c),
+            p: PHOENIX_P
+        })
+    );
+    assert_eq!(parse_fractal("lambda", None), Some(Fractal::Lambda(None)));
+    assert_eq!(parse_fractal("newton", Some(c)), None);
+}
+
+#[test]
+fn test_phoenix_and_lambda() {
+    let c = Complex {
+        re: 0.5667,
+        im: 0.0,
+    };
+    let zero = Complex { re: 0.0, im: 0.0 };
+    // p 为 0 时 Phoenix 分形就是曼德博集或朱利亚集
+    let points = [
+        Complex { re: 0.3, im: 0.5 },
+        Complex {
+            re: -0.12,
+            im: 0.75,
+        },
+        Complex { re: -1.5, im: 0.2 },
+    ];
+    for point in points {
+        assert_eq!(
+            Fractal::Phoenix { c: None, p: zero }.escape_time(point, 200),
+            Fractal::Mandelbrot.escape_time(point, 200)
+        );
+        assert_eq!(
+            Fractal::Phoenix {
+                c: Some(c),
+                p: zero
+            }
+            .escape_time(point, 200),
+            Fractal::Julia(c).escape_time(point, 200)
+        );
+    }
+    // 上一步的 z 参与迭代，p 改变后结果不同
+    let phoenix = Fractal::Phoenix {
+        c: Some(c),
+        p: PHOENIX_P,
+    };
+    let z = Complex { re: 0.5, im: 0.5 };
+    assert_eq!(
+        phoenix.step_with_previous(z, z, c),
+        z * z + c + z * PHOENIX_P
+    );
+    // 逐点求值与带统计的求值使用同样的迭代
+    for point in points {
+        let (result, _) = phoenix.evaluate(&Coloring::EscapeTime, point, 200);
+        assert_eq!(result.iterations, phoenix.escape_time(point, 200));
+    }
+    assert!(!phoenix.supports_coloring(Coloring::Distance));
+    assert!(!phoenix.supports_coloring(Coloring::TriangleInequality));
+    assert!(phoenix.supports_coloring(Coloring::Smooth));
+
+    // Lambda 映射的参数平面从临界点 1/2 出发：λ = 2 时 1/2 是不动点，λ = 5 时逃逸
+    let lambda = Fractal::Lambda(None);
+    assert_eq!(lambda.escape_time(Complex { re: 2.0, im: 0.0 }, 200), None);
+    assert_eq!(lambda.escape_time(Complex { re: 1.0, im: 0.0 }, 200), None);
+    assert!(lambda
+        .escape_time(Complex { re: 5.0, im: 0.0 }, 200)
+        .is_some());
+    // 实轴上参数平面的右端点是 λ = 4，距离估计与到它的距离同量级
+    let distance = lambda
+        .distance_estimate(Complex { re: 4.2, im: 0.0 }, 1000)
+        .unwrap();
+    let exact = 4.2 - 4.0;
+    assert!(
+        distance > exact / 4.0 && distance < exact * 4.0,
+        "{}",
+        distance
+    );
+    assert!(lambda.supports_coloring(Coloring::Distance));
+    assert!(!lambda.supports_coloring(Coloring::TriangleInequality));
+}
+
+/// 从 `z` 出发迭代 `z = z * z + c`，使用最多 `limit` 次迭代来判定轨道是否有界
+///
+/// 如果轨道逃逸，则返回 `Some(i)`，其中 `i` 是 `z` 离开以原点为中心的半径为 2
+/// 的圆时所需的迭代次数。如果轨道似乎有界（确切而言是达到了迭代次数限制但仍然
+/// 无法证明其逃逸），则返回 `None`
+///
+/// 令 `z` 为原点即得到曼德博集的判定；令 `z` 为像素对应的点、`c` 为固定常数即得到
+/// 朱利亚集的判定
+pub fn escape_time<T: Real>(z: Complex<T>, c: Complex<T>, limit: usize) -> Option<usize> {
+    iterate(z, c, limit, T::of(4.0), 1, |_, z, _, c| z * z + c).0
+}
+
+/// 周期检测判定轨道回到已记录的点时允许的距离平方
+const PERIODICITY_TOLERANCE: f64 = 1e-30;
+
+/// 从 `z` 出发反复应用 `step`，直到 `|z|^2` 超过 `bailout` 或者迭代了 `limit` 次
+///
+/// `step` 的参数依次是迭代的序号、当前的 `z`、上一步的 `z`（第一步之前为零）和 `c`。
+/// 返回逃逸时的迭代次数（未逃逸时为 `None`）和最后的 `z`。
+///
+/// 有界的轨道通常会落入一个吸引环。这里用 Brent 的方法检测环：记录某一次迭代的 `z`，
+/// 之后每次迭代都与它比较，比较的次数达到 1、2、4、8…… 时更换记录的点。轨道一旦回到
+/// 记录的点就不会逃逸，可以提前返回，不必用满 `limit` 次迭代。下一步用到上一步时状态是
+/// `z` 和上一步这一对值，因此只有两者都回到记录的值才算成环；迭代公式每 `schedule` 步
+/// 轮换一周时，只有相隔整周的两步才能比较。
+pub(crate) fn iterate<T: Real>(
+    z: Complex<T>,
+    c: Complex<T>,
+    limit: usize,
+    bailout: T,
+    schedule: usize,
+    step: impl Fn(usize, Complex<T>, Complex<T>, Complex<T>) -> Complex<T>,
+) -> (Option<usize>, Complex<T>) {
+    let chain = None::<fn(Complex<T>, Complex<T>) -> Complex<T>>;
+    iterate_with_chain(z, c, limit, bailout, schedule, step, chain)
+}
+
+/// 导数判据认定轨道落入吸引环时 `|dz|^2` 的上限
+const DERIVATIVE_BAILOUT: f64 = 1e-24;
+
+/// 与 `iterate` 相同，但给出 `chain` 时用导数判据代替周期检测认定内部点
+///
+/// `chain(z, dz)` 是一步迭代后 `z` 的导数 `dz` 经链式法则变成的值（参见
+/// `Fractal::chain`）。这里跟踪的是 `z` 对第一步之后的 `z` 的导数，即各步 `f'(z)` 的连乘：
+/// 轨道落入吸引环后每绕一周导数都乘上环的乘子，模小于 1，导数很快趋于零；逃逸的轨道
+/// 上导数则越来越大。`|dz|^2` 小于 `DERIVATIVE_BAILOUT` 时就认为点在内部，不必像周期
+/// 检测那样等轨道精确地回到记录的点，每步也只多一次复数乘法。对 `c` 的导数在内部点收敛到
+/// 有限值而不会趋于零，所以不用它。曼德博类分形从临界点 0 出发，第一步的 `f'(0)` 为零，
+/// 因此从第二步开始连乘。
+pub(crate) fn iterate_with_chain<T: Real>(
+    mut z: Complex<T>,
+    c: Complex<T>,
+    limit: usize,
+    bailout: T,
+    schedule: usize,
+    step: impl Fn(usize, Complex<T>, Complex<T>, Complex<T>) -> Complex<T>,
+    chain: Option<impl Fn(Complex<T>, Complex<T>) -> Complex<T>>,
+) -> (Option<usize>, Complex<T>) {
+    let mut previous = Complex::new(T::zero(), T::zero());
+    if let Some(chain) = chain {
+        let mut dz = Complex::new(T::one(), T::zero());
+        for i in 0..limit {
+            if z.norm_sqr() > bailout {
+                return (Some(i), z);
+            }
+            if i > 0 {
+                dz = chain(z, dz);
+                if dz.norm_sqr() < T::of(DERIVATIVE_BAILOUT) {
+                    return (None, z);
+                }
+            }
+            (z, previous) = (step(i, z, previous, c), z);
+        }
+        return (None, z);
+    }
+
+    let (mut saved, mut saved_previous) = (z, previous);
+    let mut period = 0;
+    let mut check = 1;
+    for i in 0..limit {
+        if z.norm_sqr() > bailout {
+            return (Some(i), z);
+        }
+        (z, previous) = (step(i, z, previous, c), z);
+        period += 1;
+        let tolerance = T::of(PERIODICITY_TOLERANCE);
+        if period % schedule == 0
+            && (z - saved).norm_sqr() < tolerance
+            && (previous - saved_previous).norm_sqr() < tolerance
+        {
+            return (None, z);
+        }
+        if period == check {
+            (saved, saved_previous) = (z, previous);
+            period = 0;
+            check *= 2;
+        }
+    }
+    (None, z)
+}
+
+#[test]
+fn test_iterate_with_chain() {
+    // 迭代次数限制大到不可能跑满，只有导数判据能让内部点返回
+    let origin = Complex { re: 0.0, im: 0.0 };
+    let chain = |z: Complex<f64>, dz: Complex<f64>| z * dz * 2.0;
+    let step = |_, z: Complex<f64>, _, c| z * z + c;
+    // 周期 3 的分量内部，闭式判据不认识的点
+    let interior = Complex {
+        re: -0.1226,
+        im: 0.7449,
+    };
+    let (iterations, _) =
+        iterate_with_chain(origin, interior, usize::MAX, 4.0, 1, step, Some(chain));
+    assert_eq!(iterations, None);
+    // 逃逸的点不受影响
+    let exterior = Complex { re: -0.75, im: 0.1 };
+    assert_eq!(
+        iterate_with_chain(origin, exterior, 1000, 4.0, 1, step, Some(chain)).0,
+        escape_time(origin, exterior, 1000)
+    );
+}
+
+#[test]
+fn test_iterate_periodicity() {
+    // 迭代次数限制大到不可能跑满，只有检测到周期才能返回
+    let origin = Complex { re: 0.0, im: 0.0 };
+    for (re, im) in [(-0.1, 0.1), (-1.0, 0.0), (-0.12, 0.75)] {
+        assert_eq!(escape_time(origin, Complex { re, im }, usize::MAX), None);
+    }
+    assert_eq!(
+        Fractal::BurningShip.escape_time(Complex { re: -0.5, im: 0.0 }, usize::MAX),
+        None
+    );
+}
+
+#[test]
+fn test_single_precision() {
+    // 远离边界的点在 f32 和 f64 下的逃逸次数相同
+    let points = [(0.5, 0.5), (-0.8, 0.3), (0.3, -0.6), (-2.1, 0.0), (-0.1, 0.1)];
+    for fractal in [
+        Fractal::Mandelbrot,
+        Fractal::Julia(Complex { re: -0.8, im: 0.156 }),
+        Fractal::BurningShip,
+        Fractal::Tricorn,
+        Fractal::Multibrot(3.0),
+    ] {
+        for (re, im) in points {
+            let double = Complex { re, im };
+            let single = narrow::<f32>(double);
+            assert_eq!(
+                fractal.escape_time(single, 100),
+                fractal.escape_time(double, 100),
+                "{:?} at {}",
+                fractal,
+                double
+            );
+        }
+    }
+}
+
+/// 连续着色使用的逃逸半径的平方
+///
+/// 半径越大，log(log(|z|)) 修正越接近理想的连续值，这里取半径 256
+pub(crate) const SMOOTH_BAILOUT: f64 = 256.0 * 256.0;
+
+/// 由逃逸时的迭代次数 `i` 和逃逸时的值 `z` 求出连续迭代次数 `i + 1 - log_d(ln|z|)`
+///
+/// `degree` 是迭代公式中 `z` 的次数 `d`：远离原点时每次迭代 `ln|z|` 大约变为原来的
+/// `d` 倍，因此以 `d` 为底取对数才能让相邻的整数逃逸次数平滑衔接
+pub(crate) fn smooth_value(i: usize, z: Complex<f64>, degree: f64) -> f64 {
+    let log_modulus = z.norm_sqr().ln() / 2.0;
+    i as f64 + 1.0 - log_modulus.ln() / degree.ln()
+}
+
+#[test]
+fn test_smooth_value() {
+    // 远离原点时下一步的模约为 |z|^d，连续迭代次数应当不变
+    for degree in [2.0, 3.0] {
+        let z = Complex::new(300.0, 400.0);
+        let next = Complex::new(500f64.powf(degree), 0.0);
+        assert!((smooth_value(7, z, degree) - smooth_value(8, next, degree)).abs() < 1e-12);
+    }
+    // |z| = e 时 ln|z| = 1，修正项为零
+    let e = Complex::new(std::f64::consts::E, 0.0);
+    assert!((smooth_value(3, e, 2.0) - 4.0).abs() < 1e-12);
+}
+
+/// 与 `escape_time` 相同地迭代，但返回归一化后的连续迭代次数
+///
+/// 若轨道在第 `i` 次迭代时逃逸，则返回 `Some(i + 1 - log2(ln|z|))`，其中 `z` 是
+/// 逃逸时的值，这样相邻的整数逃逸次数之间就能平滑过渡；若达到迭代次数限制仍未逃逸，
+/// 则返回 `None`
+pub fn smooth_escape_time<T: Real>(z: Complex<T>, c: Complex<T>, limit: usize) -> Option<f64> {
+    let (iterations, z) = iterate(z, c, limit, T::of(SMOOTH_BAILOUT), 1, |_, z, _, c| {
+        z * z + c
+    });
+    iterations.map(|i| smooth_value(i, widen(z), 2.0))
+}
+
+#[test]
+fn test_smooth_escape_time() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(smooth_escape_time(origin, origin, 255), None);
+    // 靠近的两个点的连续逃逸次数应当相近，且随着远离集合而减小
+    let near = smooth_escape_time(origin, Complex { re: 0.30, im: 0.0 }, 255).unwrap();
+    let nearer = smooth_escape_time(
+        origin,
+        Complex {
+            re: 0.2501,
+            im: 0.0,
+        },
+        2550,
+    )
+    .unwrap();
+    let far = smooth_escape_time(origin, Complex { re: 2.0, im: 2.0 }, 255).unwrap();
+    assert!(far < near && near < nearer);
+    let a = smooth_escape_time(origin, Complex { re: 0.5, im: 0.5 }, 255).unwrap();
+    let b = smooth_escape_time(
+        origin,
+        Complex {
+            re: 0.5001,
+            im: 0.5,
+        },
+        255,
+    )
+    .unwrap();
+    assert!((a - b).abs() < 0.1);
+}
+
+/// 把字符串 `s`（形如 `"400×600"` 或 ``"1.0,0.5"）解析成一个坐标对
+///
+/// 具体来说，`s` 应该具有<left><sep><right>的格式，其中<sep>是由`separator`
+/// 参数给出的字符，而<left>和<right>是可以被 `T:from_str` 解析的字符串。
+/// `separator` 必须是 ASCII 字符
+///
+/// 如果 `s` 具有正确的格式，就返回 `Some(x,y)`，否则返回 `None`
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+    match s.find(separator) {
+        None => None,
+        Some(index) => match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
+            (Ok(l), Ok(r)) => Some((l, r)),
+            _ => None,
+        },
+    }
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("", ','), None);
+    assert_eq!(parse_pair::<i32>("10,", ','), None);
+    assert_eq!(parse_pair::<i32>(",10", ','), None);
+    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
+    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+}
+
+/// 把一对用逗号隔开的浮点数解析为复数
+pub fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    parse_pair(s, ',').map(|(re, im)| Complex { re, im })
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(
+        parse_complex("1.25,-0.0625"),
+        Some(Complex {
+            re: 1.25,
+            im: -0.0625
+        })
+    );
+    assert_eq!(parse_complex(",-0.0625"), None);
+}
+
+/// 给定输出图像重像素的行和列，返回复平面中对应的坐标
+///
+/// `bound` 是一个 `pair`，给出了图像的像素宽度和像素高度。
+/// `pixed` 是表示给图片中特定像素的 (column, row) 二元组。
+/// `upper_left` 参数和 `lower_right` 参数是在复平面中表示指定图像覆盖范围的点。
+/// 需要逐个像素映射或者旋转视图时使用 `PixelTransform`。
+pub fn pixed_to_point<T: Real>(
+    /*
+    ·--------------------> bounds.0  re
+    丨
+    丨
+    丨
+    丨
+    丨
+    bounds.1  im
+     */
+    bounds: (usize, usize),
+    pixed: (usize, usize),
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+) -> Complex<T> {
+    PixelTransform::from_corners(bounds, upper_left, lower_right).point(pixed)
+}
+
+#[test]
+fn test_pixed_to_point() {
+    assert_eq!(
+        pixed_to_point(
+            (100, 200),
+            (25, 175),
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 1.0, im: -1.0 }
+        ),
+        Complex {
+            re: -0.5,
+            im: -0.75,
+        }
+    );
+}
+
+/// 把像素坐标映射为复平面上的点的仿射变换
+///
+/// 第 `(column, row)` 个像素对应 `origin + column * right + row * down`：`right` 和
+/// `down` 分别是向右、向下移动一个像素时点的位移。不旋转时 `right` 是正实数，`down`
+/// 是负虚数；旋转视图时两者转过相同的角度。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelTransform<T> {
+    pub origin: Complex<T>,
+    pub right: Complex<T>,
+    pub down: Complex<T>,
+}
+
+impl<T: Real> PixelTransform<T> {
+    /// 覆盖 `upper_left` 到 `lower_right` 的、不旋转的 `bounds` 大小的图像
+    pub fn from_corners(
+        bounds: (usize, usize),
+        upper_left: Complex<T>,
+        lower_right: Complex<T>,
+    ) -> PixelTransform<T> {
+        let zero = T::zero();
+        PixelTransform {
+            origin: upper_left,
+            right: Complex::new(
+                (lower_right.re - upper_left.re) / T::of(bounds.0 as f64),
+                zero,
+            ),
+            down: Complex::new(
+                zero,
+                (lower_right.im - upper_left.im) / T::of(bounds.1 as f64),
+            ),
+        }
+    }
+
+    /// 把 `bounds` 大小的图像覆盖的矩形绕其中心逆时针旋转 `degrees` 度，画面看起来
+    /// 顺时针转动
+    pub fn rotated(self, bounds: (usize, usize), degrees: f64) -> PixelTransform<T> {
+        if degrees == 0.0 {
+            return self;
+        }
+        let half = (T::of(bounds.0 as f64 / 2.0), T::of(bounds.1 as f64 / 2.0));
+        let center = self.origin + self.right * half.0 + self.down * half.1;
+        let rotation = Complex::from_polar(T::one(), T::of(degrees.to_radians()));
+        let (right, down) = (self.right * rotation, self.down * rotation);
+        PixelTransform {
+            origin: center - right * half.0 - down * half.1,
+            right,
+            down,
+        }
+    }
+
+    /// 每个像素在每个方向上再分为 `factor` 个子像素后的变换，子像素 `(0, 0)` 仍从
+    /// `origin` 开始
+    pub fn subdivided(self, factor: usize) -> PixelTransform<T> {
+        let factor = T::of(factor as f64);
+        PixelTransform {
+            origin: self.origin,
+            right: self.right / factor,
+            down: self.down / factor,
+        }
+    }
+
+    /// 像素 `pixed`（列、行）对应的点
+    ///
+    /// 等于 `row_start(行) + column_offset(列)`。逐行渲染时每行的起点和每列的偏移都只需要
+    /// 计算一次，每个像素只剩一次复数加法，结果与逐个调用 `point` 逐位相同。
+    pub fn point(&self, pixed: (usize, usize)) -> Complex<T> {
+        self.row_start(pixed.1) + self.column_offset(pixed.0)
+    }
+
+    /// 第 `row` 行第 0 列的像素对应的点
+    pub fn row_start(&self, row: usize) -> Complex<T> {
+        self.origin + self.down * T::of(row as f64)
+    }
+
+    /// 第 `column` 列的像素相对于同一行第 0 列的位移
+    ///
+    /// 按列号直接相乘而不是逐列累加 `right`，累加的舍入误差在深度缩放时会超过像素间距。
+    pub fn column_offset(&self, column: usize) -> Complex<T> {
+        self.right * T::of(column as f64)
+    }
+
+    /// 从第 `start` 列开始的 `count` 列的 `column_offset`
+    pub fn column_offsets(&self, start: usize, count: usize) -> Vec<Complex<T>> {
+        (start..start + count)
+            .map(|column| self.column_offset(column))
+            .collect()
+    }
+
+    /// `point` 所在的像素位置（列、行），是 `point` 的逆变换，结果可以是小数或者落在图像外
+    pub fn locate(&self, point: Complex<T>) -> (T, T) {
+        let offset = point - self.origin;
+        let determinant = self.right.re * self.down.im - self.down.re * self.right.im;
+        (
+            (offset.re * self.down.im - self.down.re * offset.im) / determinant,
+            (self.right.re * offset.im - offset.re * self.right.im) / determinant,
+        )
+    }
+
+    /// 相邻像素的间距
+    pub fn spacing(&self) -> T {
+        self.right.norm()
+    }
+
+    /// 图像中的行关于实轴两两对称时返回 `k`：第 `row` 行与第 `k - row` 行的点互为共轭
+    ///
+    /// 只有不旋转、且实轴恰好落在某一行上或两行正中间的视图才成立。`k` 与整数相差不到
+    /// 百万分之一行即可，这样镜像的行与实际计算的点的差距远小于一个像素。
+    pub fn mirror_axis(&self) -> Option<usize> {
+        if self.right.im != T::zero() || self.down.re != T::zero() || self.down.im == T::zero() {
+            return None;
+        }
+        let k = (-(self.origin.im + self.origin.im) / self.down.im).f64();
+        let rounded = k.round();
+        (rounded >= 0.0 && (k - rounded).abs() < 1e-6).then_some(rounded as usize)
+    }
+}
+
+impl PixelTransform<f64> {
+    /// 转换为精度为 `T` 的变换，可能损失精度
+    pub fn narrow<T: Real>(self) -> PixelTransform<T> {
+        PixelTransform {
+            origin: narrow(self.origin),
+            right: narrow(self.right),
+            down: narrow(self.down),
+        }
+    }
+}
+
+#[test]
+fn test_pixel_transform() {
+    let bounds = (4, 2);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -1.0, im: 0.5 },
+        Complex { re: 1.0, im: -0.5 },
+    );
+    assert_eq!(transform.point((2, 1)), Complex { re: 0.0, im: 0.0 });
+    assert_eq!(transform.point((4, 2)), Complex { re: 1.0, im: -0.5 });
+    assert_eq!(transform.spacing(), 0.5);
+    assert_eq!(transform.rotated(bounds, 0.0), transform);
+
+    // 旋转 90 度后向右移动对应虚部增大，中心保持不动
+    let rotated = transform.rotated(bounds, 90.0);
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+    assert!(close(rotated.point((2, 1)), Complex { re: 0.0, im: 0.0 }));
+    assert!(close(rotated.point((4, 1)), Complex { re: 0.0, im: 1.0 }));
+    assert!(close(rotated.point((2, 0)), Complex { re: -0.5, im: 0.0 }));
+    assert!((rotated.spacing() - 0.5).abs() < 1e-12);
+    assert_eq!(transform.locate(Complex { re: 0.0, im: 0.0 }), (2.0, 1.0));
+    let (column, row) = rotated.locate(rotated.point((3, 1)));
+    assert!((column - 3.0).abs() < 1e-12 && (row - 1.0).abs() < 1e-12);
+
+    let subdivided = transform.subdivided(2);
+    assert_eq!(subdivided.point((4, 2)), transform.point((2, 1)));
+
+    let offsets = rotated.column_offsets(1, 3);
+    assert_eq!(rotated.row_start(1) + offsets[2], rotated.point((3, 1)));
+
+    // 第 1 行落在实轴上，第 0 行与第 2 行对称；旋转或平移后不再对称
+    assert_eq!(transform.mirror_axis(), Some(2));
+    assert_eq!(rotated.mirror_axis(), None);
+    let shifted = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -1.0, im: 0.7 },
+        Complex { re: 1.0, im: -0.3 },
+    );
+    assert_eq!(shifted.mirror_axis(), None);
+}
+
+/// 缩放倍数为 1 时，图像较短的一边在复平面中覆盖的长度
+pub const BASE_SPAN: f64 = 4.0;
+
+/// 缩放倍数为 `zoom` 时 `bounds` 大小的图像中相邻像素在复平面中的间距
+pub fn pixel_spacing(bounds: (usize, usize), zoom: f64) -> f64 {
+    BASE_SPAN / zoom / bounds.0.min(bounds.1) as f64
+}
+
+/// 根据视图中心 `center` 和缩放倍数 `zoom` 求出覆盖范围的左上角和右下角
+///
+/// 图像较短的一边在复平面中覆盖 `BASE_SPAN / zoom` 的长度，另一边按 `bounds`
+/// 给出的像素宽高比例延伸，因此每个像素都是正方形。
+pub fn corners_from_center(
+    bounds: (usize, usize),
+    center: Complex<f64>,
+    zoom: f64,
+) -> (Complex<f64>, Complex<f64>) {
+    let spacing = pixel_spacing(bounds, zoom);
+    let half_width = bounds.0 as f64 * spacing / 2.0;
+    let half_height = bounds.1 as f64 * spacing / 2.0;
+    (
+        Complex {
+            re: center.re - half_width,
+            im: center.im + half_height,
+        },
+        Complex {
+            re: center.re + half_width,
+            im: center.im - half_height,
+        },
+    )
+}
+
+#[test]
+fn test_corners_from_center() {
+    assert_eq!(
+        corners_from_center((200, 100), Complex { re: -0.5, im: 0.0 }, 1.0),
+        (Complex { re: -4.5, im: 2.0 }, Complex { re: 3.5, im: -2.0 })
+    );
+    assert_eq!(
+        corners_from_center((100, 100), Complex { re: 1.0, im: 1.0 }, 4.0),
+        (Complex { re: 0.5, im: 1.5 }, Complex { re: 1.5, im: 0.5 })
+    );
+}
+
+/// 把字符串 `s` 解析为缩放倍数，缩放倍数必须是正的有限值
+pub fn parse_zoom(s: &str) -> Option<f64> {
+    match s.parse::<f64>() {
+        Ok(zoom) if zoom.is_finite() && zoom > 0.0 => Some(zoom),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_zoom() {
+    assert_eq!(parse_zoom("1e6"), Some(1e6));
+    assert_eq!(parse_zoom("0"), None);
+    assert_eq!(parse_zoom("-2"), None);
+    assert_eq!(parse_zoom("inf"), None);
+}
+
+/// 把字符串 `s` 解析为以度为单位的角度，角度必须是有限值
+pub fn parse_degrees(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|degrees| degrees.is_finite())
+}
+
+#[test]
+fn test_parse_degrees() {
+    assert_eq!(parse_degrees("30"), Some(30.0));
+    assert_eq!(parse_degrees("-12.5"), Some(-12.5));
+    assert_eq!(parse_degrees("nan"), None);
+    assert_eq!(parse_degrees("east"), None);
+}
+
+/// 动画中第 `frame` 帧（共 `frames` 帧）的旋转角度，在 `start` 和 `end` 之间线性插值
+///
+/// 只有一帧时返回 `start`。
+pub fn rotation_at_frame(start: f64, end: f64, frame: usize, frames: usize) -> f64 {
+    if frames <= 1 {
+        return start;
+    }
+    start + (end - start) * frame as f64 / (frames - 1) as f64
+}
+
+#[test]
+fn test_rotation_at_frame() {
+    assert_eq!(rotation_at_frame(0.0, 90.0, 0, 4), 0.0);
+    assert_eq!(rotation_at_frame(0.0, 90.0, 1, 4), 30.0);
+    assert_eq!(rotation_at_frame(0.0, 90.0, 3, 4), 90.0);
+    assert_eq!(rotation_at_frame(45.0, 90.0, 0, 1), 45.0);
+}
+
+/// 动画中第 `frame` 帧（共 `frames` 帧）的缩放倍数
+///
+/// 缩放倍数在 `start` 和 `end` 之间按对数插值，即相邻两帧之间的放大比例恒定，
+/// 这样播放时看起来是匀速缩放的。只有一帧时返回 `start`。
+pub fn zoom_at_frame(start: f64, end: f64, frame: usize, frames: usize) -> f64 {
+    if frames <= 1 {
+        return start;
+    }
+    let t = frame as f64 / (frames - 1) as f64;
+    (start.ln() + (end.ln() - start.ln()) * t).exp()
+}
+
+#[test]
+fn test_zoom_at_frame() {
+    assert_eq!(zoom_at_frame(1.0, 1000.0, 0, 4), 1.0);
+    assert!((zoom_at_frame(1.0, 1000.0, 1, 4) - 10.0).abs() < 1e-9);
+    assert!((zoom_at_frame(1.0, 1000.0, 3, 4) - 1000.0).abs() < 1e-9);
+    assert_eq!(zoom_at_frame(5.0, 1000.0, 0, 1), 5.0);
+}
+
+#[test]
+fn test_escape_time() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(Fractal::Mandelbrot.escape_time(origin, 255), None);
+    assert_eq!(
+        Fractal::Mandelbrot.escape_time(Complex { re: 2.0, im: 2.0 }, 255),
+        Some(1)
+    );
+    // c = 0 时的朱利亚集是单位圆盘
+    let julia = Fractal::Julia(origin);
+    assert_eq!(julia.escape_time(Complex { re: 0.5, im: 0.0 }, 255), None);
+    assert_eq!(
+        julia.escape_time(Complex { re: 3.0, im: 0.0 }, 255),
+        Some(0)
+    );
+    // 燃烧船的"船身"位于 c 的虚部为负的一侧，与曼德博集不同
+    let c = Complex { re: -1.5, im: -0.1 };
+    assert_eq!(Fractal::BurningShip.escape_time(c, 255), None);
+    assert_eq!(Fractal::Mandelbrot.escape_time(c, 255), Some(7));
+    let c = Complex { re: -0.3, im: 0.5 };
+    assert_eq!(Fractal::BurningShip.escape_time(c, 255), Some(4));
+    assert_eq!(Fractal::Mandelbrot.escape_time(c, 255), None);
+    // 三角集关于实轴对称，但在共轭下与曼德博集不同
+    let c = Complex { re: -0.1, im: 0.8 };
+    assert_eq!(
+        Fractal::Tricorn.escape_time(c, 255),
+        Fractal::Tricorn.escape_time(c.conj(), 255)
+    );
+    assert_eq!(Fractal::Tricorn.escape_time(c, 255), Some(3));
+    assert_eq!(Fractal::Mandelbrot.escape_time(c, 255), None);
+
+    // 整数次数与实数次数的多重曼德博集在整数处一致
+    let c = Complex { re: 0.3, im: 0.6 };
+    assert_eq!(
+        Fractal::Multibrot(3.0).escape_time(c, 255),
+        Fractal::Multibrot(3.0 + 1e-12).escape_time(c, 255)
+    );
+}
+
+/// 迭代缓冲区中表示集合内部（达到迭代次数限制仍未逃逸）像素的值
+pub const INTERIOR: u32 = u32::MAX;
+
+/// 迭代缓冲区中渲染被中断、尚未计算的像素的值，解码时与 `INTERIOR` 一样为 `None`
+pub const UNFINISHED: u32 = INTERIOR - 1;
+
+/// 迭代缓冲区中逃逸值的小数位数
+///
+/// 缓冲区以定点数保存逃逸值：整数逃逸次数 `n` 保存为 `n << FRACTION_BITS`，
+/// 连续着色得到的小数部分保存在低位中
+pub const FRACTION_BITS: u32 = 8;
+
+/// 把逃逸值 `value` 编码为迭代缓冲区中的定点数，`None` 编码为 `INTERIOR`
+pub fn encode_escape(value: Option<f64>) -> u32 {
+    match value {
+        None => INTERIOR,
+        Some(value) => {
+            let scaled = value.max(0.0) * (1u32 << FRACTION_BITS) as f64;
+            scaled.min((UNFINISHED - 1) as f64) as u32
+        }
+    }
+}
+
+/// 把迭代缓冲区中的定点数 `encoded` 解码为逃逸值，`INTERIOR` 和 `UNFINISHED` 解码为 `None`
+pub fn decode_escape(encoded: u32) -> Option<f64> {
+    match encoded {
+        INTERIOR | UNFINISHED => None,
+        encoded => Some(encoded as f64 / (1u32 << FRACTION_BITS) as f64),
+    }
+}
+
+#[test]
+fn test_encode_escape() {
+    assert_eq!(encode_escape(None), INTERIOR);
+    assert_eq!(encode_escape(Some(3.0)), 3 << FRACTION_BITS);
+    assert_eq!(encode_escape(Some(-0.5)), 0);
+    assert_eq!(decode_escape(INTERIOR), None);
+    assert_eq!(decode_escape(encode_escape(Some(1000.5))), Some(1000.5));
+    assert!(encode_escape(Some(1e12)) < UNFINISHED);
+    assert_eq!(decode_escape(UNFINISHED), None);
+}
+
+/// 把字符串 `s` 解析为迭代次数限制，限制必须为正数
+pub fn parse_max_iter(s: &str) -> Option<usize> {
+    match s.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(limit) => Some(limit),
+    }
+}
+
+#[test]
+fn test_parse_max_iter() {
+    assert_eq!(parse_max_iter("1000"), Some(1000));
+    assert_eq!(parse_max_iter("0"), None);
+    assert_eq!(parse_max_iter("-5"), None);
+    assert_eq!(parse_max_iter("many"), None);
+}
+
+/// 将分形 `fractal` 对应的矩形渲染到迭代缓冲区中
+///
+/// `bounds` 参数会给缓冲区 `iterations` 的宽度和高度，此缓冲区的每个元素都
+/// 保存一个像素按 `encode_escape` 编码的逃逸值。`transform` 把缓冲区中的像素映射为
+/// 复平面中的点。每个点最多迭代 `limit` 次，`coloring` 决定保存整数逃逸次数还是
+/// 连续逃逸值；也可以传入实现了 `Colorizer` 的自定义着色方式。
+pub fn render<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: F,
+    colorizer: C,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    transform: PixelTransform<T>,
+) {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+
+    let spacing = transform.spacing();
+    let offsets = transform.column_offsets(0, bounds.0);
+    for (raw, row) in iterations.chunks_mut(bounds.0).enumerate() {
+        let start = transform.row_start(raw);
+        render_row(&fractal, &colorizer, limit, spacing, row, |column| {
+            start + offsets[column]
+        });
+    }
+}
+
+/// 把一行像素的逃逸值写入 `row`，`point` 给出第 `column` 个像素对应的点，
+/// `spacing` 是像素间距
+///
+/// 向量化的迭代支持 `f64` 和 `f32`。
+fn render_row<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: &F,
+    colorizer: &C,
+    limit: usize,
+    spacing: T,
+    row: &mut [u32],
+    point: impl Fn(usize) -> Complex<T>,
+) {
+    #[cfg(feature = "simd")]
+    if let Some(fractal) = fractal
+        .builtin()
+        .filter(|_| colorizer.is_escape_time() && simd::enabled())
+    {
+        use std::any::TypeId;
+        // T 就是对应的类型，转换不损失精度
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            simd::render_row(fractal, limit, row, |column| widen(point(column)));
+            return;
+        }
+        if TypeId::of::<T>() == TypeId::of::<f32>() {
+            simd::render_row_single(fractal, limit, row, |column| narrow(widen(point(column))));
+            return;
+        }
+    }
+
+    for (column, value) in row.iter_mut().enumerate() {
+        *value = encode_escape(colorizer.escape_value(fractal, point(column), limit, spacing));
+    }
+}
+
+/// 渲染图像中的一个分块，返回按行排列的 `tile.len()` 个逃逸值
+///
+/// 像素坐标仍按整幅图像计算，因此结果与 `render` 中对应位置的值逐位相同
+pub(crate) fn render_tile<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: &F,
+    colorizer: &C,
+    limit: usize,
+    tile: Tile,
+    transform: PixelTransform<T>,
+) -> Vec<u32> {
+    let spacing = transform.spacing();
+    let offsets = transform.column_offsets(tile.x, tile.width);
+    let mut values = vec![0; tile.len()];
+    for (y, row) in values.chunks_mut(tile.width).enumerate() {
+        let start = transform.row_start(tile.y + y);
+        render_row(fractal, colorizer, limit, spacing, row, |x| {
+            start + offsets[x]
+        });
+    }
+    values
+}
+
+/// 与 `render_tile` 相同，但使用 Mariani–Silver 矩形细分省去大片同值区域的计算
+///
+/// 曼德博集和朱利亚集都是连通的，如果一个矩形的边框上所有像素的逃逸值都相同，
+/// 就可以认为矩形内部也都是这个值而直接填充；否则把矩形分成四块递归处理。边框
+/// 上的像素由相邻的子矩形共享，只计算一次。对不连通的分形（如燃烧船）可能会
+/// 漏掉矩形内部的细节。
+fn render_tile_subdivided<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: &F,
+    colorizer: &C,
+    limit: usize,
+    tile: Tile,
+    transform: PixelTransform<T>,
+) -> Vec<u32> {
+    let spacing = transform.spacing();
+    let offsets = transform.column_offsets(tile.x, tile.width);
+    let starts: Vec<_> = (tile.y..tile.y + tile.height)
+        .map(|row| transform.row_start(row))
+        .collect();
+    let mut subdivision = Subdivision {
+        width: tile.width,
+        values: vec![0; tile.len()],
+        done: vec![false; tile.len()],
+        compute: |x: usize, y: usize| {
+            let point = starts[y] + offsets[x];
+            encode_escape(colorizer.escape_value(fractal, point, limit, spacing))
+        },
+    };
+    if !tile.is_empty() {
+        subdivision.fill((0, 0), (tile.width - 1, tile.height - 1));
+    }
+    subdivision.values
+}
+
+/// Mariani–Silver 细分的状态：`values` 中 `done` 为真的像素已经确定
+struct Subdivision<F> {
+    width: usize,
+    values: Vec<u32>,
+    done: Vec<bool>,
+    compute: F,
+}
+
+impl<F: Fn(usize, usize) -> u32> Subdivision<F> {
+    /// 返回像素 `(x, y)` 的逃逸值，尚未确定时先计算
+    fn value(&mut self, x: usize, y: usize) -> u32 {
+        let index = y * self.width + x;
+        if !self.done[index] {
+            self.values[index] = (self.compute)(x, y);
+            self.done[index] = true;
+        }
+        self.values[index]
+    }
+
+    /// 确定以 `low` 和 `high` 为对角（均包含在内）的矩形中的所有像素
+    fn fill(&mut self, low: (usize, usize), high: (usize, usize)) {
+        // 太小的矩形没有多少内部像素可省，直接逐个计算
+        if high.0 - low.0 < 3 || high.1 - low.1 < 3 {
+            for y in low.1..=high.1 {
+                for x in low.0..=high.0 {
+                    self.value(x, y);
+                }
+            }
+            return;
+        }
+
+        let first = self.value(low.0, low.1);
+        let mut uniform = true;
+        for x in low.0..=high.0 {
+            uniform &= self.value(x, low.1) == first && self.value(x, high.1) == first;
+        }
+        for y in low.1..=high.1 {
+            uniform &= self.value(low.0, y) == first && self.value(high.0, y) == first;
+        }
+        if uniform {
+            for y in low.1 + 1..high.1 {
+                for x in low.0 + 1..high.0 {
+                    let index = y * self.width + x;
+                    self.values[index] = first;
+                    self.done[index] = true;
+                }
+            }
+            return;
+        }
+
+        let middle = ((low.0 + high.0) / 2, (low.1 + high.1) / 2);
+        self.fill(low, middle);
+        self.fill((middle.0, low.1), (high.0, middle.1));
+        self.fill((low.0, middle.1), (middle.0, high.1));
+        self.fill(middle, high);
+    }
+}
+
+#[test]
+fn test_render_tile_subdivided() {
+    let bounds = (96, 64);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let tile = Tile {
+        x: 0,
+        y: 0,
+        width: bounds.0,
+        height: bounds.1,
+    };
+    for coloring in [Coloring::EscapeTime, Coloring::Smooth] {
+        let render = |subdivide: bool| {
+            let render_tile = if subdivide {
+                render_tile_subdivided
+            } else {
+                render_tile
+            };
+            render_tile(
+                &Fractal::Mandelbrot,
+                &coloring,
+                200,
+                tile,
+                PixelTransform::from_corners(bounds, upper_left, lower_right),
+            )
+        };
+        let (exact, subdivided) = (render(false), render(true));
+        let mismatches = exact
+            .iter()
+            .zip(&subdivided)
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(mismatches * 100 < exact.len(), "{} mismatches", mismatches);
+    }
+}
+
+/// 用调色板 `palette` 把迭代缓冲区 `iterations` 映射为 RGB 像素缓冲区 `pixels`
+///
+/// 逃逸值除以迭代次数限制 `limit` 后得到 `[0, 1]` 区间内的位置，`pixels` 的每三个
+/// 通道值包含一个 RGB 像素，通道可以是 8 位或 16 位。
+pub fn colorize<C: Channel>(iterations: &[u32], limit: usize, palette: &Palette, pixels: &mut [C]) {
+    assert_eq!(pixels.len(), iterations.len() * 3);
+
+    pixels
+        .par_chunks_mut(3)
+        .zip(iterations.par_iter())
+        .for_each(|(pixel, &encoded)| {
+            let rgb = match decode_escape(encoded) {
+                None => C::interior(palette),
+                Some(value) => C::color(palette, value / limit as f64),
+            };
+            pixel.copy_from_slice(&rgb);
+        });
+}
+
+#[test]
+fn test_colorize() {
+    let iterations = [0, 50 << FRACTION_BITS, 100 << FRACTION_BITS, INTERIOR];
+    let mut pixels = [1u8; 12];
+    colorize(&iterations, 100, &Palette::gray(), &mut pixels);
+    assert_eq!(pixels, [255, 255, 255, 128, 128, 128, 0, 0, 0, 0, 0, 0]);
+
+    let mut pixels = [1u16; 12];
+    colorize(&iterations, 100, &Palette::gray(), &mut pixels);
+    assert_eq!(pixels[..6], [65535, 65535, 65535, 32768, 32768, 32768]);
+}
+
+/// 与 `colorize` 相同，但先统计整幅图像中逃逸次数的分布，再按累积分布映射到调色板
+///
+/// 大片低迭代区域在线性映射下只会用到调色板开头的一小段，直方图均衡后每段调色板
+/// 覆盖的像素数大致相同。连续着色的小数部分在相邻两个整数次数的累积比例之间插值。
+pub fn colorize_histogram<C: Channel>(
+    iterations: &[u32],
+    limit: usize,
+    palette: &Palette,
+    pixels: &mut [C],
+) {
+    assert_eq!(pixels.len(), iterations.len() * 3);
+
+    // 第一遍：统计每个整数逃逸次数的像素数，并求出累积分布
+    let mut histogram = vec![0usize; limit + 1];
+    for value in iterations
+        .iter()
+        .filter_map(|&encoded| decode_escape(encoded))
+    {
+        histogram[(value as usize).min(limit)] += 1;
+    }
+    let total: usize = histogram.iter().sum();
+    let mut cumulative = Vec::with_capacity(limit + 2);
+    let mut sum = 0;
+    cumulative.push(0.0);
+    for count in &histogram {
+        sum += count;
+        cumulative.push(sum as f64 / total.max(1) as f64);
+    }
+
+    // 第二遍：按累积比例着色
+    pixels
+        .par_chunks_mut(3)
+        .zip(iterations.par_iter())
+        .for_each(|(pixel, &encoded)| {
+            let rgb = match decode_escape(encoded) {
+                None => C::interior(palette),
+                Some(value) => {
+                    let count = (value as usize).min(limit);
+                    let (low, high) = (cumulative[count], cumulative[count + 1]);
+                    C::color(palette, low + (high - low) * value.fract())
+                }
+            };
+            pixel.copy_from_slice(&rgb);
+        });
+}
+
+#[test]
+fn test_colorize_histogram() {
+    // 大多数像素在第 1 次逃逸，均衡后它们占据调色板的前 3/4
+    let iterations = [
+        1 << FRACTION_BITS,
+        1 << FRACTION_BITS,
+        1 << FRACTION_BITS,
+        90 << FRACTION_BITS,
+        INTERIOR,
+    ];
+    let mut pixels = [1u8; 15];
+    colorize_histogram(&iterations, 100, &Palette::gray(), &mut pixels);
+    assert_eq!(
+        pixels,
+        [255, 255, 255, 255, 255, 255, 255, 255, 255, 64, 64, 64, 0, 0, 0]
+    );
+}
+
+/// 把字符串 `s`（形如 `"3x3"`）解析为每个像素在每个方向上的采样数
+///
+/// 子像素必须是正方形，因此两个方向的采样数必须相同且为正
+pub fn parse_samples(s: &str) -> Option<usize> {
+    match parse_pair::<usize>(s, 'x') {
+        Some((columns, rows)) if columns == rows && columns > 0 => Some(columns),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_samples() {
+    assert_eq!(parse_samples("3x3"), Some(3));
+    assert_eq!(parse_samples("1x1"), Some(1));
+    assert_eq!(parse_samples("2x3"), None);
+    assert_eq!(parse_samples("0x0"), None);
+    assert_eq!(parse_samples("4"), None);
+}
+
+/// 把每个方向放大了 `factor` 倍的超采样 RGB 缓冲区 `samples` 盒式滤波缩小为 `bounds`
+/// 大小的像素缓冲区 `pixels`
+///
+/// 每个输出像素取对应的 `factor * factor` 个子像素的平均值，各行并行计算。
+pub fn downsample<C: Channel>(
+    samples: &[C],
+    factor: usize,
+    pixels: &mut [C],
+    bounds: (usize, usize),
+) {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    assert_eq!(samples.len(), pixels.len() * factor * factor);
+    let sample_width = bounds.0 * factor;
+
+    pixels
+        .par_chunks_mut(bounds.0 * 3)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, pixel) in band.chunks_mut(3).enumerate() {
+                let mut sum = [0usize; 3];
+                for sub_row in row * factor..(row + 1) * factor {
+                    let start = (sub_row * sample_width + column * factor) * 3;
+                    for sample in samples[start..start + factor * 3].chunks(3) {
+                        for (total, &channel) in sum.iter_mut().zip(sample) {
+                            *total += channel.value();
+                        }
+                    }
+                }
+                let count = factor * factor;
+                for (channel, total) in pixel.iter_mut().zip(sum) {
+                    *channel = C::from_value((total + count / 2) / count);
+                }
+            }
+        });
+}
+
+/// 未完成的区域中交替的两种灰度格子的边长（像素）
+const UNFINISHED_CHECKER: usize = 8;
+
+/// 把 `pixels` 中含有 `UNFINISHED` 子像素的像素涂成灰色的棋盘格
+///
+/// `iterations` 是每个方向放大了 `factor` 倍的超采样缓冲区，`pixels` 是
+/// `bounds` 大小的 RGB 缓冲区。
+pub fn mark_unfinished<C: Channel>(
+    iterations: &[u32],
+    factor: usize,
+    pixels: &mut [C],
+    bounds: (usize, usize),
+) {
+    assert_eq!(pixels.len(), bounds.0 * bounds.1 * 3);
+    assert_eq!(iterations.len(), bounds.0 * bounds.1 * factor * factor);
+    let sample_width = bounds.0 * factor;
+    for (index, pixel) in pixels.chunks_mut(3).enumerate() {
+        let (column, row) = (index % bounds.0, index / bounds.0);
+        let unfinished = (row * factor..(row + 1) * factor).any(|sub_row| {
+            let start = sub_row * sample_width + column * factor;
+            iterations[start..start + factor].contains(&UNFINISHED)
+        });
+        if unfinished {
+            let light = (column / UNFINISHED_CHECKER + row / UNFINISHED_CHECKER).is_multiple_of(2);
+            pixel.fill(C::from_fraction(if light { 0.6 } else { 0.4 }));
+        }
+    }
+}
+
+#[test]
+fn test_mark_unfinished() {
+    let mut iterations = vec![0; 4 * 2];
+    iterations[3] = UNFINISHED;
+    let mut pixels = [1u8; 2 * 3];
+    mark_unfinished(&iterations, 2, &mut pixels, (2, 1));
+    assert_eq!(pixels, [1, 1, 1, 153, 153, 153]);
+}
+
+#[test]
+fn test_downsample() {
+    // 2x2 的图像，每个像素由 2x2 个子像素组成
+    let mut samples = vec![0u8; 4 * 4 * 3];
+    for (index, sample) in samples.chunks_mut(3).enumerate() {
+        let (column, row) = (index % 4, index / 4);
+        let value = if column < 2 && row < 2 {
+            [200, 0, 0]
+        } else if column >= 2 && row < 2 && column == row + 2 {
+            [100, 100, 100]
+        } else {
+            [0, 0, 0]
+        };
+        sample.copy_from_slice(&value);
+    }
+    let mut pixels = [0; 12];
+    downsample(&samples, 2, &mut pixels, (2, 2));
+    assert_eq!(pixels, [200, 0, 0, 50, 50, 50, 0, 0, 0, 0, 0, 0]);
+}
+
+/// 输出图像的文件格式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    /// 有损压缩的 JPEG，参数是 1 到 100 之间的质量
+    Jpeg(u8),
+    /// 无损压缩的 WebP
+    WebP,
+    /// LZW 压缩的 TIFF
+    Tiff,
+    /// 二进制的 PPM（`P6`），参见 `uncompressed` 模块
+    Ppm,
+    /// 二进制的 PGM（`P5`），彩色像素换算为亮度
+    Pgm,
+    /// 24 位不压缩的 BMP
+    Bmp,
+}
+
+/// 没有给出质量时 JPEG 使用的质量
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+impl ImageFormat {
+    /// 按文件名的扩展名（不区分大小写）推断格式，无法识别时返回 `None`
+    pub fn from_filename(filename: &str) -> Option<ImageFormat> {
+        let extension = std::path::Path::new(filename).extension()?.to_str()?;
+        parse_image_format(&extension.to_ascii_lowercase())
+    }
+
+    /// 该格式的媒体类型，用于 HTTP 响应
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg(_) => "image/jpeg",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Ppm => "image/x-portable-pixmap",
+            ImageFormat::Pgm => "image/x-portable-graymap",
+            ImageFormat::Bmp => "image/bmp",
+        }
+    }
+
+    /// 该格式文件通常使用的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg(_) => "jpg",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Pgm => "pgm",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+
+    /// 该格式能否保存每个通道 16 位的图像
+    pub fn supports_16_bit(&self) -> bool {
+        matches!(
+            self,
+            ImageFormat::Png | ImageFormat::Tiff | ImageFormat::Ppm | ImageFormat::Pgm
+        )
+    }
+
+    /// 该格式能否保存带 alpha 通道的图像
+    pub fn supports_alpha(&self) -> bool {
+        matches!(
+            self,
+            ImageFormat::Png | ImageFormat::WebP | ImageFormat::Tiff
+        )
+    }
+
+    /// 是否为不压缩的简单格式，这些格式写出时不需要回退，可以直接写到标准输出
+    pub fn is_uncompressed(&self) -> bool {
+        matches!(self, ImageFormat::Ppm | ImageFormat::Pgm | ImageFormat::Bmp)
+    }
+
+    /// JPEG 格式时以 `quality` 为质量，其他格式不受影响
+    pub fn with_quality(self, quality: u8) -> ImageFormat {
+        match self {
+            ImageFormat::Jpeg(_) => ImageFormat::Jpeg(quality),
+            format => format,
+        }
+    }
+}
+
+/// 把字符串 `s`（形如 `"png"`、`"jpeg"`、`"jpg"`、`"webp"`、`"tiff"`、`"tif"`、`"ppm"`、`"pgm"`
+/// 或 `"bmp"`）解析成图像格式，JPEG 使用默认质量
+pub fn parse_image_format(s: &str) -> Option<ImageFormat> {
+    match s {
+        "png" => Some(ImageFormat::Png),
+        "jpeg" | "jpg" => Some(ImageFormat::Jpeg(DEFAULT_JPEG_QUALITY)),
+        "webp" => Some(ImageFormat::WebP),
+        "tiff" | "tif" => Some(ImageFormat::Tiff),
+        "ppm" => Some(ImageFormat::Ppm),
+        "pgm" => Some(ImageFormat::Pgm),
+        "bmp" => Some(ImageFormat::Bmp),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_image_format() {
+    assert_eq!(parse_image_format("png"), Some(ImageFormat::Png));
+    assert_eq!(
+        parse_image_format("jpg"),
+        Some(ImageFormat::Jpeg(DEFAULT_JPEG_QUALITY))
+    );
+    assert_eq!(parse_image_format("gif"), None);
+    assert_eq!(
+        ImageFormat::from_filename("out/zoom.WEBP"),
+        Some(ImageFormat::WebP)
+    );
+    assert_eq!(
+        ImageFormat::from_filename("mandel.jpeg").map(|format| format.with_quality(50)),
+        Some(ImageFormat::Jpeg(50))
+    );
+    assert_eq!(ImageFormat::from_filename("mandel"), None);
+    assert_eq!(ImageFormat::WebP.with_quality(50), ImageFormat::WebP);
+    assert_eq!(
+        ImageFormat::from_filename("deep.tif"),
+        Some(ImageFormat::Tiff)
+    );
+    assert!(ImageFormat::Tiff.supports_16_bit());
+    assert!(!ImageFormat::Jpeg(90).supports_16_bit());
+    assert!(ImageFormat::WebP.supports_alpha());
+    assert!(!ImageFormat::Jpeg(90).supports_alpha());
+    assert_eq!(
+        ImageFormat::from_filename("out.pgm"),
+        Some(ImageFormat::Pgm)
+    );
+    assert!(ImageFormat::Ppm.supports_16_bit() && !ImageFormat::Bmp.supports_16_bit());
+    assert!(!ImageFormat::Bmp.supports_alpha());
+    assert!(ImageFormat::Bmp.is_uncompressed() && !ImageFormat::Png.is_uncompressed());
+}
+
+/// 缓冲区 `pixels` 是否为 `bounds` 大小的 RGBA 图像（否则为 RGB 图像）
+fn has_alpha<C>(pixels: &[C], bounds: (usize, usize)) -> bool {
+    let channels = pixels.len() / (bounds.0 * bounds.1).max(1);
+    assert!(
+        channels == 3 || channels == 4,
+        "expected RGB or RGBA pixels"
+    );
+    channels == 4
+}
+
+/// 缓冲区带 alpha 通道而格式 `format` 不支持时的错误
+fn alpha_unsupported(format: ImageFormat) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "`{}` output cannot have an alpha channel",
+            format.extension()
+        ),
+    )
+}
+
+/// 把 RGB 或 RGBA `pixels` 缓冲区（其尺寸由 `bounds` 给出，按通道数区分）按 `format` 编码后
+/// 写入 `output`；JPEG、PPM、PGM 和 BMP 不支持 alpha 通道
+pub fn encode_image<W: Write + Seek>(
+    output: W,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    format: ImageFormat,
+) -> Result<(), std::io::Error> {
+    let (width, height) = (bounds.0 as u32, bounds.1 as u32);
+    let alpha = has_alpha(pixels, bounds);
+    match format {
+        ImageFormat::Png => {
+            let color = if alpha {
+                ColorType::RGBA(8)
+            } else {
+                ColorType::RGB(8)
+            };
+            PNGEncoder::new(output).encode(pixels, width, height, color)
+        }
+        ImageFormat::Jpeg(_) if alpha => Err(alpha_unsupported(format)),
+        ImageFormat::Jpeg(quality) => {
+            let mut output = output;
+            JPEGEncoder::new_with_quality(&mut output, quality).encode(
+                pixels,
+                width,
+                height,
+                ColorType::RGB(8),
+            )
+        }
+        ImageFormat::WebP => {
+            let color = if alpha {
+                image_webp::ColorType::Rgba8
+            } else {
+                image_webp::ColorType::Rgb8
+            };
+            image_webp::WebPEncoder::new(output)
+                .encode(pixels, width, height, color)
+                .map_err(std::io::Error::other)
+        }
+        ImageFormat::Tiff => {
+            let mut encoder = TiffEncoder::new(output)
+                .map_err(std::io::Error::other)?
+                .with_compression(Compression::Lzw);
+            if alpha {
+                encoder.write_image::<colortype::RGBA8>(width, height, pixels)
+            } else {
+                encoder.write_image::<colortype::RGB8>(width, height, pixels)
+            }
+            .map_err(std::io::Error::other)
+        }
+        ImageFormat::Ppm | ImageFormat::Pgm | ImageFormat::Bmp => {
+            uncompressed::encode(output, pixels, bounds, format)
+        }
+    }
+}
+
+#[test]
+fn test_encode_image() {
+    use image::DynamicImage;
+
+    let pixels: Vec<u8> = (0..4 * 3 * 3).map(|n| (n * 7) as u8).collect();
+    let encode = |format| {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        encode_image(&mut bytes, &pixels, (4, 3), format).unwrap();
+        bytes.into_inner()
+    };
+    assert!(encode(ImageFormat::Png).starts_with(b"\x89PNG"));
+    assert!(encode(ImageFormat::Jpeg(90)).starts_with(&[0xff, 0xd8]));
+    let webp = encode(ImageFormat::WebP);
+    assert_eq!((&webp[..4], &webp[8..12]), (&b"RIFF"[..], &b"WEBP"[..]));
+    assert!(encode(ImageFormat::Tiff).starts_with(b"II*\0"));
+    assert!(encode(ImageFormat::Ppm).starts_with(b"P6\n4 3\n255\n"));
+    assert!(encode(ImageFormat::Bmp).starts_with(b"BM"));
+
+    // 每像素 4 个通道时写出 RGBA 图像，PNG 的 IHDR 中颜色类型 6 为 RGBA
+    let rgba: Vec<u8> = (0..4 * 3 * 4).map(|n| (n * 5) as u8).collect();
+    let encode = |format| {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        encode_image(&mut bytes, &rgba, (4, 3), format).map(|_| bytes.into_inner())
+    };
+    assert_eq!(&encode(ImageFormat::Png).unwrap()[24..26], &[8, 6]);
+    let DynamicImage::ImageRgba8(decoded) =
+        image::load_from_memory(&encode(ImageFormat::Png).unwrap()).unwrap()
+    else {
+        panic!("expected an RGBA image");
+    };
+    assert_eq!(decoded.into_raw(), rgba);
+    assert!(encode(ImageFormat::WebP).is_ok());
+    assert!(encode(ImageFormat::Tiff).is_ok());
+    assert!(encode(ImageFormat::Jpeg(90)).is_err());
+    assert!(encode(ImageFormat::Ppm).is_err());
+}
+
+/// 代表标准输出的文件名
+pub const STDOUT: &str = "-";
+
+/// 把 RGB 或 RGBA `pixels` 缓冲区（其尺寸由 `bounds` 给出）按 `format` 写入名为 `filename` 的
+/// 文件中，参见 `encode_image`；`filename` 为 `STDOUT` 时写到标准输出
+pub fn write_image(
+    filename: &str,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    format: ImageFormat,
+) -> Result<(), std::io::Error> {
+    if filename == STDOUT {
+        return write_stdout(pixels, bounds, format, |output| {
+            encode_image(output, pixels, bounds, format)
+        });
+    }
+    let mut output = BufWriter::new(File::create(filename)?);
+    encode_image(&mut output, pixels, bounds, format)?;
+    output.flush()
+}
+
+/// 把 `pixels` 按 `format` 写到标准输出
+///
+/// 标准输出不能回退，不压缩的格式逐行直接写出，其他格式先用 `encode` 编码到内存中再整体写出。
+fn write_stdout<C: Channel>(
+    pixels: &[C],
+    bounds: (usize, usize),
+    format: ImageFormat,
+    encode: impl FnOnce(&mut Cursor<Vec<u8>>) -> Result<(), std::io::Error>,
+) -> Result<(), std::io::Error> {
+    let mut output = BufWriter::new(std::io::stdout().lock());
+    if format.is_uncompressed() {
+        uncompressed::encode(&mut output, pixels, bounds, format)?;
+    } else {
+        let mut bytes = Cursor::new(Vec::new());
+        encode(&mut bytes)?;
+        output.write_all(bytes.get_ref())?;
+    }
+    output.flush()
+}
+
+/// 把每个通道 16 位的 RGB 或 RGBA `pixels` 缓冲区按 `format` 编码后写入 `output`
+///
+/// 只支持 PNG、TIFF、PPM 和 PGM。没有 alpha 通道且三个通道处处相等时（例如使用灰度调色板）写为单通道
+/// 的灰度图像。
+pub fn encode_image16<W: Write + Seek>(
+    output: W,
+    pixels: &[u16],
+    bounds: (usize, usize),
+    format: ImageFormat,
+) -> Result<(), std::io::Error> {
+    let (width, height) = (bounds.0 as u32, bounds.1 as u32);
+    let alpha = has_alpha(pixels, bounds);
+    let gray: Option<Vec<u16>> = if alpha {
+        None
+    } else {
+        pixels
+            .chunks(3)
+            .map(|rgb| (rgb[0] == rgb[1] && rgb[1] == rgb[2]).then_some(rgb[0]))
+            .collect()
+    };
+    match format {
+        ImageFormat::Png => {
+            // PNG 的 16 位采样按大端序存储
+            let (samples, color) = match &gray {
+                Some(gray) => (&gray[..], ColorType::Gray(16)),
+                None if alpha => (pixels, ColorType::RGBA(16)),
+                None => (pixels, ColorType::RGB(16)),
+            };
+            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+            PNGEncoder::new(output).encode(&bytes, width, height, color)
+        }
+        ImageFormat::Tiff => {
+            let mut encoder = TiffEncoder::new(output)
+                .map_err(std::io::Error::other)?
+                .with_compression(Compression::Lzw);
+            match &gray {
+                Some(gray) => encoder.write_image::<colortype::Gray16>(width, height, gray),
+                None if alpha => encoder.write_image::<colortype::RGBA16>(width, height, pixels),
+                None => encoder.write_image::<colortype::RGB16>(width, height, pixels),
+            }
+            .map_err(std::io::Error::other)
+        }
+        ImageFormat::Ppm | ImageFormat::Pgm => uncompressed::encode(output, pixels, bounds, format),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "16-bit output is only supported for PNG, TIFF, PPM, and PGM",
+        )),
+    }
+}
+
+#[test]
+fn test_encode_image16() {
+    let encode = |pixels: &[u16], format| {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        encode_image16(&mut bytes, pixels, (2, 1), format).map(|_| bytes.into_inner())
+    };
+    let rgb = [0, 1, 2, 65535, 32768, 0];
+    let gray = [7, 7, 7, 300, 300, 300];
+    for pixels in [&rgb, &gray] {
+        assert!(encode(pixels, ImageFormat::Png)
+            .unwrap()
+            .starts_with(b"\x89PNG"));
+        assert!(encode(pixels, ImageFormat::Tiff)
+            .unwrap()
+            .starts_with(b"II*\0"));
+    }
+    // IHDR 中位深度之后的字节是颜色类型：2 为 RGB，0 为灰度
+    assert_eq!(&encode(&rgb, ImageFormat::Png).unwrap()[24..26], &[16, 2]);
+    assert_eq!(&encode(&gray, ImageFormat::Png).unwrap()[24..26], &[16, 0]);
+    // 带 alpha 通道时即使是灰色也写为 RGBA
+    let rgba = [7, 7, 7, 0, 300, 300, 300, 65535];
+    assert_eq!(&encode(&rgba, ImageFormat::Png).unwrap()[24..26], &[16, 6]);
+    assert!(encode(&rgba, ImageFormat::Tiff).is_ok());
+    assert!(encode(&rgb, ImageFormat::WebP).is_err());
+    assert!(encode(&rgb, ImageFormat::Ppm)
+        .unwrap()
+        .starts_with(b"P6\n2 1\n65535\n"));
+}
+
+/// 把每个通道 16 位的 RGB 或 RGBA `pixels` 缓冲区按 `format` 写入名为 `filename` 的文件中，
+/// 参见 `encode_image16`；`filename` 为 `STDOUT` 时写到标准输出
+pub fn write_image16(
+    filename: &str,
+    pixels: &[u16],
+    bounds: (usize, usize),
+    format: ImageFormat,
+) -> Result<(), std::io::Error> {
+    if filename == STDOUT {
+        return write_stdout(pixels, bounds, format, |output| {
+            encode_image16(output, pixels, bounds, format)
+        });
+    }
+    let mut output = BufWriter::new(File::create(filename)?);
+    encode_image16(&mut output, pixels, bounds, format)?;
+    output.flush()
+}
+
+/// 使用 rayon 的窃取式并行把分形渲染到整个迭代缓冲区中
+///
+/// 图像按 `tile` 给出的像素尺寸划分为分块，每个分块作为一个任务交给 rayon 调度，
+/// 其余参数含义与 `render` 相同。`subdivide` 为真时每个分块内部使用 Mariani–Silver
+/// 矩形细分（参见 `render_tile_subdivided`）。每完成一个分块就在 `progress` 上记录一次。
+///
+/// 分形和着色方式都关于实轴对称、视图不旋转且横跨实轴时（参见 `PixelTransform::mirror_axis`），
+/// 整块落在实轴下方的分块不渲染，直接从上方对称的行镜像过来，这些分块不计入进度。
+///
+/// 与逐行并行、crossbeam 分带并行等其他方式的性能对比见 `bench` 模块。
+#[allow(clippy::too_many_arguments)]
+pub fn render_parallel<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: F,
+    colorizer: C,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    transform: PixelTransform<T>,
+    tile: (usize, usize),
+    subdivide: bool,
+    progress: &Progress,
+) {
+    render_parallel_checkpointed(
+        fractal, colorizer, limit, iterations, bounds, transform, tile, subdivide, progress, None,
+    );
+}
+
+/// 为真时并行渲染不再开始新的分块
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// 让进行中的 `render_parallel` 不再开始新的分块，用于响应 Ctrl-C
+///
+/// 已经开始的分块照常完成，之后的分块都填充为 `UNFINISHED`。
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// 是否已经调用过 `cancel`
+pub fn cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// 与 `render_parallel` 相同，但给出 `checkpoint` 时跳过其中已完成的分块，
+/// 并把新完成的分块追加到检查点中
+#[allow(clippy::too_many_arguments)]
+pub fn render_parallel_checkpointed<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: F,
+    colorizer: C,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    transform: PixelTransform<T>,
+    tile: (usize, usize),
+    subdivide: bool,
+    progress: &Progress,
+    checkpoint: Option<&Checkpoint>,
+) {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    let axis = mirror_axis(&fractal, &colorizer, transform);
+    // 整块都在实轴下方、且镜像的行都在图像内的分块不渲染，最后从上方的行镜像过来
+    let (mirrored, tiles): (Vec<Tile>, Vec<Tile>) = tile::tiles(bounds, tile)
+        .into_iter()
+        .partition(|tile| axis.is_some_and(|k| 2 * tile.y > k && tile.y + tile.height <= k + 1));
+    let render_tile = if subdivide {
+        render_tile_subdivided::<T, F, C>
+    } else {
+        render_tile::<T, F, C>
+    };
+    progress.start(tiles.len(), "tiles");
+
+    // rayon 二维分块：分块各自渲染到独立的缓冲区，再拷贝回整幅图像
+    let rendered: Vec<(Tile, Vec<u32>)> = tiles
+        .into_par_iter()
+        .map(|tile| {
+            if let Some(values) = checkpoint.and_then(|checkpoint| checkpoint.completed(tile)) {
+                progress.inc(1);
+                return (tile, values.to_vec());
+            }
+            if cancelled() {
+                return (tile, vec![UNFINISHED; tile.len()]);
+            }
+            let values =
+                progress.timed(|| render_tile(&fractal, &colorizer, limit, tile, transform));
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.save(tile, &values);
+            }
+            progress.inc(1);
+            (tile, values)
+        })
+        .collect();
+    for (tile, values) in rendered {
+        for (y, row) in values.chunks(tile.width).enumerate() {
+            let start = (tile.y + y) * bounds.0 + tile.x;
+            iterations[start..start + tile.width].copy_from_slice(row);
+        }
+    }
+    // 镜像的行 k - row 在实轴上方，总是属于渲染过的分块
+    if let Some(k) = axis {
+        for tile in mirrored {
+            for row in tile.y..tile.y + tile.height {
+                let source = (k - row) * bounds.0 + tile.x;
+                iterations.copy_within(source..source + tile.width, row * bounds.0 + tile.x);
+            }
+        }
+    }
+    progress.finish();
+}
+
+/// 能否只渲染实轴上方的一半再镜像：分形和着色方式都关于实轴对称、视图的行关于实轴对称时
+/// 返回 `PixelTransform::mirror_axis`
+///
+/// 用户自定义的点求值器无法判断，总是完整渲染；`set_symmetry` 关闭镜像时同样完整渲染。
+fn mirror_axis<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: &F,
+    colorizer: &C,
+    transform: PixelTransform<T>,
+) -> Option<usize> {
+    let symmetric = fractal
+        .builtin()
+        .is_some_and(|fractal| fractal.is_symmetric());
+    if !symmetric || !colorizer.is_symmetric() || !SYMMETRY.load(Ordering::Relaxed) {
+        return None;
+    }
+    transform.mirror_axis()
+}
+
+#[test]
+fn test_render_parallel_symmetric() {
+    // 第 0 行在虚部 1 处，第 31 行在虚部 -1 处，第 row 行与第 31 - row 行对称
+    let bounds = (40, 31);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 1.0, im: -1.0 },
+    );
+    assert_eq!(transform.mirror_axis(), Some(31));
+    let mut serial = vec![0; bounds.0 * bounds.1];
+    let mut parallel = vec![0; bounds.0 * bounds.1];
+    for fractal in [Fractal::Mandelbrot, Fractal::BurningShip] {
+        render(
+            fractal,
+            Coloring::EscapeTime,
+            200,
+            &mut serial,
+            bounds,
+            transform,
+        );
+        render_parallel(
+            fractal,
+            Coloring::EscapeTime,
+            200,
+            &mut parallel,
+            bounds,
+            transform,
+            (8, 8),
+            false,
+            &Progress::hidden(),
+        );
+        if fractal.is_symmetric() {
+            // 镜像的行与实际计算的结果只在个别边界像素上因舍入不同
+            for row in 16..bounds.1 {
+                let mirror = 31 - row;
+                assert_eq!(
+                    parallel[row * bounds.0..(row + 1) * bounds.0],
+                    parallel[mirror * bounds.0..(mirror + 1) * bounds.0]
+                );
+            }
+            let differing = serial.iter().zip(&parallel).filter(|(a, b)| a != b).count();
+            assert!(differing <= 4, "{} pixels differ", differing);
+        } else {
+            assert_eq!(serial, parallel);
+        }
+    }
+}
+
+#[test]
+fn test_render_parallel() {
+    let bounds = (40, 30);
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let mut serial = vec![0; bounds.0 * bounds.1];
+    let mut parallel = vec![0; bounds.0 * bounds.1];
+    let transform =
+        PixelTransform::from_corners(bounds, upper_left, lower_right).rotated(bounds, 30.0);
+    for coloring in [Coloring::EscapeTime, Coloring::Smooth] {
+        render(
+            Fractal::Mandelbrot,
+            coloring,
+            1000,
+            &mut serial,
+            bounds,
+            transform,
+        );
+        render_parallel(
+            Fractal::Mandelbrot,
+            coloring,
+            1000,
+            &mut parallel,
+            bounds,
+            transform,
+            (16, 16),
+            false,
+            &Progress::hidden(),
+        );
+        assert_eq!(serial, parallel);
+    }
+}
+
+/// 与 `render_parallel` 相同，但由 GPU 和 rayon 线程池一起渲染
+///
+/// 分块放在一个共享的队列中，一个单独的线程不断取出分块交给 `device` 渲染，rayon 的每个
+/// 线程也从同一个队列中取分块，谁空闲谁就取下一块，因此快的一方自然多做，不需要事先估计
+/// 两边的速度。`device` 与 `render_tile` 一样返回按行排列的编码后的逃逸值，出错时 GPU
+/// 线程停止，剩下的分块仍由 CPU 渲染完，最后返回该错误。成功时返回由 `device` 渲染的分块数。
+#[allow(clippy::too_many_arguments)]
+pub fn render_hybrid<T: Real, F: PointEvaluator, C: Colorizer>(
+    fractal: F,
+    colorizer: C,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    transform: PixelTransform<T>,
+    tile: (usize, usize),
+    subdivide: bool,
+    progress: &Progress,
+    mut device: impl FnMut(Tile) -> Result<Vec<u32>, String> + Send,
+) -> Result<usize, String> {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    let tiles = tile::tiles(bounds, tile);
+    let render_tile = if subdivide {
+        render_tile_subdivided::<T, F, C>
+    } else {
+        render_tile::<T, F, C>
+    };
+    progress.start(tiles.len(), "tiles");
+
+    let next = AtomicUsize::new(0);
+    let take = || tiles.get(next.fetch_add(1, Ordering::Relaxed)).copied();
+    let (on_device, on_cpu) = std::thread::scope(|scope| {
+        let take = &take;
+        let gpu = scope.spawn(move || {
+            let mut rendered = Vec::new();
+            while let Some(tile) = take() {
+                let values = if cancelled() {
+                    vec![UNFINISHED; tile.len()]
+                } else {
+                    match device(tile) {
+                        Ok(values) => values,
+                        Err(err) => return (rendered, Some((tile, err))),
+                    }
+                };
+                progress.inc(1);
+                rendered.push((tile, values));
+            }
+            (rendered, None)
+        });
+        let on_cpu: Vec<(Tile, Vec<u32>)> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .flat_map_iter(|_| {
+                let mut rendered = Vec::new();
+                while let Some(tile) = take() {
+                    let values = if cancelled() {
+                        vec![UNFINISHED; tile.len()]
+                    } else {
+                        progress.timed(|| render_tile(&fractal, &colorizer, limit, tile, transform))
+                    };
+                    progress.inc(1);
+                    rendered.push((tile, values));
+                }
+                rendered
+            })
+            .collect();
+        (gpu.join().expect("the GPU thread panicked"), on_cpu)
+    });
+    let (on_device, failed) = on_device;
+    let count = on_device.len();
+    let mut rendered = on_device;
+    rendered.extend(on_cpu);
+    if let Some((tile, _)) = failed {
+        // GPU 出错时取走的分块还没有渲染
+        let values = render_tile(&fractal, &colorizer, limit, tile, transform);
+        progress.inc(1);
+        rendered.push((tile, values));
+    }
+    for (tile, values) in rendered {
+        for (y, row) in values.chunks(tile.width).enumerate() {
+            let start = (tile.y + y) * bounds.0 + tile.x;
+            iterations[start..start + tile.width].copy_from_slice(row);
+        }
+    }
+    progress.finish();
+    match failed {
+        Some((_, err)) => Err(err),
+        None => Ok(count),
+    }
+}
+
+#[test]
+fn test_render_hybrid() {
+    let bounds = (40, 30);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.0, im: 1.0 },
+        Complex { re: 1.0, im: -1.0 },
+    );
+    let mut parallel = vec![0; bounds.0 * bounds.1];
+    let mut hybrid = vec![0; bounds.0 * bounds.1];
+    let render = |iterations: &mut [u32], failing: bool| {
+        // 用 CPU 模拟的设备，`failing` 时第一块就出错
+        let device = |tile| {
+            if failing {
+                return Err("device lost".to_string());
+            }
+            Ok(render_tile(
+                &Fractal::Mandelbrot,
+                &Coloring::Smooth,
+                500,
+                tile,
+                transform,
+            ))
+        };
+        render_hybrid(
+            Fractal::Mandelbrot,
+            Coloring::Smooth,
+            500,
+            iterations,
+            bounds,
+            transform,
+            (8, 8),
+            false,
+            &Progress::hidden(),
+            device,
+        )
+    };
+    render_parallel(
+        Fractal::Mandelbrot,
+        Coloring::Smooth,
+        500,
+        &mut parallel,
+        bounds,
+        transform,
+        (8, 8),
+        false,
+        &Progress::hidden(),
+    );
+    let on_device = render(&mut hybrid, false).unwrap();
+    assert!(on_device <= tile::tiles(bounds, (8, 8)).len());
+    assert_eq!(hybrid, parallel);
+    hybrid.fill(0);
+    // CPU 线程可能在设备取到分块之前就取完了所有分块
+    match render(&mut hybrid, true) {
+        Ok(on_device) => assert_eq!(on_device, 0),
+        Err(err) => assert_eq!(err, "device lost"),
+    }
+    assert_eq!(hybrid, parallel);
+}
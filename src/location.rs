@@ -0,0 +1,226 @@
+//! 著名位置和用户书签
+//!
+//! `--location NAME` 可以直接使用内置的著名位置，也可以使用用户自己保存的书签。
+//! 书签保存在 TOML 文件中，每个书签一个表，同名时用户书签优先于内置位置：
+//!
+//! ```toml
+//! [my-spiral]
+//! center = "-0.77568377,0.13646737"
+//! zoom = 1000000.0
+//! description = "a double spiral"
+//! ```
+
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// 复平面中一个命名的视图
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub name: String,
+    /// 视图中心，以字符串保存以便深度缩放时按任意精度解析
+    pub center: String,
+    /// 默认的缩放倍数，可以用 `--zoom` 覆盖
+    pub zoom: f64,
+    pub description: String,
+}
+
+/// 内置的著名位置：名称、中心、缩放倍数和说明
+const BUILTIN: &[(&str, &str, f64, &str)] = &[
+    ("full", "-0.5,0", 1.0, "the whole Mandelbrot set"),
+    (
+        "seahorse",
+        "-0.743643887037151,0.131825904205330",
+        200.0,
+        "Seahorse Valley between the main cardioid and the period-2 bulb",
+    ),
+    (
+        "elephant",
+        "0.285,0.01",
+        30.0,
+        "Elephant Valley at the cusp of the main cardioid",
+    ),
+    (
+        "triple-spiral",
+        "-0.088,0.654",
+        40.0,
+        "Triple Spiral Valley next to the period-3 bulb",
+    ),
+    (
+        "scepter",
+        "-1.36,0",
+        12.0,
+        "Scepter Valley between the period-2 bulb and the period-4 bulb",
+    ),
+    (
+        "minibrot",
+        "-1.754877666246693,0",
+        60.0,
+        "the period-3 minibrot on the negative real axis near -1.75",
+    ),
+    (
+        "antenna-tip",
+        "-2,0",
+        100.0,
+        "the Misiurewicz point c = -2 at the tip of the antenna",
+    ),
+    ("misiurewicz-i", "0,1", 30.0, "the Misiurewicz point c = i"),
+    (
+        "misiurewicz-3-1",
+        "-0.101096363845622,0.956286510809142",
+        30.0,
+        "the Misiurewicz point M(3,1) at the top of the period-3 bulb's antenna",
+    ),
+];
+
+/// 所有内置位置
+pub fn builtin() -> Vec<Location> {
+    BUILTIN
+        .iter()
+        .map(|&(name, center, zoom, description)| Location {
+            name: name.to_string(),
+            center: center.to_string(),
+            zoom,
+            description: description.to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_builtin() {
+    for location in builtin() {
+        assert!(
+            crate::parse_complex(&location.center).is_some(),
+            "{}",
+            location.name
+        );
+        assert!(location.zoom >= 1.0);
+    }
+}
+
+/// 用户书签文件的路径：环境变量 `MANDELBROT_BOOKMARKS`，否则为
+/// `$HOME/.mandelbrot/bookmarks.toml`
+pub fn bookmarks_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("MANDELBROT_BOOKMARKS") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".mandelbrot/bookmarks.toml"))
+}
+
+/// 解析书签文件的内容
+pub fn parse_bookmarks(text: &str) -> Result<Vec<Location>, String> {
+    let table: Table = text.parse().map_err(|err| format!("{}", err))?;
+    table
+        .iter()
+        .map(|(name, value)| {
+            let invalid = || format!("invalid bookmark `{}`", name);
+            let entry = value.as_table().ok_or_else(invalid)?;
+            let center = entry
+                .get("center")
+                .and_then(Value::as_str)
+                .ok_or_else(invalid)?;
+            crate::parse_complex(center).ok_or_else(invalid)?;
+            let zoom = match entry.get("zoom") {
+                None => 1.0,
+                Some(Value::Float(zoom)) => *zoom,
+                Some(Value::Integer(zoom)) => *zoom as f64,
+                Some(_) => return Err(invalid()),
+            };
+            let description = entry.get("description").and_then(Value::as_str);
+            Ok(Location {
+                name: name.clone(),
+                center: center.to_string(),
+                zoom,
+                description: description.unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
+/// 把书签格式化为书签文件的内容
+pub fn format_bookmarks(bookmarks: &[Location]) -> String {
+    let mut table = Table::new();
+    for location in bookmarks {
+        let mut entry = Table::new();
+        entry.insert("center".to_string(), Value::from(location.center.as_str()));
+        entry.insert("zoom".to_string(), Value::from(location.zoom));
+        if !location.description.is_empty() {
+            entry.insert(
+                "description".to_string(),
+                Value::from(location.description.as_str()),
+            );
+        }
+        table.insert(location.name.clone(), Value::Table(entry));
+    }
+    table.to_string()
+}
+
+#[test]
+fn test_parse_bookmarks() {
+    let bookmarks = vec![
+        Location {
+            name: "spiral".to_string(),
+            center: "-0.77568377,0.13646737".to_string(),
+            zoom: 1e6,
+            description: "a double spiral".to_string(),
+        },
+        Location {
+            name: "tip".to_string(),
+            center: "-2,0".to_string(),
+            zoom: 1.0,
+            description: String::new(),
+        },
+    ];
+    assert_eq!(
+        parse_bookmarks(&format_bookmarks(&bookmarks)).unwrap(),
+        bookmarks
+    );
+    assert_eq!(
+        parse_bookmarks("[a]\ncenter = \"1,2\"\nzoom = 5").unwrap()[0].zoom,
+        5.0
+    );
+    assert!(parse_bookmarks("[a]\nzoom = 5").is_err());
+    assert!(parse_bookmarks("[a]\ncenter = \"oops\"").is_err());
+    assert!(parse_bookmarks("a = 1").is_err());
+}
+
+/// 读取书签文件，文件不存在时返回空列表
+pub fn load_bookmarks(path: &Path) -> io::Result<Vec<Location>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            parse_bookmarks(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// 把书签写入文件，必要时创建所在的目录
+pub fn save_bookmarks(path: &Path, bookmarks: &[Location]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, format_bookmarks(bookmarks))
+}
+
+/// 按名称查找位置，用户书签 `bookmarks` 优先于内置位置
+pub fn find(name: &str, bookmarks: &[Location]) -> Option<Location> {
+    bookmarks
+        .iter()
+        .cloned()
+        .chain(builtin())
+        .find(|location| location.name == name)
+}
+
+#[test]
+fn test_find() {
+    let mine = Location {
+        name: "seahorse".to_string(),
+        center: "-0.75,0.1".to_string(),
+        zoom: 10.0,
+        description: String::new(),
+    };
+    assert_eq!(find("seahorse", &[]).unwrap().zoom, 200.0);
+    assert_eq!(find("seahorse", std::slice::from_ref(&mine)), Some(mine));
+    assert_eq!(find("nowhere", &[]), None);
+}
@@ -0,0 +1,169 @@
+//! Lyapunov 分形
+//!
+//! 对逻辑斯谛映射 `x = r x (1 - x)` 按序列（如 `AB`）轮流取 `r = a` 和 `r = b` 迭代，
+//! 序列循环使用。每个像素对应平面上的一点 `(a, b)`，横轴为 `a`，纵轴为 `b`。迭代的
+//! Lyapunov 指数 `λ = lim (1/N) Σ ln |r (1 - 2x)|` 衡量轨道的稳定性：`λ < 0` 时轨道
+//! 收敛到周期轨道，`λ > 0` 时轨道混沌。两种像素分别用各自的渐变着色，`λ` 越接近零
+//! 越暗，稳定区域和混沌区域之间的边界就是分形。
+
+use crate::palette::{Channel, Palette};
+use crate::pixed_to_point;
+use crate::progress::Progress;
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use std::f64::consts::LN_2;
+
+/// 逻辑斯谛映射的初值
+const START: f64 = 0.5;
+
+/// 由 `A` 和 `B` 组成的序列，决定每一步使用 `a` 还是 `b`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence {
+    /// 每一步是否使用 `b`
+    steps: Vec<bool>,
+}
+
+impl Sequence {
+    /// 第 `n` 步（从 0 开始）使用的参数
+    fn rate(&self, n: usize, a: f64, b: f64) -> f64 {
+        if self.steps[n % self.steps.len()] {
+            b
+        } else {
+            a
+        }
+    }
+}
+
+/// 把形如 `AB` 或 `aabab` 的字符串解析为序列，只能由 `A` 和 `B`（不区分大小写）组成
+pub fn parse_sequence(s: &str) -> Option<Sequence> {
+    let steps = s
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'A' => Some(false),
+            'B' => Some(true),
+            _ => None,
+        })
+        .collect::<Option<Vec<bool>>>()?;
+    (!steps.is_empty()).then_some(Sequence { steps })
+}
+
+#[test]
+fn test_parse_sequence() {
+    assert_eq!(
+        parse_sequence("AaBAB"),
+        Some(Sequence {
+            steps: vec![false, false, true, false, true]
+        })
+    );
+    assert_eq!(parse_sequence("A"), Some(Sequence { steps: vec![false] }));
+    assert_eq!(parse_sequence(""), None);
+    assert_eq!(parse_sequence("ABC"), None);
+    assert_eq!(parse_sequence("A B"), None);
+}
+
+/// 点 `(a, b)` 处的 Lyapunov 指数
+///
+/// 先迭代 `warmup` 次让轨道进入稳定状态，再用接下来的 `iterations` 次迭代求平均。
+/// `a` 或 `b` 不在 `[0, 4]` 区间内时轨道会离开 `[0, 1]`，指数没有意义，返回 `None`。
+/// 导数恰好为零时指数为负无穷。
+pub fn exponent(
+    sequence: &Sequence,
+    a: f64,
+    b: f64,
+    warmup: usize,
+    iterations: usize,
+) -> Option<f64> {
+    if !(0.0..=4.0).contains(&a) || !(0.0..=4.0).contains(&b) {
+        return None;
+    }
+    let mut x = START;
+    for n in 0..warmup {
+        let r = sequence.rate(n, a, b);
+        x = r * x * (1.0 - x);
+    }
+    let mut sum = 0.0;
+    for n in warmup..warmup + iterations {
+        let r = sequence.rate(n, a, b);
+        sum += (r * (1.0 - 2.0 * x)).abs().ln();
+        x = r * x * (1.0 - x);
+    }
+    Some(sum / iterations as f64)
+}
+
+#[test]
+fn test_exponent() {
+    let a = parse_sequence("A").unwrap();
+    // r = 3.9 时映射是混沌的，指数约为 0.49
+    let chaotic = exponent(&a, 3.9, 0.0, 100, 100_000).unwrap();
+    assert!((chaotic - 0.49).abs() < 0.02, "{}", chaotic);
+    // r = 2.5 时收敛到不动点 0.6，导数为 r (1 - 2x) = -0.5
+    let stable = exponent(&a, 2.5, 0.0, 100, 100).unwrap();
+    assert!((stable - 0.5f64.ln()).abs() < 1e-9, "{}", stable);
+    // 只用 a 的序列与 b 无关
+    assert_eq!(
+        exponent(&a, 3.2, 1.0, 10, 10),
+        exponent(&a, 3.2, 3.9, 10, 10)
+    );
+    assert_eq!(exponent(&a, 4.5, 3.0, 10, 10), None);
+    assert_eq!(exponent(&a, 3.0, -0.5, 10, 10), None);
+}
+
+/// 像素的颜色：`λ < 0` 时在 `stable` 中取位置 `1 - e^λ` 处的颜色，`λ > 0` 时在 `chaotic`
+/// 中取位置 `λ / ln 2` 处的颜色（单个参数的逻辑斯谛映射的指数最大为 `ln 2`）
+pub fn exponent_color<C: Channel>(exponent: f64, stable: &Palette, chaotic: &Palette) -> [C; 3] {
+    if exponent < 0.0 {
+        C::color(stable, 1.0 - exponent.exp())
+    } else {
+        C::color(chaotic, exponent / LN_2)
+    }
+}
+
+#[test]
+fn test_exponent_color() {
+    let stable = Palette::new(vec![(0.0, [0, 0, 0]), (1.0, [255, 200, 0])]).unwrap();
+    let chaotic = Palette::new(vec![(0.0, [0, 0, 0]), (1.0, [0, 0, 255])]).unwrap();
+    assert_eq!(exponent_color::<u8>(0.0, &stable, &chaotic), [0, 0, 0]);
+    assert_eq!(
+        exponent_color::<u8>(f64::NEG_INFINITY, &stable, &chaotic),
+        [255, 200, 0]
+    );
+    assert_eq!(
+        exponent_color::<u8>(LN_2 / 2.0, &stable, &chaotic),
+        [0, 0, 128]
+    );
+    assert_eq!(exponent_color::<u8>(5.0, &stable, &chaotic), [0, 0, 255]);
+}
+
+/// 并行渲染覆盖 `upper_left` 到 `lower_right` 的 `bounds` 大小的 Lyapunov 分形，返回 RGB 像素
+///
+/// 点的实部为 `a`，虚部为 `b`；指数没有意义的像素为黑色。每完成一行就在 `progress` 上
+/// 记录一次。
+#[allow(clippy::too_many_arguments)]
+pub fn render<C: Channel>(
+    sequence: &Sequence,
+    warmup: usize,
+    iterations: usize,
+    stable: &Palette,
+    chaotic: &Palette,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    progress: &Progress,
+) -> Vec<C> {
+    let mut pixels = vec![C::default(); bounds.0 * bounds.1 * 3];
+    progress.start(bounds.1, "rows");
+    pixels
+        .par_chunks_mut(bounds.0 * 3)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, pixel) in band.chunks_mut(3).enumerate() {
+                let point = pixed_to_point(bounds, (column, row), upper_left, lower_right);
+                if let Some(exponent) = exponent(sequence, point.re, point.im, warmup, iterations) {
+                    pixel.copy_from_slice(&exponent_color(exponent, stable, chaotic));
+                }
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    pixels
+}
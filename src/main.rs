@@ -1,219 +1,4736 @@
-use image::png::PNGEncoder;
-use image::ColorType;
+use clap::{Args, Parser, Subcommand};
+use mandelbrot::antialias;
+use mandelbrot::api;
+use mandelbrot::atlas::{self, Atlas};
+use mandelbrot::bailout::{parse_norm, Bailout, Norm, WithBailout};
+use mandelbrot::bench::{self, BenchConfig};
+use mandelbrot::buddhabrot::{self, Sampling, Selection, Tone};
+use mandelbrot::budget::{self, parse_budget, Stage};
+use mandelbrot::checkpoint::{self, Checkpoint};
+use mandelbrot::colorizer::Colorizer;
+use mandelbrot::config::{config_args, json_config_args};
+#[cfg(feature = "cuda")]
+use mandelbrot::cuda::Cuda;
+use mandelbrot::data::{IterationData, SavedView};
+use mandelbrot::dzi::{self, Pyramid};
+use mandelbrot::error::MandelbrotError;
+use mandelbrot::explore::{self, follow, iteration_limit};
+use mandelbrot::expmap::{self, ExpMap};
+use mandelbrot::extended::DoubleComplex;
+use mandelbrot::formula::{parse_formula, Formula};
+#[cfg(any(feature = "opencl", feature = "cuda"))]
+use mandelbrot::gpu;
+use mandelbrot::hybrid::{parse_hybrid, Hybrid};
+use mandelbrot::hypercomplex::{self, Algebra, Hypercomplex, Slice, Vector};
+use mandelbrot::interior::{parse_interior, Interior, InteriorColoring};
+use mandelbrot::keyframes::{parse_keyframes, Scene, Timeline};
+use mandelbrot::location::{self, Location};
+use mandelbrot::lyapunov::{self, Sequence};
+use mandelbrot::maxiter::{self, parse_max_iter_or_auto, MaxIter};
+use mandelbrot::measure::{self, Method};
+use mandelbrot::misiurewicz::find_misiurewicz;
+use mandelbrot::morph::{parse_julia_path, JuliaPath};
+use mandelbrot::newton::{self, Polynomial};
+use mandelbrot::nova::{self, Nova};
+use mandelbrot::npy::{self, NpyMetadata};
+use mandelbrot::nucleus::{exp2_scientific, find_minibrot};
+#[cfg(feature = "opencl")]
+use mandelbrot::opencl::{DeviceFloat, OpenCl};
+use mandelbrot::openexr;
+use mandelbrot::orbit;
+use mandelbrot::palette::{self, parse_hex_color, parse_palette, Channel, Palette};
+use mandelbrot::pan::{pixel_offset, render_panned};
+use mandelbrot::perturbation::{self, OrbitCache};
+use mandelbrot::precise::{self, Fixed, FixedComplex};
+use mandelbrot::progress::Progress;
+use mandelbrot::progressive::Progressive;
+use mandelbrot::queue::{self, Queue};
+use mandelbrot::rays::{self, Angle};
+use mandelbrot::real::{parse_precision, Arithmetic, Precision};
+use mandelbrot::remote::{self, parse_workers};
+use mandelbrot::serve::{self, Server, TileCache};
+use mandelbrot::shading::{self, parse_light, Light};
+use mandelbrot::stats::{EscapeHistogram, RenderStats};
+use mandelbrot::stream::PngStream;
+use mandelbrot::threads;
+#[cfg(any(feature = "opencl", feature = "cuda"))]
+use mandelbrot::tile::{self, Tile};
+use mandelbrot::transparency::{parse_region, with_alpha, Region, Transparency};
+use mandelbrot::trap::{parse_trap, Trap};
+use mandelbrot::video::VideoEncoder;
+use mandelbrot::{
+    cancel, cancelled, colorize, colorize_histogram, corners_from_center, downsample,
+    mark_unfinished, parse_coloring, parse_complex, parse_degrees, parse_fractal,
+    parse_image_format, parse_max_iter, parse_pair, parse_power, parse_samples, parse_zoom,
+    pixel_spacing, render_parallel, render_parallel_checkpointed, rotation_at_frame,
+    set_interior_check, set_symmetry, write_image, write_image16, zoom_at_frame, Coloring, Fractal,
+    ImageFormat, PixelTransform, PHOENIX_P, STDOUT,
+};
 use num::Complex;
-use rayon::iter::ParallelIterator;
-use rayon::prelude::IntoParallelIterator;
-use std::env;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::str::FromStr;
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
 
-/// 尝试测试 `c` 是否位于曼德博集中，使用最多 `limit` 次迭代来判定
+/// 曼德博集与朱利亚集渲染器
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// 不显示进度条和提示信息
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
+    /// 不预先排除曼德博集主心形和周期 2 圆盘内的点，也不用导数判据认定内部点，只靠周期
+    /// 检测（用于测量这两项优化的效果）
+    #[arg(long, global = true)]
+    no_interior_check: bool,
+
+    /// 分形和视图都关于实轴对称时也渲染整幅图像，不镜像实轴上方的一半（用于测量镜像的效果）
+    #[arg(long, global = true)]
+    no_symmetry: bool,
+
+    /// 并行渲染使用的线程数，默认每个逻辑核心一个
+    #[arg(long, value_name = "N", global = true, value_parser = parser(threads::parse_threads, "a positive integer"))]
+    threads: Option<usize>,
+
+    /// 把每个渲染线程固定在一个 CPU 核心上
+    #[arg(long, global = true)]
+    pin_threads: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 渲染一张静态图像
+    #[command(args_override_self = true)]
+    Render(RenderArgs),
+    /// 从 --zoom 缩放到 --end-zoom，渲染一组编号的动画帧
+    Animate(AnimateArgs),
+    /// 只渲染一次，逐帧移动 --palette-offset，输出调色板循环的动画帧
+    Cycle(CycleArgs),
+    /// 让朱利亚集的常数 c 沿一条路径移动，每一步渲染一帧，观察朱利亚集连续变形
+    Morph(MorphArgs),
+    /// 把整幅图像渲染为 DZI 瓦片金字塔，可以用 OpenSeadragon 等查看器平滑缩放浏览
+    Dzi(DziArgs),
+    /// 用新的调色板为保存的迭代数据重新着色，不重新计算分形
+    Recolor(RecolorArgs),
+    /// 随机采样 c，累积逃逸轨道的密度，渲染 Buddhabrot、Nebulabrot 或反 Buddhabrot
+    Buddhabrot(BuddhabrotArgs),
+    /// 渲染多项式的牛顿分形：按收敛到的根着色相，按收敛速度着亮度
+    Newton(NewtonArgs),
+    /// 渲染 Nova 分形：带松弛系数和常数 c 的牛顿迭代，按收敛所需的迭代次数着色
+    Nova(NovaArgs),
+    /// 渲染四元数曼德博集或 Mandelbulb 的二维切片，切片的偏移和方向可以逐帧移动
+    Slice(SliceArgs),
+    /// 渲染朱利亚集图谱：每个格子里画一个以格子中心为常数 c 的小朱利亚集
+    Atlas(AtlasArgs),
+    /// 把一个点的轨道画在分形上，迭代点之间用线段相连，可以同时导出为 CSV 或 JSON
+    Orbit(OrbitArgs),
+    /// 从近似位置出发用牛顿法找出给定周期的迷你曼德博集，按深度缩放所需的精度输出它的核、大小和方向
+    FindMinibrot(FindMinibrotArgs),
+    /// 从近似位置出发用牛顿法找出给定预周期和周期的米休雷维奇点，以任意精度输出它的位置和自相似的缩放倍数
+    FindMisiurewicz(FindMisiurewiczArgs),
+    /// 自动寻找值得深度缩放的位置：逐步放大到迭代次数的熵最高的区域，输出坐标和缩略图
+    Explore(ExploreArgs),
+    /// 用蒙特卡罗或像素计数在一组递增的迭代次数限制下估计曼德博集的面积，给出 95% 置信区间
+    Measure(MeasureArgs),
+    /// 渲染逻辑斯谛映射的 Lyapunov 分形：稳定区域和混沌区域分别用各自的渐变着色
+    Lyapunov(LyapunovArgs),
+    /// 在固定设置下渲染一组标准视图，比较各种并行方式在不同线程数下的速度
+    Bench(BenchArgs),
+    /// 启动 HTTP 服务器，按需渲染 XYZ 瓦片，在浏览器中打开首页即可平移缩放浏览
+    Serve(ServeArgs),
+    /// 启动 HTTP 渲染服务：POST /jobs 提交 JSON 描述的渲染任务，轮询 /jobs/ID 的状态，
+    /// 完成后从 /jobs/ID/result 下载图像
+    Api(ApiArgs),
+    /// 作为分布式渲染的工作进程，替 render --workers 和 animate --workers 渲染条带或帧
+    Worker(WorkerArgs),
+    /// 持久化的任务队列：排入渲染或动画命令，之后依次或并行执行，进程重启后继续
+    Queue(QueueArgs),
+    /// 列出或管理 --location 可用的位置书签
+    #[command(subcommand)]
+    Bookmarks(BookmarksCommand),
+    /// 打开窗口交互浏览：拖动平移，滚轮缩放，按 E 导出当前视图，按 Esc 退出
+    #[cfg(feature = "viewer")]
+    View(ViewerArgs),
+}
+
+/// 复平面中要渲染的范围
+#[derive(Args, Clone)]
+struct ViewArgs {
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1000x750", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1000x750"))]
+    size: (usize, usize),
+
+    /// 复平面中对应图像左上角的点
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, requires = "lower_right", conflicts_with = "center", value_parser = parser(parse_complex, "RE,IM"))]
+    upper_left: Option<Complex<f64>>,
+
+    /// 复平面中对应图像右下角的点
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, requires = "upper_left", value_parser = parser(parse_complex, "RE,IM"))]
+    lower_right: Option<Complex<f64>>,
+
+    /// 视图中心，深度缩放时会按原样以任意精度解析
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.5,0", value_parser = parser(|s| parse_complex(s).map(|_| s.to_string()), "RE,IM"))]
+    center: String,
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖复平面中长度为 4 的范围；默认为 1，或 --location 给出的位置自带的缩放倍数
+    #[arg(long, conflicts_with = "upper_left", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: Option<f64>,
+
+    /// 以内置的著名位置（如 seahorse、elephant、minibrot）或用户书签为视图中心，可用的名称见 bookmarks list
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["center", "upper_left"])]
+    location: Option<String>,
+
+    /// 把复平面中的采样矩形绕视图中心逆时针旋转的角度（度），画面看起来顺时针转动
+    #[arg(long, value_name = "DEGREES", default_value = "0", allow_hyphen_values = true, value_parser = parser(parse_degrees, "an angle in degrees"))]
+    rotate: f64,
+
+    /// 并行渲染时每个分块的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "64x64", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 64x64"))]
+    tile: (usize, usize),
+
+    /// 使用 Mariani–Silver 矩形细分：边框逃逸值相同的矩形直接填充，不计算内部
+    #[arg(long)]
+    subdivide: bool,
+
+    /// 超采样抗锯齿：每个像素在每个方向上取 N 个子像素，再取平均
+    #[arg(long, value_name = "NxN", default_value = "1x1", value_parser = parser(parse_samples, "NxN with equal positive sides, e.g. 3x3"))]
+    samples: usize,
+
+    /// 只对与相邻像素颜色相差较大的边界像素超采样，需要配合 --samples 使用
+    #[arg(long)]
+    adaptive: bool,
+
+    /// 自适应抗锯齿判定边界像素的颜色通道差阈值
+    #[arg(long, value_name = "T", default_value = "16", requires = "adaptive")]
+    adaptive_threshold: u8,
+
+    /// 深度缩放时逐像素使用任意精度迭代，而不是微扰渲染（慢得多，用于验证）
+    #[arg(long)]
+    no_perturbation: bool,
+
+    /// 普通渲染的浮点精度：double（f64）、single（f32）或 double-double（dd）；single 用 f32 迭代，
+    /// 向量化时每次迭代两倍的点，适合预览和较小的缩放倍数，超出 f32 的分辨率时退回 double；
+    /// double-double 在超出 f64 的分辨率之后用约 106 位的双倍精度迭代，缩放倍数可达约 1e30，
+    /// 比微扰和任意精度简单可靠，再深时退回它们；auto 按缩放深度依次选用 f32、f64、double-double
+    /// 和深度缩放的渲染方式中第一个能分辨视图的，并说明选了哪一个
+    #[arg(long, default_value = "double", value_parser = parser(parse_precision, "`single` (`f32`), `double` (`f64`), `double-double` (`dd`) or `auto`"))]
+    precision: Precision,
+}
+
+impl ViewArgs {
+    /// 返回图像尺寸以及覆盖范围的左上角和右下角
+    fn corners(&self) -> ((usize, usize), Complex<f64>, Complex<f64>) {
+        let (upper_left, lower_right) = match (self.upper_left, self.lower_right) {
+            (Some(upper_left), Some(lower_right)) => (upper_left, lower_right),
+            _ => corners_from_center(self.size, self.center_f64(), self.zoom()),
+        };
+        (self.size, upper_left, lower_right)
+    }
+
+    /// 返回图像尺寸以及把像素映射为复平面中的点的变换，包括 --rotate 给出的旋转
+    fn transform(&self) -> ((usize, usize), PixelTransform<f64>) {
+        let (bounds, upper_left, lower_right) = self.corners();
+        let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+        (bounds, transform.rotated(bounds, self.rotate))
+    }
+
+    fn center_f64(&self) -> Complex<f64> {
+        parse_complex(&self.center).expect("center was validated by clap")
+    }
+
+    fn zoom(&self) -> f64 {
+        self.zoom.unwrap_or(1.0)
+    }
+
+    /// 把 --location 给出的位置换算为中心和缩放倍数，命令行上的 --zoom 优先
+    fn resolve_location(&mut self) -> Result<(), MandelbrotError> {
+        let Some(name) = &self.location else {
+            return Ok(());
+        };
+        let location = location::find(name, &load_user_bookmarks()?).ok_or_else(|| {
+            MandelbrotError::InvalidArgument(format!(
+                "unknown location `{}` (see `bookmarks list` for the available names)",
+                name
+            ))
+        })?;
+        self.center = location.center;
+        self.zoom = self.zoom.or(Some(location.zoom));
+        Ok(())
+    }
+
+    /// 每个方向的像素数都放大 `samples` 倍、覆盖范围不变的视图，每个像素对应一个子像素
+    fn supersampled(&self) -> ViewArgs {
+        ViewArgs {
+            size: (self.size.0 * self.samples, self.size.1 * self.samples),
+            samples: 1,
+            ..self.clone()
+        }
+    }
+
+    /// 如果视图由中心和缩放倍数给出且超出了 `f64` 的分辨率，返回任意精度的中心和像素间距
+    fn precise(&self) -> Option<(FixedComplex, Fixed)> {
+        if self.upper_left.is_some() {
+            return None;
+        }
+        let spacing = pixel_spacing(self.size, self.zoom());
+        if !precise::required(self.center_f64(), spacing) {
+            return None;
+        }
+        let bits = precise::bits_for_spacing(spacing);
+        let center = FixedComplex::parse(&self.center, bits)?;
+        Some((center, Fixed::from_f64(spacing, bits)))
+    }
+}
+
+/// 分形类型及其迭代参数
+#[derive(Args)]
+struct FractalArgs {
+    /// 分形类型：mandelbrot、julia、burning-ship、tricorn、phoenix（z = z^2 + c + p z₋₁）或 lambda
+    /// （z = λ z (1 - z)）；phoenix 和 lambda 给出 --c 时是朱利亚类分形，lambda 以它为 λ
+    #[arg(long, default_value = "mandelbrot")]
+    fractal: String,
+
+    /// 朱利亚集的常数 c
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(parse_complex, "RE,IM"))]
+    c: Option<Complex<f64>>,
+
+    /// Phoenix 分形中上一步 z₋₁ 的系数 p，默认为 -0.5；与 --c 0.5667,0 一起给出经典的 Phoenix 朱利亚集
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(parse_complex, "RE,IM"))]
+    p: Option<Complex<f64>>,
+
+    /// 迭代公式 z = z^d + c 中的次数 d，只有曼德博集支持 2 以外的次数
+    #[arg(long, value_name = "D", default_value = "2", value_parser = parser(parse_power, "a number greater than 1"))]
+    power: f64,
+
+    /// 每个点的最大迭代次数；render 可以取 auto，按缩放倍数估计后用低分辨率的试渲染逐次加倍，
+    /// 直到内部像素的比例稳定下来
+    #[arg(long, value_name = "N", default_value = "255", value_parser = parser(parse_max_iter_or_auto, "a positive integer or `auto`"))]
+    max_iter: MaxIter,
+
+    /// 自定义迭代公式，如 "z^2 + c*z + c"：z 从原点出发，c 取像素对应的点，|z| > 2 时逃逸；可用 + - * / ^、i、sin cos tan exp log sqrt conj abs re im
+    #[arg(long, value_name = "EXPR", allow_hyphen_values = true, conflicts_with_all = ["fractal", "c", "power", "p"], value_parser = |s: &str| parse_formula(s).map(|formula| &*Box::leak(Box::new(formula))))]
+    formula: Option<&'static Formula>,
+
+    /// 混合分形的模式，如 "mandelbrot*2,burning-ship"：逐步轮换逗号分隔的 mandelbrot、burning-ship 或 tricorn，^d 给出次数，*n 重复 n 次；给出 --c 时是朱利亚类分形
+    #[arg(long, value_name = "PATTERN", conflicts_with_all = ["fractal", "power", "p", "formula"], value_parser = |s: &str| parse_hybrid(s).map(|hybrid| &*Box::leak(Box::new(hybrid))))]
+    hybrid: Option<&'static Hybrid>,
+}
+
+impl FractalArgs {
+    /// 给定的最大迭代次数，只有 render 支持 `--max-iter auto`
+    fn limit(&self) -> Result<usize, MandelbrotError> {
+        match self.max_iter {
+            MaxIter::Fixed(limit) => Ok(limit),
+            MaxIter::Auto => Err(MandelbrotError::InvalidArgument(
+                "--max-iter auto is only supported by render".to_string(),
+            )),
+        }
+    }
+
+    /// 选定的分形，并检查它能否按 `color` 给出的方式着色
+    fn fractal(&self, color: &ColorArgs) -> Result<Fractal, MandelbrotError> {
+        let fractal = self.parse()?;
+        match color.coloring() {
+            coloring if fractal.supports_coloring(coloring) => Ok(fractal),
+            Coloring::TriangleInequality => Err(MandelbrotError::InvalidArgument(
+                "--coloring tia is only supported for fractals iterating z^d + c, not --formula, \
+                 `phoenix`, or `lambda`"
+                    .to_string(),
+            )),
+            _ => Err(MandelbrotError::InvalidArgument(format!(
+                "`{}` does not support --coloring distance or atom-domain",
+                self.fractal
+            ))),
+        }
+    }
+
+    /// 按 --formula、--hybrid 或 --fractal 得到的分形，不检查着色方式
+    fn parse(&self) -> Result<Fractal, MandelbrotError> {
+        if let Some(formula) = self.formula {
+            return Ok(Fractal::Formula(formula));
+        }
+        if let Some(hybrid) = self.hybrid {
+            return Ok(Fractal::Hybrid { hybrid, c: self.c });
+        }
+        let fractal = match parse_fractal(&self.fractal, self.c) {
+            Some(Fractal::Phoenix { c, .. }) => Fractal::Phoenix {
+                c,
+                p: self.p.unwrap_or(PHOENIX_P),
+            },
+            _ if self.p.is_some() => {
+                return Err(MandelbrotError::InvalidArgument(format!(
+                    "--p is only supported for `phoenix`, not `{}`",
+                    self.fractal
+                )))
+            }
+            Some(fractal) => fractal,
+            None => {
+                return Err(MandelbrotError::InvalidArgument(format!(
+                    "unknown fractal `{}` (expected `mandelbrot`, `burning-ship`, `tricorn`, `phoenix`, `lambda`, or `julia` together with --c)",
+                    self.fractal
+                )))
+            }
+        };
+        fractal.with_power(self.power).ok_or_else(|| {
+            MandelbrotError::InvalidArgument(format!(
+                "--power is only supported for `mandelbrot`, not `{}`",
+                self.fractal
+            ))
+        })
+    }
+}
+
+/// 着色参数
+#[derive(Args)]
+struct ColorArgs {
+    /// 着色方式：escape-time、smooth、distance、orbit-trap、tia（三角不等式平均，只支持迭代
+    /// z^d + c 的分形，不支持 --formula、phoenix 和 lambda）或 atom-domain（按周期划分的原子域）
+    #[arg(long, default_value = "escape-time", value_parser = parser(parse_coloring, "`escape-time`, `smooth`, `distance`, `orbit-trap`, `tia`, or `atom-domain`"))]
+    coloring: Coloring,
+
+    /// 轨道陷阱：point:RE,IM、cross:RE,IM、circle:RE,IM:RADIUS 或 line:RE,IM:DEGREES
+    #[arg(long, default_value = "point:0,0", allow_hyphen_values = true, value_parser = parser(parse_trap, "a trap such as point:0,0 or circle:0,0:0.5"))]
+    trap: Trap,
+
+    #[command(flatten)]
+    palette: PaletteArgs,
+}
+
+/// 把逃逸值映射为颜色的参数，重新着色时可以更换
+#[derive(Args, Clone)]
+struct PaletteArgs {
+    /// 调色板：gray、fire、ocean、classic，在 Oklab 中插值的科学配色 viridis、magma、inferno、plasma、
+    /// turbo，形如 0:000000,1:ffffff 的渐变节点，或者 Fractint 的 .map、GIMP 的 .gpl 调色板和 .ggr 渐变文件
+    #[arg(long, default_value = "gray", value_parser = palette_value)]
+    palette: Palette,
+
+    /// 按逃逸次数的直方图均衡着色，使调色板在整幅图像中均匀使用
+    #[arg(long)]
+    histogram: bool,
+
+    /// 把调色板循环移动的量，1 为一整圈；不为零时调色板首尾相接
+    #[arg(long, value_name = "F", default_value = "0", allow_hyphen_values = true, value_parser = parser(|s| s.parse().ok().filter(|f: &f64| f.is_finite()), "a number"))]
+    palette_offset: f64,
+
+    /// 斜坡着色：把逃逸值看作高度场，用从方位角 AZ（0 为右方，90 为上方）、仰角 EL（度）照来的光
+    /// 给图像加上明暗；连续着色或距离估计着色时效果最好，不影响 view 和 serve
+    #[arg(long, value_name = "AZ,EL", allow_hyphen_values = true, value_parser = parser(parse_light, "AZIMUTH,ELEVATION in degrees, elevation between 0 and 90"))]
+    light: Option<Light>,
+
+    /// --light 时高度起伏的放大倍数
+    #[arg(long, value_name = "F", default_value = "1", requires = "light", value_parser = parser(|s| s.parse().ok().filter(|f: &f64| f.is_finite() && *f > 0.0), "a positive number"))]
+    relief: f64,
+
+    /// 让外部（exterior，逃逸的像素）或内部（interior）透明，输出带 alpha 通道的图像；不支持 jpeg、
+    /// --stream 和内部也着色的方式，不影响视频、view 和 serve
+    #[arg(long, value_name = "REGION", value_parser = parser(parse_region, "`exterior` or `interior`"))]
+    transparent: Option<Region>,
+
+    /// --transparent 区域的不透明度，0 为完全透明，1 为不透明
+    #[arg(long, value_name = "F", default_value = "0", requires = "transparent", value_parser = parser(|s| s.parse().ok().filter(|f: &f64| (0.0..=1.0).contains(f)), "a number between 0 and 1"))]
+    alpha: f64,
+}
+
+impl ColorArgs {
+    /// 选定的着色方式，轨道陷阱着色时带上 --trap 给出的陷阱
+    fn coloring(&self) -> Coloring {
+        self.coloring.with_trap(self.trap)
+    }
+}
+
+/// 用调色板 `palette` 为迭代缓冲区着色，`histogram` 时按迭代次数的直方图均衡着色
+fn colorize_palette<C: Channel>(
+    iterations: &[u32],
+    limit: usize,
+    palette: &Palette,
+    histogram: bool,
+) -> Vec<C> {
+    let mut pixels = vec![C::default(); iterations.len() * 3];
+    if histogram {
+        colorize_histogram(iterations, limit, palette, &mut pixels);
+    } else {
+        colorize(iterations, limit, palette, &mut pixels);
+    }
+    pixels
+}
+
+impl PaletteArgs {
+    /// 用选定的调色板和映射方式把迭代缓冲区着色为 RGB 像素
+    fn colorize<C: Channel>(&self, iterations: &[u32], limit: usize, pixels: &mut [C]) {
+        let palette = self.palette.clone().with_offset(self.palette_offset);
+        if self.histogram {
+            colorize_histogram(iterations, limit, &palette, pixels);
+        } else {
+            colorize(iterations, limit, &palette, pixels);
+        }
+    }
+
+    /// 给了 --light 时为宽度为 `width` 的缓冲区着色后的像素加上明暗
+    fn shade<C: Channel>(&self, iterations: &[u32], width: usize, pixels: &mut [C]) {
+        if let Some(light) = self.light {
+            let light = Light {
+                relief: self.relief,
+                ..light
+            };
+            shading::shade(iterations, width, &light, pixels);
+        }
+    }
+
+    /// 给了 --transparent 时检查 `format` 能否保存 alpha 通道
+    fn check_alpha(&self, format: ImageFormat) -> Result<(), MandelbrotError> {
+        if self.transparent.is_some() && !format.supports_alpha() {
+            return Err(MandelbrotError::InvalidArgument(format!(
+                "--transparent is not supported for `{}` output",
+                format.extension()
+            )));
+        }
+        Ok(())
+    }
+
+    /// 给了 --transparent 时为 `colorize_samples` 着色后的 RGB 像素加上 alpha 通道
+    fn with_alpha<C: Channel>(
+        &self,
+        iterations: &[u32],
+        samples: usize,
+        bounds: (usize, usize),
+        pixels: Vec<C>,
+    ) -> Vec<C> {
+        match self.transparent {
+            Some(region) => {
+                let transparency = Transparency {
+                    region,
+                    opacity: self.alpha,
+                };
+                with_alpha(&pixels, &transparency.alpha(iterations, samples, bounds))
+            }
+            None => pixels,
+        }
+    }
+}
+
+/// 输出图像的格式
+#[derive(Args)]
+struct ImageArgs {
+    /// 输出格式：png、jpeg、webp、tiff、ppm、pgm 或 bmp，默认按输出文件的扩展名推断，无法推断时为 png
+    #[arg(long, value_parser = parser(parse_image_format, "`png`, `jpeg`, `webp`, `tiff`, `ppm`, `pgm`, or `bmp`"))]
+    format: Option<ImageFormat>,
+
+    /// JPEG 的质量，1 到 100
+    #[arg(long, value_name = "Q", default_value = "90", value_parser = parser(|s| s.parse().ok().filter(|q: &u8| (1..=100).contains(q)), "an integer between 1 and 100"))]
+    quality: u8,
+
+    /// 每个颜色通道的位数：8 或 16，16 位只支持 png、tiff、ppm 和 pgm，png 和 tiff 在灰度调色板下
+    /// 输出单通道灰度图像
+    #[arg(long, value_name = "BITS", default_value = "8", value_parser = parser(|s| s.parse().ok().filter(|&bits: &u8| bits == 8 || bits == 16), "8 or 16"))]
+    bit_depth: u8,
+}
+
+impl ImageArgs {
+    /// 写入 `filename` 时使用的格式，--format 优先于文件的扩展名
+    fn format(&self, filename: Option<&str>) -> Result<ImageFormat, MandelbrotError> {
+        let format = self
+            .format
+            .or_else(|| filename.and_then(ImageFormat::from_filename))
+            .unwrap_or(ImageFormat::Png)
+            .with_quality(self.quality);
+        if self.bit_depth == 16 && !format.supports_16_bit() {
+            return Err(MandelbrotError::InvalidArgument(format!(
+                "--bit-depth 16 requires PNG, TIFF, PPM, or PGM output, not `{}`",
+                format.extension()
+            )));
+        }
+        Ok(format)
+    }
+}
+
+/// 工作进程的地址列表，写成别名是为了让 clap 把它当作一个值，而不是可以重复的选项
+type Workers = Vec<String>;
+
+/// 逗号分隔的外角，与 `Workers` 一样用别名避免 clap 把它当作可以重复的选项
+type Angles = Vec<Angle>;
+
+/// 逗号分隔的等势线的势函数值
+type Levels = Vec<f64>;
+
+/// 逗号分隔的严格递增的迭代次数限制
+type Limits = Vec<usize>;
+
+#[derive(Args)]
+struct RenderArgs {
+    /// 输出的图像文件，可以由配置文件中的 output 给出；为 - 时写到标准输出，通常与 --format ppm
+    /// 一起使用
+    #[arg(required_unless_present = "config")]
+    output: Option<String>,
+
+    /// 从 TOML 场景文件读取参数，命令行上给出的选项优先于文件中的值
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+
+    /// 同时把原始迭代数据保存到该文件，之后可以用 recolor 子命令重新着色
+    #[arg(long, value_name = "FILE")]
+    save_data: Option<String>,
+
+    /// 复用该 --save-data 文件中的迭代数据：新视图只是把它平移了整数个像素时，只计算新露出的条带；
+    /// 分形、尺寸、超采样等其余参数必须与保存时相同
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["checkpoint", "stream", "workers"])]
+    pan_from: Option<String>,
+
+    /// 每完成一个分块就把它追加到该检查点文件中，渲染成功后删除；进程被中断时可以用 --resume 续算
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<String>,
+
+    /// 从 --checkpoint 文件读入已完成的分块，只计算剩下的分块；参数必须与中断的渲染相同
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// 同时把逃逸值以 32 位浮点数写入该 OpenEXR 文件的 escape 通道，不经过调色板映射和量化；
+    /// 内部像素为 -1，超采样时按子像素的分辨率写出
+    #[arg(long, value_name = "FILE")]
+    exr: Option<String>,
+
+    /// 同时把逃逸值导出为 NumPy 数组（内部像素为 NaN），坐标范围等参数写在同名的 .json 文件中，
+    /// 覆盖已有的同名文件
+    #[arg(long, value_name = "FILE")]
+    export_npy: Option<String>,
+
+    /// 在 --exr 文件中增加 distance 通道，保存以复平面中的长度为单位的距离估计
+    #[arg(long, requires = "exr")]
+    exr_distance: bool,
+
+    /// 按水平条带逐条渲染，每个条带着色后立即写入 PNG，内存占用与图像尺寸无关，用于超大图像；
+    /// 不支持深度缩放、直方图均衡、斜坡着色和自适应抗锯齿
+    #[arg(long, conflicts_with_all = ["save_data", "checkpoint", "exr", "export_npy"])]
+    stream: bool,
+
+    /// 把图像按条带分给这些工作进程（见 worker 子命令）渲染，省略端口时为 7878
+    #[arg(long, value_name = "HOST:PORT,...", conflicts_with_all = ["checkpoint", "stream"], value_parser = parser(parse_workers, "comma-separated HOST[:PORT] addresses"))]
+    workers: Option<Workers>,
+
+    /// --stream 时每个条带的行数
+    #[arg(long, value_name = "N", default_value = "256", requires = "stream", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    strip_height: usize,
+
+    /// 集合内部的着色方式：flat（调色板的内部颜色）、period（吸引环的周期）、multiplier（吸引环
+    /// 乘子的模）或 angle（最后的 z 的辐角）；内部与外部共用调色板，不支持深度缩放和自适应抗锯齿
+    #[arg(long, value_name = "MODE", default_value = "flat", conflicts_with_all = ["checkpoint", "stream", "workers", "pan_from"], value_parser = parser(parse_interior, "`flat`, `period`, `multiplier`, or `angle`"))]
+    interior: Interior,
+
+    /// 逃逸半径 R，不小于 2；默认按着色方式取（escape-time 为 2，smooth 为 256），更大的半径让连续
+    /// 着色和三角不等式平均更准确；与 --norm 一样不支持深度缩放和自适应抗锯齿
+    #[arg(long, value_name = "R", conflicts_with_all = ["checkpoint", "stream", "workers", "pan_from", "interior"], value_parser = parser(|s| s.parse().ok().filter(|r: &f64| r.is_finite() && *r >= 2.0), "a number not less than 2"))]
+    bailout: Option<f64>,
+
+    /// 逃逸判定的范数：modulus（|z| > R）、real（|Re z| > R）或 manhattan（|Re z| + |Im z| > R），
+    /// 后两者在外部留下条纹
+    #[arg(long, default_value = "modulus", conflicts_with_all = ["checkpoint", "stream", "workers", "pan_from", "interior"], value_parser = parser(parse_norm, "`modulus`, `real`, or `manhattan`"))]
+    norm: Norm,
+
+    /// 叠加画出这些外角（以圈为单位的小数或分数，如 0,1/3,2/3）的外射线，只支持曼德博集和朱利亚集
+    #[arg(long, value_name = "TURNS,...", conflicts_with = "stream", value_parser = parser(rays::parse_angles, "comma-separated angles in turns such as 0.25 or 1/3"))]
+    rays: Option<Angles>,
+
+    /// 叠加画出势函数 G = lim ln|z_n| / 2^n 等于这些值的等势线，远处的等势线接近半径为 e^G 的圆
+    #[arg(long, value_name = "G,...", conflicts_with = "stream", value_parser = parser(|s| s.split(',').map(|g| g.trim().parse().ok().filter(|g: &f64| g.is_finite() && *g > 0.0)).collect::<Option<Levels>>(), "comma-separated positive numbers"))]
+    equipotentials: Option<Levels>,
+
+    /// 外射线向内追踪的层数，每一层势函数减半，越大越接近集合
+    #[arg(long, value_name = "N", default_value = "40", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    ray_depth: usize,
+
+    /// 外射线和等势线的颜色
+    #[arg(long, value_name = "RRGGBB", default_value = "ffffff", value_parser = parser(parse_hex_color, "a hex color such as ffffff"))]
+    overlay_color: [u8; 3],
+
+    /// 渲染之后打印统计：逃逸值的最小、最大和平均值，内部像素的比例，逃逸值的直方图，
+    /// 每秒像素数和每个线程的忙碌时间
+    #[arg(long, conflicts_with = "stream")]
+    stats: bool,
+
+    /// 把 --stats 的统计以 JSON 写入该文件
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    stats_json: Option<String>,
+
+    /// 在这么长的时间内（如 30s、2m）渲染尽可能好的图像：先以较少的迭代次数从 1/8 的分辨率
+    /// 渐进到全分辨率，再逐次加倍迭代次数直到 --max-iter，输出最后完成的一步；不支持深度缩放
+    #[arg(long, value_name = "DURATION", conflicts_with_all = ["stream", "workers", "checkpoint", "pan_from", "interior", "bailout"], value_parser = parser(parse_budget, "a duration such as 30s, 2m, or 500ms"))]
+    budget: Option<Duration>,
+
+    /// 把按整数迭代次数统计的逃逸值直方图导出到该文件，扩展名为 .json 时导出为 JSON（附带
+    /// 常用的百分位），否则为 CSV；只支持 escape-time 和 smooth 着色，不支持 --interior
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    export_histogram: Option<String>,
+
+    /// 在第一个 OpenCL GPU 设备上渲染，按 --tile 分块提交；只支持 mandelbrot、julia、
+    /// burning-ship、tricorn 和整数的 --power，以及 escape-time 和 smooth 着色，不支持深度缩放；
+    /// f32 能分辨时按 --precision 选择 f32，设备不支持 f64 时用两个 f32 模拟的 double-float
+    #[cfg(feature = "opencl")]
+    #[arg(long, group = "gpu", conflicts_with_all = ["stream", "workers", "checkpoint", "pan_from", "interior", "bailout", "norm", "budget", "adaptive"])]
+    opencl: bool,
+
+    /// 在第一个 CUDA 设备上以 f64 渲染，按 --tile 分块提交；支持的分形和着色方式与 --opencl
+    /// 相同，不支持深度缩放
+    #[cfg(feature = "cuda")]
+    #[arg(long, group = "gpu", conflicts_with_all = ["stream", "workers", "checkpoint", "pan_from", "interior", "bailout", "norm", "budget", "adaptive"])]
+    #[cfg_attr(feature = "opencl", arg(conflicts_with = "opencl"))]
+    cuda: bool,
+
+    /// 与 --opencl 或 --cuda 一起使用：GPU 和 CPU 线程池从同一个队列中取分块一起渲染，
+    /// 谁空闲谁取下一块
+    #[cfg(any(feature = "opencl", feature = "cuda"))]
+    #[arg(long, requires = "gpu")]
+    with_cpu: bool,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+impl RenderArgs {
+    fn bailout(&self) -> Option<Bailout> {
+        bailout(self.bailout, self.norm, &self.color)
+    }
+}
+
+/// 给了 --bailout 或 --norm 时的逃逸判定，没有给出半径 `radius` 时沿用着色方式的逃逸半径
+fn bailout(radius: Option<f64>, norm: Norm, color: &ColorArgs) -> Option<Bailout> {
+    if radius.is_none() && norm == Norm::Modulus {
+        return None;
+    }
+    Some(Bailout {
+        radius: radius.unwrap_or_else(|| color.coloring().bailout().sqrt()),
+        norm,
+    })
+}
+
+#[derive(Args)]
+struct CycleArgs {
+    /// 输出帧所在的目录，帧按 frame_0000.png、frame_0001.png…… 编号，扩展名随 --format 改变
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: String,
+
+    /// 直接编码成视频文件（如 cycle.mp4 或 cycle.webm，需要 ffmpeg），或者不需要 ffmpeg 的
+    /// 循环动图 cycle.gif、cycle.png（APNG），而不是输出 PNG 帧
+    #[arg(long, value_name = "FILE", conflicts_with = "out_dir")]
+    out: Option<String>,
+
+    /// 视频的帧率
+    #[arg(long, default_value = "30", requires = "out", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    fps: usize,
+
+    /// 帧数
+    #[arg(long, value_name = "N", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    frames: usize,
+
+    /// 整个动画中调色板循环的圈数，为负数时反向循环；为整数时最后一帧之后正好接回第一帧
+    #[arg(long, value_name = "K", default_value = "1", allow_hyphen_values = true, value_parser = parser(|s| s.parse().ok().filter(|f: &f64| f.is_finite()), "a number"))]
+    cycles: f64,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct MorphArgs {
+    /// 输出帧所在的目录，帧按 frame_0000.png、frame_0001.png…… 编号，扩展名随 --format 改变
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: String,
+
+    /// 直接编码成视频文件（如 morph.mp4 或 morph.webm，需要 ffmpeg），或者不需要 ffmpeg 的
+    /// 循环动图 morph.gif、morph.png（APNG），而不是输出 PNG 帧
+    #[arg(long, value_name = "FILE", conflicts_with = "out_dir")]
+    out: Option<String>,
+
+    /// 视频的帧率
+    #[arg(long, default_value = "30", requires = "out", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    fps: usize,
+
+    /// 帧数
+    #[arg(long, value_name = "N", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    frames: usize,
+
+    /// 常数 c 的路径：线段 line:RE,IM:RE,IM、圆 circle:RE,IM:RADIUS、曼德博集主心形线的边界 cardioid，
+    /// 或者依次经过各个路点的折线 points:RE,IM:RE,IM:...；圆和心形线首尾相接，可以循环播放。
+    /// 朱利亚集以原点为中心，通常配合 --center 0,0 使用
+    #[arg(long, allow_hyphen_values = true, value_parser = parser(parse_julia_path, "a path such as line:-0.8,0.156:-0.7,0.27, circle:0,0:0.7885, cardioid, or points:RE,IM:RE,IM:..."))]
+    path: JuliaPath,
+
+    /// 每个点的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "255", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct DziArgs {
+    /// 输出的 .dzi 描述文件，瓦片写入它旁边的 NAME_files 目录
+    output: String,
+
+    /// 瓦片的像素边长，不含重叠部分
+    #[arg(long, value_name = "N", default_value = "254", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    tile_size: usize,
+
+    /// 每块瓦片向相邻瓦片多包含的像素数
+    #[arg(long, value_name = "N", default_value = "1")]
+    overlap: usize,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct AnimateArgs {
+    /// 输出帧所在的目录，帧按 frame_0000.png、frame_0001.png…… 编号，扩展名随 --format 改变
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: String,
+
+    /// 直接编码成视频文件（如 zoom.mp4 或 zoom.webm，需要 ffmpeg），或者不需要 ffmpeg 的
+    /// 循环动图 zoom.gif、zoom.png（APNG），而不是输出 PNG 帧
+    #[arg(long, value_name = "FILE", conflicts_with = "out_dir")]
+    out: Option<String>,
+
+    /// 视频的帧率
+    #[arg(long, default_value = "30", requires = "out", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    fps: usize,
+
+    /// 最后一帧的缩放倍数，第一帧的缩放倍数由 --zoom 给出
+    #[arg(long, required_unless_present = "keyframes", value_parser = parser(parse_zoom, "a positive number"))]
+    end_zoom: Option<f64>,
+
+    /// 帧数
+    #[arg(long, value_name = "N", required_unless_present = "keyframes", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    frames: Option<usize>,
+
+    /// 最后一帧的旋转角度（度），第一帧的角度由 --rotate 给出，中间的帧线性插值；默认不转动
+    #[arg(long, value_name = "DEGREES", allow_hyphen_values = true, value_parser = parser(parse_degrees, "an angle in degrees"))]
+    end_rotate: Option<f64>,
+
+    /// 从 TOML 或 JSON（扩展名为 .json）关键帧文件读取每个关键帧的中心、缩放倍数、旋转角度、
+    /// 最大迭代次数、调色板偏移和朱利亚集常数 c，以及每一段的缓动曲线（linear、ease-in、
+    /// ease-out、ease-in-out 或 hold），在关键帧之间插值；最后一个关键帧是最后一帧
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["end_zoom", "frames", "end_rotate", "exp_map"])]
+    keyframes: Option<String>,
+
+    /// 先把整个缩放过程渲染为一条以 --center 为中心的对数极坐标条带（指数映射），
+    /// 每一帧再从条带中重投影，比逐帧渲染快得多
+    #[arg(long)]
+    exp_map: bool,
+
+    /// 跟随边界自动缩放：像 explore 一样从 --center 出发逐步选出细节最丰富的方向，每一帧的
+    /// 中心沿这条路径移动，最大迭代次数随缩放倍数增长
+    #[arg(long, conflicts_with_all = ["keyframes", "exp_map"])]
+    autozoom: bool,
+
+    /// 把指数映射的条带着色后另存为图像，第一行是最外圈，每一列对应一个辐角
+    #[arg(long, value_name = "FILE", requires = "exp_map")]
+    save_strip: Option<String>,
+
+    /// 把每一帧分给这些工作进程（见 worker 子命令）渲染，省略端口时为 7878
+    #[arg(long, value_name = "HOST:PORT,...", conflicts_with = "exp_map", value_parser = parser(parse_workers, "comma-separated HOST[:PORT] addresses"))]
+    workers: Option<Workers>,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct RecolorArgs {
+    /// render --save-data 保存的迭代数据文件
+    input: String,
+
+    /// 输出的图像文件
+    output: String,
+
+    /// 把按整数迭代次数统计的逃逸值直方图导出到该文件，格式与 render --export-histogram 相同；
+    /// 只支持以 escape-time 或 smooth 着色渲染的数据
+    #[arg(long, value_name = "FILE")]
+    export_histogram: Option<String>,
+
+    #[command(flatten)]
+    palette: PaletteArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// 每个视图的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "640x480", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 640x480"))]
+    size: (usize, usize),
+
+    /// 每个点的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "1000", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 依次测试 1 到 N 个线程，默认为逻辑核心数
+    #[arg(long, value_name = "N", value_parser = parser(threads::parse_threads, "a positive integer"))]
+    max_threads: Option<usize>,
+
+    /// 每种组合重复渲染的次数，取最快的一次
+    #[arg(long, value_name = "N", default_value = "3", value_parser = parser(threads::parse_threads, "a positive integer"))]
+    repeat: usize,
+
+    /// 以 JSON 格式把结果输出到标准输出
+    #[arg(long)]
+    json: bool,
+
+    /// 只测量把像素映射为复平面上的点的开销：逐像素调用 pixed_to_point，与 render
+    /// 使用的按行、按列预先计算的增量相比
+    #[arg(long, conflicts_with = "max_threads")]
+    coordinates: bool,
+}
+
+#[derive(Args)]
+struct BuddhabrotArgs {
+    /// 输出的图像文件
+    output: String,
+
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1000x1000", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1000x1000"))]
+    size: (usize, usize),
+
+    /// 视图中心
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.5,0", value_parser = parser(parse_complex, "RE,IM"))]
+    center: Complex<f64>,
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖复平面中长度为 4 的范围
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 随机采样的 c 的个数（--sampling metropolis 时是马尔可夫链的总步数）
+    #[arg(long, value_name = "N", default_value = "10000000", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    points: usize,
+
+    /// 只累积逃逸次数不少于该值的轨道
+    #[arg(long, value_name = "N", default_value = "0")]
+    min_iter: usize,
+
+    /// 每个点的最大迭代次数，到达该次数仍未逃逸的轨道不累积（--anti 时只累积这些轨道）
+    #[arg(long, value_name = "N", default_value = "1000", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 亮度映射的指数，小于 1 时提亮稀疏的轨道
+    #[arg(long, default_value = "0.5", value_parser = parser(|s| s.parse().ok().filter(|&g: &f64| g > 0.0 && g.is_finite()), "a positive number"))]
+    gamma: f64,
+
+    /// 密度到亮度的映射：gamma 按最大密度归一化后取 --gamma 次幂，log 先取对数以压缩极高的峰值；
+    /// 默认 --anti 时为 log，否则为 gamma
+    #[arg(long, value_name = "gamma|log", value_parser = parser(buddhabrot::parse_tone, "gamma or log"))]
+    tone: Option<Tone>,
+
+    /// 随机数种子，相同的种子和参数总是得到相同的图像
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// 采样 c 的方式：uniform 在整个集合范围内均匀采样；metropolis 用 Metropolis–Hastings
+    /// 方法把采样集中在轨道经过视图的区域，放大的视图收敛快得多
+    #[arg(long, value_name = "uniform|metropolis", default_value = "uniform", value_parser = parser(buddhabrot::parse_sampling, "uniform or metropolis"))]
+    sampling: Sampling,
+
+    /// 调色板：密度为零的像素取起点的颜色，最密的像素取终点的颜色
+    #[arg(long, default_value = "0:000000,1:ffffff", value_parser = palette_value)]
+    palette: Palette,
+
+    /// 渲染 Nebulabrot：红、绿、蓝三个通道分别累积逃逸次数在各自区间内的轨道，
+    /// 如 0..5000,0..500,0..50
+    #[arg(long, value_name = "R,G,B", conflicts_with_all = ["min_iter", "max_iter", "palette"], value_parser = parser(buddhabrot::parse_channels, "three MIN..MAX ranges, e.g. 0..5000,0..500,0..50"))]
+    channels: Option<[Range<usize>; 3]>,
+
+    /// 渲染反 Buddhabrot：只累积迭代 --max-iter 次仍未逃逸的轨道
+    #[arg(long, conflicts_with_all = ["channels", "min_iter"])]
+    anti: bool,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct NewtonArgs {
+    /// 输出的图像文件
+    output: String,
+
+    /// 多项式，如 z^3-1 或 z^4 - 3z^2 + 2，次数至少为 2
+    #[arg(long, value_name = "P", default_value = "z^3-1", value_parser = parser(newton::parse_polynomial, "a real polynomial in z of degree at least 2, e.g. z^3-1"))]
+    polynomial: Polynomial,
+
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1000x1000", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1000x1000"))]
+    size: (usize, usize),
+
+    /// 视图中心
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "0,0", value_parser = parser(parse_complex, "RE,IM"))]
+    center: Complex<f64>,
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖复平面中长度为 4 的范围
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 每个点的最大迭代次数，到达该次数仍未收敛的像素为黑色
+    #[arg(long, value_name = "N", default_value = "50", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct NovaArgs {
+    /// 输出的图像文件
+    output: String,
+
+    /// 迭代 z = z - R (z^p - 1) / (p z^(p-1)) + c 中多项式的次数 p，大于 1
+    #[arg(long, value_name = "P", default_value = "3", value_parser = parser(parse_power, "a number greater than 1"))]
+    power: f64,
+
+    /// 牛顿迭代的松弛系数 R
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "1,0", value_parser = parser(parse_complex, "RE,IM"))]
+    relaxation: Complex<f64>,
+
+    /// 迭代的起点 z0，给出 --c 时不使用
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "1,0", value_parser = parser(parse_complex, "RE,IM"))]
+    seed: Complex<f64>,
+
+    /// 给出时渲染以它为常数 c 的朱利亚类 Nova 分形，从像素对应的点出发
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(parse_complex, "RE,IM"))]
+    c: Option<Complex<f64>>,
+
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1000x750", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1000x750"))]
+    size: (usize, usize),
+
+    /// 视图中心
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.5,0", value_parser = parser(parse_complex, "RE,IM"))]
+    center: Complex<f64>,
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖复平面中长度为 4 的范围
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 每个点的最大迭代次数，到达该次数仍未收敛的像素取调色板的内部颜色
+    #[arg(long, value_name = "N", default_value = "100", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 判定收敛的距离：相邻两步的 z 相距小于它时停止迭代
+    #[arg(long, value_name = "EPS", default_value_t = nova::TOLERANCE, value_parser = parser(|s| s.parse().ok().filter(|f: &f64| f.is_finite() && *f > 0.0), "a positive number"))]
+    tolerance: f64,
+
+    /// 调色板，取值与 render 的 --palette 相同
+    #[arg(long, default_value = "fire", value_parser = palette_value)]
+    palette: Palette,
+
+    /// 按收敛次数的直方图均衡着色
+    #[arg(long)]
+    histogram: bool,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct SliceArgs {
+    /// 输出的图像文件；给出 --frames 时改为输出动画
+    #[arg(required_unless_present = "frames", conflicts_with = "frames")]
+    output: Option<String>,
+
+    /// 分形：quaternion（四维的四元数曼德博集）或 mandelbulb（三维的 triplex 乘方）
+    #[arg(long, value_name = "quaternion|mandelbulb", default_value = "quaternion", value_parser = parser(hypercomplex::parse_algebra, "`quaternion` or `mandelbulb`"))]
+    fractal: Algebra,
+
+    /// Mandelbulb 的次数，默认为 8
+    #[arg(long, value_name = "N", value_parser = parser(parse_power, "a number greater than 1"))]
+    power: Option<f64>,
+
+    /// 给出时渲染以它为常数 c 的朱利亚集，从像素对应的点出发
+    #[arg(long, value_name = "X,Y,Z[,W]", allow_hyphen_values = true, value_parser = parser(hypercomplex::parse_vector, "X,Y,Z or X,Y,Z,W"))]
+    julia: Option<Vector>,
+
+    /// 像素平面的原点在 c 空间（朱利亚集为起点空间）中对应的点
+    #[arg(long, value_name = "X,Y,Z[,W]", allow_hyphen_values = true, default_value = "0,0,0,0", value_parser = parser(hypercomplex::parse_vector, "X,Y,Z or X,Y,Z,W"))]
+    offset: Vector,
+
+    /// 切片的方向：把像素平面的两个轴依次在 x–z、y–z 和 z–w 平面内旋转的角度（度）；
+    /// 0,0,0 是前两个坐标轴张成的平面，0,90,0 是 x–z 平面
+    #[arg(long, value_name = "A,B,C", allow_hyphen_values = true, default_value = "0,0,0", value_parser = parser(hypercomplex::parse_orientation, "three angles A,B,C in degrees"))]
+    orientation: [f64; 3],
+
+    /// 用更大的逃逸半径计算连续逃逸时间，消除颜色的色带
+    #[arg(long)]
+    smooth: bool,
+
+    /// 每个点的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "100", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 渲染动画的帧数：切片的偏移和方向从 --offset、--orientation 线性移动到 --end-offset、--end-orientation
+    #[arg(long, value_name = "N", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    frames: Option<usize>,
+
+    /// 最后一帧的偏移，默认与 --offset 相同
+    #[arg(long, value_name = "X,Y,Z[,W]", allow_hyphen_values = true, requires = "frames", value_parser = parser(hypercomplex::parse_vector, "X,Y,Z or X,Y,Z,W"))]
+    end_offset: Option<Vector>,
+
+    /// 最后一帧的方向，默认与 --orientation 相同
+    #[arg(long, value_name = "A,B,C", allow_hyphen_values = true, requires = "frames", value_parser = parser(hypercomplex::parse_orientation, "three angles A,B,C in degrees"))]
+    end_orientation: Option<[f64; 3]>,
+
+    /// 动画帧所在的目录，帧按 frame_0000.png、frame_0001.png…… 编号，扩展名随 --format 改变
+    #[arg(long, value_name = "DIR", default_value = ".", requires = "frames")]
+    out_dir: String,
+
+    /// 把动画直接编码成视频文件（需要 ffmpeg）或循环动图 .gif、.png（APNG），而不是输出 PNG 帧
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "frames",
+        conflicts_with = "out_dir"
+    )]
+    out: Option<String>,
+
+    /// 视频的帧率
+    #[arg(long, default_value = "30", requires = "out", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    fps: usize,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    palette: PaletteArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+impl SliceArgs {
+    /// 第 `frame` 帧（共 `frames` 帧）的切片
+    fn hypercomplex_at(&self, frame: usize, frames: usize) -> Hypercomplex {
+        let start = Slice {
+            offset: self.offset,
+            orientation: self.orientation,
+        };
+        let end = Slice {
+            offset: self.end_offset.unwrap_or(self.offset),
+            orientation: self.end_orientation.unwrap_or(self.orientation),
+        };
+        let t = if frames > 1 {
+            frame as f64 / (frames - 1) as f64
+        } else {
+            0.0
+        };
+        let algebra = match (self.fractal, self.power) {
+            (Algebra::Triplex(_), Some(power)) => Algebra::Triplex(power),
+            (algebra, _) => algebra,
+        };
+        Hypercomplex {
+            algebra,
+            slice: start.interpolate(&end, t),
+            julia: self.julia,
+        }
+    }
+
+    /// 检查选项的组合：--power 只用于 mandelbulb，三维的 mandelbulb 不能使用 w 分量
+    fn check(&self) -> Result<(), MandelbrotError> {
+        if self.view.precise().is_some() || self.view.adaptive {
+            return Err(MandelbrotError::InvalidArgument(
+                "slice is not supported beyond f64 resolution or with --adaptive".to_string(),
+            ));
+        }
+        match self.fractal {
+            Algebra::Quaternion if self.power.is_some() => Err(MandelbrotError::InvalidArgument(
+                "--power is only supported for `mandelbulb`".to_string(),
+            )),
+            Algebra::Triplex(_)
+                if [Some(self.offset), self.end_offset, self.julia]
+                    .iter()
+                    .flatten()
+                    .any(|vector| vector[3] != 0.0)
+                    || [Some(self.orientation), self.end_orientation]
+                        .iter()
+                        .flatten()
+                        .any(|angles| angles[2] != 0.0) =>
+            {
+                Err(MandelbrotError::InvalidArgument(
+                    "`mandelbulb` is three-dimensional: W components and the z–w angle must be 0"
+                        .to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 渲染第 `frame` 帧（共 `frames` 帧）的（超采样）迭代缓冲区
+    fn render(&self, frame: usize, frames: usize, progress: &Progress) -> Vec<u32> {
+        let (bounds, transform) = self.view.supersampled().transform();
+        hypercomplex::render(
+            &self.hypercomplex_at(frame, frames),
+            self.max_iter,
+            self.smooth,
+            bounds,
+            transform,
+            progress,
+        )
+    }
+}
+
+#[derive(Args)]
+struct AtlasArgs {
+    /// 输出的图像文件
+    output: String,
+
+    /// 横向和纵向的格子数
+    #[arg(long, value_name = "NxM", default_value = "12x9", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(n, m)| n > 0 && m > 0), "COLUMNSxROWS, e.g. 12x9"))]
+    grid: (usize, usize),
+
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1200x900", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1200x900"))]
+    size: (usize, usize),
+
+    /// 参数平面中图谱的中心
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.75,0", value_parser = parser(parse_complex, "RE,IM"))]
+    center: Complex<f64>,
+
+    /// 参数平面的缩放倍数，为 1 时图像较短的一边覆盖参数平面中长度为 4 的范围
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 格子里朱利亚集的缩放倍数，它们都以原点为中心，为 1 时格子较短的一边覆盖长度为 4 的范围
+    #[arg(long, default_value = "1.3", value_parser = parser(parse_zoom, "a positive number"))]
+    julia_zoom: f64,
+
+    /// 格子之间分隔线的像素宽度，用调色板的内部颜色画出
+    #[arg(long, value_name = "N", default_value = "2")]
+    gap: usize,
+
+    /// 每个点的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "100", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 计算连续逃逸时间，消除颜色的色带
+    #[arg(long)]
+    smooth: bool,
+
+    /// 调色板，取值与 render 的 --palette 相同
+    #[arg(long, default_value = "fire", value_parser = palette_value)]
+    palette: Palette,
+
+    /// 按逃逸次数的直方图均衡着色
+    #[arg(long)]
+    histogram: bool,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct OrbitArgs {
+    /// 轨道的出发点：曼德博类分形中是常数 c，朱利亚类分形中是 z 的初值
+    #[arg(value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(parse_complex, "RE,IM"))]
+    point: Complex<f64>,
+
+    /// 输出的图像文件
+    #[arg(long, short, value_name = "FILE", default_value = "orbit.png")]
+    output: String,
+
+    /// 同时把轨道上的各个 z 导出到该文件：扩展名为 .json 时是 JSON，否则是 CSV
+    #[arg(long, value_name = "FILE")]
+    export: Option<String>,
+
+    /// 轨道的颜色
+    #[arg(long, value_name = "RRGGBB", default_value = "00ff00", value_parser = parser(parse_hex_color, "a hex color such as 00ff00"))]
+    orbit_color: [u8; 3],
+
+    /// 只画出迭代点，不用线段连接相邻的迭代点
+    #[arg(long)]
+    no_lines: bool,
+
+    /// 逃逸半径 R，不小于 2；给出时轨道和背景的图像都按它判定逃逸，默认轨道取 2，背景按
+    /// 着色方式取
+    #[arg(long, value_name = "R", value_parser = parser(|s| s.parse().ok().filter(|r: &f64| r.is_finite() && *r >= 2.0), "a number not less than 2"))]
+    bailout: Option<f64>,
+
+    /// 逃逸判定的范数：modulus（|z| > R）、real（|Re z| > R）或 manhattan（|Re z| + |Im z| > R）
+    #[arg(long, default_value = "modulus", value_parser = parser(parse_norm, "`modulus`, `real`, or `manhattan`"))]
+    norm: Norm,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+impl OrbitArgs {
+    fn bailout(&self) -> Option<Bailout> {
+        bailout(self.bailout, self.norm, &self.color)
+    }
+}
+
+#[derive(Args)]
+struct FindMinibrotArgs {
+    /// 迷你曼德博集附近的点，按原样以任意精度解析，可以给出超过 f64 精度的位数
+    #[arg(value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(|s| parse_complex(s).map(|_| s.to_string()), "RE,IM"))]
+    center: String,
+
+    /// 迷你曼德博集的周期，即核的轨道回到原点所需的迭代次数
+    #[arg(long, value_name = "P", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    period: usize,
+
+    /// 每种精度下牛顿法的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "64", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    max_steps: usize,
+}
+
+#[derive(Args)]
+struct FindMisiurewiczArgs {
+    /// 米休雷维奇点附近的点，按原样以任意精度解析，可以给出超过 f64 精度的位数
+    #[arg(value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(|s| parse_complex(s).map(|_| s.to_string()), "RE,IM"))]
+    center: String,
+
+    /// 预周期，即从原点出发的轨道进入环之前的迭代次数（z_0 = 0，至少为 2）
+    #[arg(long, value_name = "K", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n >= 2), "an integer of at least 2"))]
+    preperiod: usize,
+
+    /// 轨道最终进入的环的周期
+    #[arg(long, value_name = "P", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    period: usize,
+
+    /// 输出的十进制小数位数，求根的精度随之提高
+    #[arg(long, value_name = "N", default_value = "40", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    digits: usize,
+
+    /// 牛顿法的最大迭代次数
+    #[arg(long, value_name = "N", default_value = "64", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    max_steps: usize,
+}
+
+#[derive(Args)]
+struct ExploreArgs {
+    /// 目标缩放倍数
+    #[arg(long, default_value = "1e10", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 寻找的位置个数
+    #[arg(long, value_name = "N", default_value = "5", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    count: usize,
+
+    /// 起始视图的中心，按原样以任意精度解析
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.5,0", value_parser = parser(|s| parse_complex(s).map(|_| s.to_string()), "RE,IM"))]
+    center: String,
+
+    /// 起始视图的缩放倍数
+    #[arg(long, value_name = "ZOOM", default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    start_zoom: f64,
+
+    /// 缩略图所在的目录，按 location_01.png、location_02.png…… 编号，扩展名随 --format 改变
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    out_dir: String,
+
+    /// 缩略图的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "240x180", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 240x180"))]
+    thumbnail_size: (usize, usize),
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[derive(Args)]
+struct MeasureArgs {
+    /// 估计方式：monte-carlo（均匀随机采样）或 pixels（像素网格，每个像素内随机取一点）
+    #[arg(long, value_name = "monte-carlo|pixels", default_value = "monte-carlo", value_parser = parser(measure::parse_method, "`monte-carlo` or `pixels`"))]
+    method: Method,
+
+    /// 采样点数
+    #[arg(long, value_name = "N", default_value = "10000000", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    samples: usize,
+
+    /// 逗号分隔的一组严格递增的迭代次数限制，所有限制共用同一组采样点
+    #[arg(long, value_name = "N,...", default_value = "100,1000,10000", value_parser = parser(|s| s.split(',').map(|n| parse_max_iter(n.trim())).collect::<Option<Limits>>().filter(|limits| limits.windows(2).all(|pair| pair[0] < pair[1])), "comma-separated increasing positive integers"))]
+    max_iter: Limits,
+
+    /// 随机数种子，相同的种子总是得到相同的结果
+    #[arg(long, default_value = "0")]
+    seed: u64,
+}
+
+#[derive(Args)]
+struct LyapunovArgs {
+    /// 输出的图像文件
+    output: String,
+
+    /// 由 A 和 B 组成的序列，如 AB 或 AABAB，决定每一步的参数取横坐标 a 还是纵坐标 b
+    #[arg(long, default_value = "AB", value_parser = parser(lyapunov::parse_sequence, "a non-empty string of A and B, e.g. AABAB"))]
+    sequence: Sequence,
+
+    /// 图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "1000x1000", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 1000x1000"))]
+    size: (usize, usize),
+
+    /// 视图中心 a,b，a 和 b 都必须在 0 到 4 之间
+    #[arg(long, value_name = "A,B", default_value = "3,3", value_parser = parser(parse_complex, "A,B"))]
+    center: Complex<f64>,
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖长度为 4 的范围
+    #[arg(long, default_value = "2", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 计算指数前先迭代的次数，让轨道进入稳定状态
+    #[arg(long, value_name = "N", default_value = "200")]
+    warmup: usize,
+
+    /// 用来计算指数的迭代次数
+    #[arg(long, value_name = "N", default_value = "500", value_parser = parser(parse_max_iter, "a positive integer"))]
+    max_iter: usize,
+
+    /// 指数为负（稳定）的像素的渐变：指数接近零时取起点的颜色，越小越接近终点的颜色
+    #[arg(long, value_name = "PALETTE", default_value = "0:000000,1:ffd000", value_parser = palette_value)]
+    stable_palette: Palette,
+
+    /// 指数为正（混沌）的像素的渐变：指数接近零时取起点的颜色，越大越接近终点的颜色
+    #[arg(long, value_name = "PALETTE", default_value = "0:000000,1:2060ff", value_parser = palette_value)]
+    chaotic_palette: Palette,
+
+    #[command(flatten)]
+    image: ImageArgs,
+}
+
+#[cfg(feature = "viewer")]
+#[derive(Args)]
+struct ViewerArgs {
+    /// 窗口的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "800x600", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 800x600"))]
+    size: (usize, usize),
+
+    /// 初始的视图中心
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, default_value = "-0.5,0", value_parser = parser(parse_complex, "RE,IM"))]
+    center: Complex<f64>,
+
+    /// 初始的缩放倍数
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 按 E 导出的图像的像素尺寸
+    #[arg(long, value_name = "WxH", default_value = "3200x2400", value_parser = parser(|s| parse_pair::<usize>(s, 'x').filter(|&(w, h)| w > 0 && h > 0), "WIDTHxHEIGHT, e.g. 3200x2400"))]
+    export_size: (usize, usize),
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// 监听的地址
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// 监听的端口
+    #[arg(long, default_value = "8080")]
+    port: u16,
+
+    /// 缓存的瓦片数，每块瓦片是一张 256x256 的 PNG
+    #[arg(long, value_name = "N", default_value = "1024", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+    cache: usize,
+
+    #[command(flatten)]
+    fractal: FractalArgs,
+
+    #[command(flatten)]
+    color: ColorArgs,
+}
+
+#[derive(Args)]
+struct ApiArgs {
+    /// 监听的地址
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// 监听的端口
+    #[arg(long, default_value = "8081")]
+    port: u16,
+
+    /// 保存渲染结果的目录，默认为系统临时目录下的 mandelbrot-api；结果不会被自动删除
+    #[arg(long, value_name = "DIR")]
+    dir: Option<String>,
+}
+
+#[derive(Args)]
+struct WorkerArgs {
+    /// 监听的地址和端口
+    #[arg(long, value_name = "HOST:PORT", default_value = "127.0.0.1:7878")]
+    listen: String,
+}
+
+#[derive(Args)]
+struct QueueArgs {
+    /// 队列目录，默认为环境变量 MANDELBROT_QUEUE，否则为 ~/.mandelbrot/queue
+    #[arg(long, value_name = "DIR", global = true)]
+    dir: Option<String>,
+
+    #[command(subcommand)]
+    command: QueueCommand,
+}
+
+#[derive(Subcommand)]
+enum QueueCommand {
+    /// 把一条命令排入队列，如 queue add -- render out.png --zoom 5；相对路径相对于当前目录
+    Add {
+        /// 不含程序名的命令行
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// 列出队列中的任务、状态和失败的原因
+    List,
+    /// 按排入的顺序执行任务直到队列为空，上次被中断的任务重新执行
+    Run {
+        /// 同时执行的任务数，每个任务默认使用全部核心，可以在任务中用 --threads 限制
+        #[arg(long, value_name = "N", default_value = "1", value_parser = parser(|s| s.parse().ok().filter(|&n: &usize| n > 0), "a positive integer"))]
+        parallel: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum BookmarksCommand {
+    /// 列出内置位置和用户书签
+    List,
+    /// 保存一个用户书签，同名的书签会被替换
+    Add(AddBookmarkArgs),
+    /// 删除一个用户书签
+    Remove {
+        /// 书签的名称
+        name: String,
+    },
+}
+
+#[derive(Args)]
+struct AddBookmarkArgs {
+    /// 书签的名称
+    name: String,
+
+    /// 视图中心，按原样保存以保留全部精度
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parser(|s| parse_complex(s).map(|_| s.to_string()), "RE,IM"))]
+    center: String,
+
+    /// 默认的缩放倍数
+    #[arg(long, default_value = "1", value_parser = parser(parse_zoom, "a positive number"))]
+    zoom: f64,
+
+    /// 说明
+    #[arg(long, default_value = "")]
+    description: String,
+}
+
+/// 把返回 `Option` 的解析函数包装成 clap 的值解析器
+fn parser<T>(
+    parse: fn(&str) -> Option<T>,
+    expected: &'static str,
+) -> impl Fn(&str) -> Result<T, String> + Clone {
+    move |s| parse(s).ok_or_else(|| format!("expected {}", expected))
+}
+
+/// 调色板参数的值解析器：内置调色板的名字、渐变节点，或者 .map、.gpl、.ggr 调色板文件
+fn palette_value(s: &str) -> Result<Palette, String> {
+    if palette::is_palette_file(s) {
+        return palette::load_palette(Path::new(s));
+    }
+    parse_palette(s).ok_or_else(|| {
+        "expected a built-in palette name, POS:RRGGBB stops, or a .map, .gpl, or .ggr file"
+            .to_string()
+    })
+}
+
+/// 按 `view` 渲染迭代缓冲区，视图超出 `f64` 的分辨率时改用深度缩放的渲染方式
+///
+/// 第二个返回值说明了实际使用的深度缩放方式，普通渲染时为 `None`。`checkpoint` 只用于
+/// `f64` 范围内的分块渲染，`orbits` 只用于微扰渲染，在动画的各帧之间共享参考轨道。
+fn render_iterations(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    progress: &Progress,
+    checkpoint: Option<&Checkpoint>,
+    orbits: Option<&OrbitCache>,
+) -> (Vec<u32>, Option<String>) {
+    let (bounds, transform) = view.transform();
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+
+    let precise_view = view.precise();
+    // 深度缩放的渲染方式只支持部分分形和着色方式，其余情况只能用 f64 近似
+    let fallback =
+        precise_view.is_some() && (!fractal.supports_deep_zoom() || !coloring.supports_deep_zoom());
+    // 精度设置会先尝试双倍精度时，走到微扰或任意精度说明它也分辨不了视图
+    let resolution = match view.precision {
+        Precision::Extended | Precision::Auto => "double-double",
+        Precision::Single | Precision::Double => "f64",
+    };
+    let note = match precise_view.filter(|_| !fallback) {
+        Some((center, spacing))
+            if view
+                .precision
+                .arithmetic(center.to_complex(), spacing.to_f64())
+                == Arithmetic::Extended =>
+        {
+            precise::render_parallel::<DoubleComplex>(
+                fractal,
+                coloring,
+                limit,
+                &mut iterations,
+                bounds,
+                &center,
+                &spacing,
+                progress,
+            );
+            Some(
+                "pixel spacing is below f64 resolution, using double-double arithmetic".to_string(),
+            )
+        }
+        Some((center, spacing)) if view.no_perturbation => {
+            precise::render_parallel::<FixedComplex>(
+                fractal,
+                coloring,
+                limit,
+                &mut iterations,
+                bounds,
+                &center,
+                &spacing,
+                progress,
+            );
+            Some(format!(
+                "pixel spacing is below {} resolution, using {}-bit arbitrary precision",
+                resolution,
+                spacing.bits()
+            ))
+        }
+        Some((center, spacing)) => {
+            let references = perturbation::render_parallel_cached(
+                fractal,
+                coloring,
+                limit,
+                &mut iterations,
+                bounds,
+                &center,
+                spacing.to_f64(),
+                progress,
+                orbits.unwrap_or(&OrbitCache::new()),
+            );
+            Some(format!(
+                "pixel spacing is below {} resolution, used perturbation with {} {}-bit reference orbit(s)",
+                resolution,
+                references,
+                spacing.bits()
+            ))
+        }
+        None => {
+            let center = transform.point((bounds.0 / 2, bounds.1 / 2));
+            let single =
+                view.precision.arithmetic(center, transform.spacing()) == Arithmetic::Single;
+            if single {
+                render_parallel_checkpointed(
+                    fractal,
+                    coloring,
+                    limit,
+                    &mut iterations,
+                    bounds,
+                    transform.narrow::<f32>(),
+                    view.tile,
+                    view.subdivide,
+                    progress,
+                    checkpoint,
+                );
+            } else {
+                render_parallel_checkpointed(
+                    fractal,
+                    coloring,
+                    limit,
+                    &mut iterations,
+                    bounds,
+                    transform,
+                    view.tile,
+                    view.subdivide,
+                    progress,
+                    checkpoint,
+                );
+            }
+            if fallback {
+                Some(
+                    "deep zoom does not support this --power or --coloring, falling back to f64"
+                        .to_string(),
+                )
+            } else if view.precision == Precision::Single && !single {
+                Some("pixel spacing is below f32 resolution, falling back to f64".to_string())
+            } else if view.precision == Precision::Auto {
+                Some(if single {
+                    "pixel spacing is within f32 resolution, using f32".to_string()
+                } else {
+                    "pixel spacing is below f32 resolution, using f64".to_string()
+                })
+            } else {
+                None
+            }
+        }
+    };
+    (iterations, note)
+}
+
+/// 按 `view` 的分块、细分和精度设置渲染 `bounds` 大小、像素按 `transform` 映射的
+/// 迭代缓冲区，不报告进度；用于 dzi 的瓦片和 --stream 的条带这类整幅图像的一部分
+fn render_transformed(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+) -> Vec<u32> {
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    let center = transform.point((bounds.0 / 2, bounds.1 / 2));
+    if view.precision.arithmetic(center, transform.spacing()) == Arithmetic::Single {
+        render_parallel(
+            fractal,
+            coloring,
+            limit,
+            &mut iterations,
+            bounds,
+            transform.narrow::<f32>(),
+            view.tile,
+            view.subdivide,
+            &Progress::hidden(),
+        );
+    } else {
+        render_parallel(
+            fractal,
+            coloring,
+            limit,
+            &mut iterations,
+            bounds,
+            transform,
+            view.tile,
+            view.subdivide,
+            &Progress::hidden(),
+        );
+    }
+    iterations
+}
+
+/// render --opencl：在 OpenCL 设备上按 `view` 渲染迭代缓冲区，超采样时均匀超采样
+///
+/// 第二个返回值说明了使用的设备和精度。
+#[cfg(feature = "opencl")]
+fn render_opencl(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    with_cpu: bool,
+    progress: &Progress,
+) -> Result<(Vec<u32>, Option<String>), MandelbrotError> {
+    if view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--opencl is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if !gpu::supports(fractal, coloring) {
+        return Err(MandelbrotError::InvalidArgument(
+            "--opencl only supports mandelbrot, julia, burning-ship, tricorn and integer --power with escape-time or smooth coloring"
+                .to_string(),
+        ));
+    }
+    let mut device = OpenCl::new().map_err(MandelbrotError::Gpu)?;
+    let view = view.supersampled();
+    let (bounds, transform) = view.transform();
+    let center = transform.point((bounds.0 / 2, bounds.1 / 2));
+    let float = DeviceFloat::choose(
+        view.precision,
+        device.supports_double(),
+        center,
+        transform.spacing(),
+    )
+    .ok_or_else(|| {
+        MandelbrotError::InvalidArgument(format!(
+            "{} does not support f64 and the pixel spacing is below double-float resolution",
+            device.name()
+        ))
+    })?;
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    let mut note = format!(
+        "rendered on OpenCL device {} in {}",
+        device.name(),
+        float.name()
+    );
+    if with_cpu {
+        // OpenCl 只能在线程之间转移，不能共享，GPU 线程借用它的可变引用
+        let device = &mut device;
+        let shared = render_shared(
+            &view,
+            float == DeviceFloat::Single,
+            (fractal, coloring, limit),
+            &mut iterations,
+            progress,
+            move |tile| device.render_tile(float, fractal, coloring, limit, tile, transform),
+        )?;
+        note = format!("{}, {}", note, shared);
+    } else {
+        device
+            .render(
+                float,
+                fractal,
+                coloring,
+                limit,
+                &mut iterations,
+                bounds,
+                transform,
+                view.tile,
+                progress,
+            )
+            .map_err(MandelbrotError::Gpu)?;
+    }
+    Ok((iterations, Some(note)))
+}
+
+/// render --cuda：在 CUDA 设备上按 `view` 以 f64 渲染迭代缓冲区，超采样时均匀超采样
+///
+/// 第二个返回值说明了使用的设备。
+#[cfg(feature = "cuda")]
+fn render_cuda(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    with_cpu: bool,
+    progress: &Progress,
+) -> Result<(Vec<u32>, Option<String>), MandelbrotError> {
+    if view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--cuda is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if !gpu::supports(fractal, coloring) {
+        return Err(MandelbrotError::InvalidArgument(
+            "--cuda only supports mandelbrot, julia, burning-ship, tricorn and integer --power with escape-time or smooth coloring"
+                .to_string(),
+        ));
+    }
+    let device = Cuda::new().map_err(MandelbrotError::Gpu)?;
+    let view = view.supersampled();
+    let (bounds, transform) = view.transform();
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    let mut note = format!("rendered on CUDA device {} in f64", device.name());
+    if with_cpu {
+        let shared = render_shared(
+            &view,
+            false,
+            (fractal, coloring, limit),
+            &mut iterations,
+            progress,
+            |tile| device.render_tile(fractal, coloring, limit, tile, transform),
+        )?;
+        note = format!("{}, {}", note, shared);
+    } else {
+        device
+            .render(
+                fractal,
+                coloring,
+                limit,
+                &mut iterations,
+                bounds,
+                transform,
+                view.tile,
+                progress,
+            )
+            .map_err(MandelbrotError::Gpu)?;
+    }
+    Ok((iterations, Some(note)))
+}
+
+/// render --with-cpu：GPU 渲染分块的 `device` 和 CPU 线程池一起按 `view` 渲染迭代缓冲区
+///
+/// `view` 已经超采样，`single` 为真时 CPU 与 GPU 一样用 f32 计算，否则用 f64。返回说明
+/// 两边各渲染了多少分块的文字。
+#[cfg(any(feature = "opencl", feature = "cuda"))]
+fn render_shared(
+    view: &ViewArgs,
+    single: bool,
+    (fractal, coloring, limit): (Fractal, Coloring, usize),
+    iterations: &mut [u32],
+    progress: &Progress,
+    device: impl FnMut(Tile) -> Result<Vec<u32>, String> + Send,
+) -> Result<String, MandelbrotError> {
+    let (bounds, transform) = view.transform();
+    let on_device = if single {
+        mandelbrot::render_hybrid(
+            fractal,
+            coloring,
+            limit,
+            iterations,
+            bounds,
+            transform.narrow::<f32>(),
+            view.tile,
+            view.subdivide,
+            progress,
+            device,
+        )
+    } else {
+        mandelbrot::render_hybrid(
+            fractal,
+            coloring,
+            limit,
+            iterations,
+            bounds,
+            transform,
+            view.tile,
+            view.subdivide,
+            progress,
+            device,
+        )
+    }
+    .map_err(MandelbrotError::Gpu)?;
+    let tiles = tile::tiles(bounds, view.tile).len();
+    Ok(format!(
+        "{} of {} tiles on the GPU and {} on the CPU",
+        on_device,
+        tiles,
+        tiles - on_device
+    ))
+}
+
+/// 按 `view` 用内部着色或自定逃逸判定的 `colorizer` 渲染迭代缓冲区，超采样时均匀超采样
+///
+/// 总是用 `f64` 计算，因此忽略 --precision。
+fn render_colorizer<C: Colorizer>(
+    view: &ViewArgs,
+    fractal: Fractal,
+    colorizer: C,
+    limit: usize,
+    progress: &Progress,
+) -> Vec<u32> {
+    let view = view.supersampled();
+    let (bounds, transform) = view.transform();
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    render_parallel(
+        fractal,
+        colorizer,
+        limit,
+        &mut iterations,
+        bounds,
+        transform,
+        view.tile,
+        view.subdivide,
+        progress,
+    );
+    iterations
+}
+
+/// 按 `view` 渲染迭代缓冲区，启用超采样时缓冲区的每个方向都放大 `view.samples` 倍
+///
+/// 自适应抗锯齿要按着色后的颜色判断边界像素，因此也需要 `color` 中的调色板。
+/// 自适应抗锯齿时 `checkpoint` 只保存第一遍每像素一个采样的渲染。
+fn render_samples(
+    view: &ViewArgs,
+    fractal: Fractal,
+    color: &ColorArgs,
+    limit: usize,
+    progress: &Progress,
+    checkpoint: Option<&Checkpoint>,
+    orbits: Option<&OrbitCache>,
+) -> (Vec<u32>, Option<String>) {
+    let coloring = color.coloring();
+    if view.samples == 1 {
+        return render_iterations(view, fractal, coloring, limit, progress, checkpoint, orbits);
+    }
+
+    // 自适应抗锯齿需要逐个子像素用 f64 计算，深度缩放时退回到均匀超采样
+    let deep = view.precise().is_some()
+        && fractal.supports_deep_zoom()
+        && color.coloring().supports_deep_zoom();
+    if !view.adaptive || deep {
+        return render_iterations(
+            &view.supersampled(),
+            fractal,
+            coloring,
+            limit,
+            progress,
+            checkpoint,
+            orbits,
+        );
+    }
+    let bounds = view.size;
+    let (iterations, note) =
+        render_iterations(view, fractal, coloring, limit, progress, checkpoint, orbits);
+    let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+    color.palette.colorize(&iterations, limit, &mut pixels);
+    let edges = antialias::edge_pixels(&pixels, bounds, view.adaptive_threshold);
+    let (_, transform) = view.transform();
+    let samples = antialias::refine(
+        fractal,
+        color.coloring(),
+        limit,
+        &iterations,
+        &edges,
+        view.samples,
+        bounds,
+        transform,
+        progress,
+    );
+    (samples, note)
+}
+
+/// 为 `render_samples` 得到的缓冲区着色，超采样时再缩小到 `bounds` 大小
+///
+/// 渲染被中断时把未完成的像素标记为棋盘格。
+fn colorize_samples<C: Channel>(
+    iterations: &[u32],
+    samples: usize,
+    bounds: (usize, usize),
+    palette: &PaletteArgs,
+    limit: usize,
+) -> Vec<C> {
+    let mut pixels = vec![C::default(); bounds.0 * bounds.1 * 3];
+    if samples == 1 {
+        palette.colorize(iterations, limit, &mut pixels);
+        palette.shade(iterations, bounds.0, &mut pixels);
+    } else {
+        let mut colors = vec![C::default(); iterations.len() * 3];
+        palette.colorize(iterations, limit, &mut colors);
+        palette.shade(iterations, bounds.0 * samples, &mut colors);
+        downsample(&colors, samples, &mut pixels, bounds);
+    }
+    if cancelled() {
+        mark_unfinished(iterations, samples, &mut pixels, bounds);
+    }
+    pixels
+}
+
+/// 为 `render_samples` 得到的缓冲区着色，按 --bit-depth 选择的位数写入 `filename`
+fn write_colorized(
+    image: &ImageArgs,
+    filename: &str,
+    iterations: &[u32],
+    samples: usize,
+    bounds: (usize, usize),
+    palette: &PaletteArgs,
+    limit: usize,
+) -> Result<(), MandelbrotError> {
+    write_overlaid(
+        image, filename, iterations, samples, bounds, palette, limit, None,
+    )
+}
+
+/// 与 `write_colorized` 相同，但给了 `overlay` 时把掩码中的像素涂成它给出的颜色
+#[allow(clippy::too_many_arguments)]
+fn write_overlaid(
+    image: &ImageArgs,
+    filename: &str,
+    iterations: &[u32],
+    samples: usize,
+    bounds: (usize, usize),
+    palette: &PaletteArgs,
+    limit: usize,
+    overlay: Option<(&[bool], [u8; 3])>,
+) -> Result<(), MandelbrotError> {
+    let format = image.format(Some(filename))?;
+    palette.check_alpha(format)?;
+    let result = if image.bit_depth == 16 {
+        let pixels = colorize_samples(iterations, samples, bounds, palette, limit);
+        let mut pixels = palette.with_alpha(iterations, samples, bounds, pixels);
+        if let Some((mask, color)) = overlay {
+            paint_overlay::<u16>(&mut pixels, mask, color);
+        }
+        write_image16(filename, &pixels, bounds, format)
+    } else {
+        let pixels = colorize_samples(iterations, samples, bounds, palette, limit);
+        let mut pixels = palette.with_alpha(iterations, samples, bounds, pixels);
+        if let Some((mask, color)) = overlay {
+            paint_overlay::<u8>(&mut pixels, mask, color);
+        }
+        write_image(filename, &pixels, bounds, format)
+    };
+    result.map_err(MandelbrotError::writing(filename))
+}
+
+/// 把掩码 `mask` 中的像素涂成 `color`，有 alpha 通道时同时设为不透明
+fn paint_overlay<C: Channel>(pixels: &mut [C], mask: &[bool], color: [u8; 3]) {
+    let channels = pixels.len() / mask.len();
+    let color = color.map(|channel| C::from_fraction(channel as f64 / 255.0));
+    for (pixel, _) in pixels
+        .chunks_mut(channels)
+        .zip(mask)
+        .filter(|(_, &set)| set)
+    {
+        pixel[..3].copy_from_slice(&color);
+        if channels == 4 {
+            pixel[3] = C::from_fraction(1.0);
+        }
+    }
+}
+
+/// 分布式渲染时每个条带的（超采样后的）行数
+const REMOTE_STRIP_ROWS: usize = 64;
+
+/// 任务中表示视图的 JSON；中心保留原始文本，深度缩放时工作进程按原样以任意精度解析
+///
+/// 只用于已经展开超采样的视图，不包含 --samples 和自适应抗锯齿。
+fn view_value(view: &ViewArgs) -> Value {
+    let corners = view
+        .upper_left
+        .zip(view.lower_right)
+        .map(|(upper_left, lower_right)| {
+            [
+                remote::complex_value(upper_left),
+                remote::complex_value(lower_right),
+            ]
+        });
+    json!({
+        "size": [view.size.0, view.size.1],
+        "corners": corners,
+        "center": view.center,
+        "zoom": view.zoom(),
+        "rotate": view.rotate,
+        "tile": [view.tile.0, view.tile.1],
+        "subdivide": view.subdivide,
+        "no_perturbation": view.no_perturbation,
+        "precision": match view.precision {
+            Precision::Single => "single",
+            Precision::Double => "double",
+            Precision::Extended => "double-double",
+            Precision::Auto => "auto",
+        },
+    })
+}
+
+/// 从 `view_value` 的结果还原视图
+fn view_from(value: &Value) -> Result<ViewArgs, String> {
+    let invalid = || format!("invalid view {}", value);
+    let size = |value: &Value| match value.as_array()?.as_slice() {
+        [width, height] => Some((width.as_u64()? as usize, height.as_u64()? as usize))
+            .filter(|&(width, height)| width > 0 && height > 0),
+        _ => None,
+    };
+    let corners = match &value["corners"] {
+        Value::Null => None,
+        corners => match corners.as_array().map(Vec::as_slice) {
+            Some([upper_left, lower_right]) => Some((
+                remote::complex_from(upper_left).ok_or_else(invalid)?,
+                remote::complex_from(lower_right).ok_or_else(invalid)?,
+            )),
+            _ => return Err(invalid()),
+        },
+    };
+    Ok(ViewArgs {
+        size: size(&value["size"]).ok_or_else(invalid)?,
+        upper_left: corners.map(|(upper_left, _)| upper_left),
+        lower_right: corners.map(|(_, lower_right)| lower_right),
+        center: value["center"]
+            .as_str()
+            .filter(|center| parse_complex(center).is_some())
+            .ok_or_else(invalid)?
+            .to_string(),
+        zoom: Some(
+            value["zoom"]
+                .as_f64()
+                .filter(|&zoom| zoom > 0.0)
+                .ok_or_else(invalid)?,
+        ),
+        location: None,
+        rotate: value["rotate"].as_f64().ok_or_else(invalid)?,
+        tile: size(&value["tile"]).ok_or_else(invalid)?,
+        subdivide: value["subdivide"].as_bool().ok_or_else(invalid)?,
+        samples: 1,
+        adaptive: false,
+        adaptive_threshold: 16,
+        no_perturbation: value["no_perturbation"].as_bool().ok_or_else(invalid)?,
+        precision: value["precision"]
+            .as_str()
+            .and_then(parse_precision)
+            .ok_or_else(invalid)?,
+    })
+}
+
+/// 渲染已经展开超采样的 `view` 的任务，给出 `rows` 时只渲染这些行；同时返回结果中逃逸值的个数
+fn remote_job(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    rows: Option<Range<usize>>,
+) -> (Value, usize) {
+    let len = view.size.0 * rows.as_ref().map_or(view.size.1, |rows| rows.len());
+    let job = json!({
+        "view": view_value(view),
+        "fractal": remote::fractal_value(fractal),
+        "coloring": remote::coloring_value(coloring),
+        "max_iter": limit,
+        "rows": rows.map(|rows| [rows.start, rows.end]),
+    });
+    (job, len)
+}
+
+/// 工作进程执行 `remote_job` 给出的任务
+fn run_job(job: &Value) -> Result<Vec<u32>, String> {
+    let view = view_from(&job["view"])?;
+    let fractal = remote::fractal_from(&job["fractal"])?;
+    let coloring = remote::coloring_from(&job["coloring"])?;
+    let limit = job["max_iter"]
+        .as_u64()
+        .filter(|&limit| limit > 0)
+        .ok_or_else(|| format!("invalid max_iter {}", job["max_iter"]))? as usize;
+    // 任务中的视图已经展开了超采样
+    let (width, height) = view.size;
+    if width
+        .checked_mul(height)
+        .is_none_or(|pixels| pixels > remote::MAX_JOB_PIXELS)
+    {
+        return Err(format!(
+            "at most {} pixels per job are supported",
+            remote::MAX_JOB_PIXELS
+        ));
+    }
+    if job["rows"].is_null() {
+        let hidden = Progress::hidden();
+        return Ok(render_iterations(&view, fractal, coloring, limit, &hidden, None, None).0);
+    }
+    let (bounds, transform) = view.transform();
+    let rows = match job["rows"].as_array().map(Vec::as_slice) {
+        Some([start, end]) => start.as_u64().zip(end.as_u64()),
+        _ => None,
+    }
+    .map(|(start, end)| start as usize..end as usize)
+    .filter(|rows| rows.start < rows.end && rows.end <= bounds.1)
+    .ok_or_else(|| format!("invalid rows {}", job["rows"]))?;
+    let strip_transform = PixelTransform {
+        origin: transform.point((0, rows.start)),
+        ..transform
+    };
+    Ok(render_transformed(
+        &view,
+        fractal,
+        coloring,
+        limit,
+        (bounds.0, rows.len()),
+        strip_transform,
+    ))
+}
+
+/// render --workers：把展开超采样的视图按条带分给工作进程，拼成与 `render_samples` 相同的缓冲区
+///
+/// 超出 `f64` 分辨率的视图要整体做深度缩放，只作为一个任务交给一个工作进程。
+fn render_remote(
+    view: &ViewArgs,
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    workers: &[String],
+    progress: &Progress,
+) -> Result<Vec<u32>, MandelbrotError> {
+    let view = view.supersampled();
+    let (width, height) = view.size;
+    let whole = view.precise().is_some();
+    let strips: Vec<Range<usize>> = if whole {
+        std::iter::once(0..height).collect()
+    } else {
+        (0..height)
+            .step_by(REMOTE_STRIP_ROWS)
+            .map(|top| top..(top + REMOTE_STRIP_ROWS).min(height))
+            .collect()
+    };
+    let jobs: Vec<(Value, usize)> = strips
+        .iter()
+        .map(|rows| {
+            remote_job(
+                &view,
+                fractal,
+                coloring,
+                limit,
+                (!whole).then(|| rows.clone()),
+            )
+        })
+        .collect();
+    let mut iterations = vec![0; width * height];
+    progress.start(strips.len(), "strips");
+    remote::dispatch(workers, &jobs, |index, values| {
+        let rows = &strips[index];
+        iterations[rows.start * width..rows.end * width].copy_from_slice(&values);
+        progress.inc(1);
+        Ok(())
+    })?;
+    progress.finish();
+    Ok(iterations)
+}
+
+/// 保证只安装一次 Ctrl-C 的处理函数
+static INTERRUPT_HANDLER: Once = Once::new();
+
+/// 第一次 Ctrl-C 让渲染停止分派新的分块，已完成的部分照常写出；第二次直接结束进程
+fn install_interrupt_handler() {
+    INTERRUPT_HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if cancelled() {
+                std::process::exit(130);
+            }
+            cancel();
+        });
+        if let Err(err) = result {
+            eprintln!("warning: cannot handle Ctrl-C: {}", err);
+        }
+    });
+}
+
+fn render(args: &RenderArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let output = args.output.as_deref().ok_or_else(|| {
+        MandelbrotError::InvalidArgument(
+            "no output file given on the command line or in the config file".to_string(),
+        )
+    })?;
+    // 渲染之前先检查输出格式，避免白白渲染
+    let format = args.image.format(Some(output))?;
+    args.color.palette.check_alpha(format)?;
+    if args.color.palette.transparent.is_some()
+        && (args.color.coloring().colors_interior() || args.interior != Interior::Flat)
+    {
+        return Err(MandelbrotError::InvalidArgument(
+            "--transparent cannot tell the interior apart when the interior is colored".to_string(),
+        ));
+    }
+    if args.stream {
+        return render_streamed(args, output, quiet);
+    }
+    if args.checkpoint.is_some() && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--checkpoint is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.exr_distance && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--exr-distance is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.view.rotate != 0.0 && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rotate is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.interior != Interior::Flat {
+        if args.color.coloring().colors_interior() {
+            return Err(MandelbrotError::InvalidArgument(
+                "--interior cannot be combined with --coloring orbit-trap or atom-domain, which already color the interior"
+                    .to_string(),
+            ));
+        }
+        if args.view.precise().is_some() || args.view.adaptive {
+            return Err(MandelbrotError::InvalidArgument(
+                "--interior is not supported beyond f64 resolution or with --adaptive".to_string(),
+            ));
+        }
+    }
+    if args.budget.is_some() && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--budget is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.budget.is_some() && args.bailout().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--budget cannot be combined with --bailout or --norm".to_string(),
+        ));
+    }
+    if args.bailout().is_some() && (args.view.precise().is_some() || args.view.adaptive) {
+        return Err(MandelbrotError::InvalidArgument(
+            "--bailout and --norm are not supported beyond f64 resolution or with --adaptive"
+                .to_string(),
+        ));
+    }
+    if args.export_histogram.is_some() {
+        if !args.color.coloring().counts_iterations() {
+            return Err(histogram_unsupported(args.color.coloring()));
+        }
+        // 内部着色给内部像素也编码了逃逸值，它们会被当成逃逸的像素统计
+        if args.interior != Interior::Flat {
+            return Err(MandelbrotError::InvalidArgument(
+                "--export-histogram cannot be combined with --interior".to_string(),
+            ));
+        }
+    }
+    // 自适应抗锯齿要先着色才知道哪些像素需要细分，工作进程只返回逃逸值
+    if args.workers.is_some() && args.view.adaptive {
+        return Err(MandelbrotError::InvalidArgument(
+            "--adaptive is not supported with --workers".to_string(),
+        ));
+    }
+    let fractal = args.fractal.fractal(&args.color)?;
+    if args.interior != Interior::Flat && !fractal.supports_interior() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--interior is not supported for phoenix or hybrid fractals".to_string(),
+        ));
+    }
+    let overlaid = args.rays.is_some() || args.equipotentials.is_some();
+    if overlaid && !rays::supports(&fractal) {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rays and --equipotentials are only supported for `mandelbrot` and `julia`"
+                .to_string(),
+        ));
+    }
+    if overlaid && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rays and --equipotentials are not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    let limit = resolve_limit(&args.view, fractal, args.fractal.max_iter, quiet);
+    let want_stats = args.stats || args.stats_json.is_some();
+    // 需要统计时进度条顺便记录每个线程的忙碌时间
+    let timed = |progress: Progress| {
+        if want_stats {
+            progress.with_thread_timing()
+        } else {
+            progress
+        }
+    };
+    let progress = timed(Progress::new(!quiet));
+    // 深度缩放逐行渲染，不支持中途停止，Ctrl-C 照常直接结束进程；分布式渲染、平移复用和限时渲染也一样
+    if args.view.precise().is_none()
+        && args.workers.is_none()
+        && args.pan_from.is_none()
+        && args.budget.is_none()
+    {
+        install_interrupt_handler();
+    }
+    let checkpoint = match &args.checkpoint {
+        Some(filename) => {
+            let view = &args.view;
+            let fingerprint = checkpoint::fingerprint(&format!(
+                "{:?} {:?} {} {:?} {} {} {:?} {} {:?}",
+                fractal,
+                args.color.coloring(),
+                limit,
+                view.transform(),
+                view.samples,
+                view.adaptive,
+                view.tile,
+                view.subdivide,
+                view.precision
+            ));
+            let checkpoint =
+                Checkpoint::open(Path::new(filename), fingerprint, view.tile, args.resume)
+                    .map_err(MandelbrotError::reading(filename))?;
+            if !quiet && !checkpoint.is_empty() {
+                eprintln!("resuming with {} tiles from {}", checkpoint.len(), filename);
+            }
+            Some(checkpoint)
+        }
+        None => None,
+    };
+    let started = Instant::now();
+    // 限时渲染逐步提高迭代次数，最后完成的阶段的迭代次数才是着色等使用的限制
+    let (iterations, note, limit, threads) = if let Some(budget) = args.budget {
+        let hidden = timed(Progress::hidden());
+        let (iterations, limit) = render_budgeted(
+            &args.view,
+            fractal,
+            &args.color,
+            limit,
+            budget,
+            &hidden,
+            quiet,
+        );
+        (iterations, None, limit, hidden.thread_times())
+    } else {
+        let (iterations, note) = match (&args.workers, &args.pan_from) {
+            (Some(workers), _) => {
+                let coloring = args.color.coloring();
+                let iterations =
+                    render_remote(&args.view, fractal, coloring, limit, workers, &progress)?;
+                (iterations, None)
+            }
+            (None, Some(filename)) => render_panned_from(args, fractal, limit, filename)?,
+            #[cfg(feature = "opencl")]
+            (None, None) if args.opencl => render_opencl(
+                &args.view,
+                fractal,
+                args.color.coloring(),
+                limit,
+                args.with_cpu,
+                &progress,
+            )?,
+            #[cfg(feature = "cuda")]
+            (None, None) if args.cuda => render_cuda(
+                &args.view,
+                fractal,
+                args.color.coloring(),
+                limit,
+                args.with_cpu,
+                &progress,
+            )?,
+            (None, None) if args.interior != Interior::Flat => {
+                let colorizer = InteriorColoring {
+                    exterior: args.color.coloring(),
+                    interior: args.interior,
+                };
+                let iterations = render_colorizer(&args.view, fractal, colorizer, limit, &progress);
+                (iterations, None)
+            }
+            (None, None) => match args.bailout() {
+                Some(bailout) => {
+                    let colorizer = WithBailout {
+                        colorizer: args.color.coloring(),
+                        bailout,
+                    };
+                    let iterations =
+                        render_colorizer(&args.view, fractal, colorizer, limit, &progress);
+                    (iterations, None)
+                }
+                None => render_samples(
+                    &args.view,
+                    fractal,
+                    &args.color,
+                    limit,
+                    &progress,
+                    checkpoint.as_ref(),
+                    None,
+                ),
+            },
+        };
+        (iterations, note, limit, progress.thread_times())
+    };
+    if let Some(filename) = &args.export_histogram {
+        write_histogram(filename, &EscapeHistogram::collect(&iterations, limit))?;
+    }
+    let stats = want_stats.then(|| {
+        let seconds = started.elapsed().as_secs_f64();
+        RenderStats::collect(&iterations, limit, seconds, threads)
+    });
+    if let (Some(note), false) = (note, quiet) {
+        eprintln!("{}", note);
+    }
+    let bounds = args.view.size;
+    let samples = args.view.samples;
+    let mask = overlaid.then(|| {
+        let (_, transform) = args.view.transform();
+        rays::overlay(
+            &fractal,
+            args.rays.as_deref().unwrap_or_default(),
+            args.equipotentials.as_deref().unwrap_or_default(),
+            args.ray_depth,
+            limit,
+            bounds,
+            &transform,
+        )
+    });
+    write_overlaid(
+        &args.image,
+        output,
+        &iterations,
+        samples,
+        bounds,
+        &args.color.palette,
+        limit,
+        mask.as_deref().map(|mask| (mask, args.overlay_color)),
+    )?;
+    // 其余输出都是数值数据，未完成的像素会被误读为集合内部，因此不再写出
+    if cancelled() {
+        if let (Some(filename), false) = (&args.checkpoint, quiet) {
+            eprintln!(
+                "run again with --resume to finish the remaining tiles from {}",
+                filename
+            );
+        }
+        return Err(MandelbrotError::Interrupted {
+            path: output.to_string(),
+        });
+    }
+
+    if let Some(filename) = &args.exr {
+        let (sample_bounds, transform) = args.view.supersampled().transform();
+        let mut channels = vec![("escape", openexr::escape_values(&iterations))];
+        if args.exr_distance {
+            let distances = openexr::distance_estimates(fractal, limit, sample_bounds, transform);
+            channels.push(("distance", distances));
+        }
+        openexr::write_exr(filename, sample_bounds, channels)
+            .map_err(MandelbrotError::writing(filename))?;
+    }
+
+    let description = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    if let Some(filename) = &args.export_npy {
+        let (sample_bounds, upper_left, lower_right) = args.view.supersampled().corners();
+        let metadata = NpyMetadata {
+            bounds: sample_bounds,
+            samples,
+            limit,
+            coloring: args.color.coloring(),
+            upper_left,
+            lower_right,
+            rotate: args.view.rotate,
+            description: description.clone(),
+        };
+        npy::write_npy(filename, &iterations, &metadata)
+            .map_err(MandelbrotError::writing(filename))?;
+    }
+
+    if let Some(filename) = &args.save_data {
+        let data = IterationData {
+            bounds: (bounds.0 * samples, bounds.1 * samples),
+            samples,
+            limit,
+            coloring: args.color.coloring(),
+            view: saved_view(args, fractal, limit),
+            description,
+            iterations,
+        };
+        data.save(filename)
+            .map_err(MandelbrotError::writing(filename))?;
+    }
+
+    if let (Some(checkpoint), Some(filename)) = (checkpoint, &args.checkpoint) {
+        checkpoint
+            .finish()
+            .map_err(MandelbrotError::writing(filename))?;
+    }
+
+    if let Some(stats) = stats {
+        // 图像写到标准输出时统计改为打印到标准错误，不混进图像数据
+        if args.stats && output == STDOUT {
+            eprint!("{}", stats.to_text());
+        } else if args.stats {
+            print!("{}", stats.to_text());
+        }
+        if let Some(filename) = &args.stats_json {
+            std::fs::write(filename, stats.to_json())
+                .map_err(MandelbrotError::writing(filename))?;
+        }
+    }
+    Ok(())
+}
+
+/// 在 `budget` 时间内按 `budget::stages` 逐步渲染，返回最后完成的阶段的迭代缓冲区和迭代次数
+///
+/// 第一个阶段总是会完成，即使它已经超出了预算。各阶段都在不显示的进度条 `hidden` 上渲染，
+/// 它可以带着计时器。
+fn render_budgeted(
+    view: &ViewArgs,
+    fractal: Fractal,
+    color: &ColorArgs,
+    max_limit: usize,
+    budget: Duration,
+    hidden: &Progress,
+    quiet: bool,
+) -> (Vec<u32>, usize) {
+    let deadline = Instant::now() + budget;
+    let coloring = color.coloring();
+    let (bounds, transform) = view.transform();
+    let mut progressive = Progressive::new(bounds);
+    let mut previous: Option<(Stage, Duration)> = None;
+    let mut best = None;
+    for stage in budget::stages(max_limit, view.samples == 1) {
+        if let Some(previous) = previous {
+            if Instant::now() + budget::predict(previous, stage) > deadline {
+                break;
+            }
+        }
+        let started = Instant::now();
+        let iterations = match stage {
+            Stage::Pass { limit, .. } => {
+                progressive.refine(fractal, coloring, limit, transform);
+                progressive.iterations().to_vec()
+            }
+            Stage::Full { limit } => {
+                render_samples(view, fractal, color, limit, hidden, None, None).0
+            }
+        };
+        previous = Some((stage, started.elapsed()));
+        best = Some((stage, iterations));
+    }
+    let (stage, iterations) = best.expect("there is at least one stage");
+    if !quiet {
+        let resolution = match stage.step() {
+            1 => "full resolution".to_string(),
+            step => format!("1/{} resolution", step),
+        };
+        eprintln!(
+            "--budget: finished at {} with {} iterations",
+            resolution,
+            stage.limit()
+        );
+    }
+    (iterations, stage.limit())
+}
+
+/// `--max-iter` 实际使用的迭代次数，取 auto 时在宽 `maxiter::PROBE_WIDTH` 的试渲染上选择
+fn resolve_limit(view: &ViewArgs, fractal: Fractal, max_iter: MaxIter, quiet: bool) -> usize {
+    let MaxIter::Fixed(limit) = max_iter else {
+        let probe = ViewArgs {
+            size: (
+                maxiter::PROBE_WIDTH,
+                (maxiter::PROBE_WIDTH * view.size.1 / view.size.0).max(1),
+            ),
+            samples: 1,
+            adaptive: false,
+            ..view.clone()
+        };
+        let hidden = Progress::hidden();
+        let limit = maxiter::auto_limit(view.zoom(), |limit| {
+            render_iterations(
+                &probe,
+                fractal,
+                Coloring::EscapeTime,
+                limit,
+                &hidden,
+                None,
+                None,
+            )
+            .0
+        });
+        if !quiet {
+            eprintln!("--max-iter auto: using {} iterations", limit);
+        }
+        return limit;
+    };
+    limit
+}
+
+/// `--save-data` 保存的视图，`--pan-from` 只复用指纹相同的缓冲区；深度缩放时没有
 ///
-/// 如果 `c` 不是集合成员之一，则返回 `Some(i)`，其中 `i` 是 `c` 离开以原点
-/// 为中心的半径为 2 的圆时所需的迭代次数。如果 `c` 似乎是集群成员之一（确
-/// 切而言是达到了迭代次数限制但仍然无法证明 `c` 不是成员），则返回 `None`
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+/// 指纹包括内部着色方式、逃逸半径和范数，它们都会改变缓冲区中的值。
+fn saved_view(args: &RenderArgs, fractal: Fractal, limit: usize) -> Option<SavedView> {
+    let view = &args.view;
+    if view.precise().is_some() {
+        return None;
+    }
+    let fingerprint = checkpoint::fingerprint(&format!(
+        "{:?} {:?} {:?} {:?} {} {} {:?}",
+        fractal,
+        args.color.coloring(),
+        args.interior,
+        args.bailout(),
+        limit,
+        view.samples,
+        view.precision
+    ));
+    let (_, transform) = view.supersampled().transform();
+    Some(SavedView {
+        fingerprint,
+        transform,
+    })
+}
+
+/// --pan-from：从保存的缓冲区平移得到新视图的缓冲区，只计算新露出的像素
+fn render_panned_from(
+    args: &RenderArgs,
+    fractal: Fractal,
+    limit: usize,
+    filename: &str,
+) -> Result<(Vec<u32>, Option<String>), MandelbrotError> {
+    let (view, coloring) = (&args.view, args.color.coloring());
+    let Some(saved) = saved_view(args, fractal, limit) else {
+        return Err(MandelbrotError::InvalidArgument(
+            "--pan-from is not supported beyond f64 resolution".to_string(),
+        ));
+    };
+    let data = IterationData::load(filename).map_err(MandelbrotError::reading(filename))?;
+    let (bounds, transform) = view.supersampled().transform();
+    let old = data.view.ok_or_else(|| {
+        MandelbrotError::InvalidArgument(format!(
+            "{} does not record its view, render it again with --save-data",
+            filename
+        ))
+    })?;
+    if old.fingerprint != saved.fingerprint || data.bounds != bounds {
+        return Err(MandelbrotError::InvalidArgument(format!(
+            "{} was rendered with a different fractal, size, coloring, interior, bailout, limit, sampling or precision",
+            filename
+        )));
+    }
+    let offset = pixel_offset(&old.transform, &transform).ok_or_else(|| {
+        MandelbrotError::InvalidArgument(format!(
+            "the view is not a whole-pixel pan of the view in {}",
+            filename
+        ))
+    })?;
+
+    let center = transform.point((bounds.0 / 2, bounds.1 / 2));
+    let single = view.precision.arithmetic(center, transform.spacing()) == Arithmetic::Single;
+    let (iterations, computed) = if single {
+        let transform = transform.narrow::<f32>();
+        render_panned(
+            fractal,
+            coloring,
+            limit,
+            &data.iterations,
+            bounds,
+            offset,
+            transform,
+        )
+    } else {
+        render_panned(
+            fractal,
+            coloring,
+            limit,
+            &data.iterations,
+            bounds,
+            offset,
+            transform,
+        )
+    };
+    let total = bounds.0 * bounds.1;
+    let note = format!(
+        "reused {} of {} pixels from {}, panned by {},{}",
+        total - computed,
+        total,
+        filename,
+        offset.0,
+        offset.1
+    );
+    Ok((iterations, Some(note)))
+}
+
+/// --stream：按条带渲染，每个条带着色后立即交给 PNG 编码器，不保存整幅图像
+fn render_streamed(args: &RenderArgs, output: &str, quiet: bool) -> Result<(), MandelbrotError> {
+    if args.image.format(Some(output))? != ImageFormat::Png {
+        return Err(MandelbrotError::InvalidArgument(
+            "--stream only supports PNG output".to_string(),
+        ));
+    }
+    if args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--stream is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.color.palette.transparent.is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--transparent is not supported with --stream".to_string(),
+        ));
+    }
+    // 直方图均衡和自适应抗锯齿都需要整幅图像
+    if args.color.palette.histogram {
+        return Err(MandelbrotError::InvalidArgument(
+            "--histogram is not supported with --stream".to_string(),
+        ));
+    }
+    // 斜坡着色要用到相邻一行的高度，每个条带的边上会少算一行
+    if args.color.palette.light.is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--light is not supported with --stream".to_string(),
+        ));
+    }
+    if args.view.adaptive {
+        return Err(MandelbrotError::InvalidArgument(
+            "--adaptive is not supported with --stream".to_string(),
+        ));
+    }
+    let fractal = args.fractal.fractal(&args.color)?;
+    let coloring = args.color.coloring();
+    let limit = resolve_limit(&args.view, fractal, args.fractal.max_iter, quiet);
+    let bounds = args.view.size;
+    let samples = args.view.samples;
+    let (sample_bounds, transform) = args.view.supersampled().transform();
+
+    let file: Box<dyn std::io::Write> = if output == STDOUT {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(File::create(output).map_err(MandelbrotError::writing(output))?)
+    };
+    let mut stream = PngStream::new(BufWriter::new(file), bounds, args.image.bit_depth)
+        .map_err(MandelbrotError::writing(output))?;
+    let progress = Progress::new(!quiet);
+    progress.start(bounds.1, "rows");
+    for top in (0..bounds.1).step_by(args.strip_height) {
+        let rows = args.strip_height.min(bounds.1 - top);
+        let strip_bounds = (sample_bounds.0, rows * samples);
+        let strip_transform = PixelTransform {
+            origin: transform.point((0, top * samples)),
+            ..transform
+        };
+        let iterations = render_transformed(
+            &args.view,
+            fractal,
+            coloring,
+            limit,
+            strip_bounds,
+            strip_transform,
+        );
+        let palette = &args.color.palette;
+        let bytes: Vec<u8> = if args.image.bit_depth == 16 {
+            colorize_samples::<u16>(&iterations, samples, (bounds.0, rows), palette, limit)
+                .into_iter()
+                .flat_map(u16::to_be_bytes)
+                .collect()
+        } else {
+            colorize_samples(&iterations, samples, (bounds.0, rows), palette, limit)
+        };
+        stream
+            .write_rows(&bytes)
+            .map_err(MandelbrotError::writing(output))?;
+        progress.inc(rows);
+    }
+    stream.finish().map_err(MandelbrotError::writing(output))?;
+    progress.finish();
+    Ok(())
+}
+
+fn recolor(args: &RecolorArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let data = IterationData::load(&args.input).map_err(MandelbrotError::reading(&args.input))?;
+    if !quiet {
+        eprintln!("recoloring data rendered with: {}", data.description);
+    }
+    if let Some(filename) = &args.export_histogram {
+        if !data.coloring.counts_iterations() {
+            return Err(histogram_unsupported(data.coloring));
         }
-        z = z * z + c
+        write_histogram(
+            filename,
+            &EscapeHistogram::collect(&data.iterations, data.limit),
+        )?;
     }
-    None
+    write_colorized(
+        &args.image,
+        &args.output,
+        &data.iterations,
+        data.samples,
+        data.image_bounds(),
+        &args.palette,
+        data.limit,
+    )
 }
 
-/// 把字符串 `s`（形如 `"400×600"` 或 ``"1.0,0.5"）解析成一个坐标对
-///
-/// 具体来说，`s` 应该具有<left><sep><right>的格式，其中<sep>是由`separator`
-/// 参数给出的字符，而<left>和<right>是可以被 `T:from_str` 解析的字符串。
-/// `separator` 必须是 ASCII 字符
-///
-/// 如果 `s` 具有正确的格式，就返回 `Some(x,y)`，否则返回 `None`
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-            (Ok(l), Ok(r)) => Some((l, r)),
+/// 以 `coloring` 着色时逃逸值不是迭代次数，不能导出直方图
+fn histogram_unsupported(coloring: Coloring) -> MandelbrotError {
+    MandelbrotError::InvalidArgument(format!(
+        "--export-histogram counts iterations and is only supported with escape-time or smooth coloring, not {:?}",
+        coloring
+    ))
+}
+
+/// 把逃逸值直方图写入 `filename`，扩展名为 .json 时写成 JSON，否则为 CSV
+fn write_histogram(filename: &str, histogram: &EscapeHistogram) -> Result<(), MandelbrotError> {
+    let json = Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let text = if json {
+        histogram.to_json()
+    } else {
+        histogram.to_csv()
+    };
+    std::fs::write(filename, text).map_err(MandelbrotError::writing(filename))
+}
+
+fn animate(args: &AnimateArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    if args.view.upper_left.is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "animate zooms towards --center; --upper-left is not supported".to_string(),
+        ));
+    }
+    if args.workers.is_some() && args.view.adaptive {
+        return Err(MandelbrotError::InvalidArgument(
+            "--adaptive is not supported with --workers".to_string(),
+        ));
+    }
+    let fractal = args.fractal.fractal(&args.color)?;
+    if let Some(filename) = &args.keyframes {
+        return animate_keyframes(args, filename, fractal, quiet);
+    }
+    let end_zoom = args
+        .end_zoom
+        .expect("--end-zoom is required without --keyframes");
+    let frames = args
+        .frames
+        .expect("--frames is required without --keyframes");
+    let end_rotate = args.end_rotate.unwrap_or(args.view.rotate);
+    let deepest = ViewArgs {
+        zoom: Some(args.view.zoom().max(end_zoom)),
+        ..args.view.clone()
+    };
+    if (args.view.rotate != 0.0 || end_rotate != 0.0) && deepest.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rotate is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.exp_map && deepest.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--exp-map is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.autozoom {
+        return animate_autozoom(args, fractal, end_zoom, end_rotate, frames, quiet);
+    }
+    let limit = args.fractal.limit()?;
+    let progress = Progress::new(!quiet);
+    let exp_map = if args.exp_map {
+        let sample_bounds = args.view.supersampled().size;
+        let map = ExpMap::covering(
+            args.view.center_f64(),
+            sample_bounds,
+            args.view.zoom(),
+            end_zoom,
+        );
+        let strip = expmap::render(&map, fractal, args.color.coloring(), limit, &progress);
+        if let Some(filename) = &args.save_strip {
+            write_colorized(
+                &args.image,
+                filename,
+                &strip,
+                1,
+                map.bounds(),
+                &args.color.palette,
+                limit,
+            )?;
+        }
+        Some((map, strip))
+    } else {
+        None
+    };
+    // 多帧同时渲染，进度按完成的帧数计算，单帧内部不再报告进度
+    progress.start(frames, "frames");
+    let plan_at = |frame: usize| FramePlan {
+        view: ViewArgs {
+            zoom: Some(zoom_at_frame(args.view.zoom(), end_zoom, frame, frames)),
+            rotate: rotation_at_frame(args.view.rotate, end_rotate, frame, frames),
+            ..args.view.clone()
+        },
+        fractal,
+        limit,
+        palette_offset: args.color.palette.palette_offset,
+    };
+    if let Some(workers) = &args.workers {
+        write_remote_frames(workers, args, frames, &progress, plan_at)?;
+        progress.finish();
+        return Ok(());
+    }
+    let orbits = OrbitCache::new();
+    let render_frame = |frame: usize| {
+        let plan = plan_at(frame);
+        let iterations = match &exp_map {
+            Some((map, strip)) => {
+                let (sample_bounds, transform) = plan.view.supersampled().transform();
+                expmap::reproject(map, strip, sample_bounds, transform)
+            }
+            None => plan.render(&args.color, &orbits),
+        };
+        progress.inc(1);
+        RenderedFrame {
+            iterations,
+            limit,
+            palette_offset: plan.palette_offset,
+        }
+    };
+
+    write_frames(
+        args.out.as_deref(),
+        &args.out_dir,
+        args.fps,
+        frames,
+        &args.view,
+        &args.color.palette,
+        &args.image,
+        render_frame,
+    )?;
+    progress.finish();
+    report_reused_orbits(&orbits, quiet);
+    Ok(())
+}
+
+/// 沿 `explore::descend` 找到的路径从 --center 放大到 `end_zoom`，渲染 `frames` 帧动画
+fn animate_autozoom(
+    args: &AnimateArgs,
+    fractal: Fractal,
+    end_zoom: f64,
+    end_rotate: f64,
+    frames: usize,
+    quiet: bool,
+) -> Result<(), MandelbrotError> {
+    let start_zoom = args.view.zoom();
+    if end_zoom < start_zoom {
+        return Err(MandelbrotError::InvalidArgument(
+            "--autozoom requires --end-zoom to be larger than the starting zoom".to_string(),
+        ));
+    }
+    let bits = (args.view.center.len() as f64 * 3.33) as u32 + 64;
+    let start = FixedComplex::parse(&args.view.center, bits).expect("validated by clap");
+    let limit = args.fractal.limit()?;
+    let path = explore::descend(fractal, &start, start_zoom, end_zoom, limit);
+    let (_, reached) = path.last().expect("paths are not empty");
+    if *reached < end_zoom && !quiet {
+        eprintln!(
+            "lost the boundary at zoom {:e}; later frames zoom into its center",
+            reached
+        );
+    }
+
+    let progress = Progress::new(!quiet);
+    progress.start(frames, "frames");
+    let plan_at = |frame: usize| {
+        let zoom = zoom_at_frame(start_zoom, end_zoom, frame, frames);
+        let center = follow(&path, zoom);
+        // 多给两位，每个像素都能分辨
+        let digits = (-pixel_spacing(args.view.size, zoom).log10())
+            .ceil()
+            .max(0.0) as usize
+            + 2;
+        FramePlan {
+            view: ViewArgs {
+                center: format!(
+                    "{},{}",
+                    center.re.to_decimal(digits),
+                    center.im.to_decimal(digits)
+                ),
+                zoom: Some(zoom),
+                rotate: rotation_at_frame(args.view.rotate, end_rotate, frame, frames),
+                ..args.view.clone()
+            },
+            fractal,
+            limit: iteration_limit(limit, zoom),
+            palette_offset: args.color.palette.palette_offset,
+        }
+    };
+    if let Some(workers) = &args.workers {
+        write_remote_frames(workers, args, frames, &progress, plan_at)?;
+        progress.finish();
+        return Ok(());
+    }
+    let orbits = OrbitCache::new();
+    let render_frame = |frame: usize| {
+        let plan = plan_at(frame);
+        let iterations = plan.render(&args.color, &orbits);
+        progress.inc(1);
+        RenderedFrame {
+            iterations,
+            limit: plan.limit,
+            palette_offset: plan.palette_offset,
+        }
+    };
+    write_frames(
+        args.out.as_deref(),
+        &args.out_dir,
+        args.fps,
+        frames,
+        &args.view,
+        &args.color.palette,
+        &args.image,
+        render_frame,
+    )?;
+    progress.finish();
+    report_reused_orbits(&orbits, quiet);
+    Ok(())
+}
+
+/// 按关键帧文件 `filename` 渲染动画，命令行上的视图和参数是第一个关键帧的默认值
+fn animate_keyframes(
+    args: &AnimateArgs,
+    filename: &str,
+    fractal: Fractal,
+    quiet: bool,
+) -> Result<(), MandelbrotError> {
+    let text = std::fs::read_to_string(filename).map_err(MandelbrotError::reading(filename))?;
+    let json = Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let start = Scene {
+        center: args.view.center.clone(),
+        zoom: args.view.zoom(),
+        rotate: args.view.rotate,
+        max_iter: args.fractal.limit()?,
+        palette_offset: args.color.palette.palette_offset,
+        c: match fractal {
+            Fractal::Julia(c) => Some(c),
             _ => None,
         },
+    };
+    let timeline = parse_keyframes(&text, json)
+        .and_then(|keyframes| Timeline::new(&keyframes, start))
+        .map_err(|message| MandelbrotError::Parse {
+            path: filename.to_string(),
+            message,
+        })?;
+    let view_at = |scene: &Scene| ViewArgs {
+        center: scene.center.clone(),
+        zoom: Some(scene.zoom),
+        rotate: scene.rotate,
+        ..args.view.clone()
+    };
+    // 缩放倍数在每一段内单调变化，最深的一帧总是某个关键帧
+    if timeline.scenes().any(|scene| scene.rotate != 0.0)
+        && timeline
+            .scenes()
+            .any(|scene| view_at(scene).precise().is_some())
+    {
+        return Err(MandelbrotError::InvalidArgument(
+            "rotation is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    let frames = timeline.frames();
+    let progress = Progress::new(!quiet);
+    progress.start(frames, "frames");
+    let plan_at = |frame: usize| {
+        let scene = timeline.at(frame);
+        FramePlan {
+            view: view_at(&scene),
+            fractal: scene.c.map_or(fractal, Fractal::Julia),
+            limit: scene.max_iter,
+            palette_offset: scene.palette_offset,
+        }
+    };
+    if let Some(workers) = &args.workers {
+        write_remote_frames(workers, args, frames, &progress, plan_at)?;
+        progress.finish();
+        return Ok(());
     }
+    let orbits = OrbitCache::new();
+    let render_frame = |frame: usize| {
+        let plan = plan_at(frame);
+        let iterations = plan.render(&args.color, &orbits);
+        progress.inc(1);
+        RenderedFrame {
+            iterations,
+            limit: plan.limit,
+            palette_offset: plan.palette_offset,
+        }
+    };
+    write_frames(
+        args.out.as_deref(),
+        &args.out_dir,
+        args.fps,
+        frames,
+        &args.view,
+        &args.color.palette,
+        &args.image,
+        render_frame,
+    )?;
+    progress.finish();
+    report_reused_orbits(&orbits, quiet);
+    Ok(())
 }
 
-#[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("", ','), None);
-    assert_eq!(parse_pair::<i32>("10,", ','), None);
-    assert_eq!(parse_pair::<i32>(",10", ','), None);
-    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x", 'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+/// 报告动画的各帧之间复用了多少次参考轨道，没有复用时不报告
+fn report_reused_orbits(orbits: &OrbitCache, quiet: bool) {
+    if orbits.hits() > 0 && !quiet {
+        eprintln!(
+            "reused reference orbits from earlier frames {} time(s)",
+            orbits.hits()
+        );
+    }
 }
 
-/// 把一对用逗号隔开的浮点数解析为复数
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None,
+/// 一帧动画的渲染参数
+struct FramePlan {
+    view: ViewArgs,
+    fractal: Fractal,
+    limit: usize,
+    palette_offset: f64,
+}
+
+impl FramePlan {
+    /// 在本地渲染这一帧，不报告进度；深度缩放时优先复用 `orbits` 中其他帧的参考轨道
+    fn render(&self, color: &ColorArgs, orbits: &OrbitCache) -> Vec<u32> {
+        let hidden = Progress::hidden();
+        render_samples(
+            &self.view,
+            self.fractal,
+            color,
+            self.limit,
+            &hidden,
+            None,
+            Some(orbits),
+        )
+        .0
+    }
+}
+
+/// 渲染好、等待着色的一帧动画
+struct RenderedFrame {
+    iterations: Vec<u32>,
+    /// 这一帧的最大迭代次数
+    limit: usize,
+    /// 这一帧的调色板偏移
+    palette_offset: f64,
+}
+
+/// 用 `render_frame` 渲染动画的每一帧，按 `palette` 和这一帧的调色板偏移着色后编码成
+/// 视频文件 `out`，或者写成 `out_dir` 中编号的图像
+#[allow(clippy::too_many_arguments)]
+fn write_frames(
+    out: Option<&str>,
+    out_dir: &str,
+    fps: usize,
+    frames: usize,
+    view: &ViewArgs,
+    palette: &PaletteArgs,
+    image: &ImageArgs,
+    render_frame: impl Fn(usize) -> RenderedFrame + Sync,
+) -> Result<(), MandelbrotError> {
+    let bounds = view.size;
+    let samples = view.samples;
+    let palette_for = |frame: &RenderedFrame| PaletteArgs {
+        palette_offset: frame.palette_offset,
+        ..palette.clone()
+    };
+    if let Some(out) = out {
+        let mut encoder =
+            VideoEncoder::new(out, bounds, fps, frames).map_err(MandelbrotError::encoding(out))?;
+        // 视频帧必须按顺序写入，因此每次并行渲染一批帧，再依次交给编码器
+        let batch = rayon::current_num_threads();
+        for first in (0..frames).step_by(batch) {
+            let pixels: Vec<Vec<u8>> = (first..(first + batch).min(frames))
+                .into_par_iter()
+                .map(|frame| {
+                    let frame = render_frame(frame);
+                    colorize_samples(
+                        &frame.iterations,
+                        samples,
+                        bounds,
+                        &palette_for(&frame),
+                        frame.limit,
+                    )
+                })
+                .collect();
+            for pixels in pixels {
+                encoder
+                    .write_frame(&pixels)
+                    .map_err(MandelbrotError::encoding(out))?;
+            }
+        }
+        return encoder.finish().map_err(MandelbrotError::encoding(out));
     }
+
+    let format = image.format(None)?;
+    std::fs::create_dir_all(out_dir).map_err(MandelbrotError::writing(out_dir))?;
+    // 各帧之间互不依赖，直接并行渲染；每一帧内部的渲染也是并行的，交给 rayon 调度
+    (0..frames).into_par_iter().try_for_each(|index| {
+        let frame = render_frame(index);
+        let path = Path::new(out_dir).join(format!("frame_{:04}.{}", index, format.extension()));
+        write_colorized(
+            image,
+            &path.to_string_lossy(),
+            &frame.iterations,
+            samples,
+            bounds,
+            &palette_for(&frame),
+            frame.limit,
+        )
+    })
 }
 
-#[test]
-fn test_parse_complex() {
-    assert_eq!(
-        parse_complex("1.25,-0.0625"),
-        Some(Complex {
-            re: 1.25,
-            im: -0.0625
+/// animate --workers：把 `plan_at` 给出的每一帧作为一个任务分给工作进程，按 `args` 着色后
+/// 写成视频或编号的图像；帧完成的顺序不确定，视频帧先缓存起来再按顺序交给编码器
+fn write_remote_frames(
+    workers: &[String],
+    args: &AnimateArgs,
+    frames: usize,
+    progress: &Progress,
+    plan_at: impl Fn(usize) -> FramePlan,
+) -> Result<(), MandelbrotError> {
+    let bounds = args.view.size;
+    let samples = args.view.samples;
+    let plans: Vec<FramePlan> = (0..frames).map(plan_at).collect();
+    let coloring = args.color.coloring();
+    let jobs: Vec<(Value, usize)> = plans
+        .iter()
+        .map(|plan| {
+            remote_job(
+                &plan.view.supersampled(),
+                plan.fractal,
+                coloring,
+                plan.limit,
+                None,
+            )
         })
-    );
-    assert_eq!(parse_complex(",-0.0625"), None);
+        .collect();
+    let palette_for = |plan: &FramePlan| PaletteArgs {
+        palette_offset: plan.palette_offset,
+        ..args.color.palette.clone()
+    };
+
+    let mut video = match &args.out {
+        Some(out) => Some((
+            VideoEncoder::new(out, bounds, args.fps, frames)
+                .map_err(MandelbrotError::encoding(out))?,
+            out.as_str(),
+        )),
+        None => {
+            std::fs::create_dir_all(&args.out_dir)
+                .map_err(MandelbrotError::writing(&args.out_dir))?;
+            None
+        }
+    };
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    remote::dispatch(workers, &jobs, |index, iterations| {
+        progress.inc(1);
+        let Some((encoder, out)) = &mut video else {
+            let format = args.image.format(None)?;
+            let path =
+                Path::new(&args.out_dir).join(format!("frame_{:04}.{}", index, format.extension()));
+            let plan = &plans[index];
+            return write_colorized(
+                &args.image,
+                &path.to_string_lossy(),
+                &iterations,
+                samples,
+                bounds,
+                &palette_for(plan),
+                plan.limit,
+            );
+        };
+        pending.insert(index, iterations);
+        while let Some(iterations) = pending.remove(&next) {
+            let plan = &plans[next];
+            let pixels: Vec<u8> =
+                colorize_samples(&iterations, samples, bounds, &palette_for(plan), plan.limit);
+            encoder
+                .write_frame(&pixels)
+                .map_err(MandelbrotError::encoding(*out))?;
+            next += 1;
+        }
+        Ok(())
+    })?;
+    match video {
+        Some((encoder, out)) => encoder.finish().map_err(MandelbrotError::encoding(out)),
+        None => Ok(()),
+    }
 }
 
-/// 给定输出图像重像素的行和列，返回复平面中对应的坐标
-///
-/// `bound` 是一个 `pair`，给出了图像的像素宽度和像素高度。
-/// `pixed` 是表示给图片中特定像素的 (column, row) 二元组。
-/// `upper_left` 参数和 `lower_right` 参数是在复平面中表示指定图像覆盖范围的点。
-fn pixed_to_point(
-    /*
-    ·--------------------> bounds.0  re
-    丨
-    丨
-    丨
-    丨
-    丨
-    bounds.1  im
-     */
-    bounds: (usize, usize),
-    pixed: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) -> Complex<f64> {
-    let (width, height) = (
-        lower_right.re - upper_left.re, // 右-左
-        upper_left.im - lower_right.im, // 上-下
+fn cycle(args: &CycleArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    if args.view.rotate != 0.0 && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rotate is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    let fractal = args.fractal.fractal(&args.color)?;
+    let limit = args.fractal.limit()?;
+    let bounds = args.view.size;
+    let samples = args.view.samples;
+    let progress = Progress::new(!quiet);
+    let (iterations, note) = render_samples(
+        &args.view,
+        fractal,
+        &args.color,
+        limit,
+        &progress,
+        None,
+        None,
     );
+    if let (Some(note), false) = (note, quiet) {
+        eprintln!("{}", note);
+    }
+    // 每一帧只是用移动后的调色板重新着色
+    let palette_at = |frame: usize| PaletteArgs {
+        palette_offset: args.color.palette.palette_offset
+            + args.cycles * frame as f64 / args.frames as f64,
+        ..args.color.palette.clone()
+    };
+    progress.start(args.frames, "frames");
 
-    Complex {
-        re: upper_left.re + pixed.0 as f64 * width / bounds.0 as f64,
-        im: upper_left.im - pixed.1 as f64 * height / bounds.1 as f64,
+    if let Some(out) = &args.out {
+        let mut encoder = VideoEncoder::new(out, bounds, args.fps, args.frames)
+            .map_err(MandelbrotError::encoding(out))?;
+        for frame in 0..args.frames {
+            let pixels = colorize_samples(&iterations, samples, bounds, &palette_at(frame), limit);
+            encoder
+                .write_frame(&pixels)
+                .map_err(MandelbrotError::encoding(out))?;
+            progress.inc(1);
+        }
+        encoder.finish().map_err(MandelbrotError::encoding(out))?;
+        progress.finish();
+        return Ok(());
     }
+
+    let format = args.image.format(None)?;
+    std::fs::create_dir_all(&args.out_dir).map_err(MandelbrotError::writing(&args.out_dir))?;
+    (0..args.frames).into_par_iter().try_for_each(|frame| {
+        let path =
+            Path::new(&args.out_dir).join(format!("frame_{:04}.{}", frame, format.extension()));
+        write_colorized(
+            &args.image,
+            &path.to_string_lossy(),
+            &iterations,
+            samples,
+            bounds,
+            &palette_at(frame),
+            limit,
+        )?;
+        progress.inc(1);
+        Ok(())
+    })?;
+    progress.finish();
+    Ok(())
 }
 
-#[test]
-fn test_pixed_to_point() {
-    assert_eq!(
-        pixed_to_point(
-            (100, 200),
-            (25, 175),
-            Complex { re: -1.0, im: 1.0 },
-            Complex { re: 1.0, im: -1.0 }
-        ),
-        Complex {
-            re: -0.5,
-            im: -0.75,
+fn morph(args: &MorphArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    if args.view.rotate != 0.0 && args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--rotate is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    let progress = Progress::new(!quiet);
+    progress.start(args.frames, "frames");
+    let render_frame = |frame: usize| {
+        let fractal = Fractal::Julia(args.path.point_at_frame(frame, args.frames));
+        let (iterations, _) = render_samples(
+            &args.view,
+            fractal,
+            &args.color,
+            args.max_iter,
+            &Progress::hidden(),
+            None,
+            None,
+        );
+        progress.inc(1);
+        RenderedFrame {
+            iterations,
+            limit: args.max_iter,
+            palette_offset: args.color.palette.palette_offset,
         }
+    };
+    write_frames(
+        args.out.as_deref(),
+        &args.out_dir,
+        args.fps,
+        args.frames,
+        &args.view,
+        &args.color.palette,
+        &args.image,
+        render_frame,
+    )?;
+    progress.finish();
+    Ok(())
+}
+
+fn dzi(args: &DziArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(None)?;
+    if format == ImageFormat::Tiff {
+        return Err(MandelbrotError::InvalidArgument(
+            "DZI tiles must be png, jpeg, or webp".to_string(),
+        ));
+    }
+    if args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "dzi is not supported beyond f64 resolution".to_string(),
+        ));
+    }
+    // 每块瓦片单独着色，直方图均衡会让相邻瓦片的颜色不连续
+    if args.color.palette.histogram {
+        return Err(MandelbrotError::InvalidArgument(
+            "--histogram is not supported by dzi".to_string(),
+        ));
+    }
+    if args.view.adaptive {
+        return Err(MandelbrotError::InvalidArgument(
+            "--adaptive is not supported by dzi".to_string(),
+        ));
+    }
+    let fractal = args.fractal.fractal(&args.color)?;
+    let coloring = args.color.coloring();
+    let limit = args.fractal.limit()?;
+    let samples = args.view.samples;
+    let (bounds, transform) = args.view.transform();
+    let pyramid = Pyramid {
+        size: bounds,
+        tile_size: args.tile_size,
+        overlap: args.overlap,
+    };
+    let files = dzi::files_dir(Path::new(&args.output));
+    let mut tiles = Vec::new();
+    for level in 0..=pyramid.max_level() {
+        let directory = files.join(level.to_string());
+        std::fs::create_dir_all(&directory)
+            .map_err(MandelbrotError::writing(directory.display()))?;
+        let (columns, rows) = pyramid.tile_counts(level);
+        for row in 0..rows {
+            tiles.extend((0..columns).map(|column| (level, (column, row))));
+        }
+    }
+
+    let progress = Progress::new(!quiet);
+    progress.start(tiles.len(), "tiles");
+    // 瓦片之间互不依赖，直接并行渲染；每块瓦片内部的渲染也是并行的，交给 rayon 调度
+    tiles.into_par_iter().try_for_each(|(level, tile)| {
+        let (offset, tile_bounds) = pyramid.tile_rect(level, tile);
+        let sample_bounds = (tile_bounds.0 * samples, tile_bounds.1 * samples);
+        let transform = pyramid
+            .tile_transform(transform, level, offset)
+            .subdivided(samples);
+        let iterations = render_transformed(
+            &args.view,
+            fractal,
+            coloring,
+            limit,
+            sample_bounds,
+            transform,
+        );
+        let path = dzi::tile_path(&files, level, tile, format.extension());
+        write_colorized(
+            &args.image,
+            &path.to_string_lossy(),
+            &iterations,
+            samples,
+            tile_bounds,
+            &args.color.palette,
+            limit,
+        )?;
+        progress.inc(1);
+        Ok(())
+    })?;
+    progress.finish();
+
+    std::fs::write(&args.output, pyramid.descriptor(format.extension()))
+        .map_err(MandelbrotError::writing(&args.output))
+}
+
+fn buddhabrot(args: &BuddhabrotArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(Some(&args.output))?;
+    let bounds = args.size;
+    let (upper_left, lower_right) = corners_from_center(bounds, args.center, args.zoom);
+    let progress = Progress::new(!quiet);
+    let selection = if let Some(channels) = &args.channels {
+        Selection::Escaping(channels.to_vec())
+    } else if args.anti {
+        Selection::Bounded(args.max_iter)
+    } else {
+        if args.min_iter >= args.max_iter {
+            return Err(MandelbrotError::InvalidArgument(
+                "--min-iter must be less than --max-iter".to_string(),
+            ));
+        }
+        Selection::Escaping(std::iter::once(args.min_iter..args.max_iter).collect())
+    };
+    let accumulate = match args.sampling {
+        Sampling::Uniform => buddhabrot::accumulate_selected,
+        Sampling::Metropolis => buddhabrot::accumulate_metropolis,
+    };
+    let density = accumulate(
+        args.points,
+        &selection,
+        bounds,
+        upper_left,
+        lower_right,
+        args.seed,
+        &progress,
     );
+    let result = if args.image.bit_depth == 16 {
+        write_image16(
+            &args.output,
+            &buddhabrot_pixels(args, &density),
+            bounds,
+            format,
+        )
+    } else {
+        write_image(
+            &args.output,
+            &buddhabrot_pixels(args, &density),
+            bounds,
+            format,
+        )
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
 }
 
-/// 将曼德博集对应的矩形渲染到像素缓冲区中
-///
-/// `bounds` 参数会给缓冲区 `pixels` 的宽度和高度，此缓冲区的每个字节都
-/// 包含一个灰度像素。`upper_left` 和 `lower_right` 参数分别指定了
-/// 复平面中对应于像素缓冲区左上角和右上角的点。
-fn render(
-    pixels: &mut [u8],
-    bounds: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) {
-    assert_eq!(pixels.len(), bounds.0 * bounds.1);
+fn newton(args: &NewtonArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(Some(&args.output))?;
+    let bounds = args.size;
+    let (upper_left, lower_right) = corners_from_center(bounds, args.center, args.zoom);
+    let progress = Progress::new(!quiet);
+    let result = if args.image.bit_depth == 16 {
+        let pixels = newton::render(
+            &args.polynomial,
+            args.max_iter,
+            bounds,
+            upper_left,
+            lower_right,
+            &progress,
+        );
+        write_image16(&args.output, &pixels, bounds, format)
+    } else {
+        let pixels = newton::render(
+            &args.polynomial,
+            args.max_iter,
+            bounds,
+            upper_left,
+            lower_right,
+            &progress,
+        );
+        write_image(&args.output, &pixels, bounds, format)
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
+}
+
+fn nova(args: &NovaArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(Some(&args.output))?;
+    let bounds = args.size;
+    let (upper_left, lower_right) = corners_from_center(bounds, args.center, args.zoom);
+    let nova = Nova {
+        power: args.power,
+        relaxation: args.relaxation,
+        seed: args.seed,
+        julia: args.c,
+    };
+    let iterations = nova::render(
+        &nova,
+        args.max_iter,
+        args.tolerance,
+        bounds,
+        upper_left,
+        lower_right,
+        &Progress::new(!quiet),
+    );
+    let result = if args.image.bit_depth == 16 {
+        write_image16(
+            &args.output,
+            &colorize_palette(&iterations, args.max_iter, &args.palette, args.histogram),
+            bounds,
+            format,
+        )
+    } else {
+        write_image(
+            &args.output,
+            &colorize_palette(&iterations, args.max_iter, &args.palette, args.histogram),
+            bounds,
+            format,
+        )
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
+}
+
+fn slice(args: &SliceArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    args.check()?;
+    let progress = Progress::new(!quiet);
+    let Some(frames) = args.frames else {
+        let output = args
+            .output
+            .as_deref()
+            .expect("clap requires output without --frames");
+        let iterations = args.render(0, 1, &progress);
+        return write_colorized(
+            &args.image,
+            output,
+            &iterations,
+            args.view.samples,
+            args.view.size,
+            &args.palette,
+            args.max_iter,
+        );
+    };
+    progress.start(frames, "frames");
+    let render_frame = |frame: usize| {
+        let iterations = args.render(frame, frames, &Progress::hidden());
+        progress.inc(1);
+        RenderedFrame {
+            iterations,
+            limit: args.max_iter,
+            palette_offset: args.palette.palette_offset,
+        }
+    };
+    write_frames(
+        args.out.as_deref(),
+        &args.out_dir,
+        args.fps,
+        frames,
+        &args.view,
+        &args.palette,
+        &args.image,
+        render_frame,
+    )?;
+    progress.finish();
+    Ok(())
+}
+
+fn atlas(args: &AtlasArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(Some(&args.output))?;
+    let bounds = args.size;
+    if bounds.0 / args.grid.0 <= args.gap || bounds.1 / args.grid.1 <= args.gap {
+        return Err(MandelbrotError::InvalidArgument(format!(
+            "a {}x{} grid leaves no room for the Julia sets in a {}x{} image with --gap {}",
+            args.grid.0, args.grid.1, bounds.0, bounds.1, args.gap
+        )));
+    }
+    let (upper_left, lower_right) = corners_from_center(bounds, args.center, args.zoom);
+    let atlas = Atlas {
+        grid: args.grid,
+        upper_left,
+        lower_right,
+        julia_zoom: args.julia_zoom,
+        gap: args.gap,
+    };
+    let iterations = atlas::render(
+        &atlas,
+        args.max_iter,
+        args.smooth,
+        bounds,
+        &Progress::new(!quiet),
+    );
+    let result = if args.image.bit_depth == 16 {
+        write_image16(
+            &args.output,
+            &colorize_palette(&iterations, args.max_iter, &args.palette, args.histogram),
+            bounds,
+            format,
+        )
+    } else {
+        write_image(
+            &args.output,
+            &colorize_palette(&iterations, args.max_iter, &args.palette, args.histogram),
+            bounds,
+            format,
+        )
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
+}
 
-    for raw in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixed_to_point(bounds, (column, raw), upper_left, lower_right);
-            pixels[raw * bounds.0 + column] = match escape_time(point, 255) {
-                None => 0,
-                Some(count) => 255 - count as u8,
+fn orbit(args: &OrbitArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    if args.view.precise().is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "orbit does not support views beyond f64 resolution".to_string(),
+        ));
+    }
+    if args.color.palette.transparent.is_some() {
+        return Err(MandelbrotError::InvalidArgument(
+            "--transparent is not supported by orbit".to_string(),
+        ));
+    }
+    if args.bailout().is_some() && args.view.adaptive {
+        return Err(MandelbrotError::InvalidArgument(
+            "--bailout and --norm are not supported with --adaptive".to_string(),
+        ));
+    }
+    let format = args.image.format(Some(&args.output))?;
+    let fractal = args.fractal.fractal(&args.color)?;
+    let limit = args.fractal.limit()?;
+    let bailout = args.bailout();
+    let trace_bailout = bailout.unwrap_or(orbit::BAILOUT);
+    let points = orbit::trace(&fractal, args.point, limit, trace_bailout);
+    if let Some(filename) = &args.export {
+        let json = Path::new(filename)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let text = if json {
+            orbit::to_json(args.point, &points, trace_bailout)
+        } else {
+            orbit::to_csv(&points)
+        };
+        std::fs::write(filename, text).map_err(MandelbrotError::writing(filename))?;
+    }
+    if !quiet {
+        match points.len() - 1 {
+            n if orbit::escaped(&points, trace_bailout) => {
+                eprintln!("the orbit escapes after {} iterations", n)
             }
+            n => eprintln!("the orbit stays bounded for {} iterations", n),
         }
     }
+
+    let progress = Progress::new(!quiet);
+    let (iterations, note) = match bailout {
+        Some(bailout) => {
+            let colorizer = WithBailout {
+                colorizer: args.color.coloring(),
+                bailout,
+            };
+            let iterations = render_colorizer(&args.view, fractal, colorizer, limit, &progress);
+            (iterations, None)
+        }
+        None => render_samples(
+            &args.view,
+            fractal,
+            &args.color,
+            limit,
+            &progress,
+            None,
+            None,
+        ),
+    };
+    if let (Some(note), false) = (note, quiet) {
+        eprintln!("{}", note);
+    }
+    let samples = args.view.samples;
+    let (bounds, transform) = args.view.transform();
+    let palette = &args.color.palette;
+    let lines = !args.no_lines;
+    let result = if args.image.bit_depth == 16 {
+        let mut pixels = colorize_samples::<u16>(&iterations, samples, bounds, palette, limit);
+        orbit::draw(
+            &mut pixels,
+            bounds,
+            &transform,
+            &points,
+            args.orbit_color,
+            lines,
+        );
+        write_image16(&args.output, &pixels, bounds, format)
+    } else {
+        let mut pixels = colorize_samples::<u8>(&iterations, samples, bounds, palette, limit);
+        orbit::draw(
+            &mut pixels,
+            bounds,
+            &transform,
+            &points,
+            args.orbit_color,
+            lines,
+        );
+        write_image(&args.output, &pixels, bounds, format)
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
 }
 
-/// 把 `pixels` 缓冲区（其尺寸由 `bounds` 给出）写入名为 `filename` 的文件中
-fn write_image(
-    filename: &str,
-    pixels: &[u8],
-    bounds: (usize, usize),
-) -> Result<(), std::io::Error> {
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::Gray(8))?;
+fn find_minibrot_command(args: &FindMinibrotArgs) -> Result<(), MandelbrotError> {
+    // 每个十进制位约合 3.32 个二进制位，再留出余量
+    let bits = (args.center.len() as f64 * 3.33) as u32 + 64;
+    let center = FixedComplex::parse(&args.center, bits).ok_or_else(|| {
+        MandelbrotError::InvalidArgument(format!("invalid center `{}`", args.center))
+    })?;
+    let minibrot = find_minibrot(&center, args.period, args.max_steps).map_err(|message| {
+        MandelbrotError::InvalidArgument(format!(
+            "cannot find a minibrot of period {} near {}: {}",
+            args.period, args.center, message
+        ))
+    })?;
+    let digits = minibrot.digits();
+    let nucleus = format!(
+        "{},{}",
+        minibrot.nucleus.re.to_decimal(digits),
+        minibrot.nucleus.im.to_decimal(digits)
+    );
+    println!("nucleus: {}", nucleus);
+    println!("period: {}", minibrot.period);
+    println!("size: {}", exp2_scientific(minibrot.size.log2, 6));
+    println!("angle: {:.2} degrees", minibrot.angle());
+    println!(
+        "view: --center {} --zoom {} --rotate {:.2}",
+        nucleus,
+        exp2_scientific(-minibrot.size.log2, 6),
+        minibrot.angle()
+    );
+    Ok(())
+}
+
+fn find_misiurewicz_command(args: &FindMisiurewiczArgs) -> Result<(), MandelbrotError> {
+    let bits = (args.center.len().max(args.digits) as f64 * 3.33) as u32 + 64;
+    let center = FixedComplex::parse(&args.center, bits).ok_or_else(|| {
+        MandelbrotError::InvalidArgument(format!("invalid center `{}`", args.center))
+    })?;
+    let point = find_misiurewicz(&center, args.preperiod, args.period, args.max_steps).map_err(
+        |message| {
+            MandelbrotError::InvalidArgument(format!(
+                "cannot find a Misiurewicz point of preperiod {} and period {} near {}: {}",
+                args.preperiod, args.period, args.center, message
+            ))
+        },
+    )?;
+    let location = format!(
+        "{},{}",
+        point.point.re.to_decimal(args.digits),
+        point.point.im.to_decimal(args.digits)
+    );
+    println!("point: {}", location);
+    println!("preperiod: {}", point.preperiod);
+    println!("period: {}", point.period);
+    println!(
+        "multiplier: {:.6e} at {:.2} degrees",
+        point.zoom_factor(),
+        point.angle()
+    );
+    println!("view: --center {}", location);
+    Ok(())
+}
+
+fn explore_command(args: &ExploreArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let fractal = args.fractal.fractal(&args.color)?;
+    let base_limit = args.fractal.limit()?;
+    if args.zoom < args.start_zoom {
+        return Err(MandelbrotError::InvalidArgument(format!(
+            "--zoom {} is smaller than --start-zoom {}",
+            args.zoom, args.start_zoom
+        )));
+    }
+    let start_center = parse_complex(&args.center).expect("validated by clap");
+    let deep = precise::required(start_center, pixel_spacing(explore::PROBE, args.zoom));
+    if deep && !fractal.supports_deep_zoom() {
+        return Err(MandelbrotError::InvalidArgument(format!(
+            "--fractal {} does not support zooms beyond f64 resolution",
+            args.fractal.fractal
+        )));
+    }
+    let format = args.image.format(None)?;
+    args.color.palette.check_alpha(format)?;
+
+    let bits = (args.center.len() as f64 * 3.33) as u32 + 64;
+    let start = FixedComplex::parse(&args.center, bits).expect("validated by clap");
+    let progress = Progress::new(!quiet);
+    let candidates = explore::explore(
+        fractal,
+        &start,
+        args.start_zoom,
+        args.zoom,
+        args.count,
+        base_limit,
+        &progress,
+    );
+    if candidates.len() < args.count && !quiet {
+        eprintln!(
+            "found only {} of {} locations near the boundary",
+            candidates.len(),
+            args.count
+        );
+    }
+
+    std::fs::create_dir_all(&args.out_dir).map_err(MandelbrotError::writing(&args.out_dir))?;
+    let size = args.thumbnail_size;
+    for (index, candidate) in candidates.iter().enumerate() {
+        // 多给两位，缩略图中的每个像素都能分辨
+        let digits = (-pixel_spacing(size, candidate.zoom).log10())
+            .ceil()
+            .max(0.0) as usize
+            + 2;
+        let center = format!(
+            "{},{}",
+            candidate.center.re.to_decimal(digits),
+            candidate.center.im.to_decimal(digits)
+        );
+        let view = ViewArgs {
+            size,
+            upper_left: None,
+            lower_right: None,
+            center: center.clone(),
+            zoom: Some(candidate.zoom),
+            location: None,
+            rotate: 0.0,
+            tile: (64, 64),
+            subdivide: false,
+            samples: 1,
+            adaptive: false,
+            adaptive_threshold: 16,
+            no_perturbation: false,
+            precision: Precision::Double,
+        };
+        let limit = iteration_limit(base_limit, candidate.zoom);
+        let (iterations, note) = render_samples(
+            &view,
+            fractal,
+            &args.color,
+            limit,
+            &Progress::hidden(),
+            None,
+            None,
+        );
+        if let (Some(note), false) = (note, quiet) {
+            eprintln!("{}", note);
+        }
+        let path = Path::new(&args.out_dir).join(format!(
+            "location_{:02}.{}",
+            index + 1,
+            format.extension()
+        ));
+        let path = path.to_string_lossy();
+        write_colorized(
+            &args.image,
+            &path,
+            &iterations,
+            1,
+            size,
+            &args.color.palette,
+            limit,
+        )?;
+        println!(
+            "{}: --center {} --zoom {:e} --max-iter {} (entropy {:.2} bits) -> {}",
+            index + 1,
+            center,
+            candidate.zoom,
+            limit,
+            candidate.score,
+            path
+        );
+    }
+    Ok(())
+}
+
+fn measure_command(args: &MeasureArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let progress = Progress::new(!quiet);
+    let estimates = measure::measure(
+        args.method,
+        args.samples,
+        &args.max_iter,
+        args.seed,
+        &progress,
+    );
+    println!(
+        "{:>10}  {:>12}  {:>10}  95% interval",
+        "max-iter", "samples", "area"
+    );
+    for estimate in &estimates {
+        println!(
+            "{:>10}  {:>12}  {:>10.6}  {:.6} .. {:.6}",
+            estimate.limit,
+            estimate.samples,
+            estimate.area,
+            estimate.area - estimate.error,
+            estimate.area + estimate.error
+        );
+    }
     Ok(())
 }
 
-/// 单线程
-/// ➜  mandelbrot git:(master) ✗ time target/release/mandelbrot mandel.png 4000x3000 -1.20,0.35 -1,0.20
-/// target/release/mandelbrot mandel.png 4000x3000 -1.20,0.35 -1,0.20  3.30s user 0.01s system 97% cpu 3.372 total
-/// 多线程
-/// ➜  mandelbrot git:(master) ✗ time target/release/mandelbrot mandel2.png 4000x3000 -1.20,0.35 -1,0.20
-/// target/release/mandelbrot mandel2.png 4000x3000 -1.20,0.35 -1,0.20  6.34s user 0.01s system 553% cpu 1.148 total
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
+fn lyapunov(args: &LyapunovArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let format = args.image.format(Some(&args.output))?;
+    let bounds = args.size;
+    let (upper_left, lower_right) = corners_from_center(bounds, args.center, args.zoom);
+    let progress = Progress::new(!quiet);
+    let result = if args.image.bit_depth == 16 {
+        let pixels = lyapunov::render(
+            &args.sequence,
+            args.warmup,
+            args.max_iter,
+            &args.stable_palette,
+            &args.chaotic_palette,
+            bounds,
+            upper_left,
+            lower_right,
+            &progress,
+        );
+        write_image16(&args.output, &pixels, bounds, format)
+    } else {
+        let pixels = lyapunov::render(
+            &args.sequence,
+            args.warmup,
+            args.max_iter,
+            &args.stable_palette,
+            &args.chaotic_palette,
+            bounds,
+            upper_left,
+            lower_right,
+            &progress,
+        );
+        write_image(&args.output, &pixels, bounds, format)
+    };
+    result.map_err(MandelbrotError::writing(&args.output))
+}
+
+/// 把累积的密度映射为像素：Nebulabrot 的三个通道分别作为红、绿、蓝，否则按调色板着色
+fn buddhabrot_pixels<C: Channel>(args: &BuddhabrotArgs, density: &[Vec<u32>]) -> Vec<C> {
+    let tone = args
+        .tone
+        .unwrap_or(if args.anti { Tone::Log } else { Tone::Gamma });
+    match density {
+        [density] => buddhabrot::tone_map(density, tone, args.gamma)
+            .into_iter()
+            .flat_map(|t| C::color(&args.palette, t))
+            .collect(),
+        _ => buddhabrot::combine_rgb(
+            density.try_into().expect("three channels"),
+            tone,
+            args.gamma,
+        ),
+    }
+}
+
+#[cfg(feature = "viewer")]
+fn view(args: &ViewerArgs) -> Result<(), MandelbrotError> {
+    let limit = args.fractal.limit()?;
+    let viewer = mandelbrot::viewer::Viewer {
+        fractal: args.fractal.fractal(&args.color)?,
+        coloring: args.color.coloring(),
+        limit,
+        colorize: |iterations: &[u32], pixels: &mut [u8]| {
+            args.color.palette.colorize(iterations, limit, pixels)
+        },
+        size: args.size,
+        center: args.center,
+        zoom: args.zoom,
+        export_size: args.export_size,
+    };
+    viewer.run().map_err(MandelbrotError::Viewer)
+}
+
+fn serve(args: &ServeArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let limit = args.fractal.limit()?;
+    let server = Server {
+        fractal: args.fractal.fractal(&args.color)?,
+        coloring: args.color.coloring(),
+        limit,
+        colorize: |iterations: &[u32], pixels: &mut [u8]| {
+            args.color.palette.colorize(iterations, limit, pixels)
+        },
+        cache: TileCache::new(args.cache).into(),
+    };
+    let listener = std::net::TcpListener::bind((args.host.as_str(), args.port))
+        .map_err(MandelbrotError::Serve)?;
+    if !quiet {
+        let address = listener.local_addr().map_err(MandelbrotError::Serve)?;
         eprintln!(
-            "Example: {} mandel.png 1000x700 -1.20,0.35 -1,0.20",
-            args[0]
+            "serving {}x{} tiles at http://{}/",
+            serve::TILE_SIZE,
+            serve::TILE_SIZE,
+            address
         );
-        std::process::exit(1);
     }
+    server.run(listener).map_err(MandelbrotError::Serve)
+}
 
-    let bounds = parse_pair(&args[2], 'x').expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right corner point");
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+/// clap 错误信息的第一行，不含用法说明
+fn clap_message(err: clap::Error) -> String {
+    let message = err.to_string();
+    let first = message.lines().next().unwrap_or_default();
+    first.trim_start_matches("error: ").to_string()
+}
 
-    // /*
-    // ③ rayon 窃取式并行
-    let bands: Vec<(usize, &mut [u8])> = pixels.chunks_mut(bounds.0).enumerate().collect();
+/// 渲染服务接受的选项：只有视图、分形和着色
+///
+/// 其余选项会读写服务器上的文件（--config、--save-data、--stats-json 等）、连接客户端指定的
+/// 主机（--workers）或者改变整个进程的设置（--threads 等），都不接受；以后新增的选项也要
+/// 在这里显式加入才能使用。
+const API_ALLOWED: &[&str] = &[
+    "--size",
+    "--upper-left",
+    "--lower-right",
+    "--center",
+    "--zoom",
+    "--location",
+    "--rotate",
+    "--samples",
+    "--adaptive",
+    "--adaptive-threshold",
+    "--precision",
+    "--no-perturbation",
+    "--tile",
+    "--subdivide",
+    "--fractal",
+    "--c",
+    "--p",
+    "--power",
+    "--max-iter",
+    "--formula",
+    "--hybrid",
+    "--interior",
+    "--bailout",
+    "--norm",
+    "--rays",
+    "--equipotentials",
+    "--ray-depth",
+    "--overlay-color",
+    "--coloring",
+    "--trap",
+    "--palette",
+    "--histogram",
+    "--palette-offset",
+    "--light",
+    "--relief",
+    "--transparent",
+    "--alpha",
+    "--format",
+    "--quality",
+    "--bit-depth",
+];
 
-    bands.into_par_iter().for_each(|(i, band)| {
-        let top = i;
-        let band_bounds = (bounds.0, 1);
-        let band_upper_left = pixed_to_point(bounds, (0, top), upper_left, lower_right);
-        let band_lower_right = pixed_to_point(bounds, (bounds.0, top + 1), upper_left, lower_right);
-        render(band, band_bounds, band_upper_left, band_lower_right);
-    });
-    // */
-    /*
-    // ① 单线程执行
-    // render(&mut pixels, bounds, upper_left, lower_right);
-     */
-
-    /*
-    // ② 并发执行
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left = pixed_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right =
-                    pixed_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-                spawner.spawn(move |_| {
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
+/// 渲染服务接受的最大像素数（含超采样），4096×4096
+const API_MAX_PIXELS: usize = 1 << 24;
+
+/// 渲染服务接受的最大迭代次数
+const API_MAX_ITER: usize = 1_000_000;
+
+/// 把渲染服务收到的场景解析为 render 的参数，输出文件由执行任务时决定
+fn api_job(scene: &Value) -> Result<RenderArgs, String> {
+    let (output, options) = json_config_args(scene)?;
+    if output.is_some() {
+        return Err(
+            "`output` is chosen by the server, use `format` to pick the format".to_string(),
+        );
+    }
+    for (index, option) in options.iter().enumerate() {
+        if option.starts_with("--") && !API_ALLOWED.contains(&option.as_str()) {
+            return Err(format!("`{}` is not supported", &option[2..]));
+        }
+        // 调色板只能是内置的或者直接写出的渐变，不能读服务器上的文件
+        if option == "--palette"
+            && options
+                .get(index + 1)
+                .is_none_or(|value| parse_palette(value).is_none())
+        {
+            return Err("palette files are not supported".to_string());
+        }
+    }
+    let argv = ["mandelbrot", "render", "scene"]
+        .into_iter()
+        .map(String::from)
+        .chain(options);
+    let cli = Cli::try_parse_from(argv).map_err(clap_message)?;
+    let Command::Render(mut args) = cli.command else {
+        unreachable!("the scene was parsed as render arguments");
+    };
+    args.view
+        .resolve_location()
+        .map_err(|err| err.to_string())?;
+    args.image.format(None).map_err(|err| err.to_string())?;
+    let (width, height) = args.view.size;
+    let samples = args.view.samples.saturating_mul(args.view.samples);
+    if width.saturating_mul(height).saturating_mul(samples) > API_MAX_PIXELS {
+        return Err(format!(
+            "at most {} pixels including supersampling are supported",
+            API_MAX_PIXELS
+        ));
+    }
+    match args.fractal.max_iter {
+        MaxIter::Fixed(limit) if limit <= API_MAX_ITER => {}
+        _ => return Err(format!("`max_iter` must be at most {}", API_MAX_ITER)),
+    }
+    Ok(args)
+}
+
+fn api(args: &ApiArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let dir = match &args.dir {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join("mandelbrot-api"),
+    };
+    std::fs::create_dir_all(&dir).map_err(MandelbrotError::writing(dir.display()))?;
+    // 服务中的 Ctrl-C 直接结束进程，而不是让正在渲染和之后的任务都中途停下
+    INTERRUPT_HANDLER.call_once(|| {});
+    let listener = std::net::TcpListener::bind((args.host.as_str(), args.port))
+        .map_err(MandelbrotError::Serve)?;
+    if !quiet {
+        let address = listener.local_addr().map_err(MandelbrotError::Serve)?;
+        eprintln!(
+            "accepting render jobs at http://{}/jobs, results go to {}",
+            address,
+            dir.display()
+        );
+    }
+    let execute = move |id: usize, mut args: RenderArgs| {
+        let format = args.image.format(None).map_err(|err| err.to_string())?;
+        let path = dir.join(format!("job_{:04}.{}", id, format.extension()));
+        args.output = Some(path.to_string_lossy().into_owned());
+        let result = render(&args, true);
+        if !quiet {
+            match &result {
+                Ok(()) => eprintln!("job {}: wrote {}", id, path.display()),
+                Err(err) => eprintln!("job {}: {}", id, err),
+            }
+        }
+        result.map_err(|err| err.to_string())?;
+        Ok((path, format.content_type()))
+    };
+    api::serve(listener, api_job, execute).map_err(MandelbrotError::Serve)
+}
+
+fn worker(args: &WorkerArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let listener =
+        std::net::TcpListener::bind(args.listen.as_str()).map_err(MandelbrotError::Serve)?;
+    if !quiet {
+        let address = listener.local_addr().map_err(MandelbrotError::Serve)?;
+        eprintln!("waiting for render jobs on {}", address);
+    }
+    remote::serve(listener, run_job).map_err(MandelbrotError::Serve)
+}
+
+/// 检查排入队列的命令能否解析，并且是写出文件后自行结束的命令
+fn check_queued_command(command: &[String]) -> Result<(), MandelbrotError> {
+    let argv = std::iter::once("mandelbrot".to_string()).chain(command.iter().cloned());
+    let cli = Cli::try_parse_from(argv)
+        .map_err(|err| MandelbrotError::InvalidArgument(clap_message(err)))?;
+    match cli.command {
+        Command::Render(_)
+        | Command::Animate(_)
+        | Command::Cycle(_)
+        | Command::Morph(_)
+        | Command::Dzi(_)
+        | Command::Recolor(_)
+        | Command::Buddhabrot(_)
+        | Command::Newton(_)
+        | Command::Nova(_)
+        | Command::Slice(_)
+        | Command::Atlas(_)
+        | Command::Orbit(_)
+        | Command::Explore(_)
+        | Command::Lyapunov(_) => Ok(()),
+        _ => Err(MandelbrotError::InvalidArgument(
+            "only commands that render files can be queued".to_string(),
+        )),
+    }
+}
+
+/// 在子进程中执行排队的命令，失败时返回子进程报告的错误
+fn run_queued(exe: &Path, job: &queue::Job) -> Result<(), String> {
+    let output = std::process::Command::new(exe)
+        .arg("-q")
+        .args(&job.args)
+        .current_dir(&job.cwd)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("cannot run {}: {}", exe.display(), err))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map(|line| line.trim_start_matches("error: ").to_string())
+        .unwrap_or_else(|| output.status.to_string()))
+}
+
+fn queue(args: &QueueArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let dir = match &args.dir {
+        Some(dir) => PathBuf::from(dir),
+        None => queue::queue_path().ok_or_else(|| {
+            MandelbrotError::InvalidArgument(
+                "cannot locate the queue directory; set MANDELBROT_QUEUE or pass --dir".to_string(),
+            )
+        })?,
+    };
+    let queue = Queue::open(&dir).map_err(MandelbrotError::writing(dir.display()))?;
+    match &args.command {
+        QueueCommand::Add { command } => {
+            check_queued_command(command)?;
+            let cwd = std::env::current_dir().map_err(MandelbrotError::reading("."))?;
+            let id = queue
+                .add(command, &cwd)
+                .map_err(MandelbrotError::writing(dir.display()))?;
+            if !quiet {
+                eprintln!("queued job {}", id);
+            }
+            Ok(())
+        }
+        QueueCommand::List => {
+            let jobs = queue
+                .list()
+                .map_err(MandelbrotError::reading(dir.display()))?;
+            for (state, job) in jobs {
+                println!("{:>6}  {:<8} {}", job.id, state.name(), job.args.join(" "));
+                if let Some(error) = &job.error {
+                    println!("{:>6}  {:<8} {}", "", "", error);
+                }
+            }
+            Ok(())
+        }
+        QueueCommand::Run { parallel } => {
+            // 锁随 _lock 一起在函数返回时释放
+            let _lock = queue
+                .lock()
+                .map_err(MandelbrotError::writing(dir.display()))?
+                .ok_or_else(|| {
+                    MandelbrotError::InvalidArgument(format!(
+                        "{} is already being run by another process",
+                        dir.display()
+                    ))
+                })?;
+            let recovered = queue
+                .recover()
+                .map_err(MandelbrotError::writing(dir.display()))?;
+            if recovered > 0 && !quiet {
+                eprintln!("requeued {} interrupted job(s)", recovered);
+            }
+            let exe = std::env::current_exe().map_err(MandelbrotError::reading("mandelbrot"))?;
+            let failed = AtomicUsize::new(0);
+            let slot = || -> io::Result<()> {
+                while let Some(job) = queue.claim()? {
+                    if !quiet {
+                        eprintln!("job {}: {}", job.id, job.args.join(" "));
+                    }
+                    let result = run_queued(&exe, &job);
+                    if let Err(message) = &result {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        if !quiet {
+                            eprintln!("job {} failed: {}", job.id, message);
+                        }
+                    }
+                    queue.finish(&job, result)?;
+                }
+                Ok(())
+            };
+            std::thread::scope(|scope| {
+                let slots: Vec<_> = (0..*parallel).map(|_| scope.spawn(slot)).collect();
+                slots
+                    .into_iter()
+                    .try_for_each(|slot| slot.join().expect("queue slot panicked"))
+            })
+            .map_err(MandelbrotError::writing(dir.display()))?;
+            match failed.into_inner() {
+                0 => Ok(()),
+                count => Err(MandelbrotError::JobsFailed { count }),
+            }
+        }
+    }
+}
+
+/// render 给出了 --config 时，把配置文件转换为参数插在命令行参数之前重新解析
+fn apply_config(cli: Cli) -> Result<Cli, MandelbrotError> {
+    let Command::Render(args) = &cli.command else {
+        return Ok(cli);
+    };
+    let Some(filename) = &args.config else {
+        return Ok(cli);
+    };
+    let text = std::fs::read_to_string(filename).map_err(MandelbrotError::reading(filename))?;
+    let (output, mut inserted) = config_args(&text).map_err(|message| MandelbrotError::Parse {
+        path: filename.clone(),
+        message,
+    })?;
+    if args.output.is_none() {
+        inserted.extend(output);
+    }
+    let mut argv: Vec<String> = std::env::args().collect();
+    // 全局选项都不带值，第一个等于 render 的参数就是子命令本身
+    let position = argv
+        .iter()
+        .skip(1)
+        .position(|arg| arg == "render")
+        .expect("render subcommand was parsed")
+        + 2;
+    argv.splice(position..position, inserted);
+    Ok(Cli::parse_from(argv))
+}
+
+/// 读取用户书签文件，没有设置书签文件的位置时返回空列表
+fn load_user_bookmarks() -> Result<Vec<Location>, MandelbrotError> {
+    match location::bookmarks_path() {
+        Some(path) => {
+            location::load_bookmarks(&path).map_err(MandelbrotError::reading(path.display()))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+fn bench(args: &BenchArgs, quiet: bool) -> Result<(), MandelbrotError> {
+    let config = BenchConfig {
+        bounds: args.size,
+        limit: args.max_iter,
+        max_threads: args.max_threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, |threads| threads.get())
+        }),
+        repeat: args.repeat,
+    };
+    if args.coordinates {
+        let measurements = bench::measure_mapping(&config);
+        let baseline = measurements[0].1;
+        let pixels = (config.bounds.0 * config.bounds.1) as f64;
+        if args.json {
+            let results = measurements
+                .iter()
+                .map(|(mapping, seconds)| {
+                    json!({
+                        "mapping": mapping.name(),
+                        "seconds": seconds,
+                        "pixels_per_second": pixels / seconds,
+                        "speedup": baseline / seconds,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let document = json!({
+                "width": config.bounds.0,
+                "height": config.bounds.1,
+                "repeat": config.repeat,
+                "results": results,
+            });
+            println!("{}", serde_json::to_string_pretty(&document).unwrap());
+        } else {
+            println!(
+                "{:<12} {:>10} {:>12} {:>8}",
+                "mapping", "ms", "Mpixels/s", "speedup"
+            );
+            for (mapping, seconds) in measurements {
+                println!(
+                    "{:<12} {:>10.2} {:>12.1} {:>7.2}x",
+                    mapping.name(),
+                    seconds * 1000.0,
+                    pixels / seconds / 1e6,
+                    baseline / seconds
+                );
             }
+        }
+        return Ok(());
+    }
+    if !args.json {
+        println!(
+            "{:<10} {:<12} {:<6} {:>7} {:>10} {:>12} {:>8}",
+            "view", "strategy", "simd", "threads", "ms", "Mpixels/s", "speedup"
+        );
+    }
+    let measurements = bench::run(&config, |measurement| {
+        if !args.json {
+            println!(
+                "{:<10} {:<12} {:<6} {:>7} {:>10.1} {:>12.2} {:>7.2}x",
+                measurement.view,
+                measurement.strategy.name(),
+                if measurement.simd { "yes" } else { "no" },
+                measurement.threads,
+                measurement.seconds * 1000.0,
+                measurement.pixels_per_second() / 1e6,
+                measurement.speedup
+            );
+        } else if !quiet {
+            eprintln!(
+                "{} {} {} threads: {:.1} ms",
+                measurement.view,
+                measurement.strategy.name(),
+                measurement.threads,
+                measurement.seconds * 1000.0
+            );
+        }
+    });
+    if args.json {
+        print!("{}", bench::to_json(&config, &measurements));
+    }
+    Ok(())
+}
+
+fn bookmarks(command: &BookmarksCommand) -> Result<(), MandelbrotError> {
+    let mut bookmarks = load_user_bookmarks()?;
+    let path = || {
+        location::bookmarks_path().ok_or_else(|| {
+            MandelbrotError::InvalidArgument(
+                "cannot locate the bookmarks file; set MANDELBROT_BOOKMARKS".to_string(),
+            )
         })
-        .unwrap();
+    };
+    match command {
+        BookmarksCommand::List => {
+            for (kind, locations) in [("built-in", location::builtin()), ("user", bookmarks)] {
+                for location in locations {
+                    println!(
+                        "{:<16} {:<8} --center {} --zoom {}  {}",
+                        location.name, kind, location.center, location.zoom, location.description
+                    );
+                }
+            }
+            Ok(())
+        }
+        BookmarksCommand::Add(args) => {
+            let path = path()?;
+            bookmarks.retain(|location| location.name != args.name);
+            bookmarks.push(Location {
+                name: args.name.clone(),
+                center: args.center.clone(),
+                zoom: args.zoom,
+                description: args.description.clone(),
+            });
+            location::save_bookmarks(&path, &bookmarks)
+                .map_err(MandelbrotError::writing(path.display()))
+        }
+        BookmarksCommand::Remove { name } => {
+            let path = path()?;
+            let count = bookmarks.len();
+            bookmarks.retain(|location| &location.name != name);
+            if bookmarks.len() == count {
+                return Err(MandelbrotError::InvalidArgument(format!(
+                    "no user bookmark named `{}`",
+                    name
+                )));
+            }
+            location::save_bookmarks(&path, &bookmarks)
+                .map_err(MandelbrotError::writing(path.display()))
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), MandelbrotError> {
+    let mut cli = apply_config(cli)?;
+    if let Command::Render(RenderArgs { view, .. })
+    | Command::Animate(AnimateArgs { view, .. })
+    | Command::Cycle(CycleArgs { view, .. })
+    | Command::Morph(MorphArgs { view, .. })
+    | Command::Dzi(DziArgs { view, .. })
+    | Command::Orbit(OrbitArgs { view, .. }) = &mut cli.command
+    {
+        view.resolve_location()?;
+    }
+    set_interior_check(!cli.no_interior_check);
+    set_symmetry(!cli.no_symmetry);
+    threads::configure(cli.threads, cli.pin_threads).map_err(|err| {
+        MandelbrotError::InvalidArgument(format!("cannot configure the thread pool: {}", err))
+    })?;
+    match &cli.command {
+        Command::Render(args) => render(args, cli.quiet),
+        Command::Animate(args) => animate(args, cli.quiet),
+        Command::Cycle(args) => cycle(args, cli.quiet),
+        Command::Morph(args) => morph(args, cli.quiet),
+        Command::Dzi(args) => dzi(args, cli.quiet),
+        Command::Recolor(args) => recolor(args, cli.quiet),
+        Command::Buddhabrot(args) => buddhabrot(args, cli.quiet),
+        Command::Newton(args) => newton(args, cli.quiet),
+        Command::Nova(args) => nova(args, cli.quiet),
+        Command::Slice(args) => slice(args, cli.quiet),
+        Command::Atlas(args) => atlas(args, cli.quiet),
+        Command::Orbit(args) => orbit(args, cli.quiet),
+        Command::FindMinibrot(args) => find_minibrot_command(args),
+        Command::FindMisiurewicz(args) => find_misiurewicz_command(args),
+        Command::Explore(args) => explore_command(args, cli.quiet),
+        Command::Measure(args) => measure_command(args, cli.quiet),
+        Command::Lyapunov(args) => lyapunov(args, cli.quiet),
+        Command::Bench(args) => bench(args, cli.quiet),
+        Command::Serve(args) => serve(args, cli.quiet),
+        Command::Api(args) => api(args, cli.quiet),
+        Command::Worker(args) => worker(args, cli.quiet),
+        #[cfg(feature = "viewer")]
+        Command::View(args) => view(args),
+        Command::Queue(args) => queue(args, cli.quiet),
+        Command::Bookmarks(command) => bookmarks(command),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(err.exit_code())
+        }
     }
-     */
-    write_image(&args[1], &pixels, bounds).expect("error writing PNG file");
 }
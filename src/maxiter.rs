@@ -0,0 +1,110 @@
+//! 最大迭代次数的自动选择
+//!
+//! `--max-iter auto` 先按缩放倍数估计一个迭代次数（见 `explore::iteration_limit`），再用
+//! 低分辨率的试渲染逐次加倍：迭代次数不够时，边界附近逃逸得很慢的像素会被误判为内部，
+//! 加倍后内部像素的比例随之下降。比例几乎不再变化时，加倍前的迭代次数就足够了。
+
+use crate::explore::iteration_limit;
+use crate::INTERIOR;
+
+/// 试渲染的宽度，高度按图像的宽高比取
+pub const PROBE_WIDTH: usize = 160;
+
+/// 加倍迭代次数后内部像素减少不到原来的这个比例、或者不到全部像素的 `TOLERANCE` 时认为
+/// 已经稳定；前者针对内部很多的视图，后者针对几乎没有内部的视图
+pub const RELATIVE_TOLERANCE: f64 = 0.01;
+pub const TOLERANCE: f64 = 1e-3;
+
+/// 按缩放倍数估计时的最小迭代次数
+const BASE: usize = 100;
+
+/// 自动选择的迭代次数的上限
+pub const MAX_AUTO: usize = 1 << 24;
+
+/// `--max-iter` 的值：给定的迭代次数，或者自动选择
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaxIter {
+    Fixed(usize),
+    Auto,
+}
+
+pub fn parse_max_iter_or_auto(s: &str) -> Option<MaxIter> {
+    match s {
+        "auto" => Some(MaxIter::Auto),
+        s => crate::parse_max_iter(s).map(MaxIter::Fixed),
+    }
+}
+
+/// 迭代缓冲区中内部像素的比例
+fn interior_fraction(iterations: &[u32]) -> f64 {
+    let interior = iterations
+        .iter()
+        .filter(|&&value| value == INTERIOR)
+        .count();
+    interior as f64 / iterations.len().max(1) as f64
+}
+
+/// 为缩放倍数为 `zoom` 的视图选择迭代次数
+///
+/// `probe(limit)` 以 `limit` 为迭代次数限制试渲染视图，返回迭代缓冲区。从按缩放倍数估计的
+/// 迭代次数开始逐次加倍，直到内部像素的比例几乎不再下降，返回加倍前的迭代次数；
+/// 到达 `MAX_AUTO` 仍未稳定时返回 `MAX_AUTO`。
+pub fn auto_limit(zoom: f64, mut probe: impl FnMut(usize) -> Vec<u32>) -> usize {
+    let mut limit = iteration_limit(BASE, zoom).min(MAX_AUTO);
+    let mut interior = interior_fraction(&probe(limit));
+    while limit < MAX_AUTO {
+        let next = (limit * 2).min(MAX_AUTO);
+        let next_interior = interior_fraction(&probe(next));
+        let change = interior - next_interior;
+        if change < TOLERANCE || change < RELATIVE_TOLERANCE * interior {
+            return limit;
+        }
+        (limit, interior) = (next, next_interior);
+    }
+    limit
+}
+
+#[test]
+fn test_auto_limit() {
+    use crate::progress::Progress;
+    use crate::{render_parallel, Coloring, Fractal, PixelTransform};
+    use num::Complex;
+
+    assert_eq!(parse_max_iter_or_auto("auto"), Some(MaxIter::Auto));
+    assert_eq!(parse_max_iter_or_auto("500"), Some(MaxIter::Fixed(500)));
+    assert_eq!(parse_max_iter_or_auto("0"), None);
+
+    let probe = |upper_left, lower_right| {
+        move |limit| {
+            let bounds = (PROBE_WIDTH, PROBE_WIDTH * 3 / 4);
+            let mut iterations = vec![0; bounds.0 * bounds.1];
+            let transform = PixelTransform::from_corners(bounds, upper_left, lower_right);
+            render_parallel(
+                Fractal::Mandelbrot,
+                Coloring::EscapeTime,
+                limit,
+                &mut iterations,
+                bounds,
+                transform,
+                (bounds.0, 8),
+                false,
+                &Progress::hidden(),
+            );
+            iterations
+        }
+    };
+    // 整个集合只需要较少的迭代次数；海马谷中的边界细得多，需要多几轮加倍
+    let whole = auto_limit(1.0, probe(Complex::new(-2.5, 1.5), Complex::new(1.5, -1.5)));
+    let valley = auto_limit(
+        1e4,
+        probe(Complex::new(-0.7438, 0.1319), Complex::new(-0.7434, 0.1316)),
+    );
+    assert!(whole >= BASE && whole < valley, "{} {}", whole, valley);
+    // 全是外部的视图不需要加倍
+    let mut probes = 0;
+    let outside = auto_limit(1.0, |limit| {
+        probes += 1;
+        probe(Complex::new(1.0, 1.0), Complex::new(2.0, 0.5))(limit)
+    });
+    assert_eq!((outside, probes), (BASE, 2));
+}
@@ -0,0 +1,227 @@
+//! 曼德博集面积的估计
+//!
+//! 集合关于实轴对称，并且上半部分完全落在 `[-2, 0.5] × [0, 1.25]` 内，因此只在这个矩形中
+//! 采样，把落在集合内的比例乘以两倍的矩形面积。每个采样点只迭代一次，到最大的迭代次数
+//! 限制为止，同时得到所有较小限制下的结果。迭代次数限制越大，被误判为在集合内的边界
+//! 附近的点越少，估计值单调减小，逐渐接近目前已知的面积（约 1.50659）。
+//!
+//! 两种估计方式：
+//!
+//! - 蒙特卡罗：在矩形内均匀随机采样，按二项分布给出置信区间，误差按 `1/√n` 减小。
+//! - 像素计数：把矩形划分成像素，每个像素内随机取一个点（分层抽样）。只有跨越边界的
+//!   像素贡献方差，误差比蒙特卡罗小得多。每一行中相邻的两个像素合并为一层，用两者之差
+//!   估计方差（合并层方法），得到的置信区间偏保守。
+
+use crate::buddhabrot::Rng;
+use crate::progress::Progress;
+use crate::{escape_time, in_main_cardioid_or_bulb};
+use num::Complex;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+/// 采样矩形的左右边界和上边界，下边界是实轴
+const LEFT: f64 = -2.0;
+const RIGHT: f64 = 0.5;
+const TOP: f64 = 1.25;
+
+/// 蒙特卡罗采样被分成的任务数，与 `buddhabrot` 相同
+const CHUNKS: usize = 256;
+
+/// 95% 置信区间对应的标准正态分位数
+const Z_95: f64 = 1.959964;
+
+/// 估计方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    MonteCarlo,
+    Pixels,
+}
+
+pub fn parse_method(s: &str) -> Option<Method> {
+    match s {
+        "monte-carlo" => Some(Method::MonteCarlo),
+        "pixels" => Some(Method::Pixels),
+        _ => None,
+    }
+}
+
+/// 某个迭代次数限制下的面积估计
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub limit: usize,
+    /// 采样点总数和其中在集合内的点数
+    pub samples: usize,
+    pub inside: usize,
+    pub area: f64,
+    /// 95% 置信区间的半宽
+    pub error: f64,
+}
+
+/// 各个迭代次数限制下的计数，可以逐个任务相加
+#[derive(Clone, Debug)]
+struct Tally {
+    samples: usize,
+    inside: Vec<usize>,
+    /// 像素计数时每对相邻像素的结果之差的平方和，即两者恰有一个在集合内的对数
+    split_pairs: Vec<usize>,
+}
+
+impl Tally {
+    fn new(limits: &[usize]) -> Tally {
+        Tally {
+            samples: 0,
+            inside: vec![0; limits.len()],
+            split_pairs: vec![0; limits.len()],
+        }
+    }
+
+    fn sum(mut self, other: Tally) -> Tally {
+        self.samples += other.samples;
+        for (a, b) in self.inside.iter_mut().zip(other.inside) {
+            *a += b;
+        }
+        for (a, b) in self.split_pairs.iter_mut().zip(other.split_pairs) {
+            *a += b;
+        }
+        self
+    }
+}
+
+/// `c` 逃逸前的迭代次数，不逃逸时为 `limit`；`limit` 不超过它的限制下 `c` 都被判定在集合内
+fn survival(c: Complex<f64>, limit: usize) -> usize {
+    if in_main_cardioid_or_bulb(c) {
+        return limit;
+    }
+    escape_time(Complex::new(0.0, 0.0), c, limit).unwrap_or(limit)
+}
+
+/// 在严格递增的各个迭代次数限制 `limits` 下估计曼德博集的面积，约用 `samples` 个采样点
+///
+/// 像素计数时像素网格的宽高比与采样矩形相同，实际的采样点数会略有出入，见
+/// `Estimate::samples`。`seed` 决定采样序列，相同的参数总是得到相同的结果。每完成
+/// 一个任务就在 `progress` 上记录一次。
+pub fn measure(
+    method: Method,
+    samples: usize,
+    limits: &[usize],
+    seed: u64,
+    progress: &Progress,
+) -> Vec<Estimate> {
+    assert!(!limits.is_empty() && limits.windows(2).all(|pair| pair[0] < pair[1]));
+    let max_limit = limits[limits.len() - 1];
+    let record = |tally: &mut Tally, c: Complex<f64>| {
+        let survival = survival(c, max_limit);
+        for (inside, &limit) in tally.inside.iter_mut().zip(limits) {
+            *inside += usize::from(survival >= limit);
+        }
+        tally.samples += 1;
+        survival
+    };
+    let (width, height) = (RIGHT - LEFT, TOP);
+    let mix = |task: usize| seed ^ (task as u64).wrapping_mul(0xd134_2543_de82_ef95);
+
+    let tally = match method {
+        Method::MonteCarlo => {
+            progress.start(CHUNKS, "chunks");
+            (0..CHUNKS)
+                .into_par_iter()
+                .map(|chunk| {
+                    let mut rng = Rng::new(mix(chunk));
+                    let mut tally = Tally::new(limits);
+                    let count = samples / CHUNKS + usize::from(chunk < samples % CHUNKS);
+                    for _ in 0..count {
+                        let c =
+                            Complex::new(LEFT + rng.next_f64() * width, rng.next_f64() * height);
+                        record(&mut tally, c);
+                    }
+                    progress.inc(1);
+                    tally
+                })
+                .reduce(|| Tally::new(limits), Tally::sum)
+        }
+        Method::Pixels => {
+            // 宽高比为 2:1，宽度取偶数，每一行的像素恰好两两配对
+            let columns = (((2 * samples) as f64).sqrt() / 2.0).ceil().max(1.0) as usize * 2;
+            let rows = (samples / columns).max(1);
+            let pixel = (width / columns as f64, height / rows as f64);
+            progress.start(rows, "rows");
+            (0..rows)
+                .into_par_iter()
+                .map(|row| {
+                    let mut rng = Rng::new(mix(row));
+                    let mut tally = Tally::new(limits);
+                    for pair in 0..columns / 2 {
+                        let mut sample = |column: usize| {
+                            let c = Complex::new(
+                                LEFT + (column as f64 + rng.next_f64()) * pixel.0,
+                                (row as f64 + rng.next_f64()) * pixel.1,
+                            );
+                            record(&mut tally, c)
+                        };
+                        let (a, b) = (sample(2 * pair), sample(2 * pair + 1));
+                        for (split, &limit) in tally.split_pairs.iter_mut().zip(limits) {
+                            *split += usize::from((a >= limit) != (b >= limit));
+                        }
+                    }
+                    progress.inc(1);
+                    tally
+                })
+                .reduce(|| Tally::new(limits), Tally::sum)
+        }
+    };
+    progress.finish();
+
+    let total_area = 2.0 * width * height;
+    let n = tally.samples as f64;
+    limits
+        .iter()
+        .enumerate()
+        .map(|(k, &limit)| {
+            let inside = tally.inside[k];
+            let p = inside as f64 / n;
+            let variance = match method {
+                Method::MonteCarlo => p * (1.0 - p) / n,
+                Method::Pixels => tally.split_pairs[k] as f64 / (n * n),
+            };
+            Estimate {
+                limit,
+                samples: tally.samples,
+                inside,
+                area: total_area * p,
+                error: Z_95 * total_area * variance.sqrt(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_measure() {
+    assert_eq!(parse_method("pixels"), Some(Method::Pixels));
+    assert_eq!(parse_method("grid"), None);
+    assert_eq!(survival(Complex::new(1.0, 0.0), 100), 3);
+    assert_eq!(survival(Complex::new(-1.0, 0.0), 100), 100);
+
+    let limits = [10, 100, 1000];
+    let hidden = Progress::hidden();
+    let known = 1.50659;
+    let monte_carlo = measure(Method::MonteCarlo, 100_000, &limits, 1, &hidden);
+    let pixels = measure(Method::Pixels, 100_000, &limits, 1, &hidden);
+    assert_eq!(monte_carlo.len(), 3);
+    assert_eq!(monte_carlo[0].samples, 100_000);
+    assert_eq!(pixels[0].samples % 2, 0);
+    assert!((pixels[0].samples as f64 - 100_000.0).abs() < 1000.0);
+    for estimates in [&monte_carlo, &pixels] {
+        // 限制越大，在集合内的点越少
+        assert!(estimates
+            .windows(2)
+            .all(|pair| pair[0].inside >= pair[1].inside));
+        let last = &estimates[2];
+        assert_eq!(last.limit, 1000);
+        assert!((last.area - known).abs() < last.error + 0.01);
+    }
+    // 分层抽样的误差小得多；相同的种子得到相同的结果
+    assert!(pixels[2].error < monte_carlo[2].error / 3.0);
+    assert_eq!(
+        measure(Method::MonteCarlo, 100_000, &limits, 1, &hidden),
+        monte_carlo
+    );
+}
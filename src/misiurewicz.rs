@@ -0,0 +1,137 @@
+//! 米休雷维奇点的定位
+//!
+//! 米休雷维奇点是预周期的参数 `c`：从原点出发的轨道经过 `preperiod` 步以后进入周期为
+//! `period` 的环，即 `z_{preperiod + period}(c) = z_preperiod(c)`。曼德博集在这些点附近
+//! 渐近自相似，每放大 `|ρ|` 倍、转过 `arg ρ` 就重复一次（`ρ` 是环的乘子），因此它们是
+//! 缩放动画的理想目标。轨道的迭代和牛顿法的停止条件与 `nucleus` 共用：轨道用任意精度的
+//! `FixedComplex` 迭代，导数和牛顿法的修正量用 `f64` 计算。
+
+use crate::nucleus::{newton, orbit, tolerance};
+use crate::precise::FixedComplex;
+use num::Complex;
+
+/// 找到的米休雷维奇点
+#[derive(Clone, Debug, PartialEq)]
+pub struct Misiurewicz {
+    /// 点的精确位置
+    pub point: FixedComplex,
+    pub preperiod: usize,
+    pub period: usize,
+    /// 环的乘子 `ρ = Π 2 z_j`，`j` 取遍环上的 `period` 个点
+    pub multiplier: Complex<f64>,
+}
+
+impl Misiurewicz {
+    /// 图案重复一次所需的缩放倍数
+    pub fn zoom_factor(&self) -> f64 {
+        self.multiplier.norm()
+    }
+
+    /// 图案重复一次时转过的角度（度）
+    pub fn angle(&self) -> f64 {
+        self.multiplier.arg().to_degrees()
+    }
+}
+
+/// `a - b` 的 `f64` 近似；先用定点数相减，避免两个很接近的数相减时丢掉有效位
+fn difference(a: &FixedComplex, b: &FixedComplex) -> Complex<f64> {
+    Complex::new((&a.re - &b.re).to_f64(), (&a.im - &b.im).to_f64())
+}
+
+/// 在近似位置 `center` 附近找出预周期为 `preperiod`、周期为 `period` 的米休雷维奇点，
+/// 牛顿法最多迭代 `max_steps` 步
+///
+/// 方程 `z_{k+p} = z_k` 的根也包括所有预周期更短的点，其中迷你曼德博集的核还是重根，
+/// 会把牛顿法吸引过去。因此对 `f = (z_{k+p} - z_k) / Π_{i<k} (z_{i+p} - z_i)` 求根，
+/// 除掉这些根以后收敛域更大。由于 `z_1 = c`，预周期至少为 2，更短的根都是核。
+///
+/// 求根在 `center` 的精度下进行。牛顿法不收敛、或者收敛到周期是 `period` 的真因数的点
+/// 时返回说明原因的消息。
+pub fn find_misiurewicz(
+    center: &FixedComplex,
+    preperiod: usize,
+    period: usize,
+    max_steps: usize,
+) -> Result<Misiurewicz, String> {
+    assert!(preperiod >= 2 && period > 0);
+    let length = preperiod + period;
+    let c = newton(center, max_steps, |c| {
+        let (orbit, derivatives) = orbit(c, length).ok_or("Newton's method diverged")?;
+        let value = difference(&orbit[length], &orbit[preperiod]);
+        if value == Complex::new(0.0, 0.0) {
+            return Ok(None);
+        }
+        // 用对数导数 f'/f 求修正量，f 本身可能小到超出 f64 的范围
+        let ratio = |i: usize, difference: Complex<f64>| {
+            (derivatives[i + period] - derivatives[i]) / difference
+        };
+        let removed: Complex<f64> = (0..preperiod)
+            .map(|i| ratio(i, difference(&orbit[i + period], &orbit[i])))
+            .sum();
+        Ok(Some(1.0 / (ratio(preperiod, value) - removed)))
+    })?;
+
+    let (orbit, _) = orbit(&c, length).ok_or("Newton's method diverged")?;
+    let tolerance = tolerance(c.re.bits());
+    let returns = |start: usize, period: usize| {
+        difference(&orbit[start + period], &orbit[start]).norm() < tolerance
+    };
+    if returns(preperiod - 1, period) {
+        return Err(format!(
+            "Newton's method converged to a point of preperiod less than {}",
+            preperiod
+        ));
+    }
+    if let Some(k) = (1..period)
+        .filter(|&k| period.is_multiple_of(k))
+        .find(|&k| returns(preperiod, k))
+    {
+        return Err(format!(
+            "Newton's method converged to a point of period {}, not {}",
+            k, period
+        ));
+    }
+    let multiplier = orbit[preperiod..length]
+        .iter()
+        .fold(Complex::new(1.0, 0.0), |product, z| {
+            product * z.to_complex() * 2.0
+        });
+    Ok(Misiurewicz {
+        point: c,
+        preperiod,
+        period,
+        multiplier,
+    })
+}
+
+#[test]
+fn test_find_misiurewicz() {
+    // c = -2：0, -2, 2, 2……，预周期 2、周期 1，乘子为 4
+    let start = FixedComplex::parse("-1.9,0.05", 128).unwrap();
+    let tip = find_misiurewicz(&start, 2, 1, 64).unwrap();
+    assert_eq!(tip.point.to_complex(), Complex::new(-2.0, 0.0));
+    assert_eq!(tip.multiplier, Complex::new(4.0, 0.0));
+
+    // c = i：0, i, -1 + i, -i, -1 + i……，预周期 2、周期 2，乘子为 4 + 4i
+    let start = FixedComplex::parse("0.05,0.95", 128).unwrap();
+    let point = find_misiurewicz(&start, 2, 2, 64).unwrap();
+    let c = point.point.to_complex();
+    assert!((c - Complex::new(0.0, 1.0)).norm() < 1e-15);
+    assert!((point.multiplier - Complex::new(4.0, 4.0)).norm() < 1e-12);
+    assert!((point.zoom_factor() - 32f64.sqrt()).abs() < 1e-12);
+    assert!((point.angle() - 45.0).abs() < 1e-9);
+    // 位数远超 f64：修正量降到定点数的舍入误差才停止
+    let one = crate::precise::Fixed::from_f64(1.0, 128);
+    let exact = &point.point.re.abs() + &(&point.point.im - &one).abs();
+    assert!(exact.to_f64() < 1e-30);
+
+    // 除掉预周期更短的根以后，从原点或周期 2 的核附近出发也不会被它们吸引过去
+    let start = FixedComplex::parse("-0.1,0.9", 128).unwrap();
+    let point = find_misiurewicz(&start, 2, 2, 64).unwrap();
+    assert!((point.point.to_complex() - Complex::new(0.0, 1.0)).norm() < 1e-15);
+    let start = FixedComplex::parse("-1.02,0.01", 128).unwrap();
+    assert_eq!(
+        find_misiurewicz(&start, 2, 2, 64),
+        Err("Newton's method converged to a point of period 1, not 2".to_string())
+    );
+}
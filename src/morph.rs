@@ -0,0 +1,178 @@
+//! 朱利亚集常数的路径
+//!
+//! 朱利亚集的形状由常数 c 决定：c 在曼德博集内部时朱利亚集连通，越过边界后碎成尘埃。
+//! 让 c 沿一条路径移动、每一步渲染一帧，就能看到朱利亚集随 c 连续变形，c 沿着曼德博集的
+//! 边界（如主心形线）移动时变化最为丰富。
+
+use crate::parse_complex;
+use num::Complex;
+use std::f64::consts::TAU;
+
+/// 常数 c 移动的路径
+#[derive(Clone, Debug, PartialEq)]
+pub enum JuliaPath {
+    /// 从第一个点到第二个点的线段
+    Line(Complex<f64>, Complex<f64>),
+    /// 以 `center` 为圆心、`radius` 为半径，从正实轴方向出发逆时针绕一周的圆
+    Circle { center: Complex<f64>, radius: f64 },
+    /// 曼德博集主心形线的边界 c = e^(iθ)/2 - e^(2iθ)/4，从尖点 1/4 出发逆时针绕一周
+    Cardioid,
+    /// 依次经过各个路点的折线，各段按长度匀速移动
+    Points(Vec<Complex<f64>>),
+}
+
+impl JuliaPath {
+    /// 路径首尾相接时，最后一帧之后接回第一帧，不重复渲染起点
+    pub fn is_closed(&self) -> bool {
+        matches!(self, JuliaPath::Circle { .. } | JuliaPath::Cardioid)
+    }
+
+    /// 路径上参数为 `t`（0 到 1）的点
+    pub fn point(&self, t: f64) -> Complex<f64> {
+        match self {
+            JuliaPath::Line(from, to) => from + (to - from) * t,
+            JuliaPath::Circle { center, radius } => center + Complex::from_polar(*radius, TAU * t),
+            JuliaPath::Cardioid => {
+                let w = Complex::from_polar(0.5, TAU * t);
+                w - w * w
+            }
+            JuliaPath::Points(points) => {
+                let lengths: Vec<f64> = points.windows(2).map(|w| (w[1] - w[0]).norm()).collect();
+                let total: f64 = lengths.iter().sum();
+                if total == 0.0 {
+                    return points[0];
+                }
+                let mut remaining = t.clamp(0.0, 1.0) * total;
+                for (segment, &length) in points.windows(2).zip(&lengths) {
+                    if remaining <= length && length > 0.0 {
+                        return segment[0] + (segment[1] - segment[0]) * (remaining / length);
+                    }
+                    remaining -= length;
+                }
+                points[points.len() - 1]
+            }
+        }
+    }
+
+    /// 动画中第 `frame` 帧（共 `frames` 帧）的常数 c
+    ///
+    /// 不闭合的路径第一帧和最后一帧分别在起点和终点；闭合的路径均匀分布在一周上，
+    /// 循环播放时没有停顿。
+    pub fn point_at_frame(&self, frame: usize, frames: usize) -> Complex<f64> {
+        let t = if self.is_closed() {
+            frame as f64 / frames as f64
+        } else if frames <= 1 {
+            0.0
+        } else {
+            frame as f64 / (frames - 1) as f64
+        };
+        self.point(t)
+    }
+}
+
+/// 把字符串 `s` 解析为常数 c 的路径
+///
+/// 可以是 `line:RE,IM:RE,IM`、`circle:RE,IM:RADIUS`、`cardioid`，或者至少两个路点的
+/// `points:RE,IM:RE,IM:...`
+pub fn parse_julia_path(s: &str) -> Option<JuliaPath> {
+    let mut parts = s.split(':');
+    let path = match parts.next()? {
+        "line" => {
+            let from = parse_complex(parts.next()?)?;
+            JuliaPath::Line(from, parse_complex(parts.next()?)?)
+        }
+        "circle" => {
+            let center = parse_complex(parts.next()?)?;
+            let radius = parts.next()?.parse().ok().filter(|r: &f64| r.is_finite())?;
+            JuliaPath::Circle { center, radius }
+        }
+        "cardioid" => JuliaPath::Cardioid,
+        "points" => {
+            let points = parts
+                .by_ref()
+                .map(parse_complex)
+                .collect::<Option<Vec<_>>>()?;
+            if points.len() < 2 {
+                return None;
+            }
+            JuliaPath::Points(points)
+        }
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(path)
+}
+
+#[test]
+fn test_parse_julia_path() {
+    assert_eq!(
+        parse_julia_path("line:-0.8,0.156:-0.7,0.27"),
+        Some(JuliaPath::Line(
+            Complex::new(-0.8, 0.156),
+            Complex::new(-0.7, 0.27)
+        ))
+    );
+    assert_eq!(
+        parse_julia_path("circle:0,0:0.7885"),
+        Some(JuliaPath::Circle {
+            center: Complex::new(0.0, 0.0),
+            radius: 0.7885
+        })
+    );
+    assert_eq!(parse_julia_path("cardioid"), Some(JuliaPath::Cardioid));
+    assert_eq!(
+        parse_julia_path("points:0,0:1,0:1,1"),
+        Some(JuliaPath::Points(vec![
+            Complex::new(0.0, 0.0),
+            Complex::new(1.0, 0.0),
+            Complex::new(1.0, 1.0)
+        ]))
+    );
+    assert_eq!(parse_julia_path("points:0,0"), None);
+    assert_eq!(parse_julia_path("line:0,0"), None);
+    assert_eq!(parse_julia_path("circle:0,0:1:2"), None);
+    assert_eq!(parse_julia_path("cardioid:1"), None);
+    assert_eq!(parse_julia_path("spiral:0,0"), None);
+}
+
+#[test]
+fn test_point_at_frame() {
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+
+    let line = JuliaPath::Line(Complex::new(0.0, 0.0), Complex::new(1.0, 2.0));
+    assert!(close(line.point_at_frame(0, 5), Complex::new(0.0, 0.0)));
+    assert!(close(line.point_at_frame(2, 5), Complex::new(0.5, 1.0)));
+    assert!(close(line.point_at_frame(4, 5), Complex::new(1.0, 2.0)));
+    assert!(close(line.point_at_frame(0, 1), Complex::new(0.0, 0.0)));
+
+    // 闭合路径的最后一帧停在起点之前
+    let circle = JuliaPath::Circle {
+        center: Complex::new(1.0, 0.0),
+        radius: 2.0,
+    };
+    assert!(close(circle.point_at_frame(0, 4), Complex::new(3.0, 0.0)));
+    assert!(close(circle.point_at_frame(1, 4), Complex::new(1.0, 2.0)));
+    assert!(close(circle.point_at_frame(3, 4), Complex::new(1.0, -2.0)));
+
+    // 心形线从尖点出发，半周时到达与周期 2 圆盘的切点 -3/4
+    assert!(close(
+        JuliaPath::Cardioid.point(0.0),
+        Complex::new(0.25, 0.0)
+    ));
+    assert!(close(
+        JuliaPath::Cardioid.point(0.5),
+        Complex::new(-0.75, 0.0)
+    ));
+
+    // 折线按长度匀速移动：第一段长 1，第二段长 3
+    let points = JuliaPath::Points(vec![
+        Complex::new(0.0, 0.0),
+        Complex::new(1.0, 0.0),
+        Complex::new(1.0, 3.0),
+    ]);
+    assert!(close(points.point(0.125), Complex::new(0.5, 0.0)));
+    assert!(close(points.point(0.5), Complex::new(1.0, 1.0)));
+    assert!(close(points.point_at_frame(2, 3), Complex::new(1.0, 3.0)));
+}
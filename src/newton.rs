@@ -0,0 +1,272 @@
+//! 牛顿分形
+//!
+//! 对多项式 `p` 从每个像素对应的点出发做牛顿迭代 `z = z - p(z) / p'(z)`，几乎所有的
+//! 起点都会收敛到 `p` 的某个根，各个根的吸引域之间的边界就是分形。与逃逸时间分形不同，
+//! 这里的停止条件是 `z` 足够接近某个根，而不是离开逃逸半径。像素的色相由收敛到的根
+//! 决定，亮度由收敛所需的迭代次数决定，在 `limit` 次以内没有收敛的像素为黑色。
+
+use crate::palette::Channel;
+use crate::pixed_to_point;
+use crate::progress::Progress;
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 判定收敛的距离：`z` 与某个根的距离小于它时停止迭代
+pub const TOLERANCE: f64 = 1e-6;
+
+/// 用 Durand–Kerner 方法求根时的最大迭代次数
+const ROOT_ITERATIONS: usize = 1000;
+
+/// 复系数多项式，系数按次数从低到高排列，最高次系数不为零
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<Complex<f64>>,
+}
+
+impl Polynomial {
+    /// 由按次数从低到高排列的系数构造多项式，去掉末尾为零的系数
+    pub fn new(mut coefficients: Vec<Complex<f64>>) -> Polynomial {
+        while coefficients
+            .last()
+            .is_some_and(|c| *c == Complex::new(0.0, 0.0))
+        {
+            coefficients.pop();
+        }
+        Polynomial { coefficients }
+    }
+
+    /// 多项式的次数，零多项式的次数记为 0
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    /// 用 Horner 方法同时求出 `p(z)` 和 `p'(z)`
+    pub fn evaluate(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        let zero = Complex::new(0.0, 0.0);
+        self.coefficients
+            .iter()
+            .rev()
+            .fold((zero, zero), |(value, derivative), &c| {
+                (value * z + c, derivative * z + value)
+            })
+    }
+
+    /// 用 Durand–Kerner 方法同时求出全部根，按辐角从小到大排列
+    ///
+    /// 排序使相邻的根得到相邻的色相，也让结果不依赖于求根的过程。
+    pub fn roots(&self) -> Vec<Complex<f64>> {
+        let degree = self.degree();
+        let leading = self.coefficients[degree];
+        // 初值取一个既不是实数也不是单位根的数的各次幂，避免对称性让迭代停滞
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex<f64>> = (0..degree).map(|k| seed.powu(k as u32)).collect();
+        for _ in 0..ROOT_ITERATIONS {
+            let mut change: f64 = 0.0;
+            for i in 0..degree {
+                let (value, _) = self.evaluate(roots[i]);
+                let denominator = (0..degree)
+                    .filter(|&j| j != i)
+                    .fold(leading, |product, j| product * (roots[i] - roots[j]));
+                let step = value / denominator;
+                roots[i] -= step;
+                change = change.max(step.norm());
+            }
+            if change < f64::EPSILON {
+                break;
+            }
+        }
+        roots.sort_by(|a, b| a.arg().total_cmp(&b.arg()));
+        roots
+    }
+}
+
+/// 把形如 `z^3-1`、`z^4 - 3z^2 + 2` 或 `2*z^5+z` 的字符串解析为实系数多项式
+///
+/// 每一项由可选的系数、可选的 `z` 和可选的指数 `^n` 组成，次数至少为 2。
+pub fn parse_polynomial(s: &str) -> Option<Polynomial> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut coefficients = Vec::new();
+    let mut rest = s.as_str();
+    while !rest.is_empty() {
+        let (sign, body) = match rest.as_bytes()[0] {
+            b'+' => (1.0, &rest[1..]),
+            b'-' => (-1.0, &rest[1..]),
+            _ if coefficients.is_empty() => (1.0, rest),
+            _ => return None,
+        };
+        let end = body.find(['+', '-']).unwrap_or(body.len());
+        let (term, remainder) = body.split_at(end);
+        rest = remainder;
+
+        let (coefficient, power) = match term.split_once('z') {
+            None => (term.parse::<f64>().ok()?, 0),
+            Some((coefficient, power)) => {
+                let coefficient = match coefficient.strip_suffix('*').unwrap_or(coefficient) {
+                    "" => 1.0,
+                    coefficient => coefficient.parse().ok()?,
+                };
+                let power = match power {
+                    "" => 1,
+                    power => power.strip_prefix('^')?.parse().ok()?,
+                };
+                (coefficient, power)
+            }
+        };
+        if coefficients.len() <= power {
+            coefficients.resize(power + 1, Complex::new(0.0, 0.0));
+        }
+        coefficients[power] += sign * coefficient;
+    }
+    let polynomial = Polynomial::new(coefficients);
+    (polynomial.degree() >= 2).then_some(polynomial)
+}
+
+#[test]
+fn test_parse_polynomial() {
+    let real = |coefficients: &[f64]| {
+        Some(Polynomial::new(
+            coefficients.iter().map(|&c| Complex::new(c, 0.0)).collect(),
+        ))
+    };
+    assert_eq!(parse_polynomial("z^3-1"), real(&[-1.0, 0.0, 0.0, 1.0]));
+    assert_eq!(
+        parse_polynomial("z^4 - 3z^2 + 2"),
+        real(&[2.0, 0.0, -3.0, 0.0, 1.0])
+    );
+    assert_eq!(parse_polynomial("-1+2*z^2+z"), real(&[-1.0, 1.0, 2.0]));
+    assert_eq!(parse_polynomial("z^2+z^2"), real(&[0.0, 0.0, 2.0]));
+    assert_eq!(parse_polynomial("z-1"), None);
+    assert_eq!(parse_polynomial("z^2-z^2+z"), None);
+    assert_eq!(parse_polynomial("z^2*3"), None);
+    assert_eq!(parse_polynomial("z^2++1"), None);
+    assert_eq!(parse_polynomial(""), None);
+}
+
+#[test]
+fn test_roots() {
+    let polynomial = parse_polynomial("z^3-1").unwrap();
+    let roots = polynomial.roots();
+    assert_eq!(roots.len(), 3);
+    for (root, angle) in roots.iter().zip([-120.0f64, 0.0, 120.0]) {
+        let expected = Complex::from_polar(1.0, angle.to_radians());
+        assert!((root - expected).norm() < 1e-12, "{} != {}", root, expected);
+    }
+    let (value, derivative) = polynomial.evaluate(Complex::new(2.0, 0.0));
+    assert_eq!((value.re, derivative.re), (7.0, 12.0));
+}
+
+/// 从 `z` 出发做最多 `limit` 次牛顿迭代
+///
+/// 收敛时返回 `Some((k, i))`：`z` 在第 `i` 次迭代后离 `roots[k]` 不到 `TOLERANCE`。
+/// 达到 `limit` 仍未收敛，或者遇到导数为零的点时返回 `None`。
+pub fn converge(
+    polynomial: &Polynomial,
+    roots: &[Complex<f64>],
+    mut z: Complex<f64>,
+    limit: usize,
+) -> Option<(usize, usize)> {
+    for i in 0..=limit {
+        if let Some(root) = roots
+            .iter()
+            .position(|&root| (z - root).norm_sqr() < TOLERANCE * TOLERANCE)
+        {
+            return Some((root, i));
+        }
+        if i == limit {
+            break;
+        }
+        let (value, derivative) = polynomial.evaluate(z);
+        let step = value / derivative;
+        if !step.is_finite() {
+            return None;
+        }
+        z -= step;
+    }
+    None
+}
+
+#[test]
+fn test_converge() {
+    let polynomial = parse_polynomial("z^3-1").unwrap();
+    let roots = polynomial.roots();
+    let one = roots.iter().position(|root| root.re > 0.0).unwrap();
+    assert_eq!(
+        converge(&polynomial, &roots, Complex::new(1.0, 0.0), 50),
+        Some((one, 0))
+    );
+    let (root, iterations) = converge(&polynomial, &roots, Complex::new(2.0, 0.0), 50).unwrap();
+    assert_eq!(root, one);
+    assert!(iterations > 1);
+    // 导数在原点为零
+    assert_eq!(
+        converge(&polynomial, &roots, Complex::new(0.0, 0.0), 50),
+        None
+    );
+    assert_eq!(
+        converge(&polynomial, &roots, Complex::new(2.0, 0.0), 1),
+        None
+    );
+}
+
+/// 色相 `hue`（`[0, 1)`，0 为红色）、饱和度为 1、亮度为 `value` 的颜色
+fn hsv(hue: f64, value: f64) -> [f64; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let [r, g, b] = match h as usize {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    };
+    [r * value, g * value, b * value]
+}
+
+/// 像素的颜色：第 `root` 个根（共 `count` 个）决定色相，迭代次数 `iterations` 越少越亮
+pub fn root_color<C: Channel>(
+    root: usize,
+    count: usize,
+    iterations: usize,
+    limit: usize,
+) -> [C; 3] {
+    let brightness = (1.0 - iterations as f64 / limit as f64).powi(2);
+    hsv(root as f64 / count as f64, brightness).map(C::from_fraction)
+}
+
+#[test]
+fn test_root_color() {
+    assert_eq!(root_color::<u8>(0, 3, 0, 10), [255, 0, 0]);
+    assert_eq!(root_color::<u8>(1, 3, 0, 10), [0, 255, 0]);
+    assert_eq!(root_color::<u8>(2, 3, 5, 10), [0, 0, 64]);
+}
+
+/// 并行渲染覆盖 `upper_left` 到 `lower_right` 的 `bounds` 大小的牛顿分形，返回 RGB 像素
+///
+/// 每完成一行就在 `progress` 上记录一次。
+pub fn render<C: Channel>(
+    polynomial: &Polynomial,
+    limit: usize,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    progress: &Progress,
+) -> Vec<C> {
+    let roots = polynomial.roots();
+    let mut pixels = vec![C::default(); bounds.0 * bounds.1 * 3];
+    progress.start(bounds.1, "rows");
+    pixels
+        .par_chunks_mut(bounds.0 * 3)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, pixel) in band.chunks_mut(3).enumerate() {
+                let point = pixed_to_point(bounds, (column, row), upper_left, lower_right);
+                if let Some((root, iterations)) = converge(polynomial, &roots, point, limit) {
+                    pixel.copy_from_slice(&root_color(root, roots.len(), iterations, limit));
+                }
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    pixels
+}
@@ -0,0 +1,159 @@
+//! Nova 分形
+//!
+//! 对 `z^p - 1` 做带松弛系数 `R` 的牛顿迭代，每一步再加上常数 `c`：
+//! `z = z - R (z^p - 1) / (p z^(p-1)) + c`。与曼德博集一样以像素对应的点为 `c`、从固定的
+//! 种子出发（默认为 1，`R = 1` 时它是迭代映射的临界点）；给出 `c` 时则与朱利亚集一样从
+//! 像素对应的点出发。
+//!
+//! 与牛顿分形一样，停止条件是收敛而不是逃逸：相邻两步的距离小于 `tolerance` 时认为轨道
+//! 已经收敛。收敛的像素按所需的迭代次数在调色板中取色，在 `limit` 次以内没有收敛的像素
+//! 视为集合内部。
+
+use crate::progress::Progress;
+use crate::{encode_escape, pixed_to_point};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 默认的收敛判定距离
+pub const TOLERANCE: f64 = 1e-6;
+
+/// Nova 分形的参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Nova {
+    /// 多项式 `z^p - 1` 的次数 `p`，大于 1
+    pub power: f64,
+    /// 牛顿迭代的松弛系数 `R`
+    pub relaxation: Complex<f64>,
+    /// 曼德博类 Nova 分形的迭代起点
+    pub seed: Complex<f64>,
+    /// 给出时是以它为常数的朱利亚类 Nova 分形
+    pub julia: Option<Complex<f64>>,
+}
+
+impl Nova {
+    /// 复平面上的点 `point` 对应的迭代起点 `z` 和常数 `c`
+    pub fn orbit_start(&self, point: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        match self.julia {
+            Some(c) => (point, c),
+            None => (self.seed, point),
+        }
+    }
+
+    /// 对 `z` 做一次迭代
+    pub fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let one = Complex::new(1.0, 0.0);
+        let (power, derivative) = if self.power.fract() == 0.0 {
+            let below = z.powi(self.power as i32 - 1);
+            (below * z, below * self.power)
+        } else {
+            let below = z.powf(self.power - 1.0);
+            (below * z, below * self.power)
+        };
+        z - self.relaxation * (power - one) / derivative + c
+    }
+
+    /// 点 `point` 的轨道收敛所需的连续迭代次数，最多迭代 `limit` 次
+    ///
+    /// 第 `i` 步的步长首次小于 `tolerance` 时，在前后两步的步长的对数之间插值求出 `i` 到
+    /// `i + 1` 之间的小数，颜色因此连续变化。没有收敛或者迭代到了零点（下一步没有定义）
+    /// 时返回 `None`。
+    pub fn converge(&self, point: Complex<f64>, limit: usize, tolerance: f64) -> Option<f64> {
+        let (mut z, c) = self.orbit_start(point);
+        let mut previous: Option<f64> = None;
+        for i in 0..limit {
+            let next = self.step(z, c);
+            let step = (next - z).norm();
+            if !step.is_finite() {
+                return None;
+            }
+            if step < tolerance {
+                let fraction = previous.map_or(0.0, |previous| {
+                    ((tolerance.ln() - previous) / (step.ln() - previous)).clamp(0.0, 1.0)
+                });
+                return Some(i as f64 + fraction);
+            }
+            previous = Some(step.ln());
+            z = next;
+        }
+        None
+    }
+}
+
+#[test]
+fn test_converge() {
+    let nova = Nova {
+        power: 3.0,
+        relaxation: Complex::new(1.0, 0.0),
+        seed: Complex::new(1.0, 0.0),
+        julia: None,
+    };
+    // c = 0 时种子就是 z^3 - 1 的根，第一步就收敛
+    assert_eq!(
+        nova.converge(Complex::new(0.0, 0.0), 100, TOLERANCE),
+        Some(0.0)
+    );
+    // 松弛系数为 1、c = 0 的朱利亚类 Nova 分形就是牛顿分形，收敛次数与之相当
+    let newton = Nova {
+        julia: Some(Complex::new(0.0, 0.0)),
+        ..nova
+    };
+    let polynomial = crate::newton::parse_polynomial("z^3-1").unwrap();
+    let roots = polynomial.roots();
+    for point in [Complex::new(2.0, 0.0), Complex::new(-0.5, 0.7)] {
+        let (_, iterations) = crate::newton::converge(&polynomial, &roots, point, 100).unwrap();
+        let value = newton.converge(point, 100, TOLERANCE).unwrap();
+        assert!(
+            (value - iterations as f64).abs() <= 1.0,
+            "{} vs {}",
+            value,
+            iterations
+        );
+    }
+    // 原点的导数为零，下一步没有定义
+    assert_eq!(
+        newton.converge(Complex::new(0.0, 0.0), 100, TOLERANCE),
+        None
+    );
+    // 更严格的判定需要更多次迭代，连续迭代次数随之连续增加
+    let point = Complex::new(-0.3, 0.2);
+    let loose = nova.converge(point, 500, 1e-3).unwrap();
+    let strict = nova.converge(point, 500, 1e-9).unwrap();
+    assert!(strict > loose);
+    assert_eq!(nova.converge(point, 1, 1e-9), None);
+    // 非整数次数用 powf 迭代，整数次数的两种算法一致
+    let fractional = Nova {
+        power: 3.0 + 1e-12,
+        ..nova
+    };
+    let z = Complex::new(0.3, -0.8);
+    assert!((fractional.step(z, point) - nova.step(z, point)).norm() < 1e-9);
+}
+
+/// 并行渲染覆盖 `upper_left` 到 `lower_right` 的 `bounds` 大小的 Nova 分形，返回逐行排列的
+/// 迭代缓冲区，收敛次数按 `encode_escape` 编码，可以直接用 `colorize` 着色
+///
+/// 每完成一行就在 `progress` 上记录一次。
+pub fn render(
+    nova: &Nova,
+    limit: usize,
+    tolerance: f64,
+    bounds: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    progress: &Progress,
+) -> Vec<u32> {
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    progress.start(bounds.1, "rows");
+    iterations
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, value) in band.iter_mut().enumerate() {
+                let point = pixed_to_point(bounds, (column, row), upper_left, lower_right);
+                *value = encode_escape(nova.converge(point, limit, tolerance));
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+    iterations
+}
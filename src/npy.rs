@@ -0,0 +1,197 @@
+//! 导出 NumPy 数组
+//!
+//! `render --export-npy data.npy` 把迭代缓冲区解码后写成 NumPy 的 `.npy` 文件，用
+//! `numpy.load` 直接读入即可在 Python 中做后续处理：数组形状为 `(高度, 宽度)`，元素是
+//! 小端序的 `f64` 逃逸值，集合内部的像素为 NaN。渲染参数和坐标范围写在同名的
+//! `.json` 附属文件中，该文件已经存在时会被覆盖，重新渲染时两者因此总是配套的。
+
+use crate::{decode_escape, Coloring};
+use num::Complex;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// 把 `bounds` 大小的迭代缓冲区 `iterations` 按 NumPy 1.0 格式写入 `output`
+pub fn encode_npy(
+    mut output: impl Write,
+    iterations: &[u32],
+    bounds: (usize, usize),
+) -> io::Result<()> {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        bounds.1, bounds.0
+    );
+    // 魔数、版本号、头部长度和头部一共占 64 字节的整数倍，头部以换行结尾
+    let unpadded = MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    output.write_all(MAGIC)?;
+    output.write_all(&[1, 0])?;
+    output.write_all(&(header.len() as u16).to_le_bytes())?;
+    output.write_all(header.as_bytes())?;
+    for &encoded in iterations {
+        let value = decode_escape(encoded).unwrap_or(f64::NAN);
+        output.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_encode_npy() {
+    use crate::{encode_escape, INTERIOR};
+
+    let iterations = [
+        encode_escape(Some(1.5)),
+        INTERIOR,
+        0,
+        encode_escape(Some(9.0)),
+    ];
+    let mut bytes = Vec::new();
+    encode_npy(&mut bytes, &iterations, (2, 2)).unwrap();
+
+    assert!(bytes.starts_with(MAGIC));
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+    assert!(header.contains("'shape': (2, 2)"));
+    assert!(header.ends_with('\n'));
+
+    let values: Vec<f64> = bytes[10 + header_len..]
+        .chunks(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[0], 1.5);
+    assert!(values[1].is_nan());
+    assert_eq!(values[2..], [0.0, 9.0]);
+}
+
+/// 写在附属 JSON 文件中的渲染参数
+pub struct NpyMetadata {
+    /// 数组的宽度和高度，超采样时是子像素的尺寸
+    pub bounds: (usize, usize),
+    /// 每个输出像素在每个方向上的采样数
+    pub samples: usize,
+    /// 每个点的最大迭代次数
+    pub limit: usize,
+    /// 数组中逃逸值的含义
+    pub coloring: Coloring,
+    /// 第一行第一列的元素对应的点
+    pub upper_left: Complex<f64>,
+    /// 最后一行最后一列的元素右下方的点
+    pub lower_right: Complex<f64>,
+    /// 以上两点是旋转前的角，整个矩形再绕中心逆时针旋转这么多度
+    pub rotate: f64,
+    /// 渲染时的命令行参数
+    pub description: String,
+}
+
+/// 把 `s` 格式化为 JSON 字符串字面量
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl NpyMetadata {
+    /// 格式化为 JSON 对象，复数写成 `[实部, 虚部]`
+    pub fn to_json(&self) -> String {
+        let coloring = match self.coloring {
+            Coloring::EscapeTime => "escape-time",
+            Coloring::Smooth => "smooth",
+            Coloring::Distance => "distance",
+            Coloring::OrbitTrap(_) => "orbit-trap",
+            Coloring::TriangleInequality => "tia",
+            Coloring::AtomDomain => "atom-domain",
+        };
+        let complex = |c: Complex<f64>| format!("[{}, {}]", c.re, c.im);
+        format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"samples\": {},\n  \"max_iter\": {},\n  \
+             \"coloring\": {},\n  \"upper_left\": {},\n  \"lower_right\": {},\n  \
+             \"rotate\": {},\n  \"interior\": \"NaN\",\n  \"description\": {}\n}}\n",
+            self.bounds.0,
+            self.bounds.1,
+            self.samples,
+            self.limit,
+            json_string(coloring),
+            complex(self.upper_left),
+            complex(self.lower_right),
+            self.rotate,
+            json_string(&self.description)
+        )
+    }
+}
+
+#[test]
+fn test_to_json() {
+    let metadata = NpyMetadata {
+        bounds: (4, 2),
+        samples: 2,
+        limit: 500,
+        coloring: Coloring::Smooth,
+        upper_left: Complex { re: -2.0, im: 1.25 },
+        lower_right: Complex { re: 0.5, im: -1.25 },
+        rotate: 30.0,
+        description: "render \"a b.png\"\n".to_string(),
+    };
+    let json = metadata.to_json();
+    assert!(json.contains("\"width\": 4,"));
+    assert!(json.contains("\"coloring\": \"smooth\","));
+    assert!(json.contains("\"upper_left\": [-2, 1.25],"));
+    assert!(json.contains("\"rotate\": 30,"));
+    assert!(json.contains("\"description\": \"render \\\"a b.png\\\"\\u000a\""));
+}
+
+/// `.npy` 文件 `filename` 的附属 JSON 文件的路径
+pub fn sidecar_path(filename: &str) -> PathBuf {
+    Path::new(filename).with_extension("json")
+}
+
+/// 把迭代缓冲区写入 `filename`，再把 `metadata` 写入 `sidecar_path` 给出的附属文件
+///
+/// 附属文件已经存在时覆盖它；`filename` 本身的扩展名为 `.json` 时附属文件会覆盖数组，
+/// 此时返回错误。
+pub fn write_npy(filename: &str, iterations: &[u32], metadata: &NpyMetadata) -> io::Result<()> {
+    if sidecar_path(filename) == Path::new(filename) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the array would be overwritten by its .json sidecar, use a .npy extension",
+        ));
+    }
+    let mut output = BufWriter::new(File::create(filename)?);
+    encode_npy(&mut output, iterations, metadata.bounds)?;
+    output.flush()?;
+    std::fs::write(sidecar_path(filename), metadata.to_json())
+}
+
+#[test]
+fn test_write_npy() {
+    assert_eq!(sidecar_path("out/data.npy"), Path::new("out/data.json"));
+    let metadata = NpyMetadata {
+        bounds: (1, 1),
+        samples: 1,
+        limit: 100,
+        coloring: Coloring::EscapeTime,
+        upper_left: Complex { re: -2.0, im: 1.0 },
+        lower_right: Complex { re: 1.0, im: -1.0 },
+        rotate: 0.0,
+        description: String::new(),
+    };
+    let error = write_npy("data.json", &[0], &metadata).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,265 @@
+//! 迷你曼德博集的定位
+//!
+//! 周期为 `p` 的双曲分量的中心（核）`c` 满足 `z_p(c) = 0`：从原点出发迭代 `p` 次恰好回到
+//! 原点。从给定的近似位置出发，对这个方程用牛顿法求根即可精确地找到附近的迷你曼德博集。
+//! 深处的迷你曼德博集远小于 `f64` 的分辨率，因此轨道用任意精度的 `FixedComplex` 迭代；
+//! 对 `c` 的导数和牛顿法的修正量只需要相对精度，用 `f64` 计算。
+//!
+//! 找到核之后按轨道估计迷你曼德博集的大小和方向：迷你曼德博集近似于整个曼德博集缩小
+//! `|s|` 倍并转过 `arg s` 的像，`s` 由 `size_estimate` 给出。位数不够表示这个大小时加大
+//! 精度重新求根，直到坐标足以用于深度缩放。
+
+use crate::precise::{bits_for_log2_spacing, Fixed, FixedComplex};
+use crate::Fractal;
+use num::Complex;
+
+/// 找到的迷你曼德博集
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minibrot {
+    /// 核的精确位置
+    pub nucleus: FixedComplex,
+    pub period: usize,
+    /// 迷你曼德博集相对于整个曼德博集的缩放和旋转，参见 `size_estimate`
+    pub size: Size,
+}
+
+/// 迷你曼德博集的大小估计 `s`
+///
+/// 周期较长时 `|s|` 远小于 `f64` 能表示的最小值，因此模以 2 为底的对数保存。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Size {
+    /// `log2 |s|`
+    pub log2: f64,
+    /// `arg s`（弧度）
+    pub arg: f64,
+}
+
+impl Size {
+    /// `|s|`，太小时下溢为 0
+    pub fn norm(&self) -> f64 {
+        self.log2.exp2()
+    }
+}
+
+impl Minibrot {
+    /// 让迷你曼德博集与缩放倍数为 1 时的整个曼德博集一样大的缩放倍数，太大时为无穷大
+    pub fn zoom(&self) -> f64 {
+        (-self.size.log2).exp2()
+    }
+
+    /// 迷你曼德博集相对于整个曼德博集逆时针转过的角度（度），用作视图的 `rotate` 时
+    /// 迷你曼德博集摆正
+    pub fn angle(&self) -> f64 {
+        self.size.arg.to_degrees()
+    }
+
+    /// 足以在迷你曼德博集的尺度上区分出百万分之一的十进制小数位数
+    pub fn digits(&self) -> usize {
+        (6.0 - self.size.log2 * std::f64::consts::LOG10_2)
+            .ceil()
+            .max(1.0) as usize
+    }
+}
+
+/// 把 `2^log2` 写成保留 `precision` 位小数的科学计数法，超出 `f64` 的范围时也能写出
+pub fn exp2_scientific(log2: f64, precision: usize) -> String {
+    let log10 = log2 * std::f64::consts::LOG10_2;
+    let mut exponent = log10.floor();
+    let mut mantissa = format!("{:.*}", precision, 10f64.powf(log10 - exponent));
+    // 舍入后可能进位到 10
+    if mantissa.starts_with("10") {
+        exponent += 1.0;
+        mantissa = format!("{:.*}", precision, 1.0);
+    }
+    format!("{}e{}", mantissa, exponent)
+}
+
+#[test]
+fn test_exp2_scientific() {
+    assert_eq!(exp2_scientific(10.0, 3), "1.024e3");
+    assert_eq!(exp2_scientific(-1.0, 2), "5.00e-1");
+    assert_eq!(exp2_scientific(0.0, 1), "1.0e0");
+    assert_eq!(exp2_scientific(-2000.0, 3), "8.710e-603");
+}
+
+/// 轨道的模超过它的平方时认为牛顿法已经发散；定点数的位数随 `z` 的平方翻倍，不能任其增长
+pub(crate) const DIVERGED: f64 = 1e100;
+
+/// 从原点出发迭代 `length` 次，返回包括原点在内的各步的 `z` 和它们对 `c` 的导数，
+/// 轨道逃逸到很远时返回 `None`
+///
+/// 与 `misiurewicz` 共用。
+pub(crate) fn orbit(
+    c: &FixedComplex,
+    length: usize,
+) -> Option<(Vec<FixedComplex>, Vec<Complex<f64>>)> {
+    let mut z = FixedComplex::zero(c.re.bits());
+    let mut dc = Complex::new(0.0, 0.0);
+    let (mut orbit, mut derivatives) = (vec![z.clone()], vec![dc]);
+    for _ in 0..length {
+        dc = z.to_complex() * dc * 2.0 + 1.0;
+        z = z.step(Fractal::Mandelbrot, c);
+        if z.to_complex().norm_sqr() > DIVERGED {
+            return None;
+        }
+        orbit.push(z.clone());
+        derivatives.push(dc);
+    }
+    Some((orbit, derivatives))
+}
+
+/// 从 `c` 出发用牛顿法求根，最多 `max_steps` 步
+///
+/// `correction` 给出当前位置的修正量 `f / f'`，恰好落在根上时返回 `None`。修正量降到
+/// `c` 的精度的舍入误差量级就不会再变小了，这时停止；修正量不是有限值时认为已经发散。
+/// 与 `misiurewicz` 共用。
+pub(crate) fn newton(
+    c: &FixedComplex,
+    max_steps: usize,
+    mut correction: impl FnMut(&FixedComplex) -> Result<Option<Complex<f64>>, String>,
+) -> Result<FixedComplex, String> {
+    let bits = c.re.bits();
+    let mut c = c.clone();
+    for _ in 0..max_steps {
+        let Some(delta) = correction(&c)? else {
+            return Ok(c);
+        };
+        if !delta.re.is_finite() || !delta.im.is_finite() {
+            return Err("Newton's method diverged".to_string());
+        }
+        c = FixedComplex {
+            re: &c.re - &Fixed::from_f64(delta.re, bits),
+            im: &c.im - &Fixed::from_f64(delta.im, bits),
+        };
+        if delta.norm() < 2f64.powi(16 - bits as i32) {
+            return Ok(c);
+        }
+    }
+    Err(format!(
+        "Newton's method did not converge within {} steps",
+        max_steps
+    ))
+}
+
+/// 以 `bits` 位精度求出的根上，轨道中两点的距离小于它就认为轨道回到了同一点
+pub(crate) fn tolerance(bits: u32) -> f64 {
+    2f64.powi(32 - bits as i32)
+}
+
+/// 核 `c` 处周期为 `period` 的迷你曼德博集的大小估计 `s`，轨道发散或结果不是有限值时
+/// 返回 `None`
+///
+/// 沿轨道累积 `l = z_1' z_2' ... z_j'`（`z_j' = 2 z_j`）和 `b = 1 + Σ 1/l`，`s = 1 / (b l²)`。
+/// `|l|` 随周期指数增长，很快就超出 `f64` 的范围，因此表示为 `l · 2^exponent`，每步都把
+/// `l` 的模规范到 `[1, 2)` 区间。
+pub fn size_estimate(c: &FixedComplex, period: usize) -> Option<Size> {
+    let one = Complex::new(1.0, 0.0);
+    let (orbit, _) = orbit(c, period)?;
+    let (mut l, mut exponent, mut b) = (one, 0i32, one);
+    for z in &orbit[1..period] {
+        l = l * z.to_complex() * 2.0;
+        let shift = l.norm().log2().floor();
+        if !shift.is_finite() {
+            return None;
+        }
+        l *= (-shift).exp2();
+        exponent = exponent.saturating_add(shift as i32);
+        b += one / l * 2f64.powi(exponent.saturating_neg());
+    }
+    let s = one / (b * l * l);
+    let size = Size {
+        log2: s.norm().log2() - 2.0 * exponent as f64,
+        arg: s.arg(),
+    };
+    size.log2.is_finite().then_some(size)
+}
+
+/// 在近似位置 `center` 附近找出周期为 `period` 的迷你曼德博集，每种精度下牛顿法最多迭代
+/// `max_steps` 步
+///
+/// 精度从 `center` 的位数开始，不足以表示迷你曼德博集的百万分之一时加大精度重新求根。
+/// 牛顿法不收敛、或者收敛到周期是 `period` 的真因数的分量时返回说明原因的消息。
+pub fn find_minibrot(
+    center: &FixedComplex,
+    period: usize,
+    max_steps: usize,
+) -> Result<Minibrot, String> {
+    assert!(period > 0);
+    let mut c = center.clone();
+    loop {
+        // 求 z_period(c) = 0 的根
+        c = newton(&c, max_steps, |c| {
+            let (orbit, derivatives) = orbit(c, period).ok_or("Newton's method diverged")?;
+            Ok(Some(orbit[period].to_complex() / derivatives[period]))
+        })?;
+        // 收敛到周期更短的分量时轨道经过原点，没有大小估计，交给下面的检查报告
+        let Some(size) = size_estimate(&c, period) else {
+            break;
+        };
+        let bits = bits_for_log2_spacing(size.log2 - 1e6f64.log2());
+        if bits <= c.re.bits() {
+            break;
+        }
+        c = c.with_bits(bits);
+    }
+
+    let (orbit, _) = orbit(&c, period).ok_or("Newton's method diverged")?;
+    let tolerance = tolerance(c.re.bits());
+    if let Some(k) = (1..period)
+        .filter(|&k| period.is_multiple_of(k))
+        .find(|&k| orbit[k].to_complex().norm() < tolerance)
+    {
+        return Err(format!(
+            "Newton's method converged to a component of period {}, not {}",
+            k, period
+        ));
+    }
+    Ok(Minibrot {
+        size: size_estimate(&c, period).ok_or("Newton's method diverged")?,
+        nucleus: c,
+        period,
+    })
+}
+
+#[test]
+fn test_find_minibrot() {
+    // 周期 3 的迷你曼德博集在负实轴上，核约为 -1.754877666，大小约为整个集合的 1/50
+    let start = FixedComplex::parse("-1.75,0.01", 64).unwrap();
+    let minibrot = find_minibrot(&start, 3, 64).unwrap();
+    let nucleus = minibrot.nucleus.to_complex();
+    assert!((nucleus.re + 1.754877666246693).abs() < 1e-12 && nucleus.im.abs() < 1e-12);
+    assert!((0.018..0.021).contains(&minibrot.size.norm()));
+    assert!((48.0..56.0).contains(&minibrot.zoom()));
+    assert_eq!(minibrot.digits(), 8);
+
+    // 周期 1 的核是原点，大小为 1；从周期 2 的核出发找周期 4 会落在周期 2 的分量上
+    let origin = find_minibrot(&FixedComplex::parse("0.1,0.1", 64).unwrap(), 1, 64).unwrap();
+    assert_eq!(origin.nucleus.to_complex(), Complex::new(0.0, 0.0));
+    assert_eq!(
+        origin.size,
+        Size {
+            log2: 0.0,
+            arg: 0.0
+        }
+    );
+    let error = find_minibrot(&FixedComplex::parse("-1,0", 64).unwrap(), 4, 64);
+    assert_eq!(
+        error,
+        Err("Newton's method converged to a component of period 2, not 4".to_string())
+    );
+
+    // 远小于 f64 分辨率的迷你曼德博集：精度随大小自动提高，核的轨道恰好回到原点
+    let start = FixedComplex::parse("-1.7400623825793399052,0.0281753397792110489", 64).unwrap();
+    let minibrot = find_minibrot(&start, 24, 64).unwrap();
+    assert!((1e-23..1e-21).contains(&minibrot.size.norm()));
+    assert!(minibrot.nucleus.re.bits() > 128);
+    assert_eq!(minibrot.digits(), 29);
+    let (orbit, _) = orbit(&minibrot.nucleus, 24).unwrap();
+    assert!(orbit[24].to_complex().norm() < 1e-35);
+    assert!(find_minibrot(&start, 400, 64).is_err());
+
+    // 更小的迷你曼德博集：大小的估计过程中 `l²` 超出 f64 的范围
+    let start = FixedComplex::parse("-1.9999999,0", 64).unwrap();
+    let minibrot = find_minibrot(&start, 260, 64).unwrap();
+    assert!(minibrot.size.log2 < -500.0 && minibrot.digits() > 150);
+}
@@ -0,0 +1,70 @@
+// 逃逸时间内核的 OpenCL 部分，由 opencl.rs 嵌入，与 gpu_escape.h 拼接后在运行时编译
+//
+// 编译选项 -D DOUBLE 时用 double 迭代，-D DOUBLE_FLOAT 时用两个 float 之和表示的
+// double-float 迭代，否则用 float。
+
+// double-float 的无误差变换依赖每一步单独舍入，不能把乘法和加法合并
+#pragma OPENCL FP_CONTRACT OFF
+
+#if defined(DOUBLE)
+#pragma OPENCL EXTENSION cl_khr_fp64 : enable
+typedef double real;
+typedef double output;
+#elif defined(DOUBLE_FLOAT)
+// x 为高位，y 为低位，数值为两者之和，与 extended.rs 中的 DoubleDouble 相同
+typedef float2 real;
+typedef float output;
+#else
+typedef float real;
+typedef float output;
+#endif
+
+#ifdef DOUBLE_FLOAT
+inline real quick_two_sum(float a, float b)
+{
+    const float s = a + b;
+    return (float2)(s, b - (s - a));
+}
+
+inline real two_sum(float a, float b)
+{
+    const float s = a + b;
+    const float v = s - a;
+    return (float2)(s, (a - (s - v)) + (b - v));
+}
+
+inline real from_output(float a) { return (float2)(a, 0.0f); }
+inline output to_output(real a) { return a.x; }
+
+inline real add(real a, real b)
+{
+    real s = two_sum(a.x, b.x);
+    const real t = two_sum(a.y, b.y);
+    s = quick_two_sum(s.x, s.y + t.x);
+    return quick_two_sum(s.x, s.y + t.y);
+}
+
+inline real sub(real a, real b) { return add(a, -b); }
+
+inline real mul(real a, real b)
+{
+    const float p = a.x * b.x;
+    const float e = fma(a.x, b.x, -p) + (a.x * b.y + a.y * b.x);
+    return quick_two_sum(p, e);
+}
+
+inline real absolute(real a) { return a.x < 0 ? -a : a; }
+#else
+inline real from_output(output a) { return a; }
+inline output to_output(real a) { return a; }
+inline real add(real a, real b) { return a + b; }
+inline real sub(real a, real b) { return a - b; }
+inline real mul(real a, real b) { return a * b; }
+inline real absolute(real a) { return fabs(a); }
+#endif
+
+// gpu_escape.h 中的内核主体需要的限定符和像素位置
+#define KERNEL __kernel
+#define GLOBAL __global
+#define COLUMN get_global_id(0)
+#define ROW get_global_id(1)
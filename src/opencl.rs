@@ -0,0 +1,395 @@
+//! 在 OpenCL 设备上渲染逃逸时间
+//!
+//! 内核源码 `opencl.cl` 与 `gpu_escape.h` 拼接后嵌入在可执行文件中，打开设备时分别以
+//! `float`、用两个 `float` 模拟的 double-float 和（设备支持时）`double` 编译，使用哪一种
+//! 参见 `DeviceFloat`。图像与 `render_parallel` 一样按分块划分，每个分块一次提交给设备，
+//! 读回逃逸次数和逃逸时的 `z` 后在主机上编码，连续着色的修正项也在主机上计算。只支持深度
+//! 缩放支持的那部分分形和着色方式，参见 `gpu::supports`。
+
+use crate::gpu::{self, KernelFractal};
+use crate::progress::Progress;
+use crate::real::{Arithmetic, Precision, Real};
+use crate::tile::{self, Tile};
+use crate::{cancelled, Coloring, Fractal, PixelTransform, UNFINISHED};
+use num::Complex;
+use opencl3::command_queue::CommandQueue;
+use opencl3::context::Context;
+use opencl3::device::{Device, CL_DEVICE_TYPE_GPU, CL_FP_FMA};
+use opencl3::kernel::{ExecuteKernel, Kernel};
+use opencl3::memory::{Buffer, CL_MEM_WRITE_ONLY};
+use opencl3::platform::get_platforms;
+use opencl3::program::Program;
+use opencl3::types::CL_BLOCKING;
+use std::ptr;
+use std::sync::Mutex;
+
+/// 内核源码
+const SOURCE: &str = concat!(include_str!("opencl.cl"), include_str!("gpu_escape.h"));
+
+/// 设备上迭代使用的数值类型
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceFloat {
+    /// `float`
+    Single,
+    /// 两个 `float` 之和，精度约为 `float` 的两倍，接近 `double`；用于不支持 `double`
+    /// 的设备
+    DoubleFloat,
+    /// `double`
+    Double,
+}
+
+impl DeviceFloat {
+    /// 按精度设置 `precision` 为以 `center` 为中心、像素间距为 `spacing` 的视图选择数值类型
+    ///
+    /// `float` 能分辨时按 `Precision::arithmetic` 的选择使用 `float`，否则设备支持
+    /// `double` 时用 `double`，再否则用 double-float；都分辨不了时返回 `None`。
+    pub fn choose(
+        precision: Precision,
+        supports_double: bool,
+        center: Complex<f64>,
+        spacing: f64,
+    ) -> Option<DeviceFloat> {
+        match precision.arithmetic(center, spacing) {
+            Arithmetic::Single => Some(DeviceFloat::Single),
+            Arithmetic::Double if supports_double => Some(DeviceFloat::Double),
+            _ if resolves_double_float(center, spacing) => Some(DeviceFloat::DoubleFloat),
+            _ => None,
+        }
+    }
+
+    /// 数值类型的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceFloat::Single => "f32",
+            DeviceFloat::DoubleFloat => "double-float",
+            DeviceFloat::Double => "f64",
+        }
+    }
+}
+
+/// double-float 的相对精度：高位和低位各有 24 位有效数字
+const DOUBLE_FLOAT_EPSILON: f64 = (f32::EPSILON as f64) * (f32::EPSILON as f64);
+
+/// 判断以 `center` 为中心、像素间距为 `spacing` 的视图能否用 double-float 分辨，
+/// 判据与 `real::resolves` 相同
+pub fn resolves_double_float(center: Complex<f64>, spacing: f64) -> bool {
+    let magnitude = center.re.abs().max(center.im.abs()).max(1.0);
+    spacing >= magnitude * DOUBLE_FLOAT_EPSILON * 256.0
+}
+
+#[test]
+fn test_choose() {
+    let center = Complex::new(-0.75, 0.1);
+    let choose =
+        |precision, double, spacing| DeviceFloat::choose(precision, double, center, spacing);
+    assert_eq!(
+        choose(Precision::Auto, true, 4e-3),
+        Some(DeviceFloat::Single)
+    );
+    assert_eq!(
+        choose(Precision::Double, true, 4e-3),
+        Some(DeviceFloat::Double)
+    );
+    assert_eq!(
+        choose(Precision::Auto, true, 1e-9),
+        Some(DeviceFloat::Double)
+    );
+    assert_eq!(
+        choose(Precision::Auto, false, 1e-9),
+        Some(DeviceFloat::DoubleFloat)
+    );
+    assert_eq!(
+        choose(Precision::Single, false, 1e-9),
+        Some(DeviceFloat::DoubleFloat)
+    );
+    assert_eq!(
+        choose(Precision::Auto, true, 1e-12),
+        Some(DeviceFloat::Double)
+    );
+    assert_eq!(choose(Precision::Auto, false, 1e-12), None);
+}
+
+/// 把 `value` 拆成高位和低位两个 `f32`，两者之和与 `value` 的误差在 double-float 精度内
+fn split(value: f64) -> [f32; 2] {
+    let high = value as f32;
+    [high, (value - high as f64) as f32]
+}
+
+#[test]
+fn test_split() {
+    let value = -0.743_643_887_037_158_7;
+    let [high, low] = split(value);
+    assert_eq!(high, value as f32);
+    assert!((high as f64 + low as f64 - value).abs() < value.abs() * DOUBLE_FLOAT_EPSILON);
+}
+
+/// 打开的 OpenCL 设备和编译好的内核
+pub struct OpenCl {
+    device: Device,
+    context: Context,
+    queue: CommandQueue,
+    single: Kernel,
+    double_float: Kernel,
+    double: Option<Kernel>,
+    /// 在分块之间复用的设备缓冲区，按渲染过的最大分块分配；`float` 和 double-float 内核
+    /// 写出的 `z` 都是 `float`
+    float_buffers: Mutex<Option<Buffers<f32>>>,
+    double_buffers: Mutex<Option<Buffers<f64>>>,
+}
+
+/// 能容纳 `len` 个像素的逃逸次数和逃逸时的 `z` 的设备缓冲区
+struct Buffers<T> {
+    len: usize,
+    counts: Buffer<u32>,
+    final_z: Buffer<T>,
+}
+
+impl OpenCl {
+    /// 打开第一个 OpenCL GPU 设备并编译内核，失败时返回说明原因的错误信息
+    pub fn new() -> Result<OpenCl, String> {
+        let device = get_platforms()
+            .map_err(|err| format!("cannot list OpenCL platforms: {}", err))?
+            .iter()
+            .filter_map(|platform| platform.get_devices(CL_DEVICE_TYPE_GPU).ok())
+            .flatten()
+            .next()
+            .map(Device::new)
+            .ok_or_else(|| "no OpenCL GPU device found".to_string())?;
+        let context = Context::from_device(&device)
+            .map_err(|err| format!("cannot create an OpenCL context: {}", err))?;
+        let queue = CommandQueue::create(&context, device.id(), 0)
+            .map_err(|err| format!("cannot create an OpenCL command queue: {}", err))?;
+        let build = |options: &str| {
+            let program = Program::create_and_build_from_source(&context, SOURCE, options)
+                .map_err(|log| format!("cannot build the OpenCL kernel: {}", log))?;
+            Kernel::create(&program, "escape")
+                .map_err(|err| format!("cannot create the OpenCL kernel: {}", err))
+        };
+        let single = build("")?;
+        let double_float = build("-D DOUBLE_FLOAT")?;
+        let double = if device.supports_double(CL_FP_FMA) {
+            Some(build("-D DOUBLE")?)
+        } else {
+            None
+        };
+        Ok(OpenCl {
+            device,
+            context,
+            queue,
+            single,
+            double_float,
+            double,
+            float_buffers: Mutex::new(None),
+            double_buffers: Mutex::new(None),
+        })
+    }
+
+    /// 设备的名字
+    pub fn name(&self) -> String {
+        self.device.name().unwrap_or_else(|_| "unknown".to_string())
+    }
+
+    /// 设备是否支持 `f64`，不支持时只能用 `f32` 或 double-float 渲染
+    pub fn supports_double(&self) -> bool {
+        self.double.is_some()
+    }
+
+    /// 在设备上用 `float` 类型的数值把分形渲染到整个迭代缓冲区中
+    ///
+    /// 参数含义与 `render_parallel` 相同：图像按 `tile` 划分为分块，每完成一个分块就在
+    /// `progress` 上记录一次，调用 `cancel` 之后剩下的分块填充为 `UNFINISHED`。
+    /// 要求参见 `render_tile`。
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        float: DeviceFloat,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        iterations: &mut [u32],
+        bounds: (usize, usize),
+        transform: PixelTransform<f64>,
+        tile: (usize, usize),
+        progress: &Progress,
+    ) -> Result<(), String> {
+        assert_eq!(iterations.len(), bounds.0 * bounds.1);
+        let tiles = tile::tiles(bounds, tile);
+        progress.start(tiles.len(), "tiles");
+        for tile in tiles {
+            let values = if cancelled() {
+                vec![UNFINISHED; tile.len()]
+            } else {
+                self.render_tile(float, fractal, coloring, limit, tile, transform)?
+            };
+            for (y, row) in values.chunks(tile.width).enumerate() {
+                let start = (tile.y + y) * bounds.0 + tile.x;
+                iterations[start..start + tile.width].copy_from_slice(row);
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    /// 在设备上用 `float` 类型的数值渲染分块 `tile`，返回按行排列的编码后的逃逸值
+    ///
+    /// `float` 为 `DeviceFloat::Double` 时设备必须支持 `f64`，参见 `supports_double`；
+    /// 分形和着色方式必须满足 `gpu::supports`。
+    pub fn render_tile(
+        &self,
+        float: DeviceFloat,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        tile: Tile,
+        transform: PixelTransform<f64>,
+    ) -> Result<Vec<u32>, String> {
+        assert!(
+            gpu::supports(fractal, coloring),
+            "{:?} is not supported",
+            fractal
+        );
+        let arguments = (fractal, coloring, limit);
+        match float {
+            DeviceFloat::Single => self.render_tile_with(
+                (&self.single, &self.float_buffers),
+                float,
+                arguments,
+                tile,
+                transform,
+            ),
+            DeviceFloat::DoubleFloat => self.render_tile_with(
+                (&self.double_float, &self.float_buffers),
+                float,
+                arguments,
+                tile,
+                transform,
+            ),
+            DeviceFloat::Double => self.render_tile_with(
+                (
+                    self.double.as_ref().expect("the device supports f64"),
+                    &self.double_buffers,
+                ),
+                float,
+                arguments,
+                tile,
+                transform,
+            ),
+        }
+    }
+
+    /// `render_tile` 的实现，`T` 为内核写出的逃逸时的 `z` 的类型，`buffers` 是这种类型的
+    /// 复用缓冲区
+    fn render_tile_with<T: Real>(
+        &self,
+        (kernel, buffers): (&Kernel, &Mutex<Option<Buffers<T>>>),
+        float: DeviceFloat,
+        (fractal, coloring, limit): (Fractal, Coloring, usize),
+        tile: Tile,
+        transform: PixelTransform<f64>,
+    ) -> Result<Vec<u32>, String> {
+        let mut buffers = buffers.lock().expect("OpenCL buffers are not poisoned");
+        // 分块大小都不超过第一个分块，通常只在第一个分块分配一次
+        if buffers
+            .as_ref()
+            .is_none_or(|buffers| buffers.len < tile.len())
+        {
+            let buffer_error = |err| format!("cannot allocate OpenCL buffers: {}", err);
+            // 缓冲区由设备分配，不使用主机指针
+            *buffers = Some(Buffers {
+                len: tile.len(),
+                counts: Buffer::<u32>::create(
+                    &self.context,
+                    CL_MEM_WRITE_ONLY,
+                    tile.len(),
+                    ptr::null_mut(),
+                )
+                .map_err(buffer_error)?,
+                final_z: Buffer::<T>::create(
+                    &self.context,
+                    CL_MEM_WRITE_ONLY,
+                    2 * tile.len(),
+                    ptr::null_mut(),
+                )
+                .map_err(buffer_error)?,
+            });
+        }
+        let buffers = buffers.as_ref().expect("the buffers were just allocated");
+
+        let mut tile_counts = vec![0u32; tile.len()];
+        let mut tile_z = vec![T::zero(); 2 * tile.len()];
+        self.run(
+            kernel,
+            float,
+            fractal,
+            coloring,
+            limit,
+            tile,
+            transform,
+            (&buffers.counts, &buffers.final_z),
+            (&mut tile_counts, &mut tile_z),
+        )
+        .map_err(|err| format!("OpenCL rendering failed: {}", err))?;
+        Ok(gpu::encode(fractal, coloring, limit, &tile_counts, &tile_z))
+    }
+
+    /// 在设备上计算分块 `tile`，把逃逸次数和逃逸时的 `z` 读回 `output`
+    ///
+    /// `buffers` 至少能容纳分块中的像素，只读回 `output` 那么多的部分。
+    #[allow(clippy::too_many_arguments)]
+    fn run<T: Real>(
+        &self,
+        kernel: &Kernel,
+        float: DeviceFloat,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        tile: Tile,
+        transform: PixelTransform<f64>,
+        buffers: (&Buffer<u32>, &Buffer<T>),
+        output: (&mut [u32], &mut [T]),
+    ) -> opencl3::Result<()> {
+        let params = KernelFractal::new(fractal, coloring);
+        // 参数的个数和类型必须与 gpu_escape.h 中的 escape 内核一致，坐标按 float 转换
+        let mut execute = ExecuteKernel::new(kernel);
+        execute
+            .set_arg(buffers.0)
+            .set_arg(buffers.1)
+            .set_arg(&(tile.x as u32))
+            .set_arg(&(tile.y as u32))
+            .set_arg(&(tile.width as u32))
+            .set_arg(&(tile.height as u32));
+        for value in [
+            transform.origin.re,
+            transform.origin.im,
+            transform.right.re,
+            transform.right.im,
+            transform.down.re,
+            transform.down.im,
+        ] {
+            set_real(&mut execute, float, value);
+        }
+        execute.set_arg(&params.kind).set_arg(&params.power);
+        set_real(&mut execute, float, params.julia.re);
+        set_real(&mut execute, float, params.julia.im);
+        execute
+            .set_arg(&(limit.min(u32::MAX as usize) as u32))
+            .set_arg(&T::of(params.bailout))
+            .set_global_work_sizes(&[tile.width, tile.height])
+            .enqueue_nd_range(&self.queue)?;
+        self.queue
+            .enqueue_read_buffer(buffers.0, CL_BLOCKING, 0, output.0, &[])?;
+        self.queue
+            .enqueue_read_buffer(buffers.1, CL_BLOCKING, 0, output.1, &[])?;
+        Ok(())
+    }
+}
+
+/// 把 `value` 按 `float` 转换后设为内核的下一个参数
+fn set_real(execute: &mut ExecuteKernel, float: DeviceFloat, value: f64) {
+    match float {
+        DeviceFloat::Single => execute.set_arg(&(value as f32)),
+        DeviceFloat::DoubleFloat => execute.set_arg(&split(value)),
+        DeviceFloat::Double => execute.set_arg(&value),
+    };
+}
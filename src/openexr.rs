@@ -0,0 +1,143 @@
+//! OpenEXR 浮点输出
+//!
+//! PNG 等格式只能保存经过调色板映射和量化的颜色，合成软件和数值分析需要的则是原始数值。
+//! EXR 文件中的每个通道以 32 位浮点数保存一个逐像素的量：`escape` 通道是逃逸值
+//! （连续着色时带有小数部分），`distance` 通道是以复平面中的长度为单位的距离估计。
+
+use crate::{decode_escape, Fractal, PixelTransform};
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage,
+};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+
+/// 集合内部（未逃逸）像素在 `escape` 通道中的值
+pub const INTERIOR_VALUE: f32 = -1.0;
+
+/// 把迭代缓冲区解码为逐像素的逃逸值，内部像素为 `INTERIOR_VALUE`
+pub fn escape_values(iterations: &[u32]) -> Vec<f32> {
+    iterations
+        .iter()
+        .map(|&encoded| decode_escape(encoded).map_or(INTERIOR_VALUE, |value| value as f32))
+        .collect()
+}
+
+/// 并行求出 `bounds` 大小的图像中每个像素到集合的距离估计，以复平面中的长度为单位
+///
+/// 内部像素和达到 `limit` 仍未逃逸的像素距离为 0。
+pub fn distance_estimates(
+    fractal: Fractal,
+    limit: usize,
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+) -> Vec<f32> {
+    let mut distances = vec![0.0; bounds.0 * bounds.1];
+    distances
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, distance) in band.iter_mut().enumerate() {
+                let point = transform.point((column, row));
+                *distance = fractal.distance_estimate(point, limit).unwrap_or(0.0) as f32;
+            }
+        });
+    distances
+}
+
+/// 把若干个 `bounds` 大小的浮点通道（名称和逐像素的值）编码为 EXR 写入 `output`
+pub fn encode_exr<W: Write + Seek>(
+    output: W,
+    bounds: (usize, usize),
+    channels: Vec<(&str, Vec<f32>)>,
+) -> io::Result<()> {
+    let channels = channels
+        .into_iter()
+        .map(|(name, values)| {
+            assert_eq!(values.len(), bounds.0 * bounds.1);
+            AnyChannel::new(name, FlatSamples::F32(values))
+        })
+        .collect::<Vec<_>>();
+    let layer = Layer::new(
+        bounds,
+        LayerAttributes::named("mandelbrot"),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(channels.into()),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_buffered(output)
+        .map_err(io::Error::other)
+}
+
+/// 把浮点通道写入名为 `filename` 的 EXR 文件，参见 `encode_exr`
+pub fn write_exr(
+    filename: &str,
+    bounds: (usize, usize),
+    channels: Vec<(&str, Vec<f32>)>,
+) -> io::Result<()> {
+    let mut output = BufWriter::new(File::create(filename)?);
+    encode_exr(&mut output, bounds, channels)?;
+    output.flush()
+}
+
+#[test]
+fn test_encode_exr() {
+    use crate::{encode_escape, FRACTION_BITS, INTERIOR};
+    use exr::prelude::{ReadChannels, ReadLayers};
+
+    let iterations = [encode_escape(Some(2.5)), INTERIOR, 0, 7 << FRACTION_BITS];
+    let escape = escape_values(&iterations);
+    assert_eq!(escape, [2.5, INTERIOR_VALUE, 0.0, 7.0]);
+
+    let mut bytes = io::Cursor::new(Vec::new());
+    let distance = vec![0.5, 0.0, 1e-9, 3.0];
+    encode_exr(
+        &mut bytes,
+        (2, 2),
+        vec![("escape", escape.clone()), ("distance", distance.clone())],
+    )
+    .unwrap();
+
+    bytes.set_position(0);
+    let image = exr::prelude::read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(bytes)
+        .unwrap();
+    let channels = &image.layer_data.channel_data.list;
+    let values = |name: &str| {
+        let channel = channels
+            .iter()
+            .find(|channel| channel.name == *name)
+            .unwrap();
+        match &channel.sample_data {
+            FlatSamples::F32(values) => values.clone(),
+            _ => panic!("{} is not a float channel", name),
+        }
+    };
+    assert_eq!(values("escape"), escape);
+    assert_eq!(values("distance"), distance);
+}
+
+#[test]
+fn test_distance_estimates() {
+    use num::Complex;
+
+    // 实轴上 c > 1/4 的点到集合的距离就是 c - 1/4，原点在集合内部
+    let distances = distance_estimates(
+        Fractal::Mandelbrot,
+        1000,
+        (2, 1),
+        PixelTransform::from_corners(
+            (2, 1),
+            Complex { re: 0.0, im: 0.0 },
+            Complex { re: 2.0, im: 0.0 },
+        ),
+    );
+    assert_eq!(distances[0], 0.0);
+    assert!(distances[1] > 0.75 / 4.0 && distances[1] < 0.75 * 4.0);
+}
@@ -0,0 +1,259 @@
+//! 单个点的轨道
+//!
+//! 从一个点出发逐步迭代，记录每一步的 `z`，直到逃逸或者到达最大迭代次数。轨道可以画在
+//! 分形图像上（迭代点之间用线段相连），也可以导出为 CSV 或 JSON，用于教学和调试新的公式。
+
+use crate::bailout::{Bailout, Norm};
+use crate::palette::Channel;
+use crate::{Fractal, PixelTransform};
+use num::Complex;
+use serde_json::json;
+
+/// 默认的逃逸判定，与 `Fractal::escape_time` 相同
+pub const BAILOUT: Bailout = Bailout {
+    radius: 2.0,
+    norm: Norm::Modulus,
+};
+
+/// 点 `point` 在 `fractal` 中的轨道：第一项是迭代起点，之后每一项是一次迭代的结果
+///
+/// 逃逸时最后一项是第一个按 `bailout` 判定逃逸的点，否则共有 `limit + 1` 项。
+pub fn trace(
+    fractal: &Fractal,
+    point: Complex<f64>,
+    limit: usize,
+    bailout: Bailout,
+) -> Vec<Complex<f64>> {
+    let (mut z, c) = fractal.orbit_start(point);
+    let mut previous = Complex::new(0.0, 0.0);
+    let mut orbit = vec![z];
+    for i in 0..limit {
+        if bailout.escaped(z) {
+            break;
+        }
+        (z, previous) = (fractal.step_at(i, z, previous, c), z);
+        orbit.push(z);
+    }
+    orbit
+}
+
+/// 用 `bailout` 追踪的轨道是否逃逸了
+pub fn escaped(orbit: &[Complex<f64>], bailout: Bailout) -> bool {
+    orbit.last().is_some_and(|&z| bailout.escaped(z))
+}
+
+/// 轨道的 CSV 表示，每一行是迭代次数和 `z` 的实部、虚部
+pub fn to_csv(orbit: &[Complex<f64>]) -> String {
+    let mut csv = String::from("n,re,im\n");
+    for (n, z) in orbit.iter().enumerate() {
+        csv.push_str(&format!("{},{},{}\n", n, z.re, z.im));
+    }
+    csv
+}
+
+/// 用 `bailout` 追踪的轨道的 JSON 表示，`point` 是轨道的出发点
+pub fn to_json(point: Complex<f64>, orbit: &[Complex<f64>], bailout: Bailout) -> String {
+    let value = json!({
+        "point": [point.re, point.im],
+        "escaped": escaped(orbit, bailout),
+        "iterations": orbit.len() - 1,
+        "orbit": orbit.iter().map(|z| [z.re, z.im]).collect::<Vec<_>>(),
+    });
+    format!("{:#}\n", value)
+}
+
+#[test]
+fn test_trace() {
+    // c = -1 落入周期 2 的环 0, -1, 0, -1……
+    let orbit = trace(&Fractal::Mandelbrot, Complex::new(-1.0, 0.0), 4, BAILOUT);
+    assert_eq!(
+        orbit,
+        [0.0, -1.0, 0.0, -1.0, 0.0].map(|re| Complex::new(re, 0.0))
+    );
+    assert!(!escaped(&orbit, BAILOUT));
+
+    // c = 1：0, 1, 2, 5，第三步逃逸
+    let orbit = trace(&Fractal::Mandelbrot, Complex::new(1.0, 0.0), 100, BAILOUT);
+    assert_eq!(orbit.len() - 1, 3);
+    assert_eq!(orbit[3], Complex::new(5.0, 0.0));
+    assert!(escaped(&orbit, BAILOUT));
+    assert_eq!(
+        Fractal::Mandelbrot.escape_time(Complex::new(1.0, 0.0), 100),
+        Some(orbit.len() - 1)
+    );
+    assert_eq!(to_csv(&orbit), "n,re,im\n0,0,0\n1,1,0\n2,2,0\n3,5,0\n");
+    let json: serde_json::Value =
+        serde_json::from_str(&to_json(Complex::new(1.0, 0.0), &orbit, BAILOUT)).unwrap();
+    assert_eq!(json["escaped"], true);
+    assert_eq!(json["iterations"], 3);
+    assert_eq!(json["orbit"][3], json!([5.0, 0.0]));
+
+    // 朱利亚集从点本身出发；Phoenix 分形用到上一步
+    let julia = trace(
+        &Fractal::Julia(Complex::new(0.0, 0.0)),
+        Complex::new(0.5, 0.0),
+        2,
+        BAILOUT,
+    );
+    assert_eq!(julia, [0.5, 0.25, 0.0625].map(|re| Complex::new(re, 0.0)));
+    let phoenix = Fractal::Phoenix {
+        c: Some(Complex::new(0.0, 0.0)),
+        p: Complex::new(1.0, 0.0),
+    };
+    let orbit = trace(&phoenix, Complex::new(0.5, 0.0), 2, BAILOUT);
+    assert_eq!(orbit, [0.5, 0.25, 0.5625].map(|re| Complex::new(re, 0.0)));
+
+    // 更大的逃逸半径让轨道多走几步：0, 1, 2, 5, 26, 677
+    let wide = Bailout {
+        radius: 100.0,
+        norm: Norm::Modulus,
+    };
+    let orbit = trace(&Fractal::Mandelbrot, Complex::new(1.0, 0.0), 100, wide);
+    assert_eq!(orbit.len() - 1, 5);
+    assert_eq!(orbit[5], Complex::new(677.0, 0.0));
+    assert!(escaped(&orbit, wide));
+
+    // 按实部判定时 0.5 + 3i 还没有逃逸
+    let real = Bailout {
+        radius: 2.0,
+        norm: Norm::Real,
+    };
+    let c = Complex::new(0.5, 3.0);
+    assert_eq!(
+        trace(&Fractal::Mandelbrot, c, 1, BAILOUT),
+        [Complex::new(0.0, 0.0), c]
+    );
+    assert!(escaped(
+        &trace(&Fractal::Mandelbrot, c, 1, BAILOUT),
+        BAILOUT
+    ));
+    assert!(!escaped(&trace(&Fractal::Mandelbrot, c, 1, real), real));
+}
+
+/// 把线段 `from`–`to` 裁剪到 `[min, max]` 的矩形内，整条线段都在外面时返回 `None`
+fn clip(
+    from: (f64, f64),
+    to: (f64, f64),
+    min: (f64, f64),
+    max: (f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    let delta = (to.0 - from.0, to.1 - from.1);
+    let (mut enter, mut leave) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-delta.0, from.0 - min.0),
+        (delta.0, max.0 - from.0),
+        (-delta.1, from.1 - min.1),
+        (delta.1, max.1 - from.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            leave = leave.min(q / p);
+        }
+    }
+    (enter <= leave).then(|| {
+        let at = |t: f64| (from.0 + t * delta.0, from.1 + t * delta.1);
+        (at(enter), at(leave))
+    })
+}
+
+/// 用一像素宽的线段依次连接像素位置 `positions`，对 `bounds` 以内的每个像素（列、行）
+/// 调用 `plot`；画面以外的部分被裁掉，因此很远的点也不会拖慢绘制
+pub(crate) fn polyline(
+    positions: &[(f64, f64)],
+    bounds: (usize, usize),
+    mut plot: impl FnMut(usize, usize),
+) {
+    let (min, max) = ((-1.0, -1.0), (bounds.0 as f64, bounds.1 as f64));
+    for pair in positions.windows(2) {
+        let Some((from, to)) = clip(pair[0], pair[1], min, max) else {
+            continue;
+        };
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+        for step in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f64 / steps as f64
+            };
+            let (x, y) = (
+                (from.0 + t * (to.0 - from.0)).round(),
+                (from.1 + t * (to.1 - from.1)).round(),
+            );
+            if x >= 0.0 && y >= 0.0 && x < bounds.0 as f64 && y < bounds.1 as f64 {
+                plot(x as usize, y as usize);
+            }
+        }
+    }
+}
+
+/// 把轨道画在按 `transform` 覆盖复平面的 `bounds` 大小的 RGB 像素上
+///
+/// 每个迭代点画成一个 3×3 的方块，出发点画成 5×5 的方块；`lines` 为真时再用一像素宽的
+/// 线段依次连接相邻的迭代点。画面以外的点和线段被裁掉。
+pub fn draw<C: Channel>(
+    pixels: &mut [C],
+    bounds: (usize, usize),
+    transform: &PixelTransform<f64>,
+    orbit: &[Complex<f64>],
+    color: [u8; 3],
+    lines: bool,
+) {
+    let color = color.map(|channel| C::from_fraction(channel as f64 / 255.0));
+    let mut plot = |x: f64, y: f64| {
+        let (x, y) = (x.round(), y.round());
+        if x >= 0.0 && y >= 0.0 && x < bounds.0 as f64 && y < bounds.1 as f64 {
+            let offset = (y as usize * bounds.0 + x as usize) * 3;
+            pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    };
+    let positions: Vec<(f64, f64)> = orbit.iter().map(|&z| transform.locate(z)).collect();
+    if lines {
+        polyline(&positions, bounds, |x, y| plot(x as f64, y as f64));
+    }
+    for (i, &(x, y)) in positions.iter().enumerate() {
+        if !x.is_finite() || !y.is_finite() {
+            continue;
+        }
+        let radius: i32 = if i == 0 { 2 } else { 1 };
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                plot(x + dx as f64, y + dy as f64);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_draw() {
+    assert_eq!(
+        clip((-5.0, 1.0), (15.0, 1.0), (0.0, 0.0), (10.0, 10.0)),
+        Some(((0.0, 1.0), (10.0, 1.0)))
+    );
+    assert_eq!(
+        clip((-5.0, -1.0), (15.0, -1.0), (0.0, 0.0), (10.0, 10.0)),
+        None
+    );
+
+    // 10×10 的图像覆盖 [-5, 5]×[-5, 5]，每个像素边长为 1
+    let bounds = (10, 10);
+    let transform =
+        PixelTransform::from_corners(bounds, Complex::new(-5.0, 5.0), Complex::new(5.0, -5.0));
+    let mut pixels = vec![0u8; 300];
+    let orbit = [
+        Complex::new(-3.0, 1.0),
+        Complex::new(2.0, 1.0),
+        Complex::new(100.0, 100.0),
+    ];
+    draw(&mut pixels, bounds, &transform, &orbit, [255, 0, 0], true);
+    let red = |x: usize, y: usize| pixels[(y * 10 + x) * 3] == 255;
+    // 出发点是像素 (2, 4) 对应的点，画成 5×5 的方块；两点之间的线段经过第 4 行
+    assert!(red(0, 2) && red(4, 6) && !red(5, 2));
+    assert!((0..9).all(|x| red(x, 4)));
+    assert!(red(8, 5) && !red(8, 6));
+    assert!(pixels.chunks(3).all(|pixel| pixel[1] == 0 && pixel[2] == 0));
+}
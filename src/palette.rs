@@ -0,0 +1,621 @@
+//! 把逃逸值映射为 RGB 颜色的调色板
+//!
+//! 调色板由若干渐变节点组成，每个节点给出 `[0, 1]` 区间内的位置和该位置的颜色，
+//! 节点之间默认在 sRGB 中按线性插值取色，科学配色（viridis 等）则在感知均匀的
+//! Oklab 空间中插值，亮度随位置均匀变化，适合定量地展示迭代数据。也可以从 Fractint 的 `.map`、GIMP 的 `.gpl` 调色板和
+//! `.ggr` 渐变文件读入调色板。
+
+use std::path::Path;
+
+/// 渐变节点之间插值所在的颜色空间
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Space {
+    /// 直接对 sRGB 通道值插值
+    Srgb,
+    /// 在感知均匀的 Oklab 空间中插值
+    Oklab,
+}
+
+/// 多节点 RGB 渐变调色板
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    /// 按位置升序排列的渐变节点，位置都位于 `[0, 1]` 区间内
+    stops: Vec<(f64, [u8; 3])>,
+    /// 集合内部（未逃逸）像素的颜色
+    interior: [u8; 3],
+    /// 节点之间插值所在的颜色空间
+    space: Space,
+    /// 取色前加在位置上的偏移量，不为零时位置按 1 取模，调色板首尾相接地循环
+    offset: f64,
+}
+
+impl Palette {
+    /// 用渐变节点 `stops` 构造调色板，内部像素为黑色
+    ///
+    /// `stops` 至少要有一个节点，且位置必须在 `[0, 1]` 区间内按升序排列，否则返回 `None`
+    pub fn new(stops: Vec<(f64, [u8; 3])>) -> Option<Palette> {
+        if stops.is_empty()
+            || stops.iter().any(|&(t, _)| !(0.0..=1.0).contains(&t))
+            || stops.windows(2).any(|w| w[0].0 > w[1].0)
+        {
+            return None;
+        }
+        Some(Palette {
+            stops,
+            interior: [0, 0, 0],
+            space: Space::Srgb,
+            offset: 0.0,
+        })
+    }
+
+    /// 把调色板循环移动 `offset` 的调色板，用于调色板循环动画
+    pub fn with_offset(self, offset: f64) -> Palette {
+        Palette { offset, ..self }
+    }
+
+    /// 改在 `space` 中插值的调色板
+    pub fn in_space(self, space: Space) -> Palette {
+        Palette { space, ..self }
+    }
+
+    /// 由在 `[0, 1]` 区间内等距采样的十六进制颜色构成、在 Oklab 中插值的科学配色
+    fn colormap(samples: &[&str]) -> Palette {
+        let colors = samples
+            .iter()
+            .map(|hex| parse_hex_color(hex).expect("built-in colors are valid"))
+            .collect();
+        evenly_spaced(colors)
+            .expect("built-in colormaps are not empty")
+            .in_space(Space::Oklab)
+    }
+
+    /// matplotlib 的 viridis：深紫、蓝、绿、黄，亮度单调增加
+    pub fn viridis() -> Palette {
+        Palette::colormap(&[
+            "440154", "482878", "3e4a89", "31688e", "26828e", "1f9e89", "35b779", "6dcd59",
+            "b4de2c", "fde725",
+        ])
+    }
+
+    /// matplotlib 的 magma：黑、紫、橙红、浅黄
+    pub fn magma() -> Palette {
+        Palette::colormap(&[
+            "000004", "180f3e", "451077", "721f81", "9f2f7f", "cd4071", "f1605d", "fd9567",
+            "fec98d", "fcfdbf",
+        ])
+    }
+
+    /// matplotlib 的 inferno：黑、紫、红、橙、浅黄
+    pub fn inferno() -> Palette {
+        Palette::colormap(&[
+            "000004", "1b0c42", "4b0c6b", "781c6d", "a52c60", "cf4446", "ed6925", "fb9a06",
+            "f7d03c", "fcffa4",
+        ])
+    }
+
+    /// matplotlib 的 plasma：深蓝、紫、橙、黄
+    pub fn plasma() -> Palette {
+        Palette::colormap(&[
+            "0d0887", "47039f", "7301a8", "9c179e", "bd3786", "d8576b", "ed7953", "fa9e3b",
+            "fdc926", "f0f921",
+        ])
+    }
+
+    /// Google 的 turbo：改进的彩虹配色，从深蓝经青、绿、黄到深红
+    pub fn turbo() -> Palette {
+        Palette::colormap(&[
+            "30123b", "4662d7", "36aaf9", "1ae4b6", "72fe5e", "c7ef34", "faba39", "f66b19",
+            "cb2a04", "7a0403",
+        ])
+    }
+
+    /// 从白到黑的灰度渐变，与最初的灰度输出一致
+    pub fn gray() -> Palette {
+        Palette::new(vec![(0.0, [255, 255, 255]), (1.0, [0, 0, 0])]).unwrap()
+    }
+
+    /// 黑、红、橙、黄、白的火焰渐变
+    pub fn fire() -> Palette {
+        Palette::new(vec![
+            (0.0, [0, 0, 0]),
+            (0.25, [128, 0, 0]),
+            (0.5, [255, 64, 0]),
+            (0.75, [255, 200, 0]),
+            (1.0, [255, 255, 255]),
+        ])
+        .unwrap()
+    }
+
+    /// 深蓝、蓝、青、白的海洋渐变
+    pub fn ocean() -> Palette {
+        Palette::new(vec![
+            (0.0, [0, 8, 32]),
+            (0.35, [0, 64, 160]),
+            (0.7, [0, 200, 220]),
+            (1.0, [240, 255, 255]),
+        ])
+        .unwrap()
+    }
+
+    /// Ultra Fractal 的经典默认渐变
+    pub fn classic() -> Palette {
+        Palette::new(vec![
+            (0.0, [0, 7, 100]),
+            (0.16, [32, 107, 203]),
+            (0.42, [237, 255, 255]),
+            (0.6425, [255, 170, 0]),
+            (0.8575, [0, 2, 0]),
+            (1.0, [0, 7, 100]),
+        ])
+        .unwrap()
+    }
+
+    /// 集合内部像素的颜色
+    pub fn interior(&self) -> [u8; 3] {
+        self.interior
+    }
+
+    /// 返回渐变中位置 `t` 处的颜色，`t` 会被截断到 `[0, 1]` 区间内，再加上偏移量
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        self.interpolate(t).map(|channel| channel.round() as u8)
+    }
+
+    /// 与 `color` 相同，但以 16 位精度插值，缓慢的渐变不会因 8 位量化而出现色带
+    pub fn color16(&self, t: f64) -> [u16; 3] {
+        self.interpolate(t)
+            .map(|channel| (channel * 257.0).round() as u16)
+    }
+
+    /// 渐变中位置 `t` 处的颜色，各通道以 0 到 255 之间的浮点数表示
+    fn interpolate(&self, t: f64) -> [f64; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let t = if self.offset == 0.0 {
+            t
+        } else {
+            (t + self.offset).rem_euclid(1.0)
+        };
+        let upper = self.stops.partition_point(|&(pos, _)| pos < t);
+        if upper == 0 {
+            return self.stops[0].1.map(f64::from);
+        }
+        if upper == self.stops.len() {
+            return self.stops[upper - 1].1.map(f64::from);
+        }
+        let (t0, c0) = self.stops[upper - 1];
+        let (t1, c1) = self.stops[upper];
+        let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+        match self.space {
+            Space::Srgb => lerp(c0.map(f64::from), c1.map(f64::from), f),
+            Space::Oklab => oklab_to_srgb(lerp(srgb_to_oklab(c0), srgb_to_oklab(c1), f)),
+        }
+    }
+}
+
+/// `a` 和 `b` 之间比例为 `f` 处的线性插值
+fn lerp(a: [f64; 3], b: [f64; 3], f: f64) -> [f64; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * f)
+}
+
+/// 把 8 位 sRGB 颜色转换为 Oklab 的 `[L, a, b]`
+fn srgb_to_oklab(rgb: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|channel| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// 把 Oklab 的 `[L, a, b]` 转换为 sRGB，各通道为 0 到 255 之间的浮点数，超出色域的部分截断
+fn oklab_to_srgb(lab: [f64; 3]) -> [f64; 3] {
+    let [lightness, a, b] = lab;
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    let linear = [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ];
+    linear.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        let encoded = if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        encoded * 255.0
+    })
+}
+
+#[test]
+fn test_oklab() {
+    for rgb in [
+        [0, 0, 0],
+        [255, 255, 255],
+        [255, 0, 0],
+        [68, 1, 84],
+        [253, 231, 37],
+    ] {
+        let back = oklab_to_srgb(srgb_to_oklab(rgb));
+        assert_eq!(back.map(|c| c.round() as u8), rgb);
+    }
+    // 白色的亮度为 1，色度为零
+    let [l, a, b] = srgb_to_oklab([255, 255, 255]);
+    assert!((l - 1.0).abs() < 1e-6 && a.abs() < 1e-6 && b.abs() < 1e-6);
+}
+
+#[test]
+fn test_colormaps() {
+    let viridis = Palette::viridis();
+    assert_eq!(viridis.color(0.0), [0x44, 0x01, 0x54]);
+    assert_eq!(viridis.color(1.0), [0xfd, 0xe7, 0x25]);
+    // 节点之间在 Oklab 中插值，与直接对 sRGB 插值不同
+    let rgb = viridis.clone().in_space(Space::Srgb);
+    assert_ne!(viridis.color(0.05), rgb.color(0.05));
+    // 亮度随位置单调增加
+    for palette in [
+        Palette::viridis(),
+        Palette::magma(),
+        Palette::inferno(),
+        Palette::plasma(),
+    ] {
+        let lightness: Vec<f64> = (0..=100)
+            .map(|i| srgb_to_oklab(palette.color(i as f64 / 100.0))[0])
+            .collect();
+        assert!(
+            lightness.windows(2).all(|w| w[1] >= w[0] - 1e-3),
+            "{:?}",
+            palette
+        );
+    }
+    assert_eq!(parse_palette("turbo"), Some(Palette::turbo()));
+}
+
+#[test]
+fn test_palette_color() {
+    let gray = Palette::gray();
+    assert_eq!(gray.color(0.0), [255, 255, 255]);
+    assert_eq!(gray.color(1.0), [0, 0, 0]);
+    assert_eq!(gray.color(0.5), [128, 128, 128]);
+    assert_eq!(gray.color(-1.0), [255, 255, 255]);
+    assert_eq!(gray.color(2.0), [0, 0, 0]);
+
+    let single = Palette::new(vec![(0.5, [1, 2, 3])]).unwrap();
+    assert_eq!(single.color(0.0), [1, 2, 3]);
+    assert_eq!(single.color(1.0), [1, 2, 3]);
+
+    assert_eq!(gray.color16(0.0), [65535, 65535, 65535]);
+    assert_eq!(gray.color16(0.5), [32768, 32768, 32768]);
+    assert_eq!(gray.color16(0.501), [32702, 32702, 32702]);
+
+    // 偏移后首尾相接：0.75 处取原来 1.0 之后绕回的 0.0 处的颜色
+    let shifted = Palette::gray().with_offset(0.25);
+    assert_eq!(shifted.color(0.0), gray.color(0.25));
+    assert_eq!(shifted.color(0.75), gray.color(0.0));
+    assert_eq!(shifted.color(0.5), gray.color(0.75));
+    assert_eq!(
+        Palette::gray().with_offset(-0.25).color(0.0),
+        gray.color(0.75)
+    );
+}
+
+/// 像素的颜色通道：8 位的 `u8` 或 16 位的 `u16`
+pub trait Channel: Copy + Default + Send + Sync {
+    /// 把 `[0, 1]` 区间内的亮度换算为通道值
+    fn from_fraction(t: f64) -> Self;
+
+    /// 调色板 `palette` 中位置 `t` 处的颜色
+    fn color(palette: &Palette, t: f64) -> [Self; 3];
+
+    /// 调色板 `palette` 中集合内部像素的颜色
+    fn interior(palette: &Palette) -> [Self; 3];
+
+    /// 通道值本身，用于对多个采样求平均
+    fn value(self) -> usize;
+
+    /// 由 `value` 得到的平均值还原为通道值
+    fn from_value(value: usize) -> Self;
+}
+
+impl Channel for u8 {
+    fn from_fraction(t: f64) -> u8 {
+        (t * 255.0).round() as u8
+    }
+
+    fn color(palette: &Palette, t: f64) -> [u8; 3] {
+        palette.color(t)
+    }
+
+    fn interior(palette: &Palette) -> [u8; 3] {
+        palette.interior()
+    }
+
+    fn value(self) -> usize {
+        self as usize
+    }
+
+    fn from_value(value: usize) -> u8 {
+        value as u8
+    }
+}
+
+impl Channel for u16 {
+    fn from_fraction(t: f64) -> u16 {
+        (t * 65535.0).round() as u16
+    }
+
+    fn color(palette: &Palette, t: f64) -> [u16; 3] {
+        palette.color16(t)
+    }
+
+    fn interior(palette: &Palette) -> [u16; 3] {
+        palette.interior().map(|channel| channel as u16 * 257)
+    }
+
+    fn value(self) -> usize {
+        self as usize
+    }
+
+    fn from_value(value: usize) -> u16 {
+        value as u16
+    }
+}
+
+#[test]
+fn test_palette_new() {
+    assert_eq!(Palette::new(vec![]), None);
+    assert_eq!(Palette::new(vec![(1.5, [0, 0, 0])]), None);
+    assert_eq!(Palette::new(vec![(0.5, [0, 0, 0]), (0.2, [0, 0, 0])]), None);
+}
+
+/// 把形如 `"ff8000"` 的十六进制字符串解析为 RGB 颜色
+pub fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
+    if s.len() != 6 || !s.is_ascii() {
+        return None;
+    }
+    let mut rgb = [0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(rgb)
+}
+
+/// 把字符串 `s` 解析为调色板
+///
+/// `s` 可以是内置调色板的名字（`gray`、`fire`、`ocean`、`classic`，以及在 Oklab 中插值的
+/// 科学配色 `viridis`、`magma`、`inferno`、`plasma`、`turbo`），也可以是
+/// 用逗号隔开的自定义渐变节点，每个节点形如 `<pos>:<rrggbb>`，例如
+/// `"0:000000,0.5:ff0000,1:ffffff"`
+pub fn parse_palette(s: &str) -> Option<Palette> {
+    match s {
+        "gray" => Some(Palette::gray()),
+        "fire" => Some(Palette::fire()),
+        "ocean" => Some(Palette::ocean()),
+        "classic" => Some(Palette::classic()),
+        "viridis" => Some(Palette::viridis()),
+        "magma" => Some(Palette::magma()),
+        "inferno" => Some(Palette::inferno()),
+        "plasma" => Some(Palette::plasma()),
+        "turbo" => Some(Palette::turbo()),
+        _ => {
+            let mut stops = Vec::new();
+            for stop in s.split(',') {
+                let (pos, color) = stop.split_once(':')?;
+                stops.push((pos.parse().ok()?, parse_hex_color(color)?));
+            }
+            Palette::new(stops)
+        }
+    }
+}
+
+#[test]
+fn test_parse_palette() {
+    assert_eq!(parse_palette("fire"), Some(Palette::fire()));
+    assert_eq!(
+        parse_palette("0:000000,1:ff8000"),
+        Palette::new(vec![(0.0, [0, 0, 0]), (1.0, [255, 128, 0])])
+    );
+    assert_eq!(parse_palette("0:000000,1:ff80"), None);
+    assert_eq!(parse_palette("0:000000;1:ff8000"), None);
+    assert_eq!(parse_palette("sunset"), None);
+}
+
+/// 在 `[0, 1]` 区间内等距排列的颜色组成的调色板，用于只给出颜色序列的调色板文件
+///
+/// 颜色数少于迭代次数的范围时，相邻颜色之间按渐变线性插值。
+fn evenly_spaced(colors: Vec<[u8; 3]>) -> Option<Palette> {
+    let last = colors.len().saturating_sub(1).max(1) as f64;
+    let stops = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| (i as f64 / last, color))
+        .collect();
+    Palette::new(stops)
+}
+
+/// 把一行中空白隔开的前三个整数解析为 RGB 颜色，其余部分（颜色名或注释）忽略
+fn parse_rgb_line(line: &str) -> Option<[u8; 3]> {
+    let mut fields = line.split_whitespace();
+    let mut rgb = [0; 3];
+    for channel in &mut rgb {
+        *channel = fields.next()?.parse().ok()?;
+    }
+    Some(rgb)
+}
+
+/// 解析 Fractint 的 `.map` 调色板：每行一个颜色，由 0 到 255 之间的三个整数组成，
+/// 后面可以跟注释
+pub fn parse_fractint_map(text: &str) -> Result<Palette, String> {
+    let mut colors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let color =
+            parse_rgb_line(line).ok_or_else(|| format!("line {}: expected `R G B`", number + 1))?;
+        colors.push(color);
+    }
+    evenly_spaced(colors).ok_or_else(|| "no colors".to_string())
+}
+
+#[test]
+fn test_parse_fractint_map() {
+    let palette = parse_fractint_map("0 0 0 black\n\n255 128 0   orange\n  0 0 255\n").unwrap();
+    assert_eq!(
+        palette,
+        Palette::new(vec![
+            (0.0, [0, 0, 0]),
+            (0.5, [255, 128, 0]),
+            (1.0, [0, 0, 255])
+        ])
+        .unwrap()
+    );
+    assert_eq!(palette.color(0.25), [128, 64, 0]);
+    assert!(parse_fractint_map("0 0 0\n256 0 0\n")
+        .unwrap_err()
+        .starts_with("line 2"));
+    assert!(parse_fractint_map("").is_err());
+}
+
+/// 解析 GIMP 的 `.gpl` 调色板：第一行为 `GIMP Palette`，之后是 `Name:`、`Columns:`
+/// 等头部、`#` 开头的注释和每行一个的颜色 `R G B 名称`
+pub fn parse_gimp_palette(text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Palette") {
+        return Err("expected `GIMP Palette` on the first line".to_string());
+    }
+    let mut colors = Vec::new();
+    for (number, line) in lines {
+        let line = line.trim();
+        // 头部形如 `Name: 名称`，冒号前只有一个词；颜色名称中也可能有冒号
+        let header = line
+            .split_once(':')
+            .is_some_and(|(key, _)| !key.contains(char::is_whitespace));
+        if line.is_empty() || line.starts_with('#') || header {
+            continue;
+        }
+        let color =
+            parse_rgb_line(line).ok_or_else(|| format!("line {}: expected `R G B`", number + 1))?;
+        colors.push(color);
+    }
+    evenly_spaced(colors).ok_or_else(|| "no colors".to_string())
+}
+
+#[test]
+fn test_parse_gimp_palette() {
+    let text = "GIMP Palette\nName: Test\nColumns: 2\n#\n  0   0   0\tBlack\n255 255 255\tWhite\n";
+    assert_eq!(
+        parse_gimp_palette(text),
+        Ok(Palette::new(vec![(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]).unwrap())
+    );
+    // 只有一个颜色时整个渐变都是这个颜色
+    assert_eq!(
+        parse_gimp_palette("GIMP Palette\n10 20 30 Dark\n")
+            .unwrap()
+            .color(0.7),
+        [10, 20, 30]
+    );
+    assert_eq!(
+        parse_gimp_palette("GIMP Palette\nName: Clock\n1 2 3\tNoon: 12:00\n")
+            .unwrap()
+            .color(0.0),
+        [1, 2, 3]
+    );
+    assert!(parse_gimp_palette("0 0 0\n").is_err());
+    assert!(parse_gimp_palette("GIMP Palette\n0 0\n").is_err());
+}
+
+/// 解析 GIMP 的 `.ggr` 渐变：`GIMP Gradient`、`Name:` 和段数之后每行一段，依次为
+/// 左端、中点、右端的位置和左右两端的 RGBA 颜色（都在 `[0, 1]` 区间内），其余字段
+/// 是混合方式和颜色类型
+///
+/// 每段转换为左端、中点和右端三个节点，中点取两端颜色的平均值。这与 GIMP 的线性
+/// 混合完全一致，其他混合方式（曲线、正弦、球面）近似为线性；透明度被忽略。
+pub fn parse_gimp_gradient(text: &str) -> Result<Palette, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Gradient") {
+        return Err("expected `GIMP Gradient` on the first line".to_string());
+    }
+    let mut lines = lines.skip_while(|(_, line)| line.starts_with("Name:"));
+    let (number, count) = lines
+        .next()
+        .ok_or_else(|| "missing the number of segments".to_string())?;
+    let count: usize = count
+        .trim()
+        .parse()
+        .map_err(|_| format!("line {}: expected the number of segments", number + 1))?;
+    let mut stops = Vec::new();
+    for (number, line) in lines.take(count) {
+        let fields: Option<Vec<f64>> = line
+            .split_whitespace()
+            .take(11)
+            .map(|field| field.parse().ok())
+            .collect();
+        let [left, middle, right, r0, g0, b0, _, r1, g1, b1, _] = fields
+            .and_then(|fields| <[f64; 11]>::try_from(fields).ok())
+            .ok_or_else(|| format!("line {}: expected a gradient segment", number + 1))?;
+        let to_rgb =
+            |rgb: [f64; 3]| rgb.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let average = [(r0 + r1) / 2.0, (g0 + g1) / 2.0, (b0 + b1) / 2.0];
+        stops.push((left, to_rgb([r0, g0, b0])));
+        stops.push((middle, to_rgb(average)));
+        stops.push((right, to_rgb([r1, g1, b1])));
+    }
+    if stops.len() != count * 3 {
+        return Err(format!("expected {} segments", count));
+    }
+    Palette::new(stops)
+        .ok_or_else(|| "segment positions must be ascending within [0, 1]".to_string())
+}
+
+#[test]
+fn test_parse_gimp_gradient() {
+    let text = "GIMP Gradient\nName: Two\n2\n\
+                0 0.25 0.5 0 0 0 1 1 0 0 1 0 0\n\
+                0.5 0.75 1 1 0 0 1 1 1 1 1 0 0\n";
+    let palette = parse_gimp_gradient(text).unwrap();
+    assert_eq!(palette.color(0.0), [0, 0, 0]);
+    assert_eq!(palette.color(0.25), [128, 0, 0]);
+    assert_eq!(palette.color(0.5), [255, 0, 0]);
+    assert_eq!(palette.color(0.75), [255, 128, 128]);
+    assert_eq!(palette.color(1.0), [255, 255, 255]);
+    assert!(parse_gimp_gradient("GIMP Gradient\n2\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+    assert!(parse_gimp_gradient("GIMP Gradient\n1\n0 0.5 1 0 0 0\n").is_err());
+    assert!(parse_gimp_gradient("GIMP Palette\n").is_err());
+}
+
+/// 按扩展名读取调色板文件：Fractint 的 `.map`、GIMP 的 `.gpl` 调色板或 `.ggr` 渐变
+///
+/// 出错时返回可以直接展示给用户的说明。
+pub fn load_palette(path: &Path) -> Result<Palette, String> {
+    let parse = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("map") => parse_fractint_map,
+        Some("gpl") => parse_gimp_palette,
+        Some("ggr") => parse_gimp_gradient,
+        _ => return Err("palette files must end in .map, .gpl, or .ggr".to_string()),
+    };
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+    parse(&text).map_err(|message| format!("{}: {}", path.display(), message))
+}
+
+/// 文件名 `s` 是否有调色板文件的扩展名
+pub fn is_palette_file(s: &str) -> bool {
+    [".map", ".gpl", ".ggr"]
+        .iter()
+        .any(|extension| s.ends_with(extension))
+}
@@ -0,0 +1,226 @@
+//! 平移时复用重叠的像素
+//!
+//! 视图平移整数个像素而缩放和旋转不变时，新图像中的像素 `(x, y)` 与旧图像中的像素
+//! `(x + dx, y + dy)` 对应复平面中同一个点（只差浮点舍入），不必重新计算。
+//! `pixel_offset` 判断两个变换是否正好相差整数个像素的平移，`render_panned` 把旧缓冲区
+//! 中重叠的部分移到新的位置，只计算新露出的条带。
+
+use crate::real::Real;
+use crate::tile::{tiles, Tile};
+use crate::{render_tile, Coloring, Fractal, PixelTransform};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+/// 两个变换的步长差别在这个相对误差以内时看作相同
+const SCALE_TOLERANCE: f64 = 1e-9;
+
+/// 平移量与整数的差别在这么多个像素以内时看作整数
+const PIXEL_TOLERANCE: f64 = 1e-6;
+
+/// 新露出的条带再按这个尺寸划分为并行的任务
+const TILE: (usize, usize) = (64, 64);
+
+/// 如果 `new` 只是把 `old` 平移了整数个像素，返回新图像的原点在旧图像中的像素坐标
+/// `(dx, dy)`，否则返回 `None`
+pub fn pixel_offset(
+    old: &PixelTransform<f64>,
+    new: &PixelTransform<f64>,
+) -> Option<(isize, isize)> {
+    let close =
+        |a: num::Complex<f64>, b: num::Complex<f64>| (a - b).norm() <= SCALE_TOLERANCE * b.norm();
+    if !close(new.right, old.right) || !close(new.down, old.down) {
+        return None;
+    }
+    // 把原点的位移分解为 right 和 down 两个方向上的像素数
+    let (right, down) = (old.right, old.down);
+    let delta = new.origin - old.origin;
+    let det = right.re * down.im - right.im * down.re;
+    if det == 0.0 {
+        return None;
+    }
+    let dx = (delta.re * down.im - delta.im * down.re) / det;
+    let dy = (right.re * delta.im - right.im * delta.re) / det;
+    let whole = |value: f64| {
+        let rounded = value.round();
+        ((value - rounded).abs() <= PIXEL_TOLERANCE && rounded.abs() < isize::MAX as f64)
+            .then_some(rounded as isize)
+    };
+    Some((whole(dx)?, whole(dy)?))
+}
+
+/// 把 `bounds` 大小的 `old` 平移 `offset` 得到新缓冲区，新缓冲区的像素 `(x, y)` 取
+/// `old` 的像素 `(x + dx, y + dy)`；同时返回没有对应像素、需要重新计算的分块
+pub fn shift(old: &[u32], bounds: (usize, usize), offset: (isize, isize)) -> (Vec<u32>, Vec<Tile>) {
+    assert_eq!(old.len(), bounds.0 * bounds.1);
+    let (width, height) = bounds;
+    // 新图像中能从旧图像取值的列和行的范围
+    let span = |size: usize, delta: isize| {
+        let start = (-delta).clamp(0, size as isize) as usize;
+        let end = (size as isize - delta).clamp(0, size as isize) as usize;
+        (start, end.max(start))
+    };
+    let (left, right) = span(width, offset.0);
+    let (top, bottom) = span(height, offset.1);
+
+    let mut iterations = vec![0; width * height];
+    if left == right || top == bottom {
+        let whole = Tile {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        return (iterations, vec![whole]);
+    }
+    for y in top..bottom {
+        let from = (y as isize + offset.1) as usize * width;
+        let from = from + (left as isize + offset.0) as usize;
+        iterations[y * width + left..y * width + right]
+            .copy_from_slice(&old[from..from + right - left]);
+    }
+
+    let exposed = [
+        (0, 0, width, top),
+        (0, bottom, width, height - bottom),
+        (0, top, left, bottom - top),
+        (right, top, width - right, bottom - top),
+    ];
+    let exposed = exposed
+        .into_iter()
+        .map(|(x, y, width, height)| Tile {
+            x,
+            y,
+            width,
+            height,
+        })
+        .filter(|tile| !tile.is_empty())
+        .collect();
+    (iterations, exposed)
+}
+
+/// 由旧视图的缓冲区 `old` 得到平移 `offset` 个像素后的新视图 `transform` 的缓冲区，
+/// 只计算新露出的像素；返回新缓冲区和计算的像素数
+///
+/// 其余参数含义与 `render` 相同。新计算的像素与 `render` 的结果逐位相同。
+pub fn render_panned<T: Real>(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    old: &[u32],
+    bounds: (usize, usize),
+    offset: (isize, isize),
+    transform: PixelTransform<T>,
+) -> (Vec<u32>, usize) {
+    let (mut iterations, exposed) = shift(old, bounds, offset);
+    let tasks: Vec<Tile> = exposed
+        .iter()
+        .flat_map(|strip| {
+            tiles((strip.width, strip.height), TILE)
+                .into_iter()
+                .map(move |tile| Tile {
+                    x: strip.x + tile.x,
+                    y: strip.y + tile.y,
+                    ..tile
+                })
+        })
+        .collect();
+    let rendered: Vec<(Tile, Vec<u32>)> = tasks
+        .par_iter()
+        .map(|&tile| {
+            (
+                tile,
+                render_tile(&fractal, &coloring, limit, tile, transform),
+            )
+        })
+        .collect();
+    let mut computed = 0;
+    for (tile, values) in rendered {
+        for (row, values) in values.chunks(tile.width).enumerate() {
+            let start = (tile.y + row) * bounds.0 + tile.x;
+            iterations[start..start + tile.width].copy_from_slice(values);
+        }
+        computed += tile.len();
+    }
+    (iterations, computed)
+}
+
+#[test]
+fn test_pixel_offset() {
+    use num::Complex;
+
+    let transform = PixelTransform::from_corners(
+        (40, 30),
+        Complex { re: -2.0, im: 1.5 },
+        Complex { re: 2.0, im: -1.5 },
+    )
+    .rotated((40, 30), 30.0);
+    let moved = PixelTransform {
+        origin: transform.point((3, 0)) - transform.down * 2.0,
+        ..transform
+    };
+    assert_eq!(pixel_offset(&transform, &moved), Some((3, -2)));
+    assert_eq!(pixel_offset(&transform, &transform), Some((0, 0)));
+
+    // 半个像素的平移或缩放都不能复用
+    let half = PixelTransform {
+        origin: transform.origin + transform.right * 0.5,
+        ..transform
+    };
+    assert_eq!(pixel_offset(&transform, &half), None);
+    let zoomed = PixelTransform {
+        right: transform.right * 0.5,
+        down: transform.down * 0.5,
+        ..transform
+    };
+    assert_eq!(pixel_offset(&transform, &zoomed), None);
+}
+
+#[test]
+fn test_render_panned() {
+    use crate::render;
+    use num::Complex;
+
+    let bounds = (37, 21);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        Complex { re: -2.5, im: 1.25 },
+        Complex {
+            re: 2.125,
+            im: -1.375,
+        },
+    );
+    // 像素间距是 1/8，平移前后每个像素对应的点都精确相同
+    let render_at = |transform| {
+        let mut iterations = vec![0; bounds.0 * bounds.1];
+        render(
+            Fractal::Mandelbrot,
+            Coloring::EscapeTime,
+            200,
+            &mut iterations,
+            bounds,
+            transform,
+        );
+        iterations
+    };
+    let old = render_at(transform);
+    for offset in [(0, 0), (5, -3), (-36, 20), (40, 0), (0, -21)] {
+        let moved = PixelTransform {
+            origin: transform.origin
+                + transform.right * offset.0 as f64
+                + transform.down * offset.1 as f64,
+            ..transform
+        };
+        let (iterations, computed) = render_panned(
+            Fractal::Mandelbrot,
+            Coloring::EscapeTime,
+            200,
+            &old,
+            bounds,
+            offset,
+            moved,
+        );
+        assert_eq!(iterations, render_at(moved), "offset {:?}", offset);
+        let kept = (bounds.0 as isize - offset.0.abs()).max(0)
+            * (bounds.1 as isize - offset.1.abs()).max(0);
+        assert_eq!(computed, bounds.0 * bounds.1 - kept as usize);
+    }
+}
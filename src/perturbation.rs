@@ -0,0 +1,559 @@
+//! 基于微扰理论的深度缩放渲染
+//!
+//! 逐像素使用任意精度迭代太慢。微扰渲染只为一个参考点以任意精度计算参考轨道
+//! `Z_n`，其余像素只需用 `f64` 迭代与参考轨道之间的偏差 `δ_n`：
+//!
+//! `δ_{n+1} = 2 Z_n δ_n + δ_n² + δc`
+//!
+//! 当 `|Z_n + δ_n|` 远小于 `|Z_n|` 时偏差的相对精度会丢失，这样的像素称为"失真"
+//! 像素（glitch）。失真像素会以其中一个像素为新的参考点重新计算。
+
+use crate::precise::{self, Fixed, FixedComplex};
+use crate::progress::Progress;
+use crate::{encode_escape, smooth_value, Coloring, Fractal, SMOOTH_BAILOUT};
+use num::Complex;
+use rayon::prelude::{ParallelIterator, ParallelSlice};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Pauldelbrot 失真判据的阈值：`|Z_n + δ_n|² < GLITCH_TOLERANCE * |Z_n|²` 即视为失真
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+/// 最多使用的参考点个数，超过之后剩余的失真像素保留近似结果
+pub const MAX_REFERENCES: usize = 32;
+
+/// `OrbitCache` 中最多保留的参考轨道个数
+const CACHED_ORBITS: usize = 8;
+
+/// `OrbitCache` 计算参考轨道时比视图所需多用的二进制小数位数，使缩放倍数再大 2^32 倍
+/// 以内的视图仍能使用它
+const SPARE_BITS: u32 = 32;
+
+/// 以任意精度计算的参考轨道，每个点都已转换为 `f64`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferenceOrbit {
+    fractal: Fractal,
+    orbit: Vec<Complex<f64>>,
+}
+
+impl ReferenceOrbit {
+    /// 从 `z` 出发以任意精度迭代分形 `fractal`，记录 `|z|²` 不超过 `bailout` 的前缀，
+    /// 最多记录 `limit` 个点
+    pub fn new(
+        fractal: Fractal,
+        mut z: FixedComplex,
+        c: &FixedComplex,
+        limit: usize,
+        bailout: f64,
+    ) -> ReferenceOrbit {
+        let mut orbit = Vec::new();
+        for _ in 0..limit {
+            let point = z.to_complex();
+            if point.norm_sqr() > bailout {
+                break;
+            }
+            orbit.push(point);
+            z = z.step(fractal, c);
+        }
+        ReferenceOrbit { fractal, orbit }
+    }
+
+    /// 参考轨道的长度，参考点在此之后逃逸（或者达到了迭代次数限制）
+    pub fn len(&self) -> usize {
+        self.orbit.len()
+    }
+
+    /// 参考轨道是否为空，即参考点一开始就在逃逸半径之外
+    pub fn is_empty(&self) -> bool {
+        self.orbit.is_empty()
+    }
+
+    /// 从偏差 `delta` 出发、以常数偏差 `delta_c` 迭代，参考点的迭代次数限制为 `limit`
+    ///
+    /// 返回 `Ok(Some((i, z)))` 表示在第 `i` 次迭代时逃逸，`z` 为逃逸时的值；
+    /// `Ok(None)` 表示达到迭代次数限制仍未逃逸；`Err(())` 表示像素失真，或者
+    /// 参考轨道提前结束，需要换一个参考点重新计算。
+    #[allow(clippy::result_unit_err)]
+    pub fn iterate(
+        &self,
+        mut delta: Complex<f64>,
+        delta_c: Complex<f64>,
+        limit: usize,
+        bailout: f64,
+    ) -> Result<Option<(usize, Complex<f64>)>, ()> {
+        for i in 0..limit {
+            let Some(&reference) = self.orbit.get(i) else {
+                return Err(());
+            };
+            let z = reference + delta;
+            if z.norm_sqr() > bailout {
+                return Ok(Some((i, z)));
+            }
+            if z.norm_sqr() < GLITCH_TOLERANCE * reference.norm_sqr() {
+                return Err(());
+            }
+            delta = self.step_delta(reference, delta) + delta_c;
+        }
+        Ok(None)
+    }
+
+    /// 参考点为 `reference`、偏差为 `delta` 时，下一次迭代的偏差（不含 `δc`）
+    fn step_delta(&self, reference: Complex<f64>, delta: Complex<f64>) -> Complex<f64> {
+        match self.fractal {
+            Fractal::Mandelbrot | Fractal::Julia(_) => reference * delta * 2.0 + delta * delta,
+            Fractal::BurningShip => {
+                // 实部不受绝对值影响；虚部 2|XY| 的偏差用 diffabs 计算以避免相消误差
+                let (x, y) = (reference.re, reference.im);
+                let (a, b) = (delta.re, delta.im);
+                Complex {
+                    re: (2.0 * x + a) * a - (2.0 * y + b) * b,
+                    im: 2.0 * diffabs(x * y, x * b + a * y + a * b),
+                }
+            }
+            // conj(Z + δ)^2 - conj(Z)^2 = conj(2Zδ + δ^2)
+            Fractal::Tricorn => (reference * delta * 2.0 + delta * delta).conj(),
+            Fractal::Multibrot(power) => {
+                // (Z + δ)^d - Z^d 按二项式展开，避免两个相近的大数相减
+                let d = power as u32;
+                let mut sum = Complex { re: 0.0, im: 0.0 };
+                let mut binomial = 1.0;
+                let mut delta_power = Complex { re: 1.0, im: 0.0 };
+                for k in 1..=d {
+                    binomial = binomial * (d - k + 1) as f64 / k as f64;
+                    delta_power *= delta;
+                    sum += reference.powu(d - k) * delta_power * binomial;
+                }
+                sum
+            }
+            Fractal::Formula(_)
+            | Fractal::Phoenix { .. }
+            | Fractal::Lambda(_)
+            | Fractal::Hybrid { .. } => {
+                unreachable!("{:?} does not support deep zoom", self.fractal)
+            }
+        }
+    }
+}
+
+/// 在不直接相减的情况下计算 `|c + d| - |c|`
+fn diffabs(c: f64, d: f64) -> f64 {
+    if c >= 0.0 {
+        if c + d >= 0.0 {
+            d
+        } else {
+            -(2.0 * c + d)
+        }
+    } else if c + d > 0.0 {
+        2.0 * c + d
+    } else {
+        -d
+    }
+}
+
+#[test]
+fn test_diffabs() {
+    for (c, d) in [
+        (1.0f64, 0.5f64),
+        (1.0, -3.0),
+        (-1.0, 0.5),
+        (-1.0, 3.0),
+        (0.0, -2.0),
+    ] {
+        let expected = (c + d).abs() - c.abs();
+        assert_eq!(diffabs(c, d), expected);
+    }
+}
+
+#[test]
+fn test_reference_orbit() {
+    let bits = 64;
+    let origin = FixedComplex::from_complex(Complex { re: 0.0, im: 0.0 }, bits);
+    let c = Complex { re: -0.75, im: 0.1 };
+    let orbit = ReferenceOrbit::new(
+        Fractal::Mandelbrot,
+        origin,
+        &FixedComplex::from_complex(c, bits),
+        1000,
+        4.0,
+    );
+    assert_eq!(
+        orbit.len(),
+        crate::escape_time(Complex { re: 0.0, im: 0.0 }, c, 1000).unwrap()
+    );
+
+    // 参考轨道比像素先结束时需要换参考点
+    let zero = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(orbit.iterate(zero, zero, 1000, 4.0), Err(()));
+    let escaping = Complex { re: 0.0, im: 0.2 };
+    assert_eq!(
+        orbit
+            .iterate(zero, escaping, 1000, 4.0)
+            .unwrap()
+            .map(|(i, _)| i),
+        crate::escape_time(zero, c + escaping, 1000)
+    );
+}
+
+/// 缓存中的一条参考轨道
+struct CachedOrbit {
+    /// 参考点
+    point: FixedComplex,
+    bailout: f64,
+    /// 计算参考轨道时的迭代次数限制
+    limit: usize,
+    orbit: Arc<ReferenceOrbit>,
+}
+
+impl CachedOrbit {
+    /// 该参考轨道能否用于以 `center` 为中心、像素间距为 `spacing` 的 `bounds` 大小视图，
+    /// 能用时返回参考点相对 `center` 的偏移
+    fn offset(
+        &self,
+        fractal: Fractal,
+        center: &FixedComplex,
+        bounds: (usize, usize),
+        spacing: f64,
+        limit: usize,
+        bailout: f64,
+    ) -> Option<Complex<f64>> {
+        let bits = center.re.bits();
+        // 参考点提前逃逸时轨道已经完整，不受迭代次数限制的影响
+        let complete = self.limit >= limit || self.orbit.len() < self.limit;
+        if self.orbit.fractal != fractal
+            || self.bailout != bailout
+            || !complete
+            || self.point.re.bits() < bits
+        {
+            return None;
+        }
+        let point = self.point.with_bits(bits);
+        let offset = Complex {
+            re: (&point.re - &center.re).to_f64(),
+            im: (&point.im - &center.im).to_f64(),
+        };
+        let inside = offset.re.abs() <= bounds.0 as f64 * spacing / 2.0
+            && offset.im.abs() <= bounds.1 as f64 * spacing / 2.0;
+        inside.then_some(offset)
+    }
+}
+
+/// 在多次渲染之间共享的参考轨道
+///
+/// 动画中相邻的帧通常以同一个点或相距很近的点为中心。只要之前算出的参考点仍在视图之内，
+/// 计算时的精度和迭代次数也足够，就可以直接作为这一帧的第一个参考点，省去深度缩放中
+/// 代价最高的任意精度迭代。可以同时被多个渲染使用。
+#[derive(Default)]
+pub struct OrbitCache {
+    orbits: Mutex<Vec<CachedOrbit>>,
+    hits: AtomicUsize,
+}
+
+impl OrbitCache {
+    /// 空的缓存
+    pub fn new() -> OrbitCache {
+        OrbitCache::default()
+    }
+
+    /// 复用缓存中参考轨道的次数
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// 返回以 `center` 为中心的视图可以使用的参考轨道，以及参考点相对 `center` 的偏移
+    ///
+    /// 缓存中没有可用的参考轨道时以 `center` 为参考点计算一条放入缓存，计算期间持有锁，
+    /// 同时渲染的其他帧会等它算完再看能否复用，而不是各自重复计算。
+    fn reference(
+        &self,
+        fractal: Fractal,
+        center: &FixedComplex,
+        bounds: (usize, usize),
+        spacing: f64,
+        limit: usize,
+        bailout: f64,
+    ) -> (Arc<ReferenceOrbit>, Complex<f64>) {
+        let mut orbits = self
+            .orbits
+            .lock()
+            .expect("orbit cache lock is not poisoned");
+        for cached in orbits.iter().rev() {
+            if let Some(offset) = cached.offset(fractal, center, bounds, spacing, limit, bailout) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return (cached.orbit.clone(), offset);
+            }
+        }
+
+        let point = center.with_bits(center.re.bits() + SPARE_BITS);
+        let (z, c) = precise::orbit_start(fractal, point.clone());
+        let orbit = Arc::new(ReferenceOrbit::new(fractal, z, &c, limit, bailout));
+        if orbits.len() == CACHED_ORBITS {
+            orbits.remove(0);
+        }
+        orbits.push(CachedOrbit {
+            point,
+            bailout,
+            limit,
+            orbit: orbit.clone(),
+        });
+        (orbit, Complex { re: 0.0, im: 0.0 })
+    }
+}
+
+/// 像素相对视图中心的偏移，以 `spacing` 为像素间距，行号越大虚部越小
+fn pixel_offset(bounds: (usize, usize), pixel: (usize, usize), spacing: f64) -> Complex<f64> {
+    Complex {
+        re: (2.0 * pixel.0 as f64 - bounds.0 as f64) * spacing / 2.0,
+        im: (bounds.1 as f64 - 2.0 * pixel.1 as f64) * spacing / 2.0,
+    }
+}
+
+/// 以微扰理论把以 `center` 为中心、像素间距为 `spacing` 的视图渲染到迭代缓冲区中
+///
+/// 参数含义与 `precise::render_parallel` 相同。先以视图中心为参考点计算所有像素，
+/// 再反复从剩余的失真像素中选一个作为新的参考点重新计算它们，最多使用
+/// `MAX_REFERENCES` 个参考点。返回实际使用的参考点个数。每算出一个像素的最终结果
+/// 就在 `progress` 上记录一次。
+#[allow(clippy::too_many_arguments)]
+pub fn render_parallel(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    center: &FixedComplex,
+    spacing: f64,
+    progress: &Progress,
+) -> usize {
+    render_parallel_cached(
+        fractal,
+        coloring,
+        limit,
+        iterations,
+        bounds,
+        center,
+        spacing,
+        progress,
+        &OrbitCache::new(),
+    )
+}
+
+/// 与 `render_parallel` 相同，但第一个参考点的参考轨道优先从 `orbits` 中取，
+/// 没有可用的才以视图中心为参考点计算，并放入 `orbits` 供之后的渲染使用
+#[allow(clippy::too_many_arguments)]
+pub fn render_parallel_cached(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    center: &FixedComplex,
+    spacing: f64,
+    progress: &Progress,
+    orbits: &OrbitCache,
+) -> usize {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    progress.start(iterations.len(), "pixels");
+    let bits = center.re.bits();
+    let bailout = match coloring {
+        Coloring::EscapeTime => 4.0,
+        Coloring::Smooth => SMOOTH_BAILOUT,
+        Coloring::Distance
+        | Coloring::OrbitTrap(_)
+        | Coloring::TriangleInequality
+        | Coloring::AtomDomain => {
+            panic!("{:?} coloring is not supported for deep zooms", coloring)
+        }
+    };
+    let value = |escaped: Option<(usize, Complex<f64>)>| {
+        encode_escape(escaped.map(|(count, z)| match coloring {
+            Coloring::EscapeTime => count as f64,
+            Coloring::Smooth => smooth_value(count, z, fractal.degree()),
+            Coloring::Distance
+            | Coloring::OrbitTrap(_)
+            | Coloring::TriangleInequality
+            | Coloring::AtomDomain => {
+                unreachable!()
+            }
+        }))
+    };
+
+    let mut pending: Vec<usize> = (0..iterations.len()).collect();
+    let mut reference_offset = Complex { re: 0.0, im: 0.0 };
+    let mut references = 0;
+    while !pending.is_empty() && references < MAX_REFERENCES {
+        references += 1;
+        let orbit = if references == 1 {
+            let (orbit, offset) =
+                orbits.reference(fractal, center, bounds, spacing, limit, bailout);
+            reference_offset = offset;
+            orbit
+        } else {
+            let reference_point = FixedComplex {
+                re: &center.re + &Fixed::from_f64(reference_offset.re, bits),
+                im: &center.im + &Fixed::from_f64(reference_offset.im, bits),
+            };
+            let (z, c) = precise::orbit_start(fractal, reference_point);
+            Arc::new(ReferenceOrbit::new(fractal, z, &c, limit, bailout))
+        };
+
+        let last_round = references == MAX_REFERENCES;
+        // 每个任务计算一行像素那么多的点，计时和进度都按任务记录
+        let results: Vec<(usize, Result<u32, ()>)> = pending
+            .par_chunks(bounds.0.max(1))
+            .flat_map_iter(|indices| {
+                let results = progress.timed(|| {
+                    indices
+                        .iter()
+                        .map(|&index| {
+                            let pixel = (index % bounds.0, index / bounds.0);
+                            let offset = pixel_offset(bounds, pixel, spacing) - reference_offset;
+                            let zero = Complex { re: 0.0, im: 0.0 };
+                            // 曼德博集和燃烧船中像素的差异体现在 c 上，朱利亚集中则体现在 z 的起点上
+                            let (delta, delta_c) = match fractal {
+                                Fractal::Mandelbrot
+                                | Fractal::BurningShip
+                                | Fractal::Tricorn
+                                | Fractal::Multibrot(_)
+                                | Fractal::Formula(_) => (zero, offset),
+                                Fractal::Julia(_) => (offset, zero),
+                                Fractal::Phoenix { .. }
+                                | Fractal::Lambda(_)
+                                | Fractal::Hybrid { .. } => {
+                                    unreachable!("{:?} does not support deep zoom", fractal)
+                                }
+                            };
+                            let result = orbit.iterate(delta, delta_c, limit, bailout).map(value);
+                            (index, result)
+                        })
+                        .collect::<Vec<_>>()
+                });
+                progress.inc(results.iter().filter(|(_, result)| result.is_ok()).count());
+                results
+            })
+            .collect();
+
+        pending.clear();
+        for (index, result) in results {
+            match result {
+                Ok(encoded) => iterations[index] = encoded,
+                Err(()) if last_round => {
+                    // 没有参考点可用了，退回到直接用 f64 迭代
+                    let pixel = (index % bounds.0, index / bounds.0);
+                    let point = center.to_complex() + pixel_offset(bounds, pixel, spacing);
+                    iterations[index] =
+                        encode_escape(fractal.escape_value(coloring, point, limit, spacing));
+                    progress.inc(1);
+                }
+                Err(()) => pending.push(index),
+            }
+        }
+        if let Some(&index) = pending.get(pending.len() / 2) {
+            reference_offset = pixel_offset(bounds, (index % bounds.0, index / bounds.0), spacing);
+        }
+    }
+    progress.finish();
+    references
+}
+
+#[test]
+fn test_render_parallel() {
+    // 浅缩放时微扰渲染的结果应当与 f64 渲染基本一致
+    let bounds = (32, 24);
+    let center = Complex { re: -0.75, im: 0.1 };
+    let zoom = 20.0;
+    let spacing = crate::pixel_spacing(bounds, zoom);
+    let (upper_left, lower_right) = crate::corners_from_center(bounds, center, zoom);
+    for fractal in [
+        Fractal::Mandelbrot,
+        Fractal::Julia(Complex {
+            re: -0.8,
+            im: 0.156,
+        }),
+        Fractal::BurningShip,
+        Fractal::Tricorn,
+        Fractal::Multibrot(3.0),
+    ] {
+        let mut expected = vec![0; bounds.0 * bounds.1];
+        crate::render(
+            fractal,
+            Coloring::EscapeTime,
+            500,
+            &mut expected,
+            bounds,
+            crate::PixelTransform::from_corners(bounds, upper_left, lower_right),
+        );
+        let mut actual = vec![0; bounds.0 * bounds.1];
+        let references = render_parallel(
+            fractal,
+            Coloring::EscapeTime,
+            500,
+            &mut actual,
+            bounds,
+            &FixedComplex::from_complex(center, 64),
+            spacing,
+            &Progress::hidden(),
+        );
+        assert!(references >= 1);
+        let mismatches = actual.iter().zip(&expected).filter(|(a, e)| a != e).count();
+        assert!(mismatches * 100 < actual.len(), "{} mismatches", mismatches);
+    }
+}
+
+#[test]
+fn test_render_parallel_cached() {
+    // 放大到同一个中心的第二帧复用第一帧的参考轨道，结果与不使用缓存时相同
+    let bounds = (24, 16);
+    let bits = 128;
+    let center =
+        FixedComplex::parse("-0.743643887037158704752,0.131825904205311970493", bits).unwrap();
+    let orbits = OrbitCache::new();
+    for spacing in [1e-17, 1e-19, 1e-19] {
+        let mut expected = vec![0; bounds.0 * bounds.1];
+        render_parallel(
+            Fractal::Mandelbrot,
+            Coloring::EscapeTime,
+            2000,
+            &mut expected,
+            bounds,
+            &center,
+            spacing,
+            &Progress::hidden(),
+        );
+        let mut actual = vec![0; bounds.0 * bounds.1];
+        render_parallel_cached(
+            Fractal::Mandelbrot,
+            Coloring::EscapeTime,
+            2000,
+            &mut actual,
+            bounds,
+            &center,
+            spacing,
+            &Progress::hidden(),
+            &orbits,
+        );
+        assert_eq!(actual, expected);
+    }
+    assert_eq!(orbits.hits(), 2);
+
+    // 参考点不在视图之内、迭代次数限制更大或者分形不同时不能复用
+    let moved = FixedComplex::parse("-0.7436438870371587,0.1318259042053119", bits).unwrap();
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    for (fractal, limit, center) in [
+        (Fractal::Mandelbrot, 2000, &moved),
+        (Fractal::Mandelbrot, 4000, &center),
+        (Fractal::Tricorn, 2000, &center),
+    ] {
+        render_parallel_cached(
+            fractal,
+            Coloring::EscapeTime,
+            limit,
+            &mut iterations,
+            bounds,
+            center,
+            1e-19,
+            &Progress::hidden(),
+            &orbits,
+        );
+    }
+    assert_eq!(orbits.hits(), 2);
+}
@@ -0,0 +1,595 @@
+//! 任意精度的深度缩放
+//!
+//! 缩放倍数超过大约 1e14 之后，相邻像素的坐标之差已经小于 `f64` 的分辨率，
+//! 渲染结果会变成一块块的色斑。这里用 `BigInt` 实现定点数 `Fixed`，在像素坐标的
+//! 计算和迭代中都使用足够多的二进制小数位，代价是比 `f64` 慢得多。
+
+use crate::progress::Progress;
+use crate::real::resolves;
+use crate::{encode_escape, smooth_value, Coloring, Fractal, SMOOTH_BAILOUT};
+use num::bigint::Sign;
+use num::{BigInt, Complex, Signed, ToPrimitive, Zero};
+use rayon::iter::IndexedParallelIterator;
+use rayon::prelude::{ParallelIterator, ParallelSliceMut};
+use std::ops::{Add, Mul, Sub};
+
+/// 二进制定点数，值为 `mantissa / 2^bits`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixed {
+    mantissa: BigInt,
+    bits: u32,
+}
+
+impl Fixed {
+    /// 用 `bits` 个二进制小数位表示 `f64` 值 `value`，`value` 必须是有限值
+    pub fn from_f64(value: f64, bits: u32) -> Fixed {
+        assert!(value.is_finite());
+        // f64 的尾数只有 53 位，先放大到整数再移位不会丢失精度
+        let (mantissa, exponent, sign) = num::Float::integer_decode(value);
+        let mut mantissa = BigInt::from(mantissa);
+        let shift = exponent as i64 + bits as i64;
+        if shift >= 0 {
+            mantissa <<= shift as u64;
+        } else {
+            mantissa >>= (-shift) as u64;
+        }
+        if sign < 0 {
+            mantissa = -mantissa;
+        }
+        Fixed { mantissa, bits }
+    }
+
+    /// 把十进制字符串 `s`（形如 `"-0.74364388703715870475"` 或 `"1.5e-20"`）解析为
+    /// 带 `bits` 个二进制小数位的定点数，不经过 `f64`，因此不会损失精度
+    pub fn parse(s: &str, bits: u32) -> Option<Fixed> {
+        let (number, exponent) = match s.find(['e', 'E']) {
+            Some(index) => (&s[..index], s[index + 1..].parse::<i32>().ok()?),
+            None => (s, 0),
+        };
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let digits: BigInt = format!("0{}{}", integer, fraction).parse().ok()?;
+        let scale = exponent - fraction.len() as i32;
+        let ten = BigInt::from(10);
+        let mut mantissa = digits << bits as u64;
+        if scale >= 0 {
+            mantissa *= num::pow(ten, scale as usize);
+        } else {
+            mantissa /= num::pow(ten, (-scale) as usize);
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+        Some(Fixed { mantissa, bits })
+    }
+
+    /// 最接近该定点数的 `f64` 值
+    pub fn to_f64(&self) -> f64 {
+        // 只保留最高的 64 位有效数字，避免超出 f64 的表示范围
+        let excess = self.mantissa.bits().saturating_sub(64);
+        let top = (&self.mantissa >> excess).to_f64().unwrap_or(0.0);
+        top * 2f64.powi(excess as i32 - self.bits as i32)
+    }
+
+    /// 该定点数的二进制小数位数
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// 改用 `bits` 个二进制小数位表示，位数减少时截去多余的位
+    pub fn with_bits(&self, bits: u32) -> Fixed {
+        let mantissa = if bits >= self.bits {
+            &self.mantissa << (bits - self.bits) as u64
+        } else {
+            &self.mantissa >> (self.bits - bits) as u64
+        };
+        Fixed { mantissa, bits }
+    }
+
+    /// 四舍五入到 `digits` 位小数的十进制表示，不经过 `f64`，因此不会损失精度
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let half = BigInt::from(1) << self.bits as u64 >> 1u64;
+        let scaled =
+            (self.mantissa.abs() * num::pow(BigInt::from(10), digits) + half) >> self.bits as u64;
+        let text = format!("{:0>width$}", scaled, width = digits + 1);
+        let (integer, fraction) = text.split_at(text.len() - digits);
+        let sign = if self.is_negative() && !scaled.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        if digits == 0 {
+            format!("{}{}", sign, integer)
+        } else {
+            format!("{}{}.{}", sign, integer, fraction)
+        }
+    }
+
+    pub(crate) fn zero(bits: u32) -> Fixed {
+        Fixed {
+            mantissa: BigInt::zero(),
+            bits,
+        }
+    }
+
+    /// 乘以整数 `n`，这比乘以一个定点数便宜得多
+    pub(crate) fn mul_int(&self, n: i64) -> Fixed {
+        Fixed {
+            mantissa: &self.mantissa * n,
+            bits: self.bits,
+        }
+    }
+
+    fn is_negative(&self) -> bool {
+        self.mantissa.sign() == Sign::Minus
+    }
+
+    /// 绝对值
+    pub fn abs(&self) -> Fixed {
+        Fixed {
+            mantissa: self.mantissa.abs(),
+            bits: self.bits,
+        }
+    }
+}
+
+impl Add for &Fixed {
+    type Output = Fixed;
+    fn add(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: &self.mantissa + &other.mantissa,
+            bits: self.bits,
+        }
+    }
+}
+
+impl Sub for &Fixed {
+    type Output = Fixed;
+    fn sub(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: &self.mantissa - &other.mantissa,
+            bits: self.bits,
+        }
+    }
+}
+
+impl Mul for &Fixed {
+    type Output = Fixed;
+    fn mul(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed {
+            mantissa: (&self.mantissa * &other.mantissa) >> self.bits as u64,
+            bits: self.bits,
+        }
+    }
+}
+
+#[test]
+fn test_fixed_parse() {
+    assert_eq!(Fixed::parse("1.5", 8), Some(Fixed::from_f64(1.5, 8)));
+    assert_eq!(Fixed::parse("-0.25", 8), Some(Fixed::from_f64(-0.25, 8)));
+    assert_eq!(Fixed::parse("25e-2", 8), Some(Fixed::from_f64(0.25, 8)));
+    assert_eq!(Fixed::parse(".5", 8), Some(Fixed::from_f64(0.5, 8)));
+    assert_eq!(Fixed::parse("1.", 8), Some(Fixed::from_f64(1.0, 8)));
+    assert_eq!(Fixed::parse("", 8), None);
+    assert_eq!(Fixed::parse("1.2.3", 8), None);
+    assert_eq!(Fixed::parse("0x10", 8), None);
+
+    // 与相差 1e-30 的数能区分开
+    let a = Fixed::parse("-0.743643887037158704752191506114774", 128).unwrap();
+    let b = Fixed::parse("-0.743643887037158704752191506114775", 128).unwrap();
+    assert_eq!((&a - &b).mantissa.sign(), Sign::Plus);
+    assert_eq!(a.to_f64(), -0.7436438870371587);
+}
+
+#[test]
+fn test_fixed_arithmetic() {
+    let a = Fixed::from_f64(1.5, 64);
+    let b = Fixed::from_f64(-0.25, 64);
+    assert_eq!((&a + &b).to_f64(), 1.25);
+    assert_eq!((&a - &b).to_f64(), 1.75);
+    assert_eq!((&a * &b).to_f64(), -0.375);
+    assert_eq!(a.mul_int(-3).to_f64(), -4.5);
+    assert_eq!(Fixed::from_f64(1e-30, 200).to_f64(), 1e-30);
+    assert_eq!(a.with_bits(128), Fixed::from_f64(1.5, 128));
+    assert_eq!(
+        Fixed::from_f64(0.75, 2).with_bits(1),
+        Fixed::from_f64(0.5, 1)
+    );
+}
+
+#[test]
+fn test_fixed_to_decimal() {
+    assert_eq!(Fixed::from_f64(-0.375, 16).to_decimal(3), "-0.375");
+    assert_eq!(Fixed::from_f64(-0.375, 16).to_decimal(2), "-0.38");
+    assert_eq!(Fixed::from_f64(12.5, 16).to_decimal(0), "13");
+    assert_eq!(Fixed::from_f64(-0.001, 64).to_decimal(2), "0.00");
+    let digits = "-0.7436438870371587047521915061147746";
+    assert_eq!(Fixed::parse(digits, 160).unwrap().to_decimal(34), digits);
+}
+
+/// 使用 `Fixed` 表示实部和虚部的复数
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedComplex {
+    pub re: Fixed,
+    pub im: Fixed,
+}
+
+impl FixedComplex {
+    /// 用 `bits` 个二进制小数位表示复数 `c`
+    pub fn from_complex(c: Complex<f64>, bits: u32) -> FixedComplex {
+        FixedComplex {
+            re: Fixed::from_f64(c.re, bits),
+            im: Fixed::from_f64(c.im, bits),
+        }
+    }
+
+    /// 把形如 `"-0.743643887,0.131825904"` 的字符串解析为高精度复数
+    pub fn parse(s: &str, bits: u32) -> Option<FixedComplex> {
+        let (re, im) = s.split_once(',')?;
+        Some(FixedComplex {
+            re: Fixed::parse(re, bits)?,
+            im: Fixed::parse(im, bits)?,
+        })
+    }
+
+    /// 最接近该复数的 `Complex<f64>`
+    pub fn to_complex(&self) -> Complex<f64> {
+        Complex {
+            re: self.re.to_f64(),
+            im: self.im.to_f64(),
+        }
+    }
+
+    /// 改用 `bits` 个二进制小数位表示实部和虚部
+    pub fn with_bits(&self, bits: u32) -> FixedComplex {
+        FixedComplex {
+            re: self.re.with_bits(bits),
+            im: self.im.with_bits(bits),
+        }
+    }
+
+    pub(crate) fn zero(bits: u32) -> FixedComplex {
+        FixedComplex {
+            re: Fixed::zero(bits),
+            im: Fixed::zero(bits),
+        }
+    }
+
+    fn mul(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex {
+            re: &(&self.re * &other.re) - &(&self.im * &other.im),
+            im: &(&self.re * &other.im) + &(&self.im * &other.re),
+        }
+    }
+
+    /// 对 `self` 做一次分形 `fractal` 的迭代，与 `Fractal::step` 相同
+    ///
+    /// 调用者需要先用 `Fractal::supports_deep_zoom` 确认迭代公式的次数是整数
+    pub fn step(&self, fractal: Fractal, c: &FixedComplex) -> FixedComplex {
+        let folded;
+        let z = match fractal {
+            Fractal::Mandelbrot | Fractal::Julia(_) => self,
+            Fractal::BurningShip => {
+                folded = FixedComplex {
+                    re: self.re.abs(),
+                    im: self.im.abs(),
+                };
+                &folded
+            }
+            Fractal::Tricorn => {
+                folded = FixedComplex {
+                    re: self.re.clone(),
+                    im: self.im.mul_int(-1),
+                };
+                &folded
+            }
+            Fractal::Multibrot(power) => {
+                assert!(fractal.supports_deep_zoom(), "non-integer power {}", power);
+                let mut product = self.clone();
+                for _ in 1..power as u32 {
+                    product = product.mul(self);
+                }
+                return FixedComplex {
+                    re: &product.re + &c.re,
+                    im: &product.im + &c.im,
+                };
+            }
+            Fractal::Formula(_)
+            | Fractal::Phoenix { .. }
+            | Fractal::Lambda(_)
+            | Fractal::Hybrid { .. } => {
+                unreachable!("{:?} does not support deep zoom", fractal)
+            }
+        };
+        let re2 = &z.re * &z.re;
+        let im2 = &z.im * &z.im;
+        FixedComplex {
+            re: &(&re2 - &im2) + &c.re,
+            im: &(&z.re * &z.im).mul_int(2) + &c.im,
+        }
+    }
+}
+
+/// 返回点 `point` 在分形 `fractal` 中的迭代起点和常数，与 `Fractal::orbit_start` 相同
+pub fn orbit_start(fractal: Fractal, point: FixedComplex) -> (FixedComplex, FixedComplex) {
+    let bits = point.re.bits();
+    match fractal {
+        Fractal::Mandelbrot
+        | Fractal::BurningShip
+        | Fractal::Tricorn
+        | Fractal::Multibrot(_)
+        | Fractal::Formula(_)
+        | Fractal::Phoenix { c: None, .. }
+        | Fractal::Hybrid { c: None, .. } => (FixedComplex::zero(bits), point),
+        Fractal::Julia(c)
+        | Fractal::Phoenix { c: Some(c), .. }
+        | Fractal::Lambda(Some(c))
+        | Fractal::Hybrid { c: Some(c), .. } => (point, FixedComplex::from_complex(c, bits)),
+        Fractal::Lambda(None) => (
+            FixedComplex::from_complex(Complex::new(0.5, 0.0), bits),
+            point,
+        ),
+    }
+}
+
+/// 判断以 `center` 为中心、像素间距为 `spacing` 的视图是否超出了 `f64` 的分辨率，
+/// 需要改用 `Fixed`，参见 `real::resolves`
+pub fn required(center: Complex<f64>, spacing: f64) -> bool {
+    !resolves::<f64>(center, spacing)
+}
+
+#[test]
+fn test_required() {
+    let center = Complex { re: -0.75, im: 0.1 };
+    assert!(!required(center, 4.0 / 1000.0));
+    assert!(!required(center, 1e-10));
+    assert!(required(center, 1e-16));
+}
+
+/// 像素间距为 `spacing` 时所需的二进制小数位数
+///
+/// 除了表示像素间距本身所需的位数之外，再留出 64 位余量吸收迭代中的舍入误差
+pub fn bits_for_spacing(spacing: f64) -> u32 {
+    bits_for_log2_spacing(spacing.log2())
+}
+
+/// 像素间距以 2 为底的对数为 `log2` 时所需的二进制小数位数，参见 `bits_for_spacing`
+///
+/// 间距为 0 或者小到超出 `u32` 时饱和为 `u32::MAX`。
+pub fn bits_for_log2_spacing(log2: f64) -> u32 {
+    ((-log2).ceil().max(0.0) as u32).saturating_add(64)
+}
+
+#[test]
+fn test_bits_for_spacing() {
+    assert_eq!(bits_for_spacing(0.25), 66);
+    assert_eq!(bits_for_spacing(4.0), 64);
+    assert_eq!(bits_for_log2_spacing(-1000.0), 1064);
+    assert_eq!(bits_for_spacing(0.0), u32::MAX);
+}
+
+/// 以任意精度从 `z` 出发迭代分形 `fractal`
+///
+/// 当 `|z|^2` 超过 `bailout` 时返回逃逸时的迭代次数和转换为 `f64` 的 `z`；
+/// 达到迭代次数限制仍未逃逸则返回 `None`
+pub fn escape(
+    fractal: Fractal,
+    mut z: FixedComplex,
+    c: &FixedComplex,
+    limit: usize,
+    bailout: f64,
+) -> Option<(usize, Complex<f64>)> {
+    let bits = c.re.bits();
+    let bailout = Fixed::from_f64(bailout, bits);
+    for i in 0..limit {
+        let norm_sqr = &(&z.re * &z.re) + &(&z.im * &z.im);
+        if (&bailout - &norm_sqr).is_negative() {
+            return Some((i, z.to_complex()));
+        }
+        z = z.step(fractal, c);
+    }
+    None
+}
+
+#[test]
+fn test_escape() {
+    let bits = 64;
+    let origin = FixedComplex::from_complex(Complex { re: 0.0, im: 0.0 }, bits);
+    for c in [
+        Complex { re: -0.75, im: 0.1 },
+        Complex { re: 2.0, im: 2.0 },
+        Complex { re: 0.3, im: 0.5 },
+    ] {
+        let fixed = FixedComplex::from_complex(c, bits);
+        assert_eq!(
+            escape(Fractal::Mandelbrot, origin.clone(), &fixed, 500, 4.0).map(|(count, _)| count),
+            crate::escape_time(Complex { re: 0.0, im: 0.0 }, c, 500)
+        );
+        assert_eq!(
+            escape(Fractal::BurningShip, origin.clone(), &fixed, 500, 4.0).map(|(count, _)| count),
+            Fractal::BurningShip.escape_time(c, 500)
+        );
+        assert_eq!(
+            escape(Fractal::Tricorn, origin.clone(), &fixed, 500, 4.0).map(|(count, _)| count),
+            Fractal::Tricorn.escape_time(c, 500)
+        );
+        assert_eq!(
+            escape(Fractal::Multibrot(4.0), origin.clone(), &fixed, 500, 4.0)
+                .map(|(count, _)| count),
+            Fractal::Multibrot(4.0).escape_time(c, 500)
+        );
+    }
+    assert_eq!(
+        escape(Fractal::Mandelbrot, origin.clone(), &origin, 500, 4.0),
+        None
+    );
+}
+
+/// 深度缩放时计算像素坐标和迭代所用的复数运算，`render_parallel` 对它泛型
+///
+/// 任意精度的 `FixedComplex` 和双倍精度的 `extended::DoubleComplex` 实现了它。
+pub trait DeepComplex: Sized + Sync {
+    /// 像素间距的数值类型
+    type Spacing: Sync;
+
+    /// 视图中心 `center` 和半个像素间距 `spacing / 2` 换算为该运算的表示
+    fn view(center: &FixedComplex, spacing: &Fixed) -> (Self, Self::Spacing);
+
+    /// 从 `self` 出发，实部加上 `re` 个、虚部加上 `im` 个 `half_spacing` 得到的点
+    fn offset(&self, half_spacing: &Self::Spacing, re: i64, im: i64) -> Self;
+
+    /// 点 `point` 在分形 `fractal` 中的迭代起点和常数
+    fn orbit_start(fractal: Fractal, point: Self) -> (Self, Self);
+
+    /// 从 `z` 出发迭代，逃逸时返回迭代次数和转换为 `f64` 的 `z`，参见 `escape`
+    fn escape(
+        fractal: Fractal,
+        z: Self,
+        c: &Self,
+        limit: usize,
+        bailout: f64,
+    ) -> Option<(usize, Complex<f64>)>;
+}
+
+impl DeepComplex for FixedComplex {
+    type Spacing = Fixed;
+
+    fn view(center: &FixedComplex, spacing: &Fixed) -> (FixedComplex, Fixed) {
+        let half_spacing = Fixed {
+            mantissa: &spacing.mantissa >> 1u64,
+            bits: center.re.bits(),
+        };
+        (center.clone(), half_spacing)
+    }
+
+    fn offset(&self, half_spacing: &Fixed, re: i64, im: i64) -> FixedComplex {
+        FixedComplex {
+            re: &self.re + &half_spacing.mul_int(re),
+            im: &self.im + &half_spacing.mul_int(im),
+        }
+    }
+
+    fn orbit_start(fractal: Fractal, point: FixedComplex) -> (FixedComplex, FixedComplex) {
+        orbit_start(fractal, point)
+    }
+
+    fn escape(
+        fractal: Fractal,
+        z: FixedComplex,
+        c: &FixedComplex,
+        limit: usize,
+        bailout: f64,
+    ) -> Option<(usize, Complex<f64>)> {
+        escape(fractal, z, c, limit, bailout)
+    }
+}
+
+/// 以 `T` 的精度把以 `center` 为中心、像素间距为 `spacing` 的视图渲染到迭代缓冲区中
+///
+/// 参数含义与 `crate::render` 相同，只是视图由中心和像素间距给出，
+/// 所有坐标计算都使用 `T` 的精度：`FixedComplex` 时使用 `center` 的精度，
+/// `extended::DoubleComplex` 时 `center` 和 `spacing` 先舍入到双倍精度，调用者需要先用
+/// `extended::resolves` 确认双倍精度足以分辨该视图。每一行像素作为一个任务交给 rayon
+/// 调度，完成后在 `progress` 上记录一次。只支持 `Coloring::supports_deep_zoom` 为真的
+/// 着色方式。
+#[allow(clippy::too_many_arguments)]
+pub fn render_parallel<T: DeepComplex>(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    iterations: &mut [u32],
+    bounds: (usize, usize),
+    center: &FixedComplex,
+    spacing: &Fixed,
+    progress: &Progress,
+) {
+    assert_eq!(iterations.len(), bounds.0 * bounds.1);
+    progress.start(bounds.1, "rows");
+    let bailout = match coloring {
+        Coloring::EscapeTime => 4.0,
+        Coloring::Smooth => SMOOTH_BAILOUT,
+        Coloring::Distance
+        | Coloring::OrbitTrap(_)
+        | Coloring::TriangleInequality
+        | Coloring::AtomDomain => {
+            panic!("{:?} coloring is not supported for deep zooms", coloring)
+        }
+    };
+
+    let (center, half_spacing) = T::view(center, spacing);
+
+    iterations
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            progress.timed(|| {
+                // 像素相对中心的偏移以半个像素间距为单位，行号越大虚部越小
+                let im = bounds.1 as i64 - 2 * row as i64;
+                for (column, value) in band.iter_mut().enumerate() {
+                    let re = 2 * column as i64 - bounds.0 as i64;
+                    let point = center.offset(&half_spacing, re, im);
+                    let (z, c) = T::orbit_start(fractal, point);
+                    let escaped = T::escape(fractal, z, &c, limit, bailout);
+                    *value = encode_escape(escaped.map(|(count, z)| match coloring {
+                        Coloring::EscapeTime => count as f64,
+                        Coloring::Smooth => smooth_value(count, z, fractal.degree()),
+                        Coloring::Distance
+                        | Coloring::OrbitTrap(_)
+                        | Coloring::TriangleInequality
+                        | Coloring::AtomDomain => {
+                            unreachable!()
+                        }
+                    }));
+                }
+            });
+            progress.inc(1);
+        });
+    progress.finish();
+}
+
+#[test]
+fn test_render_parallel() {
+    // 浅缩放时任意精度的结果应当与 f64 渲染一致
+    let bounds = (16, 12);
+    let center = Complex { re: -0.5, im: 0.0 };
+    let zoom = 1.0;
+    let (upper_left, lower_right) = crate::corners_from_center(bounds, center, zoom);
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    crate::render(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        100,
+        &mut expected,
+        bounds,
+        crate::PixelTransform::from_corners(bounds, upper_left, lower_right),
+    );
+
+    let bits = 64;
+    let mut actual = vec![0; bounds.0 * bounds.1];
+    render_parallel::<FixedComplex>(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        100,
+        &mut actual,
+        bounds,
+        &FixedComplex::from_complex(center, bits),
+        &Fixed::from_f64(crate::pixel_spacing(bounds, zoom), bits),
+        &Progress::hidden(),
+    );
+    assert_eq!(actual, expected);
+}
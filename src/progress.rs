@@ -0,0 +1,177 @@
+//! 渲染进度的报告
+//!
+//! 渲染函数把整个任务分成若干单元（行或像素），每完成一些单元就累加一个原子计数器。
+//! 计数器由多个 rayon 线程共享，绘制进度条则限制为每 `REDRAW_INTERVAL` 最多一次，
+//! 并且只由抢到锁的那个线程负责。
+//!
+//! 需要 `--stats` 时进度条还带着一个 `ThreadTimer`，渲染函数用 `timed` 给每个并行任务计时。
+
+use crate::stats::{ThreadTime, ThreadTimer};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 两次重绘进度条之间的最短间隔
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 进度条的宽度（字符数）
+const BAR_WIDTH: usize = 30;
+
+/// 一个可以在线程之间共享的进度条，输出到标准错误
+pub struct Progress {
+    enabled: bool,
+    done: AtomicUsize,
+    state: Mutex<State>,
+    timer: Option<ThreadTimer>,
+}
+
+struct State {
+    total: usize,
+    unit: &'static str,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    /// 创建进度条，`enabled` 为假时所有操作都不输出任何内容
+    pub fn new(enabled: bool) -> Progress {
+        Progress {
+            enabled,
+            done: AtomicUsize::new(0),
+            state: Mutex::new(State {
+                total: 0,
+                unit: "",
+                start: Instant::now(),
+                last_draw: None,
+            }),
+            timer: None,
+        }
+    }
+
+    /// 同时记录每个线程的忙碌时间
+    pub fn with_thread_timing(self) -> Progress {
+        Progress {
+            timer: Some(ThreadTimer::new()),
+            ..self
+        }
+    }
+
+    /// 不输出任何内容的进度条
+    pub fn hidden() -> Progress {
+        Progress::new(false)
+    }
+
+    /// 开始一个由 `total` 个单元组成的新任务，`unit` 是单元的名称（如 `"rows"`）
+    pub fn start(&self, total: usize, unit: &'static str) {
+        self.done.store(0, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        *state = State {
+            total,
+            unit,
+            start: Instant::now(),
+            last_draw: None,
+        };
+    }
+
+    /// 记录又完成了 `n` 个单元
+    pub fn inc(&self, n: usize) {
+        let done = self.done.fetch_add(n, Ordering::Relaxed) + n;
+        if !self.enabled {
+            return;
+        }
+        // 其他线程正在绘制时直接跳过，不必等待
+        if let Ok(mut state) = self.state.try_lock() {
+            let now = Instant::now();
+            if state
+                .last_draw
+                .is_none_or(|last| now - last >= REDRAW_INTERVAL)
+            {
+                state.last_draw = Some(now);
+                draw(&state, done, now);
+            }
+        }
+    }
+
+    /// 执行并行渲染中的一个任务（分块或行），带着计时器的话把耗时记在当前线程名下
+    pub fn timed<R>(&self, task: impl FnOnce() -> R) -> R {
+        match &self.timer {
+            Some(timer) => timer.timed(task),
+            None => task(),
+        }
+    }
+
+    /// 创建以来每个线程的忙碌时间，没有计时器时为空
+    pub fn thread_times(&self) -> Vec<ThreadTime> {
+        self.timer
+            .as_ref()
+            .map_or_else(Vec::new, ThreadTimer::times)
+    }
+
+    /// 结束当前任务，绘制最终的进度并换行
+    pub fn finish(&self) {
+        if !self.enabled {
+            return;
+        }
+        let state = self.state.lock().unwrap();
+        draw(&state, self.done.load(Ordering::Relaxed), Instant::now());
+        eprintln!();
+    }
+}
+
+/// 在标准错误的当前行上重绘进度条
+fn draw(state: &State, done: usize, now: Instant) {
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r{}", format_line(state, done, now));
+    let _ = stderr.flush();
+}
+
+/// 形如 `[#######-----]  45% 120 rows/s ETA 3s` 的一行进度
+fn format_line(state: &State, done: usize, now: Instant) -> String {
+    let fraction = if state.total == 0 {
+        1.0
+    } else {
+        (done as f64 / state.total as f64).min(1.0)
+    };
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+    let elapsed = (now - state.start).as_secs_f64();
+    let rate = if elapsed > 0.0 {
+        done as f64 / elapsed
+    } else {
+        0.0
+    };
+    let eta = if rate > 0.0 {
+        format!("{:.0}s", state.total.saturating_sub(done) as f64 / rate)
+    } else {
+        "?".to_string()
+    };
+    format!(
+        "[{}{}] {:3.0}% {:.0} {}/s ETA {}",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        fraction * 100.0,
+        rate,
+        state.unit,
+        eta
+    )
+}
+
+#[test]
+fn test_format_line() {
+    let start = Instant::now();
+    let state = State {
+        total: 200,
+        unit: "rows",
+        start,
+        last_draw: None,
+    };
+    let line = format_line(&state, 50, start + Duration::from_secs(5));
+    assert_eq!(
+        line,
+        format!(
+            "[{}{}]  25% 10 rows/s ETA 15s",
+            "#".repeat(7),
+            "-".repeat(23)
+        )
+    );
+}
@@ -0,0 +1,132 @@
+//! 渐进式渲染
+//!
+//! 第一遍只计算行号和列号都是 8 的倍数的像素，每个像素填满它右下方 8×8 的块，很快就能
+//! 得到一幅粗略的图像。之后每一遍把步长减半，只计算坐标是新步长的倍数、但前几遍还没有
+//! 算过的像素，再按新的步长填充。四遍之后每个像素恰好计算了一次，结果与 `render` 逐位
+//! 相同；与逐级重新渲染低分辨率的图像相比，没有重复的计算。
+
+use crate::real::Real;
+use crate::{encode_escape, Coloring, Fractal, PixelTransform};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 第一遍的步长
+pub const COARSEST: usize = 8;
+
+/// 逐遍细化的迭代缓冲区
+pub struct Progressive {
+    bounds: (usize, usize),
+    iterations: Vec<u32>,
+    /// 下一遍的步长，全部完成后为 `None`
+    next: Option<usize>,
+}
+
+impl Progressive {
+    /// `bounds` 大小、还没有计算任何像素的缓冲区
+    pub fn new(bounds: (usize, usize)) -> Progressive {
+        Progressive {
+            bounds,
+            iterations: vec![0; bounds.0 * bounds.1],
+            next: Some(COARSEST),
+        }
+    }
+
+    /// 每个像素都已经算好的缓冲区，例如平移视图时复用的像素（见 `pan`）
+    pub fn finished(bounds: (usize, usize), iterations: Vec<u32>) -> Progressive {
+        assert_eq!(iterations.len(), bounds.0 * bounds.1);
+        Progressive {
+            bounds,
+            iterations,
+            next: None,
+        }
+    }
+
+    /// 当前的迭代缓冲区，没有计算的像素取所在块左上角像素的值
+    pub fn iterations(&self) -> &[u32] {
+        &self.iterations
+    }
+
+    /// 每个像素是否都已经计算过
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// 计算下一遍的像素，并用它们填充还没有计算的像素；全部完成后什么也不做
+    pub fn refine<T: Real>(
+        &mut self,
+        fractal: Fractal,
+        coloring: Coloring,
+        limit: usize,
+        transform: PixelTransform<T>,
+    ) {
+        let Some(step) = self.next else {
+            return;
+        };
+        let width = self.bounds.0;
+        let spacing = transform.spacing();
+        // 每个块的第一行计算这一遍的像素并在行内填充，再复制到块的其余各行
+        self.iterations
+            .par_chunks_mut(width * step)
+            .enumerate()
+            .for_each(|(block, rows)| {
+                let y = block * step;
+                let (first, rest) = rows.split_at_mut(width);
+                for x in (0..width).step_by(step) {
+                    // 两个坐标都是 2 * step 的倍数的像素在前几遍已经算过
+                    let computed = step < COARSEST && x % (2 * step) == 0 && y % (2 * step) == 0;
+                    if !computed {
+                        let point = transform.point((x, y));
+                        first[x] =
+                            encode_escape(fractal.escape_value(coloring, point, limit, spacing));
+                    }
+                    let value = first[x];
+                    first[x..(x + step).min(width)].fill(value);
+                }
+                for row in rest.chunks_mut(width) {
+                    row.copy_from_slice(first);
+                }
+            });
+        self.next = (step > 1).then_some(step / 2);
+    }
+}
+
+#[test]
+fn test_progressive() {
+    use crate::render;
+
+    // 尺寸不是 8 的倍数，最后的块不完整
+    let bounds = (37, 21);
+    let transform = PixelTransform::from_corners(
+        bounds,
+        num::Complex { re: -2.0, im: 1.2 },
+        num::Complex { re: 0.8, im: -1.2 },
+    );
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    render(
+        Fractal::Mandelbrot,
+        Coloring::Smooth,
+        200,
+        &mut expected,
+        bounds,
+        transform,
+    );
+
+    let mut progressive = Progressive::new(bounds);
+    progressive.refine(Fractal::Mandelbrot, Coloring::Smooth, 200, transform);
+    // 第一遍之后每个 8×8 的块都是左上角像素的值
+    let iterations = progressive.iterations();
+    for y in 0..bounds.1 {
+        for x in 0..bounds.0 {
+            assert_eq!(
+                iterations[y * bounds.0 + x],
+                expected[y / 8 * 8 * bounds.0 + x / 8 * 8]
+            );
+        }
+    }
+    let mut passes = 1;
+    while !progressive.is_finished() {
+        progressive.refine(Fractal::Mandelbrot, Coloring::Smooth, 200, transform);
+        passes += 1;
+    }
+    assert_eq!(passes, 4);
+    assert_eq!(progressive.iterations(), expected);
+}
@@ -0,0 +1,279 @@
+//! 持久化的渲染任务队列
+//!
+//! `queue add` 把一条 mandelbrot 命令行排入队列，`queue run` 依次（或用几个并行的
+//! 执行槽）执行排队的命令，直到队列为空。队列是一个目录，每个任务是其中的一个 JSON
+//! 文件，按状态放在 `pending`、`running`、`done` 和 `failed` 四个子目录中：
+//!
+//! ```text
+//! queue/
+//!   lock
+//!   pending/000003.json
+//!   running/000002.json
+//!   done/000001.json
+//! ```
+//!
+//! 认领任务就是把文件从 `pending` 改名到 `running`，改名是原子的，同一个任务不会被执行
+//! 两次。`queue run` 运行期间持有 `lock` 文件的锁，进程崩溃或被杀死时锁自动释放；下一次
+//! `queue run` 拿到锁后，`running` 中剩下的任务一定已经无人执行，会被放回 `pending` 重新
+//! 执行。因此进程重启之后队列照常继续。
+
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// 队列目录的默认位置：环境变量 `MANDELBROT_QUEUE`，否则为 `$HOME/.mandelbrot/queue`
+pub fn queue_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("MANDELBROT_QUEUE") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".mandelbrot/queue"))
+}
+
+/// 任务的状态，也是任务文件所在的子目录
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl State {
+    pub const ALL: [State; 4] = [State::Pending, State::Running, State::Done, State::Failed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Pending => "pending",
+            State::Running => "running",
+            State::Done => "done",
+            State::Failed => "failed",
+        }
+    }
+}
+
+/// 一个排队的任务
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    /// 不含程序名的命令行参数
+    pub args: Vec<String>,
+    /// 排入任务时的工作目录，命令中的相对路径相对于它
+    pub cwd: PathBuf,
+    /// 失败的原因
+    pub error: Option<String>,
+}
+
+impl Job {
+    fn to_json(&self) -> String {
+        let mut value = json!({
+            "args": self.args,
+            "cwd": self.cwd.to_string_lossy(),
+        });
+        if let Some(error) = &self.error {
+            value["error"] = json!(error);
+        }
+        format!("{:#}\n", value)
+    }
+
+    fn from_json(id: u64, text: &str) -> io::Result<Job> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid job {}", id));
+        let value: Value = serde_json::from_str(text).map_err(|_| invalid())?;
+        let args = value["args"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|arg| arg.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        Ok(Job {
+            id,
+            args,
+            cwd: PathBuf::from(value["cwd"].as_str().ok_or_else(invalid)?),
+            error: value["error"].as_str().map(str::to_string),
+        })
+    }
+}
+
+/// 队列目录
+pub struct Queue {
+    dir: PathBuf,
+}
+
+impl Queue {
+    /// 打开队列目录，不存在时创建
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Queue> {
+        let queue = Queue { dir: dir.into() };
+        for state in State::ALL {
+            fs::create_dir_all(queue.dir.join(state.name()))?;
+        }
+        Ok(queue)
+    }
+
+    fn path(&self, state: State, id: u64) -> PathBuf {
+        self.dir.join(state.name()).join(format!("{:06}.json", id))
+    }
+
+    /// 处于 `state` 的任务的编号，从小到大排列
+    fn ids(&self, state: State) -> io::Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(self.dir.join(state.name()))? {
+            let name = entry?.file_name();
+            let id = name.to_str().and_then(|name| name.strip_suffix(".json"));
+            if let Some(id) = id.and_then(|id| id.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// 排入一条命令，返回任务的编号
+    pub fn add(&self, args: &[String], cwd: &Path) -> io::Result<u64> {
+        let mut id = 1;
+        for state in State::ALL {
+            id = self
+                .ids(state)?
+                .into_iter()
+                .fold(id, |id, used| id.max(used + 1));
+        }
+        // 同时排入的另一个任务可能抢先用了这个编号
+        loop {
+            let job = Job {
+                id,
+                args: args.to_vec(),
+                cwd: cwd.to_path_buf(),
+                error: None,
+            };
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.path(State::Pending, id));
+            match file {
+                Ok(mut file) => {
+                    file.write_all(job.to_json().as_bytes())?;
+                    return Ok(id);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => id += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// 所有任务及其状态，按编号排列
+    pub fn list(&self) -> io::Result<Vec<(State, Job)>> {
+        let mut jobs = Vec::new();
+        for state in State::ALL {
+            for id in self.ids(state)? {
+                // 列出的同时任务可能被认领或完成，文件已经不在了
+                match fs::read_to_string(self.path(state, id)) {
+                    Ok(text) => jobs.push((state, Job::from_json(id, &text)?)),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        jobs.sort_by_key(|(_, job)| job.id);
+        Ok(jobs)
+    }
+
+    /// 尝试取得执行队列的锁，另一个进程正在执行队列时返回 `None`；锁在返回的文件关闭时释放
+    pub fn lock(&self) -> io::Result<Option<File>> {
+        let file = File::create(self.dir.join("lock"))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(file)),
+            Err(fs::TryLockError::WouldBlock) => Ok(None),
+            Err(fs::TryLockError::Error(err)) => Err(err),
+        }
+    }
+
+    /// 把上一次执行时没有完成的任务放回 `pending`，返回任务数；只能在持有锁时调用
+    pub fn recover(&self) -> io::Result<usize> {
+        let ids = self.ids(State::Running)?;
+        for &id in &ids {
+            fs::rename(self.path(State::Running, id), self.path(State::Pending, id))?;
+        }
+        Ok(ids.len())
+    }
+
+    /// 认领编号最小的排队任务，队列为空时返回 `None`
+    pub fn claim(&self) -> io::Result<Option<Job>> {
+        for id in self.ids(State::Pending)? {
+            let running = self.path(State::Running, id);
+            match fs::rename(self.path(State::Pending, id), &running) {
+                Ok(()) => return Job::from_json(id, &fs::read_to_string(running)?).map(Some),
+                // 被另一个执行槽抢先认领了
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 记录认领的任务的结果，失败时保存原因
+    pub fn finish(&self, job: &Job, result: Result<(), String>) -> io::Result<()> {
+        let (state, error) = match result {
+            Ok(()) => (State::Done, None),
+            Err(error) => (State::Failed, Some(error)),
+        };
+        let job = Job {
+            error,
+            ..job.clone()
+        };
+        fs::write(self.path(state, job.id), job.to_json())?;
+        fs::remove_file(self.path(State::Running, job.id))
+    }
+}
+
+#[test]
+fn test_queue() {
+    let dir = std::env::temp_dir().join(format!("mandelbrot-queue-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let queue = Queue::open(&dir).unwrap();
+    let args = |s: &str| s.split(' ').map(str::to_string).collect::<Vec<_>>();
+    let cwd = Path::new("/work");
+    assert_eq!(queue.add(&args("render a.png"), cwd).unwrap(), 1);
+    assert_eq!(queue.add(&args("render b.png --zoom 2"), cwd).unwrap(), 2);
+    assert_eq!(queue.add(&args("cycle --frames 3"), cwd).unwrap(), 3);
+
+    let first = queue.claim().unwrap().unwrap();
+    assert_eq!(first.id, 1);
+    assert_eq!(first.args, args("render a.png"));
+    assert_eq!(first.cwd, cwd);
+    let second = queue.claim().unwrap().unwrap();
+    queue.finish(&first, Ok(())).unwrap();
+    queue
+        .finish(&second, Err("invalid palette".to_string()))
+        .unwrap();
+    let third = queue.claim().unwrap().unwrap();
+    assert_eq!(third.id, 3);
+    assert_eq!(queue.claim().unwrap(), None);
+
+    let states: Vec<(State, u64)> = queue
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|(state, job)| (state, job.id))
+        .collect();
+    assert_eq!(
+        states,
+        [(State::Done, 1), (State::Failed, 2), (State::Running, 3)]
+    );
+    assert_eq!(
+        queue.list().unwrap()[1].1.error.as_deref(),
+        Some("invalid palette")
+    );
+
+    // 编号不会重复使用已经完成的任务的编号
+    assert_eq!(queue.add(&args("render c.png"), cwd).unwrap(), 4);
+
+    // 第一个锁释放之前拿不到第二个锁；执行中断的任务重新排队
+    let lock = queue.lock().unwrap();
+    assert!(lock.is_some());
+    assert!(queue.lock().unwrap().is_none());
+    drop(lock);
+    assert!(queue.lock().unwrap().is_some());
+    assert_eq!(queue.recover().unwrap(), 1);
+    assert_eq!(queue.claim().unwrap().map(|job| job.id), Some(3));
+    fs::remove_dir_all(&dir).unwrap();
+}
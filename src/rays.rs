@@ -0,0 +1,291 @@
+//! 外射线和等势线
+//!
+//! 曼德博集和二次朱利亚集的外部可以用 Böttcher 坐标 `Φ` 共形地映射到单位圆盘的外部：
+//! `Φ` 的模的对数就是势函数 `G = lim ln|z_n| / 2^n`，辐角给出外角。`G` 为常数的曲线是
+//! 等势线，外角为常数的曲线是外射线。有理外角的射线落在集合边界上的特定点，是研究
+//! 集合组合结构的基本工具。
+//!
+//! 外射线用牛顿法从逃逸半径外向内逐点追踪：势函数每减半一次，`z` 需要多迭代一次，
+//! 外角随之加倍，每一层取 `SHARPNESS` 个点，以上一个点作为牛顿法的初值。外角以圈为
+//! 单位精确地保存为分数，加倍多少次都不损失精度。
+//!
+//! 只支持曼德博集（参数平面的射线）和朱利亚集（动力平面的射线）。
+
+use crate::orbit::polyline;
+use crate::{Fractal, PixelTransform};
+use num::Complex;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use std::f64::consts::TAU;
+
+/// 外射线出发的半径
+const RADIUS: f64 = 65536.0;
+
+/// 外射线每一层（势函数减半一次）取的点数
+const SHARPNESS: usize = 8;
+
+/// 每个点最多的牛顿迭代次数
+const NEWTON_STEPS: usize = 64;
+
+/// 计算势函数时的逃逸半径平方，越大越准确
+const POTENTIAL_BAILOUT: f64 = 1e20;
+
+/// 以圈为单位的外角，精确地保存为分数 `numerator / denominator`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Angle {
+    numerator: u64,
+    denominator: u64,
+}
+
+impl Angle {
+    /// 加倍 `k` 次之后的外角，以圈为单位，在 `[0, 1)` 之内
+    pub fn doubled(&self, k: usize) -> f64 {
+        let mut numerator = self.numerator as u128;
+        let denominator = self.denominator as u128;
+        for _ in 0..k {
+            numerator = numerator * 2 % denominator;
+        }
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// 把字符串 `s` 解析为外角：分数 `p/q` 或者 `[0, 1)` 之内的小数，都以圈为单位
+pub fn parse_angle(s: &str) -> Option<Angle> {
+    let s = s.trim();
+    let (numerator, denominator) = match s.split_once('/') {
+        Some((p, q)) => (p.trim().parse::<u64>().ok()?, q.trim().parse::<u64>().ok()?),
+        None => {
+            let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+            if !whole.trim_start_matches('0').is_empty() || fraction.len() > 18 {
+                return None;
+            }
+            let numerator = if fraction.is_empty() {
+                0
+            } else {
+                fraction.parse::<u64>().ok()?
+            };
+            (numerator, 10u64.pow(fraction.len() as u32))
+        }
+    };
+    (denominator > 0 && numerator < denominator).then_some(Angle {
+        numerator,
+        denominator,
+    })
+}
+
+/// 把逗号分隔的外角解析为列表
+pub fn parse_angles(s: &str) -> Option<Vec<Angle>> {
+    s.split(',').map(parse_angle).collect()
+}
+
+#[test]
+fn test_parse_angle() {
+    let third = parse_angle("1/3").unwrap();
+    assert_eq!(third.doubled(0), 1.0 / 3.0);
+    assert_eq!(third.doubled(1), 2.0 / 3.0);
+    assert_eq!(third.doubled(100), third.doubled(0));
+    assert_eq!(parse_angle("0.25"), parse_angle("25/100"));
+    assert_eq!(parse_angle("0.25").unwrap().doubled(1), 0.5);
+    assert_eq!(parse_angle("0").unwrap().doubled(3), 0.0);
+    assert_eq!(parse_angle(".5").unwrap().doubled(0), 0.5);
+    assert_eq!(parse_angle("1"), None);
+    assert_eq!(parse_angle("3/2"), None);
+    assert_eq!(parse_angle("1/0"), None);
+    assert_eq!(parse_angle("-0.5"), None);
+    assert_eq!(
+        parse_angles("0,1/3, 2/3").map(|angles| angles.len()),
+        Some(3)
+    );
+    assert_eq!(parse_angles("0,x"), None);
+}
+
+/// 分形是否支持外射线和等势线
+pub fn supports(fractal: &Fractal) -> bool {
+    matches!(fractal, Fractal::Mandelbrot | Fractal::Julia(_))
+}
+
+/// 点 `point` 的势函数 `G`，最多迭代 `limit` 次，不逃逸时返回零
+///
+/// 曼德博集的 `z_1 = c`，因此 `G(c) = lim ln|z_(n+1)| / 2^n`，远处接近 `ln|c|`；朱利亚集
+/// 的 `G(z) = lim ln|z_n| / 2^n`，远处接近 `ln|z|`。
+pub fn potential(fractal: &Fractal, point: Complex<f64>, limit: usize) -> f64 {
+    let (mut z, c) = fractal.orbit_start(point);
+    let shift = match fractal {
+        Fractal::Mandelbrot => 1,
+        _ => 0,
+    };
+    for n in 0..=limit {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > POTENTIAL_BAILOUT {
+            return norm_sqr.ln() / 2.0 / 2f64.powi(n as i32 - shift);
+        }
+        z = z * z + c;
+    }
+    0.0
+}
+
+/// 沿外角为 `angle` 的外射线向内追踪 `depth` 层，返回射线上依次排列的点
+///
+/// 第 `m` 个点的势函数为 `ln(RADIUS) / 2^(m / SHARPNESS)`。牛顿法不收敛时射线提前结束。
+/// 分形不支持外射线时返回空列表。
+pub fn external_ray(fractal: &Fractal, angle: Angle, depth: usize) -> Vec<Complex<f64>> {
+    if !supports(fractal) {
+        return Vec::new();
+    }
+    let mut point = Complex::from_polar(RADIUS, TAU * angle.doubled(0));
+    let mut ray = vec![point];
+    for m in 1..depth * SHARPNESS {
+        let k = m / SHARPNESS;
+        let radius = RADIUS.powf(0.5f64.powf((m % SHARPNESS) as f64 / SHARPNESS as f64));
+        let target = Complex::from_polar(radius, TAU * angle.doubled(k));
+        let Some(next) = newton(fractal, point, k, target) else {
+            break;
+        };
+        point = next;
+        ray.push(point);
+    }
+    ray
+}
+
+/// 用牛顿法求 `z_k` 等于 `target` 的点，曼德博集为 `z_(k+1)`，从 `guess` 出发
+fn newton(
+    fractal: &Fractal,
+    guess: Complex<f64>,
+    k: usize,
+    target: Complex<f64>,
+) -> Option<Complex<f64>> {
+    let one = Complex::new(1.0, 0.0);
+    let (steps, dc) = match fractal {
+        Fractal::Mandelbrot => (k + 1, one),
+        _ => (k, Complex::new(0.0, 0.0)),
+    };
+    let mut point = guess;
+    for _ in 0..NEWTON_STEPS {
+        let (mut z, c) = fractal.orbit_start(point);
+        let mut dz = one - dc;
+        for _ in 0..steps {
+            dz = z * dz * 2.0 + dc;
+            z = z * z + c;
+        }
+        let next = point - (z - target) / dz;
+        if !next.re.is_finite() || !next.im.is_finite() {
+            return None;
+        }
+        let converged = (next - point).norm() <= next.norm() * 1e-12;
+        point = next;
+        if converged {
+            return Some(point);
+        }
+    }
+    Some(point)
+}
+
+#[test]
+fn test_external_ray() {
+    // 朱利亚集 c = 0 是单位圆，Böttcher 坐标就是 z 本身，外射线是从原点出发的射线
+    let circle = Fractal::Julia(Complex::new(0.0, 0.0));
+    assert!((potential(&circle, Complex::new(3.0, 0.0), 100) - 3f64.ln()).abs() < 1e-12);
+    assert_eq!(potential(&circle, Complex::new(0.5, 0.0), 100), 0.0);
+    let ray = external_ray(&circle, parse_angle("1/4").unwrap(), 20);
+    assert_eq!(ray.len(), 20 * SHARPNESS);
+    for z in &ray {
+        assert!(z.re.abs() < 1e-9 * z.norm() && z.im > 1.0);
+    }
+    assert!((ray.last().unwrap().im - 1.0).abs() < 1e-3);
+
+    // 曼德博集外角为 1/2 的射线沿负实轴落在 -2，外角为 0 的射线沿正实轴落在 1/4
+    let mandelbrot = Fractal::Mandelbrot;
+    let ray = external_ray(&mandelbrot, parse_angle("1/2").unwrap(), 30);
+    assert!(ray.iter().all(|c| c.im.abs() < 1e-6 && c.re < -2.0));
+    assert!((ray.last().unwrap().re + 2.0).abs() < 1e-3);
+    let ray = external_ray(&mandelbrot, parse_angle("0").unwrap(), 30);
+    assert!(ray.iter().all(|c| c.im.abs() < 1e-6 && c.re > 0.25));
+    assert!(ray.last().unwrap().re < 0.35);
+
+    // 射线上的点的势函数与追踪时的目标一致
+    let ray = external_ray(&mandelbrot, parse_angle("1/7").unwrap(), 10);
+    for m in [SHARPNESS * 3, SHARPNESS * 9 + 5] {
+        let expected = RADIUS.ln() / 2f64.powf(m as f64 / SHARPNESS as f64);
+        let actual = potential(&mandelbrot, ray[m], 1000);
+        assert!(
+            (actual - expected).abs() < 1e-6 * expected,
+            "{} vs {}",
+            actual,
+            expected
+        );
+    }
+    assert!(external_ray(&Fractal::BurningShip, parse_angle("0").unwrap(), 10).is_empty());
+}
+
+/// 外射线和等势线叠加在 `bounds` 大小、按 `transform` 覆盖复平面的图像上时经过的像素
+///
+/// 返回逐行排列的掩码，每条外射线向内追踪 `depth` 层，`levels` 给出各条等势线的势函数值；
+/// 等势线经过势函数与之相比变号的相邻像素，计算势函数时最多迭代 `limit` 次。
+pub fn overlay(
+    fractal: &Fractal,
+    rays: &[Angle],
+    levels: &[f64],
+    depth: usize,
+    limit: usize,
+    bounds: (usize, usize),
+    transform: &PixelTransform<f64>,
+) -> Vec<bool> {
+    let mut mask = vec![false; bounds.0 * bounds.1];
+    if !levels.is_empty() {
+        let mut potentials = vec![0.0; bounds.0 * bounds.1];
+        potentials
+            .par_chunks_mut(bounds.0)
+            .enumerate()
+            .for_each(|(row, band)| {
+                for (column, value) in band.iter_mut().enumerate() {
+                    *value = potential(fractal, transform.point((column, row)), limit);
+                }
+            });
+        let crosses = |a: f64, b: f64| levels.iter().any(|&level| (a < level) != (b < level));
+        for row in 0..bounds.1 {
+            for column in 0..bounds.0 {
+                let index = row * bounds.0 + column;
+                let here = potentials[index];
+                if (column + 1 < bounds.0 && crosses(here, potentials[index + 1]))
+                    || (row + 1 < bounds.1 && crosses(here, potentials[index + bounds.0]))
+                {
+                    mask[index] = true;
+                }
+            }
+        }
+    }
+    for &angle in rays {
+        let positions: Vec<(f64, f64)> = external_ray(fractal, angle, depth)
+            .into_iter()
+            .map(|point| transform.locate(point))
+            .collect();
+        polyline(&positions, bounds, |column, row| {
+            mask[row * bounds.0 + column] = true;
+        });
+    }
+    mask
+}
+
+#[test]
+fn test_overlay() {
+    // 11×11 的图像覆盖 [-2.5, 3]×[-3, 2.5]，像素 (5, 5) 对应原点
+    let bounds = (11, 11);
+    let transform =
+        PixelTransform::from_corners(bounds, Complex::new(-2.5, 2.5), Complex::new(3.0, -3.0));
+    let circle = Fractal::Julia(Complex::new(0.0, 0.0));
+    // 外角为 0 的射线从中心向右，落在单位圆上
+    let mask = overlay(
+        &circle,
+        &[parse_angle("0").unwrap()],
+        &[],
+        30,
+        100,
+        bounds,
+        &transform,
+    );
+    let row: Vec<bool> = mask[5 * 11..6 * 11].to_vec();
+    assert_eq!(row, [&[false; 7][..], &[true; 4]].concat());
+    assert_eq!(mask.iter().filter(|&&set| set).count(), 4);
+    // 势函数为 ln 1.75 的等势线是半径为 1.75 的圆，穿过第 5 行的第 1、2 列和第 8、9 列之间
+    let mask = overlay(&circle, &[], &[1.75f64.ln()], 30, 100, bounds, &transform);
+    assert!(mask[5 * 11 + 1] && mask[5 * 11 + 8] && !mask[5 * 11 + 5] && !mask[5 * 11 + 10]);
+}
@@ -0,0 +1,160 @@
+//! 迭代使用的浮点类型
+//!
+//! 逃逸时间的迭代和像素坐标的映射对浮点类型 `T: Real` 是泛型的。默认使用 `f64`；
+//! `--precision single`（或 `f32`）改用 `f32`，缩放倍数不大时画面几乎没有差别，启用
+//! `simd` 特性时每次可以同时迭代两倍的点。超出 `f64` 分辨率的深度缩放另由 `extended`、
+//! `precise` 和 `perturbation` 模块处理，`Precision::arithmetic` 按缩放深度在它们之间选择。
+
+use num::{Complex, Float, FromPrimitive};
+use std::fmt::Debug;
+
+/// 可以用来迭代的浮点类型
+pub trait Real: Float + FromPrimitive + Debug + Send + Sync + 'static {
+    /// 把 `f64` 常数转换为该类型，可能损失精度
+    fn of(value: f64) -> Self {
+        Self::from_f64(value).expect("every f64 converts to a float")
+    }
+
+    /// 转换为 `f64`
+    fn f64(self) -> f64 {
+        self.to_f64().expect("every float converts to f64")
+    }
+}
+
+impl Real for f32 {}
+impl Real for f64 {}
+
+/// 把 `Complex<T>` 转换为 `Complex<f64>`
+pub fn widen<T: Real>(z: Complex<T>) -> Complex<f64> {
+    Complex {
+        re: z.re.f64(),
+        im: z.im.f64(),
+    }
+}
+
+/// 把 `Complex<f64>` 转换为 `Complex<T>`，可能损失精度
+pub fn narrow<T: Real>(z: Complex<f64>) -> Complex<T> {
+    Complex {
+        re: T::of(z.re),
+        im: T::of(z.im),
+    }
+}
+
+#[test]
+fn test_widen_narrow() {
+    let z = Complex { re: 0.1, im: -2.5 };
+    assert_eq!(narrow::<f64>(z), z);
+    let single = narrow::<f32>(z);
+    assert_eq!(
+        single,
+        Complex {
+            re: 0.1f32,
+            im: -2.5
+        }
+    );
+    assert_eq!(widen(single).im, -2.5);
+    assert!((widen(single).re - 0.1).abs() < 1e-8);
+}
+
+/// 判断以 `center` 为中心、像素间距为 `spacing` 的视图能否用 `T` 分辨
+///
+/// 中心坐标附近相邻两个 `T` 值之差约为 `|center| * EPSILON`，当像素间距小于它的
+/// 256 倍时，像素内部的细节已经无法分辨。
+pub fn resolves<T: Real>(center: Complex<f64>, spacing: f64) -> bool {
+    let magnitude = center.re.abs().max(center.im.abs()).max(1.0);
+    spacing >= magnitude * T::epsilon().f64() * 256.0
+}
+
+#[test]
+fn test_resolves() {
+    let center = Complex { re: -0.75, im: 0.1 };
+    assert!(resolves::<f32>(center, 4.0 / 1000.0));
+    assert!(!resolves::<f32>(center, 1e-6));
+    assert!(resolves::<f64>(center, 1e-6));
+    assert!(!resolves::<f64>(center, 1e-16));
+}
+
+/// 普通渲染使用的浮点精度
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    /// `f32`，速度更快，只适合较小的缩放倍数
+    Single,
+    /// `f64`
+    Double,
+    /// 在 `f64` 的分辨率之内与 `Double` 相同，超出之后改用 `extended::DoubleDouble`
+    /// 而不是微扰或任意精度，直到双倍精度也无法分辨
+    Extended,
+    /// 按缩放深度依次选用 `f32`、`f64`、双倍精度和微扰（或任意精度）中第一个能分辨视图的
+    Auto,
+}
+
+/// 渲染一个视图实际使用的迭代方式，由 `Precision::arithmetic` 选出
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arithmetic {
+    /// `f32`
+    Single,
+    /// `f64`
+    Double,
+    /// `extended::DoubleDouble`
+    Extended,
+    /// 微扰或任意精度，参见 `perturbation` 和 `precise`
+    Deep,
+}
+
+impl Precision {
+    /// 以 `center` 为中心、像素间距为 `spacing` 的视图在该精度设置下使用的迭代方式
+    ///
+    /// 所选的类型分辨不了视图时逐级提高精度：`f32` 退回 `f64`，`f64` 之后只有
+    /// `Extended` 和 `Auto` 会先尝试双倍精度，最后都交给深度缩放的渲染方式。
+    pub fn arithmetic(self, center: Complex<f64>, spacing: f64) -> Arithmetic {
+        let single = matches!(self, Precision::Single | Precision::Auto);
+        let extended = matches!(self, Precision::Extended | Precision::Auto);
+        if single && resolves::<f32>(center, spacing) {
+            Arithmetic::Single
+        } else if resolves::<f64>(center, spacing) {
+            Arithmetic::Double
+        } else if extended && crate::extended::resolves(center, spacing) {
+            Arithmetic::Extended
+        } else {
+            Arithmetic::Deep
+        }
+    }
+}
+
+#[test]
+fn test_arithmetic() {
+    let center = Complex { re: -0.75, im: 0.1 };
+    let arithmetic = |precision: Precision, spacing| precision.arithmetic(center, spacing);
+    assert_eq!(arithmetic(Precision::Auto, 4e-3), Arithmetic::Single);
+    assert_eq!(arithmetic(Precision::Auto, 1e-6), Arithmetic::Double);
+    assert_eq!(arithmetic(Precision::Auto, 1e-20), Arithmetic::Extended);
+    assert_eq!(arithmetic(Precision::Auto, 1e-40), Arithmetic::Deep);
+    assert_eq!(arithmetic(Precision::Single, 1e-20), Arithmetic::Deep);
+    assert_eq!(arithmetic(Precision::Double, 4e-3), Arithmetic::Double);
+    assert_eq!(arithmetic(Precision::Double, 1e-20), Arithmetic::Deep);
+    assert_eq!(arithmetic(Precision::Extended, 4e-3), Arithmetic::Double);
+    assert_eq!(arithmetic(Precision::Extended, 1e-20), Arithmetic::Extended);
+}
+
+/// 把 `single`（`f32`）、`double`（`f64`）、`double-double`（`dd`）或 `auto` 解析为精度
+pub fn parse_precision(s: &str) -> Option<Precision> {
+    match s {
+        "single" | "f32" => Some(Precision::Single),
+        "double" | "f64" => Some(Precision::Double),
+        "double-double" | "dd" => Some(Precision::Extended),
+        "auto" => Some(Precision::Auto),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_precision() {
+    assert_eq!(parse_precision("single"), Some(Precision::Single));
+    assert_eq!(parse_precision("double"), Some(Precision::Double));
+    assert_eq!(parse_precision("f32"), Some(Precision::Single));
+    assert_eq!(parse_precision("f64"), Some(Precision::Double));
+    assert_eq!(parse_precision("double-double"), Some(Precision::Extended));
+    assert_eq!(parse_precision("dd"), Some(Precision::Extended));
+    assert_eq!(parse_precision("auto"), Some(Precision::Auto));
+    assert_eq!(parse_precision("half"), None);
+}
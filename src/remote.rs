@@ -0,0 +1,572 @@
+//! 分布式渲染
+//!
+//! `worker --listen HOST:PORT` 启动工作进程；`render` 或 `animate` 给出
+//! `--workers HOST:PORT,...` 时成为协调进程，把图像的条带或动画的帧通过 TCP 分发给各个
+//! 工作进程，收到逃逸值之后再拼装、着色和写出。每个工作进程内部照常并行渲染。
+//!
+//! 协议很简单：协调进程每次发送一行 JSON 描述的任务，工作进程回复 `OK N` 一行，后接
+//! `N` 个小端序的 `u32` 逃逸值；任务无法完成时回复 `ERR 原因` 一行。同一个连接上可以
+//! 依次发送任意多个任务。某个工作进程断开时，它手上的任务交给其他工作进程重做。
+
+use crate::error::MandelbrotError;
+use crate::formula::{parse_formula, Formula};
+use crate::hybrid::{parse_hybrid, Hybrid};
+use crate::serve::accept;
+use crate::trap::{Trap, TrapShape};
+use crate::{Coloring, Fractal};
+use num::Complex;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// 工作进程的默认端口
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// 工作进程接受的一个任务最多的像素数，对应 1 GiB 的逃逸值
+pub const MAX_JOB_PIXELS: usize = 1 << 28;
+
+/// 工作进程上连接的读写超时
+///
+/// 协调进程在队列暂时为空时会让连接空闲，直到别的工作进程完成或断开，所以这个超时要比
+/// 单个任务的渲染时间长得多；超时断开的连接由协调进程当作断开的工作进程处理。
+const WORKER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// 解析逗号分隔的工作进程地址列表，省略端口时使用 `DEFAULT_PORT`
+pub fn parse_workers(s: &str) -> Option<Vec<String>> {
+    s.split(',')
+        .map(|address| {
+            let address = address.trim();
+            if address.is_empty() {
+                None
+            } else if address
+                .rsplit_once(':')
+                .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+            {
+                Some(address.to_string())
+            } else {
+                Some(format!("{}:{}", address, DEFAULT_PORT))
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_workers() {
+    assert_eq!(
+        parse_workers("alpha,10.0.0.2:9000"),
+        Some(vec!["alpha:7878".to_string(), "10.0.0.2:9000".to_string()])
+    );
+    assert_eq!(parse_workers("alpha,"), None);
+}
+
+pub fn complex_value(c: Complex<f64>) -> Value {
+    json!([c.re, c.im])
+}
+
+pub fn complex_from(value: &Value) -> Option<Complex<f64>> {
+    match value.as_array()?.as_slice() {
+        [re, im] => Some(Complex::new(re.as_f64()?, im.as_f64()?)),
+        _ => None,
+    }
+}
+
+/// 任务中表示分形的 JSON
+pub fn fractal_value(fractal: Fractal) -> Value {
+    match fractal {
+        Fractal::Mandelbrot => json!({"kind": "mandelbrot"}),
+        Fractal::Julia(c) => json!({"kind": "julia", "c": complex_value(c)}),
+        Fractal::BurningShip => json!({"kind": "burning-ship"}),
+        Fractal::Tricorn => json!({"kind": "tricorn"}),
+        Fractal::Multibrot(power) => json!({"kind": "multibrot", "power": power}),
+        Fractal::Formula(formula) => json!({"kind": "formula", "source": formula.source()}),
+        Fractal::Phoenix { c, p } => {
+            json!({"kind": "phoenix", "c": c.map(complex_value), "p": complex_value(p)})
+        }
+        Fractal::Lambda(lambda) => json!({"kind": "lambda", "lambda": lambda.map(complex_value)}),
+        Fractal::Hybrid { hybrid, c } => {
+            json!({"kind": "hybrid", "pattern": hybrid.source(), "c": c.map(complex_value)})
+        }
+    }
+}
+
+/// 工作进程解析过的公式，同一个公式只编译、泄漏一次
+static FORMULAS: Mutex<Vec<&'static Formula>> = Mutex::new(Vec::new());
+
+/// 工作进程解析过的混合分形模式，与公式一样只解析、泄漏一次
+static HYBRIDS: Mutex<Vec<&'static Hybrid>> = Mutex::new(Vec::new());
+
+/// 从 `fractal_value` 的结果还原分形
+pub fn fractal_from(value: &Value) -> Result<Fractal, String> {
+    let invalid = || format!("invalid fractal {}", value);
+    // 可以省略的常数为 null 时是 None，给出时必须是合法的复数
+    let optional = |key: &str| match &value[key] {
+        Value::Null => Ok(None),
+        constant => complex_from(constant).map(Some).ok_or_else(invalid),
+    };
+    let fractal = match value["kind"].as_str().ok_or_else(invalid)? {
+        "mandelbrot" => Fractal::Mandelbrot,
+        "julia" => Fractal::Julia(complex_from(&value["c"]).ok_or_else(invalid)?),
+        "burning-ship" => Fractal::BurningShip,
+        "tricorn" => Fractal::Tricorn,
+        "multibrot" => Fractal::Multibrot(value["power"].as_f64().ok_or_else(invalid)?),
+        "phoenix" => Fractal::Phoenix {
+            c: optional("c")?,
+            p: complex_from(&value["p"]).ok_or_else(invalid)?,
+        },
+        "lambda" => Fractal::Lambda(optional("lambda")?),
+        "formula" => {
+            let source = value["source"].as_str().ok_or_else(invalid)?;
+            let mut formulas = FORMULAS.lock().unwrap();
+            match formulas.iter().find(|formula| formula.source() == source) {
+                Some(formula) => Fractal::Formula(formula),
+                None => {
+                    let formula: &'static Formula = Box::leak(Box::new(parse_formula(source)?));
+                    formulas.push(formula);
+                    Fractal::Formula(formula)
+                }
+            }
+        }
+        "hybrid" => {
+            let pattern = value["pattern"].as_str().ok_or_else(invalid)?;
+            let mut hybrids = HYBRIDS.lock().unwrap();
+            let hybrid = match hybrids.iter().find(|hybrid| hybrid.source() == pattern) {
+                Some(hybrid) => hybrid,
+                None => {
+                    let hybrid: &'static Hybrid = Box::leak(Box::new(parse_hybrid(pattern)?));
+                    hybrids.push(hybrid);
+                    hybrid
+                }
+            };
+            Fractal::Hybrid {
+                hybrid,
+                c: optional("c")?,
+            }
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(fractal)
+}
+
+/// 任务中表示着色方式的 JSON
+pub fn coloring_value(coloring: Coloring) -> Value {
+    match coloring {
+        Coloring::EscapeTime => json!({"kind": "escape-time"}),
+        Coloring::Smooth => json!({"kind": "smooth"}),
+        Coloring::Distance => json!({"kind": "distance"}),
+        Coloring::OrbitTrap(trap) => {
+            let shape = match trap.shape {
+                TrapShape::Point => "point",
+                TrapShape::Cross => "cross",
+                TrapShape::Circle => "circle",
+                TrapShape::Line => "line",
+            };
+            json!({
+                "kind": "orbit-trap",
+                "shape": shape,
+                "center": complex_value(trap.center),
+                "size": trap.size,
+            })
+        }
+        Coloring::TriangleInequality => json!({"kind": "tia"}),
+        Coloring::AtomDomain => json!({"kind": "atom-domain"}),
+    }
+}
+
+/// 从 `coloring_value` 的结果还原着色方式
+pub fn coloring_from(value: &Value) -> Result<Coloring, String> {
+    let invalid = || format!("invalid coloring {}", value);
+    let coloring = match value["kind"].as_str().ok_or_else(invalid)? {
+        "escape-time" => Coloring::EscapeTime,
+        "smooth" => Coloring::Smooth,
+        "distance" => Coloring::Distance,
+        "tia" => Coloring::TriangleInequality,
+        "atom-domain" => Coloring::AtomDomain,
+        "orbit-trap" => {
+            let shape = match value["shape"].as_str().ok_or_else(invalid)? {
+                "point" => TrapShape::Point,
+                "cross" => TrapShape::Cross,
+                "circle" => TrapShape::Circle,
+                "line" => TrapShape::Line,
+                _ => return Err(invalid()),
+            };
+            Coloring::OrbitTrap(Trap {
+                shape,
+                center: complex_from(&value["center"]).ok_or_else(invalid)?,
+                size: value["size"].as_f64().ok_or_else(invalid)?,
+            })
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(coloring)
+}
+
+#[test]
+fn test_fractal_and_coloring_values() {
+    // 经过文本往返之后完全相同，包括浮点数的每一位
+    let roundtrip = |value: Value| -> Value { serde_json::from_str(&value.to_string()).unwrap() };
+    for fractal in [
+        Fractal::Mandelbrot,
+        Fractal::Julia(Complex::new(-0.7269, 0.1889 + 1e-17)),
+        Fractal::BurningShip,
+        Fractal::Tricorn,
+        Fractal::Multibrot(3.3),
+        Fractal::Phoenix {
+            c: Some(Complex::new(0.5667, 0.0)),
+            p: Complex::new(-0.5, 0.1),
+        },
+        Fractal::Phoenix {
+            c: None,
+            p: Complex::new(-0.5, 0.0),
+        },
+        Fractal::Lambda(Some(Complex::new(1.0, 0.1))),
+        Fractal::Lambda(None),
+    ] {
+        assert_eq!(
+            fractal_from(&roundtrip(fractal_value(fractal))),
+            Ok(fractal)
+        );
+    }
+    let formula: &'static Formula = Box::leak(Box::new(parse_formula("z^2 + c*z + c").unwrap()));
+    let Ok(Fractal::Formula(decoded)) =
+        fractal_from(&roundtrip(fractal_value(Fractal::Formula(formula))))
+    else {
+        panic!("expected a formula");
+    };
+    assert_eq!(decoded.source(), formula.source());
+    let hybrid: &'static Hybrid =
+        Box::leak(Box::new(parse_hybrid("mandelbrot*2,tricorn").unwrap()));
+    for c in [None, Some(Complex::new(-0.4, 0.6))] {
+        let fractal = Fractal::Hybrid { hybrid, c };
+        assert_eq!(
+            fractal_from(&roundtrip(fractal_value(fractal))),
+            Ok(fractal)
+        );
+    }
+
+    let trap = Trap {
+        shape: TrapShape::Line,
+        center: Complex::new(0.1, -0.2),
+        size: 1.0f64.to_radians(),
+    };
+    for coloring in [
+        Coloring::EscapeTime,
+        Coloring::Smooth,
+        Coloring::Distance,
+        Coloring::OrbitTrap(trap),
+        Coloring::TriangleInequality,
+        Coloring::AtomDomain,
+    ] {
+        assert_eq!(
+            coloring_from(&roundtrip(coloring_value(coloring))),
+            Ok(coloring)
+        );
+    }
+    assert!(fractal_from(&json!({"kind": "julia"})).is_err());
+    assert!(fractal_from(&json!({"kind": "lambda", "lambda": "1,0"})).is_err());
+    assert!(coloring_from(&json!({"kind": "plasma"})).is_err());
+}
+
+/// 发送一个任务
+pub fn write_job(output: &mut impl Write, job: &Value) -> io::Result<()> {
+    writeln!(output, "{}", job)?;
+    output.flush()
+}
+
+/// 读取下一个任务，连接关闭时返回 `None`
+pub fn read_job(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// 发送任务的结果：逃逸值或者失败的原因
+pub fn write_result(output: &mut impl Write, result: Result<&[u32], &str>) -> io::Result<()> {
+    match result {
+        Ok(values) => {
+            writeln!(output, "OK {}", values.len())?;
+            let bytes: Vec<u8> = values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            output.write_all(&bytes)?;
+        }
+        Err(message) => writeln!(output, "ERR {}", message.replace('\n', " "))?,
+    }
+    output.flush()
+}
+
+/// 读取应当有 `expected` 个逃逸值的任务结果，外层的错误表示连接出了问题，内层的错误是工作
+/// 进程给出的失败原因
+///
+/// 个数不符时不读取其后的数据，直接作为失败原因返回，不按对方给出的个数分配内存。
+pub fn read_result(
+    input: &mut impl BufRead,
+    expected: usize,
+) -> io::Result<Result<Vec<u32>, String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let line = line.trim_end();
+    if let Some(message) = line.strip_prefix("ERR ") {
+        return Ok(Err(message.to_string()));
+    }
+    let count: usize = line
+        .strip_prefix("OK ")
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("bad reply `{}`", line))
+        })?;
+    if count != expected {
+        return Ok(Err(format!(
+            "returned {} values, expected {}",
+            count, expected
+        )));
+    }
+    let mut bytes = vec![0; count * 4];
+    input.read_exact(&mut bytes)?;
+    Ok(Ok(bytes
+        .chunks_exact(4)
+        .map(|value| u32::from_le_bytes(value.try_into().unwrap()))
+        .collect()))
+}
+
+#[test]
+fn test_messages() {
+    let mut buffer = Vec::new();
+    write_job(&mut buffer, &json!({"rows": [0, 2]})).unwrap();
+    write_result(&mut buffer, Ok(&[1, u32::MAX, 0])).unwrap();
+    write_result(&mut buffer, Err("bad\nview")).unwrap();
+    let mut input = &buffer[..];
+    assert_eq!(read_job(&mut input).unwrap(), Some(json!({"rows": [0, 2]})));
+    assert_eq!(
+        read_result(&mut input, 3).unwrap(),
+        Ok(vec![1, u32::MAX, 0])
+    );
+    assert_eq!(
+        read_result(&mut input, 3).unwrap(),
+        Err("bad view".to_string())
+    );
+    assert!(read_result(&mut input, 3).is_err());
+    assert_eq!(read_job(&mut input).unwrap(), None);
+
+    // 个数不符时不按对方给出的个数分配
+    let mut input = &b"OK 18446744073709551615\n"[..];
+    assert_eq!(
+        read_result(&mut input, 3).unwrap(),
+        Err("returned 18446744073709551615 values, expected 3".to_string())
+    );
+}
+
+/// 工作进程：在 `listener` 上接受连接，每个连接用一个线程依次处理其中的任务，直到进程结束
+///
+/// `handle` 把一个任务渲染为逃逸值。单个连接出错或超过 `WORKER_TIMEOUT` 没有读写只影响
+/// 这个连接，接受连接出错时打印错误后继续。
+pub fn serve(
+    listener: TcpListener,
+    handle: impl Fn(&Value) -> Result<Vec<u32>, String> + Sync,
+) -> io::Result<()> {
+    let handle = &handle;
+    thread::scope(|scope| {
+        accept(&listener, WORKER_TIMEOUT, |stream| {
+            scope.spawn(move || -> io::Result<()> {
+                let mut input = BufReader::new(stream.try_clone()?);
+                let mut output = BufWriter::new(stream);
+                while let Some(job) = read_job(&mut input)? {
+                    match handle(&job) {
+                        Ok(values) => write_result(&mut output, Ok(&values))?,
+                        Err(message) => write_result(&mut output, Err(&message))?,
+                    }
+                }
+                Ok(())
+            });
+        });
+        Ok(())
+    })
+}
+
+/// 等待分发的任务
+struct Queue {
+    /// 还没有交给工作进程的任务的下标
+    pending: VecDeque<usize>,
+    /// 正在某个工作进程上执行的任务数
+    running: usize,
+    /// 协调进程放弃了整个分发
+    aborted: bool,
+}
+
+/// 工作进程线程交给协调进程的消息
+enum Message {
+    /// 第一个值是任务的下标
+    Done(usize, Vec<u32>),
+    /// 工作进程连接失败或断开，它的任务已经放回队列
+    Lost(MandelbrotError),
+    /// 任务本身无法完成，整个分发失败
+    Failed(MandelbrotError),
+}
+
+/// 把 `jobs` 分发给 `workers` 中的各个工作进程，每完成一个任务就在当前线程中用它的下标
+/// 和结果调用 `on_result`，完成的顺序不确定
+///
+/// `jobs` 中的每一项是任务和它应当返回的逃逸值个数，个数不符的结果视为任务失败。
+///
+/// 某个工作进程连接失败或中途断开时，其余的工作进程继续完成全部任务；全部断开时返回
+/// 最后一个连接错误。工作进程报告任务失败或 `on_result` 返回错误时停止分发。
+pub fn dispatch(
+    workers: &[String],
+    jobs: &[(Value, usize)],
+    mut on_result: impl FnMut(usize, Vec<u32>) -> Result<(), MandelbrotError>,
+) -> Result<(), MandelbrotError> {
+    let queue = Mutex::new(Queue {
+        pending: (0..jobs.len()).collect(),
+        running: 0,
+        aborted: false,
+    });
+    let changed = Condvar::new();
+    let (sender, receiver) = mpsc::channel();
+    let error = |address: &str, source: io::Error| MandelbrotError::Worker {
+        address: address.to_string(),
+        source,
+    };
+    thread::scope(|scope| {
+        for address in workers {
+            let (queue, changed, sender) = (&queue, &changed, sender.clone());
+            scope.spawn(move || {
+                let connection = TcpStream::connect(address.as_str()).and_then(|stream| {
+                    Ok((BufReader::new(stream.try_clone()?), BufWriter::new(stream)))
+                });
+                let (mut input, mut output) = match connection {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        let _ = sender.send(Message::Lost(error(address, err)));
+                        return;
+                    }
+                };
+                loop {
+                    let index = {
+                        let mut queue = queue.lock().unwrap();
+                        loop {
+                            if queue.aborted {
+                                return;
+                            }
+                            if let Some(index) = queue.pending.pop_front() {
+                                queue.running += 1;
+                                break index;
+                            }
+                            // 队列空了但还有任务在运行，它们可能因为断开而被放回队列
+                            if queue.running == 0 {
+                                return;
+                            }
+                            queue = changed.wait(queue).unwrap();
+                        }
+                    };
+                    let (job, len) = &jobs[index];
+                    let result =
+                        write_job(&mut output, job).and_then(|()| read_result(&mut input, *len));
+                    let mut queue = queue.lock().unwrap();
+                    queue.running -= 1;
+                    let (message, connected) = match result {
+                        Ok(Ok(values)) => (Message::Done(index, values), true),
+                        Ok(Err(reason)) => (
+                            Message::Failed(error(address, io::Error::other(reason))),
+                            false,
+                        ),
+                        Err(err) => {
+                            queue.pending.push_front(index);
+                            (Message::Lost(error(address, err)), false)
+                        }
+                    };
+                    changed.notify_all();
+                    drop(queue);
+                    if sender.send(message).is_err() || !connected {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut completed = 0;
+        let mut lost = None;
+        let mut outcome = Ok(());
+        for message in &receiver {
+            let result = match message {
+                Message::Done(index, values) => {
+                    completed += 1;
+                    on_result(index, values)
+                }
+                Message::Lost(err) => {
+                    lost = Some(err);
+                    Ok(())
+                }
+                Message::Failed(err) => Err(err),
+            };
+            if let Err(err) = result {
+                outcome = Err(err);
+                queue.lock().unwrap().aborted = true;
+                changed.notify_all();
+                break;
+            }
+        }
+        outcome?;
+        if completed < jobs.len() {
+            return Err(lost.unwrap_or_else(|| {
+                MandelbrotError::InvalidArgument("no workers were given".to_string())
+            }));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_dispatch() {
+    // 任务是一个数，工作进程返回它的平方；第二个工作进程在完成一个任务后断开
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let flaky = TcpListener::bind("127.0.0.1:0").unwrap();
+    let workers = vec![
+        listener.local_addr().unwrap().to_string(),
+        flaky.local_addr().unwrap().to_string(),
+        // 没有工作进程在监听的地址
+        "127.0.0.1:1".to_string(),
+    ];
+    thread::spawn(move || {
+        serve(listener, |job| {
+            let n = job.as_u64().ok_or("not a number")? as u32;
+            Ok(vec![n * n])
+        })
+    });
+    thread::spawn(move || {
+        let (stream, _) = flaky.accept().unwrap();
+        let mut input = BufReader::new(stream.try_clone().unwrap());
+        let mut output = BufWriter::new(stream);
+        let job = read_job(&mut input).unwrap().unwrap();
+        let n = job.as_u64().unwrap() as u32;
+        write_result(&mut output, Ok(&[n * n])).unwrap();
+        read_job(&mut input).unwrap();
+    });
+
+    let jobs: Vec<(Value, usize)> = (0..20).map(|n| (json!(n), 1)).collect();
+    let mut results = vec![None; jobs.len()];
+    dispatch(&workers, &jobs, |index, values| {
+        results[index] = Some(values[0]);
+        Ok(())
+    })
+    .unwrap();
+    let expected: Vec<Option<u32>> = (0..20).map(|n| Some(n * n)).collect();
+    assert_eq!(results, expected);
+
+    // 任务失败时整个分发失败
+    let error = dispatch(&workers[..1], &[(json!("x"), 1)], |_, _| Ok(())).unwrap_err();
+    assert!(error.to_string().contains("not a number"), "{}", error);
+    let error = dispatch(&workers[..1], &[(json!(3), 2)], |_, _| Ok(())).unwrap_err();
+    assert!(error.to_string().contains("expected 2"), "{}", error);
+    assert!(dispatch(&workers[2..], &jobs, |_, _| Ok(())).is_err());
+}
@@ -0,0 +1,391 @@
+//! 瓦片地图服务器
+//!
+//! 以 XYZ 瓦片的形式通过 HTTP 提供分形：`/tiles/{z}/{x}/{y}.png` 是第 `z` 级第 `x` 列、
+//! 第 `y` 行的 256×256 瓦片，`/` 是一个用 Leaflet 浏览瓦片的网页。第 0 级只有一块
+//! 瓦片，覆盖以 `-0.5+0i` 为中心、边长为 4 的正方形，每深一级瓦片的边长减半。瓦片在
+//! 第一次被请求时渲染，最近使用过的瓦片保存在 LRU 缓存中。
+//!
+//! 带查询参数 `?preview` 时只做渐进式渲染的第一遍（见 `progressive`），每 8×8 个像素只
+//! 计算一个，几乎立即返回一块粗略的瓦片。网页先铺一层这样的预览瓦片，完整的瓦片渲染好
+//! 之后覆盖在上面。预览瓦片不进入缓存。
+//!
+//! 每块瓦片单独着色，用直方图均衡着色时相邻瓦片的颜色可能不连续。
+
+use crate::progress::Progress;
+use crate::progressive::Progressive;
+use crate::{encode_image, render_parallel, Coloring, Fractal, ImageFormat, PixelTransform};
+use num::Complex;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Cursor, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// 瓦片的像素边长
+pub const TILE_SIZE: usize = 256;
+
+/// 最深的缩放级别，再深一级瓦片的像素间距就接近 `f64` 的分辨率
+pub const MAX_ZOOM: u32 = 32;
+
+/// 第 0 级瓦片覆盖的正方形的中心
+const ORIGIN: Complex<f64> = Complex { re: -0.5, im: 0.0 };
+
+/// 第 0 级瓦片的边长
+const BASE_SPAN: f64 = 4.0;
+
+/// 渲染瓦片时的分块尺寸
+const BLOCK: (usize, usize) = (64, 64);
+
+/// HTTP 连接的读写超时，停住不动的客户端不会一直占着处理它的线程
+pub(crate) const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 浏览瓦片的网页
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>mandelbrot</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map { height: 100%; margin: 0; background: #000; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+var bounds = [[-256, 0], [0, 256]];
+var map = L.map('map', { crs: L.CRS.Simple, minZoom: 0, maxZoom: MAX_ZOOM, maxBounds: bounds });
+var options = { noWrap: true, bounds: bounds, maxZoom: MAX_ZOOM };
+L.tileLayer('/tiles/{z}/{x}/{y}.png?preview', options).addTo(map);
+L.tileLayer('/tiles/{z}/{x}/{y}.png', options).addTo(map);
+map.setView([-128, 128], 1);
+</script>
+</body>
+</html>
+"#;
+
+/// 一块瓦片的缩放级别、列和行
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub z: u32,
+    pub x: u64,
+    pub y: u64,
+}
+
+impl Tile {
+    /// 瓦片的左上角和右下角在复平面中对应的点
+    pub fn corners(&self) -> (Complex<f64>, Complex<f64>) {
+        let span = BASE_SPAN / (1u64 << self.z) as f64;
+        let upper_left = Complex {
+            re: ORIGIN.re - BASE_SPAN / 2.0 + self.x as f64 * span,
+            im: ORIGIN.im + BASE_SPAN / 2.0 - self.y as f64 * span,
+        };
+        let lower_right = Complex {
+            re: upper_left.re + span,
+            im: upper_left.im - span,
+        };
+        (upper_left, lower_right)
+    }
+}
+
+/// 把形如 `/tiles/3/5/2.png` 的路径解析为瓦片，列和行必须在该级别的范围内
+pub fn parse_tile_path(path: &str) -> Option<Tile> {
+    let rest = path.strip_prefix("/tiles/")?.strip_suffix(".png")?;
+    let mut parts = rest.split('/');
+    let z: u32 = parts.next()?.parse().ok()?;
+    let x: u64 = parts.next()?.parse().ok()?;
+    let y: u64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || z > MAX_ZOOM || x >> z != 0 || y >> z != 0 {
+        return None;
+    }
+    Some(Tile { z, x, y })
+}
+
+#[test]
+fn test_parse_tile_path() {
+    assert_eq!(
+        parse_tile_path("/tiles/3/5/2.png"),
+        Some(Tile { z: 3, x: 5, y: 2 })
+    );
+    assert_eq!(
+        parse_tile_path("/tiles/0/0/0.png"),
+        Some(Tile { z: 0, x: 0, y: 0 })
+    );
+    assert_eq!(parse_tile_path("/tiles/3/8/2.png"), None);
+    assert_eq!(parse_tile_path("/tiles/0/0/1.png"), None);
+    assert_eq!(parse_tile_path("/tiles/33/0/0.png"), None);
+    assert_eq!(parse_tile_path("/tiles/3/5/2"), None);
+    assert_eq!(parse_tile_path("/tiles/3/5/2/1.png"), None);
+    assert_eq!(parse_tile_path("/tiles/3/-1/2.png"), None);
+    assert_eq!(parse_tile_path("/"), None);
+}
+
+#[test]
+fn test_tile_corners() {
+    let (upper_left, lower_right) = Tile { z: 0, x: 0, y: 0 }.corners();
+    assert_eq!(upper_left, Complex { re: -2.5, im: 2.0 });
+    assert_eq!(lower_right, Complex { re: 1.5, im: -2.0 });
+    // 第 2 级每块瓦片边长为 1，第 1 列第 2 行从 (-1.5, 0) 开始
+    let (upper_left, lower_right) = Tile { z: 2, x: 1, y: 2 }.corners();
+    assert_eq!(upper_left, Complex { re: -1.5, im: 0.0 });
+    assert_eq!(lower_right, Complex { re: -0.5, im: -1.0 });
+}
+
+/// 最多保存 `capacity` 块瓦片的 LRU 缓存
+pub struct TileCache {
+    capacity: usize,
+    /// 每次访问递增，记录每块瓦片最后一次被访问的时刻
+    clock: u64,
+    entries: HashMap<Tile, (u64, Arc<Vec<u8>>)>,
+}
+
+impl TileCache {
+    pub fn new(capacity: usize) -> TileCache {
+        TileCache {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// 取出缓存的瓦片并标记为最近使用
+    pub fn get(&mut self, tile: &Tile) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let (stamp, png) = self.entries.get_mut(tile)?;
+        *stamp = self.clock;
+        Some(png.clone())
+    }
+
+    /// 保存瓦片，超出容量时丢弃最久没有使用的瓦片
+    pub fn insert(&mut self, tile: Tile, png: Arc<Vec<u8>>) {
+        self.clock += 1;
+        self.entries.insert(tile, (self.clock, png));
+        while self.entries.len() > self.capacity {
+            let oldest = *self
+                .entries
+                .iter()
+                .min_by_key(|(_, (stamp, _))| *stamp)
+                .map(|(tile, _)| tile)
+                .expect("cache is not empty");
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[test]
+fn test_tile_cache() {
+    let tile = |x| Tile { z: 2, x, y: 0 };
+    let png = |byte| Arc::new(vec![byte]);
+    let mut cache = TileCache::new(2);
+    cache.insert(tile(0), png(0));
+    cache.insert(tile(1), png(1));
+    // 访问第 0 块后，第 1 块成为最久没有使用的瓦片
+    assert_eq!(cache.get(&tile(0)), Some(png(0)));
+    cache.insert(tile(2), png(2));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&tile(1)), None);
+    assert_eq!(cache.get(&tile(0)), Some(png(0)));
+    assert_eq!(cache.get(&tile(2)), Some(png(2)));
+}
+
+/// 服务器的渲染参数和瓦片缓存
+pub struct Server<C> {
+    pub fractal: Fractal,
+    pub coloring: Coloring,
+    pub limit: usize,
+    /// 把迭代缓冲区着色为 RGB 像素
+    pub colorize: C,
+    pub cache: Mutex<TileCache>,
+}
+
+impl<C: Fn(&[u32], &mut [u8]) + Sync> Server<C> {
+    /// 渲染瓦片并编码为 PNG
+    fn render_tile(&self, tile: &Tile) -> io::Result<Vec<u8>> {
+        let bounds = (TILE_SIZE, TILE_SIZE);
+        let (upper_left, lower_right) = tile.corners();
+        let mut iterations = vec![0; bounds.0 * bounds.1];
+        render_parallel(
+            self.fractal,
+            self.coloring,
+            self.limit,
+            &mut iterations,
+            bounds,
+            PixelTransform::from_corners(bounds, upper_left, lower_right),
+            BLOCK,
+            true,
+            &Progress::hidden(),
+        );
+        self.encode_tile(&iterations)
+    }
+
+    /// 只做渐进式渲染的第一遍，得到粗略的预览瓦片并编码为 PNG
+    fn render_preview(&self, tile: &Tile) -> io::Result<Vec<u8>> {
+        let bounds = (TILE_SIZE, TILE_SIZE);
+        let (upper_left, lower_right) = tile.corners();
+        let mut progressive = Progressive::new(bounds);
+        progressive.refine(
+            self.fractal,
+            self.coloring,
+            self.limit,
+            PixelTransform::from_corners(bounds, upper_left, lower_right),
+        );
+        self.encode_tile(progressive.iterations())
+    }
+
+    /// 为瓦片的迭代缓冲区着色并编码为 PNG
+    fn encode_tile(&self, iterations: &[u32]) -> io::Result<Vec<u8>> {
+        let bounds = (TILE_SIZE, TILE_SIZE);
+        let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+        (self.colorize)(iterations, &mut pixels);
+        let mut png = Cursor::new(Vec::new());
+        encode_image(&mut png, &pixels, bounds, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    /// 缓存中的瓦片，不在缓存中时渲染并加入缓存
+    ///
+    /// 渲染时不持有缓存的锁，两个连接同时请求同一块瓦片时可能各自渲染一次。
+    fn tile(&self, tile: &Tile) -> io::Result<Arc<Vec<u8>>> {
+        if let Some(png) = self.cache.lock().unwrap().get(tile) {
+            return Ok(png);
+        }
+        let png = Arc::new(self.render_tile(tile)?);
+        self.cache.lock().unwrap().insert(*tile, png.clone());
+        Ok(png)
+    }
+
+    /// 读取一个 HTTP 请求并写出响应，之后关闭连接
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // 忽略其余的请求头
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+            line.clear();
+        }
+
+        let mut words = request.split_whitespace();
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let tile = parse_tile_path(path);
+        let mut stream = &stream;
+        match (method, path, tile) {
+            ("GET", "/", _) => respond(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                &index_html(),
+            ),
+            ("GET", _, Some(tile)) if query.split('&').any(|param| param == "preview") => {
+                let png = self.render_preview(&tile)?;
+                respond(&mut stream, "200 OK", "image/png", &png)
+            }
+            ("GET", _, Some(tile)) => {
+                let png = self.tile(&tile)?;
+                respond(&mut stream, "200 OK", "image/png", &png)
+            }
+            ("GET", _, None) => respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+            _ => respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"method not allowed\n",
+            ),
+        }
+    }
+
+    /// 在 `listener` 上接受连接，每个连接用一个线程处理，直到进程结束
+    ///
+    /// 单个连接出错（如客户端提前断开或超时）只影响这个连接。
+    pub fn run(&self, listener: TcpListener) -> io::Result<()> {
+        thread::scope(|scope| {
+            accept(&listener, HTTP_TIMEOUT, |stream| {
+                scope.spawn(move || {
+                    let _ = self.handle(stream);
+                });
+            });
+            Ok(())
+        })
+    }
+}
+
+/// 不断接受 `listener` 上的连接，给每个连接设置读写超时 `timeout` 后交给 `handle`
+///
+/// 接受连接失败（如文件描述符暂时用尽）时把错误打印到标准错误，稍等片刻再接受下一个，
+/// 不结束整个服务。
+pub(crate) fn accept(listener: &TcpListener, timeout: Duration, mut handle: impl FnMut(TcpStream)) {
+    for stream in listener.incoming() {
+        let stream = stream.and_then(|stream| {
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            Ok(stream)
+        });
+        match stream {
+            Ok(stream) => handle(stream),
+            Err(err) => {
+                eprintln!("cannot accept a connection: {}", err);
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_accept() {
+    use std::sync::mpsc;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        accept(&listener, Duration::from_secs(5), |stream| {
+            let _ = sender.send(stream.read_timeout().unwrap());
+        })
+    });
+    let _stream = TcpStream::connect(address).unwrap();
+    assert_eq!(receiver.recv().unwrap(), Some(Duration::from_secs(5)));
+}
+
+/// 填入最深缩放级别的网页
+fn index_html() -> Vec<u8> {
+    INDEX_HTML
+        .replace("MAX_ZOOM", &MAX_ZOOM.to_string())
+        .into_bytes()
+}
+
+/// 写出状态为 `status`、内容为 `body` 的响应
+pub(crate) fn respond(
+    stream: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[test]
+fn test_respond() {
+    let mut response = Vec::new();
+    respond(&mut response, "404 Not Found", "text/plain", b"not found\n").unwrap();
+    assert_eq!(
+        String::from_utf8(response).unwrap(),
+        "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 10\r\nConnection: close\r\n\r\nnot found\n"
+    );
+}
@@ -0,0 +1,180 @@
+//! 斜坡着色：把逃逸值看作高度场，按法线和光照方向给着色后的图像加上明暗
+//!
+//! 连续逃逸时间或距离估计在图像中是一个光滑的曲面。用相邻像素的差分求出每个像素处的
+//! 法线，再按 Lambert 漫反射和 Phong 高光计算亮度，乘到调色板给出的颜色上，得到浮雕般
+//! 的立体效果。光照方向由方位角（从图像右方起逆时针）和仰角给出，`relief` 放大高度的
+//! 起伏。集合内部的像素没有高度，保持原来的颜色；与内部相邻的像素只用另一侧的差分。
+
+use crate::decode_escape;
+use crate::palette::Channel;
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 光照参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// 光源的方位角（度），0 为图像右方，90 为上方
+    pub azimuth: f64,
+    /// 光源的仰角（度），90 为正上方
+    pub elevation: f64,
+    /// 高度的放大倍数
+    pub relief: f64,
+    /// 背光处仍保留的亮度比例
+    pub ambient: f64,
+    /// 高光的强度
+    pub specular: f64,
+    /// 高光的集中程度，越大高光越小越亮
+    pub shininess: f64,
+}
+
+impl Light {
+    /// 从方位角 `azimuth` 和仰角 `elevation`（度）照射的光，其余参数取默认值
+    pub fn new(azimuth: f64, elevation: f64) -> Light {
+        Light {
+            azimuth,
+            elevation,
+            relief: 1.0,
+            ambient: 0.3,
+            specular: 0.2,
+            shininess: 20.0,
+        }
+    }
+
+    /// 指向光源的单位向量，`z` 轴垂直于图像朝向观察者
+    fn direction(&self) -> [f64; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        ]
+    }
+
+    /// 法线为 `normal` 的表面相对于原色的亮度倍数和高光的强度
+    fn intensity(&self, normal: [f64; 3]) -> (f64, f64) {
+        let light = self.direction();
+        let diffuse = dot(normal, light).max(0.0);
+        // 观察方向是 z 轴，反射方向 R = 2 (N·L) N - L 与它的点积就是 R 的 z 分量
+        let reflected = 2.0 * dot(normal, light) * normal[2] - light[2];
+        let highlight = if diffuse > 0.0 {
+            reflected.max(0.0).powf(self.shininess)
+        } else {
+            0.0
+        };
+        (
+            self.ambient + (1.0 - self.ambient) * diffuse,
+            self.specular * highlight,
+        )
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// 把字符串 `s` 解析为光照方向，形如 `AZIMUTH,ELEVATION`（度），仰角必须在 `[0, 90]` 之间
+pub fn parse_light(s: &str) -> Option<Light> {
+    let (azimuth, elevation) = s.split_once(',')?;
+    let azimuth: f64 = azimuth.trim().parse().ok()?;
+    let elevation: f64 = elevation.trim().parse().ok()?;
+    if !azimuth.is_finite() || !(0.0..=90.0).contains(&elevation) {
+        return None;
+    }
+    Some(Light::new(azimuth, elevation))
+}
+
+#[test]
+fn test_parse_light() {
+    assert_eq!(parse_light("45,30"), Some(Light::new(45.0, 30.0)));
+    assert_eq!(parse_light("-90, 90"), Some(Light::new(-90.0, 90.0)));
+    assert_eq!(parse_light("45"), None);
+    assert_eq!(parse_light("45,120"), None);
+    assert_eq!(parse_light("north,30"), None);
+}
+
+/// 宽度为 `width` 的逃逸值缓冲区 `iterations` 中像素 `(x, y)` 处的单位法线，`relief`
+/// 为高度的放大倍数；内部像素返回 `None`
+fn normal(iterations: &[u32], width: usize, x: usize, y: usize, relief: f64) -> Option<[f64; 3]> {
+    let height = iterations.len() / width;
+    let at = |x: usize, y: usize| decode_escape(iterations[y * width + x]);
+    let center = at(x, y)?;
+    // 两侧都有高度时用中心差分，只有一侧时用单侧差分
+    let slope = |before: Option<f64>, after: Option<f64>| match (before, after) {
+        (Some(before), Some(after)) => (after - before) / 2.0,
+        (Some(before), None) => center - before,
+        (None, Some(after)) => after - center,
+        (None, None) => 0.0,
+    };
+    let dx = slope(
+        x.checked_sub(1).and_then(|x| at(x, y)),
+        (x + 1 < width).then(|| at(x + 1, y)).flatten(),
+    );
+    // 图像的行号向下增大，而光照方向的 y 轴向上
+    let dy = -slope(
+        y.checked_sub(1).and_then(|y| at(x, y)),
+        (y + 1 < height).then(|| at(x, y + 1)).flatten(),
+    );
+    let (nx, ny) = (-dx * relief, -dy * relief);
+    let length = (nx * nx + ny * ny + 1.0).sqrt();
+    Some([nx / length, ny / length, 1.0 / length])
+}
+
+/// 按 `light` 给宽度为 `width` 的逃逸值缓冲区 `iterations` 着色后的 RGB 像素 `pixels`
+/// 加上明暗
+pub fn shade<C: Channel>(iterations: &[u32], width: usize, light: &Light, pixels: &mut [C]) {
+    assert_eq!(pixels.len(), iterations.len() * 3);
+    let max = C::from_fraction(1.0).value() as f64;
+    pixels
+        .par_chunks_mut(3)
+        .enumerate()
+        .for_each(|(index, pixel)| {
+            let Some(normal) = normal(
+                iterations,
+                width,
+                index % width,
+                index / width,
+                light.relief,
+            ) else {
+                return;
+            };
+            let (brightness, highlight) = light.intensity(normal);
+            for channel in pixel {
+                let value = channel.value() as f64 * brightness + highlight * max;
+                *channel = C::from_value(value.round().clamp(0.0, max) as usize);
+            }
+        });
+}
+
+#[test]
+fn test_shade() {
+    use crate::{encode_escape, INTERIOR};
+
+    // 向右升高的斜坡朝向左方：光从左方照来时比从右方照来时亮，内部像素不变
+    let ramp: Vec<u32> = (0..4)
+        .flat_map(|_| (0..4).map(|x| encode_escape(Some(x as f64))))
+        .chain([INTERIOR; 4])
+        .collect();
+    let lit = |azimuth| {
+        let mut pixels = vec![200u8; ramp.len() * 3];
+        shade(&ramp, 4, &Light::new(azimuth, 45.0), &mut pixels);
+        pixels
+    };
+    let (from_right, from_left) = (lit(0.0), lit(180.0));
+    assert!(from_right[..48]
+        .iter()
+        .zip(&from_left[..48])
+        .all(|(a, b)| a < b));
+    assert_eq!(&from_right[48..], &[200; 12]);
+
+    // 正上方的光照在平面上时环境光与漫反射之和正好是原色，再加上正对观察者的高光
+    let flat = vec![encode_escape(Some(1.0)); 4];
+    let mut pixels = vec![100u16; 12];
+    let light = Light {
+        specular: 0.0,
+        ..Light::new(0.0, 90.0)
+    };
+    shade(&flat, 2, &light, &mut pixels);
+    assert_eq!(pixels, vec![100; 12]);
+    let mut pixels = vec![100u16; 12];
+    shade(&flat, 2, &Light::new(0.0, 90.0), &mut pixels);
+    assert!(pixels.iter().all(|&channel| channel == 100 + 13107));
+}
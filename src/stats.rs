@@ -0,0 +1,429 @@
+//! 渲染统计
+//!
+//! `render --stats` 在渲染之后报告迭代缓冲区的统计：逃逸值的最小、最大和平均值，内部
+//! 像素的比例，逃逸值的直方图，以及总耗时、每秒像素数和每个线程的忙碌时间。直方图的
+//! 最后几格很满说明还有不少像素差一点就逃逸，值得加大迭代次数；内部比例很高而最大
+//! 逃逸值远小于限制则说明迭代次数可以减小。
+//!
+//! `--export-histogram` 则按整数迭代次数导出逃逸值的完整直方图（CSV 或 JSON），便于在别处
+//! 分析每个位置的迭代次数花在了哪里。
+//!
+//! 每个线程的忙碌时间由 `ThreadTimer` 记录。计时器随 `Progress` 传给渲染函数，并行渲染
+//! 的每个任务（分块或行）通过 `Progress::timed` 计时，没有计时器时只多一次判断。
+
+use crate::{decode_escape, INTERIOR};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// 直方图的格数，各格等宽地覆盖 `[0, limit)`
+pub const BINS: usize = 20;
+
+/// 文字报告中直方图最长的一条的字符数
+const BAR_WIDTH: usize = 40;
+
+/// 一个线程完成的任务数和忙碌的秒数
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreadTime {
+    pub tasks: usize,
+    pub busy: f64,
+}
+
+/// 一次渲染中每个线程的忙碌时间
+///
+/// 按 rayon 线程序号各占一项，每项有自己的锁，线程之间不会互相等待；不在线程池中执行
+/// 的任务记在第 0 项。
+pub struct ThreadTimer {
+    times: Vec<Mutex<ThreadTime>>,
+}
+
+impl ThreadTimer {
+    /// 为当前线程池的每个线程准备一项记录
+    pub fn new() -> ThreadTimer {
+        ThreadTimer {
+            times: (0..rayon::current_num_threads().max(1))
+                .map(|_| Mutex::default())
+                .collect(),
+        }
+    }
+
+    /// 执行并行渲染中的一个任务，把耗时记在当前线程名下
+    pub fn timed<R>(&self, task: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = task();
+        let busy = start.elapsed().as_secs_f64();
+        let index = rayon::current_thread_index()
+            .filter(|&index| index < self.times.len())
+            .unwrap_or(0);
+        let mut time = self.times[index]
+            .lock()
+            .expect("thread times are not poisoned");
+        time.tasks += 1;
+        time.busy += busy;
+        result
+    }
+
+    /// 每个线程的忙碌时间，按线程序号排列，去掉末尾没有执行过任务的线程
+    pub fn times(&self) -> Vec<ThreadTime> {
+        let mut times: Vec<ThreadTime> = self
+            .times
+            .iter()
+            .map(|time| time.lock().expect("thread times are not poisoned").clone())
+            .collect();
+        while times.last().is_some_and(|time| time.tasks == 0) {
+            times.pop();
+        }
+        times
+    }
+}
+
+impl Default for ThreadTimer {
+    fn default() -> ThreadTimer {
+        ThreadTimer::new()
+    }
+}
+
+/// 一次渲染的统计
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderStats {
+    pub limit: usize,
+    /// 迭代缓冲区中的像素数，超采样时是子像素数
+    pub pixels: usize,
+    pub interior: usize,
+    /// 逃逸像素的最小、最大和平均逃逸值，没有逃逸像素时为 `None`
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// 逃逸值的直方图，共 `BINS` 格；超出限制的值（如平滑着色）记入最后一格
+    pub histogram: Vec<usize>,
+    /// 渲染的总秒数
+    pub seconds: f64,
+    pub threads: Vec<ThreadTime>,
+}
+
+impl RenderStats {
+    /// 统计以 `limit` 为迭代次数限制、用 `seconds` 秒渲染得到的 `iterations`
+    pub fn collect(
+        iterations: &[u32],
+        limit: usize,
+        seconds: f64,
+        threads: Vec<ThreadTime>,
+    ) -> RenderStats {
+        let mut histogram = vec![0; BINS];
+        let (mut min, mut max, mut sum, mut escaped) = (f64::INFINITY, 0.0f64, 0.0, 0);
+        for &value in iterations {
+            let Some(value) = decode_escape(value) else {
+                continue;
+            };
+            min = min.min(value);
+            max = max.max(value);
+            sum += value;
+            escaped += 1;
+            let bin = (value / limit as f64 * BINS as f64) as usize;
+            histogram[bin.min(BINS - 1)] += 1;
+        }
+        RenderStats {
+            limit,
+            pixels: iterations.len(),
+            interior: iterations
+                .iter()
+                .filter(|&&value| value == INTERIOR)
+                .count(),
+            min: (escaped > 0).then_some(min),
+            max: (escaped > 0).then_some(max),
+            mean: (escaped > 0).then(|| sum / escaped as f64),
+            histogram,
+            seconds,
+            threads,
+        }
+    }
+
+    /// 每秒渲染的像素数
+    pub fn pixels_per_second(&self) -> f64 {
+        self.pixels as f64 / self.seconds
+    }
+
+    /// 内部像素的百分比
+    pub fn interior_percentage(&self) -> f64 {
+        100.0 * self.interior as f64 / self.pixels as f64
+    }
+
+    /// 第 `bin` 格覆盖的逃逸值范围
+    fn bin_range(&self, bin: usize) -> (f64, f64) {
+        let width = self.limit as f64 / BINS as f64;
+        (bin as f64 * width, (bin + 1) as f64 * width)
+    }
+
+    /// 格式化为 JSON 文档
+    pub fn to_json(&self) -> String {
+        let value = json!({
+            "max_iter": self.limit,
+            "pixels": self.pixels,
+            "interior": self.interior,
+            "interior_percentage": self.interior_percentage(),
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "histogram": (0..BINS).map(|bin| {
+                let (from, to) = self.bin_range(bin);
+                json!({"from": from, "to": to, "count": self.histogram[bin]})
+            }).collect::<Vec<_>>(),
+            "seconds": self.seconds,
+            "pixels_per_second": self.pixels_per_second(),
+            "threads": self.threads.iter().enumerate().map(|(index, thread)| json!({
+                "thread": index,
+                "tasks": thread.tasks,
+                "busy_seconds": thread.busy,
+            })).collect::<Vec<_>>(),
+        });
+        format!("{:#}\n", value)
+    }
+
+    /// 格式化为给人看的多行报告
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "pixels: {} in {:.3} s ({:.0} pixels/s)\n",
+            self.pixels,
+            self.seconds,
+            self.pixels_per_second()
+        );
+        match (self.min, self.max, self.mean) {
+            (Some(min), Some(max), Some(mean)) => text.push_str(&format!(
+                "escaped: min {:.2}, max {:.2}, mean {:.2} (max-iter {})\n",
+                min, max, mean, self.limit
+            )),
+            _ => text.push_str("escaped: none\n"),
+        }
+        text.push_str(&format!(
+            "interior: {} pixels ({:.2}%)\n",
+            self.interior,
+            self.interior_percentage()
+        ));
+        let largest = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (bin, &count) in self.histogram.iter().enumerate() {
+            let (from, to) = self.bin_range(bin);
+            let bar = (count * BAR_WIDTH).div_ceil(largest);
+            text.push_str(&format!(
+                "{:>10.0} ..{:>8.0}  {:<width$} {}\n",
+                from,
+                to,
+                "#".repeat(bar),
+                count,
+                width = BAR_WIDTH
+            ));
+        }
+        for (index, thread) in self.threads.iter().enumerate() {
+            text.push_str(&format!(
+                "thread {:>2}: {:>6} tasks, {:.3} s busy ({:.0}%)\n",
+                index,
+                thread.tasks,
+                thread.busy,
+                100.0 * thread.busy / self.seconds
+            ));
+        }
+        text
+    }
+}
+
+/// 按整数迭代次数统计的逃逸值直方图，与着色无关，用于分析迭代次数花在了哪里
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscapeHistogram {
+    pub limit: usize,
+    pub interior: usize,
+    /// 逃逸值向下取整后的迭代次数到像素数的映射，只包含出现过的次数；超出限制的值记在
+    /// `limit - 1` 名下
+    pub counts: BTreeMap<usize, usize>,
+}
+
+/// JSON 中给出的逃逸值百分位
+const PERCENTILES: [f64; 5] = [50.0, 90.0, 99.0, 99.9, 100.0];
+
+impl EscapeHistogram {
+    /// 统计以 `limit` 为迭代次数限制渲染得到的 `iterations`
+    ///
+    /// 只按出现过的迭代次数计数，占用的内存与 `limit` 无关。
+    pub fn collect(iterations: &[u32], limit: usize) -> EscapeHistogram {
+        let mut counts = BTreeMap::new();
+        let mut interior = 0;
+        for &value in iterations {
+            match decode_escape(value) {
+                Some(value) => {
+                    *counts
+                        .entry((value as usize).min(limit.saturating_sub(1)))
+                        .or_default() += 1
+                }
+                None if value == INTERIOR => interior += 1,
+                None => {}
+            }
+        }
+        EscapeHistogram {
+            limit,
+            interior,
+            counts,
+        }
+    }
+
+    /// 逃逸的像素数
+    pub fn escaped(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// 逃逸像素中 `percent`% 在多少次迭代之内逃逸；没有逃逸像素时为 `None`
+    ///
+    /// 把 `--max-iter` 取得比 99.9 百分位稍大，就只有极少数逃逸像素被误画成内部。
+    pub fn percentile(&self, percent: f64) -> Option<usize> {
+        let escaped = self.escaped();
+        if escaped == 0 {
+            return None;
+        }
+        let target = ((percent / 100.0 * escaped as f64).ceil() as usize).max(1);
+        let mut cumulative = 0;
+        self.counts.iter().find_map(|(&n, &count)| {
+            cumulative += count;
+            (cumulative >= target).then_some(n)
+        })
+    }
+
+    /// CSV 表示，每一行是迭代次数、在这一次逃逸的像素数，以及到这一次为止逃逸的像素占全部
+    /// 像素的比例，没有像素逃逸的次数不出现；最后一行的比例与 1 之差就是内部像素的比例
+    pub fn to_csv(&self) -> String {
+        let pixels = (self.escaped() + self.interior).max(1) as f64;
+        let mut csv = String::from("iterations,count,escaped_fraction\n");
+        let mut cumulative = 0;
+        for (&n, &count) in &self.counts {
+            cumulative += count;
+            csv.push_str(&format!("{},{},{}\n", n, count, cumulative as f64 / pixels));
+        }
+        csv
+    }
+
+    /// JSON 表示，包括几个常用的百分位和按迭代次数排列的各项计数
+    pub fn to_json(&self) -> String {
+        let value = json!({
+            "max_iter": self.limit,
+            "pixels": self.escaped() + self.interior,
+            "interior": self.interior,
+            "percentiles": PERCENTILES
+                .iter()
+                .map(|&percent| (percent.to_string(), json!(self.percentile(percent))))
+                .collect::<serde_json::Map<_, _>>(),
+            "counts": self
+                .counts
+                .iter()
+                .map(|(&n, &count)| json!({ "iterations": n, "count": count }))
+                .collect::<Vec<_>>(),
+        });
+        format!("{:#}\n", value)
+    }
+}
+
+#[test]
+fn test_escape_histogram() {
+    use crate::{encode_escape, UNFINISHED};
+
+    let mut iterations: Vec<u32> = [Some(0.5), Some(2.0), Some(2.9), Some(3.0), Some(7.0)]
+        .into_iter()
+        .chain([None; 5])
+        .map(encode_escape)
+        .collect();
+    iterations.push(UNFINISHED);
+    let histogram = EscapeHistogram::collect(&iterations, 4);
+    assert_eq!(
+        histogram.counts.clone().into_iter().collect::<Vec<_>>(),
+        [(0, 1), (2, 2), (3, 2)]
+    );
+    assert_eq!(histogram.interior, 5);
+    assert_eq!(histogram.escaped(), 5);
+    assert_eq!(histogram.percentile(20.0), Some(0));
+    assert_eq!(histogram.percentile(50.0), Some(2));
+    assert_eq!(histogram.percentile(100.0), Some(3));
+    assert_eq!(
+        histogram.to_csv(),
+        "iterations,count,escaped_fraction\n0,1,0.1\n2,2,0.3\n3,2,0.5\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&histogram.to_json()).unwrap();
+    assert_eq!(json["pixels"], 10);
+    assert_eq!(json["percentiles"]["99.9"], 3);
+    assert_eq!(json["counts"][1]["iterations"], 2);
+    assert_eq!(json["counts"][1]["count"], 2);
+
+    let empty = EscapeHistogram::collect(&[INTERIOR; 3], 10);
+    assert_eq!(empty.percentile(50.0), None);
+
+    // 计数的项数只取决于出现过的迭代次数，与限制无关
+    let deep = EscapeHistogram::collect(&iterations, usize::MAX);
+    assert_eq!(deep.counts.len(), 4);
+    assert_eq!(deep.percentile(100.0), Some(7));
+}
+
+#[test]
+fn test_render_stats() {
+    use crate::encode_escape;
+
+    let iterations: Vec<u32> = [Some(0.0), Some(9.0), Some(10.0), Some(99.0), Some(150.0)]
+        .into_iter()
+        .chain([None; 3])
+        .map(encode_escape)
+        .collect();
+    let threads = vec![ThreadTime {
+        tasks: 4,
+        busy: 1.5,
+    }];
+    let stats = RenderStats::collect(&iterations, 100, 2.0, threads);
+    assert_eq!(stats.pixels, 8);
+    assert_eq!(stats.interior, 3);
+    assert_eq!(stats.interior_percentage(), 37.5);
+    assert_eq!((stats.min, stats.max), (Some(0.0), Some(150.0)));
+    assert_eq!(stats.mean, Some(53.6));
+    assert_eq!(stats.pixels_per_second(), 4.0);
+    // 每格宽 5：0 和 9 分在前两格，超出限制的 150 记入最后一格
+    assert_eq!(stats.histogram[..3], [1, 1, 1]);
+    assert_eq!(stats.histogram[19], 2);
+    assert_eq!(stats.histogram.iter().sum::<usize>(), 5);
+
+    let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+    assert_eq!(json["interior"], 3);
+    assert_eq!(json["histogram"][19]["count"], 2);
+    assert_eq!(json["threads"][0]["busy_seconds"], 1.5);
+    let text = stats.to_text();
+    assert!(text.contains("interior: 3 pixels (37.50%)"));
+    assert!(text.contains("thread  0:      4 tasks, 1.500 s busy (75%)"));
+
+    let empty = RenderStats::collect(&[INTERIOR; 4], 100, 1.0, Vec::new());
+    assert_eq!(empty.mean, None);
+    assert!(empty.to_text().contains("escaped: none"));
+}
+
+#[test]
+fn test_thread_timer() {
+    use crate::progress::Progress;
+
+    // 没有计时器时不记录
+    let hidden = Progress::hidden();
+    assert_eq!(hidden.timed(|| 42), 42);
+    assert!(hidden.thread_times().is_empty());
+
+    // 在线程池之外执行的任务记在第 0 项
+    let progress = Progress::hidden().with_thread_timing();
+    assert_eq!(progress.timed(|| 42), 42);
+    progress.timed(|| std::thread::sleep(std::time::Duration::from_millis(2)));
+    let times = progress.thread_times();
+    assert_eq!(times.len(), 1);
+    assert_eq!(times[0].tasks, 2);
+    assert!(times[0].busy >= 0.002);
+
+    // 线程池中的任务记在各自的线程名下，总数不变
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(3)
+        .build()
+        .unwrap();
+    let timer = pool.install(ThreadTimer::new);
+    pool.install(|| {
+        use rayon::prelude::*;
+        (0..30).into_par_iter().for_each(|_| timer.timed(|| ()));
+    });
+    let times = timer.times();
+    assert!(times.len() <= 3);
+    assert_eq!(times.iter().map(|time| time.tasks).sum::<usize>(), 30);
+}
@@ -0,0 +1,189 @@
+//! 逐条带写出的 PNG
+//!
+//! `image` 的 PNG 编码器要求一次给出整幅图像，超大图像（如 100000×100000）的像素
+//! 缓冲区放不进内存。`PngStream` 先写出文件头，之后每次接收若干行像素，压缩后立即
+//! 写入输出，内存占用只与条带的大小有关，与图像的尺寸无关。
+//!
+//! 每一行都使用 Sub 过滤器（与左边像素的差），压缩后的数据按 `CHUNK` 字节切分为
+//! 多个 `IDAT` 块。
+
+use deflate::write::ZlibEncoder;
+use deflate::Compression;
+use std::io::{self, Write};
+
+/// PNG 文件的签名
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// 每个 `IDAT` 块最多包含的压缩数据字节数
+pub(crate) const CHUNK: usize = 1 << 16;
+
+/// PNG 使用的 CRC-32（多项式 0xedb88320）
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b"IEND"), 0xae42_6082);
+}
+
+/// 写出类型为 `kind`、内容为 `data` 的 PNG 块
+pub(crate) fn write_chunk<W: Write>(output: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    output.write_all(&(data.len() as u32).to_be_bytes())?;
+    output.write_all(kind)?;
+    output.write_all(data)?;
+    let mut crc_input = kind.to_vec();
+    crc_input.extend_from_slice(data);
+    output.write_all(&crc32(&crc_input).to_be_bytes())
+}
+
+/// 用 Sub 过滤器（每个字节减去左边像素的同一字节）过滤一行每个像素 `pixel_bytes`
+/// 字节的像素，写入开头是过滤器类型字节的 `filtered`
+pub(crate) fn sub_filter(row: &[u8], pixel_bytes: usize, filtered: &mut [u8]) {
+    filtered[0] = 1;
+    for (i, &byte) in row.iter().enumerate() {
+        let left = i.checked_sub(pixel_bytes).map_or(0, |j| row[j]);
+        filtered[1 + i] = byte.wrapping_sub(left);
+    }
+}
+
+/// 把压缩后的数据攒够 `CHUNK` 字节就写成一个 `IDAT` 块
+struct IdatWriter<W: Write> {
+    output: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> IdatWriter<W> {
+    /// 写出剩余的数据，返回底层的输出
+    fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            write_chunk(&mut self.output, b"IDAT", &self.buffer)?;
+        }
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> Write for IdatWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        while self.buffer.len() >= CHUNK {
+            write_chunk(&mut self.output, b"IDAT", &self.buffer[..CHUNK])?;
+            self.buffer.drain(..CHUNK);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// 逐条带写出的 RGB PNG 编码器
+pub struct PngStream<W: Write> {
+    encoder: ZlibEncoder<IdatWriter<W>>,
+    bounds: (usize, usize),
+    /// 每个像素的字节数：8 位时为 3，16 位时为 6
+    pixel_bytes: usize,
+    /// 已经写入的行数
+    rows: usize,
+    /// 过滤后的一行，包括开头的过滤器类型字节
+    filtered: Vec<u8>,
+}
+
+impl<W: Write> PngStream<W> {
+    /// 写出 `bounds` 大小、每个通道 `bit_depth`（8 或 16）位的 RGB 图像的文件头
+    pub fn new(mut output: W, bounds: (usize, usize), bit_depth: u8) -> io::Result<PngStream<W>> {
+        assert!(bit_depth == 8 || bit_depth == 16);
+        let (width, height) = (
+            u32::try_from(bounds.0).map_err(io::Error::other)?,
+            u32::try_from(bounds.1).map_err(io::Error::other)?,
+        );
+        output.write_all(&SIGNATURE)?;
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 位深、颜色类型 2（RGB）、压缩方式、过滤方式、不隔行
+        header.extend_from_slice(&[bit_depth, 2, 0, 0, 0]);
+        write_chunk(&mut output, b"IHDR", &header)?;
+        let pixel_bytes = 3 * bit_depth as usize / 8;
+        Ok(PngStream {
+            encoder: ZlibEncoder::new(
+                IdatWriter {
+                    output,
+                    buffer: Vec::with_capacity(CHUNK),
+                },
+                Compression::Default,
+            ),
+            bounds,
+            pixel_bytes,
+            rows: 0,
+            filtered: vec![0; 1 + bounds.0 * pixel_bytes],
+        })
+    }
+
+    /// 写入紧接着上一个条带的若干行像素，16 位的采样按大端序排列
+    pub fn write_rows(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let row_bytes = self.bounds.0 * self.pixel_bytes;
+        assert_eq!(bytes.len() % row_bytes, 0);
+        for row in bytes.chunks_exact(row_bytes) {
+            if self.rows == self.bounds.1 {
+                return Err(io::Error::other("more rows than the image height"));
+            }
+            sub_filter(row, self.pixel_bytes, &mut self.filtered);
+            self.encoder.write_all(&self.filtered)?;
+            self.rows += 1;
+        }
+        Ok(())
+    }
+
+    /// 写出剩余的压缩数据和文件尾，返回底层的输出
+    pub fn finish(self) -> io::Result<W> {
+        if self.rows != self.bounds.1 {
+            return Err(io::Error::other(format!(
+                "only {} of {} rows were written",
+                self.rows, self.bounds.1
+            )));
+        }
+        let mut output = self.encoder.finish()?.finish()?;
+        write_chunk(&mut output, b"IEND", &[])?;
+        output.flush()?;
+        Ok(output)
+    }
+}
+
+#[test]
+fn test_png_stream() {
+    use image::DynamicImage;
+
+    let bounds = (5, 4);
+    let pixels: Vec<u8> = (0..bounds.0 * bounds.1 * 3)
+        .map(|i| (i * 37 % 256) as u8)
+        .collect();
+    let mut stream = PngStream::new(Vec::new(), bounds, 8).unwrap();
+    stream.write_rows(&pixels[..bounds.0 * 3]).unwrap();
+    stream.write_rows(&pixels[bounds.0 * 3..]).unwrap();
+    let png = stream.finish().unwrap();
+
+    let DynamicImage::ImageRgb8(decoded) = image::load_from_memory(&png).unwrap() else {
+        panic!("expected an RGB image");
+    };
+    assert_eq!(decoded.dimensions(), (5, 4));
+    assert_eq!(decoded.into_raw(), pixels);
+
+    let mut stream = PngStream::new(Vec::new(), bounds, 8).unwrap();
+    stream.write_rows(&pixels[..bounds.0 * 3]).unwrap();
+    assert!(stream.finish().is_err());
+}
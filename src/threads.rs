@@ -0,0 +1,46 @@
+//! rayon 线程池的配置
+//!
+//! 所有并行渲染都在 rayon 的全局线程池中进行，默认每个逻辑核心一个线程。`--threads N`
+//! 把线程数限制为 `N`，在共享的机器上可以留出空闲的核心，也可以用来测量并行的加速比；
+//! `--pin-threads` 把第 `i` 个工作线程固定在第 `i` 个核心上（核心不够时循环使用），
+//! 避免线程在核心之间迁移。
+
+use std::io;
+
+/// 把字符串 `s` 解析为线程数，线程数必须为正数
+pub fn parse_threads(s: &str) -> Option<usize> {
+    match s.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(threads) => Some(threads),
+    }
+}
+
+#[test]
+fn test_parse_threads() {
+    assert_eq!(parse_threads("4"), Some(4));
+    assert_eq!(parse_threads("0"), None);
+    assert_eq!(parse_threads("-1"), None);
+    assert_eq!(parse_threads("all"), None);
+}
+
+/// 按 `threads` 和 `pin` 配置全局线程池，必须在第一次并行渲染之前调用
+///
+/// `threads` 为 `None` 时使用 rayon 默认的线程数。两者都是默认值时不做任何事。
+pub fn configure(threads: Option<usize>, pin: bool) -> io::Result<()> {
+    if threads.is_none() && !pin {
+        return Ok(());
+    }
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    if pin {
+        let cores = core_affinity::get_core_ids()
+            .filter(|cores| !cores.is_empty())
+            .ok_or_else(|| io::Error::other("cannot list the CPU cores to pin threads to"))?;
+        builder = builder.start_handler(move |index| {
+            core_affinity::set_for_current(cores[index % cores.len()]);
+        });
+    }
+    builder.build_global().map_err(io::Error::other)
+}
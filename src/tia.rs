@@ -0,0 +1,108 @@
+//! 三角不等式平均（TIA）着色
+//!
+//! 迭代 `z' = z^d + c` 时，由三角不等式 `|z'|` 总在 `m = ||z|^d - |c||` 与
+//! `M = |z|^d + |c|` 之间。每一步记录 `|z'|` 在这个区间中的相对位置
+//! `t = (|z'| - m) / (M - m)`，逃逸后取所有 `t` 的平均值。单独的平均值在逃逸次数
+//! 变化处会跳变，因此再按连续逃逸时间的小数部分在最后两个平均值（含与不含最后
+//! 一步）之间插值。与轨道陷阱一样，这需要迭代过程中每一步的数据，总是用 `f64` 计算。
+//! 自定义公式、Phoenix 分形和 Lambda 映射的迭代不是这种形式，不支持这种着色方式。
+
+use crate::evaluator::PointEvaluator;
+use crate::{Coloring, Fractal};
+use num::Complex;
+
+/// 默认的逃逸半径的平方；半径越大，最后两个平均值越接近，插值后越平滑
+pub(crate) const BAILOUT: f64 = 1e12;
+
+/// 累积中的三角不等式平均
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Average {
+    /// 所有 `t` 的和
+    sum: f64,
+    /// 不含最后一个 `t` 的和
+    previous: f64,
+    /// `t` 的个数
+    count: usize,
+}
+
+impl Average {
+    /// 记录一步迭代得到的 `next`，`c` 是迭代公式中的常数
+    ///
+    /// `next - c` 就是 `z^d`（燃烧船和三角集是折叠或共轭之后的 `z^d`，模相同）；其他形式的
+    /// 迭代公式不满足这一点，参见 `Fractal::supports_coloring`。
+    pub fn observe(&mut self, next: Complex<f64>, c: Complex<f64>) {
+        let (power, offset) = ((next - c).norm(), c.norm());
+        let (low, high) = ((power - offset).abs(), power + offset);
+        // z 为零时区间退化，这一步没有信息
+        if high > low {
+            self.previous = self.sum;
+            self.sum += ((next.norm() - low) / (high - low)).clamp(0.0, 1.0);
+            self.count += 1;
+        }
+    }
+
+    /// 逃逸时的值为 `final_z`、迭代公式的次数为 `degree`、逃逸半径的平方为 `bailout` 时
+    /// 插值后的平均值，在 `[0, 1]` 区间内
+    pub fn value(&self, final_z: Complex<f64>, degree: f64, bailout: f64) -> f64 {
+        // ln|z| / ln R 在 (1, d] 之间，刚越过逃逸半径时小数部分为 1
+        let fraction = 1.0 - (final_z.norm_sqr().ln() / bailout.ln()).ln() / degree.ln();
+        let last = self.sum / self.count.max(1) as f64;
+        let before = if self.count > 1 {
+            self.previous / (self.count - 1) as f64
+        } else {
+            last
+        };
+        before + (last - before) * fraction.clamp(0.0, 1.0)
+    }
+}
+
+/// 点 `point` 在分形 `fractal` 中的三角不等式平均值，在 `[0, 1]` 区间内；
+/// 迭代 `limit` 次仍未逃逸时返回 `None`
+pub fn average(fractal: Fractal, point: Complex<f64>, limit: usize) -> Option<f64> {
+    let coloring = Coloring::TriangleInequality;
+    let (result, stats) = fractal.evaluate(&coloring, point, limit);
+    result.iterations?;
+    Some(
+        stats
+            .average
+            .value(result.final_z, fractal.degree(), result.bailout),
+    )
+}
+
+#[test]
+fn test_average() {
+    assert_eq!(
+        average(Fractal::Mandelbrot, Complex::new(-0.5, 0.0), 1000),
+        None
+    );
+    // 集合外的点的平均值在 [0, 1] 区间内，沿着一条穿过多个逃逸次数的线段连续变化
+    let escapes = |re| crate::escape_time(Complex::new(0.0, 0.0), Complex::new(re, 0.5), 100);
+    assert!(escapes(0.5) > escapes(1.5));
+    let steps = 2000;
+    let values: Vec<f64> = (0..=steps)
+        .map(|i| {
+            let c = Complex::new(0.5 + 1.0 * i as f64 / steps as f64, 0.5);
+            average(Fractal::Mandelbrot, c, 1000).unwrap()
+        })
+        .collect();
+    assert!(values.iter().all(|value| (0.0..=1.0).contains(value)));
+    let jump = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f64::max);
+    assert!(jump < 0.01, "{}", jump);
+
+    // 朱利亚集从像素对应的点出发，其他次数的分形同样得到 [0, 1] 区间内的值
+    let julia = Fractal::Julia(Complex::new(-0.8, 0.156));
+    assert!(average(julia, Complex::new(0.9, 0.3), 1000).is_some());
+    let value = average(Fractal::Multibrot(3.0), Complex::new(0.6, 0.4), 1000).unwrap();
+    assert!((0.0..=1.0).contains(&value));
+
+    // 自定义公式的 `next - c` 不一定是 `z^d`，不能用三角不等式平均着色
+    let coloring = Coloring::TriangleInequality;
+    assert!(Fractal::Multibrot(3.0).supports_coloring(coloring));
+    let formula = Box::leak(Box::new(
+        crate::formula::parse_formula("z^2+c*z+c").unwrap(),
+    ));
+    assert!(!Fractal::Formula(formula).supports_coloring(coloring));
+}
@@ -0,0 +1,84 @@
+//! 把图像划分为矩形分块
+//!
+//! 以整行为单位分配任务时，一行的计算量差别很大（穿过集合内部的行要迭代满
+//! `limit` 次），而且行太窄，缓存利用也不好。二维分块让每个任务的计算量更均匀，
+//! 也便于以后按块输出和断点续算。
+
+/// 图像中的一个矩形分块，坐标以像素为单位
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    /// 左上角像素所在的列
+    pub x: usize,
+    /// 左上角像素所在的行
+    pub y: usize,
+    /// 分块的宽度
+    pub width: usize,
+    /// 分块的高度
+    pub height: usize,
+}
+
+impl Tile {
+    /// 分块内的像素数
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// 分块是否不含任何像素
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 把 `bounds` 大小的图像按 `size` 划分为分块，按从左到右、从上到下的顺序返回
+///
+/// 图像右边和下边不足一整块的部分单独成为较小的分块。
+pub fn tiles(bounds: (usize, usize), size: (usize, usize)) -> Vec<Tile> {
+    assert!(size.0 > 0 && size.1 > 0);
+    let mut tiles = Vec::new();
+    for y in (0..bounds.1).step_by(size.1) {
+        for x in (0..bounds.0).step_by(size.0) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.0.min(bounds.0 - x),
+                height: size.1.min(bounds.1 - y),
+            });
+        }
+    }
+    tiles
+}
+
+#[test]
+fn test_tiles() {
+    let tiles = tiles((100, 70), (64, 64));
+    assert_eq!(
+        tiles,
+        [
+            Tile {
+                x: 0,
+                y: 0,
+                width: 64,
+                height: 64
+            },
+            Tile {
+                x: 64,
+                y: 0,
+                width: 36,
+                height: 64
+            },
+            Tile {
+                x: 0,
+                y: 64,
+                width: 64,
+                height: 6
+            },
+            Tile {
+                x: 64,
+                y: 64,
+                width: 36,
+                height: 6
+            },
+        ]
+    );
+    assert_eq!(tiles.iter().map(Tile::len).sum::<usize>(), 100 * 70);
+}
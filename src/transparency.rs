@@ -0,0 +1,124 @@
+//! 透明输出：按像素是否逃逸给着色后的 RGB 图像加上 alpha 通道
+//!
+//! 外部（逃逸的）或内部（未逃逸的）像素可以设为透明或半透明，输出的 RGBA 图像可以在图像
+//! 编辑器或网页中叠加到其他背景上。超采样时每个像素的不透明度按其中透明的子像素所占的
+//! 比例插值，集合的边缘因此也是抗锯齿的。未完成的像素总是不透明，以便看到棋盘格。
+//!
+//! 区分内外依靠迭代缓冲区中的 `INTERIOR`，因此内部也着色的方式（如原子域或非 flat 的
+//! 内部着色）下不能使用。
+
+use crate::palette::Channel;
+use crate::{INTERIOR, UNFINISHED};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+
+/// 设为透明的区域
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    /// 逃逸的点
+    Exterior,
+    /// 集合内部的点
+    Interior,
+}
+
+/// 把字符串 `s` 解析为透明的区域：`exterior` 或 `interior`
+pub fn parse_region(s: &str) -> Option<Region> {
+    match s {
+        "exterior" => Some(Region::Exterior),
+        "interior" => Some(Region::Interior),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_parse_region() {
+    assert_eq!(parse_region("exterior"), Some(Region::Exterior));
+    assert_eq!(parse_region("interior"), Some(Region::Interior));
+    assert_eq!(parse_region("inside"), None);
+}
+
+/// 透明输出的参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transparency {
+    /// 设为透明的区域
+    pub region: Region,
+    /// 该区域的不透明度，0 为完全透明，1 为不透明
+    pub opacity: f64,
+}
+
+impl Transparency {
+    /// 迭代缓冲区中的值 `value` 是否属于透明的区域
+    fn contains(&self, value: u32) -> bool {
+        match self.region {
+            Region::Exterior => value < UNFINISHED,
+            Region::Interior => value == INTERIOR,
+        }
+    }
+
+    /// 每个方向放大了 `factor` 倍的超采样迭代缓冲区 `iterations` 对应的 `bounds` 大小的
+    /// alpha 通道
+    pub fn alpha<C: Channel>(
+        &self,
+        iterations: &[u32],
+        factor: usize,
+        bounds: (usize, usize),
+    ) -> Vec<C> {
+        assert_eq!(iterations.len(), bounds.0 * bounds.1 * factor * factor);
+        let sample_width = bounds.0 * factor;
+        let count = (factor * factor) as f64;
+        let mut alpha = vec![C::default(); bounds.0 * bounds.1];
+        alpha
+            .par_chunks_mut(bounds.0)
+            .enumerate()
+            .for_each(|(row, band)| {
+                for (column, alpha) in band.iter_mut().enumerate() {
+                    let transparent = (row * factor..(row + 1) * factor)
+                        .flat_map(|sub_row| {
+                            let start = sub_row * sample_width + column * factor;
+                            &iterations[start..start + factor]
+                        })
+                        .filter(|&&value| self.contains(value))
+                        .count();
+                    let coverage = transparent as f64 / count;
+                    *alpha = C::from_fraction(1.0 - coverage * (1.0 - self.opacity));
+                }
+            });
+        alpha
+    }
+}
+
+/// 把 RGB 像素 `rgb` 和对应的 alpha 通道 `alpha` 交织为 RGBA 像素
+pub fn with_alpha<C: Copy>(rgb: &[C], alpha: &[C]) -> Vec<C> {
+    assert_eq!(rgb.len(), alpha.len() * 3);
+    rgb.chunks(3)
+        .zip(alpha)
+        .flat_map(|(pixel, &alpha)| [pixel[0], pixel[1], pixel[2], alpha])
+        .collect()
+}
+
+#[test]
+fn test_alpha() {
+    use crate::encode_escape;
+
+    // 2x1 的图像，每个像素由 2x2 个子像素组成：左边的像素全部逃逸，右边的一半逃逸
+    let escaped = encode_escape(Some(3.0));
+    let iterations = [
+        escaped, escaped, escaped, INTERIOR, //
+        escaped, escaped, INTERIOR, escaped,
+    ];
+    let exterior = Transparency {
+        region: Region::Exterior,
+        opacity: 0.0,
+    };
+    assert_eq!(exterior.alpha::<u8>(&iterations, 2, (2, 1)), [0, 128]);
+    let interior = Transparency {
+        region: Region::Interior,
+        opacity: 0.5,
+    };
+    assert_eq!(interior.alpha::<u8>(&iterations, 2, (2, 1)), [255, 191]);
+    assert_eq!(interior.alpha::<u8>(&[UNFINISHED], 1, (1, 1)), [255]);
+
+    assert_eq!(
+        with_alpha(&[1u16, 2, 3, 4, 5, 6], &[7, 8]),
+        [1, 2, 3, 7, 4, 5, 6, 8]
+    );
+}
@@ -0,0 +1,136 @@
+//! 轨道陷阱着色
+//!
+//! 轨道陷阱是复平面上的一个图形。迭代时记录轨道上的点到图形的最小距离，再按这个
+//! 距离着色。与逃逸时间不同，集合内部的点也会得到有层次的颜色。
+
+use crate::evaluator::PointEvaluator;
+use crate::{parse_complex, Coloring, Fractal};
+use num::Complex;
+
+/// 陷阱的形状
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrapShape {
+    /// 单个点
+    Point,
+    /// 过中心的水平线和竖直线组成的十字
+    Cross,
+    /// 以中心为圆心、`size` 为半径的圆
+    Circle,
+    /// 过中心、与实轴夹角为 `size` 弧度的直线
+    Line,
+}
+
+/// 一个轨道陷阱：形状、中心以及形状的参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub center: Complex<f64>,
+    /// 圆的半径或直线的倾角（弧度），其他形状不使用
+    pub size: f64,
+}
+
+impl Default for Trap {
+    /// 原点处的点陷阱
+    fn default() -> Trap {
+        Trap {
+            shape: TrapShape::Point,
+            center: Complex { re: 0.0, im: 0.0 },
+            size: 0.0,
+        }
+    }
+}
+
+impl Trap {
+    /// 点 `z` 到陷阱图形的距离
+    pub fn distance(&self, z: Complex<f64>) -> f64 {
+        let offset = z - self.center;
+        match self.shape {
+            TrapShape::Point => offset.norm(),
+            TrapShape::Cross => offset.re.abs().min(offset.im.abs()),
+            TrapShape::Circle => (offset.norm() - self.size).abs(),
+            TrapShape::Line => (offset * Complex::from_polar(1.0, -self.size)).im.abs(),
+        }
+    }
+
+    /// 点 `point` 在分形 `fractal` 中的轨道（最多 `limit` 次迭代，逃逸即停止）到陷阱的
+    /// 最小距离
+    pub fn min_distance(&self, fractal: Fractal, point: Complex<f64>, limit: usize) -> f64 {
+        fractal
+            .evaluate(&Coloring::OrbitTrap(*self), point, limit)
+            .1
+            .trap_distance
+    }
+}
+
+#[test]
+fn test_trap_distance() {
+    let z = Complex { re: 3.0, im: 4.0 };
+    let trap = |shape, size| Trap {
+        shape,
+        center: Complex { re: 0.0, im: 0.0 },
+        size,
+    };
+    assert_eq!(trap(TrapShape::Point, 0.0).distance(z), 5.0);
+    assert_eq!(trap(TrapShape::Cross, 0.0).distance(z), 3.0);
+    assert_eq!(trap(TrapShape::Circle, 2.0).distance(z), 3.0);
+    assert!((trap(TrapShape::Line, std::f64::consts::FRAC_PI_2).distance(z) - 3.0).abs() < 1e-12);
+}
+
+/// 把最小距离换算为 `[0, limit]` 区间内的值，以便与其他着色方式共用调色板映射
+///
+/// 落在陷阱上的轨道映射到调色板的起点，距离 1 以外的映射到终点，取平方根是为了
+/// 突出陷阱附近的层次。
+pub fn trap_value(distance: f64, limit: usize) -> f64 {
+    limit as f64 * distance.min(1.0).sqrt()
+}
+
+/// 把字符串 `s` 解析为轨道陷阱
+///
+/// 可以是 `point:RE,IM`、`cross:RE,IM`、`circle:RE,IM:RADIUS` 或
+/// `line:RE,IM:DEGREES`（直线与实轴的夹角以度为单位）
+pub fn parse_trap(s: &str) -> Option<Trap> {
+    let mut parts = s.split(':');
+    let shape = match parts.next()? {
+        "point" => TrapShape::Point,
+        "cross" => TrapShape::Cross,
+        "circle" => TrapShape::Circle,
+        "line" => TrapShape::Line,
+        _ => return None,
+    };
+    let center = parse_complex(parts.next()?)?;
+    let size = match (shape, parts.next()) {
+        (TrapShape::Point | TrapShape::Cross, None) => 0.0,
+        (TrapShape::Circle, Some(radius)) => radius.parse().ok().filter(|&r: &f64| r >= 0.0)?,
+        (TrapShape::Line, Some(degrees)) => degrees.parse::<f64>().ok()?.to_radians(),
+        _ => return None,
+    };
+    if parts.next().is_some() || !size.is_finite() {
+        return None;
+    }
+    Some(Trap {
+        shape,
+        center,
+        size,
+    })
+}
+
+#[test]
+fn test_parse_trap() {
+    assert_eq!(parse_trap("point:0,0"), Some(Trap::default()));
+    assert_eq!(
+        parse_trap("circle:-0.5,0.25:0.1"),
+        Some(Trap {
+            shape: TrapShape::Circle,
+            center: Complex { re: -0.5, im: 0.25 },
+            size: 0.1
+        })
+    );
+    assert_eq!(
+        parse_trap("line:0,0:180").map(|trap| trap.size),
+        Some(std::f64::consts::PI)
+    );
+    assert_eq!(parse_trap("cross:1,1:2"), None);
+    assert_eq!(parse_trap("circle:0,0"), None);
+    assert_eq!(parse_trap("circle:0,0:-1"), None);
+    assert_eq!(parse_trap("square:0,0"), None);
+}
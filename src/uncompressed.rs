@@ -0,0 +1,137 @@
+//! 不压缩的简单图像格式：PPM、PGM 和 BMP
+//!
+//! 这些格式只有一个固定的文件头，后面按行排列像素，不依赖任何编码库，写出时也不需要在
+//! 输出中回退，因此可以直接写到管道里交给其他工具处理，例如
+//! `mandelbrot render - --format ppm | ffmpeg -i - out.mp4`。
+//!
+//! PPM 和 PGM 使用二进制的 `P6`、`P5` 变体，8 位和 16 位都支持，16 位采样按大端序存储；
+//! PGM 把彩色像素按 Rec. 601 的权重换算为亮度。BMP 只写 24 位、自下而上的图像。三者都
+//! 不支持 alpha 通道。
+
+use crate::palette::Channel;
+use crate::{alpha_unsupported, has_alpha, ImageFormat};
+use std::io::{self, Write};
+
+/// 把 RGB `pixels` 缓冲区（其尺寸由 `bounds` 给出）按 PPM、PGM 或 BMP 格式 `format` 写入
+/// `output`
+///
+/// 通道为 8 位或 16 位，BMP 只支持 8 位；缓冲区带 alpha 通道时返回错误。
+pub fn encode<C: Channel>(
+    mut output: impl Write,
+    pixels: &[C],
+    bounds: (usize, usize),
+    format: ImageFormat,
+) -> io::Result<()> {
+    if has_alpha(pixels, bounds) {
+        return Err(alpha_unsupported(format));
+    }
+    let max = C::from_fraction(1.0).value();
+    match format {
+        ImageFormat::Ppm | ImageFormat::Pgm => {
+            let magic = if format == ImageFormat::Ppm {
+                "P6"
+            } else {
+                "P5"
+            };
+            write!(output, "{}\n{} {}\n{}\n", magic, bounds.0, bounds.1, max)?;
+            let mut row = Vec::new();
+            for pixels in pixels.chunks(3 * bounds.0.max(1)) {
+                row.clear();
+                for rgb in pixels.chunks(3) {
+                    let rgb = [rgb[0].value(), rgb[1].value(), rgb[2].value()];
+                    let samples = if format == ImageFormat::Ppm {
+                        &rgb[..]
+                    } else {
+                        &[luma(rgb)][..]
+                    };
+                    for &sample in samples {
+                        if max > 255 {
+                            row.extend_from_slice(&(sample as u16).to_be_bytes());
+                        } else {
+                            row.push(sample as u8);
+                        }
+                    }
+                }
+                output.write_all(&row)?;
+            }
+            output.flush()
+        }
+        ImageFormat::Bmp if max > 255 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "BMP output is 8-bit only",
+        )),
+        ImageFormat::Bmp => {
+            // 每行补齐到 4 字节的整数倍
+            let stride = (3 * bounds.0).next_multiple_of(4);
+            let offset = 14 + 40;
+            let size = offset + stride * bounds.1;
+            // 文件头
+            output.write_all(b"BM")?;
+            output.write_all(&(size as u32).to_le_bytes())?;
+            output.write_all(&[0; 4])?;
+            output.write_all(&(offset as u32).to_le_bytes())?;
+            // BITMAPINFOHEADER：高度为正表示自下而上存储，不压缩，24 位
+            output.write_all(&40u32.to_le_bytes())?;
+            output.write_all(&(bounds.0 as i32).to_le_bytes())?;
+            output.write_all(&(bounds.1 as i32).to_le_bytes())?;
+            output.write_all(&1u16.to_le_bytes())?;
+            output.write_all(&24u16.to_le_bytes())?;
+            output.write_all(&[0; 4])?;
+            output.write_all(&((stride * bounds.1) as u32).to_le_bytes())?;
+            // 水平和垂直分辨率取 72 DPI，调色板为空
+            output.write_all(&2835u32.to_le_bytes())?;
+            output.write_all(&2835u32.to_le_bytes())?;
+            output.write_all(&[0; 8])?;
+            let mut row = vec![0; stride];
+            for pixels in pixels.chunks(3 * bounds.0.max(1)).rev() {
+                for (bgr, rgb) in row.chunks_mut(3).zip(pixels.chunks(3)) {
+                    bgr.copy_from_slice(
+                        &[rgb[2].value(), rgb[1].value(), rgb[0].value()]
+                            .map(|channel| channel as u8),
+                    );
+                }
+                output.write_all(&row)?;
+            }
+            output.flush()
+        }
+        _ => unreachable!("{:?} is not an uncompressed format", format),
+    }
+}
+
+/// 颜色 `rgb` 按 Rec. 601 的权重换算的亮度，灰色保持不变
+fn luma(rgb: [usize; 3]) -> usize {
+    (299 * rgb[0] + 587 * rgb[1] + 114 * rgb[2] + 500) / 1000
+}
+
+#[test]
+fn test_encode() {
+    let encoded = |pixels: &[u8], format| {
+        let mut bytes = Vec::new();
+        encode(&mut bytes, pixels, (2, 1), format).map(|_| bytes)
+    };
+    let rgb = [255, 0, 0, 7, 7, 7];
+    assert_eq!(
+        encoded(&rgb, ImageFormat::Ppm).unwrap(),
+        b"P6\n2 1\n255\n\xff\x00\x00\x07\x07\x07"
+    );
+    assert_eq!(
+        encoded(&rgb, ImageFormat::Pgm).unwrap(),
+        b"P5\n2 1\n255\n\x4c\x07"
+    );
+    let mut bytes = Vec::new();
+    encode(&mut bytes, &[65535u16, 0, 256], (1, 1), ImageFormat::Ppm).unwrap();
+    assert_eq!(bytes, b"P6\n1 1\n65535\n\xff\xff\x00\x00\x01\x00");
+
+    // 两个像素占 6 字节，补齐到 8 字节；像素按 BGR 存储
+    let bmp = encoded(&rgb, ImageFormat::Bmp).unwrap();
+    assert_eq!(bmp.len(), 14 + 40 + 8);
+    assert_eq!(&bmp[..2], b"BM");
+    assert_eq!(&bmp[2..6], &(bmp.len() as u32).to_le_bytes());
+    assert_eq!(&bmp[54..], &[0, 0, 255, 7, 7, 7, 0, 0]);
+    let decoded = image::load_from_memory(&bmp).unwrap().to_rgb();
+    assert_eq!(decoded.into_raw(), rgb);
+
+    assert!(encoded(&[0; 8], ImageFormat::Ppm).is_err());
+    let mut bytes = Vec::new();
+    assert!(encode(&mut bytes, &[0u16; 3], (1, 1), ImageFormat::Bmp).is_err());
+}
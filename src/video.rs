@@ -0,0 +1,158 @@
+//! 把动画帧直接编码成视频
+//!
+//! 扩展名为 `.gif`、`.png` 或 `.apng` 的输出由 `animated` 模块直接编码成动图；
+//! 其他格式交给外部的 `ffmpeg` 进程完成：每一帧以原始 RGB 字节写入它的标准输入，
+//! 容器和编码器由输出文件的扩展名决定（例如 `.mp4` 或 `.webm`）。
+
+use crate::animated::{ApngEncoder, GifEncoder};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// 写入视频文件的编码器
+pub struct VideoEncoder {
+    backend: Backend,
+    frame_len: usize,
+}
+
+/// 实际完成编码的一方
+enum Backend {
+    Ffmpeg { child: Child, stdin: ChildStdin },
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng(ApngEncoder<BufWriter<File>>),
+}
+
+/// 按扩展名（不区分大小写）不经过 ffmpeg 直接编码的动图格式
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Animated {
+    Gif,
+    Apng,
+}
+
+fn animated_format(filename: &str) -> Option<Animated> {
+    let extension = Path::new(filename)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "gif" => Some(Animated::Gif),
+        "png" | "apng" => Some(Animated::Apng),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_animated_format() {
+    assert_eq!(animated_format("loop.gif"), Some(Animated::Gif));
+    assert_eq!(animated_format("out/loop.GIF"), Some(Animated::Gif));
+    assert_eq!(animated_format("loop.png"), Some(Animated::Apng));
+    assert_eq!(animated_format("loop.apng"), Some(Animated::Apng));
+    assert_eq!(animated_format("zoom.mp4"), None);
+    assert_eq!(animated_format("zoom"), None);
+}
+
+/// 启动 `ffmpeg` 所用的命令行参数，`bounds` 是每帧的像素尺寸，`fps` 是帧率
+fn ffmpeg_args(filename: &str, bounds: (usize, usize), fps: usize) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "-y",
+        "-loglevel",
+        "error",
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        "rgb24",
+        "-s",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    args.push(format!("{}x{}", bounds.0, bounds.1));
+    args.push("-r".to_string());
+    args.push(fps.to_string());
+    // 大多数播放器只支持 yuv420p，它要求宽高都是偶数，奇数时裁掉最后一行或一列
+    for arg in [
+        "-i",
+        "-",
+        "-vf",
+        "crop=trunc(iw/2)*2:trunc(ih/2)*2",
+        "-pix_fmt",
+        "yuv420p",
+    ] {
+        args.push(arg.to_string());
+    }
+    args.push(filename.to_string());
+    args
+}
+
+#[test]
+fn test_ffmpeg_args() {
+    let args = ffmpeg_args("zoom.mp4", (640, 480), 30);
+    assert_eq!(args.last().map(String::as_str), Some("zoom.mp4"));
+    let size = args.iter().position(|arg| arg == "-s").unwrap();
+    assert_eq!(args[size + 1], "640x480");
+    let rate = args.iter().position(|arg| arg == "-r").unwrap();
+    assert_eq!(args[rate + 1], "30");
+}
+
+impl VideoEncoder {
+    /// 准备把 `bounds` 大小、帧率为 `fps` 的 `frames` 帧写入 `filename`，视频格式需要启动 `ffmpeg`
+    pub fn new(
+        filename: &str,
+        bounds: (usize, usize),
+        fps: usize,
+        frames: usize,
+    ) -> io::Result<VideoEncoder> {
+        let backend = match animated_format(filename) {
+            Some(format) => {
+                let output = BufWriter::new(File::create(filename)?);
+                match format {
+                    Animated::Gif => Backend::Gif(GifEncoder::new(output, bounds, fps)?),
+                    Animated::Apng => Backend::Apng(ApngEncoder::new(output, bounds, fps, frames)?),
+                }
+            }
+            None => {
+                let mut child = Command::new("ffmpeg")
+                    .args(ffmpeg_args(filename, bounds, fps))
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|err| {
+                        io::Error::new(err.kind(), format!("failed to run ffmpeg: {}", err))
+                    })?;
+                let stdin = child.stdin.take().expect("stdin was piped");
+                Backend::Ffmpeg { child, stdin }
+            }
+        };
+        Ok(VideoEncoder {
+            backend,
+            frame_len: bounds.0 * bounds.1 * 3,
+        })
+    }
+
+    /// 写入一帧 RGB 像素，帧必须按播放顺序写入
+    pub fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        assert_eq!(pixels.len(), self.frame_len);
+        match &mut self.backend {
+            Backend::Ffmpeg { stdin, .. } => stdin.write_all(pixels),
+            Backend::Gif(encoder) => encoder.write_frame(pixels),
+            Backend::Apng(encoder) => encoder.write_frame(pixels),
+        }
+    }
+
+    /// 写完文件；使用 `ffmpeg` 时关闭它的输入并等待它退出
+    pub fn finish(self) -> io::Result<()> {
+        match self.backend {
+            Backend::Ffmpeg { mut child, stdin } => {
+                drop(stdin);
+                let status = child.wait()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("ffmpeg exited with {}", status)))
+                }
+            }
+            Backend::Gif(encoder) => encoder.finish().map(drop),
+            Backend::Apng(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
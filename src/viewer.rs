@@ -0,0 +1,229 @@
+//! 交互式查看器
+//!
+//! 在窗口中显示分形，按住左键拖动平移，滚动滚轮以光标为中心缩放。每次视图变化后
+//! 先只计算每 8×8 个像素中的一个，再逐遍细化（见 `progressive`），每一遍都立即显示，
+//! 因此导航时画面始终能及时响应。画面已经算完时拖动整数个像素，只计算新露出的条带，
+//! 其余像素直接从原来的缓冲区平移过来（见 `pan`）。按 `E` 把当前视图以更高的分辨率导出为 PNG。
+
+use crate::pan::{pixel_offset, render_panned};
+use crate::progress::Progress;
+use crate::progressive::Progressive;
+use crate::{
+    corners_from_center, pixel_spacing, render_parallel, write_image, Coloring, Fractal,
+    ImageFormat, PixelTransform,
+};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use num::Complex;
+use std::io;
+use std::path::Path;
+
+/// 滚轮每滚动一格的缩放倍数
+const ZOOM_STEP: f64 = 1.25;
+
+/// 渲染时的分块尺寸
+const TILE: (usize, usize) = (64, 64);
+
+/// 查看器的参数和当前视图
+pub struct Viewer<C> {
+    pub fractal: Fractal,
+    pub coloring: Coloring,
+    pub limit: usize,
+    /// 把迭代缓冲区着色为 RGB 像素
+    pub colorize: C,
+    /// 窗口的像素尺寸
+    pub size: (usize, usize),
+    pub center: Complex<f64>,
+    pub zoom: f64,
+    /// 按 `E` 导出的图像的像素尺寸
+    pub export_size: (usize, usize),
+}
+
+/// 平移 `delta` 个像素（向右、向下为正）后，原来位于中心的内容移动到新位置时的中心
+fn pan(center: Complex<f64>, zoom: f64, size: (usize, usize), delta: (f64, f64)) -> Complex<f64> {
+    let spacing = pixel_spacing(size, zoom);
+    Complex {
+        re: center.re - delta.0 * spacing,
+        im: center.im + delta.1 * spacing,
+    }
+}
+
+/// 以像素 `cursor` 处的点为不动点放大 `factor` 倍后的中心和缩放倍数
+fn zoom_about(
+    center: Complex<f64>,
+    zoom: f64,
+    size: (usize, usize),
+    cursor: (f64, f64),
+    factor: f64,
+) -> (Complex<f64>, f64) {
+    let spacing = pixel_spacing(size, zoom);
+    let offset = Complex {
+        re: (cursor.0 - size.0 as f64 / 2.0) * spacing,
+        im: -(cursor.1 - size.1 as f64 / 2.0) * spacing,
+    };
+    let point = center + offset;
+    (point - offset / factor, zoom * factor)
+}
+
+#[test]
+fn test_pan_and_zoom() {
+    let size = (400, 200);
+    let center = Complex { re: -0.5, im: 0.0 };
+    // 缩放 1 时较短的一边覆盖长度 4，每个像素 0.02
+    let moved = pan(center, 1.0, size, (50.0, -25.0));
+    assert!((moved - Complex { re: -1.5, im: -0.5 }).norm() < 1e-12);
+
+    // 光标下的点在缩放前后保持不动
+    let cursor = (300.0, 50.0);
+    let (new_center, new_zoom) = zoom_about(center, 1.0, size, cursor, 2.0);
+    assert_eq!(new_zoom, 2.0);
+    let point_at = |center: Complex<f64>, zoom| {
+        let (upper_left, _) = corners_from_center(size, center, zoom);
+        let spacing = pixel_spacing(size, zoom);
+        Complex {
+            re: upper_left.re + cursor.0 * spacing,
+            im: upper_left.im - cursor.1 * spacing,
+        }
+    };
+    assert!((point_at(center, 1.0) - point_at(new_center, new_zoom)).norm() < 1e-12);
+}
+
+impl<C: Fn(&[u32], &mut [u8])> Viewer<C> {
+    /// 窗口中的像素到复平面的变换
+    fn transform(&self) -> PixelTransform<f64> {
+        let (upper_left, lower_right) = corners_from_center(self.size, self.center, self.zoom);
+        PixelTransform::from_corners(self.size, upper_left, lower_right)
+    }
+
+    /// 把视图平移 `delta` 个像素；原来的画面已经算完时复用重叠的像素，否则从头渐进渲染
+    fn pan(&mut self, progressive: &Progressive, delta: (f64, f64)) -> Progressive {
+        let old = self.transform();
+        self.center = pan(self.center, self.zoom, self.size, delta);
+        let transform = self.transform();
+        match pixel_offset(&old, &transform).filter(|_| progressive.is_finished()) {
+            Some(offset) => {
+                let (iterations, _) = render_panned(
+                    self.fractal,
+                    self.coloring,
+                    self.limit,
+                    progressive.iterations(),
+                    self.size,
+                    offset,
+                    transform,
+                );
+                Progressive::finished(self.size, iterations)
+            }
+            None => Progressive::new(self.size),
+        }
+    }
+
+    /// 对当前视图再细化一遍，返回窗口大小的 `0RGB` 缓冲区
+    fn refine(&self, progressive: &mut Progressive) -> Vec<u32> {
+        progressive.refine(self.fractal, self.coloring, self.limit, self.transform());
+        let mut pixels = vec![0; self.size.0 * self.size.1 * 3];
+        (self.colorize)(progressive.iterations(), &mut pixels);
+        pixels
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32)
+            .collect()
+    }
+
+    /// 以 `bounds` 的分辨率渲染当前视图，返回 RGB 像素
+    fn render_pixels(&self, bounds: (usize, usize)) -> Vec<u8> {
+        let (upper_left, lower_right) = corners_from_center(bounds, self.center, self.zoom);
+        let mut iterations = vec![0; bounds.0 * bounds.1];
+        render_parallel(
+            self.fractal,
+            self.coloring,
+            self.limit,
+            &mut iterations,
+            bounds,
+            PixelTransform::from_corners(bounds, upper_left, lower_right),
+            TILE,
+            true,
+            &Progress::hidden(),
+        );
+        let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+        (self.colorize)(&iterations, &mut pixels);
+        pixels
+    }
+
+    /// 把当前视图以 `export_size` 的分辨率写入当前目录下第一个未被占用的
+    /// `view_NNNN.png`，返回文件名
+    fn export(&self) -> io::Result<String> {
+        let filename = (0..)
+            .map(|n| format!("view_{:04}.png", n))
+            .find(|name| !Path::new(name).exists())
+            .expect("some file name is free");
+        let pixels = self.render_pixels(self.export_size);
+        write_image(&filename, &pixels, self.export_size, ImageFormat::Png)?;
+        Ok(filename)
+    }
+
+    /// 打开窗口并处理输入，直到窗口关闭或按下 `Esc`
+    pub fn run(mut self) -> io::Result<()> {
+        let mut window = Window::new(
+            "mandelbrot",
+            self.size.0,
+            self.size.1,
+            WindowOptions::default(),
+        )
+        .map_err(io::Error::other)?;
+        window.set_target_fps(60);
+
+        let mut progressive = Progressive::new(self.size);
+        // 平移复用像素后缓冲区已经算完，但还没有显示
+        let mut shown = false;
+        let mut drag: Option<(f32, f32)> = None;
+        while window.is_open() && !window.is_key_down(Key::Escape) {
+            if let Some((x, y)) = window.get_mouse_pos(MouseMode::Clamp) {
+                if window.get_mouse_down(MouseButton::Left) {
+                    // 只按整数个像素平移，不足一个像素的部分留到下一次
+                    let last = drag.unwrap_or((x, y));
+                    let delta = ((x - last.0).round(), (y - last.1).round());
+                    if delta != (0.0, 0.0) {
+                        progressive = self.pan(&progressive, (delta.0 as f64, delta.1 as f64));
+                        shown = false;
+                    }
+                    drag = Some((last.0 + delta.0, last.1 + delta.1));
+                } else {
+                    drag = None;
+                }
+                if let Some((_, scroll)) = window.get_scroll_wheel().filter(|&(_, dy)| dy != 0.0) {
+                    let factor = if scroll > 0.0 {
+                        ZOOM_STEP
+                    } else {
+                        1.0 / ZOOM_STEP
+                    };
+                    let cursor = (x as f64, y as f64);
+                    (self.center, self.zoom) =
+                        zoom_about(self.center, self.zoom, self.size, cursor, factor);
+                    progressive = Progressive::new(self.size);
+                    shown = false;
+                }
+            }
+
+            if window.is_key_pressed(Key::E, KeyRepeat::No) {
+                let filename = self.export()?;
+                eprintln!(
+                    "exported {}: --center {},{} --zoom {}",
+                    filename, self.center.re, self.center.im, self.zoom
+                );
+            }
+
+            if progressive.is_finished() && shown {
+                window.update();
+            } else {
+                let buffer = self.refine(&mut progressive);
+                shown = true;
+                window
+                    .update_with_buffer(&buffer, self.size.0, self.size.1)
+                    .map_err(io::Error::other)?;
+                window.set_title(&format!(
+                    "mandelbrot  {},{}  zoom {:.3e}",
+                    self.center.re, self.center.im, self.zoom
+                ));
+            }
+        }
+        Ok(())
+    }
+}
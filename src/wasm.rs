@@ -0,0 +1,290 @@
+//! WebAssembly 接口
+//!
+//! 启用 `wasm` 特性后，用 wasm-bindgen 导出 `Renderer`，可以在网页中设置视图和
+//! 着色参数，把分形渲染进 RGBA 缓冲区（如 `ImageData` 的数据），不读写任何文件。
+//! 用 `wasm-pack build --target web -- --features wasm` 构建。
+//!
+//! ```js
+//! const renderer = new Renderer(800, 600);
+//! renderer.setCenter(-0.75, 0.1);
+//! renderer.setZoom(20);
+//! renderer.setPalette("fire");
+//! context.putImageData(new ImageData(renderer.renderImage(), 800, 600), 0, 0);
+//! ```
+//!
+//! wasm32-unknown-unknown 上没有线程，rayon 退回到在当前线程中执行，因此这里
+//! 逐行顺序渲染。
+
+use crate::palette::{parse_palette, Palette};
+use crate::{
+    colorize, colorize_histogram, corners_from_center, parse_coloring, parse_fractal, render,
+    Coloring, Fractal, PixelTransform,
+};
+use num::Complex;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+
+/// 按 `transform` 把 `bounds` 大小的视图渲染为 RGBA 像素写入 `rgba`，每个像素的不透明度都是 255
+#[allow(clippy::too_many_arguments)]
+pub fn render_rgba(
+    fractal: Fractal,
+    coloring: Coloring,
+    limit: usize,
+    palette: &Palette,
+    histogram: bool,
+    bounds: (usize, usize),
+    transform: PixelTransform<f64>,
+    rgba: &mut [u8],
+) {
+    assert_eq!(rgba.len(), bounds.0 * bounds.1 * 4);
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    render(fractal, coloring, limit, &mut iterations, bounds, transform);
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    if histogram {
+        colorize_histogram(&iterations, limit, palette, &mut pixels);
+    } else {
+        colorize(&iterations, limit, palette, &mut pixels);
+    }
+    for (rgba, rgb) in rgba.chunks_exact_mut(4).zip(pixels.chunks_exact(3)) {
+        rgba[..3].copy_from_slice(rgb);
+        rgba[3] = 255;
+    }
+}
+
+/// 导出给 JavaScript 的渲染器：保存渲染参数，按需渲染整幅图像
+#[wasm_bindgen]
+pub struct Renderer {
+    bounds: (usize, usize),
+    center: Complex<f64>,
+    zoom: f64,
+    rotate: f64,
+    limit: usize,
+    fractal: Fractal,
+    coloring: Coloring,
+    palette: Palette,
+    histogram: bool,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    /// `width`x`height` 的渲染器，初始参数与 `render` 子命令的默认值相同
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize) -> Renderer {
+        Renderer {
+            bounds: (width, height),
+            center: Complex { re: -0.5, im: 0.0 },
+            zoom: 1.0,
+            rotate: 0.0,
+            limit: 255,
+            fractal: Fractal::Mandelbrot,
+            coloring: Coloring::EscapeTime,
+            palette: Palette::gray(),
+            histogram: false,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.bounds.0
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.bounds.1
+    }
+
+    /// 改变图像的像素尺寸
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.bounds = (width, height);
+    }
+
+    /// 视图中心
+    #[wasm_bindgen(js_name = setCenter)]
+    pub fn set_center(&mut self, re: f64, im: f64) -> Result<(), JsError> {
+        if !re.is_finite() || !im.is_finite() {
+            return Err(JsError::new("the center must be finite"));
+        }
+        self.center = Complex { re, im };
+        Ok(())
+    }
+
+    /// 缩放倍数，为 1 时图像较短的一边覆盖复平面中长度为 4 的范围
+    #[wasm_bindgen(js_name = setZoom)]
+    pub fn set_zoom(&mut self, zoom: f64) -> Result<(), JsError> {
+        if !zoom.is_finite() || zoom <= 0.0 {
+            return Err(JsError::new("the zoom must be a positive number"));
+        }
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// 采样矩形绕视图中心逆时针旋转的角度（度）
+    #[wasm_bindgen(js_name = setRotate)]
+    pub fn set_rotate(&mut self, degrees: f64) -> Result<(), JsError> {
+        if !degrees.is_finite() {
+            return Err(JsError::new("the rotation must be finite"));
+        }
+        self.rotate = degrees;
+        Ok(())
+    }
+
+    /// 每个点的最大迭代次数
+    #[wasm_bindgen(js_name = setMaxIter)]
+    pub fn set_max_iter(&mut self, limit: usize) -> Result<(), JsError> {
+        if limit == 0 {
+            return Err(JsError::new("the iteration limit must be positive"));
+        }
+        self.limit = limit;
+        Ok(())
+    }
+
+    /// 分形类型：mandelbrot、burning-ship、tricorn、phoenix 或 lambda，朱利亚集用 `setJulia`
+    #[wasm_bindgen(js_name = setFractal)]
+    pub fn set_fractal(&mut self, name: &str) -> Result<(), JsError> {
+        let fractal = parse_fractal(name, None).ok_or_else(|| {
+            JsError::new(&format!(
+                "unknown fractal `{}` (expected `mandelbrot`, `burning-ship`, `tricorn`, `phoenix`, or `lambda`)",
+                name
+            ))
+        })?;
+        check_coloring(fractal, self.coloring)?;
+        self.fractal = fractal;
+        Ok(())
+    }
+
+    /// 以 `re + im i` 为常数 c 的朱利亚集
+    #[wasm_bindgen(js_name = setJulia)]
+    pub fn set_julia(&mut self, re: f64, im: f64) {
+        self.fractal = Fractal::Julia(Complex { re, im });
+    }
+
+    /// 迭代公式 z = z^d + c 中的次数 d，只有曼德博集支持 2 以外的次数
+    #[wasm_bindgen(js_name = setPower)]
+    pub fn set_power(&mut self, power: f64) -> Result<(), JsError> {
+        if !power.is_finite() || power <= 1.0 {
+            return Err(JsError::new("the power must be a number greater than 1"));
+        }
+        self.fractal = self
+            .fractal
+            .with_power(power)
+            .ok_or_else(|| JsError::new("the power is only supported for `mandelbrot`"))?;
+        Ok(())
+    }
+
+    /// 着色方式：escape-time、smooth、distance、orbit-trap、tia 或 atom-domain
+    #[wasm_bindgen(js_name = setColoring)]
+    pub fn set_coloring(&mut self, name: &str) -> Result<(), JsError> {
+        let coloring = parse_coloring(name).ok_or_else(|| {
+            JsError::new(&format!(
+                "unknown coloring `{}` (expected `escape-time`, `smooth`, `distance`, `orbit-trap`, `tia`, or `atom-domain`)",
+                name
+            ))
+        })?;
+        check_coloring(self.fractal, coloring)?;
+        self.coloring = coloring;
+        Ok(())
+    }
+
+    /// 调色板：gray、fire、ocean、classic、viridis、magma、inferno、plasma、turbo，或形如 0:000000,1:ffffff 的渐变节点
+    #[wasm_bindgen(js_name = setPalette)]
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsError> {
+        self.palette = parse_palette(palette)
+            .ok_or_else(|| JsError::new(&format!("invalid palette `{}`", palette)))?;
+        Ok(())
+    }
+
+    /// 是否按逃逸次数的直方图均衡着色
+    #[wasm_bindgen(js_name = setHistogram)]
+    pub fn set_histogram(&mut self, histogram: bool) {
+        self.histogram = histogram;
+    }
+
+    /// 把当前视图渲染进 `width * height * 4` 字节的 RGBA 缓冲区
+    pub fn render(&self, rgba: &mut [u8]) -> Result<(), JsError> {
+        if rgba.len() != self.bounds.0 * self.bounds.1 * 4 {
+            return Err(JsError::new(&format!(
+                "the buffer must hold {}x{} RGBA pixels",
+                self.bounds.0, self.bounds.1
+            )));
+        }
+        render_rgba(
+            self.fractal,
+            self.coloring,
+            self.limit,
+            &self.palette,
+            self.histogram,
+            self.bounds,
+            self.transform(),
+            rgba,
+        );
+        Ok(())
+    }
+
+    /// 渲染当前视图，返回可以直接用来构造 `ImageData` 的 RGBA 像素
+    #[wasm_bindgen(js_name = renderImage)]
+    pub fn render_image(&self) -> Clamped<Vec<u8>> {
+        let mut rgba = vec![0; self.bounds.0 * self.bounds.1 * 4];
+        render_rgba(
+            self.fractal,
+            self.coloring,
+            self.limit,
+            &self.palette,
+            self.histogram,
+            self.bounds,
+            self.transform(),
+            &mut rgba,
+        );
+        Clamped(rgba)
+    }
+}
+
+/// 分形 `fractal` 不能按 `coloring` 着色时的错误，参见 `Fractal::supports_coloring`
+fn check_coloring(fractal: Fractal, coloring: Coloring) -> Result<(), JsError> {
+    if fractal.supports_coloring(coloring) {
+        Ok(())
+    } else {
+        Err(JsError::new(&format!(
+            "{:?} cannot be colored with {:?}",
+            fractal, coloring
+        )))
+    }
+}
+
+impl Renderer {
+    /// 当前视图的像素变换
+    fn transform(&self) -> PixelTransform<f64> {
+        let (upper_left, lower_right) = corners_from_center(self.bounds, self.center, self.zoom);
+        PixelTransform::from_corners(self.bounds, upper_left, lower_right)
+            .rotated(self.bounds, self.rotate)
+    }
+}
+
+#[test]
+fn test_renderer() {
+    let mut renderer = Renderer::new(40, 30);
+    renderer.set_zoom(2.0).unwrap();
+    renderer.set_palette("fire").unwrap();
+    let Clamped(rgba) = renderer.render_image();
+    assert_eq!(rgba.len(), 40 * 30 * 4);
+    assert!(rgba.chunks_exact(4).all(|pixel| pixel[3] == 255));
+
+    // 与普通渲染再着色的结果一致
+    let bounds = (40, 30);
+    let (upper_left, lower_right) = corners_from_center(bounds, Complex { re: -0.5, im: 0.0 }, 2.0);
+    let mut iterations = vec![0; bounds.0 * bounds.1];
+    render(
+        Fractal::Mandelbrot,
+        Coloring::EscapeTime,
+        255,
+        &mut iterations,
+        bounds,
+        PixelTransform::from_corners(bounds, upper_left, lower_right),
+    );
+    let mut expected = vec![0u8; bounds.0 * bounds.1 * 3];
+    colorize(&iterations, 255, &Palette::fire(), &mut expected);
+    let rgb: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| pixel[..3].to_vec())
+        .collect();
+    assert_eq!(rgb, expected);
+}